@@ -1,3 +1,4 @@
+// expect-exit: 11
 int main()
 {
     // should be 11 not 14
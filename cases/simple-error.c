@@ -1,3 +1,4 @@
+// expect-exit: 255
 int main(a)
 {
     return 0;
@@ -0,0 +1,7 @@
+//! Lexes and parses arbitrary bytes, run with `cargo fuzz run parse`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| crust::fuzz::parse(data));
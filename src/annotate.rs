@@ -0,0 +1,55 @@
+//! Source listings interleaved with the IR that each line compiles to.
+
+use std::collections::BTreeMap;
+
+use crate::{ast::Definition, sources::SourceMap, token::Span, Ast};
+
+/// Lists every file in `sources`, following each line with the IR of the definitions and
+/// statements that start on it.
+pub fn annotate(sources: &SourceMap, ast: &Ast) -> String {
+    // IR lines keyed by the base of their file and their line within it
+    let mut ir = BTreeMap::<(usize, usize), Vec<String>>::new();
+    let mut add = |span: &Span, text: String| {
+        if let Some((file, local)) = sources.locate(span) {
+            let line = file
+                .source
+                .chars()
+                .take(local.start)
+                .filter(|c| *c == '\n')
+                .count();
+            ir.entry((file.base, line)).or_default().push(text);
+        }
+    };
+
+    for def in &ast.defs {
+        match def {
+            Definition::Func(func) => {
+                add(&func.span, def.to_string());
+                for (statement, span) in &func.body {
+                    add(span, format!("  {statement}"));
+                }
+            }
+            Definition::Struct { span, .. } | Definition::Import { span, .. } => {
+                add(span, def.to_string())
+            }
+            Definition::Macro(r#macro) => add(&r#macro.span, def.to_string()),
+            Definition::Prototype(prototype) => add(&prototype.span, def.to_string()),
+            Definition::Global(global) => add(&global.span, def.to_string()),
+        }
+    }
+
+    let mut listing = String::new();
+    for file in sources.files() {
+        if sources.files().len() > 1 {
+            listing.push_str(&format!("// {}\n", file.name));
+        }
+
+        for (line, text) in file.source.lines().enumerate() {
+            listing.push_str(&format!("{:>4} | {text}\n", line + 1));
+            for annotation in ir.get(&(file.base, line)).into_iter().flatten() {
+                listing.push_str(&format!("     |     ; {annotation}\n"));
+            }
+        }
+    }
+    listing
+}
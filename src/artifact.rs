@@ -0,0 +1,361 @@
+//! IR files written by `build` and read back by `run`.
+//!
+//! Since format version 2 they hold the [typed IR](crate::typed), written in the layout of
+//! [`ir::schema`](crate::ir::schema), and before that they held the AST, which is resolved into
+//! the typed IR as it's read so that older files still run. Format version 3 added globals, which
+//! a program of format version 2 is read as having none of.
+
+use serde::Deserialize;
+
+use crate::{
+    binary,
+    ir::schema,
+    messages::Message,
+    typed::{self, Callee, Expr, Program, Stmt, Type},
+    Ast, Builtins, Diagnostic,
+};
+
+/// Version of the IR layout, bumped whenever the shape of the serialized program changes.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// The oldest version of the IR layout that can still be read.
+pub const OLDEST_FORMAT_VERSION: u32 = 1;
+
+/// Version of the compiler writing the IR.
+pub const COMPILER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Bounds on the programs read from IR files, so that a crafted file can't exhaust the memory or
+/// overflow the stack of the process reading or running it.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Levels of nested objects and arrays (or structs, sequences and enums in binary IR)
+    pub nesting: usize,
+    pub definitions: usize,
+    /// Statements in a single function body
+    pub statements: usize,
+    /// Bytes in the name of a function, struct, parameter, variable or called builtin
+    pub name_len: usize,
+    /// Elements in a single array, which the interpreter allocates up front
+    pub array_len: u32,
+}
+
+/// The limits enforced by [`Artifact::read`].
+pub const LIMITS: Limits = Limits {
+    nesting: 512,
+    definitions: 10_000,
+    statements: 100_000,
+    name_len: 1024,
+    array_len: 1 << 24,
+};
+
+/// The contents of an IR file: the program plus the versions needed to check it can be read.
+#[derive(Debug)]
+pub struct Artifact {
+    pub format_version: u32,
+    pub compiler_version: String,
+    pub program: Program,
+}
+
+/// The contents of an IR file of format version 1, which holds the AST.
+#[derive(Deserialize)]
+struct ArtifactV1 {
+    #[allow(dead_code)]
+    format_version: u32,
+    compiler_version: String,
+    ast: Ast,
+}
+
+/// The contents of an IR file of format version 2, whose program has no globals.
+#[derive(Deserialize)]
+struct ArtifactV2 {
+    #[allow(dead_code)]
+    format_version: u32,
+    compiler_version: String,
+    program: ProgramV2,
+}
+
+#[derive(Deserialize)]
+struct ProgramV2 {
+    structs: Vec<schema::Struct>,
+    funcs: Vec<schema::Func>,
+}
+
+/// The version fields of an artifact, readable from any IR file regardless of its AST shape.
+#[derive(Deserialize)]
+struct Header {
+    format_version: Option<u32>,
+    compiler_version: Option<String>,
+}
+
+impl Artifact {
+    /// The artifact of `ast`, which has passed semantic analysis, failing if a name in it can't be
+    /// resolved.
+    pub fn new(ast: &Ast) -> Result<Self, Diagnostic> {
+        let program = typed::resolve(ast, &Builtins::default())
+            .map_err(|e| Diagnostic::error("E0303", Message::new("E0303").arg("error", e)))?;
+        Ok(Self {
+            format_version: FORMAT_VERSION,
+            compiler_version: COMPILER_VERSION.to_string(),
+            program,
+        })
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(&schema::Artifact::from(self)).unwrap()
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        binary::to_bytes(&schema::Artifact::from(self)).unwrap()
+    }
+
+    /// Reads an artifact in either the binary or JSON format, checking its version first so that
+    /// IR from an incompatible compiler produces a clear error instead of a deserialization one.
+    pub fn read(bytes: &[u8]) -> Result<Self, Diagnostic> {
+        let is_binary = binary::is_binary(bytes);
+        let header = match is_binary {
+            true => binary::from_bytes_prefix::<(u32, String)>(bytes)
+                .map(|(format_version, compiler_version)| Header {
+                    format_version: Some(format_version),
+                    compiler_version: Some(compiler_version),
+                })
+                .map_err(|e| e.to_string()),
+            false => from_json::<Header>(bytes, LIMITS.nesting),
+        }
+        .map_err(|e| Diagnostic::error("E0300", Message::new("E0300").arg("error", e)))?;
+
+        let Some(format_version) = header.format_version else {
+            return Err(
+                Diagnostic::error("E0301", Message::new("E0301.unversioned"))
+                    .with_note(Message::new("note.predates-versioning")),
+            );
+        };
+
+        if !(OLDEST_FORMAT_VERSION..=FORMAT_VERSION).contains(&format_version) {
+            let built_with = header.compiler_version.as_deref().unwrap_or("unknown");
+            return Err(Diagnostic::error(
+                "E0301",
+                Message::new("E0301.version").arg("version", format_version.to_string()),
+            )
+            .with_note(
+                Message::new("note.built-with")
+                    .arg("built_with", built_with)
+                    .arg("current", COMPILER_VERSION)
+                    .arg("oldest", OLDEST_FORMAT_VERSION.to_string())
+                    .arg("supported", FORMAT_VERSION.to_string()),
+            ));
+        }
+
+        let read_error = |e| Diagnostic::error("E0300", Message::new("E0300").arg("error", e));
+        let artifact = match format_version {
+            1 => {
+                let old = decode::<ArtifactV1>(bytes, is_binary).map_err(read_error)?;
+                let program = typed::resolve(&old.ast, &Builtins::default()).map_err(read_error)?;
+                Self {
+                    format_version: FORMAT_VERSION,
+                    compiler_version: old.compiler_version,
+                    program,
+                }
+            }
+            2 => {
+                let old = decode::<ArtifactV2>(bytes, is_binary).map_err(read_error)?;
+                schema::Artifact {
+                    format_version: FORMAT_VERSION,
+                    compiler_version: old.compiler_version,
+                    program: schema::Program {
+                        structs: old.program.structs,
+                        funcs: old.program.funcs,
+                        globals: Vec::new(),
+                    },
+                }
+                .into()
+            }
+            _ => decode::<schema::Artifact>(bytes, is_binary)
+                .map_err(read_error)?
+                .into(),
+        };
+
+        LIMITS.check(&artifact.program).map_err(|limit| {
+            Diagnostic::error("E0302", Message::new("E0302").arg("limit", limit))
+        })?;
+        typed::check(&artifact.program).map_err(read_error)?;
+        Ok(artifact)
+    }
+}
+
+impl From<&Artifact> for schema::Artifact {
+    fn from(artifact: &Artifact) -> Self {
+        Self {
+            format_version: artifact.format_version,
+            compiler_version: artifact.compiler_version.clone(),
+            program: schema::Program::from(&artifact.program),
+        }
+    }
+}
+
+impl From<schema::Artifact> for Artifact {
+    fn from(artifact: schema::Artifact) -> Self {
+        Self {
+            format_version: artifact.format_version,
+            compiler_version: artifact.compiler_version,
+            program: artifact.program.into(),
+        }
+    }
+}
+
+/// Decodes binary or JSON IR, failing before it's decoded if it's nested too deeply.
+fn decode<T: for<'de> Deserialize<'de>>(bytes: &[u8], is_binary: bool) -> Result<T, String> {
+    match is_binary {
+        true => binary::from_bytes::<T>(bytes, LIMITS.nesting).map_err(|e| e.to_string()),
+        false => from_json(bytes, LIMITS.nesting),
+    }
+}
+
+/// Decodes JSON IR, failing before it's decoded if it's nested more than `max_depth` levels deep.
+fn from_json<T: for<'de> Deserialize<'de>>(bytes: &[u8], max_depth: usize) -> Result<T, String> {
+    if json_depth(bytes) > max_depth {
+        return Err(String::from("JSON IR is nested too deeply"));
+    }
+
+    // the depth is checked above instead, since serde_json's own limit rejects real programs
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    deserializer.disable_recursion_limit();
+    let value = T::deserialize(&mut deserializer).map_err(|e| e.to_string())?;
+    deserializer.end().map_err(|e| e.to_string())?;
+    Ok(value)
+}
+
+/// The deepest nesting of objects and arrays in `json`, which doesn't need to be valid.
+fn json_depth(json: &[u8]) -> usize {
+    let (mut depth, mut max) = (0usize, 0);
+    let mut bytes = json.iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'{' | b'[' => {
+                depth += 1;
+                max = max.max(depth);
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b'"' => {
+                while let Some(byte) = bytes.next() {
+                    match byte {
+                        b'\\' => {
+                            bytes.next();
+                        }
+                        b'"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    max
+}
+
+impl Limits {
+    /// Checks every limit other than nesting, which is checked while decoding.
+    pub fn check(&self, program: &Program) -> Result<(), Message> {
+        if program.structs.len() + program.funcs.len() + program.globals.len() > self.definitions {
+            return Err(Message::new("limit.definitions").arg("max", self.definitions.to_string()));
+        }
+
+        for r#struct in &program.structs {
+            self.name(&r#struct.name)?;
+            for field in &r#struct.fields {
+                self.name(&field.name)?;
+            }
+        }
+        for global in &program.globals {
+            self.name(&global.var.name)?;
+            if let Some((expr, _)) = &global.expr {
+                self.expr(expr)?;
+            }
+        }
+        for func in &program.funcs {
+            self.name(&func.name)?;
+            if func.body.len() > self.statements {
+                return Err(Message::new("limit.statements")
+                    .arg("name", func.name.as_str())
+                    .arg("max", self.statements.to_string()));
+            }
+            for var in &func.slots {
+                self.name(&var.name)?;
+                if let Type::Array { len, .. } = var.ty {
+                    if len > self.array_len {
+                        return Err(Message::new("limit.array")
+                            .arg("name", var.name.as_str())
+                            .arg("max", self.array_len.to_string()));
+                    }
+                }
+            }
+            for (statement, _) in &func.body {
+                self.statement(statement)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self, name: &str) -> Result<(), Message> {
+        match name.len() > self.name_len {
+            true => Err(Message::new("limit.name").arg("max", self.name_len.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    fn statement(&self, statement: &Stmt) -> Result<(), Message> {
+        match statement {
+            Stmt::ReturnVoid
+            | Stmt::Array { .. }
+            | Stmt::Inline { .. }
+            | Stmt::Break
+            | Stmt::Continue => Ok(()),
+            Stmt::Return(expr) | Stmt::Expr(expr) | Stmt::Let { expr, .. } => self.expr(&expr.0),
+            Stmt::Reassign { expr, .. } => self.expr(&expr.0),
+            Stmt::Store { index, expr, .. } => {
+                self.expr(&index.0)?;
+                self.expr(&expr.0)
+            }
+            Stmt::Write { pointer, expr } => {
+                self.expr(&pointer.0)?;
+                self.expr(&expr.0)
+            }
+            Stmt::Switch { expr, cases } => {
+                self.expr(&expr.0)?;
+                cases
+                    .iter()
+                    .flat_map(|case| &case.body)
+                    .try_for_each(|(statement, _)| self.statement(statement))
+            }
+        }
+    }
+
+    /// Checks the names of the builtins `expr` calls, the only names left in expressions.
+    fn expr(&self, expr: &Expr) -> Result<(), Message> {
+        match expr {
+            Expr::Int(_)
+            | Expr::Float(_)
+            | Expr::Str(_)
+            | Expr::Var(_)
+            | Expr::PreInc(_)
+            | Expr::PreDec(_)
+            | Expr::AddrOf(_) => Ok(()),
+            Expr::Neg(inner) | Expr::Not(inner) | Expr::Deref(inner) => self.expr(&inner.0),
+            Expr::Binary(_, lhs, rhs) | Expr::Index(lhs, rhs) | Expr::Logical(_, lhs, rhs) => {
+                self.expr(&lhs.0)?;
+                self.expr(&rhs.0)
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                self.expr(&cond.0)?;
+                self.expr(&then.0)?;
+                self.expr(&otherwise.0)
+            }
+            Expr::Call { callee, args } | Expr::Spawn { callee, args } => {
+                if let Callee::Extern(name) = callee {
+                    self.name(name)?;
+                }
+                args.iter().try_for_each(|arg| self.expr(&arg.0))
+            }
+            Expr::Join(handle) => self.expr(&handle.0),
+        }
+    }
+}
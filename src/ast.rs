@@ -1,17 +1,65 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    mem,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
 
 use chumsky::{
-    error::Simple,
-    primitive::{filter_map, just},
+    error::{Error, Simple},
+    prelude::BoxedParser,
+    primitive::{choice, just, none_of},
     recovery,
     recursive::recursive,
-    Error, Parser,
+    select, Parser,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::Token;
+use crate::{
+    diagnostics::Diagnostic,
+    interrupt::Interrupt,
+    ir::BinOp,
+    literal::{self, IntError},
+    messages::Message,
+    precedence::{Assoc, Ops, LEVELS},
+    semantics::{ArithError, IntMode},
+    suggest,
+    token::{Span, INLINE_BLOCKS, KEYWORDS},
+    types::TypeTable,
+    value::Pointer,
+    visit::{walk_expr, Visitor},
+    Builtins, Symbol, Token, Value,
+};
+
+/// Label of the parser errors for identifiers that misspell a keyword, whose message is the
+/// keyword.
+pub const KEYWORD_TYPO: &str = "keyword-typo";
+
+/// Label of the parser errors for C constructs the language doesn't have, whose message names
+/// the construct: the keyword that starts a loop or an `if`.
+pub const UNSUPPORTED: &str = "unsupported";
+
+/// Labels of the parser errors for int literals too large for an int and for int literals with
+/// no value otherwise, whose messages are the literal.
+pub const TOO_LARGE: &str = "too-large";
+pub const INVALID_INT: &str = "invalid-int";
+
+/// Label of the parser errors for float literals that don't parse, whose messages are the literal.
+pub const INVALID_FLOAT: &str = "invalid-float";
+
+/// The return type of functions that return nothing, which no variable can have.
+pub const VOID: &str = "void";
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A node paired with the span of source it was parsed from.
+pub type Spanned<T> = (T, Span);
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Ast {
     pub defs: Vec<Definition>,
 }
@@ -24,37 +72,876 @@ impl Ast {
             .map(|defs| Self { defs })
     }
 
-    pub fn run_main(&self) -> i32 {
-        let mut funcs = HashMap::new();
-        for def in &self.defs {
-            match def {
-                Definition::Func(func) => {
-                    if let Some(_) = funcs.insert(func.name.clone(), func.clone()) {
-                        panic!("Duplicate functions with name {}", func.name);
+    /// Removes every function except `name` and those it calls, directly or through others,
+    /// keeping struct definitions and globals intact.
+    ///
+    /// Returns `false` if no function with that name exists.
+    pub fn retain_func(&mut self, name: &str) -> bool {
+        struct Callees<'a>(Vec<&'a str>);
+
+        impl<'a> Visitor<'a> for Callees<'a> {
+            fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+                if let Expr::Call { name, .. } | Expr::Spawn { name, .. } = &expr.0 {
+                    if !self.0.contains(&name.as_str()) {
+                        self.0.push(name.as_str());
+                    }
+                }
+                walk_expr(self, expr);
+            }
+        }
+
+        let mut kept = Callees(vec![name]);
+        let mut visited = 0;
+        while let Some(&caller) = kept.0.get(visited) {
+            visited += 1;
+            for def in &self.defs {
+                if let Definition::Func(func) = def {
+                    if func.name == caller {
+                        kept.visit_func(func);
                     }
                 }
-                _ => (),
             }
         }
+        let kept = kept.0.into_iter().map(String::from).collect::<Vec<_>>();
+
+        self.defs.retain(|def| match def {
+            Definition::Func(func) => kept.iter().any(|kept| func.name == kept.as_str()),
+            Definition::Struct { .. }
+            | Definition::Import { .. }
+            | Definition::Macro(_)
+            | Definition::Prototype(_)
+            | Definition::Global(_) => true,
+        });
+
+        self.defs
+            .iter()
+            .any(|def| matches!(def, Definition::Func(func) if func.name == name))
+    }
+
+    /// Runs the `main` function, binding `args` to its parameters.
+    pub fn run_main(&self, args: &[String]) -> Result<i32, Diagnostic> {
+        self.run_main_with(RunOptions::default(), args)
+    }
+
+    /// Runs the `main` function with custom builtins and limits.
+    pub fn run_main_with(&self, options: RunOptions, args: &[String]) -> Result<i32, Diagnostic> {
+        let resolved = Resolved::new(self)?;
+        let runtime = self.runtime(&resolved, options);
+        let Some(main) = runtime.func(Symbol::intern("main")) else {
+            return Err(Diagnostic::error("E0200", Message::new("main-not-found")));
+        };
+        let main_func = runtime.funcs[main];
+
+        let args = main_args(main_func, args)?;
+        let value = runtime.call(main_func, args)?;
+        exit_code(main_func, value)
+    }
+
+    /// Calls the function `name` with `args` as its arguments, as `main` would call it, returning
+    /// the value it returns.
+    pub fn call_with(
+        &self,
+        options: RunOptions,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, Diagnostic> {
+        let resolved = Resolved::new(self)?;
+        let (runtime, func) = self.entry(&resolved, options, name, args.len())?;
+        runtime.call(func, args)
+    }
+
+    /// Runs `statement` as though it were in the body of a function whose variables so far are
+    /// `vars`, for the REPL, adding any variable it declares to them. The globals are set afresh
+    /// for each statement, as they are for each run of a program.
+    ///
+    /// Returns the value the statement returns, if it's a `return`.
+    pub fn run_statement(
+        &self,
+        options: RunOptions,
+        statement: &Spanned<Statement>,
+        vars: &mut Vec<(Symbol, Value)>,
+    ) -> Result<Option<Value>, Diagnostic> {
+        let resolved = Resolved::new(self)?;
+        let mut runtime = self.runtime(&resolved, options);
+        let mut stack = Vec::new();
+
+        let max_call_depth = runtime.max_call_depth;
+        let result = on_call_stack(max_call_depth, || {
+            runtime.set_globals(&mut stack)?;
+            stack.append(vars);
+            Statement::eval(statement, &mut stack, runtime.globals, &mut runtime)?.into_value()
+        })?;
+        vars.extend(stack.into_iter().skip(runtime.globals));
+        result
+    }
+
+    /// The runtime for a call to the function `name` with `arity` arguments, as `main` would call
+    /// it, and the function.
+    fn entry<'a>(
+        &'a self,
+        resolved: &'a Resolved,
+        options: RunOptions,
+        name: &str,
+        arity: usize,
+    ) -> Result<(Runtime<'a>, &'a Func), Diagnostic> {
+        let runtime = self.runtime(resolved, options);
+        let Some(index) = runtime.func(Symbol::intern(name)) else {
+            return Err(Diagnostic::error(
+                "E0200",
+                Message::new("unknown-function").arg("name", name),
+            ));
+        };
+        let func = runtime.funcs[index];
+        if arity != func.params.len() {
+            return Err(arity_error(func, arity, &func.span));
+        }
+        Ok((runtime, func))
+    }
+
+    /// The runtime for a run of the program, once it's been `resolved`.
+    fn runtime<'a>(&'a self, resolved: &'a Resolved, options: RunOptions) -> Runtime<'a> {
+        let mut runtime = self.thread_runtime(
+            resolved,
+            Arc::new(options.builtins),
+            options.max_call_depth,
+            options.ints,
+            options.interrupt,
+            options.context,
+        );
+        runtime.hooks = options.hooks;
+        runtime
+    }
+
+    /// The runtime for a thread of the program, once it's been `resolved`, which shares
+    /// `builtins`, `interrupt` and `context` with the others and has no hooks.
+    fn thread_runtime<'a>(
+        &'a self,
+        resolved: &'a Resolved,
+        builtins: Arc<Builtins>,
+        max_call_depth: usize,
+        ints: IntMode,
+        interrupt: Interrupt,
+        context: Arc<dyn EvalContext>,
+    ) -> Runtime<'a> {
+        let funcs = self
+            .defs
+            .iter()
+            .filter_map(|def| match def {
+                Definition::Func(func) => Some(func),
+                _ => None,
+            })
+            .collect();
+
+        Runtime {
+            funcs,
+            resolved,
+            builtins,
+            max_call_depth,
+            ints,
+            interrupt,
+            hooks: Vec::new(),
+            context,
+            calls: Vec::new(),
+            frames: vec![(0, 0)],
+            globals: 0,
+            next_call: 1,
+            entry: "main",
+            program: self,
+            shared: None,
+            threads: HashMap::new(),
+        }
+    }
+}
+
+/// A program loaded to be called into any number of times, resolved once and run on a thread it
+/// keeps for its calls rather than on one of their own.
+#[derive(Debug)]
+pub struct Interpreter {
+    program: Arc<Ast>,
+    resolved: Arc<Resolved>,
+    /// The thread the calls run on, one after another, started by the first call and started
+    /// again by any call nesting deeper than it has the stack for
+    worker: Mutex<Option<Worker>>,
+}
+
+/// A thread running the calls it's sent, which has the stack for `max_call_depth` nested calls.
+#[derive(Debug)]
+struct Worker {
+    calls: Sender<Box<dyn FnOnce() + Send>>,
+    max_call_depth: usize,
+}
+
+impl Interpreter {
+    /// Resolves `program` to be called into.
+    pub fn new(program: Ast) -> Result<Self, Diagnostic> {
+        let resolved = Resolved::new(&program)?;
+        Ok(Self {
+            program: Arc::new(program),
+            resolved: Arc::new(resolved),
+            worker: Mutex::new(None),
+        })
+    }
+
+    /// Calls the function `name` with `args` as its arguments, as `main` would call it, returning
+    /// the value it returns.
+    pub fn call(
+        &self,
+        options: RunOptions,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, Diagnostic> {
+        let max_call_depth = options.max_call_depth;
+        let (program, resolved) = (self.program.clone(), self.resolved.clone());
+        let name = name.to_string();
+        let (sender, result) = mpsc::channel();
+        let call = move || {
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                let (mut runtime, func) = program.entry(&resolved, options, &name, args.len())?;
+                runtime.shared = Some((program.clone(), resolved.clone()));
+                runtime.call_here(func, args)
+            }));
+            // the call waits for its result, so it's there to receive it
+            let _ = sender.send(result);
+        };
+
+        {
+            let mut worker = self.worker.lock().unwrap();
+            let worker = match &mut *worker {
+                Some(worker) if worker.max_call_depth >= max_call_depth => worker,
+                worker => worker.insert(Worker::start(max_call_depth)?),
+            };
+            worker.calls.send(Box::new(call)).unwrap();
+        }
+        match result.recv().unwrap() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Worker {
+    fn start(max_call_depth: usize) -> Result<Self, Diagnostic> {
+        let (calls, received) = mpsc::channel::<Box<dyn FnOnce() + Send>>();
+        std::thread::Builder::new()
+            .name(String::from("main"))
+            .stack_size(stack_size(max_call_depth))
+            .spawn(move || {
+                for call in received {
+                    call();
+                }
+            })
+            .map_err(|e| start_error(e, max_call_depth))?;
+        Ok(Self {
+            calls,
+            max_call_depth,
+        })
+    }
+}
+
+/// The functions of a program resolved to their indices, and the layout of its structs, which
+/// every run of it starts from.
+#[derive(Debug, Clone)]
+struct Resolved {
+    /// The index among the program's functions of each function, by the number of its symbol
+    funcs: Vec<Option<usize>>,
+    types: TypeTable,
+}
+
+impl Resolved {
+    fn new(program: &Ast) -> Result<Self, Diagnostic> {
+        let mut funcs = Vec::new();
+        let mut count = 0;
+        for def in &program.defs {
+            if let Definition::Func(func) = def {
+                let name = func.name.number();
+                if funcs.len() <= name {
+                    funcs.resize(name + 1, None);
+                }
+                if funcs[name].replace(count).is_some() {
+                    return Err(duplicate_function(func));
+                }
+                count += 1;
+            }
+        }
+        let types = TypeTable::new(program).map_err(|mut errors| errors.remove(0))?;
+        Ok(Self { funcs, types })
+    }
+}
+
+/// What a call to a name runs: the builtin with its name if there is one, and otherwise the
+/// function.
+#[derive(Debug, Clone, Copy)]
+enum Callee {
+    /// The builtin at this index of the [`Builtins`]
+    Builtin(usize),
+    /// The function at this index of [`Runtime::funcs`]
+    Func(usize),
+}
+
+/// The native stack for `max_call_depth` nested calls.
+///
+/// The interpreter recurses on the native stack, so the call depth limit has to be reached
+/// before the native stack runs out.
+fn stack_size(max_call_depth: usize) -> usize {
+    STACK_PER_CALL
+        .saturating_mul(max_call_depth)
+        .saturating_add(BASE_STACK)
+}
+
+/// Runs `f` on a thread with enough stack for `max_call_depth` nested calls.
+fn on_call_stack<T: Send>(
+    max_call_depth: usize,
+    f: impl FnOnce() -> T + Send,
+) -> Result<T, Diagnostic> {
+    let result = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .name(String::from("main"))
+            .stack_size(stack_size(max_call_depth))
+            .spawn_scoped(scope, f)
+            .map(|handle| handle.join())
+    });
+
+    match result {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(panic)) => std::panic::resume_unwind(panic),
+        Err(e) => Err(start_error(e, max_call_depth)),
+    }
+}
+
+/// The error of a thread for `max_call_depth` nested calls that couldn't be started.
+fn start_error(error: std::io::Error, max_call_depth: usize) -> Diagnostic {
+    Diagnostic::error(
+        "E0200",
+        Message::new("E0200.start").arg("error", error.to_string()),
+    )
+    .with_note(
+        Message::new("note.stack-size")
+            .arg("depth", max_call_depth.to_string())
+            .arg("bytes", stack_size(max_call_depth).to_string()),
+    )
+}
+
+pub(crate) fn duplicate_function(func: &Func) -> Diagnostic {
+    Diagnostic::error(
+        "E0200",
+        Message::new("E0200.duplicate-function").arg("name", func.name.as_str()),
+    )
+    .with_label(func.span.clone(), Message::new("label.defined-again"))
+}
+
+/// Parses the command line arguments given to `main` into the values of its parameters.
+///
+/// A `main` without parameters can be given any arguments, which it reads with `argc` and `arg`.
+pub(crate) fn main_args(main: &Func, args: &[String]) -> Result<Vec<Value>, Diagnostic> {
+    if main.params.is_empty() {
+        return Ok(Vec::new());
+    }
+    if args.len() != main.params.len() {
+        return Err(Diagnostic::error(
+            "E0200",
+            Message::new("E0200.main-arity")
+                .arg("expected", main.params.len().to_string())
+                .arg("found", args.len().to_string()),
+        )
+        .with_label(main.span.clone(), Message::new("label.main-defined")));
+    }
+
+    args.iter()
+        .zip(&main.params)
+        .map(|(arg, param)| match param.ty.as_str() {
+            "string" => Ok(Value::Str(arg.clone())),
+            _ => arg.parse::<i32>().map(Value::from).map_err(|_| {
+                Diagnostic::error(
+                    "E0200",
+                    Message::new("E0200.invalid-int").arg("arg", arg.as_str()),
+                )
+                .with_label(param.span.clone(), Message::new("label.for-parameter"))
+            }),
+        })
+        .collect()
+}
+
+/// The exit code of a program whose `main` returned `value`, which must be an int.
+pub(crate) fn exit_code(main: &Func, value: Value) -> Result<i32, Diagnostic> {
+    match value {
+        // like a process's exit status, the code is the low 32 bits of a 64-bit int
+        Value::Int(code) => Ok(code as i32),
+        other => Err(Diagnostic::error(
+            "E0200",
+            Message::new("E0200.main-return").arg("type", other.type_name()),
+        )
+        .with_label(main.span.clone(), Message::new("label.main-defined"))),
+    }
+}
+
+/// The default limit on nested user function calls.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// The most call sites labelled in a backtrace, the rest are only listed in its note.
+const MAX_BACKTRACE_LABELS: usize = 8;
+
+/// Native stack reserved for each nested call, sized for unoptimized builds.
+const STACK_PER_CALL: usize = 64 * 1024;
+/// Native stack reserved on top of the per call stack, for the rest of the interpreter.
+const BASE_STACK: usize = 8 * 1024 * 1024;
+
+/// Configuration for running a program.
+pub struct RunOptions {
+    pub builtins: Builtins,
+    /// Nested user function calls deeper than this are reported as a stack overflow
+    pub max_call_depth: usize,
+    /// The width of ints and what happens when they overflow
+    pub ints: IntMode,
+    /// Stops the program before its next statement once it's set, e.g. on Ctrl-C
+    pub interrupt: Interrupt,
+    /// Told about every statement and call the interpreter runs, e.g. by the debugger
+    pub hooks: Vec<Box<dyn Hook>>,
+    /// Evaluates the int operators the interpreter runs, shared with every thread it spawns
+    pub context: Arc<dyn EvalContext>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            builtins: Builtins::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            ints: IntMode::default(),
+            interrupt: Interrupt::default(),
+            hooks: Vec::new(),
+            context: Arc::new(DefaultContext),
+        }
+    }
+}
+
+/// Watches a program as the interpreter runs it. Every method does nothing unless it's
+/// overridden.
+pub trait Hook: Send {
+    /// Called before `statement` runs, with the state of the program at that point.
+    ///
+    /// Returning an error stops the program with it.
+    fn before_statement(
+        &mut self,
+        _statement: &Spanned<Statement>,
+        _state: State<'_>,
+    ) -> Result<(), Diagnostic> {
+        Ok(())
+    }
+
+    /// Called when a call to the user function `name` starts, including the outermost one.
+    fn enter(&mut self, _name: &str) {}
+
+    /// Called when a call to the user function `name` returns. Calls an error propagates out of
+    /// never return.
+    fn exit(&mut self, _name: &str) {}
+
+    /// Called once the program has finished, whether or not it failed.
+    fn finish(&mut self) {}
+
+    /// Functions to run in place of the program's functions with the same names, asked for
+    /// after each call to [`Hook::before_statement`]. They're run from the next call to them on,
+    /// and calls in progress finish running the function they started in.
+    fn replacements(&mut self) -> Vec<Func> {
+        Vec::new()
+    }
+}
+
+/// The state of a running program, as a [`Hook`] sees it.
+pub struct State<'a> {
+    /// The function whose statement is about to run
+    pub func: &'a str,
+    /// The user function calls in progress below the outermost one, innermost last, with the
+    /// span of each call site
+    pub calls: &'a [Spanned<&'a str>],
+    /// The variables of the innermost call, in the order they were declared
+    pub vars: &'a [(Symbol, Value)],
+    /// The program's functions, as they are after any replacements
+    pub funcs: &'a [&'a Func],
+}
+
+/// Evaluates the operators on ints for the interpreter, so that an embedder can watch or change
+/// them, e.g. to log every division or to saturate instead of wrapping. Every method does what the
+/// language does unless it's overridden.
+pub trait EvalContext: Send + Sync {
+    /// Evaluates `lhs op rhs` for the operator at `span`, including the one of a compound
+    /// assignment like `+=`.
+    fn binary(
+        &self,
+        op: BinOp,
+        lhs: i64,
+        rhs: i64,
+        ints: IntMode,
+        _span: &Span,
+    ) -> Result<i64, ArithError> {
+        ints.binary(op, lhs, rhs)
+    }
+
+    /// Evaluates `lhs && rhs` or `lhs || rhs`, whose right side is only evaluated by calling
+    /// `rhs`, which the language does only when `lhs` doesn't decide the result.
+    fn logical(
+        &self,
+        op: LogicalOp,
+        lhs: i64,
+        rhs: &mut dyn FnMut() -> Result<i64, Diagnostic>,
+    ) -> Result<i64, Diagnostic> {
+        Ok(match (op, lhs) {
+            (LogicalOp::And, 0) => 0,
+            (LogicalOp::Or, lhs) if lhs != 0 => 1,
+            _ => (rhs()? != 0) as i64,
+        })
+    }
+}
+
+/// The [`EvalContext`] a program runs with unless it's given another, which overrides nothing.
+pub struct DefaultContext;
+
+impl EvalContext for DefaultContext {}
+
+/// `&&` or `||`, as an [`EvalContext`] is asked to evaluate it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+/// Functions available to a running program, and the calls currently in progress.
+pub struct Runtime<'a> {
+    pub funcs: Vec<&'a Func>,
+    /// The program's functions resolved to their indices in `funcs`, and its structs laid out
+    resolved: &'a Resolved,
+    pub builtins: Arc<Builtins>,
+    pub max_call_depth: usize,
+    pub ints: IntMode,
+    pub interrupt: Interrupt,
+    /// The user function calls in progress, innermost last, with the span of each call site.
+    ///
+    /// Calls are left in place when an error propagates out of them, so that after a failed run
+    /// this is the stack at the point of failure.
+    pub calls: Vec<Spanned<&'a str>>,
+    /// The index of the first variable and the number of each call in progress including the
+    /// outermost, innermost last, which is where a pointer's variable is looked for
+    frames: Vec<(usize, usize)>,
+    /// How many of the first variables are the program's globals, which every call can use
+    globals: usize,
+    /// The number the next call will have, which no call has had before
+    next_call: usize,
+    /// The function the outermost call is to, usually `main`
+    pub entry: &'a str,
+    pub hooks: Vec<Box<dyn Hook>>,
+    pub context: Arc<dyn EvalContext>,
+    /// The program being run, which a spawned thread runs a copy of
+    program: &'a Ast,
+    /// The copy of `program` and of `resolved` the threads this one spawns share, made on its
+    /// first `spawn` unless it runs on one already
+    shared: Option<(Arc<Ast>, Arc<Resolved>)>,
+    /// The threads this one has spawned and not yet joined, by number
+    threads: HashMap<u64, JoinHandle<Result<Value, Diagnostic>>>,
+}
+
+/// The number the next spawned thread will have, which is unique across every thread so that a
+/// handle can't join a thread it wasn't given by.
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(0);
+
+impl<'a> Runtime<'a> {
+    /// Runs `func` with `args` bound to its parameters, as the outermost call, once the globals
+    /// are set, on a thread with the stack for `max_call_depth` nested calls.
+    fn call(self, func: &'a Func, args: Vec<Value>) -> Result<Value, Diagnostic> {
+        on_call_stack(self.max_call_depth, || self.call_here(func, args))?
+    }
+
+    /// Like [`Runtime::call`], but on the current thread, which has to have the stack for
+    /// `max_call_depth` nested calls.
+    fn call_here(mut self, func: &'a Func, args: Vec<Value>) -> Result<Value, Diagnostic> {
+        self.entry = &func.name;
+        let result = self.run(func, args);
+        for hook in &mut self.hooks {
+            hook.finish();
+        }
+        result.map_err(|diagnostic| self.backtrace(diagnostic))
+    }
+
+    /// Sets the globals and runs `func` with `args`.
+    fn run(&mut self, func: &'a Func, args: Vec<Value>) -> Result<Value, Diagnostic> {
+        let mut vars = Vec::new();
+        self.set_globals(&mut vars)?;
+        vars.extend(func.params.iter().map(|param| param.name).zip(args));
+        self.enter(&func.name);
+        let value = func.eval(&mut vars, self.globals, self)?;
+        self.exit(&func.name);
+        Ok(value)
+    }
+
+    /// Sets the program's globals at the bottom of `vars`, which is empty, in the order they're
+    /// defined.
+    fn set_globals(&mut self, vars: &mut Vec<(Symbol, Value)>) -> Result<(), Diagnostic> {
+        let program = self.program;
+        for def in &program.defs {
+            if let Definition::Global(global) = def {
+                let value = match &global.expr {
+                    Some(expr) => Expr::eval(expr, vars, 0, self)?.convert(&global.ty, self.ints),
+                    None => Value::zero(&global.ty),
+                };
+                vars.push((global.name, value));
+            }
+        }
+        self.globals = vars.len();
+        Ok(())
+    }
+
+    fn enter(&mut self, name: &str) {
+        for hook in &mut self.hooks {
+            hook.enter(name);
+        }
+    }
+
+    fn exit(&mut self, name: &str) {
+        for hook in &mut self.hooks {
+            hook.exit(name);
+        }
+    }
+
+    /// What a call to `name` runs, if it's a builtin or a function.
+    fn callee(&self, name: Symbol) -> Option<Callee> {
+        match self.builtins.resolve(name) {
+            Some(index) => Some(Callee::Builtin(index)),
+            None => self
+                .resolved
+                .funcs
+                .get(name.number())
+                .copied()
+                .flatten()
+                .map(Callee::Func),
+        }
+    }
+
+    /// The index in `funcs` of the function `name`, unless there's no such function or a builtin
+    /// takes its place.
+    fn func(&self, name: Symbol) -> Option<usize> {
+        match self.callee(name)? {
+            Callee::Func(index) => Some(index),
+            Callee::Builtin(_) => None,
+        }
+    }
+
+    /// Runs `func` in place of the function with its name from its next call on.
+    fn replace(&mut self, func: Func) {
+        let Some(index) = self.func(func.name) else {
+            return;
+        };
+        // the program's functions are borrowed for as long as it runs, which a replacement can
+        // only be by living as long as the process
+        self.funcs[index] = Box::leak(Box::new(func));
+    }
+
+    /// Starts a thread calling `name` with `args`, on a copy of the program of its own with globals
+    /// of its own, so that the only values it shares with this one are the ones it's passed,
+    /// returning its handle.
+    fn spawn(&mut self, name: Symbol, args: Vec<Value>, span: &Span) -> Result<Value, Diagnostic> {
+        let Some(callee) = self.callee(name) else {
+            return Err(runtime_error(span)(
+                Message::new("unknown-function").arg("name", name.as_str()),
+            ));
+        };
+        if args.iter().any(|arg| matches!(arg, Value::Pointer(_))) {
+            return Err(runtime_error(span)(Message::new("E0202.spawn-pointer"))
+                .with_note(Message::new("note.shared-variable"))
+                .with_note(Message::new("note.share-atomics")));
+        }
+
+        let (program, resolved) = (self.program, self.resolved);
+        let (program, resolved) = self
+            .shared
+            .get_or_insert_with(|| (Arc::new(program.clone()), Arc::new(resolved.clone())))
+            .clone();
+        let builtins = self.builtins.clone();
+        let (max_call_depth, ints) = (self.max_call_depth, self.ints);
+        let (interrupt, context) = (self.interrupt.clone(), self.context.clone());
+        let call_span = span.clone();
+        let thread = std::thread::Builder::new()
+            .name(name.to_string())
+            .stack_size(stack_size(max_call_depth))
+            .spawn(move || {
+                let index = match callee {
+                    Callee::Builtin(index) => {
+                        return builtins.at(index)(&args).map_err(runtime_error(&call_span))
+                    }
+                    Callee::Func(index) => index,
+                };
+                // the copy of the program resolves every call as this one does
+                let mut runtime = program.thread_runtime(
+                    &resolved,
+                    builtins,
+                    max_call_depth,
+                    ints,
+                    interrupt,
+                    context,
+                );
+                runtime.shared = Some((program.clone(), resolved.clone()));
+                let func = runtime.funcs[index];
+                if args.len() != func.params.len() {
+                    return Err(arity_error(func, args.len(), &call_span));
+                }
+                runtime.call_here(func, args)
+            })
+            .map_err(|e| {
+                runtime_error(span)(Message::new("E0202.spawn").arg("error", e.to_string()))
+            })?;
+
+        let id = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+        self.threads.insert(id, thread);
+        Ok(Value::Thread(id))
+    }
 
-        let Some(main_func) = funcs.get("main") else {
-            panic!("main function not found");
+    /// Waits for the thread `id` this one spawned to finish, returning the value its function
+    /// returned or the error it failed with.
+    fn join(&mut self, id: u64, span: &Span) -> Result<Value, Diagnostic> {
+        let Some(thread) = self.threads.remove(&id) else {
+            return Err(runtime_error(span)(Message::new("E0202.joined")));
         };
+        match thread.join() {
+            Ok(result) => result
+                .map_err(|error| error.with_label(span.clone(), Message::new("label.joined-here"))),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// `lhs op rhs` for the operator at `span`, as the context evaluates it.
+    fn binary(&self, op: BinOp, lhs: i64, rhs: i64, span: &Span) -> Result<i64, Diagnostic> {
+        self.context
+            .binary(op, lhs, rhs, self.ints, span)
+            .map_err(arith_error(span))
+    }
+
+    /// `lhs op rhs` for the operator at `span`, in floats if either is a float. The operands are
+    /// ints or floats.
+    fn numeric(&self, op: BinOp, lhs: Value, rhs: Value, span: &Span) -> Result<Value, Diagnostic> {
+        if let Some(value) = Value::float_binary(op, &lhs, &rhs) {
+            return Ok(value);
+        }
+        let lhs = lhs.as_int().map_err(runtime_error(span))?;
+        let rhs = rhs.as_int().map_err(runtime_error(span))?;
+        self.binary(op, lhs, rhs, span).map(Value::Int)
+    }
+
+    /// `lhs + rhs` for the `+` at `span`, which concatenates if either is a string.
+    fn add(&self, lhs: Value, rhs: Value, span: &Span) -> Result<Value, Diagnostic> {
+        match (lhs, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => {
+                self.binary(BinOp::Add, lhs, rhs, span).map(Value::Int)
+            }
+            (lhs, rhs) => lhs.add(rhs, self.ints).map_err(runtime_error(span)),
+        }
+    }
+
+    /// A pointer to the variable at `slot`, which belongs to the innermost call whose variables
+    /// start at or before it, the globals belonging to the outermost.
+    fn pointer(&self, slot: usize) -> Value {
+        let (_, call) = self
+            .frames
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= slot)
+            .copied()
+            .unwrap_or_default();
+        Value::Pointer(Pointer { slot, call })
+    }
+
+    /// The index in `vars` of the variable `pointer` points to, if the call it belongs to hasn't
+    /// returned.
+    fn slot(
+        &self,
+        pointer: Pointer,
+        vars: &[(Symbol, Value)],
+        span: &Span,
+    ) -> Result<usize, Diagnostic> {
+        let owner = self
+            .frames
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= pointer.slot);
+        match owner {
+            Some((_, call)) if *call == pointer.call && pointer.slot < vars.len() => {
+                Ok(pointer.slot)
+            }
+            _ => Err(runtime_error(span)(Message::new("E0202.dangling-pointer"))),
+        }
+    }
+
+    /// Adds a backtrace of the calls in progress to `diagnostic`.
+    fn backtrace(&self, diagnostic: Diagnostic) -> Diagnostic {
+        backtrace(&self.calls, diagnostic)
+    }
+}
+
+/// Adds a backtrace of `calls`, the user function calls in progress innermost last, to
+/// `diagnostic`, labelling each call site.
+///
+/// Directly recursive calls are collapsed into a single frame.
+pub(crate) fn backtrace(calls: &[Spanned<&str>], mut diagnostic: Diagnostic) -> Diagnostic {
+    if calls.is_empty() {
+        return diagnostic;
+    }
+
+    let mut frames = Vec::<(&str, usize)>::new();
+    let mut labelled = diagnostic
+        .labels
+        .iter()
+        .map(|label| label.span.clone())
+        .collect::<Vec<_>>();
+    let mut remaining_labels = MAX_BACKTRACE_LABELS;
+    for (name, span) in calls.iter().rev() {
+        match frames.last_mut() {
+            Some((last, count)) if last == name => *count += 1,
+            _ => frames.push((name, 1)),
+        }
 
-        main_func.clone().eval(&mut Vec::new(), &mut funcs)
+        if remaining_labels > 0 && !labelled.contains(span) {
+            remaining_labels -= 1;
+            labelled.push(span.clone());
+            diagnostic = diagnostic.with_label(
+                span.clone(),
+                Message::new("label.func-called").arg("name", *name),
+            );
+        }
     }
+    frames.push(("main", 1));
+
+    let trace = frames
+        .iter()
+        .enumerate()
+        .map(|(i, (name, count))| {
+            let frame = match count {
+                1 => Message::new("backtrace.frame"),
+                _ => Message::new("backtrace.recursive-frame").arg("count", count.to_string()),
+            };
+            frame.arg("index", format!("{i:>4}")).arg("name", *name)
+        })
+        .collect();
+    diagnostic.with_note(Message::new("note.backtrace").arg("trace", Message::Lines(trace)))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Definition {
-    Struct { name: String, params: Vec<Param> },
+    Struct {
+        name: Symbol,
+        params: Vec<Param>,
+        span: Span,
+    },
     Func(Func),
+    /// `import "path";`, resolved and merged away by the compile pipeline
+    Import {
+        path: String,
+        span: Span,
+    },
+    Macro(Macro),
+    /// `ret name(params);`, declaring a function that's defined elsewhere in the program
+    Prototype(Prototype),
+    Global(Global),
 }
 
 impl Definition {
-    fn parser() -> impl Parser<Token, Self, Error = Simple<Token>> {
+    pub fn parser() -> impl Parser<Token, Self, Error = Simple<Token>> {
         let r#struct = just(Token::Struct)
-            .ignore_then(parse_ident())
+            .ignore_then(parse_ident().map_with_span(|name, span| (name, span)))
             .then(
                 Param::parser()
                     .then_ignore(just(Token::Ctrl(';')))
@@ -68,10 +955,45 @@ impl Definition {
                     )),
             )
             .then_ignore(just(Token::Ctrl(';')))
-            .map(|(name, params)| Definition::Struct { name, params });
+            .map(|((name, span), params)| Definition::Struct { name, params, span });
 
-        let func = parse_ident()
-            .then(parse_ident())
+        let prototype = parse_type()
+            .then(parse_ident().map_with_span(|name, span| (name, span)))
+            .then(
+                Param::parser()
+                    .separated_by(just(Token::Ctrl(',')))
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .then_ignore(just(Token::Ctrl(';')))
+            .map(|((ret, (name, span)), params)| {
+                Self::Prototype(Prototype {
+                    name,
+                    params,
+                    ret,
+                    span,
+                })
+            });
+
+        let global = just(Token::Const)
+            .or_not()
+            .then(parse_type())
+            .then(parse_ident().map_with_span(|name, span| (name, span)))
+            .then(just(Token::Op("=")).ignore_then(Expr::parser()).or_not())
+            .then_ignore(just(Token::Ctrl(';')))
+            .map(|(((constant, ty), (name, span)), expr)| {
+                Self::Global(Global {
+                    ty,
+                    name,
+                    expr,
+                    constant: constant.is_some(),
+                    span,
+                })
+            });
+
+        let func = just(Token::Comptime)
+            .or_not()
+            .then(parse_type())
+            .then(parse_ident().map_with_span(|name, span| (name, span)))
             .then(
                 Param::parser()
                     .separated_by(just(Token::Ctrl(',')))
@@ -83,110 +1005,779 @@ impl Definition {
                         |_| Vec::new(),
                     )),
             )
+            .then(body())
+            .map(|((((comptime, ret), (name, span)), params), body)| {
+                Self::Func(Func {
+                    name,
+                    params,
+                    ret,
+                    body,
+                    span,
+                    comptime: comptime.is_some(),
+                })
+            });
+
+        let r#macro = just(Token::Macro)
+            .ignore_then(parse_ident().map_with_span(|name, span| (name, span)))
             .then(
-                Statement::parser()
-                    .repeated()
-                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+                parse_ident()
+                    .map_with_span(|param, span| (param, span))
+                    .separated_by(just(Token::Ctrl(',')))
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
                     .recover_with(recovery::nested_delimiters(
-                        Token::Ctrl('{'),
-                        Token::Ctrl('}'),
+                        Token::Ctrl('('),
+                        Token::Ctrl(')'),
                         [],
                         |_| Vec::new(),
                     )),
             )
-            .map(|(((ret, name), params), body)| {
-                Self::Func(Func {
+            .then(body())
+            .map(|(((name, span), params), body)| {
+                Self::Macro(Macro {
                     name,
                     params,
-                    ret,
                     body,
+                    span,
                 })
             });
 
-        r#struct.or(func)
+        let import = just(Token::Import)
+            .ignore_then(select! { Token::Str(path) => path })
+            .then_ignore(just(Token::Ctrl(';')))
+            .map_with_span(|path, span| Definition::Import { path, span });
+
+        // misspelled keywords are reported, and then parsed as if they'd been spelled correctly
+        let struct_typo = keyword_typo(Token::Struct)
+            .ignore_then(parse_ident().map_with_span(|name, span| (name, span)))
+            .then(
+                Param::parser()
+                    .then_ignore(just(Token::Ctrl(';')))
+                    .repeated()
+                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
+            )
+            .then_ignore(just(Token::Ctrl(';')))
+            .map(|((name, span), params)| Definition::Struct { name, params, span });
+        let import_typo = keyword_typo(Token::Import)
+            .ignore_then(select! { Token::Str(path) => path })
+            .then_ignore(just(Token::Ctrl(';')))
+            .map_with_span(|path, span| Definition::Import { path, span });
+
+        import
+            .or(r#struct)
+            .or(r#macro)
+            .or(prototype)
+            .or(func)
+            .or(global)
+            .or(struct_typo)
+            .or(import_typo)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Func {
-    pub name: String,
-    pub params: Vec<Param>,
-    pub ret: String,
-    pub body: Vec<Statement>,
+/// Formats every definition, with the statements of each function body on their own lines.
+impl Display for Ast {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for def in &self.defs {
+            writeln!(f, "{def}")?;
+            let body = match def {
+                Definition::Func(func) => &func.body[..],
+                Definition::Macro(r#macro) => &r#macro.body,
+                Definition::Struct { .. }
+                | Definition::Import { .. }
+                | Definition::Prototype(_)
+                | Definition::Global(_) => &[],
+            };
+            for (statement, _) in body {
+                writeln!(f, "  {statement}")?;
+            }
+        }
+        Ok(())
+    }
 }
 
-impl Func {
-    fn eval(&self, vars: &mut Vec<(String, i32)>, funcs: &mut HashMap<String, Func>) -> i32 {
-        for statement in &self.body {
-            match statement.eval(vars, funcs) {
-                Some(value) => return value,
-                None => (),
+/// Formats the signature of a definition, without any function body.
+impl Display for Definition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let params = |params: &[Param]| {
+            params
+                .iter()
+                .map(|param| format!("{} {}", param.ty, param.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Self::Struct {
+                name, params: p, ..
+            } => write!(f, "struct {name} {{ {} }}", params(p)),
+            Self::Func(func) => write!(
+                f,
+                "{}func {}({}) -> {}",
+                if func.comptime { "@comptime " } else { "" },
+                func.name,
+                params(&func.params),
+                func.ret
+            ),
+            Self::Import { path, .. } => write!(f, "import {}", literal::escape(path)),
+            Self::Macro(r#macro) => {
+                let params = r#macro
+                    .params
+                    .iter()
+                    .map(|(param, _)| param.as_str())
+                    .collect::<Vec<_>>();
+                write!(f, "macro {}({})", r#macro.name, params.join(", "))
+            }
+            Self::Prototype(prototype) => write!(
+                f,
+                "declare func {}({}) -> {}",
+                prototype.name,
+                params(&prototype.params),
+                prototype.ret
+            ),
+            Self::Global(global) => {
+                let keyword = if global.constant { "const" } else { "global" };
+                write!(f, "{keyword} {} {}", global.ty, global.name)?;
+                match &global.expr {
+                    Some((expr, _)) => write!(f, " = {expr}"),
+                    None => Ok(()),
+                }
             }
         }
-
-        panic!("reached end of function with no return");
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Func {
+    pub name: Symbol,
+    pub params: Vec<Param>,
+    pub ret: Symbol,
+    pub body: Vec<Spanned<Statement>>,
+    /// Span of the function's name
+    pub span: Span,
+    /// Whether the function is annotated `@comptime`, so that `build` evaluates calls to it with
+    /// constant arguments. The calls are replaced before the IR is written, so it isn't kept there.
+    #[serde(skip)]
+    pub comptime: bool,
+}
+
+impl Func {
+    /// Runs the body of the function, whose arguments have been bound at the start of its frame.
+    ///
+    /// `vars` holds the variables of every call in progress, outermost first, and those of the
+    /// current call start at `frame`.
+    fn eval<'a>(
+        &'a self,
+        vars: &mut Vec<(Symbol, Value)>,
+        frame: usize,
+        runtime: &mut Runtime<'a>,
+    ) -> Result<Value, Diagnostic> {
+        let ints = runtime.ints;
+        for (var, param) in vars[frame..].iter_mut().zip(&self.params) {
+            var.1 = mem::replace(&mut var.1, Value::Int(0)).convert(&param.ty, ints);
+        }
+        for statement in &self.body {
+            if let Some(value) = Statement::eval(statement, vars, frame, runtime)?.into_value()? {
+                return Ok(value.convert(&self.ret, ints));
+            }
+        }
+
+        // a void function's value is never used, so any will do
+        match self.ret == VOID {
+            true => Ok(Value::Int(0)),
+            false => Err(missing_return(self)),
+        }
+    }
+}
+
+/// The signature of a function declared before it's defined, so that it reads as in C. Every
+/// function can be called from anywhere in the program whether or not it's declared, so a
+/// prototype only has to match the function's definition.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prototype {
+    pub name: Symbol,
+    pub params: Vec<Param>,
+    pub ret: Symbol,
+    /// Span of the function's name
+    pub span: Span,
+}
+
+/// `ty name = expr;` outside of any function, a variable that every function can use unless it
+/// declares one with the same name. A `const` one can't be assigned to.
+///
+/// Globals are set when the program starts, in the order they're defined, so the value of one
+/// can only use those defined before it, and has to be worked out without calling anything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Global {
+    pub ty: Symbol,
+    pub name: Symbol,
+    /// The value it starts with, or `None` for zero or an empty string
+    pub expr: Option<Spanned<Expr>>,
+    pub constant: bool,
+    /// Span of its name
+    pub span: Span,
+}
+
+/// `macro name(params) { body }`, whose expansions `name!(args);` are replaced by its body when
+/// the `macros` feature is enabled. Macros are expanded by the compile pipeline, so they never
+/// reach the IR.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: Symbol,
+    pub params: Vec<Spanned<Symbol>>,
+    pub body: Vec<Spanned<Statement>>,
+    /// Span of the macro's name
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Param {
-    pub name: String,
-    pub ty: String,
+    pub name: Symbol,
+    pub ty: Symbol,
+    pub span: Span,
 }
 
 impl Param {
     fn parser() -> impl Parser<Token, Self, Error = Simple<Token>> {
-        parse_ident()
+        parse_type()
             .then(parse_ident())
-            .map(|(ty, name)| Self { name, ty })
+            .map_with_span(|(ty, name), span| Self { name, ty, span })
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Statement {
     Invalid,
-    Return(Box<Expr>),
+    Return(Box<Spanned<Expr>>),
     Assign {
-        ty: String,
-        name: String,
-        expr: Box<Expr>,
+        ty: Symbol,
+        name: Symbol,
+        expr: Box<Spanned<Expr>>,
+    },
+    Array {
+        ty: Symbol,
+        name: Symbol,
+        len: u32,
+    },
+    Store {
+        name: Symbol,
+        index: Box<Spanned<Expr>>,
+        expr: Box<Spanned<Expr>>,
     },
+    /// `name = expr;`, or a compound assignment like `name += expr;`, to a declared variable
+    Reassign {
+        name: Symbol,
+        op: AssignOp,
+        expr: Box<Spanned<Expr>>,
+    },
+    /// `name!(args);`, replaced by the body of the macro `name` by the compile pipeline
+    Expand {
+        name: Symbol,
+        args: Vec<Spanned<Expr>>,
+    },
+    /// `return;`, leaving a void function
+    ReturnVoid,
+    /// An expression evaluated for its side effects, such as a call, discarding its value
+    Expr(Box<Spanned<Expr>>),
+    /// `__asm { code }` or `__ir { code }`, hand-written code that the backend for `lang` copies
+    /// into its output and nothing else can run
+    Inline {
+        lang: InlineLang,
+        code: String,
+    },
+    /// `*pointer = expr;`, assigning to the variable a pointer points to
+    Write {
+        pointer: Box<Spanned<Expr>>,
+        expr: Box<Spanned<Expr>>,
+    },
+    /// `switch (expr) { cases }`, running the statements from the case whose value the int
+    /// equals, or else from `default`, up to a `break` or the end of the switch
+    Switch {
+        expr: Box<Spanned<Expr>>,
+        cases: Vec<Case>,
+    },
+    /// `break;`, leaving the switch it's in
+    Break,
+    /// `continue;`, which would go on to the next iteration of a loop, but there are none yet
+    Continue,
+}
+
+/// A `case value:` or `default:` label of a [`Statement::Switch`], and the statements after it
+/// up to the next label. A label with no statements falls through to the next one, as does the
+/// end of any case that doesn't `break`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Case {
+    /// The value the case matches, or `None` for `default`
+    pub value: Option<i64>,
+    /// Span of the label
+    pub span: Span,
+    pub body: Vec<Spanned<Statement>>,
+}
+
+/// The backend a [`Statement::Inline`] is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InlineLang {
+    /// x86-64 assembly in GNU syntax, for the native backend
+    Asm,
+    /// LLVM IR, for the LLVM backend
+    Ir,
+}
+
+impl InlineLang {
+    /// The word that starts a block in this language, e.g. `__asm`.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Self::Asm => INLINE_BLOCKS[0],
+            Self::Ir => INLINE_BLOCKS[1],
+        }
+    }
+}
+
+/// A piece of the code in a [`Statement::Inline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlinePiece<'a> {
+    Text(&'a str),
+    /// `{name}`, which the backend replaces with where the variable `name` is stored
+    Var(&'a str),
+}
+
+/// Splits the code of an inline block into text and the variables it names. Braces around
+/// anything but an identifier, like an LLVM struct type, are kept as text.
+pub fn inline_pieces(code: &str) -> Vec<InlinePiece<'_>> {
+    let mut pieces = Vec::new();
+    // where the text not yet added starts, and where to look for the next `{`
+    let (mut text, mut from) = (0, 0);
+    while let Some(open) = code[from..].find('{').map(|i| from + i) {
+        let name = code[open + 1..]
+            .split_once('}')
+            .map(|(name, _)| name)
+            .filter(|name| {
+                name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+        let Some(name) = name else {
+            from = open + 1;
+            continue;
+        };
+        if text < open {
+            pieces.push(InlinePiece::Text(&code[text..open]));
+        }
+        pieces.push(InlinePiece::Var(name));
+        text = open + name.len() + 2;
+        from = text;
+    }
+    if text < code.len() {
+        pieces.push(InlinePiece::Text(&code[text..]));
+    }
+    pieces
+}
+
+/// The operator of a [`Statement::Reassign`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssignOp {
+    Set,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl AssignOp {
+    pub const ALL: [Self; 5] = [Self::Set, Self::Add, Self::Sub, Self::Mul, Self::Div];
+
+    /// The operator as it's written, e.g. `+=`.
+    pub fn text(self) -> &'static str {
+        match self {
+            Self::Set => "=",
+            Self::Add => "+=",
+            Self::Sub => "-=",
+            Self::Mul => "*=",
+            Self::Div => "/=",
+        }
+    }
 }
 
 impl Statement {
-    fn parser() -> impl Parser<Token, Self, Error = Simple<Token>> {
-        let ret = just(Token::Return)
-            .ignore_then(Expr::parser())
-            .then_ignore(just(Token::Ctrl(';')))
-            .map(|expr| Self::Return(Box::new(expr)));
+    pub fn parser() -> impl Parser<Token, Spanned<Self>, Error = Simple<Token>> {
+        recursive(|statement| {
+            let ret = just(Token::Return)
+                .ignore_then(Expr::parser())
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|expr| Self::Return(Box::new(expr)));
 
-        let assign = parse_ident()
-            .then(parse_ident())
-            .then_ignore(just(Token::Op('=')))
-            .then(Expr::parser())
-            .then_ignore(just(Token::Ctrl(';')))
-            .map(|((ty, name), expr)| Self::Assign {
-                ty,
-                name,
-                expr: Box::new(expr),
+            let ret_void = just(Token::Return)
+                .then(just(Token::Ctrl(';')))
+                .to(Self::ReturnVoid);
+
+            let assign = parse_type()
+                .then(parse_ident())
+                .then_ignore(just(Token::Op("=")))
+                .then(Expr::parser())
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|((ty, name), expr)| Self::Assign {
+                    ty,
+                    name,
+                    expr: Box::new(expr),
+                });
+
+            let array = parse_type()
+                .then(parse_ident())
+                .then(
+                    int_literal(literal::INT_MAX)
+                        .map(Option::unwrap_or_default)
+                        .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']'))),
+                )
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|((ty, name), len)| Self::Array { ty, name, len });
+
+            let store = parse_ident()
+                .then(
+                    Expr::parser()
+                        .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
+                        .recover_with(recovery::nested_delimiters(
+                            Token::Ctrl('['),
+                            Token::Ctrl(']'),
+                            [(Token::Ctrl('('), Token::Ctrl(')'))],
+                            |span| (Expr::Err, span),
+                        )),
+                )
+                .then_ignore(just(Token::Op("=")))
+                .then(Expr::parser())
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|((name, index), expr)| Self::Store {
+                    name,
+                    index: Box::new(index),
+                    expr: Box::new(expr),
+                });
+
+            let reassign = parse_ident()
+                .then(choice(
+                    AssignOp::ALL
+                        .iter()
+                        .map(|op| just(Token::Op(op.text())).to(*op))
+                        .collect::<Vec<_>>(),
+                ))
+                .then(Expr::parser())
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|((name, op), expr)| Self::Reassign {
+                    name,
+                    op,
+                    expr: Box::new(expr),
+                });
+
+            let expand = parse_ident()
+                .then_ignore(just(Token::Op("!")))
+                .then(
+                    Expr::parser()
+                        .separated_by(just(Token::Ctrl(',')))
+                        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+                )
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|(name, args)| Self::Expand { name, args });
+
+            let write = Expr::parser()
+                .try_map(|(pointer, span), _| match pointer {
+                    Expr::Deref(pointer) => Ok(pointer),
+                    _ => Err(Simple::expected_input_found(span, [], None)),
+                })
+                .then_ignore(just(Token::Op("=")))
+                .then(Expr::parser())
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|(pointer, expr)| Self::Write {
+                    pointer,
+                    expr: Box::new(expr),
+                });
+
+            // `int * p;` would multiply two variables if declaring a pointer weren't the likelier
+            // meaning, though without a value it's neither
+            let discard = pointer_type()
+                .not()
+                .rewind()
+                .ignore_then(Expr::parser())
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|expr| Self::Expr(Box::new(expr)));
+
+            let inline = select! {
+                Token::Asm(code) => (InlineLang::Asm, code),
+                Token::Ir(code) => (InlineLang::Ir, code),
+            }
+            .map(|(lang, code): (_, String)| {
+                // indentation and blank lines at either end don't matter to either backend, so they
+                // aren't kept and the formatter is free to indent the code with the block
+                let lines = code.lines().map(str::trim).collect::<Vec<_>>();
+                let start = lines.iter().position(|line| !line.is_empty());
+                let end = lines.iter().rposition(|line| !line.is_empty());
+                let code = match (start, end) {
+                    (Some(start), Some(end)) => lines[start..=end].join("\n"),
+                    _ => String::new(),
+                };
+                Self::Inline { lang, code }
             });
 
-        ret.or(assign)
+            let ret_typo = keyword_typo(Token::Return)
+                .ignore_then(Expr::parser())
+                .then_ignore(just(Token::Ctrl(';')))
+                .map(|expr| Self::Return(Box::new(expr)));
+
+            // a label is an int literal, which may be negated but must fit in an int, and one
+            // with no value has been reported already
+            let label = just(Token::Op("-"))
+                .ignore_then(int_literal(literal::NEGATED_INT_MAX))
+                .map(|value| value.map_or(0, |value| -i64::from(value)))
+                .or(int_literal(literal::INT_MAX).map(|value| value.map_or(0, i64::from)));
+            // the statements of a case run up to the next label or the end of the switch
+            let case_body = choice((
+                just(Token::Ctrl('}')),
+                just(Token::Case),
+                just(Token::Default),
+            ))
+            .not()
+            .rewind()
+            .ignore_then(recovering(statement))
+            .repeated();
+            let case = just(Token::Case)
+                .ignore_then(label.map(Some))
+                .or(just(Token::Default).to(None))
+                .map_with_span(|value, span| (value, span))
+                .then_ignore(just(Token::Op(":")))
+                .then(case_body)
+                .map(|((value, span), body)| Case { value, span, body });
+            let switch = just(Token::Switch)
+                .ignore_then(
+                    Expr::parser().delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+                )
+                .then(
+                    case.repeated()
+                        .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
+                )
+                .map(|(expr, cases)| Self::Switch {
+                    expr: Box::new(expr),
+                    cases,
+                });
+
+            let r#break = just(Token::Break)
+                .then(just(Token::Ctrl(';')))
+                .to(Self::Break);
+            let r#continue = just(Token::Continue)
+                .then(just(Token::Ctrl(';')))
+                .to(Self::Continue);
+
+            ret.or(ret_void)
+                .or(switch)
+                .or(r#break)
+                .or(r#continue)
+                .or(assign)
+                .or(array)
+                .or(store)
+                .or(reassign)
+                .or(expand)
+                .or(write)
+                .or(discard)
+                .or(inline)
+                .or(ret_typo)
+                .or(unsupported_statement())
+                .map_with_span(|statement, span| (statement, span))
+        })
     }
 
-    fn eval(
-        &self,
-        vars: &mut Vec<(String, i32)>,
-        funcs: &mut HashMap<String, Func>,
-    ) -> Option<i32> {
+    fn eval<'a>(
+        spanned: &'a Spanned<Self>,
+        vars: &mut Vec<(Symbol, Value)>,
+        frame: usize,
+        runtime: &mut Runtime<'a>,
+    ) -> Result<ControlFlow, Diagnostic> {
+        let (statement, span) = spanned;
+        runtime.interrupt.check(span)?;
+        let mut replacements = Vec::new();
+        for hook in &mut runtime.hooks {
+            let state = State {
+                func: runtime.calls.last().map_or(runtime.entry, |(name, _)| name),
+                calls: &runtime.calls,
+                vars: &vars[frame..],
+                funcs: &runtime.funcs,
+            };
+            hook.before_statement(spanned, state)?;
+            replacements.extend(hook.replacements());
+        }
+        for func in replacements {
+            runtime.replace(func);
+        }
+
+        match statement {
+            Self::Invalid => Err(runtime_error(span)(Message::new("E0202.invalid-statement"))),
+            Self::Expand { name, .. } => Err(runtime_error(span)(
+                Message::new("E0202.unexpanded-macro").arg("name", name.as_str()),
+            )),
+            Self::Inline { lang, .. } => Err(runtime_error(span)(
+                Message::new("E0202.inline-code").arg("block", lang.keyword()),
+            )
+            .with_note(Message::new("note.inline-backends"))),
+            Self::Return(expr) => Ok(ControlFlow::Return(Expr::eval(expr, vars, frame, runtime)?)),
+            Self::ReturnVoid => Ok(ControlFlow::Return(Value::Int(0))),
+            Self::Expr(expr) => {
+                Expr::eval(expr, vars, frame, runtime)?;
+                Ok(ControlFlow::Normal)
+            }
+            Self::Assign { ty, name, expr } => {
+                let value = Expr::eval(expr, vars, frame, runtime)?.convert(ty, runtime.ints);
+                vars.push((*name, value));
+                Ok(ControlFlow::Normal)
+            }
+            Self::Array { ty, name, len } => {
+                let elem = Value::zero(ty);
+                vars.push((*name, Value::Array(vec![elem; *len as usize])));
+                Ok(ControlFlow::Normal)
+            }
+            Self::Store {
+                name,
+                index: index_expr,
+                expr,
+            } => {
+                let index = Expr::eval_int(index_expr, vars, frame, runtime)?;
+                let value = Expr::eval(expr, vars, frame, runtime)?;
+                let ints = runtime.ints;
+                let elem = var_mut(vars, frame, runtime.globals, name, span)?
+                    .element_mut(index)
+                    .map_err(runtime_error(&index_expr.1))?;
+                *elem = value.convert_like(elem, ints);
+                Ok(ControlFlow::Normal)
+            }
+            Self::Reassign { name, op, expr } => {
+                // the variable is read before the value is evaluated, as it is in `x = x + 1`
+                let current = match op {
+                    AssignOp::Set => None,
+                    AssignOp::Add => {
+                        Some(var_mut(vars, frame, runtime.globals, name, span)?.clone())
+                    }
+                    AssignOp::Sub | AssignOp::Mul | AssignOp::Div => Some(
+                        number(var_mut(vars, frame, runtime.globals, name, span)?.clone())
+                            .map_err(runtime_error(span))?,
+                    ),
+                };
+
+                let value = match (op, current) {
+                    (AssignOp::Set, _) | (_, None) => Expr::eval(expr, vars, frame, runtime)?,
+                    (AssignOp::Add, Some(current)) => {
+                        let value = Expr::eval(expr, vars, frame, runtime)?;
+                        runtime.add(current, value, span)?
+                    }
+                    (op, Some(current)) => {
+                        let rhs = Expr::eval_number(expr, vars, frame, runtime)?;
+                        let op = match op {
+                            AssignOp::Sub => BinOp::Sub,
+                            AssignOp::Mul => BinOp::Mul,
+                            _ => BinOp::Div,
+                        };
+                        runtime.numeric(op, current, rhs, span)?
+                    }
+                };
+                let ints = runtime.ints;
+                let var = var_mut(vars, frame, runtime.globals, name, span)?;
+                *var = value.convert_like(var, ints);
+                Ok(ControlFlow::Normal)
+            }
+            Self::Write { pointer, expr } => {
+                let target = Expr::eval(pointer, vars, frame, runtime)?
+                    .as_pointer()
+                    .map_err(runtime_error(&pointer.1))?;
+                let value = Expr::eval(expr, vars, frame, runtime)?;
+                let slot = runtime.slot(target, vars, span)?;
+                vars[slot].1 = value.convert_like(&vars[slot].1, runtime.ints);
+                Ok(ControlFlow::Normal)
+            }
+            Self::Switch { expr, cases } => {
+                let value = Expr::eval_int(expr, vars, frame, runtime)?;
+                let start = cases
+                    .iter()
+                    .position(|case| case.value == Some(value))
+                    .or_else(|| cases.iter().position(|case| case.value.is_none()));
+                let Some(start) = start else {
+                    return Ok(ControlFlow::Normal);
+                };
+
+                let len = vars.len();
+                for statement in cases[start..].iter().flat_map(|case| &case.body) {
+                    match Self::eval(statement, vars, frame, runtime)? {
+                        ControlFlow::Normal => {}
+                        ControlFlow::Break(_) => break,
+                        flow => {
+                            vars.truncate(len);
+                            return Ok(flow);
+                        }
+                    }
+                }
+                // sema keeps cases from declaring variables, but the REPL doesn't run it
+                vars.truncate(len);
+                Ok(ControlFlow::Normal)
+            }
+            Self::Break => Ok(ControlFlow::Break(span.clone())),
+            Self::Continue => Ok(ControlFlow::Continue(span.clone())),
+        }
+    }
+}
+
+/// Where running a statement goes next.
+enum ControlFlow {
+    /// On to the statement after it
+    Normal,
+    /// Out of the function, with the value it returns
+    Return(Value),
+    /// Out of the switch it's in, from the `break` at the span
+    Break(Span),
+    /// On to the next iteration of the loop it's in, from the `continue` at the span
+    Continue(Span),
+}
+
+impl ControlFlow {
+    /// The value returned, if any, once the statement has left every switch it could.
+    fn into_value(self) -> Result<Option<Value>, Diagnostic> {
         match self {
-            Self::Invalid => panic!("reached invalid statement"),
-            Self::Return(expr) => Some(expr.eval(vars, funcs)),
-            Self::Assign { ty: _, name, expr } => {
-                let value = expr.eval(vars, funcs);
-                vars.push((name.clone(), value));
-                None
+            Self::Normal => Ok(None),
+            Self::Return(value) => Ok(Some(value)),
+            Self::Break(span) => Err(runtime_error(&span)(Message::new("E0202.break"))),
+            Self::Continue(span) => Err(runtime_error(&span)(Message::new("E0202.continue"))),
+        }
+    }
+}
+
+/// Formats a statement as a prefix expression, e.g. `(let int x (+ a 1))`.
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid => write!(f, "(invalid)"),
+            Self::Return(expr) => write!(f, "(return {})", expr.0),
+            Self::ReturnVoid => write!(f, "(return)"),
+            Self::Expr(expr) => write!(f, "(discard {})", expr.0),
+            Self::Inline { lang, code } => {
+                write!(f, "({} {})", lang.keyword(), literal::escape(code))
+            }
+            Self::Assign { ty, name, expr } => write!(f, "(let {ty} {name} {})", expr.0),
+            Self::Array { ty, name, len } => write!(f, "(array {ty} {name} {len})"),
+            Self::Store { name, index, expr } => {
+                write!(f, "(store {name} {} {})", index.0, expr.0)
+            }
+            Self::Reassign { name, op, expr } => write!(f, "({} {name} {})", op.text(), expr.0),
+            Self::Write { pointer, expr } => write!(f, "(write {} {})", pointer.0, expr.0),
+            Self::Expand { name, args } => {
+                write!(f, "({name}!")?;
+                for (arg, _) in args {
+                    write!(f, " {arg}")?;
+                }
+                write!(f, ")")
+            }
+            Self::Switch { expr, cases } => {
+                write!(f, "(switch {}", expr.0)?;
+                for case in cases {
+                    match case.value {
+                        Some(value) => write!(f, " (case {value}")?,
+                        None => write!(f, " (default")?,
+                    }
+                    for (statement, _) in &case.body {
+                        write!(f, " {statement}")?;
+                    }
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
             }
+            Self::Break => write!(f, "(break)"),
+            Self::Continue => write!(f, "(continue)"),
         }
     }
 }
@@ -195,107 +1786,828 @@ impl Statement {
 pub enum Expr {
     Err,
     Int(u32),
-    Neg(Box<Expr>),
-    Mul(Box<Expr>, Box<Expr>),
-    Div(Box<Expr>, Box<Expr>),
-    Add(Box<Expr>, Box<Expr>),
-    Sub(Box<Expr>, Box<Expr>),
-    Var(String),
-    Call { name: String, params: Vec<Expr> },
+    Str(String),
+    Neg(Box<Spanned<Expr>>),
+    Mul(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Div(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Add(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Sub(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Var(Symbol),
+    Index(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Call {
+        name: Symbol,
+        params: Vec<Spanned<Expr>>,
+    },
+    // binary IR writes variants by index, so ones added later go last to keep old files readable
+    Rem(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    BitAnd(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    BitOr(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    BitXor(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Shl(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Shr(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    /// `!expr`, which is 1 if the int is 0 and 0 otherwise
+    Not(Box<Spanned<Expr>>),
+    /// `++name`, incrementing an int variable and evaluating to its new value
+    PreInc(Symbol),
+    /// `--name`, decrementing an int variable and evaluating to its new value
+    PreDec(Symbol),
+    /// The comparisons, which are 1 if they hold and 0 otherwise
+    Lt(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Le(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Gt(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Ge(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Eq(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Ne(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    /// `cond ? then : otherwise`, which evaluates only the branch the int `cond` picks
+    Cond(Box<Spanned<Expr>>, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    /// `&name`, a pointer to a variable
+    AddrOf(Symbol),
+    /// `*expr`, the value of the variable the pointer `expr` points to
+    Deref(Box<Spanned<Expr>>),
+    /// `sizeof(type)`, the size of a type in bytes, which the compile pipeline folds to an int
+    SizeOf(Symbol),
+    /// `spawn name(params)`, calling a function on a new thread and evaluating to its handle
+    Spawn {
+        name: Symbol,
+        params: Vec<Spanned<Expr>>,
+    },
+    /// `join(handle)`, waiting for a spawned thread and evaluating to what its function returned
+    Join(Box<Spanned<Expr>>),
+    /// `lhs && rhs`, which is 1 if both ints are nonzero, evaluating `rhs` only if `lhs` is
+    And(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    /// `lhs || rhs`, which is 1 if either int is nonzero, evaluating `rhs` only if `lhs` isn't
+    Or(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Float(f64),
 }
 
 impl Expr {
-    fn parser() -> impl Parser<Token, Self, Error = Simple<Token>> {
+    pub fn parser() -> impl Parser<Token, Spanned<Self>, Error = Simple<Token>> {
         recursive(|expr| {
-            let int = filter_map(|span, token| match token {
-                Token::Num(value) => Ok(Expr::Int(value.parse::<u32>().unwrap())),
-                _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
+            let int = int_literal(literal::INT_MAX).map(|value| value.map_or(Expr::Err, Expr::Int));
+            // the smallest int is only written as the negation of a literal one past the largest,
+            // so a literal after a `-` may be that large
+            let negated_int = just(Token::Op("-"))
+                .ignore_then(
+                    int_literal(literal::NEGATED_INT_MAX)
+                        .map_with_span(|value, span| (value.map_or(Expr::Err, Expr::Int), span)),
+                )
+                .map(|literal| Expr::Neg(Box::new(literal)));
+
+            // the lexer only makes float tokens of digits around a decimal point, which parse
+            // once their separators are removed, but a literal that doesn't is still an error
+            // rather than a panic
+            let float = select! { Token::Float(text) => text }.validate(|text, span, emit| {
+                match text.replace('_', "").parse() {
+                    Ok(value) => Expr::Float(value),
+                    Err(_) => {
+                        emit(Simple::custom(span, text).with_label(INVALID_FLOAT));
+                        Expr::Err
+                    }
+                }
             });
 
-            let call = parse_ident()
-                .then(
+            let string = select! { Token::Str(value) => Expr::Str(value) };
+
+            let keyword = |word: &'static str| {
+                select! { Token::Ident(ident) if ident == word => () }
+            };
+
+            // a name and its arguments, which are a call or, after `spawn`, a thread
+            let invocation = parse_ident().then(
+                expr.clone()
+                    .separated_by(just(Token::Ctrl(',')))
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
+                    .recover_with(recovery::nested_delimiters(
+                        Token::Ctrl('('),
+                        Token::Ctrl(')'),
+                        [],
+                        |_| Vec::new(),
+                    )),
+            );
+            let call = invocation
+                .clone()
+                .map(|(name, params)| Self::Call { name, params });
+
+            let spawn = keyword("spawn")
+                .ignore_then(invocation)
+                .map(|(name, params)| Self::Spawn { name, params });
+            let join = keyword("join")
+                .ignore_then(
                     expr.clone()
-                        .separated_by(just(Token::Ctrl(',')))
-                        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
-                        .recover_with(recovery::nested_delimiters(
-                            Token::Ctrl('('),
-                            Token::Ctrl(')'),
-                            [],
-                            |_| Vec::new(),
-                        )),
+                        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
                 )
-                .map(|(name, params)| Self::Call { name, params });
+                .map(|handle| Self::Join(Box::new(handle)));
+
+            let variable = parse_ident().map(Self::Var);
 
-            let variable = parse_ident().map(|name| Self::Var(name));
+            let size_of = select! { Token::Ident(ident) if ident == "sizeof" => () }
+                .ignore_then(
+                    parse_type().delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+                )
+                .map(Self::SizeOf);
 
             let atom = int
-                .or(expr.delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))))
+                .or(negated_int)
+                .or(float)
+                .or(string)
+                .or(size_of)
+                .or(spawn)
+                .or(join)
                 .or(call)
-                .or(variable);
-
-            let unary = just(Token::Op('-'))
-                .repeated()
-                .then(atom)
-                .foldr(|_op, rhs| Expr::Neg(Box::new(rhs)));
-
-            let product = unary
-                .clone()
-                .then(
-                    just(Token::Op('*'))
-                        .to(Expr::Mul as fn(_, _) -> _)
-                        .or(just(Token::Op('/')).to(Expr::Div as fn(_, _) -> _))
-                        .then(unary)
-                        .repeated(),
-                )
-                .foldl(|lhs, (op, rhs)| op(Box::new(lhs), Box::new(rhs)));
+                .or(variable)
+                .map_with_span(|expr, span| (expr, span))
+                .or(expr
+                    .clone()
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
+                    .recover_with(recovery::nested_delimiters(
+                        Token::Ctrl('('),
+                        Token::Ctrl(')'),
+                        [(Token::Ctrl('['), Token::Ctrl(']'))],
+                        |span| (Self::Err, span),
+                    )));
 
-            let sum = product
-                .clone()
+            let index = atom
                 .then(
-                    just(Token::Op('+'))
-                        .to(Expr::Add as fn(_, _) -> _)
-                        .or(just(Token::Op('-')).to(Expr::Sub as fn(_, _) -> _))
-                        .then(product)
+                    expr.clone()
+                        .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
+                        .recover_with(recovery::nested_delimiters(
+                            Token::Ctrl('['),
+                            Token::Ctrl(']'),
+                            [(Token::Ctrl('('), Token::Ctrl(')'))],
+                            |span| (Self::Err, span),
+                        ))
+                        .map_with_span(|index, span: Span| (index, span))
                         .repeated(),
                 )
-                .foldl(|lhs, (op, rhs)| op(Box::new(lhs), Box::new(rhs)));
+                .foldl(|array, (index, span)| {
+                    let span = array.1.start..span.end;
+                    (Expr::Index(Box::new(array), Box::new(index)), span)
+                });
 
-            sum
+            // every level of the precedence table wraps the one before it, from the tightest
+            // binding to the loosest
+            let mut operand = index.boxed();
+            for level in LEVELS {
+                operand = match level.ops {
+                    // calls are atoms, and indexes are parsed with them
+                    Ops::Postfix(_) => operand,
+                    Ops::Prefix { ops, steps } => prefix(operand, ops, steps),
+                    Ops::Binary(ops) => binary(operand, ops, level.assoc),
+                    // right associative since the otherwise branch is any expression, so
+                    // `a ? b : c ? d : e` is `a ? b : (c ? d : e)`
+                    Ops::Conditional => operand
+                        .then(
+                            just(Token::Op("?"))
+                                .ignore_then(expr.clone())
+                                .then_ignore(just(Token::Op(":")))
+                                .then(expr.clone())
+                                .or_not(),
+                        )
+                        .map(|(cond, branches)| match branches {
+                            None => cond,
+                            Some((then, otherwise)) => {
+                                let span = cond.1.start..otherwise.1.end;
+                                let cond =
+                                    Expr::Cond(Box::new(cond), Box::new(then), Box::new(otherwise));
+                                (cond, span)
+                            }
+                        })
+                        .boxed(),
+                };
+            }
+            operand
         })
     }
 
-    fn eval(&self, vars: &mut Vec<(String, i32)>, funcs: &mut HashMap<String, Func>) -> i32 {
-        match self {
-            Self::Int(value) => *value as i32,
-            Self::Neg(expr) => -expr.eval(vars, funcs),
-            Self::Err => panic!("invalid expression found"),
-            Self::Add(lhs, rhs) => lhs.eval(vars, funcs) + rhs.eval(vars, funcs),
-            Self::Sub(lhs, rhs) => lhs.eval(vars, funcs) - rhs.eval(vars, funcs),
-            Self::Mul(lhs, rhs) => lhs.eval(vars, funcs) * rhs.eval(vars, funcs),
-            Self::Div(lhs, rhs) => lhs.eval(vars, funcs) / rhs.eval(vars, funcs),
-            Self::Var(name) => match vars.iter().rev().find(|(vname, _)| vname == name) {
-                None => panic!("undeclared variable {name}"),
-                Some((_, value)) => *value,
-            },
+    fn eval<'a>(
+        (expr, span): &'a Spanned<Self>,
+        vars: &mut Vec<(Symbol, Value)>,
+        frame: usize,
+        runtime: &mut Runtime<'a>,
+    ) -> Result<Value, Diagnostic> {
+        match expr {
+            Self::Int(value) => runtime
+                .ints
+                .literal((*value).into())
+                .map(Value::Int)
+                .map_err(arith_error(span)),
+            Self::Str(value) => Ok(Value::Str(value.clone())),
+            Self::Float(value) => Ok(Value::Float(*value)),
+            Self::Neg(expr) => {
+                // `-2147483648` is a 32-bit int, though `2147483648` isn't
+                if let Self::Int(value) = expr.0 {
+                    return runtime
+                        .ints
+                        .literal(-i64::from(value))
+                        .map(Value::Int)
+                        .map_err(arith_error(span));
+                }
+                let value = match Self::eval_number(expr, vars, frame, runtime)? {
+                    Value::Float(value) => return Ok(Value::Float(-value)),
+                    value => value.as_int().map_err(runtime_error(&expr.1))?,
+                };
+                runtime
+                    .ints
+                    .neg(value)
+                    .map(Value::Int)
+                    .map_err(arith_error(span))
+            }
+            Self::Not(expr) => Ok(Value::Int(
+                (Self::eval_int(expr, vars, frame, runtime)? == 0) as i64,
+            )),
+            Self::PreInc(name) | Self::PreDec(name) => {
+                let ints = runtime.ints;
+                let var = var_mut(vars, frame, runtime.globals, name, span)?;
+                let value = var.as_int().map_err(runtime_error(span))?;
+                let value = match expr {
+                    Self::PreInc(_) => ints.add(value, 1),
+                    _ => ints.sub(value, 1),
+                }
+                .map_err(arith_error(span))?;
+                *var = Value::Int(value);
+                Ok(Value::Int(value))
+            }
+            Self::Err => Err(runtime_error(span)(Message::new(
+                "E0202.invalid-expression",
+            ))),
+            Self::Add(lhs, rhs) => {
+                let lhs = Self::eval(lhs, vars, frame, runtime)?;
+                let rhs = Self::eval(rhs, vars, frame, runtime)?;
+                runtime.add(lhs, rhs, span)
+            }
+            Self::Sub(lhs, rhs)
+            | Self::Mul(lhs, rhs)
+            | Self::Div(lhs, rhs)
+            | Self::Rem(lhs, rhs)
+            | Self::Shl(lhs, rhs)
+            | Self::Shr(lhs, rhs)
+            | Self::BitAnd(lhs, rhs)
+            | Self::BitOr(lhs, rhs)
+            | Self::BitXor(lhs, rhs)
+            | Self::Lt(lhs, rhs)
+            | Self::Le(lhs, rhs)
+            | Self::Gt(lhs, rhs)
+            | Self::Ge(lhs, rhs)
+            | Self::Eq(lhs, rhs)
+            | Self::Ne(lhs, rhs) => {
+                let op = match expr {
+                    Self::Sub(..) => BinOp::Sub,
+                    Self::Mul(..) => BinOp::Mul,
+                    Self::Div(..) => BinOp::Div,
+                    Self::Rem(..) => BinOp::Rem,
+                    Self::Shl(..) => BinOp::Shl,
+                    Self::Shr(..) => BinOp::Shr,
+                    Self::BitAnd(..) => BinOp::And,
+                    Self::BitOr(..) => BinOp::Or,
+                    Self::BitXor(..) => BinOp::Xor,
+                    Self::Lt(..) => BinOp::Lt,
+                    Self::Le(..) => BinOp::Le,
+                    Self::Gt(..) => BinOp::Gt,
+                    Self::Ge(..) => BinOp::Ge,
+                    Self::Eq(..) => BinOp::Eq,
+                    _ => BinOp::Ne,
+                };
+                if !op.takes_floats() {
+                    let lhs = Self::eval_int(lhs, vars, frame, runtime)?;
+                    let rhs = Self::eval_int(rhs, vars, frame, runtime)?;
+                    return runtime.binary(op, lhs, rhs, span).map(Value::Int);
+                }
+                let lhs = Self::eval_number(lhs, vars, frame, runtime)?;
+                let rhs = Self::eval_number(rhs, vars, frame, runtime)?;
+                runtime.numeric(op, lhs, rhs, span)
+            }
+            Self::And(lhs, rhs) | Self::Or(lhs, rhs) => {
+                let op = match expr {
+                    Self::And(..) => LogicalOp::And,
+                    _ => LogicalOp::Or,
+                };
+                let lhs = Self::eval_int(lhs, vars, frame, runtime)?;
+                let context = runtime.context.clone();
+                context
+                    .logical(op, lhs, &mut || Self::eval_int(rhs, vars, frame, runtime))
+                    .map(Value::Int)
+            }
+            Self::Cond(cond, then, otherwise) => {
+                let branch = match Self::eval_int(cond, vars, frame, runtime)? {
+                    0 => otherwise,
+                    _ => then,
+                };
+                Self::eval(branch, vars, frame, runtime)
+            }
+            Self::Var(name) => {
+                let slot = var_slot(vars, frame, runtime.globals, name, span)?;
+                Ok(vars[slot].1.clone())
+            }
+            Self::AddrOf(name) => {
+                let slot = var_slot(vars, frame, runtime.globals, name, span)?;
+                Ok(runtime.pointer(slot))
+            }
+            Self::Deref(pointer) => {
+                let value = Self::eval(pointer, vars, frame, runtime)?;
+                let pointer = value.as_pointer().map_err(runtime_error(&pointer.1))?;
+                let slot = runtime.slot(pointer, vars, span)?;
+                Ok(vars[slot].1.clone())
+            }
+            Self::SizeOf(ty) => {
+                let size = runtime.resolved.types.size_of(ty).map_err(|error| {
+                    error.with_label(span.clone(), Message::new("label.sized-here"))
+                })?;
+                Ok(Value::Int(size.into()))
+            }
+            Self::Spawn { name, params } => {
+                let args = params
+                    .iter()
+                    .map(|expr| Self::eval(expr, vars, frame, runtime))
+                    .collect::<Result<Vec<_>, _>>()?;
+                runtime.spawn(*name, args, span)
+            }
+            Self::Join(handle) => {
+                let value = Self::eval(handle, vars, frame, runtime)?;
+                let id = value.as_thread().map_err(runtime_error(&handle.1))?;
+                runtime.join(id, span)
+            }
+            Self::Index(array, index) => {
+                let array = Self::eval(array, vars, frame, runtime)?;
+                let index = Self::eval_int(index, vars, frame, runtime)?;
+                array.element(index).cloned().map_err(runtime_error(span))
+            }
             Self::Call { name, params } => {
-                let Some(func) = funcs.get(name).cloned() else {
-                    panic!("unknown function {name}");
+                let index = match runtime.callee(*name) {
+                    Some(Callee::Func(index)) => index,
+                    Some(Callee::Builtin(index)) => {
+                        let args = params
+                            .iter()
+                            .map(|expr| Self::eval(expr, vars, frame, runtime))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        return runtime.builtins.at(index)(&args).map_err(runtime_error(span));
+                    }
+                    None => {
+                        return Err(runtime_error(span)(
+                            Message::new("unknown-function").arg("name", name.as_str()),
+                        ))
+                    }
                 };
+                let func = runtime.funcs[index];
+
+                if params.len() != func.params.len() {
+                    return Err(arity_error(func, params.len(), span));
+                }
+
+                if runtime.calls.len() >= runtime.max_call_depth {
+                    return Err(stack_overflow(name, span, runtime.max_call_depth));
+                }
 
-                let mut function_vars = Vec::new();
-                for (expr, param) in params.iter().zip(func.params.iter()) {
-                    function_vars.push((param.name.clone(), expr.eval(vars, funcs)));
+                // the callee's frame starts with its arguments, which are unnamed until they've all
+                // been evaluated so that none of them shadow the caller's variables
+                let callee_frame = vars.len();
+                for expr in params {
+                    let value = Self::eval(expr, vars, frame, runtime)?;
+                    vars.push((Symbol::EMPTY, value));
                 }
+                for (var, param) in vars[callee_frame..].iter_mut().zip(&func.params) {
+                    var.0 = param.name;
+                }
+
+                runtime.calls.push((name, span.clone()));
+                runtime.frames.push((callee_frame, runtime.next_call));
+                runtime.next_call += 1;
+                runtime.enter(name);
+                let value = func.eval(vars, callee_frame, runtime)?;
+                runtime.exit(name);
+                runtime.frames.pop();
+                runtime.calls.pop();
+                vars.truncate(callee_frame);
+                Ok(value)
+            }
+        }
+    }
+
+    /// Evaluates `expr`, requiring the result to be an int or a float.
+    fn eval_number<'a>(
+        expr: &'a Spanned<Self>,
+        vars: &mut Vec<(Symbol, Value)>,
+        frame: usize,
+        runtime: &mut Runtime<'a>,
+    ) -> Result<Value, Diagnostic> {
+        number(Self::eval(expr, vars, frame, runtime)?).map_err(runtime_error(&expr.1))
+    }
+
+    /// Evaluates `expr`, requiring the result to be an int.
+    fn eval_int<'a>(
+        expr: &'a Spanned<Self>,
+        vars: &mut Vec<(Symbol, Value)>,
+        frame: usize,
+        runtime: &mut Runtime<'a>,
+    ) -> Result<i64, Diagnostic> {
+        Self::eval(expr, vars, frame, runtime)?
+            .as_int()
+            .map_err(runtime_error(&expr.1))
+    }
+}
 
-                func.eval(&mut function_vars, funcs)
+/// Formats an expression in prefix form, e.g. `(+ a (call f 1))`.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Err => write!(f, "(error)"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Str(value) => write!(f, "{}", literal::escape(value)),
+            Self::Float(value) => write!(f, "{}", literal::float(*value)),
+            Self::Neg(expr) => write!(f, "(- {})", expr.0),
+            Self::Mul(lhs, rhs) => write!(f, "(* {} {})", lhs.0, rhs.0),
+            Self::Div(lhs, rhs) => write!(f, "(/ {} {})", lhs.0, rhs.0),
+            Self::Add(lhs, rhs) => write!(f, "(+ {} {})", lhs.0, rhs.0),
+            Self::Sub(lhs, rhs) => write!(f, "(- {} {})", lhs.0, rhs.0),
+            Self::Rem(lhs, rhs) => write!(f, "(% {} {})", lhs.0, rhs.0),
+            Self::BitAnd(lhs, rhs) => write!(f, "(& {} {})", lhs.0, rhs.0),
+            Self::BitOr(lhs, rhs) => write!(f, "(| {} {})", lhs.0, rhs.0),
+            Self::And(lhs, rhs) => write!(f, "(&& {} {})", lhs.0, rhs.0),
+            Self::Or(lhs, rhs) => write!(f, "(|| {} {})", lhs.0, rhs.0),
+            Self::BitXor(lhs, rhs) => write!(f, "(^ {} {})", lhs.0, rhs.0),
+            Self::Shl(lhs, rhs) => write!(f, "(<< {} {})", lhs.0, rhs.0),
+            Self::Shr(lhs, rhs) => write!(f, "(>> {} {})", lhs.0, rhs.0),
+            Self::Not(expr) => write!(f, "(! {})", expr.0),
+            Self::PreInc(name) => write!(f, "(++ {name})"),
+            Self::PreDec(name) => write!(f, "(-- {name})"),
+            Self::AddrOf(name) => write!(f, "(& {name})"),
+            Self::Deref(expr) => write!(f, "(* {})", expr.0),
+            Self::SizeOf(ty) => write!(f, "(sizeof {ty})"),
+            Self::Spawn { name, params } => {
+                write!(f, "(spawn {name}")?;
+                for param in params {
+                    write!(f, " {}", param.0)?;
+                }
+                write!(f, ")")
+            }
+            Self::Join(handle) => write!(f, "(join {})", handle.0),
+            Self::Lt(lhs, rhs) => write!(f, "(< {} {})", lhs.0, rhs.0),
+            Self::Le(lhs, rhs) => write!(f, "(<= {} {})", lhs.0, rhs.0),
+            Self::Gt(lhs, rhs) => write!(f, "(> {} {})", lhs.0, rhs.0),
+            Self::Ge(lhs, rhs) => write!(f, "(>= {} {})", lhs.0, rhs.0),
+            Self::Eq(lhs, rhs) => write!(f, "(== {} {})", lhs.0, rhs.0),
+            Self::Ne(lhs, rhs) => write!(f, "(!= {} {})", lhs.0, rhs.0),
+            Self::Cond(cond, then, otherwise) => {
+                write!(f, "(? {} {} {})", cond.0, then.0, otherwise.0)
+            }
+            Self::Var(name) => write!(f, "{name}"),
+            Self::Index(array, index) => write!(f, "(index {} {})", array.0, index.0),
+            Self::Call { name, params } => {
+                write!(f, "(call {name}")?;
+                for (param, _) in params {
+                    write!(f, " {param}")?;
+                }
+                write!(f, ")")
             }
         }
     }
 }
 
-fn parse_ident() -> impl Parser<Token, String, Error = Simple<Token>> + Clone {
-    filter_map(|span, token| match token {
-        Token::Ident(ident) => Ok(ident.clone()),
-        _ => Err(Simple::expected_input_found(span, Vec::new(), Some(token))),
+/// Builds a closure that turns a runtime error message into a diagnostic pointing at `span`.
+pub(crate) fn runtime_error<M: Into<Message>>(span: &Span) -> impl FnOnce(M) -> Diagnostic + '_ {
+    move |message| {
+        Diagnostic::error("E0202", message)
+            .with_label(span.clone(), Message::new("label.error-here"))
+    }
+}
+
+/// `value` if it's an int or a float, or the error for a value that isn't an int otherwise.
+fn number(value: Value) -> Result<Value, Message> {
+    match value {
+        Value::Float(_) => Ok(value),
+        value => value.as_int().map(Value::Int),
+    }
+}
+
+/// Builds a closure that turns an arithmetic error into a diagnostic pointing at `span`.
+pub(crate) fn arith_error(span: &Span) -> impl FnOnce(ArithError) -> Diagnostic + '_ {
+    move |error| runtime_error(span)(error)
+}
+
+/// The index in `vars` of the variable `name` used at `span` by the call whose variables start at
+/// `frame`, which is one of its own or else one of the first `globals`.
+fn var_slot(
+    vars: &[(Symbol, Value)],
+    frame: usize,
+    globals: usize,
+    name: &str,
+    span: &Span,
+) -> Result<usize, Diagnostic> {
+    let find = |vars: &[(Symbol, Value)]| vars.iter().rposition(|(vname, _)| *vname == name);
+    match find(&vars[frame..]) {
+        Some(slot) => Ok(frame + slot),
+        None => find(&vars[..globals.min(frame)]).ok_or_else(|| undeclared_variable(name, span)),
+    }
+}
+
+/// The innermost variable called `name` in `vars`, for a statement or expression at `span` that
+/// changes it.
+fn var_mut<'v>(
+    vars: &'v mut [(Symbol, Value)],
+    frame: usize,
+    globals: usize,
+    name: &str,
+    span: &Span,
+) -> Result<&'v mut Value, Diagnostic> {
+    let slot = var_slot(vars, frame, globals, name, span)?;
+    Ok(&mut vars[slot].1)
+}
+
+pub(crate) fn undeclared_variable(name: &str, span: &Span) -> Diagnostic {
+    runtime_error(span)(Message::new("undeclared-variable").arg("name", name))
+}
+
+/// The error for calling `func` with `found` arguments at `span`.
+pub(crate) fn arity_error(func: &Func, found: usize, span: &Span) -> Diagnostic {
+    runtime_error(span)(
+        Message::new("arity")
+            .arg("name", func.name.as_str())
+            .arg("expected", func.params.len().to_string())
+            .arg("found", found.to_string()),
+    )
+    .with_label(
+        func.span.clone(),
+        Message::new("label.func-defined").arg("name", func.name.as_str()),
+    )
+}
+
+/// The error for a call to `name` at `span` that would exceed `max_call_depth`.
+pub(crate) fn stack_overflow(name: &str, span: &Span, max_call_depth: usize) -> Diagnostic {
+    Diagnostic::error("E0203", Message::new("E0203").arg("name", name))
+        .with_label(span.clone(), Message::new("label.stack-overflow"))
+        .with_note(Message::new("note.max-call-depth").arg("depth", max_call_depth.to_string()))
+}
+
+/// The error for reaching the end of `func` without returning.
+pub(crate) fn missing_return(func: &Func) -> Diagnostic {
+    Diagnostic::error("E0201", Message::new("E0201")).with_label(
+        func.span.clone(),
+        Message::new("label.in-function").arg("name", func.name.as_str()),
+    )
+}
+
+pub type UnaryOp = fn(Box<Spanned<Expr>>) -> Expr;
+pub type BinaryOp = fn(Box<Spanned<Expr>>, Box<Spanned<Expr>>) -> Expr;
+/// An operator on a variable, like [`Expr::PreInc`] or [`Expr::AddrOf`].
+pub type StepOp = fn(Symbol) -> Expr;
+
+/// The parser of one level of expressions. Every level is boxed, since a binary level uses the
+/// level before it twice and would otherwise hold two copies of it, doubling in size at each one.
+type ExprParser = BoxedParser<'static, Token, Spanned<Expr>, Simple<Token>>;
+
+fn fold_binary(lhs: Spanned<Expr>, (op, rhs): (BinaryOp, Spanned<Expr>)) -> Spanned<Expr> {
+    let span = lhs.1.start..rhs.1.end;
+    (op(Box::new(lhs), Box::new(rhs)), span)
+}
+
+/// Parses any number of the prefix operators in `ops` before an `operand`, or one of the `steps`
+/// before a variable.
+fn prefix(
+    operand: ExprParser,
+    ops: &'static [(&'static str, UnaryOp)],
+    steps: &'static [(&'static str, StepOp)],
+) -> ExprParser {
+    let step = choice(
+        steps
+            .iter()
+            .map(|(op, node)| just(Token::Op(op)).to(*node))
+            .collect::<Vec<_>>(),
+    )
+    .then(parse_ident())
+    .map_with_span(|(op, name), span| (op(name), span));
+
+    // a `-` right before an int literal is left for the atom, which reads a negated literal as
+    // one so that the smallest int can be written
+    let int = select! { Token::Num(_) => () };
+    choice(
+        ops.iter()
+            .map(|(op, node)| match *op {
+                "-" => just(Token::Op(op))
+                    .then_ignore(int.not().rewind())
+                    .to(*node)
+                    .boxed(),
+                _ => just(Token::Op(op)).to(*node).boxed(),
+            })
+            .collect::<Vec<_>>(),
+    )
+    .map_with_span(|op, span: Span| (op, span))
+    .repeated()
+    .then(step.or(operand))
+    .foldr(|(op, span), rhs| {
+        let span = span.start..rhs.1.end;
+        (op(Box::new(rhs)), span)
     })
+    .boxed()
+}
+
+/// Parses `operand`s separated by any of the operators in `ops`, grouping them by `assoc`.
+fn binary(
+    operand: ExprParser,
+    ops: &'static [(&'static str, BinaryOp)],
+    assoc: Assoc,
+) -> ExprParser {
+    let op = choice(
+        ops.iter()
+            .map(|(op, node)| just(Token::Op(op)).to(*node))
+            .collect::<Vec<_>>(),
+    );
+    operand
+        .clone()
+        .then(op.then(operand).repeated())
+        .map(move |(first, rest)| match assoc {
+            Assoc::Left => rest.into_iter().fold(first, fold_binary),
+            Assoc::Right => {
+                // each operand is paired with the operator after it, and the last stands alone
+                let (mut lhs, mut pairs) = (first, Vec::new());
+                for (op, rhs) in rest {
+                    pairs.push((lhs, op));
+                    lhs = rhs;
+                }
+                pairs
+                    .into_iter()
+                    .rev()
+                    .fold(lhs, |rhs, (lhs, op)| fold_binary(lhs, (op, rhs)))
+            }
+        })
+        .boxed()
+}
+
+/// The statements of a function or macro body, in braces.
+///
+/// A statement that doesn't parse is skipped up to the `;` that ends it or the end of the body,
+/// and kept as a [`Statement::Invalid`], so that a mistake is reported once and the statements
+/// after it are still parsed and checked.
+fn body() -> impl Parser<Token, Vec<Spanned<Statement>>, Error = Simple<Token>> {
+    // the end of the body is never skipped, so that it's still there to close the body
+    just(Token::Ctrl('}'))
+        .not()
+        .rewind()
+        .ignore_then(recovering(Statement::parser()))
+        .repeated()
+        .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+        .recover_with(recovery::nested_delimiters(
+            Token::Ctrl('{'),
+            Token::Ctrl('}'),
+            [],
+            |_| Vec::new(),
+        ))
+}
+
+/// `statement`, or the tokens of a statement that doesn't parse up to the `;` that ends it or
+/// the `}` after it, as a [`Statement::Invalid`].
+fn recovering(
+    statement: impl Parser<Token, Spanned<Statement>, Error = Simple<Token>>,
+) -> impl Parser<Token, Spanned<Statement>, Error = Simple<Token>> {
+    statement
+        .map(Ok)
+        .recover_with(recovery::skip_until(
+            [Token::Ctrl(';'), Token::Ctrl('}')],
+            Err,
+        ))
+        .then(
+            just(Token::Ctrl(';'))
+                .map_with_span(|_, span| span)
+                .or_not(),
+        )
+        .validate(
+            |(statement, semicolon), _, emit| match (statement, semicolon) {
+                (Ok(statement), None) => statement,
+                // the `;` after a statement that parsed would start the next one, which it can't
+                (Ok(statement), Some(span)) => {
+                    emit(Simple::expected_input_found(
+                        span,
+                        [],
+                        Some(Token::Ctrl(';')),
+                    ));
+                    statement
+                }
+                (Err(span), semicolon) => {
+                    let end = semicolon.map_or(span.end, |semicolon| semicolon.end);
+                    (Statement::Invalid, span.start..end)
+                }
+            },
+        )
+}
+
+fn parse_ident() -> impl Parser<Token, Symbol, Error = Simple<Token>> + Clone {
+    select! { Token::Ident(ident) => ident }
+}
+
+/// An identifier that misspells `keyword`, reported with the keyword as a suggestion.
+///
+/// This is only tried after every correct alternative has failed, since it produces an error
+/// even when it succeeds.
+fn keyword_typo(keyword: Token) -> impl Parser<Token, (), Error = Simple<Token>> + Clone {
+    let (text, _) = KEYWORDS
+        .iter()
+        .find(|(_, token)| *token == keyword)
+        .unwrap();
+    parse_ident()
+        .try_map(
+            move |ident, span| match suggest::is_misspelling(&ident, text) {
+                true => Ok(()),
+                // no keyword is expected here, the correct alternatives say what is
+                false => Err(Simple::expected_input_found(
+                    span,
+                    [],
+                    Some(Token::Ident(ident)),
+                )),
+            },
+        )
+        .validate(|(), span, emit| emit(Simple::custom(span, *text).with_label(KEYWORD_TYPO)))
+}
+
+/// An int literal of at most `max`, or `None` if it has no value, which is reported at its span.
+fn int_literal(max: u32) -> impl Parser<Token, Option<u32>, Error = Simple<Token>> + Clone {
+    select! { Token::Num(text) => text }.validate(move |text, span, emit| {
+        literal::int(&text, max)
+            .map_err(|error| {
+                let label = match error {
+                    IntError::TooLarge => TOO_LARGE,
+                    _ => INVALID_INT,
+                };
+                emit(Simple::custom(span, text).with_label(label));
+            })
+            .ok()
+    })
+}
+
+/// C's names for the types a pointer is most often declared to, which a `*` after makes a
+/// pointer type rather than the start of a multiplication.
+const POINTEE_TYPES: &[&str] = &["int", "string", VOID, "char", "long", "float", "double"];
+
+/// A type, which is a pointer type like `int*` if the name is followed by `*`s.
+fn parse_type() -> impl Parser<Token, Symbol, Error = Simple<Token>> + Clone {
+    parse_ident()
+        .then(just(Token::Op("*")).repeated())
+        .map(|(name, stars)| match stars.len() {
+            0 => name,
+            stars => Symbol::intern(&(name.to_string() + &"*".repeat(stars))),
+        })
+}
+
+/// A pointer type to one of the [`POINTEE_TYPES`].
+fn pointer_type() -> impl Parser<Token, String, Error = Simple<Token>> + Clone {
+    select! { Token::Ident(ty) if POINTEE_TYPES.contains(&ty.as_str()) => ty.to_string() }
+        .then_ignore(just(Token::Op("*")).repeated().at_least(1))
+}
+
+/// Whether `ty` is a pointer type, like `int*`.
+pub fn is_pointer(ty: &str) -> bool {
+    ty.ends_with('*')
+}
+
+/// A statement using C syntax the language doesn't have, which is reported as unsupported and
+/// parsed as an invalid statement so that the statements after it are still checked: a `for`,
+/// `while` or `do` loop, or an `if`.
+fn unsupported_statement() -> impl Parser<Token, Statement, Error = Simple<Token>> {
+    let tree = recursive(|tree| {
+        let group = |open, close| {
+            tree.clone()
+                .repeated()
+                .delimited_by(just(Token::Ctrl(open)), just(Token::Ctrl(close)))
+                .ignored()
+        };
+        group('(', ')')
+            .or(group('[', ']'))
+            .or(group('{', '}'))
+            .or(none_of([Token::Ctrl(')'), Token::Ctrl(']'), Token::Ctrl('}')]).ignored())
+    });
+    let group = |open, close| {
+        tree.clone()
+            .repeated()
+            .delimited_by(just(Token::Ctrl(open)), just(Token::Ctrl(close)))
+            .ignored()
+    };
+    // a body in braces, or a single statement up to its `;`
+    let body = group('{', '}').or(none_of([Token::Ctrl(';'), Token::Ctrl('}')])
+        .repeated()
+        .then(just(Token::Ctrl(';')))
+        .ignored());
+    let keyword = |word: &'static str| {
+        select! { Token::Ident(ident) if ident == word => word }
+            .map_with_span(|word, span| (word, span))
+    };
+
+    keyword("for")
+        .or(keyword("while"))
+        .then_ignore(group('(', ')'))
+        .then_ignore(body.clone())
+        .or(keyword("do")
+            .then_ignore(body.clone())
+            .then_ignore(keyword("while"))
+            .then_ignore(group('(', ')'))
+            .then_ignore(just(Token::Ctrl(';'))))
+        .or(keyword("if")
+            .then_ignore(group('(', ')'))
+            .then_ignore(body.clone())
+            .then_ignore(
+                keyword("else")
+                    .then(keyword("if").then(group('(', ')')).or_not())
+                    .then(body)
+                    .repeated(),
+            ))
+        .validate(|(word, span), _, emit| {
+            emit(Simple::custom(span, word).with_label(UNSUPPORTED));
+            Statement::Invalid
+        })
 }
@@ -0,0 +1,258 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, ThreadId},
+};
+
+use crate::{messages::Message, Symbol, Value};
+
+/// A function implemented by the interpreter rather than in source code.
+///
+/// Returning an error aborts the program with the message reported at the call site.
+pub type BuiltinFn = Box<dyn Fn(&[Value]) -> Result<Value, Message> + Send + Sync>;
+
+/// Registry of builtin functions, consulted before user defined functions on every call.
+pub struct Builtins {
+    funcs: Vec<BuiltinFn>,
+    /// The index in `funcs` of each builtin, by name
+    indices: HashMap<String, usize>,
+    /// The index in `funcs` of each builtin, by the number of the symbol of its name
+    symbols: Vec<Option<usize>>,
+}
+
+impl Default for Builtins {
+    /// Creates a registry containing the standard builtins.
+    fn default() -> Self {
+        let mut builtins = Self::empty();
+        builtins.register("print", |args| {
+            print!("{}", join(args));
+            io::stdout().flush().map_err(io_error("print"))?;
+            Ok(Value::Int(0))
+        });
+        builtins.register("println", |args| {
+            println!("{}", join(args));
+            Ok(Value::Int(0))
+        });
+        builtins.register("read_int", |_| {
+            let mut line = String::new();
+            io::stdin()
+                .read_line(&mut line)
+                .map_err(io_error("read_int"))?;
+            match line.trim().parse::<i32>() {
+                Ok(value) => Ok(Value::from(value)),
+                Err(_) => Err(Message::new("E0202.read-int").arg("line", line.trim())),
+            }
+        });
+        builtins.register("len", |args| match args {
+            [Value::Array(values)] => Ok(Value::Int(values.len() as i64)),
+            [Value::Str(value)] => Ok(Value::Int(value.chars().count() as i64)),
+            _ => Err(expects("len", "args.array-or-string")),
+        });
+        builtins.set_args(Vec::new());
+        builtins.set_env(Vec::new());
+        builtins.register_sync();
+        builtins
+    }
+}
+
+impl Builtins {
+    /// Creates a registry with no builtins registered.
+    pub fn empty() -> Self {
+        Self {
+            funcs: Vec::new(),
+            indices: HashMap::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Registers `func` under `name`, replacing any builtin already registered with that name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        func: impl Fn(&[Value]) -> Result<Value, Message> + Send + Sync + 'static,
+    ) {
+        let func: BuiltinFn = Box::new(func);
+        match self.indices.entry(name.into()) {
+            Entry::Occupied(entry) => self.funcs[*entry.get()] = func,
+            Entry::Vacant(entry) => {
+                let symbol = Symbol::intern(entry.key()).number();
+                if self.symbols.len() <= symbol {
+                    self.symbols.resize(symbol + 1, None);
+                }
+                self.symbols[symbol] = Some(self.funcs.len());
+                entry.insert(self.funcs.len());
+                self.funcs.push(func);
+            }
+        }
+    }
+
+    /// Registers `argc` and `arg`, which give the program how many `args` it was run with and
+    /// each of them by index.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        let count = args.len() as i64;
+        self.register("argc", move |_| Ok(Value::Int(count)));
+        self.register("arg", move |params| match params {
+            [Value::Int(index)] => usize::try_from(*index)
+                .ok()
+                .and_then(|i| args.get(i))
+                .map(|arg| Value::Str(arg.clone()))
+                .ok_or_else(|| {
+                    Message::new("E0202.no-arg")
+                        .arg("index", index.to_string())
+                        .arg("count", count.to_string())
+                }),
+            _ => Err(expects("arg", "args.int")),
+        });
+    }
+
+    /// Registers `getenv`, which gives the program the value of each of `vars` by name and an
+    /// empty string for any other. The program can only read the variables it's given, never
+    /// those of the environment it runs in.
+    pub fn set_env(&mut self, vars: Vec<(String, String)>) {
+        let vars = vars.into_iter().collect::<HashMap<_, _>>();
+        self.register("getenv", move |params| match params {
+            [Value::Str(name)] => Ok(Value::Str(vars.get(name).cloned().unwrap_or_default())),
+            _ => Err(expects("getenv", "args.string")),
+        });
+    }
+
+    /// Registers the builtins threads share state through, each naming what it shares with a
+    /// string: `atomic_load`, `atomic_store` and `atomic_add` read and change an atomic int,
+    /// which starts at 0, and `lock` and `unlock` take and release a lock, waiting for as long
+    /// as another thread holds it. `atomic_add` returns the value it added to.
+    ///
+    /// Every thread a program spawns calls the same builtins, so they share these while sharing
+    /// no variables. A thread that ends holding a lock keeps it, and any thread waiting for it
+    /// waits until the program is stopped.
+    fn register_sync(&mut self) {
+        let atomics = Arc::new(Mutex::new(HashMap::<String, Arc<AtomicI64>>::new()));
+        let atomic = move |name: &str| {
+            let mut atomics = atomics.lock().unwrap();
+            atomics.entry(name.to_string()).or_default().clone()
+        };
+        let load = atomic.clone();
+        self.register("atomic_load", move |params| match params {
+            [Value::Str(name)] => Ok(Value::Int(load(name).load(Ordering::SeqCst))),
+            _ => Err(expects("atomic_load", "args.string")),
+        });
+        let store = atomic.clone();
+        self.register("atomic_store", move |params| match params {
+            [Value::Str(name), Value::Int(value)] => {
+                store(name).store(*value, Ordering::SeqCst);
+                Ok(Value::Int(0))
+            }
+            _ => Err(expects("atomic_store", "args.string-and-int")),
+        });
+        self.register("atomic_add", move |params| match params {
+            [Value::Str(name), Value::Int(value)] => {
+                Ok(Value::Int(atomic(name).fetch_add(*value, Ordering::SeqCst)))
+            }
+            _ => Err(expects("atomic_add", "args.string-and-int")),
+        });
+
+        let locks = Arc::new(Locks::default());
+        let unlock = locks.clone();
+        self.register("lock", move |params| match params {
+            [Value::Str(name)] => locks.lock(name).map(|()| Value::Int(0)),
+            _ => Err(expects("lock", "args.string")),
+        });
+        self.register("unlock", move |params| match params {
+            [Value::Str(name)] => unlock.unlock(name).map(|()| Value::Int(0)),
+            _ => Err(expects("unlock", "args.string")),
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BuiltinFn> {
+        self.indices.get(name).map(|&index| self.at(index))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.indices.contains_key(name)
+    }
+
+    /// The index of the builtin `name`, which stays the same as builtins are registered, found
+    /// by the number of its symbol so that the interpreter can resolve a call as it's made
+    /// without hashing the name. The builtin is run with [`Builtins::at`].
+    pub fn resolve(&self, name: Symbol) -> Option<usize> {
+        self.symbols.get(name.number()).copied().flatten()
+    }
+
+    /// The builtin at `index`, as given by [`Builtins::resolve`].
+    pub fn at(&self, index: usize) -> &BuiltinFn {
+        &self.funcs[index]
+    }
+
+    /// The names of every registered builtin, in sorted order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self.indices.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// The error of the builtin `name` called with arguments other than `args`, the key of the
+/// message describing the ones it takes.
+fn expects(name: &str, args: &'static str) -> Message {
+    Message::new("E0202.builtin-args")
+        .arg("name", name)
+        .arg("args", Message::new(args))
+}
+
+/// Builds a closure that turns an error reading or writing in the builtin `name` into its
+/// message.
+fn io_error(name: &str) -> impl FnOnce(io::Error) -> Message + '_ {
+    move |e| {
+        Message::new("E0202.builtin-io")
+            .arg("name", name)
+            .arg("error", e.to_string())
+    }
+}
+
+fn join(args: &[Value]) -> String {
+    args.iter()
+        .map(|arg| arg.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The locks taken with `lock`, by name, with the thread holding each of them.
+#[derive(Default)]
+struct Locks {
+    held: Mutex<HashMap<String, ThreadId>>,
+    /// Notified whenever a lock is released
+    released: Condvar,
+}
+
+impl Locks {
+    /// Takes the lock `name` for the current thread once no other thread holds it.
+    fn lock(&self, name: &str) -> Result<(), Message> {
+        let current = thread::current().id();
+        let mut held = self.held.lock().unwrap();
+        loop {
+            match held.get(name) {
+                None => break,
+                Some(holder) if *holder == current => {
+                    return Err(Message::new("E0202.locked").arg("name", name))
+                }
+                Some(_) => held = self.released.wait(held).unwrap(),
+            }
+        }
+        held.insert(name.to_string(), current);
+        Ok(())
+    }
+
+    /// Releases the lock `name`, which the current thread has to hold.
+    fn unlock(&self, name: &str) -> Result<(), Message> {
+        let mut held = self.held.lock().unwrap();
+        if held.get(name) != Some(&thread::current().id()) {
+            return Err(Message::new("E0202.not-locked").arg("name", name));
+        }
+        held.remove(name);
+        self.released.notify_all();
+        Ok(())
+    }
+}
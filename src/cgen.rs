@@ -0,0 +1,1521 @@
+//! C source generation, for `--emit c`.
+//!
+//! Programs are translated from the checked AST to C99 that compiles with `cc -std=c99`, each
+//! struct, global, function and statement becoming the C that reads the same, so that the output
+//! can be compiled natively and its runs compared against the interpreter's. Every int type is a C
+//! `int` and every float type a `double`, since ints are 32-bit and floats 64-bit when a program
+//! runs, and a `string` is a `const char*`, which is null for the empty string a variable starts
+//! with.
+//!
+//! The arithmetic C leaves undefined goes through `crust_` functions written at the top of the
+//! file, so that ints wrap and fail exactly as [`semantics`](crate::semantics) specifies and a
+//! float stored in an int is truncated as the interpreter truncates it. Runtime errors print a
+//! message to stderr and exit with status 255, as they do in the native backends. Only the
+//! functions the program needs are written, and the strings they build are never freed.
+//!
+//! C leaves the order the operands of an operator and the arguments of a call are evaluated in to
+//! the compiler, where the interpreter evaluates them left to right. So when one of them calls a
+//! function, changes a variable or may fail, it and those before it are stored in temporaries,
+//! declared at the top of the function, with comma expressions that C evaluates in order. Calls
+//! aren't counted, so runaway recursion overflows the native stack rather than failing with an
+//! error. Anything else that can't be written with the same behaviour, like threads or an array
+//! used as a value, is rejected.
+//!
+//! Names that C or its standard headers reserve, and names starting with `crust_`, are written with
+//! a `crust_u_` prefix, and a variable that would hide another name in its function is numbered.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    ast::{self, AssignOp, Definition, Expr, Func, Param, Spanned, Statement},
+    diagnostics::Diagnostic,
+    ir::BinOp,
+    literal,
+    messages::Message,
+    token::Span,
+    types::{self, TypeTable},
+    visit::{walk_expr, Visitor},
+    Ast, Builtins,
+};
+
+/// The keywords of C99, with `main`, separated by spaces.
+const KEYWORDS: &str = "\
+    auto break case char const continue default do double else enum extern float for goto if \
+    inline int long register restrict return short signed sizeof static struct switch typedef \
+    union unsigned void volatile while _Bool _Complex _Imaginary main";
+
+/// The names declared by `stdarg.h`, `stdio.h`, `stdlib.h` and `string.h`, which the output
+/// includes, separated by spaces.
+const LIBRARY: &str = "\
+    va_list va_start va_arg va_end va_copy FILE fpos_t size_t NULL BUFSIZ EOF FOPEN_MAX \
+    FILENAME_MAX L_tmpnam SEEK_CUR SEEK_END SEEK_SET TMP_MAX stderr stdin stdout remove rename \
+    tmpfile tmpnam fclose fflush fopen freopen setbuf setvbuf fprintf fscanf printf scanf \
+    snprintf sprintf sscanf vfprintf vfscanf vprintf vscanf vsnprintf vsprintf vsscanf fgetc \
+    fgets fputc fputs getc getchar gets putc putchar puts ungetc fread fwrite fgetpos fseek \
+    fsetpos ftell rewind clearerr feof ferror perror div_t ldiv_t lldiv_t wchar_t EXIT_FAILURE \
+    EXIT_SUCCESS RAND_MAX MB_CUR_MAX atof atoi atol atoll strtod strtof strtold strtol strtoll \
+    strtoul strtoull rand srand calloc free malloc realloc abort atexit exit _Exit getenv system \
+    bsearch qsort abs labs llabs div ldiv lldiv mblen mbtowc wctomb mbstowcs wcstombs memcpy \
+    memmove strcpy strncpy strcat strncat memcmp strcmp strcoll strncmp strxfrm memchr strchr \
+    strcspn strpbrk strrchr strspn strstr strtok memset strerror strlen";
+
+/// The functions generated code calls, each with the others it calls, in the order they're
+/// written.
+const HELPERS: &[(&str, &[&str], &str)] = &[
+    (
+        "crust_fail",
+        &[],
+        r#"static int crust_fail(const char* message)
+{
+    fflush(stdout);
+    fprintf(stderr, "runtime error: %s\n", message);
+    exit(255);
+}
+"#,
+    ),
+    (
+        "crust_add",
+        &[],
+        "static int crust_add(int lhs, int rhs)
+{
+    return (int)((unsigned)lhs + (unsigned)rhs);
+}
+",
+    ),
+    (
+        "crust_sub",
+        &[],
+        "static int crust_sub(int lhs, int rhs)
+{
+    return (int)((unsigned)lhs - (unsigned)rhs);
+}
+",
+    ),
+    (
+        "crust_mul",
+        &[],
+        "static int crust_mul(int lhs, int rhs)
+{
+    return (int)((unsigned)lhs * (unsigned)rhs);
+}
+",
+    ),
+    (
+        "crust_neg",
+        &[],
+        "static int crust_neg(int value)
+{
+    return (int)(0u - (unsigned)value);
+}
+",
+    ),
+    (
+        "crust_inc",
+        &["crust_add"],
+        "static int crust_inc(int* var)
+{
+    return *var = crust_add(*var, 1);
+}
+",
+    ),
+    (
+        "crust_dec",
+        &["crust_sub"],
+        "static int crust_dec(int* var)
+{
+    return *var = crust_sub(*var, 1);
+}
+",
+    ),
+    (
+        "crust_div",
+        &["crust_fail", "crust_neg"],
+        r#"static int crust_div(int lhs, int rhs)
+{
+    if (rhs == 0)
+        crust_fail("attempt to divide by zero");
+    return rhs == -1 ? crust_neg(lhs) : lhs / rhs;
+}
+"#,
+    ),
+    (
+        "crust_rem",
+        &["crust_fail"],
+        r#"static int crust_rem(int lhs, int rhs)
+{
+    if (rhs == 0)
+        crust_fail("attempt to divide by zero");
+    return rhs == -1 ? 0 : lhs % rhs;
+}
+"#,
+    ),
+    (
+        "crust_shl",
+        &[],
+        "static int crust_shl(int lhs, int rhs)
+{
+    return (int)((unsigned)lhs << (rhs & 31));
+}
+",
+    ),
+    (
+        "crust_shr",
+        &[],
+        "static int crust_shr(int lhs, int rhs)
+{
+    return lhs < 0 ? ~(~lhs >> (rhs & 31)) : lhs >> (rhs & 31);
+}
+",
+    ),
+    (
+        "crust_trunc",
+        &[],
+        "static int crust_trunc(double value)
+{
+    if (value != value)
+        return 0;
+    if (value <= -2147483648.0)
+        return -2147483647 - 1;
+    if (value >= 2147483647.0)
+        return 2147483647;
+    return (int)value;
+}
+",
+    ),
+    (
+        "crust_index",
+        &["crust_fail"],
+        r#"static int crust_index(int index, int len)
+{
+    if (index < 0 || index >= len)
+    {
+        char message[80];
+        snprintf(message, sizeof message, "index %d out of bounds for array of length %d", index, len);
+        crust_fail(message);
+    }
+    return index;
+}
+"#,
+    ),
+    (
+        "crust_str",
+        &[],
+        r#"static const char* crust_str(const char* value)
+{
+    return value ? value : "";
+}
+"#,
+    ),
+    (
+        "crust_int_str",
+        &[],
+        r#"static const char* crust_int_str(int value)
+{
+    char* text = malloc(12);
+    snprintf(text, 12, "%d", value);
+    return text;
+}
+"#,
+    ),
+    (
+        "crust_float_str",
+        &[],
+        r#"/* The float as the interpreter prints it: the fewest digits that read back as the same value,
+   never with an exponent, and with a decimal point. */
+static const char* crust_float_str(double value)
+{
+    if (value != value)
+        return "NaN";
+    if (value - value != 0)
+        return value < 0 ? "-inf" : "inf";
+
+    char scientific[32];
+    for (int precision = 0; precision <= 16; precision++)
+    {
+        snprintf(scientific, sizeof scientific, "%.*e", precision, value);
+        if (strtod(scientific, NULL) == value)
+            break;
+    }
+    char digits[24];
+    int count = 0;
+    const char* c = scientific + (value < 0 || (value == 0 && scientific[0] == '-'));
+    for (; *c != 'e'; c++)
+        if (*c != '.')
+            digits[count++] = *c;
+    int exponent = atoi(c + 1);
+    while (count > 1 && digits[count - 1] == '0')
+        count--;
+
+    char* text = malloc(count + (exponent < 0 ? -exponent : exponent) + 8);
+    char* out = text;
+    if (scientific[0] == '-')
+        *out++ = '-';
+    if (exponent < 0)
+    {
+        *out++ = '0';
+        *out++ = '.';
+        for (int i = -1; i > exponent; i--)
+            *out++ = '0';
+        for (int i = 0; i < count; i++)
+            *out++ = digits[i];
+    }
+    else
+    {
+        for (int i = 0; i <= exponent; i++)
+            *out++ = i < count ? digits[i] : '0';
+        *out++ = '.';
+        if (count <= exponent + 1)
+            *out++ = '0';
+        for (int i = exponent + 1; i < count; i++)
+            *out++ = digits[i];
+    }
+    *out = '\0';
+    return text;
+}
+"#,
+    ),
+    (
+        "crust_pointer_str",
+        &[],
+        r#"static const char* crust_pointer_str(const void* pointer)
+{
+    (void)pointer;
+    return "<pointer>";
+}
+"#,
+    ),
+    (
+        "crust_concat",
+        &["crust_str"],
+        "static const char* crust_concat(const char* lhs, const char* rhs)
+{
+    lhs = crust_str(lhs);
+    rhs = crust_str(rhs);
+    size_t len = strlen(lhs);
+    char* text = malloc(len + strlen(rhs) + 1);
+    memcpy(text, lhs, len);
+    strcpy(text + len, rhs);
+    return text;
+}
+",
+    ),
+    (
+        "crust_strlen",
+        &[],
+        "/* The number of characters in the UTF-8 string, rather than of bytes. */
+static int crust_strlen(const char* value)
+{
+    int count = 0;
+    for (; value && *value; value++)
+        count += (*value & 0xC0) != 0x80;
+    return count;
+}
+",
+    ),
+    (
+        "crust_print",
+        &[],
+        "static int crust_print(const char* format, ...)
+{
+    va_list args;
+    va_start(args, format);
+    vprintf(format, args);
+    va_end(args);
+    fflush(stdout);
+    return 0;
+}
+",
+    ),
+    (
+        "crust_parse_int",
+        &[],
+        "/* Parses an int as the interpreter does, with an optional sign and nothing but digits. */
+static int crust_parse_int(const char* text, int* value)
+{
+    const char* digit = text + (*text == '+' || *text == '-');
+    long long parsed = 0;
+    if (!*digit)
+        return 0;
+    for (; *digit; digit++)
+    {
+        if (*digit < '0' || *digit > '9')
+            return 0;
+        parsed = parsed * 10 + (*digit - '0');
+        if (parsed > 2147483648LL)
+            return 0;
+    }
+    if (*text == '-')
+        parsed = -parsed;
+    if (parsed > 2147483647LL)
+        return 0;
+    *value = (int)parsed;
+    return 1;
+}
+",
+    ),
+    (
+        "crust_read_int",
+        &["crust_fail", "crust_parse_int"],
+        r#"static int crust_read_int(void)
+{
+    char line[4096];
+    if (!fgets(line, sizeof line, stdin))
+        line[0] = '\0';
+    char* start = line;
+    while (*start == ' ' || (*start >= '\t' && *start <= '\r'))
+        start++;
+    char* end = start + strlen(start);
+    while (end > start && (end[-1] == ' ' || (end[-1] >= '\t' && end[-1] <= '\r')))
+        end--;
+    *end = '\0';
+
+    int value;
+    if (!crust_parse_int(start, &value))
+    {
+        char message[4200];
+        snprintf(message, sizeof message, "read_int: '%s' is not a valid int", start);
+        crust_fail(message);
+    }
+    return value;
+}
+"#,
+    ),
+    (
+        "crust_check_args",
+        &[],
+        r#"static void crust_check_args(int argc, int params)
+{
+    if (argc - 1 != params)
+    {
+        fprintf(stderr, "main takes %d arguments but %d were supplied\n", params, argc - 1);
+        exit(255);
+    }
+}
+"#,
+    ),
+    (
+        "crust_arg_int",
+        &["crust_parse_int"],
+        r#"static int crust_arg_int(const char* arg)
+{
+    int value;
+    if (!crust_parse_int(arg, &value))
+    {
+        fprintf(stderr, "argument '%s' is not a valid int\n", arg);
+        exit(255);
+    }
+    return value;
+}
+"#,
+    ),
+];
+
+/// How tightly each kind of C expression binds, loosest first.
+const ASSIGN: u8 = 2;
+const COND: u8 = 3;
+const OR: u8 = 4;
+const AND: u8 = 5;
+const BIT_OR: u8 = 6;
+const BIT_XOR: u8 = 7;
+const BIT_AND: u8 = 8;
+const EQUALITY: u8 = 9;
+const RELATIONAL: u8 = 10;
+const SHIFT: u8 = 11;
+const ADDITIVE: u8 = 12;
+const MULTIPLICATIVE: u8 = 13;
+const UNARY: u8 = 14;
+const PRIMARY: u8 = 15;
+
+/// Generates the C source of a whole program, which must have an int returning `main`.
+pub fn emit_c(ast: &Ast) -> Result<String, Diagnostic> {
+    let types = TypeTable::new(ast).map_err(|mut errors| errors.remove(0))?;
+    let funcs = ast
+        .defs
+        .iter()
+        .filter_map(|def| match def {
+            Definition::Func(func) => Some((func.name.as_str(), func)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let Some(main) = funcs.get("main").copied() else {
+        return Err(Diagnostic::error("E0400", Message::new("main-not-found")));
+    };
+    if !types::is_int(&main.ret) {
+        return Err(
+            Diagnostic::error("E0400", Message::new("E0400.main-return"))
+                .with_label(main.span.clone(), Message::new("label.main-defined")),
+        );
+    }
+
+    let mut emitter = Emitter {
+        types,
+        builtins: Builtins::default(),
+        funcs,
+        globals: HashMap::new(),
+        taken: HashSet::new(),
+        helpers: HashSet::new(),
+        code: String::new(),
+        vars: Vec::new(),
+        ret: Type::Void,
+        indent: 0,
+        temps: Vec::new(),
+    };
+    for def in &ast.defs {
+        emitter.take(def);
+    }
+    emitter.structs(ast);
+    let globals = emitter.globals(ast)?;
+    emitter.prototypes(ast);
+    for def in &ast.defs {
+        if let Definition::Func(func) = def {
+            emitter.func(func)?;
+        }
+    }
+    emitter.entry(main, &globals)?;
+    Ok(emitter.finish())
+}
+
+/// The type of a variable or of the value of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Type {
+    Int,
+    Float,
+    Str,
+    Pointer(Box<Type>),
+    Struct(String),
+    /// An array of this many elements, which only a variable can be
+    Array(Box<Type>, u32),
+    Void,
+}
+
+impl Type {
+    fn of(ty: &str) -> Self {
+        if let Some(pointee) = ty.strip_suffix('*') {
+            return Self::Pointer(Box::new(Self::of(pointee)));
+        }
+        match ty {
+            "string" => Self::Str,
+            ast::VOID => Self::Void,
+            ty if types::is_int(ty) => Self::Int,
+            ty if types::is_float(ty) => Self::Float,
+            name => Self::Struct(name.to_string()),
+        }
+    }
+
+    /// The type in C, which for an array is the type of its elements.
+    fn c(&self) -> String {
+        match self {
+            Self::Int => String::from("int"),
+            Self::Float => String::from("double"),
+            Self::Str => String::from("const char*"),
+            Self::Pointer(pointee) => format!("{}*", pointee.c()),
+            Self::Struct(name) => format!("struct {}", c_name(name)),
+            Self::Array(elem, _) => elem.c(),
+            Self::Void => String::from("void"),
+        }
+    }
+
+    /// The name of the type in an error, which is the name the interpreter gives its values.
+    fn name(&self) -> String {
+        match self {
+            Self::Int => String::from("int"),
+            Self::Float => String::from("float"),
+            Self::Str => String::from("string"),
+            Self::Pointer(_) => String::from("pointer"),
+            Self::Struct(name) => name.clone(),
+            Self::Array(..) => String::from("array"),
+            Self::Void => String::from(ast::VOID),
+        }
+    }
+}
+
+/// An expression written in C, with its type and how tightly it binds.
+struct Code {
+    text: String,
+    ty: Type,
+    prec: u8,
+}
+
+impl Code {
+    fn new(text: String, ty: Type, prec: u8) -> Self {
+        Self { text, ty, prec }
+    }
+
+    /// The expression as the operand of an operator that binds as tightly as `prec`, in
+    /// parentheses if it binds more loosely.
+    fn at(&self, prec: u8) -> String {
+        // `- -x` mustn't become `--x`
+        let negated = prec == UNARY && self.text.starts_with('-');
+        match self.prec < prec || negated {
+            true => format!("({})", self.text),
+            false => self.text.clone(),
+        }
+    }
+}
+
+struct Emitter<'a> {
+    types: TypeTable,
+    builtins: Builtins,
+    funcs: HashMap<&'a str, &'a Func>,
+    /// The type and C name of each global
+    globals: HashMap<&'a str, (Type, String)>,
+    /// The C name of every function, global and variable, which a numbered name mustn't be
+    taken: HashSet<String>,
+    /// The helpers the code written so far calls
+    helpers: HashSet<&'static str>,
+    /// The structs, globals and functions written so far
+    code: String,
+    /// The variables of the function being written with their types and C names, latest last so
+    /// that it hides earlier ones
+    vars: Vec<(&'a str, Type, String)>,
+    /// The return type of the function being written
+    ret: Type,
+    /// How many levels the statements being written are indented
+    indent: usize,
+    /// The declarations of the temporaries the function being written needs, which are written
+    /// at the top of it
+    temps: Vec<String>,
+}
+
+impl<'a> Emitter<'a> {
+    /// Marks the C names `def` declares as taken.
+    fn take(&mut self, def: &'a Definition) {
+        fn declared(taken: &mut HashSet<String>, body: &[Spanned<Statement>]) {
+            for (statement, _) in body {
+                match statement {
+                    Statement::Assign { name, .. } | Statement::Array { name, .. } => {
+                        taken.insert(c_name(name));
+                    }
+                    Statement::Switch { cases, .. } => {
+                        for case in cases {
+                            declared(taken, &case.body);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        match def {
+            Definition::Func(func) => {
+                self.taken.insert(func_name(&func.name));
+                for param in &func.params {
+                    self.taken.insert(c_name(&param.name));
+                }
+                declared(&mut self.taken, &func.body);
+            }
+            Definition::Global(global) => {
+                self.taken.insert(c_name(&global.name));
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes the structs, each after those it holds a field of.
+    fn structs(&mut self, ast: &'a Ast) {
+        let structs = ast
+            .defs
+            .iter()
+            .filter_map(|def| match def {
+                Definition::Struct { name, params, .. } => Some((name.as_str(), params)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if structs.is_empty() {
+            return;
+        }
+
+        // declared first, so that a field can point to a struct written after it
+        for (name, _) in &structs {
+            writeln!(self.code, "struct {};", c_name(name)).unwrap();
+        }
+        self.code.push('\n');
+        let mut written = HashSet::new();
+        for (name, _) in &structs {
+            self.write_struct(name, &structs, &mut written);
+        }
+    }
+
+    fn write_struct(
+        &mut self,
+        name: &'a str,
+        structs: &[(&'a str, &'a Vec<Param>)],
+        written: &mut HashSet<&'a str>,
+    ) {
+        let Some((_, fields)) = structs.iter().find(|(other, _)| *other == name) else {
+            return;
+        };
+        if !written.insert(name) {
+            return;
+        }
+        for field in fields.iter() {
+            if let Type::Struct(inner) = Type::of(&field.ty) {
+                if let Some((inner, _)) = structs.iter().find(|(other, _)| *other == inner) {
+                    self.write_struct(inner, structs, written);
+                }
+            }
+        }
+
+        writeln!(self.code, "struct {}\n{{", c_name(name)).unwrap();
+        for field in fields.iter() {
+            let ty = Type::of(&field.ty);
+            writeln!(self.code, "    {} {};", ty.c(), c_name(&field.name)).unwrap();
+        }
+        self.code.push_str("};\n\n");
+    }
+
+    /// Writes the globals, those whose value is a literal with it, and returns the statements
+    /// that set the others, which `main` runs first.
+    fn globals(&mut self, ast: &'a Ast) -> Result<Vec<String>, Diagnostic> {
+        let mut sets = Vec::new();
+        for def in &ast.defs {
+            let Definition::Global(global) = def else {
+                continue;
+            };
+            let ty = Type::of(&global.ty);
+            if ty == Type::Void {
+                return Err(unsupported(
+                    &global.span,
+                    Message::new("feature.void-variable"),
+                ));
+            }
+            let name = c_name(&global.name);
+            let value = match &global.expr {
+                Some(expr) => Some((self.expr(expr)?, &expr.1)),
+                None => None,
+            };
+            match value {
+                Some((value, span)) if is_literal(&global.expr) => {
+                    let value = self.convert(&ty, value, span)?;
+                    // after the type, where it applies to a pointer rather than what it points to
+                    let constant = if global.constant { " const" } else { "" };
+                    writeln!(self.code, "static {}{constant} {name} = {value};", ty.c()).unwrap();
+                }
+                Some((value, span)) => {
+                    sets.push(format!("{name} = {};", self.convert(&ty, value, span)?));
+                    writeln!(self.code, "static {} {name};", ty.c()).unwrap();
+                }
+                None => writeln!(self.code, "static {} {name};", ty.c()).unwrap(),
+            }
+            self.globals.insert(&global.name, (ty, name));
+        }
+        // the temporaries of every global are declared in the function that sets them
+        sets.splice(0..0, std::mem::take(&mut self.temps));
+        if !self.globals.is_empty() {
+            self.code.push('\n');
+        }
+        Ok(sets)
+    }
+
+    /// Declares every function, so that each can call any other.
+    fn prototypes(&mut self, ast: &'a Ast) {
+        for def in &ast.defs {
+            if let Definition::Func(func) = def {
+                writeln!(self.code, "{};", signature(func)).unwrap();
+            }
+        }
+        self.code.push('\n');
+    }
+
+    fn func(&mut self, func: &'a Func) -> Result<(), Diagnostic> {
+        self.vars.clear();
+        self.ret = Type::of(&func.ret);
+        for param in &func.params {
+            let ty = Type::of(&param.ty);
+            let name = c_name(&param.name);
+            self.vars.push((&param.name, ty, name));
+        }
+
+        writeln!(self.code, "{}\n{{", signature(func)).unwrap();
+        let start = self.code.len();
+        self.indent = 1;
+        for statement in &func.body {
+            self.statement(statement)?;
+        }
+        let temps = std::mem::take(&mut self.temps)
+            .into_iter()
+            .map(|temp| format!("    {temp}\n"))
+            .collect::<String>();
+        self.code.insert_str(start, &temps);
+        if self.ret != Type::Void && !matches!(func.body.last(), Some((Statement::Return(_), _))) {
+            let message = format!("reached end of function {} with no return", func.name);
+            let fail = self.call("crust_fail", &[c_string(&message)], Type::Int);
+            self.line(format!("{};", fail.text));
+            // cc can't tell that crust_fail never returns
+            self.line("abort();");
+        }
+        self.code.push_str("}\n\n");
+        Ok(())
+    }
+
+    /// Writes the C `main`, which sets the globals and calls `main` with the command line.
+    fn entry(&mut self, main: &'a Func, globals: &[String]) -> Result<(), Diagnostic> {
+        if !globals.is_empty() {
+            self.code
+                .push_str("static void crust_set_globals(void)\n{\n");
+            for set in globals {
+                writeln!(self.code, "    {set}").unwrap();
+            }
+            self.code.push_str("}\n\n");
+        }
+
+        let mut args = Vec::new();
+        for (i, param) in main.params.iter().enumerate() {
+            let arg = format!("argv[{}]", i + 1);
+            args.push(match Type::of(&param.ty) {
+                Type::Str => arg,
+                Type::Int | Type::Float => self.call("crust_arg_int", &[arg], Type::Int).text,
+                ty => {
+                    return Err(mismatch(&param.span, &Type::Int, &ty));
+                }
+            });
+        }
+        match main.params.is_empty() {
+            true => self.code.push_str("int main(void)\n{\n"),
+            false => {
+                self.code.push_str("int main(int argc, char** argv)\n{\n");
+                let params = main.params.len().to_string();
+                let check = self.call(
+                    "crust_check_args",
+                    &[String::from("argc"), params],
+                    Type::Void,
+                );
+                writeln!(self.code, "    {};", check.text).unwrap();
+            }
+        }
+        if !globals.is_empty() {
+            self.code.push_str("    crust_set_globals();\n");
+        }
+        writeln!(
+            self.code,
+            "    return {}({});\n}}",
+            func_name("main"),
+            args.join(", ")
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    /// The whole source, with the headers and helpers the code needs before it.
+    fn finish(self) -> String {
+        let mut needed = HashSet::new();
+        let mut pending = self.helpers.into_iter().collect::<Vec<_>>();
+        while let Some(helper) = pending.pop() {
+            if needed.insert(helper) {
+                let (_, calls, _) = HELPERS.iter().find(|(name, ..)| *name == helper).unwrap();
+                pending.extend(calls.iter());
+            }
+        }
+
+        let mut source = format!(
+            "// Generated by crust {}, compile with `cc -std=c99`\n\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        for header in ["stdarg.h", "stdio.h", "stdlib.h", "string.h"] {
+            writeln!(source, "#include <{header}>").unwrap();
+        }
+        source.push('\n');
+        for (name, _, code) in HELPERS {
+            if needed.contains(name) {
+                source.push_str(code);
+                source.push('\n');
+            }
+        }
+        source + &self.code
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        for _ in 0..self.indent {
+            self.code.push_str("    ");
+        }
+        self.code.push_str(line.as_ref());
+        self.code.push('\n');
+    }
+
+    fn statement(&mut self, (statement, span): &'a Spanned<Statement>) -> Result<(), Diagnostic> {
+        match statement {
+            Statement::Invalid => {
+                return Err(unsupported(
+                    span,
+                    Message::new("feature.invalid-statements"),
+                ))
+            }
+            Statement::Expand { .. } => {
+                return Err(unsupported(span, Message::new("feature.unexpanded-macros")))
+            }
+            Statement::Inline { lang, .. } => {
+                return Err(unsupported(
+                    span,
+                    Message::new("feature.inline-block").arg("block", lang.keyword()),
+                ))
+            }
+            Statement::Continue => return Err(unsupported(span, Message::new("feature.continue"))),
+            Statement::Return(expr) => {
+                let value = self.expr(expr)?;
+                let ret = self.ret.clone();
+                let value = self.convert(&ret, value, &expr.1)?;
+                self.line(format!("return {value};"));
+            }
+            Statement::ReturnVoid => self.line("return;"),
+            Statement::Break => self.line("break;"),
+            Statement::Expr(expr) => {
+                let value = self.expr(expr)?;
+                self.line(format!("{};", value.text));
+            }
+            Statement::Assign { ty, name, expr } => {
+                let ty = Type::of(ty);
+                if ty == Type::Void {
+                    return Err(unsupported(span, Message::new("feature.void-variable")));
+                }
+                let value = self.expr(expr)?;
+                let value = self.convert(&ty, value, &expr.1)?;
+                let c = self.declare(name, ty.clone());
+                self.line(format!("{} {c} = {value};", ty.c()));
+            }
+            Statement::Array { ty, name, len } => {
+                let elem = Type::of(ty);
+                if elem == Type::Void {
+                    return Err(unsupported(span, Message::new("feature.void-variable")));
+                }
+                let c = self.declare(name, Type::Array(Box::new(elem.clone()), *len));
+                // C has no empty arrays, but an index into one always fails anyway
+                self.line(format!("{} {c}[{}] = {{0}};", elem.c(), (*len).max(1)));
+            }
+            Statement::Store { name, index, expr } => {
+                let (ty, c) = self.var(name, span)?;
+                let Type::Array(elem, len) = ty else {
+                    return Err(unsupported(span, Message::new("feature.index-non-array")));
+                };
+                let index = self.int_operand(index)?;
+                let value = self.expr(expr)?;
+                let value = self.convert(&elem, value, &expr.1)?;
+                let index = self.call("crust_index", &[index.text, len.to_string()], Type::Int);
+                self.line(format!("{c}[{}] = {value};", index.text));
+            }
+            Statement::Reassign { name, op, expr } => {
+                let (ty, c) = self.var(name, span)?;
+                if let Type::Array(..) = ty {
+                    return Err(unsupported(span, Message::new("feature.assign-array")));
+                }
+                let value = self.expr(expr)?;
+                let value = match op {
+                    AssignOp::Set => value,
+                    AssignOp::Add => {
+                        self.add(Code::new(c.clone(), ty.clone(), PRIMARY), value, span)?
+                    }
+                    AssignOp::Sub | AssignOp::Mul | AssignOp::Div => {
+                        let op = match op {
+                            AssignOp::Sub => BinOp::Sub,
+                            AssignOp::Mul => BinOp::Mul,
+                            _ => BinOp::Div,
+                        };
+                        let var = Code::new(c.clone(), ty.clone(), PRIMARY);
+                        self.binary(op, var, value, span)?
+                    }
+                };
+                let value = self.convert(&ty, value, &expr.1)?;
+                self.line(format!("{c} = {value};"));
+            }
+            Statement::Write { pointer, expr } => {
+                let target = self.expr(pointer)?;
+                let Type::Pointer(pointee) = target.ty.clone() else {
+                    return Err(mismatch(
+                        &pointer.1,
+                        &Type::Pointer(Box::new(Type::Int)),
+                        &target.ty,
+                    ));
+                };
+                let value = self.expr(expr)?;
+                let value = self.convert(&pointee, value, &expr.1)?;
+                self.line(format!("*{} = {value};", target.at(UNARY)));
+            }
+            Statement::Switch { expr, cases } => {
+                let value = self.int_operand(expr)?;
+                self.line(format!("switch ({})", value.text));
+                self.line("{");
+                for (i, case) in cases.iter().enumerate() {
+                    self.indent += 1;
+                    match case.value {
+                        // an int is never beyond 32 bits, so a case that is is only fallen into
+                        Some(value) if i32::try_from(value).is_err() => {}
+                        Some(value) => self.line(format!("case {value}:")),
+                        None => self.line("default:"),
+                    }
+                    self.indent += 1;
+                    for statement in &case.body {
+                        self.statement(statement)?;
+                    }
+                    // a label has to label a statement
+                    if i == cases.len() - 1 && case.body.is_empty() {
+                        self.line("break;");
+                    }
+                    self.indent -= 2;
+                }
+                self.line("}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Declares the variable `name` in the function being written, returning its C name, which is
+    /// numbered if it would hide a name the function can already use.
+    fn declare(&mut self, name: &'a str, ty: Type) -> String {
+        let mut c = c_name(name);
+        let hides = |c: &str, emitter: &Self| {
+            emitter.vars.iter().any(|(_, _, other)| other == c)
+                || emitter.globals.values().any(|(_, other)| other == c)
+                || emitter.funcs.keys().any(|func| func_name(func) == c)
+        };
+        if hides(&c, self) {
+            let base = c;
+            c = (1..)
+                .map(|n| format!("{base}_{n}"))
+                .find(|c| !self.taken.contains(c) && !hides(c, self))
+                .unwrap();
+            self.taken.insert(c.clone());
+        }
+        self.vars.push((name, ty, c.clone()));
+        c
+    }
+
+    /// The type and C name of the variable or global `name`.
+    fn var(&self, name: &str, span: &Span) -> Result<(Type, String), Diagnostic> {
+        if let Some((_, ty, c)) = self.vars.iter().rev().find(|(var, ..)| *var == name) {
+            return Ok((ty.clone(), c.clone()));
+        }
+        match self.globals.get(name) {
+            Some((ty, c)) => Ok((ty.clone(), c.clone())),
+            None => Err(ast::undeclared_variable(name, span)),
+        }
+    }
+
+    /// A call of `helper` with `args`, which is written with the program.
+    fn call(&mut self, helper: &'static str, args: &[String], ty: Type) -> Code {
+        self.helpers.insert(helper);
+        Code::new(format!("{helper}({})", args.join(", ")), ty, PRIMARY)
+    }
+
+    /// `value` stored where a `ty` is, truncated if it's a float stored in an int.
+    fn convert(&mut self, ty: &Type, value: Code, span: &Span) -> Result<String, Diagnostic> {
+        match (ty, &value.ty) {
+            (Type::Int, Type::Float) => Ok(self.call("crust_trunc", &[value.text], Type::Int).text),
+            (Type::Float, Type::Int) => Ok(value.text),
+            (ty, found) if ty == found && !matches!(ty, Type::Array(..) | Type::Void) => {
+                Ok(value.text)
+            }
+            (ty, found) => Err(mismatch(span, ty, found)),
+        }
+    }
+
+    /// Writes an expression whose value must be an int.
+    fn int_operand(&mut self, expr: &'a Spanned<Expr>) -> Result<Code, Diagnostic> {
+        let value = self.expr(expr)?;
+        match value.ty {
+            Type::Int => Ok(value),
+            _ => Err(mismatch(&expr.1, &Type::Int, &value.ty)),
+        }
+    }
+
+    fn expr(&mut self, (expr, span): &'a Spanned<Expr>) -> Result<Code, Diagnostic> {
+        Ok(match expr {
+            Expr::Err => {
+                return Err(unsupported(
+                    span,
+                    Message::new("feature.invalid-expressions"),
+                ))
+            }
+            Expr::Int(value) => int_literal(*value as i32),
+            Expr::Float(value) => float_literal(*value),
+            Expr::Str(value) => Code::new(c_string(value), Type::Str, PRIMARY),
+            Expr::Var(name) => {
+                let (ty, c) = self.var(name, span)?;
+                Code::new(c, ty, PRIMARY)
+            }
+            Expr::Neg(inner) => {
+                let value = self.expr(inner)?;
+                match (&inner.0, &value.ty) {
+                    // negating a literal that fits never wraps
+                    (Expr::Int(literal), Type::Int) if *literal <= i32::MAX as u32 => {
+                        int_literal(-(*literal as i32))
+                    }
+                    (_, Type::Int) => self.call("crust_neg", &[value.text], Type::Int),
+                    (_, Type::Float) => {
+                        Code::new(format!("-{}", value.at(UNARY)), Type::Float, UNARY)
+                    }
+                    (_, ty) => return Err(mismatch(&inner.1, &Type::Int, ty)),
+                }
+            }
+            Expr::Not(inner) => {
+                let value = self.int_operand(inner)?;
+                Code::new(format!("!{}", value.at(UNARY)), Type::Int, UNARY)
+            }
+            Expr::Add(lhs, rhs) => self.operands(BinOp::Add, lhs, rhs, span)?,
+            Expr::Sub(lhs, rhs) => self.operands(BinOp::Sub, lhs, rhs, span)?,
+            Expr::Mul(lhs, rhs) => self.operands(BinOp::Mul, lhs, rhs, span)?,
+            Expr::Div(lhs, rhs) => self.operands(BinOp::Div, lhs, rhs, span)?,
+            Expr::Rem(lhs, rhs) => self.operands(BinOp::Rem, lhs, rhs, span)?,
+            Expr::BitAnd(lhs, rhs) => self.operands(BinOp::And, lhs, rhs, span)?,
+            Expr::BitOr(lhs, rhs) => self.operands(BinOp::Or, lhs, rhs, span)?,
+            Expr::BitXor(lhs, rhs) => self.operands(BinOp::Xor, lhs, rhs, span)?,
+            Expr::Shl(lhs, rhs) => self.operands(BinOp::Shl, lhs, rhs, span)?,
+            Expr::Shr(lhs, rhs) => self.operands(BinOp::Shr, lhs, rhs, span)?,
+            Expr::Lt(lhs, rhs) => self.operands(BinOp::Lt, lhs, rhs, span)?,
+            Expr::Le(lhs, rhs) => self.operands(BinOp::Le, lhs, rhs, span)?,
+            Expr::Gt(lhs, rhs) => self.operands(BinOp::Gt, lhs, rhs, span)?,
+            Expr::Ge(lhs, rhs) => self.operands(BinOp::Ge, lhs, rhs, span)?,
+            Expr::Eq(lhs, rhs) => self.operands(BinOp::Eq, lhs, rhs, span)?,
+            Expr::Ne(lhs, rhs) => self.operands(BinOp::Ne, lhs, rhs, span)?,
+            Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => {
+                let (op, prec) = match expr {
+                    Expr::And(..) => ("&&", AND),
+                    _ => ("||", OR),
+                };
+                let (lhs, rhs) = (self.int_operand(lhs)?, self.int_operand(rhs)?);
+                let text = format!("{} {op} {}", lhs.at(prec), rhs.at(prec + 1));
+                Code::new(text, Type::Int, prec)
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                let cond = self.int_operand(cond)?;
+                let (then, otherwise) = (self.expr(then)?, self.expr(otherwise)?);
+                // the interpreter keeps whichever value it picks as it is, where C would convert
+                // an int to a float
+                if then.ty != otherwise.ty || matches!(then.ty, Type::Array(..) | Type::Void) {
+                    return Err(mismatch(span, &then.ty, &otherwise.ty));
+                }
+                let text = format!(
+                    "{} ? {} : {}",
+                    cond.at(OR),
+                    then.at(ASSIGN),
+                    otherwise.at(COND)
+                );
+                Code::new(text, then.ty, COND)
+            }
+            Expr::PreInc(name) | Expr::PreDec(name) => {
+                let (ty, c) = self.var(name, span)?;
+                if ty != Type::Int {
+                    return Err(mismatch(span, &Type::Int, &ty));
+                }
+                let helper = match expr {
+                    Expr::PreInc(_) => "crust_inc",
+                    _ => "crust_dec",
+                };
+                self.call(helper, &[format!("&{c}")], Type::Int)
+            }
+            Expr::AddrOf(name) => {
+                let (ty, c) = self.var(name, span)?;
+                if let Type::Array(..) = ty {
+                    return Err(unsupported(span, Message::new("feature.array-as-value")));
+                }
+                Code::new(format!("&{c}"), Type::Pointer(Box::new(ty)), UNARY)
+            }
+            Expr::Deref(pointer) => {
+                let value = self.expr(pointer)?;
+                let Type::Pointer(pointee) = value.ty.clone() else {
+                    return Err(mismatch(
+                        &pointer.1,
+                        &Type::Pointer(Box::new(Type::Int)),
+                        &value.ty,
+                    ));
+                };
+                Code::new(format!("*{}", value.at(UNARY)), *pointee, UNARY)
+            }
+            Expr::SizeOf(ty) => match self.types.size_of(ty) {
+                Ok(size) => int_literal(size as i32),
+                // the interpreter only fails on it if it's reached
+                Err(error) => self.call(
+                    "crust_fail",
+                    &[c_string(&error.message.to_string())],
+                    Type::Int,
+                ),
+            },
+            Expr::Spawn { .. } | Expr::Join(_) => {
+                return Err(unsupported(span, Message::new("feature.threads")))
+            }
+            Expr::Index(array, index) => {
+                let (Expr::Var(name), _) = &**array else {
+                    return Err(unsupported(
+                        span,
+                        Message::new("feature.index-non-variable"),
+                    ));
+                };
+                let (ty, c) = self.var(name, &array.1)?;
+                let Type::Array(elem, len) = ty else {
+                    return Err(unsupported(span, Message::new("feature.index-non-array")));
+                };
+                let index = self.int_operand(index)?;
+                let index = self.call("crust_index", &[index.text, len.to_string()], Type::Int);
+                Code::new(format!("{c}[{}]", index.text), *elem, PRIMARY)
+            }
+            Expr::Call { name, params } => {
+                // builtins shadow the functions of the program, as they do in the interpreter
+                if self.builtins.contains(name) {
+                    return self.builtin(name, params, span);
+                }
+                let Some(func) = self.funcs.get(name.as_str()).copied() else {
+                    return Err(Diagnostic::error(
+                        "E0400",
+                        Message::new("unknown-function").arg("name", name.as_str()),
+                    )
+                    .with_label(span.clone(), Message::new("label.called-here")));
+                };
+                if func.params.len() != params.len() {
+                    return Err(ast::arity_error(func, params.len(), span));
+                }
+                let (values, assignments) = self.sequence(&params.iter().collect::<Vec<_>>())?;
+                let mut args = Vec::new();
+                for ((param, arg), value) in func.params.iter().zip(params).zip(values) {
+                    args.push(self.convert(&Type::of(&param.ty), value, &arg.1)?);
+                }
+                let text = format!("{}({})", func_name(name), args.join(", "));
+                after(assignments, Code::new(text, Type::of(&func.ret), PRIMARY))
+            }
+        })
+    }
+
+    /// Writes `lhs op rhs`, with its operands evaluated in order.
+    fn operands(
+        &mut self,
+        op: BinOp,
+        lhs: &'a Spanned<Expr>,
+        rhs: &'a Spanned<Expr>,
+        span: &Span,
+    ) -> Result<Code, Diagnostic> {
+        let (mut values, assignments) = self.sequence(&[lhs, rhs])?;
+        let (rhs, lhs) = (values.pop().unwrap(), values.pop().unwrap());
+        let value = match op {
+            BinOp::Add => self.add(lhs, rhs, span)?,
+            _ => self.binary(op, lhs, rhs, span)?,
+        };
+        Ok(after(assignments, value))
+    }
+
+    /// Writes `exprs`, the operands of an operator or arguments of a call, so that they're
+    /// evaluated in order. If one of them has effects, it and each before it are stored in
+    /// temporaries first, unless nothing but literals comes after them, since C could evaluate the
+    /// others on either side of it.
+    /// Returns the values, and the assignments to the temporaries that have to come first.
+    fn sequence(
+        &mut self,
+        exprs: &[&'a Spanned<Expr>],
+    ) -> Result<(Vec<Code>, Vec<String>), Diagnostic> {
+        let literal =
+            |expr: &Spanned<Expr>| matches!(expr.0, Expr::Int(_) | Expr::Float(_) | Expr::Str(_));
+        let last = exprs.iter().rposition(|expr| has_effects(expr));
+        let mut values = Vec::new();
+        let mut assignments = Vec::new();
+        for (i, expr) in exprs.iter().enumerate() {
+            let value = self.expr(expr)?;
+            // a literal is the same whenever it's evaluated
+            let read_later = exprs[i + 1..].iter().any(|expr| !literal(expr));
+            if literal(expr) || last.is_none_or(|last| i > last) || !read_later {
+                values.push(value);
+                continue;
+            }
+            let temp = format!("crust_t{}", self.temps.len());
+            self.temps.push(format!("{} {temp};", value.ty.c()));
+            assignments.push(format!("{temp} = {}", value.at(ASSIGN)));
+            values.push(Code::new(temp, value.ty, PRIMARY));
+        }
+        Ok((values, assignments))
+    }
+
+    /// `lhs + rhs`, which concatenates if either is a string.
+    fn add(&mut self, lhs: Code, rhs: Code, span: &Span) -> Result<Code, Diagnostic> {
+        if lhs.ty != Type::Str && rhs.ty != Type::Str {
+            return self.binary(BinOp::Add, lhs, rhs, span);
+        }
+        let lhs = self.string(lhs, span)?;
+        let rhs = self.string(rhs, span)?;
+        Ok(self.call("crust_concat", &[lhs, rhs], Type::Str))
+    }
+
+    /// `value` as the string the interpreter would concatenate or print.
+    fn string(&mut self, value: Code, span: &Span) -> Result<String, Diagnostic> {
+        Ok(match value.ty {
+            Type::Str => value.text,
+            Type::Int => self.call("crust_int_str", &[value.text], Type::Str).text,
+            Type::Float => self.call("crust_float_str", &[value.text], Type::Str).text,
+            Type::Pointer(_) => {
+                self.call("crust_pointer_str", &[value.text], Type::Str)
+                    .text
+            }
+            Type::Array(..) => {
+                return Err(unsupported(span, Message::new("feature.array-as-value")))
+            }
+            ty => return Err(mismatch(span, &Type::Str, &ty)),
+        })
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: Code, rhs: Code, span: &Span) -> Result<Code, Diagnostic> {
+        let number = |ty: &Type| matches!(ty, Type::Int | Type::Float);
+        let ints = lhs.ty == Type::Int && rhs.ty == Type::Int;
+        let floats = number(&lhs.ty) && number(&rhs.ty) && !ints;
+        let helper = match op {
+            BinOp::Add => "crust_add",
+            BinOp::Sub => "crust_sub",
+            BinOp::Mul => "crust_mul",
+            BinOp::Div => "crust_div",
+            BinOp::Rem => "crust_rem",
+            BinOp::Shl => "crust_shl",
+            BinOp::Shr => "crust_shr",
+            _ => "",
+        };
+        let (text, prec) = match op {
+            BinOp::Add => ("+", ADDITIVE),
+            BinOp::Sub => ("-", ADDITIVE),
+            BinOp::Mul => ("*", MULTIPLICATIVE),
+            BinOp::Div => ("/", MULTIPLICATIVE),
+            BinOp::Rem => ("%", MULTIPLICATIVE),
+            BinOp::Shl => ("<<", SHIFT),
+            BinOp::Shr => (">>", SHIFT),
+            BinOp::And => ("&", BIT_AND),
+            BinOp::Or => ("|", BIT_OR),
+            BinOp::Xor => ("^", BIT_XOR),
+            BinOp::Lt => ("<", RELATIONAL),
+            BinOp::Le => ("<=", RELATIONAL),
+            BinOp::Gt => (">", RELATIONAL),
+            BinOp::Ge => (">=", RELATIONAL),
+            BinOp::Eq => ("==", EQUALITY),
+            BinOp::Ne => ("!=", EQUALITY),
+        };
+        let float_op = matches!(op, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div);
+        let comparison = prec == RELATIONAL || prec == EQUALITY;
+
+        if ints && !helper.is_empty() {
+            return Ok(self.call(helper, &[lhs.text, rhs.text], Type::Int));
+        }
+        if ints || floats && (float_op || comparison) {
+            let ty = match floats && float_op {
+                true => Type::Float,
+                false => Type::Int,
+            };
+            let text = format!("{} {text} {}", lhs.at(prec), rhs.at(prec + 1));
+            return Ok(Code::new(text, ty, prec));
+        }
+        let found = match lhs.ty {
+            Type::Int => &rhs.ty,
+            _ => &lhs.ty,
+        };
+        Err(mismatch(span, &Type::Int, found))
+    }
+
+    fn builtin(
+        &mut self,
+        name: &str,
+        params: &'a [Spanned<Expr>],
+        span: &Span,
+    ) -> Result<Code, Diagnostic> {
+        match name {
+            "print" | "println" => {
+                let mut format = String::new();
+                let mut args = vec![String::new()];
+                let (values, assignments) = self.sequence(&params.iter().collect::<Vec<_>>())?;
+                for (i, (param, value)) in params.iter().zip(values).enumerate() {
+                    if i > 0 {
+                        format.push(' ');
+                    }
+                    if let (Expr::Str(value), _) = param {
+                        format.push_str(&value.replace('%', "%%"));
+                        continue;
+                    }
+                    match value.ty {
+                        Type::Int => {
+                            format.push_str("%d");
+                            args.push(value.text);
+                        }
+                        Type::Str => {
+                            format.push_str("%s");
+                            args.push(self.call("crust_str", &[value.text], Type::Str).text);
+                        }
+                        _ => {
+                            format.push_str("%s");
+                            args.push(self.string(value, &param.1)?);
+                        }
+                    }
+                }
+                if name == "println" {
+                    format.push('\n');
+                }
+                args[0] = c_string(&format);
+                Ok(after(
+                    assignments,
+                    self.call("crust_print", &args, Type::Int),
+                ))
+            }
+            "read_int" if params.is_empty() => Ok(self.call("crust_read_int", &[], Type::Int)),
+            "len" => {
+                let [param] = params else {
+                    return Err(unsupported(span, Message::new("feature.len-arity")));
+                };
+                if let (Expr::Var(name), _) = param {
+                    if let (Type::Array(_, len), _) = self.var(name, &param.1)? {
+                        return Ok(int_literal(len as i32));
+                    }
+                }
+                let value = self.expr(param)?;
+                match value.ty {
+                    Type::Str => Ok(self.call("crust_strlen", &[value.text], Type::Int)),
+                    ty => Err(mismatch(&param.1, &Type::Str, &ty)),
+                }
+            }
+            _ => Err(unsupported(
+                span,
+                Message::new("feature.builtin").arg("name", name),
+            )),
+        }
+    }
+}
+
+/// `value`, written after `assignments`, which C evaluates in order.
+fn after(assignments: Vec<String>, value: Code) -> Code {
+    match assignments.is_empty() {
+        true => value,
+        false => Code::new(
+            format!("({}, {})", assignments.join(", "), value.text),
+            value.ty,
+            PRIMARY,
+        ),
+    }
+}
+
+/// Whether evaluating `expr` may do more than give its value: call a function, change a variable
+/// or fail.
+fn has_effects(expr: &Spanned<Expr>) -> bool {
+    struct Effects(bool);
+
+    impl<'a> Visitor<'a> for Effects {
+        fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+            self.0 |= matches!(
+                expr.0,
+                Expr::Call { .. }
+                    | Expr::PreInc(_)
+                    | Expr::PreDec(_)
+                    | Expr::Div(..)
+                    | Expr::Rem(..)
+                    | Expr::Index(..)
+                    | Expr::Spawn { .. }
+                    | Expr::Join(_)
+            );
+            walk_expr(self, expr);
+        }
+    }
+
+    let mut effects = Effects(false);
+    effects.visit_expr(expr);
+    effects.0
+}
+
+/// Whether the value of a global is a literal, which C can set it to where it's declared.
+fn is_literal(expr: &Option<Spanned<Expr>>) -> bool {
+    match expr.as_ref().map(|(expr, _)| expr) {
+        Some(Expr::Int(_) | Expr::Float(_) | Expr::Str(_)) => true,
+        Some(Expr::Neg(inner)) => matches!(inner.0, Expr::Int(_) | Expr::Float(_)),
+        _ => false,
+    }
+}
+
+/// The C declaration of `func`.
+fn signature(func: &Func) -> String {
+    let params = func
+        .params
+        .iter()
+        .map(|param| format!("{} {}", Type::of(&param.ty).c(), c_name(&param.name)))
+        .collect::<Vec<_>>();
+    let params = match params.is_empty() {
+        true => String::from("void"),
+        false => params.join(", "),
+    };
+    format!(
+        "static {} {}({params})",
+        Type::of(&func.ret).c(),
+        func_name(&func.name)
+    )
+}
+
+/// The name of a struct, global or variable in C.
+fn c_name(name: &str) -> String {
+    let reserved = KEYWORDS
+        .split_whitespace()
+        .chain(LIBRARY.split_whitespace())
+        .any(|reserved| reserved == name)
+        || name.starts_with("crust_")
+        || name.starts_with("__")
+        || name.starts_with('_') && name[1..].starts_with(|c: char| c.is_ascii_uppercase());
+    match reserved {
+        true => format!("crust_u_{name}"),
+        false => name.to_string(),
+    }
+}
+
+/// The name of a function in C, where `main` is the function that reads the command line.
+fn func_name(name: &str) -> String {
+    match name {
+        "main" => String::from("crust_main"),
+        name => c_name(name),
+    }
+}
+
+fn int_literal(value: i32) -> Code {
+    match value {
+        // the literal is the negation of a number too large to be an int
+        i32::MIN => Code::new(String::from("(-2147483647 - 1)"), Type::Int, PRIMARY),
+        value if value < 0 => Code::new(value.to_string(), Type::Int, UNARY),
+        _ => Code::new(value.to_string(), Type::Int, PRIMARY),
+    }
+}
+
+fn float_literal(value: f64) -> Code {
+    let (text, prec) = match value {
+        value if value.is_nan() => (String::from("(0.0 / 0.0)"), PRIMARY),
+        value if value.is_infinite() && value > 0.0 => (String::from("(1.0 / 0.0)"), PRIMARY),
+        value if value.is_infinite() => (String::from("(-1.0 / 0.0)"), PRIMARY),
+        value if value.is_sign_negative() => (literal::float(value), UNARY),
+        value => (literal::float(value), PRIMARY),
+    };
+    Code::new(text, Type::Float, prec)
+}
+
+/// `value` as a C string literal.
+fn c_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    let mut previous = 0;
+    for byte in value.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            b'\r' => escaped.push_str("\\r"),
+            // `??` starts a trigraph
+            b'?' if previous == b'?' => escaped.push_str("\\?"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => write!(escaped, "\\{byte:03o}").unwrap(),
+        }
+        previous = byte;
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn mismatch(span: &Span, expected: &Type, found: &Type) -> Diagnostic {
+    let feature = match found {
+        Type::Void => Message::new("feature.void-value"),
+        Type::Array(..) => Message::new("feature.array-as-value"),
+        _ => Message::new("feature.type-mismatch")
+            .arg("found", found.name())
+            .arg("expected", expected.name()),
+    };
+    unsupported(span, feature)
+}
+
+fn unsupported(span: &Span, feature: Message) -> Diagnostic {
+    Diagnostic::error(
+        "E0400",
+        Message::new("E0400.unsupported-c").arg("feature", feature),
+    )
+    .with_label(span.clone(), Message::new("label.used-here"))
+    .with_note(Message::new("note.use-interpreter"))
+}
@@ -0,0 +1,662 @@
+//! Native code generation for x86-64 Linux.
+//!
+//! Programs are translated from the [`ir`] to GNU assembly, which the system C compiler (`cc`)
+//! assembles and links against libc. Every temporary and array element takes an 8 byte slot in
+//! the stack frame, operands are loaded into registers for each instruction, and ints use 32 bit
+//! instructions so that they wrap exactly as [`semantics`](crate::semantics) specifies.
+//!
+//! The interpreter remains the reference implementation. Anything that can't be compiled with
+//! the same behaviour is rejected by [`ir::lower`], and runtime errors print a message to stderr
+//! and exit with status 255, the same status the CLI uses when the interpreter fails.
+
+use std::{
+    fmt::Write,
+    fs,
+    path::Path,
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    ast::{InlineLang, DEFAULT_MAX_CALL_DEPTH},
+    diagnostics::Diagnostic,
+    ir::{self, BinOp, BlockId, Function, InlinePiece, Inst, Operand, Terminator, Ty},
+    messages::Message,
+    semantics::ArithError,
+    token::Span,
+};
+
+/// Registers used for the first six arguments of a call, in order.
+const ARG_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+/// What the system toolchain should produce from the generated assembly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// A relocatable object file
+    Object,
+    /// A linked executable
+    Executable,
+}
+
+/// Generates the assembly for a whole program.
+pub fn emit_asm(program: &ir::Program) -> Result<String, Diagnostic> {
+    let mut emitter = Emitter {
+        text: String::new(),
+        strings: Vec::new(),
+        labels: 0,
+    };
+
+    let main = program
+        .func("main")
+        .ok_or_else(|| Diagnostic::error("E0400", Message::new("main-not-found")))?;
+    emitter.entry(main)?;
+    for func in &program.funcs {
+        emitter.func(func)?;
+    }
+
+    Ok(emitter.finish())
+}
+
+/// Assembles (and for [`Output::Executable`], links) `asm` into `output` using `cc`.
+pub fn assemble(asm: &str, output: &Path, kind: Output) -> Result<(), Diagnostic> {
+    let failed = |message: Message| Diagnostic::error("E0401", message);
+
+    // several programs may be assembled at once, by this process and others
+    static ASSEMBLED: AtomicUsize = AtomicUsize::new(0);
+    let count = ASSEMBLED.fetch_add(1, Ordering::Relaxed);
+    let source = std::env::temp_dir().join(format!("crust-{}-{count}.s", std::process::id()));
+    fs::write(&source, asm)
+        .map_err(|e| failed(Message::new("E0401.write").arg("error", e.to_string())))?;
+
+    let mut command = Command::new("cc");
+    if kind == Output::Object {
+        command.arg("-c");
+    }
+    let result = command.arg(&source).arg("-o").arg(output).output();
+    let _ = fs::remove_file(&source);
+
+    let result =
+        result.map_err(|e| failed(Message::new("E0401.run").arg("error", e.to_string())))?;
+    if !result.status.success() {
+        return Err(
+            failed(Message::new("E0401.failed")).with_note(Message::Text(
+                String::from_utf8_lossy(&result.stderr).trim().to_string(),
+            )),
+        );
+    }
+    Ok(())
+}
+
+/// Where each temporary and array of a function lives, as offsets below `%rbp`.
+///
+/// Arrays store their first element at `-offset(%rbp)` and the rest at increasing addresses.
+struct Frame {
+    temps: Vec<usize>,
+    arrays: Vec<usize>,
+    /// Bytes of stack used by the frame, kept a multiple of 16 so calls stay aligned
+    size: usize,
+}
+
+impl Frame {
+    fn new(func: &Function) -> Self {
+        let mut used = 0;
+        let temps = func
+            .temps
+            .iter()
+            .map(|_| {
+                used += 8;
+                used
+            })
+            .collect();
+        let arrays = func
+            .arrays
+            .iter()
+            .map(|array| {
+                used += 8 * array.len as usize;
+                used
+            })
+            .collect();
+        Self {
+            temps,
+            arrays,
+            size: used.next_multiple_of(16),
+        }
+    }
+}
+
+struct Emitter {
+    text: String,
+    /// String literals, each emitted as `.Lstr{index}`
+    strings: Vec<String>,
+    labels: usize,
+}
+
+impl Emitter {
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.text.push_str("    ");
+        self.text.push_str(line.as_ref());
+        self.text.push('\n');
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn place_label(&mut self, label: &str) {
+        writeln!(self.text, "{label}:").unwrap();
+    }
+
+    fn string(&mut self, value: &str) -> String {
+        let index = match self.strings.iter().position(|s| s == value) {
+            Some(index) => index,
+            None => {
+                self.strings.push(value.to_string());
+                self.strings.len() - 1
+            }
+        };
+        format!(".Lstr{index}")
+    }
+
+    /// Loads the address of a string constant into `register`.
+    fn load_string(&mut self, value: &str, register: &str) {
+        let label = self.string(value);
+        self.line(format!("leaq {label}(%rip), {register}"));
+    }
+
+    /// Loads `operand` into the 64 bit `register`.
+    fn load(&mut self, operand: &Operand, register: &str, frame: &Frame) {
+        match operand {
+            Operand::Temp(temp) => {
+                self.line(format!("movq -{}(%rbp), {register}", frame.temps[temp.0]));
+            }
+            Operand::Int(value) => self.line(format!("movq ${value}, {register}")),
+            Operand::Str(value) => self.load_string(value, register),
+        }
+    }
+
+    fn store(&mut self, temp: ir::Temp, frame: &Frame) {
+        self.line(format!("movq %rax, -{}(%rbp)", frame.temps[temp.0]));
+    }
+
+    /// Jumps to a runtime error with `message` when the preceding comparison set `condition`.
+    fn error_if(&mut self, condition: &str, message: &str) {
+        let ok = self.label();
+        self.line(format!("j{} {ok}", invert(condition)));
+        self.load_string(message, "%rdi");
+        self.line("call crust_error");
+        self.place_label(&ok);
+    }
+
+    /// The `main` symbol called by libc, which parses the command line and calls `main`.
+    fn entry(&mut self, main: &Function) -> Result<(), Diagnostic> {
+        if main.params.len() > ARG_REGISTERS.len() {
+            return Err(unsupported(
+                &main.span,
+                Message::new("feature.many-main-params")
+                    .arg("count", ARG_REGISTERS.len().to_string()),
+            ));
+        }
+
+        self.text.push_str("    .globl main\nmain:\n");
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        self.line("pushq %rbx");
+        self.line("subq $56, %rsp");
+        self.line("movq %rsi, %rbx");
+
+        let ok = self.label();
+        self.line(format!("cmpl ${}, %edi", main.params.len() + 1));
+        self.line(format!("je {ok}"));
+        self.line("leal -1(%rdi), %ecx");
+        self.line(format!("movl ${}, %edx", main.params.len()));
+        self.load_string("main takes %d arguments but %d were supplied\n", "%rsi");
+        self.line("movl $2, %edi");
+        self.line("xorl %eax, %eax");
+        self.line("call dprintf@PLT");
+        self.line("movl $255, %edi");
+        self.line("call exit@PLT");
+        self.place_label(&ok);
+
+        for (i, param) in main.params.iter().enumerate() {
+            self.line(format!("movq {}(%rbx), %rdi", 8 * (i + 1)));
+            if main.temps[param.0].ty == Ty::Int {
+                self.line("call crust_parse_int");
+                self.line("movq %rax, %rdi");
+            }
+            self.line(format!("movq %rdi, {}(%rsp)", 8 * i));
+        }
+        for (i, register) in ARG_REGISTERS.iter().take(main.params.len()).enumerate() {
+            self.line(format!("movq {}(%rsp), {register}", 8 * i));
+        }
+        self.line("call crust_fn_main");
+        self.line("movq -8(%rbp), %rbx");
+        self.line("leave");
+        self.line("ret");
+        self.text.push('\n');
+        Ok(())
+    }
+
+    fn func(&mut self, func: &Function) -> Result<(), Diagnostic> {
+        if func.params.len() > ARG_REGISTERS.len() {
+            return Err(unsupported(
+                &func.span,
+                Message::new("feature.many-params").arg("count", ARG_REGISTERS.len().to_string()),
+            ));
+        }
+
+        let frame = Frame::new(func);
+        writeln!(self.text, "crust_fn_{}:", func.name).unwrap();
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        self.line(format!("subq ${}, %rsp", frame.size));
+
+        // track the call depth so that runaway recursion fails the way it does in the interpreter
+        // rather than by overflowing the native stack, main counts as the first call
+        self.line("movl crust_call_depth(%rip), %eax");
+        self.line("incl %eax");
+        self.line(format!("cmpl ${}, %eax", DEFAULT_MAX_CALL_DEPTH + 1));
+        let message =
+            format!("stack overflow, exceeded the maximum call depth of {DEFAULT_MAX_CALL_DEPTH}");
+        self.error_if("a", &message);
+        self.line("movl %eax, crust_call_depth(%rip)");
+
+        for (param, register) in func.params.iter().zip(ARG_REGISTERS) {
+            self.line(format!("movq {register}, -{}(%rbp)", frame.temps[param.0]));
+        }
+
+        for (i, block) in func.blocks.iter().enumerate() {
+            self.place_label(&block_label(func, i));
+            for inst in &block.insts {
+                self.inst(inst, func, &frame)?;
+            }
+            self.terminator(&block.terminator, BlockId(i), func, &frame);
+        }
+        self.text.push('\n');
+        Ok(())
+    }
+
+    fn inst(&mut self, inst: &Inst, func: &Function, frame: &Frame) -> Result<(), Diagnostic> {
+        match inst {
+            Inst::Copy { dest, src } => {
+                self.load(src, "%rax", frame);
+                self.store(*dest, frame);
+            }
+            Inst::Neg { dest, src } => {
+                self.load(src, "%rax", frame);
+                self.line("negl %eax");
+                self.store(*dest, frame);
+            }
+            Inst::Not { dest, src } => {
+                self.load(src, "%rax", frame);
+                self.line("testl %eax, %eax");
+                self.line("sete %al");
+                self.line("movzbl %al, %eax");
+                self.store(*dest, frame);
+            }
+            Inst::Binary { dest, op, lhs, rhs } => {
+                self.load(lhs, "%rax", frame);
+                self.load(rhs, "%rcx", frame);
+                match op {
+                    BinOp::Add => self.line("addl %ecx, %eax"),
+                    BinOp::Sub => self.line("subl %ecx, %eax"),
+                    BinOp::Mul => self.line("imull %ecx, %eax"),
+                    BinOp::Div => {
+                        self.line("testl %ecx, %ecx");
+                        self.error_if("e", &Message::from(ArithError::DivisionByZero).to_string());
+
+                        // idiv traps on INT_MIN / -1, whose wrapped result is the dividend itself
+                        let done = self.label();
+                        let divide = self.label();
+                        self.line("cmpl $-1, %ecx");
+                        self.line(format!("jne {divide}"));
+                        self.line("cmpl $-2147483648, %eax");
+                        self.line(format!("je {done}"));
+                        self.place_label(&divide);
+                        self.line("cltd");
+                        self.line("idivl %ecx");
+                        self.place_label(&done);
+                    }
+                    BinOp::Rem => {
+                        self.line("testl %ecx, %ecx");
+                        self.error_if("e", &Message::from(ArithError::DivisionByZero).to_string());
+
+                        // idiv traps on INT_MIN % -1, and anything % -1 is 0
+                        let done = self.label();
+                        let divide = self.label();
+                        self.line("cmpl $-1, %ecx");
+                        self.line(format!("jne {divide}"));
+                        self.line("xorl %eax, %eax");
+                        self.line(format!("jmp {done}"));
+                        self.place_label(&divide);
+                        self.line("cltd");
+                        self.line("idivl %ecx");
+                        self.line("movl %edx, %eax");
+                        self.place_label(&done);
+                    }
+                    BinOp::And => self.line("andl %ecx, %eax"),
+                    BinOp::Or => self.line("orl %ecx, %eax"),
+                    BinOp::Xor => self.line("xorl %ecx, %eax"),
+                    // shifts of 32-bit registers only use the low 5 bits of %cl
+                    BinOp::Shl => self.line("shll %cl, %eax"),
+                    BinOp::Shr => self.line("sarl %cl, %eax"),
+                    BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge | BinOp::Eq | BinOp::Ne => {
+                        let condition = match op {
+                            BinOp::Lt => "l",
+                            BinOp::Le => "le",
+                            BinOp::Gt => "g",
+                            BinOp::Ge => "ge",
+                            BinOp::Eq => "e",
+                            _ => "ne",
+                        };
+                        self.line("cmpl %ecx, %eax");
+                        self.line(format!("set{condition} %al"));
+                        self.line("movzbl %al, %eax");
+                    }
+                }
+                self.store(*dest, frame);
+            }
+            Inst::Fill { array, value } => {
+                let len = func.arrays[array.0].len;
+                self.load(value, "%rax", frame);
+                self.line(format!("leaq -{}(%rbp), %rdi", frame.arrays[array.0]));
+                self.line(format!("movl ${len}, %ecx"));
+                self.line("rep stosq");
+            }
+            Inst::Load { dest, array, index } => {
+                self.load(index, "%rax", frame);
+                self.bounds_check("%eax", func.arrays[array.0].len);
+                self.line(format!("leaq -{}(%rbp), %rdx", frame.arrays[array.0]));
+                self.line("movq (%rdx,%rax,8), %rax");
+                self.store(*dest, frame);
+            }
+            Inst::Store {
+                array,
+                index,
+                value,
+            } => {
+                self.load(index, "%rcx", frame);
+                self.load(value, "%rax", frame);
+                self.bounds_check("%ecx", func.arrays[array.0].len);
+                self.line(format!("leaq -{}(%rbp), %rdx", frame.arrays[array.0]));
+                self.line("movq %rax, (%rdx,%rcx,8)");
+            }
+            Inst::Call {
+                dest,
+                func: callee,
+                args,
+            } => {
+                for (arg, register) in args.iter().zip(ARG_REGISTERS) {
+                    self.load(arg, register, frame);
+                }
+                self.line(format!("call crust_fn_{callee}"));
+                if let Some(dest) = dest {
+                    self.store(*dest, frame);
+                }
+            }
+            Inst::Print { args, newline } => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.line("movl $32, %edi");
+                        self.line("call putchar@PLT");
+                    }
+                    self.load(arg, "%rdi", frame);
+                    match func.ty(arg) {
+                        Ty::Int => self.line("call crust_print_int"),
+                        Ty::Str => self.line("call crust_print_str"),
+                        Ty::Void => unreachable!("no operand is void"),
+                    }
+                }
+                if *newline {
+                    self.line("movl $10, %edi");
+                    self.line("call putchar@PLT");
+                } else {
+                    self.line("xorl %edi, %edi");
+                    self.line("call fflush@PLT");
+                }
+            }
+            Inst::ReadInt { dest } => {
+                self.line("call crust_read_int");
+                self.store(*dest, frame);
+            }
+            Inst::StrLen { dest, src } => {
+                self.load(src, "%rdi", frame);
+                self.line("call strlen@PLT");
+                self.store(*dest, frame);
+            }
+            // the blocks that jump here have already copied their operand to `dest`
+            Inst::Phi { .. } => {}
+            Inst::Inline {
+                lang: InlineLang::Asm,
+                pieces,
+                ..
+            } => {
+                let code = pieces
+                    .iter()
+                    .map(|piece| match piece {
+                        InlinePiece::Text(text) => text.clone(),
+                        InlinePiece::Temp(temp) => format!("-{}(%rbp)", frame.temps[temp.0]),
+                        InlinePiece::Array(array) => format!("-{}(%rbp)", frame.arrays[array.0]),
+                    })
+                    .collect::<String>();
+                for line in code.lines() {
+                    self.line(line);
+                }
+            }
+            Inst::Inline { lang, span, .. } => {
+                return Err(Diagnostic::error(
+                    "E0400",
+                    Message::new("E0400.unsupported-native").arg(
+                        "feature",
+                        Message::new("feature.inline-block").arg("block", lang.keyword()),
+                    ),
+                )
+                .with_label(span.clone(), Message::new("label.used-here"))
+                .with_note(Message::new("note.inline-backends")))
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the operands the phis of `to` take from `from`, before `from` jumps there.
+    fn phi_moves(&mut self, from: BlockId, to: BlockId, func: &Function, frame: &Frame) {
+        for (dest, value) in func.phi_moves(from, to) {
+            self.load(value, "%rax", frame);
+            self.store(dest, frame);
+        }
+    }
+
+    fn terminator(
+        &mut self,
+        terminator: &Terminator,
+        block: BlockId,
+        func: &Function,
+        frame: &Frame,
+    ) {
+        match terminator {
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    self.load(value, "%rax", frame);
+                }
+                self.line("decl crust_call_depth(%rip)");
+                self.line("leave");
+                self.line("ret");
+            }
+            Terminator::Jump(target) => {
+                self.phi_moves(block, *target, func, frame);
+                self.line(format!("jmp {}", block_label(func, target.0)));
+            }
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                // each phi is only read in its own block, so copying for both targets is harmless
+                self.phi_moves(block, *then, func, frame);
+                self.phi_moves(block, *otherwise, func, frame);
+                self.load(cond, "%rax", frame);
+                self.line("testl %eax, %eax");
+                self.line(format!("jne {}", block_label(func, then.0)));
+                self.line(format!("jmp {}", block_label(func, otherwise.0)));
+            }
+            Terminator::MissingReturn => {
+                let message = format!("reached end of function {} with no return", func.name);
+                self.load_string(&message, "%rdi");
+                self.line("call crust_error");
+            }
+        }
+    }
+
+    /// Checks the int in `register` is a valid index, leaving it zero extended to 64 bits.
+    fn bounds_check(&mut self, register: &str, len: u32) {
+        self.line(format!("cmpl ${len}, {register}"));
+        self.error_if("ae", "array index out of bounds");
+        self.line(format!("movl {register}, {register}"));
+    }
+
+    /// Appends the runtime support routines and string constants.
+    fn finish(mut self) -> String {
+        self.text.push_str(RUNTIME);
+
+        self.text.push_str("\n    .section .rodata\n");
+        for (i, value) in self.strings.iter().enumerate() {
+            writeln!(self.text, ".Lstr{i}:\n    .asciz \"{}\"", asm_escape(value)).unwrap();
+        }
+        self.text
+            .push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+
+        format!("    .text\n\n{}", self.text)
+    }
+}
+
+fn block_label(func: &Function, block: usize) -> String {
+    format!(".L{}.bb{block}", func.name)
+}
+
+/// Support routines called by generated code.
+const RUNTIME: &str = r#"crust_error:
+    andq $-16, %rsp
+    pushq %rdi
+    pushq %rdi
+    xorl %edi, %edi
+    call fflush@PLT
+    popq %rdx
+    leaq .Lruntime_error(%rip), %rsi
+    movl $2, %edi
+    xorl %eax, %eax
+    call dprintf@PLT
+    movl $255, %edi
+    call exit@PLT
+
+crust_print_int:
+    pushq %rbp
+    movq %rsp, %rbp
+    movl %edi, %esi
+    leaq .Lint_format(%rip), %rdi
+    xorl %eax, %eax
+    call printf@PLT
+    leave
+    ret
+
+crust_print_str:
+    pushq %rbp
+    movq %rsp, %rbp
+    movq %rdi, %rsi
+    leaq .Lstr_format(%rip), %rdi
+    xorl %eax, %eax
+    call printf@PLT
+    leave
+    ret
+
+crust_read_int:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $16, %rsp
+    leaq -4(%rbp), %rsi
+    leaq .Lint_format(%rip), %rdi
+    xorl %eax, %eax
+    call scanf@PLT
+    cmpl $1, %eax
+    jne 1f
+    movl -4(%rbp), %eax
+    leave
+    ret
+1:
+    leaq .Lread_int_error(%rip), %rdi
+    call crust_error
+
+crust_parse_int:
+    pushq %rbp
+    movq %rsp, %rbp
+    pushq %rbx
+    subq $24, %rsp
+    movq %rdi, %rbx
+    leaq -16(%rbp), %rdx
+    leaq -17(%rbp), %rcx
+    leaq .Larg_format(%rip), %rsi
+    xorl %eax, %eax
+    call sscanf@PLT
+    cmpl $1, %eax
+    jne 1f
+    movl -16(%rbp), %eax
+    movq -8(%rbp), %rbx
+    leave
+    ret
+1:
+    movq %rbx, %rdx
+    leaq .Larg_error(%rip), %rsi
+    movl $2, %edi
+    xorl %eax, %eax
+    call dprintf@PLT
+    movl $255, %edi
+    call exit@PLT
+
+    .section .rodata
+.Lruntime_error:
+    .asciz "runtime error: %s\n"
+.Lint_format:
+    .asciz "%d"
+.Lstr_format:
+    .asciz "%s"
+.Larg_format:
+    .asciz "%d%c"
+.Lread_int_error:
+    .asciz "read_int: input is not a valid int"
+.Larg_error:
+    .asciz "argument '%s' is not a valid int\n"
+
+    .bss
+crust_call_depth:
+    .zero 4
+    .text
+"#;
+
+fn unsupported(span: &Span, feature: Message) -> Diagnostic {
+    Diagnostic::error(
+        "E0400",
+        Message::new("E0400.unsupported-native").arg("feature", feature),
+    )
+    .with_label(span.clone(), Message::new("label.used-here"))
+    .with_note(Message::new("note.use-interpreter"))
+}
+
+/// The condition code that holds exactly when `condition` doesn't.
+fn invert(condition: &str) -> String {
+    match condition.strip_prefix('n') {
+        Some(condition) => condition.to_string(),
+        None => format!("n{condition}"),
+    }
+}
+
+fn asm_escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => write!(escaped, "\\{byte:03o}").unwrap(),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,384 @@
+//! Shell completion scripts and a man page, generated from the CLI's clap definition.
+//!
+//! Everything is derived by walking a built [`Command`], so new subcommands and flags show up
+//! without touching this module.
+
+use std::fmt::Write;
+
+use clap::{builder::PossibleValue, Arg, Command, ValueHint};
+
+/// A command along with the names of the subcommands leading to it, starting with the binary.
+struct Node<'a> {
+    path: Vec<&'a str>,
+    cmd: &'a Command,
+}
+
+impl Node<'_> {
+    /// A name for the command that is safe to use in shell function names.
+    fn ident(&self) -> String {
+        self.path.join("_").replace('-', "_")
+    }
+
+    fn subcommands(&self) -> impl Iterator<Item = &Command> {
+        self.cmd.get_subcommands().filter(|sub| !sub.is_hide_set())
+    }
+
+    fn flags(&self) -> impl Iterator<Item = &Arg> {
+        self.cmd
+            .get_arguments()
+            .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+    }
+}
+
+/// Every command reachable from `cmd`, parents before their subcommands.
+fn walk(cmd: &Command) -> Vec<Node<'_>> {
+    let mut nodes = vec![Node {
+        path: vec![cmd.get_name()],
+        cmd,
+    }];
+    let mut i = 0;
+    while i < nodes.len() {
+        let parent = nodes[i].cmd;
+        let children = parent
+            .get_subcommands()
+            .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+            .map(|sub| {
+                let mut path = nodes[i].path.clone();
+                path.push(sub.get_name());
+                Node { path, cmd: sub }
+            })
+            .collect::<Vec<_>>();
+        nodes.extend(children);
+        i += 1;
+    }
+    nodes
+}
+
+/// Every spelling of a flag, like `-o` and `--output`.
+fn spellings(arg: &Arg) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Some(short) = arg.get_short() {
+        names.push(format!("-{short}"));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("--{long}"));
+    }
+    names
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(PossibleValue::get_name)
+        .map(String::from)
+        .collect()
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_num_args().is_some_and(|range| range.takes_values())
+}
+
+/// Whether the value of `arg` is a path, and so should be completed from the filesystem.
+fn is_path(arg: &Arg) -> bool {
+    matches!(
+        arg.get_value_hint(),
+        ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath
+    )
+}
+
+fn help(arg: &Arg) -> String {
+    arg.get_help()
+        .map(|help| help.to_string())
+        .unwrap_or_default()
+}
+
+fn about(cmd: &Command) -> String {
+    cmd.get_about()
+        .map(|about| about.to_string())
+        .unwrap_or_default()
+}
+
+/// A completion script for bash, to be sourced or installed in `bash-completion/completions`.
+pub fn bash(cmd: &Command) -> String {
+    let nodes = walk(cmd);
+    let name = cmd.get_name();
+    let mut script = String::new();
+
+    writeln!(script, "_{name}() {{").unwrap();
+    writeln!(script, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"").unwrap();
+    writeln!(script, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(script, "    local cmd=\"{name}\" i").unwrap();
+    writeln!(script, "    for ((i = 1; i < COMP_CWORD; i++)); do").unwrap();
+    writeln!(script, "        case \"${{cmd}}:${{COMP_WORDS[i]}}\" in").unwrap();
+    for node in &nodes {
+        for sub in node.subcommands() {
+            writeln!(
+                script,
+                "            {}:{}) cmd=\"{}_{}\" ;;",
+                node.ident(),
+                sub.get_name(),
+                node.ident(),
+                sub.get_name().replace('-', "_")
+            )
+            .unwrap();
+        }
+    }
+    writeln!(script, "        esac").unwrap();
+    writeln!(script, "    done").unwrap();
+    writeln!(script).unwrap();
+
+    writeln!(script, "    case \"${{cmd}}:${{prev}}\" in").unwrap();
+    for node in &nodes {
+        for arg in node.flags().filter(|arg| takes_value(arg)) {
+            let pattern = spellings(arg)
+                .iter()
+                .map(|flag| format!("{}:{flag}", node.ident()))
+                .collect::<Vec<_>>()
+                .join("|");
+            let values = possible_values(arg);
+            let reply = match (values.is_empty(), is_path(arg)) {
+                (false, _) => format!(
+                    "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                    values.join(" ")
+                ),
+                (true, true) => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+                (true, false) => "COMPREPLY=()".to_string(),
+            };
+            writeln!(script, "        {pattern}) {reply}; return ;;").unwrap();
+        }
+    }
+    writeln!(script, "    esac").unwrap();
+    writeln!(script).unwrap();
+
+    writeln!(script, "    local opts").unwrap();
+    writeln!(script, "    case \"$cmd\" in").unwrap();
+    for node in &nodes {
+        let words = node
+            .subcommands()
+            .map(|sub| sub.get_name().to_string())
+            .chain(node.flags().flat_map(spellings))
+            .collect::<Vec<_>>();
+        writeln!(
+            script,
+            "        {}) opts=\"{}\" ;;",
+            node.ident(),
+            words.join(" ")
+        )
+        .unwrap();
+    }
+    writeln!(script, "    esac").unwrap();
+    writeln!(
+        script,
+        "    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\") $(compgen -f -- \"$cur\"))"
+    )
+    .unwrap();
+    writeln!(script, "}}").unwrap();
+    writeln!(script).unwrap();
+    writeln!(script, "complete -F _{name} -o filenames {name}").unwrap();
+    script
+}
+
+/// A completion script for zsh, to be installed as `_<name>` somewhere on `$fpath`.
+pub fn zsh(cmd: &Command) -> String {
+    let escape = |text: &str| {
+        text.replace('\\', "\\\\")
+            .replace('\'', "'\\''")
+            .replace('[', "\\[")
+            .replace(']', "\\]")
+            .replace(':', "\\:")
+    };
+
+    let mut script = format!("#compdef {}\n", cmd.get_name());
+    for node in walk(cmd) {
+        writeln!(script).unwrap();
+        writeln!(script, "_{}() {{", node.ident()).unwrap();
+        writeln!(script, "    local line state").unwrap();
+        writeln!(script, "    _arguments -C \\").unwrap();
+
+        for arg in node.flags() {
+            let help = escape(&help(arg));
+            let value = match (takes_value(arg), possible_values(arg)) {
+                (false, _) => String::new(),
+                (true, values) if values.is_empty() && is_path(arg) => {
+                    format!(":{}:_files", arg.get_id())
+                }
+                (true, values) if values.is_empty() => format!(":{}: ", arg.get_id()),
+                (true, values) => format!(":{}:({})", arg.get_id(), values.join(" ")),
+            };
+            for flag in spellings(arg) {
+                writeln!(script, "        '{flag}[{help}]{value}' \\").unwrap();
+            }
+        }
+
+        if node.subcommands().next().is_some() {
+            let subcommands = node
+                .subcommands()
+                .map(|sub| format!("{}\\:\"{}\"", sub.get_name(), escape(&about(sub))))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(script, "        '1: :(({subcommands}))' \\").unwrap();
+            writeln!(script, "        '*:: :->args'").unwrap();
+            writeln!(script, "    case $state in").unwrap();
+            writeln!(script, "        args)").unwrap();
+            writeln!(script, "            case $line[1] in").unwrap();
+            for sub in node.subcommands().filter(|sub| sub.get_name() != "help") {
+                writeln!(
+                    script,
+                    "                {}) _{}_{} ;;",
+                    sub.get_name(),
+                    node.ident(),
+                    sub.get_name().replace('-', "_")
+                )
+                .unwrap();
+            }
+            writeln!(script, "            esac ;;").unwrap();
+            writeln!(script, "    esac").unwrap();
+        } else {
+            writeln!(script, "        '*:file:_files'").unwrap();
+        }
+        writeln!(script, "}}").unwrap();
+    }
+
+    writeln!(script).unwrap();
+    writeln!(script, "_{} \"$@\"", cmd.get_name()).unwrap();
+    script
+}
+
+/// A completion script for fish, to be installed in `fish/completions`.
+pub fn fish(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let quote = |text: &str| format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut script = String::new();
+
+    for node in walk(cmd) {
+        // the words that lead to this command, and the subcommands that would lead past it
+        let parents = &node.path[1..];
+        let children = node
+            .subcommands()
+            .map(Command::get_name)
+            .collect::<Vec<_>>();
+        let mut conditions = match parents {
+            [] => vec!["__fish_use_subcommand".to_string()],
+            _ => parents
+                .iter()
+                .map(|parent| format!("__fish_seen_subcommand_from {parent}"))
+                .collect(),
+        };
+        if !parents.is_empty() && !children.is_empty() {
+            conditions.push(format!(
+                "not __fish_seen_subcommand_from {}",
+                children.join(" ")
+            ));
+        }
+        let condition = quote(&conditions.join("; and "));
+
+        for sub in node.subcommands() {
+            writeln!(
+                script,
+                "complete -c {name} -n {condition} -f -a {} -d {}",
+                sub.get_name(),
+                quote(&about(sub))
+            )
+            .unwrap();
+        }
+
+        for arg in node.flags() {
+            let mut line = format!("complete -c {name} -n {condition}");
+            if let Some(short) = arg.get_short() {
+                write!(line, " -s {short}").unwrap();
+            }
+            if let Some(long) = arg.get_long() {
+                write!(line, " -l {long}").unwrap();
+            }
+            let values = possible_values(arg);
+            if takes_value(arg) {
+                match (values.is_empty(), is_path(arg)) {
+                    (false, _) => write!(line, " -x -a {}", quote(&values.join(" "))).unwrap(),
+                    (true, true) => line.push_str(" -r -F"),
+                    (true, false) => line.push_str(" -x"),
+                }
+            }
+            write!(line, " -d {}", quote(&help(arg))).unwrap();
+            writeln!(script, "{line}").unwrap();
+        }
+    }
+    script
+}
+
+/// A man page in roff format, documenting every subcommand in its own section.
+pub fn manpage(cmd: &Command) -> String {
+    let escape = |text: &str| text.replace('\\', "\\e").replace('-', "\\-");
+    let name = cmd.get_name();
+    let mut page = String::new();
+
+    writeln!(
+        page,
+        ".TH {} 1 \"\" \"{name} {}\"",
+        name.to_uppercase(),
+        cmd.get_version().unwrap_or_default()
+    )
+    .unwrap();
+    writeln!(page, ".SH NAME").unwrap();
+    writeln!(page, "{name} \\- {}", escape(&about(cmd))).unwrap();
+
+    for node in walk(cmd) {
+        let title = node.path.join(" ");
+        match node.path.len() {
+            1 => writeln!(page, ".SH SYNOPSIS").unwrap(),
+            _ => {
+                writeln!(page, ".SH \"{}\"", title.to_uppercase()).unwrap();
+                if !about(node.cmd).is_empty() {
+                    writeln!(page, "{}", escape(&about(node.cmd))).unwrap();
+                    writeln!(page, ".PP").unwrap();
+                }
+            }
+        }
+
+        let usage = node
+            .cmd
+            .get_arguments()
+            .filter(|arg| arg.is_positional() && !arg.is_hide_set())
+            .map(|arg| {
+                let value = arg.get_id().as_str().to_uppercase();
+                match arg.is_required_set() {
+                    true => format!("<{value}>"),
+                    false => format!("[{value}]..."),
+                }
+            })
+            .collect::<Vec<_>>();
+        let subcommand = match node.subcommands().next() {
+            Some(_) => " <COMMAND>",
+            None => "",
+        };
+        writeln!(
+            page,
+            "\\fB{title}\\fR [OPTIONS]{subcommand} {}",
+            escape(&usage.join(" "))
+        )
+        .unwrap();
+
+        for arg in node.cmd.get_arguments().filter(|arg| !arg.is_hide_set()) {
+            let mut term = match arg.is_positional() {
+                true => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+                false => spellings(arg)
+                    .iter()
+                    .map(|flag| format!("\\fB{}\\fR", escape(flag)))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+            if !arg.is_positional() && takes_value(arg) {
+                let values = possible_values(arg);
+                match values.is_empty() {
+                    true => write!(term, " <{}>", arg.get_id().as_str().to_uppercase()).unwrap(),
+                    false => write!(term, " <{}>", values.join("|")).unwrap(),
+                }
+            }
+            writeln!(page, ".TP").unwrap();
+            writeln!(page, "{term}").unwrap();
+            writeln!(page, "{}", escape(&help(arg))).unwrap();
+        }
+    }
+
+    page
+}
@@ -0,0 +1,197 @@
+//! Evaluation of `@comptime` functions by `build`.
+//!
+//! A call to a function annotated `@comptime` whose arguments are constant is run by the
+//! interpreter while building, and replaced by the int or string it returns, so the IR and
+//! everything generated from it only hold the constant. Arguments are constant when they're made
+//! of literals, operators and other such calls, which are evaluated first. Calls with any other
+//! arguments are left to run with the program, as are the functions themselves. So are calls that
+//! return something else with 64-bit ints or `--overflow trap` than they do by default, since
+//! the program may be run with either.
+//!
+//! Builtins that read or write the console fail when called at build time, so building a program
+//! never has side effects.
+
+use crate::{
+    ast::{Definition, Expr, Spanned, Statement},
+    diagnostics::Diagnostic,
+    messages::Message,
+    semantics::{IntMode, Overflow, Width},
+    visit::{self, Folder},
+    Ast, Builtins, RunOptions, Value,
+};
+
+/// Replaces every call to a `@comptime` function with constant arguments by its result, failing
+/// with the errors of the calls that couldn't be evaluated.
+pub fn expand(ast: &mut Ast) -> Result<(), Vec<Diagnostic>> {
+    let funcs = ast
+        .defs
+        .iter()
+        .filter_map(|def| match def {
+            Definition::Func(func) => Some(Definition::Func(func.clone())),
+            Definition::Global(global) => Some(Definition::Global(global.clone())),
+            Definition::Struct { .. }
+            | Definition::Import { .. }
+            | Definition::Macro(_)
+            | Definition::Prototype(_) => None,
+        })
+        .collect();
+    let mut expander = Expander {
+        program: Ast { defs: funcs },
+        errors: Vec::new(),
+    };
+
+    let defs = std::mem::take(&mut ast.defs);
+    ast.defs = defs
+        .into_iter()
+        .map(|def| expander.fold_definition(def))
+        .collect();
+    match expander.errors.is_empty() {
+        true => Ok(()),
+        false => Err(expander.errors),
+    }
+}
+
+/// The builtins available at build time: the standard ones, except for those using the console,
+/// the arguments and environment the program is run with, or the state its threads share.
+pub fn builtins() -> Builtins {
+    let mut builtins = Builtins::default();
+    for name in [
+        "print",
+        "println",
+        "read_int",
+        "argc",
+        "arg",
+        "getenv",
+        "atomic_load",
+        "atomic_store",
+        "atomic_add",
+        "lock",
+        "unlock",
+    ] {
+        builtins.register(name, move |_| {
+            Err(Message::new("E0202.comptime-builtin").arg("name", name))
+        });
+    }
+    builtins
+}
+
+struct Expander {
+    /// The functions of the program as they were written, which the calls are evaluated with
+    program: Ast,
+    errors: Vec<Diagnostic>,
+}
+
+impl Expander {
+    fn is_comptime(&self, name: &str) -> bool {
+        self.program
+            .defs
+            .iter()
+            .any(|def| matches!(def, Definition::Func(func) if func.comptime && func.name == name))
+    }
+
+    /// Runs `call` with `ints`, returning the value it returns.
+    fn run(&self, call: &Spanned<Expr>, ints: IntMode) -> Result<Option<Value>, Diagnostic> {
+        let options = RunOptions {
+            builtins: builtins(),
+            ints,
+            ..RunOptions::default()
+        };
+        let statement = (Statement::Return(Box::new(call.clone())), call.1.clone());
+        self.program
+            .run_statement(options, &statement, &mut Vec::new())
+    }
+
+    /// Runs `call`, a call to `name`, returning the constant it evaluates to, or the call itself
+    /// if what it evaluates to depends on the width of ints or on what overflowing does.
+    fn eval(&mut self, name: &str, call: Spanned<Expr>) -> Result<Expr, Diagnostic> {
+        let span = call.1.clone();
+        let value = self.run(&call, IntMode::default()).map_err(|diagnostic| {
+            diagnostic
+                .with_label(span.clone(), Message::new("label.comptime"))
+                .with_note(Message::new("note.comptime"))
+        })?;
+        let modes = [Width::W32, Width::W64].into_iter().flat_map(|width| {
+            [Overflow::Wrap, Overflow::Trap].map(|overflow| IntMode { width, overflow })
+        });
+        for ints in modes.filter(|ints| *ints != IntMode::default()) {
+            if self.run(&call, ints).ok() != Some(value.clone()) {
+                return Ok(call.0);
+            }
+        }
+
+        match value {
+            Some(Value::Int(value)) if value < 0 => Ok(Expr::Neg(Box::new((
+                Expr::Int(value.wrapping_neg() as u32),
+                span,
+            )))),
+            Some(Value::Int(value)) => Ok(Expr::Int(value as u32)),
+            Some(Value::Str(value)) => Ok(Expr::Str(value)),
+            Some(Value::Float(value)) if value.is_sign_negative() => {
+                Ok(Expr::Neg(Box::new((Expr::Float(-value), span))))
+            }
+            Some(Value::Float(value)) => Ok(Expr::Float(value)),
+            Some(Value::Pointer(_)) => Err(Diagnostic::error(
+                "E0107",
+                Message::new("E0107.pointer").arg("name", name),
+            )
+            .with_label(span, Message::new("label.comptime"))),
+            Some(Value::Array(_) | Value::Thread(_)) | None => Err(Diagnostic::error(
+                "E0107",
+                Message::new("E0107").arg("name", name),
+            )
+            .with_label(span, Message::new("label.comptime"))),
+        }
+    }
+}
+
+impl Folder for Expander {
+    fn fold_expr(&mut self, expr: Spanned<Expr>) -> Spanned<Expr> {
+        let (expr, span) = visit::fold_expr(self, expr);
+        let Expr::Call { name, params } = &expr else {
+            return (expr, span);
+        };
+        if !self.is_comptime(name) || !params.iter().all(|param| is_constant(&param.0)) {
+            return (expr, span);
+        }
+
+        match self.eval(&name.clone(), (expr.clone(), span.clone())) {
+            Ok(constant) => (constant, span),
+            Err(error) => {
+                self.errors.push(error);
+                (expr, span)
+            }
+        }
+    }
+}
+
+/// Whether `expr` is made only of literals and operators, once the calls in it have been folded.
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Int(_) | Expr::Float(_) | Expr::Str(_) | Expr::SizeOf(_) => true,
+        Expr::Err | Expr::Var(_) | Expr::Index(..) | Expr::Call { .. } => false,
+        Expr::Spawn { .. } | Expr::Join(_) => false,
+        Expr::PreInc(_) | Expr::PreDec(_) | Expr::AddrOf(_) | Expr::Deref(_) => false,
+        Expr::Neg(inner) | Expr::Not(inner) => is_constant(&inner.0),
+        Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, rhs)
+        | Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Rem(lhs, rhs)
+        | Expr::BitAnd(lhs, rhs)
+        | Expr::BitOr(lhs, rhs)
+        | Expr::BitXor(lhs, rhs)
+        | Expr::Shl(lhs, rhs)
+        | Expr::Shr(lhs, rhs)
+        | Expr::Lt(lhs, rhs)
+        | Expr::Le(lhs, rhs)
+        | Expr::Gt(lhs, rhs)
+        | Expr::Ge(lhs, rhs)
+        | Expr::Eq(lhs, rhs)
+        | Expr::Ne(lhs, rhs)
+        | Expr::And(lhs, rhs)
+        | Expr::Or(lhs, rhs) => is_constant(&lhs.0) && is_constant(&rhs.0),
+        Expr::Cond(cond, then, otherwise) => {
+            is_constant(&cond.0) && is_constant(&then.0) && is_constant(&otherwise.0)
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Settings shared between runs through a `crust.toml` file and `CRUST_*` environment variables.
+//!
+//! A setting is resolved from, in order of precedence: its command line flag, its environment
+//! variable, the nearest `crust.toml` (searching up from the current directory, or the file named
+//! by `CRUST_CONFIG`), and finally its default. Only the subset of TOML needed for flat settings
+//! is supported: `[section]` headers, and `key = value` pairs whose values are strings, integers
+//! or booleans.
+//!
+//! ```toml
+//! error-format = "json"
+//!
+//! [run]
+//! max-call-depth = 5000
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{messages::Message, Diagnostic};
+
+pub const FILE_NAME: &str = "crust.toml";
+
+/// Names a config file to use instead of searching for [`FILE_NAME`].
+pub const CONFIG_VAR: &str = "CRUST_CONFIG";
+
+/// A setting that can be configured, keyed by its section and name joined with a `.`.
+#[derive(Debug)]
+pub struct Setting {
+    pub key: &'static str,
+    pub description: &'static str,
+}
+
+impl Setting {
+    /// The environment variable overriding this setting, e.g. `CRUST_RUN_MAX_CALL_DEPTH`.
+    pub fn env_var(&self) -> String {
+        format!("CRUST_{}", self.key.replace(['.', '-'], "_").to_uppercase())
+    }
+}
+
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "error-format",
+        description: "how diagnostics are written to stderr (human or json)",
+    },
+    Setting {
+        key: "locale",
+        description: "language diagnostics are written in (en or es)",
+    },
+    Setting {
+        key: "build.emit",
+        description: "output format of build when --emit is omitted",
+    },
+    Setting {
+        key: "run.max-call-depth",
+        description: "maximum depth of nested function calls",
+    },
+    Setting {
+        key: "telemetry.file",
+        description: "file to append usage metrics to, one JSON object per line (off when unset)",
+    },
+    Setting {
+        key: "log",
+        description: "how pass timings and program size are written to stderr (human or json, off when unset)",
+    },
+];
+
+/// Where a resolved setting came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Flag,
+    Env(String),
+    File(PathBuf),
+    Default,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag => write!(f, "command line flag"),
+            Self::Env(var) => write!(f, "environment variable {var}"),
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// The settings from the config file and environment, before flags are applied.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The config file that was read, if any
+    pub path: Option<PathBuf>,
+    /// Values from the config file, by key
+    pub values: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Finds and reads the config file for the current directory.
+    pub fn load() -> Result<Self, Diagnostic> {
+        let path = match env::var_os(CONFIG_VAR) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => env::current_dir().ok().and_then(|dir| find(&dir)),
+        };
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+
+        let source = fs::read_to_string(&path).map_err(|e| {
+            Diagnostic::error(
+                "E0500",
+                Message::new("E0500.read")
+                    .arg("path", path.display().to_string())
+                    .arg("error", e.to_string()),
+            )
+        })?;
+        let values = parse(&source).map_err(|(line, message)| {
+            Diagnostic::error(
+                "E0500",
+                Message::new("E0500.invalid")
+                    .arg("path", path.display().to_string())
+                    .arg("line", line.to_string())
+                    .arg("reason", message),
+            )
+        })?;
+
+        Ok(Self {
+            path: Some(path),
+            values,
+        })
+    }
+
+    /// The value of `key` from the environment or config file, and where it came from.
+    pub fn get(&self, key: &str) -> Option<(String, Source)> {
+        let setting = SETTINGS.iter().find(|setting| setting.key == key)?;
+        let var = setting.env_var();
+        if let Ok(value) = env::var(&var) {
+            return Some((value, Source::Env(var)));
+        }
+
+        let value = self.values.get(key)?;
+        Some((
+            value.clone(),
+            Source::File(self.path.clone().unwrap_or_default()),
+        ))
+    }
+}
+
+/// Finds the nearest config file in `dir` or its ancestors.
+fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Parses a config file into values keyed by `section.key`, or the line and reason it's invalid.
+fn parse(source: &str) -> Result<BTreeMap<String, String>, (usize, String)> {
+    let mut values = BTreeMap::new();
+    let mut section = String::new();
+    for (i, line) in source.lines().enumerate() {
+        let fail = |message: String| (i + 1, message);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let Some(name) = header.strip_suffix(']') else {
+                return Err(fail("unclosed section header".into()));
+            };
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(fail(format!("expected `key = value`, found `{line}`")));
+        };
+        let key = match section.is_empty() {
+            true => key.trim().to_string(),
+            false => format!("{section}.{}", key.trim()),
+        };
+        if !SETTINGS.iter().any(|setting| setting.key == key) {
+            return Err(fail(format!("unknown setting `{key}`")));
+        }
+
+        let value = value.trim();
+        let value = match value.strip_prefix('"') {
+            Some(string) => match string.strip_suffix('"') {
+                Some(string) => string.to_string(),
+                None => return Err(fail(format!("unterminated string {value}"))),
+            },
+            None if value.parse::<i64>().is_ok() || value == "true" || value == "false" => {
+                value.to_string()
+            }
+            None => return Err(fail(format!("invalid value `{value}`"))),
+        };
+        if values.insert(key.clone(), value).is_some() {
+            return Err(fail(format!("`{key}` is set more than once")));
+        }
+    }
+    Ok(values)
+}
+
+/// Removes a trailing `#` comment, ignoring any `#` inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
@@ -0,0 +1,488 @@
+//! The source-level debugger behind `run --debug`, which pauses the interpreter before statements
+//! and reads commands until it's told to carry on.
+//!
+//! The program is paused before its first statement, and again wherever a breakpoint, `step` or
+//! `next` says to. Breakpoints are set on a function, which pauses before the first statement of
+//! each call to it, or on a line of the main file (`file:line` for any other file).
+//!
+//! `edit` opens a function in `$VISUAL` or `$EDITOR`, and the function it's saved as replaces the
+//! old one from its next call on, while the calls in progress carry on as they were. The edited
+//! source is added to the program's [`SourceMap`], which is shared with whoever reports the
+//! program's errors so that they can point into it.
+
+use std::{
+    fs,
+    io::{BufRead, Write},
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+use chumsky::{primitive::end, Parser, Stream};
+
+use crate::{
+    ast::{Definition, Func, Hook, Spanned, State, Statement},
+    delimiters,
+    diagnostics::Diagnostic,
+    literal,
+    messages::Message,
+    pipeline,
+    sources::SourceMap,
+    token::Span,
+    Token, Value,
+};
+
+const HELP: &str = "\
+break <function|line|file:line>  pause there (b)
+delete <number>                  remove a breakpoint (d)
+step                             run to the next statement, entering calls (s)
+next                             run to the next statement in this function or its callers (n)
+continue                         run to the next breakpoint (c)
+locals                           print the variables of this function (l)
+print <name>                     print a variable (p)
+backtrace                        print the calls in progress (bt)
+edit <function>                  change a function in $EDITOR, from its next call on (e)
+quit                             stop the program (q)";
+
+/// A line of a source file, numbered from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line {
+    file: usize,
+    line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Breakpoint {
+    Func(String),
+    Line(Line),
+}
+
+/// How far to run before pausing again.
+#[derive(Debug, Clone, Copy)]
+enum Resume {
+    /// Pause before the next statement
+    Step,
+    /// Pause before the next statement at most this many calls deep
+    Next(usize),
+    /// Pause at the next breakpoint
+    Continue,
+}
+
+pub struct Debugger<R, W> {
+    sources: Arc<Mutex<SourceMap>>,
+    input: R,
+    output: W,
+    /// Whether to prompt for commands, as when reading them from a terminal
+    prompt: bool,
+    breakpoints: Vec<Breakpoint>,
+    resume: Resume,
+    /// The line and call depth of the last statement run, to pause only on entering a line or
+    /// a function
+    last: Option<(Line, usize)>,
+    /// Functions edited since the program last asked for them
+    edited: Vec<Func>,
+    /// The number of edits made, which names the file each is kept as
+    edits: usize,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    /// A debugger for a program compiled from `sources`, reading commands from `input` and
+    /// writing to `output`.
+    pub fn new(sources: Arc<Mutex<SourceMap>>, input: R, output: W, prompt: bool) -> Self {
+        Self {
+            sources,
+            input,
+            output,
+            prompt,
+            breakpoints: Vec::new(),
+            resume: Resume::Step,
+            last: None,
+            edited: Vec::new(),
+            edits: 0,
+        }
+    }
+
+    /// The file and line `span` starts on.
+    fn line(&self, span: &Span) -> Line {
+        let sources = self.sources.lock().unwrap();
+        let Some((file, span)) = sources.locate(span) else {
+            return Line { file: 0, line: 1 };
+        };
+        let index = sources
+            .files()
+            .iter()
+            .position(|other| other.base == file.base)
+            .unwrap_or(0);
+        let line = file
+            .source
+            .chars()
+            .take(span.start)
+            .filter(|&c| c == '\n')
+            .count();
+        Line {
+            file: index,
+            line: line + 1,
+        }
+    }
+
+    /// `file:line`, naming the file it's in.
+    fn location(&self, line: &Line) -> String {
+        let sources = self.sources.lock().unwrap();
+        let name = sources
+            .files()
+            .get(line.file)
+            .map_or("<unknown>", |file| file.name.as_str());
+        format!("{name}:{}", line.line)
+    }
+
+    /// Whether the statement on `line`, `depth` calls deep in `func`, is where to pause.
+    fn should_pause(&self, line: &Line, depth: usize, func: &str) -> bool {
+        let entered = self.last.as_ref().is_none_or(|(_, last)| depth > *last);
+        let new_line = self.last.as_ref() != Some(&(line.clone(), depth));
+        let breakpoint = self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            Breakpoint::Func(name) => entered && name == func,
+            Breakpoint::Line(at) => new_line && at == line,
+        });
+        breakpoint
+            || match self.resume {
+                Resume::Step => true,
+                Resume::Next(max) => depth <= max,
+                Resume::Continue => false,
+            }
+    }
+
+    /// Parses the target of `break`: a function, a line of the main file or `file:line`.
+    fn breakpoint(&self, target: &str) -> Result<Breakpoint, String> {
+        let (name, line) = target.rsplit_once(':').unwrap_or(("", target));
+        let Ok(line) = line.parse::<usize>() else {
+            return match target.is_empty() {
+                true => Err(String::from("Expected a function or line to break at")),
+                false => Ok(Breakpoint::Func(target.to_string())),
+            };
+        };
+        let file = match name {
+            "" => 0,
+            name => self
+                .sources
+                .lock()
+                .unwrap()
+                .files()
+                .iter()
+                .position(|file| file.name == name || file.name.ends_with(&format!("/{name}")))
+                .ok_or_else(|| format!("No file named {name}"))?,
+        };
+        Ok(Breakpoint::Line(Line { file, line }))
+    }
+
+    /// Prints where the program is paused, with the line of source about to run.
+    fn show(&mut self, line: &Line, func: &str) -> std::io::Result<()> {
+        let text = self
+            .sources
+            .lock()
+            .unwrap()
+            .files()
+            .get(line.file)
+            .and_then(|file| file.source.lines().nth(line.line - 1))
+            .unwrap_or("")
+            .trim()
+            .to_string();
+        writeln!(self.output, "{} in {func}", self.location(line))?;
+        writeln!(self.output, "{:>5} | {text}", line.line)
+    }
+
+    /// Reads and runs commands until one resumes the program, returning `false` to stop it.
+    fn pause(&mut self, state: &State<'_>) -> std::io::Result<bool> {
+        loop {
+            if self.prompt {
+                write!(self.output, "(debug) ")?;
+                self.output.flush()?;
+            }
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                // without anyone to ask, the program runs to the end
+                self.breakpoints.clear();
+                self.resume = Resume::Continue;
+                return Ok(true);
+            }
+
+            let command = command.trim();
+            match command.split_once(' ').unwrap_or((command, "")) {
+                ("", _) => {}
+                ("step" | "s", _) => {
+                    self.resume = Resume::Step;
+                    return Ok(true);
+                }
+                ("next" | "n", _) => {
+                    self.resume = Resume::Next(state.calls.len());
+                    return Ok(true);
+                }
+                ("continue" | "c", _) => {
+                    self.resume = Resume::Continue;
+                    return Ok(true);
+                }
+                ("quit" | "q", _) => return Ok(false),
+                ("break" | "b", target) => match self.breakpoint(target.trim()) {
+                    Ok(breakpoint) => {
+                        let at = match &breakpoint {
+                            Breakpoint::Func(name) => name.clone(),
+                            Breakpoint::Line(line) => self.location(line),
+                        };
+                        self.breakpoints.push(breakpoint);
+                        writeln!(self.output, "Breakpoint {} at {at}", self.breakpoints.len())?;
+                    }
+                    Err(e) => writeln!(self.output, "{e}")?,
+                },
+                ("delete" | "d", number) => match number.trim().parse::<usize>() {
+                    Ok(number) if (1..=self.breakpoints.len()).contains(&number) => {
+                        self.breakpoints.remove(number - 1);
+                    }
+                    _ => writeln!(self.output, "No breakpoint {number}")?,
+                },
+                ("locals" | "l", _) => {
+                    // a variable declared again hides the one before it
+                    let mut shown = Vec::new();
+                    for (i, (name, value)) in state.vars.iter().enumerate() {
+                        if !state.vars[i + 1..].iter().any(|(other, _)| other == name) {
+                            shown.push(format!("{name} = {}", show(value)));
+                        }
+                    }
+                    match shown.is_empty() {
+                        true => writeln!(self.output, "No variables")?,
+                        false => writeln!(self.output, "{}", shown.join("\n"))?,
+                    }
+                }
+                ("print" | "p", name) => {
+                    let name = name.trim();
+                    match state.vars.iter().rev().find(|(var, _)| *var == name) {
+                        Some((_, value)) => writeln!(self.output, "{name} = {}", show(value))?,
+                        None => writeln!(self.output, "No variable {name} in {}", state.func)?,
+                    }
+                }
+                ("backtrace" | "bt", _) => {
+                    let frames = backtrace(state.calls);
+                    for (i, (name, call)) in frames.iter().enumerate() {
+                        match call {
+                            Some(span) => {
+                                let line = self.line(span);
+                                writeln!(
+                                    self.output,
+                                    "{i:>4}: {name}, called at {}",
+                                    self.location(&line)
+                                )?
+                            }
+                            None => writeln!(self.output, "{i:>4}: {name}")?,
+                        }
+                    }
+                }
+                ("edit" | "e", name) => {
+                    let name = name.trim();
+                    match self.edit(name, state) {
+                        Ok(()) if backtrace(state.calls).iter().any(|(call, _)| *call == name) => {
+                            writeln!(
+                                self.output,
+                                "Edited {name}, which runs from its next call while the calls \
+                                 in progress finish as they were"
+                            )?
+                        }
+                        Ok(()) => writeln!(self.output, "Edited {name}")?,
+                        Err(e) => writeln!(self.output, "{e}")?,
+                    }
+                }
+                ("help" | "h", _) => writeln!(self.output, "{HELP}")?,
+                (command, _) => writeln!(self.output, "Unknown command {command}, try help")?,
+            }
+        }
+    }
+
+    /// Opens the function `name` in the editor, keeping the function it's saved as to replace
+    /// the old one.
+    fn edit(&mut self, name: &str, state: &State<'_>) -> Result<(), String> {
+        let Some(func) = state.funcs.iter().find(|func| func.name == name) else {
+            return Err(match name.is_empty() {
+                true => String::from("Expected a function to edit"),
+                false => format!("No function {name}"),
+            });
+        };
+        let source = func_source(&self.sources.lock().unwrap(), &func.span)
+            .ok_or_else(|| format!("The source of {name} isn't available"))?;
+
+        let path = std::env::temp_dir().join(format!("crust-edit-{}-{name}.c", std::process::id()));
+        fs::write(&path, &source)
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        let edited = open_editor(&path).and_then(|()| {
+            fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {e}", path.display()))
+        });
+        let _ = fs::remove_file(&path);
+        let edited = edited?;
+
+        self.edits += 1;
+        let file = format!("<edit {}: {name}>", self.edits);
+        let base = self
+            .sources
+            .lock()
+            .unwrap()
+            .add(file.as_str(), edited.as_str());
+        let new = parse_def(&edited, base).map_err(|errors| {
+            let errors = errors
+                .iter()
+                .map(|error| {
+                    let label = error.labels.first().map(|label| {
+                        let line = self.line(&label.span);
+                        format!("{}: {}", self.location(&line), label.message)
+                    });
+                    label.unwrap_or_else(|| error.message.to_string())
+                })
+                .collect::<Vec<_>>();
+            format!("{name} wasn't changed:\n{}", errors.join("\n"))
+        })?;
+
+        let Definition::Func(new) = new else {
+            return Err(format!(
+                "{name} wasn't changed, since the edit isn't a function"
+            ));
+        };
+        // callers that aren't running yet were checked against the parameters it has now
+        if new.name != name {
+            return Err(format!("{name} wasn't changed, since the edit renamed it"));
+        }
+        if new.params.len() != func.params.len() {
+            return Err(format!(
+                "{name} wasn't changed, since its callers pass it {} arguments",
+                func.params.len()
+            ));
+        }
+        self.edited.push(new);
+        Ok(())
+    }
+}
+
+impl<R: BufRead + Send, W: Write + Send> Hook for Debugger<R, W> {
+    fn before_statement(
+        &mut self,
+        (_, span): &Spanned<Statement>,
+        state: State<'_>,
+    ) -> Result<(), Diagnostic> {
+        let line = self.line(span);
+        let depth = state.calls.len();
+        let pause = self.should_pause(&line, depth, state.func);
+        self.last = Some((line.clone(), depth));
+        if !pause {
+            return Ok(());
+        }
+
+        let resume = self
+            .show(&line, state.func)
+            .and_then(|()| self.pause(&state));
+        match resume {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Diagnostic::error(
+                "E0200",
+                Message::new("E0200.debugger-quit"),
+            )),
+            Err(e) => Err(Diagnostic::error(
+                "E0200",
+                Message::new("E0200.debugger-io").arg("error", e.to_string()),
+            )),
+        }
+    }
+
+    fn replacements(&mut self) -> Vec<Func> {
+        std::mem::take(&mut self.edited)
+    }
+}
+
+/// Runs `$VISUAL` or `$EDITOR` on the file at `path`, waiting for it to exit.
+fn open_editor(path: &std::path::Path) -> Result<(), String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // the editor may be given with arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("$EDITOR is empty")?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Failed to start {program}: {e}"))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("{program} failed, so nothing was changed")),
+    }
+}
+
+/// The source of the function whose name is at `span`, from its return type, or the annotation
+/// before it, to its closing brace.
+fn func_source(sources: &SourceMap, span: &Span) -> Option<String> {
+    let (file, span) = sources.locate(span)?;
+    let tokens = pipeline::lex(&file.source).ok()?;
+    let name = tokens.iter().position(|(_, token)| *token == span)?;
+
+    let mut first = name.checked_sub(1)?;
+    while tokens[first].0 == Token::Op("*") {
+        first = first.checked_sub(1)?;
+    }
+    if first > 0 && tokens[first - 1].0 == Token::Comptime {
+        first -= 1;
+    }
+    let mut depth = 0;
+    let last = name
+        + tokens[name..].iter().position(|(token, _)| {
+            match token {
+                Token::Ctrl('{') => depth += 1,
+                Token::Ctrl('}') => depth -= 1,
+                _ => return false,
+            }
+            depth == 0
+        })?;
+
+    let start = tokens[first].1.start;
+    let len = tokens[last].1.end - start;
+    Some(file.source.chars().skip(start).take(len).collect())
+}
+
+/// Parses `source`, whose spans start at `base`, as a single definition.
+fn parse_def(source: &str, base: usize) -> Result<Definition, Vec<Diagnostic>> {
+    let offset = |errors: Vec<Diagnostic>| {
+        errors
+            .into_iter()
+            .map(|error| error.offset(base))
+            .collect::<Vec<_>>()
+    };
+    let tokens = pipeline::lex(source).map_err(offset)?;
+    let errors = delimiters::check(source, &tokens);
+    if !errors.is_empty() {
+        return Err(offset(errors));
+    }
+
+    let len = source.chars().count();
+    let tokens = tokens
+        .into_iter()
+        .map(|(token, span)| (token, span.start + base..span.end + base));
+    let stream = Stream::from_iter(base + len..base + len + 1, tokens);
+    Definition::parser()
+        .then_ignore(end())
+        .parse(stream)
+        .map_err(|errors| errors.into_iter().map(Diagnostic::from).collect())
+}
+
+/// The calls in progress innermost first, each with the span it was called from, ending with
+/// `main`.
+fn backtrace<'a>(calls: &[Spanned<&'a str>]) -> Vec<(&'a str, Option<Span>)> {
+    calls
+        .iter()
+        .rev()
+        .map(|(name, span)| (*name, Some(span.clone())))
+        .chain([("main", None)])
+        .collect()
+}
+
+/// A value as it's written in source, so that strings are quoted.
+pub(crate) fn show(value: &Value) -> String {
+    match value {
+        Value::Str(value) => literal::escape(value),
+        Value::Array(values) => {
+            let values = values.iter().map(show).collect::<Vec<_>>();
+            format!("[{}]", values.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
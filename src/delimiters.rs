@@ -0,0 +1,138 @@
+//! Matching brackets before parsing, so that an unbalanced one is reported once with where it
+//! opened and where it should have closed, instead of by every rule of the parser it breaks.
+
+use crate::{ast::Spanned, messages::Message, token::Span, Diagnostic, Symbol, Token};
+
+/// The opening and closing characters of each pair of delimiters.
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// How deep delimiters can nest, beyond which parsing them would take more time and stack than
+/// any real program needs.
+pub const MAX_DEPTH: usize = 256;
+
+/// Reports every delimiter in `tokens` lexed from `source` that's never closed, or closes
+/// nothing.
+///
+/// Parentheses and brackets only ever hold expressions, so a `;` or brace inside one means it
+/// should have been closed before it. The exception is the header of a C `for` loop, whose `;`s
+/// are left for the parser to report the loop as unsupported. An unclosed brace is expected to close before the next
+/// line that's indented no further than the line it opened on.
+pub fn check(source: &str, tokens: &[Spanned<Token>]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut open = Vec::<(char, Span)>::new();
+    // the `(` of every `for` loop's header
+    let mut headers = Vec::<Span>::new();
+    let mut too_deep = false;
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        let Token::Ctrl(c) = *token else {
+            continue;
+        };
+
+        if c == '(' && i > 0 && tokens[i - 1].0 == Token::Ident(Symbol::intern("for")) {
+            headers.push(span.clone());
+        }
+        if matches!(c, ';' | '{' | '}') {
+            let closes = |(opener, opened): &mut (char, Span)| {
+                *opener != '{' && !(c == ';' && headers.contains(opened))
+            };
+            while let Some((opener, opened)) = open.pop_if(closes) {
+                diagnostics.push(unclosed(opener, opened, Some(span.clone())));
+            }
+        }
+
+        if PAIRS.iter().any(|(opener, _)| *opener == c) {
+            open.push((c, span.clone()));
+            // reported only where the limit is first passed
+            if open.len() == MAX_DEPTH + 1 && !too_deep {
+                too_deep = true;
+                diagnostics.push(
+                    Diagnostic::error(
+                        "E0003",
+                        Message::new("E0003.too-deep").arg("max", MAX_DEPTH.to_string()),
+                    )
+                    .with_label(span.clone(), Message::new("label.nested-too-deep")),
+                );
+            }
+        } else if let Some((opener, _)) = PAIRS.iter().find(|(_, closer)| *closer == c) {
+            match open.iter().rposition(|(open, _)| open == opener) {
+                Some(matching) => {
+                    for (opener, opened) in open.drain(matching..).skip(1).rev() {
+                        diagnostics.push(unclosed(opener, opened, Some(span.clone())));
+                    }
+                }
+                None => diagnostics.push(
+                    Diagnostic::error(
+                        "E0003",
+                        Message::new("E0003.unexpected").arg("close", c.to_string()),
+                    )
+                    .with_label(
+                        span.clone(),
+                        Message::new("label.closes-nothing").arg("open", opener.to_string()),
+                    ),
+                ),
+            }
+        }
+    }
+
+    for (opener, opened) in open.into_iter().rev() {
+        let expected = expected_close(source, tokens, &opened);
+        diagnostics.push(unclosed(opener, opened, expected));
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.labels[0].span.start);
+    diagnostics
+}
+
+/// An `opener` at `opened` that should have been closed before `expected`, or by the end of the
+/// file if that's `None`.
+fn unclosed(opener: char, opened: Span, expected: Option<Span>) -> Diagnostic {
+    let (_, closer) = PAIRS.iter().find(|(open, _)| *open == opener).unwrap();
+    let diagnostic = Diagnostic::error(
+        "E0003",
+        Message::new("E0003.unclosed").arg("open", opener.to_string()),
+    )
+    .with_label(
+        opened,
+        Message::new("label.opened-here").arg("open", opener.to_string()),
+    );
+    match expected {
+        Some(expected) => diagnostic.with_label(
+            expected,
+            Message::new("label.expected-close").arg("close", closer.to_string()),
+        ),
+        None => diagnostic
+            .with_note(Message::new("note.expected-close-by-end").arg("close", closer.to_string())),
+    }
+}
+
+/// The first token that starts a line after `opened`'s, indented no further than it, where a
+/// brace opened at `opened` was most likely meant to close.
+fn expected_close(source: &str, tokens: &[Spanned<Token>], opened: &Span) -> Option<Span> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let line_start = |pos: usize| {
+        chars[..pos]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |i| i + 1)
+    };
+    let indent = |pos: usize| {
+        let start = line_start(pos);
+        chars[start..]
+            .iter()
+            .take_while(|c| **c == ' ' || **c == '\t')
+            .count()
+    };
+
+    let opened_line = line_start(opened.start);
+    let opened_indent = indent(opened.start);
+    tokens
+        .iter()
+        .filter(|(_, span)| span.start > opened.start && line_start(span.start) > opened_line)
+        // the first token on its line
+        .filter(|(_, span)| {
+            chars[line_start(span.start)..span.start]
+                .iter()
+                .all(|c| c.is_whitespace())
+        })
+        .find(|(token, span)| indent(span.start) <= opened_indent && *token != Token::Ctrl('}'))
+        .map(|(_, span)| span.clone())
+}
@@ -0,0 +1,358 @@
+use std::{
+    fmt::Display,
+    hash::Hash,
+    io::{self, Write},
+};
+
+use ariadne::{Color, Fmt, Report, ReportKind};
+use chumsky::error::{Simple, SimpleReason};
+use serde::Serialize;
+
+use crate::{
+    ast,
+    literal::{self, IntError},
+    messages::{Locale, Message},
+    sources::SourceMap,
+    token::{self, Span},
+    Token,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A message about a problem in the source, with labeled spans pointing at the cause.
+///
+/// Every phase of the compiler (and the interpreter) reports problems with this type so that
+/// they can all be rendered the same way.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: Message,
+    pub labels: Vec<Label>,
+    pub notes: Vec<Message>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Label {
+    pub span: Span,
+    pub message: Message,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<Message>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<Message>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message)
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<Message>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<Message>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Writes every message in `locale`, so the diagnostic is rendered in that language.
+    pub fn localize(mut self, locale: Locale) -> Self {
+        let localize = |message: &mut Message| *message = Message::Text(message.render(locale));
+        localize(&mut self.message);
+        self.labels
+            .iter_mut()
+            .for_each(|label| localize(&mut label.message));
+        self.notes.iter_mut().for_each(localize);
+        self
+    }
+
+    /// Shifts every label by `offset`, used to place diagnostics from one file into a [`SourceMap`].
+    pub fn offset(mut self, offset: usize) -> Self {
+        for label in &mut self.labels {
+            label.span = label.span.start + offset..label.span.end + offset;
+        }
+        self
+    }
+
+    /// Renders the diagnostic to stderr with source snippets for every label.
+    ///
+    /// Falls back to [`Diagnostic::eprint_plain`] under `fallback` when no sources are available.
+    pub fn eprint(&self, sources: &SourceMap, fallback: &str) {
+        self.write(sources, fallback, io::stderr()).unwrap();
+    }
+
+    /// Like [`Diagnostic::eprint`], but renders the diagnostic to `out`, so that what several
+    /// compiles report can be held back and written one after the other.
+    pub fn write(
+        &self,
+        sources: &SourceMap,
+        fallback: &str,
+        mut out: impl Write,
+    ) -> io::Result<()> {
+        let Some(primary) = sources.files().first() else {
+            return self.write_plain(fallback, out);
+        };
+
+        let kind = match self.severity {
+            Severity::Error => ReportKind::Error,
+            Severity::Warning => ReportKind::Warning,
+        };
+        let color = match self.severity {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+        };
+
+        let (file, offset) = self
+            .labels
+            .first()
+            .and_then(|label| sources.locate(&label.span))
+            .map_or((primary, 0), |(file, span)| (file, span.start));
+        let mut report = Report::build(kind, file.name.clone(), offset)
+            .with_code(self.code)
+            .with_message(&self.message);
+        for label in &self.labels {
+            let Some((file, span)) = sources.locate(&label.span) else {
+                continue;
+            };
+            report = report.with_label(
+                ariadne::Label::new((file.name.clone(), span))
+                    .with_message(label.message.to_string().fg(color))
+                    .with_color(color),
+            );
+        }
+        for note in &self.notes {
+            report = report.with_note(note);
+        }
+
+        let cache = ariadne::sources(
+            sources
+                .files()
+                .iter()
+                .map(|file| (file.name.clone(), file.source.clone())),
+        );
+        report.finish().write(cache, &mut out)
+    }
+
+    /// Renders the diagnostic to stderr without source snippets, for when the source is unavailable.
+    pub fn eprint_plain(&self, filename: &str) {
+        self.write_plain(filename, io::stderr()).unwrap();
+    }
+
+    /// Like [`Diagnostic::eprint_plain`], but renders the diagnostic to `out`.
+    pub fn write_plain(&self, filename: &str, mut out: impl Write) -> io::Result<()> {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        writeln!(out, "{severity}[{}]: {}", self.code, self.message)?;
+        for label in &self.labels {
+            writeln!(
+                out,
+                "  --> {filename}@{}..{}: {}",
+                label.span.start, label.span.end, label.message
+            )?;
+        }
+        for note in &self.notes {
+            writeln!(out, "  = note: {note}")?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the diagnostic as a single line of JSON, with every label tagged by its file.
+    ///
+    /// Labels that can't be located in `sources` are attributed to `fallback`.
+    pub fn to_json(&self, sources: &SourceMap, fallback: &str) -> String {
+        #[derive(Serialize)]
+        struct Json<'a> {
+            file: &'a str,
+            severity: Severity,
+            code: &'static str,
+            message: &'a Message,
+            labels: Vec<JsonLabel<'a>>,
+            notes: &'a [Message],
+        }
+
+        #[derive(Serialize)]
+        struct JsonLabel<'a> {
+            file: &'a str,
+            span: Span,
+            message: &'a Message,
+        }
+
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                let (file, span) = sources
+                    .locate(&label.span)
+                    .map_or((fallback, label.span.clone()), |(file, span)| {
+                        (file.name.as_str(), span)
+                    });
+                JsonLabel {
+                    file,
+                    span,
+                    message: &label.message,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let file = labels.first().map_or_else(
+            || sources.files().first().map_or(fallback, |file| &file.name),
+            |label| label.file,
+        );
+
+        serde_json::to_string(&Json {
+            file,
+            severity: self.severity,
+            code: self.code,
+            message: &self.message,
+            labels,
+            notes: &self.notes,
+        })
+        .unwrap()
+    }
+}
+
+impl From<Simple<char>> for Diagnostic {
+    fn from(error: Simple<char>) -> Self {
+        match (error.reason(), error.label(), error.found()) {
+            (SimpleReason::Unclosed { span, .. }, Some(kind), _) => {
+                let (message, label, note) = match kind {
+                    token::UNTERMINATED_RAW_STRING => (
+                        "E0001.unterminated-raw-string",
+                        "label.raw-string-started",
+                        "note.close-raw-string",
+                    ),
+                    token::UNTERMINATED_COMMENT => (
+                        "E0001.unterminated-comment",
+                        "label.comment-started",
+                        "note.close-comment",
+                    ),
+                    _ => (
+                        "E0001.unterminated-string",
+                        "label.string-started",
+                        "note.close-string",
+                    ),
+                };
+                Diagnostic::error("E0001", Message::new(message))
+                    .with_label(span.clone(), Message::new(label))
+                    .with_label(error.span(), Message::new("label.file-ends"))
+                    .with_note(Message::new(note))
+            }
+            (SimpleReason::Custom(name), Some(token::DIRECTIVE), _) => Diagnostic::error(
+                "E0004",
+                Message::new("E0004.directive").arg("name", name.as_str()),
+            )
+            .with_label(error.span(), Message::new("label.unsupported"))
+            .with_note(Message::new("note.no-preprocessor")),
+            // every token starts with a character that the lexer recognises, so a character that
+            // isn't expected is one that can't start a token
+            (SimpleReason::Unexpected, _, Some(found)) => {
+                Diagnostic::error("E0001", Message::new("E0001"))
+                    .with_label(
+                        error.span(),
+                        Message::new("label.unexpected-char").arg("char", found.to_string()),
+                    )
+                    .with_note(
+                        Message::new("note.allowed-chars")
+                            .arg("operators", token::operator_chars())
+                            .arg("delimiters", token::DELIMITERS),
+                    )
+            }
+            _ => from_simple("E0001", error),
+        }
+    }
+}
+
+impl From<Simple<Token>> for Diagnostic {
+    fn from(error: Simple<Token>) -> Self {
+        match (error.reason(), error.label()) {
+            (SimpleReason::Custom(keyword), Some(ast::KEYWORD_TYPO)) => {
+                Diagnostic::error("E0002", Message::new("E0002.keyword-typo")).with_label(
+                    error.span(),
+                    Message::new("label.did-you-mean").arg("keyword", keyword.as_str()),
+                )
+            }
+            (SimpleReason::Custom(literal), Some(ast::TOO_LARGE)) => Diagnostic::error(
+                "E0005",
+                Message::new("E0005").arg("literal", literal.as_str()),
+            )
+            .with_label(error.span(), Message::new("label.too-large"))
+            .with_note(Message::new("note.int-range")),
+            (SimpleReason::Custom(literal), Some(ast::INVALID_FLOAT)) => Diagnostic::error(
+                "E0007",
+                Message::new("E0007").arg("literal", literal.as_str()),
+            )
+            .with_label(error.span(), Message::new("label.invalid-float")),
+            (SimpleReason::Custom(literal), Some(ast::INVALID_INT)) => {
+                let label = match literal::int(literal, u32::MAX) {
+                    Err(IntError::InvalidDigit { digit, radix }) => {
+                        Message::new("label.invalid-digit")
+                            .arg("digit", digit.to_string())
+                            .arg("radix", radix.to_string())
+                    }
+                    _ => Message::new("label.no-digits"),
+                };
+                Diagnostic::error(
+                    "E0006",
+                    Message::new("E0006").arg("literal", literal.as_str()),
+                )
+                .with_label(error.span(), label)
+            }
+            (SimpleReason::Custom(construct), Some(ast::UNSUPPORTED)) => {
+                let (message, note) = match construct.as_str() {
+                    "if" => ("E0004.if", "note.no-if"),
+                    _ => ("E0004.loop", "note.no-loops"),
+                };
+                Diagnostic::error(
+                    "E0004",
+                    Message::new(message).arg("keyword", construct.as_str()),
+                )
+                .with_label(error.span(), Message::new("label.unsupported"))
+                .with_note(Message::new(note))
+            }
+            _ => from_simple("E0002", error),
+        }
+    }
+}
+
+fn from_simple<T: Display + Hash + Eq>(code: &'static str, error: Simple<T>) -> Diagnostic {
+    let diagnostic = Diagnostic::error(code, Message::new(code));
+    match error.reason() {
+        SimpleReason::Unclosed { span, delimiter } => diagnostic
+            .with_label(error.span(), Message::Text(error.to_string()))
+            .with_label(
+                span.clone(),
+                Message::new("label.unclosed-delimiter").arg("delimiter", delimiter.to_string()),
+            ),
+        SimpleReason::Custom(msg) => {
+            diagnostic.with_label(error.span(), Message::Text(msg.clone()))
+        }
+        SimpleReason::Unexpected => {
+            diagnostic.with_label(error.span(), Message::Text(error.to_string()))
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Readable dumps of what the front end produces, for `build --emit tokens` and `--emit ast`.
+
+use crate::{
+    ast::{Definition, Expr, Spanned, Statement},
+    literal, Ast, Token,
+};
+
+/// Lists `tokens` lexed from `source` in a table, one per line, with the line and column each
+/// starts at, its span, its kind and its text in the source.
+pub fn tokens(source: &str, tokens: &[Spanned<Token>]) -> String {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut rows = vec![[
+        String::from("LINE:COL"),
+        String::from("SPAN"),
+        String::from("KIND"),
+        String::from("TEXT"),
+    ]];
+    for (token, span) in tokens {
+        let before = &chars[..span.start];
+        let line = before.iter().filter(|c| **c == '\n').count() + 1;
+        let column = span.start - before.iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1) + 1;
+        let text = chars[span.clone()]
+            .iter()
+            .map(|c| match c.is_control() {
+                true => c.escape_debug().to_string(),
+                false => c.to_string(),
+            })
+            .collect();
+        rows.push([
+            format!("{line}:{column}"),
+            format!("{}..{}", span.start, span.end),
+            String::from(kind(token)),
+            text,
+        ]);
+    }
+
+    let widths = (0..3)
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    let mut table = String::new();
+    for row in rows {
+        for (cell, width) in row.iter().zip(&widths) {
+            table += &format!("{cell:<width$}  ");
+        }
+        table += &row[3];
+        table.push('\n');
+    }
+    table
+}
+
+fn kind(token: &Token) -> &'static str {
+    match token {
+        Token::Return
+        | Token::Struct
+        | Token::Import
+        | Token::Macro
+        | Token::Switch
+        | Token::Case
+        | Token::Default
+        | Token::Break
+        | Token::Continue
+        | Token::Const => "keyword",
+        Token::Comptime => "annotation",
+        Token::Asm(_) | Token::Ir(_) => "inline",
+        Token::Op(_) => "operator",
+        Token::Ident(_) => "identifier",
+        Token::Ctrl(_) => "delimiter",
+        Token::Num(_) | Token::Float(_) => "number",
+        Token::Str(_) => "string",
+    }
+}
+
+/// Draws `ast` as a tree, with each definition at the root of its own.
+pub fn tree(ast: &Ast) -> String {
+    let mut tree = String::new();
+    for def in &ast.defs {
+        draw(&mut tree, &definition(def), "", "");
+    }
+    tree
+}
+
+/// A node of a drawn tree.
+struct Node {
+    label: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn leaf(label: impl Into<String>) -> Self {
+        Self::new(label, Vec::new())
+    }
+
+    fn new(label: impl Into<String>, children: Vec<Node>) -> Self {
+        Self {
+            label: label.into(),
+            children,
+        }
+    }
+}
+
+/// Draws `node` after `lead`, and its children below it each after `indent`.
+fn draw(tree: &mut String, node: &Node, lead: &str, indent: &str) {
+    *tree += &format!("{lead}{}\n", node.label);
+    for (i, child) in node.children.iter().enumerate() {
+        match i + 1 == node.children.len() {
+            true => draw(
+                tree,
+                child,
+                &format!("{indent}└── "),
+                &format!("{indent}    "),
+            ),
+            false => draw(
+                tree,
+                child,
+                &format!("{indent}├── "),
+                &format!("{indent}│   "),
+            ),
+        }
+    }
+}
+
+fn definition(def: &Definition) -> Node {
+    match def {
+        Definition::Import { path, .. } => Node::leaf(format!("import {}", literal::escape(path))),
+        Definition::Struct { name, params, .. } => Node::new(
+            format!("struct {name}"),
+            params
+                .iter()
+                .map(|param| Node::leaf(format!("field {} {}", param.ty, param.name)))
+                .collect(),
+        ),
+        Definition::Func(func) => Node::new(
+            def.to_string(),
+            func.body
+                .iter()
+                .map(|(statement, _)| self::statement(statement))
+                .collect(),
+        ),
+        Definition::Prototype(_) => Node::leaf(def.to_string()),
+        Definition::Global(global) => {
+            let keyword = if global.constant { "const" } else { "global" };
+            Node::new(
+                format!("{keyword} {} {}", global.ty, global.name),
+                global.expr.iter().map(self::expr).collect(),
+            )
+        }
+        Definition::Macro(r#macro) => Node::new(
+            def.to_string(),
+            r#macro
+                .body
+                .iter()
+                .map(|(statement, _)| self::statement(statement))
+                .collect(),
+        ),
+    }
+}
+
+fn statement(statement: &Statement) -> Node {
+    match statement {
+        Statement::Invalid => Node::leaf("invalid"),
+        Statement::Return(value) => Node::new("return", vec![expr(value)]),
+        Statement::ReturnVoid => Node::leaf("return"),
+        Statement::Expr(value) => Node::new("discard", vec![expr(value)]),
+        Statement::Inline { lang, code } => {
+            Node::new(lang.keyword(), code.lines().map(Node::leaf).collect())
+        }
+        Statement::Assign { ty, name, expr } => {
+            Node::new(format!("let {ty} {name}"), vec![self::expr(expr)])
+        }
+        Statement::Array { ty, name, len } => Node::leaf(format!("array {ty} {name}[{len}]")),
+        Statement::Store { name, index, expr } => Node::new(
+            format!("store {name}"),
+            vec![
+                Node::new("index", vec![self::expr(index)]),
+                Node::new("value", vec![self::expr(expr)]),
+            ],
+        ),
+        Statement::Reassign { name, op, expr } => {
+            Node::new(format!("{name} {}", op.text()), vec![self::expr(expr)])
+        }
+        Statement::Write { pointer, expr } => Node::new(
+            "write",
+            vec![
+                Node::new("pointer", vec![self::expr(pointer)]),
+                Node::new("value", vec![self::expr(expr)]),
+            ],
+        ),
+        Statement::Expand { name, args } => {
+            Node::new(format!("expand {name}!"), args.iter().map(expr).collect())
+        }
+        Statement::Switch { expr, cases } => Node::new(
+            "switch",
+            [self::expr(expr)]
+                .into_iter()
+                .chain(cases.iter().map(|case| {
+                    let label = match case.value {
+                        Some(value) => format!("case {value}"),
+                        None => String::from("default"),
+                    };
+                    let body = case.body.iter();
+                    Node::new(
+                        label,
+                        body.map(|(statement, _)| self::statement(statement))
+                            .collect(),
+                    )
+                }))
+                .collect(),
+        ),
+        Statement::Break => Node::leaf("break"),
+        Statement::Continue => Node::leaf("continue"),
+    }
+}
+
+fn expr((expr, _): &Spanned<Expr>) -> Node {
+    let binary = |op: &str, lhs, rhs| Node::new(op, vec![self::expr(lhs), self::expr(rhs)]);
+    match expr {
+        Expr::Err => Node::leaf("error"),
+        Expr::Int(value) => Node::leaf(value.to_string()),
+        Expr::Float(value) => Node::leaf(literal::float(*value)),
+        Expr::Str(value) => Node::leaf(literal::escape(value)),
+        Expr::Var(name) => Node::leaf(format!("var {name}")),
+        Expr::Neg(inner) => Node::new("negate", vec![self::expr(inner)]),
+        Expr::Not(inner) => Node::new("not", vec![self::expr(inner)]),
+        Expr::PreInc(name) => Node::leaf(format!("++ {name}")),
+        Expr::PreDec(name) => Node::leaf(format!("-- {name}")),
+        Expr::AddrOf(name) => Node::leaf(format!("address of {name}")),
+        Expr::Deref(inner) => Node::new("deref", vec![self::expr(inner)]),
+        Expr::SizeOf(ty) => Node::leaf(format!("sizeof {ty}")),
+        Expr::Mul(lhs, rhs) => binary("*", lhs, rhs),
+        Expr::Div(lhs, rhs) => binary("/", lhs, rhs),
+        Expr::Add(lhs, rhs) => binary("+", lhs, rhs),
+        Expr::Sub(lhs, rhs) => binary("-", lhs, rhs),
+        Expr::Rem(lhs, rhs) => binary("%", lhs, rhs),
+        Expr::BitAnd(lhs, rhs) => binary("&", lhs, rhs),
+        Expr::BitOr(lhs, rhs) => binary("|", lhs, rhs),
+        Expr::BitXor(lhs, rhs) => binary("^", lhs, rhs),
+        Expr::Shl(lhs, rhs) => binary("<<", lhs, rhs),
+        Expr::Shr(lhs, rhs) => binary(">>", lhs, rhs),
+        Expr::Lt(lhs, rhs) => binary("<", lhs, rhs),
+        Expr::Le(lhs, rhs) => binary("<=", lhs, rhs),
+        Expr::Gt(lhs, rhs) => binary(">", lhs, rhs),
+        Expr::Ge(lhs, rhs) => binary(">=", lhs, rhs),
+        Expr::Eq(lhs, rhs) => binary("==", lhs, rhs),
+        Expr::Ne(lhs, rhs) => binary("!=", lhs, rhs),
+        Expr::And(lhs, rhs) => binary("&&", lhs, rhs),
+        Expr::Or(lhs, rhs) => binary("||", lhs, rhs),
+        Expr::Cond(cond, then, otherwise) => Node::new(
+            "?:",
+            vec![self::expr(cond), self::expr(then), self::expr(otherwise)],
+        ),
+        Expr::Index(array, index) => binary("index", array, index),
+        Expr::Call { name, params } => Node::new(
+            format!("call {name}"),
+            params.iter().map(self::expr).collect(),
+        ),
+        Expr::Spawn { name, params } => Node::new(
+            format!("spawn {name}"),
+            params.iter().map(self::expr).collect(),
+        ),
+        Expr::Join(handle) => Node::new("join", vec![self::expr(handle)]),
+    }
+}
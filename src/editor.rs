@@ -0,0 +1,219 @@
+//! Editor support files generated from the lexer's own tables, so that highlighting and bracket
+//! matching can't drift from what the compiler actually accepts.
+
+use serde_json::{json, Value};
+
+use crate::{
+    literal::ESCAPES,
+    token::{
+        ANNOTATIONS, BLOCK_COMMENT, DELIMITERS, INLINE_BLOCKS, KEYWORDS, LINE_COMMENT, OPERATORS,
+    },
+    Builtins,
+};
+
+pub const LANGUAGE_ID: &str = "crust";
+pub const SCOPE_NAME: &str = "source.crust";
+/// Extensions that `run` treats as source code.
+pub const EXTENSIONS: &[&str] = &[".c", ".cst"];
+
+/// Every generated file, as a file name and its contents.
+pub fn files(builtins: &Builtins) -> Vec<(&'static str, String)> {
+    [
+        ("crust.tmLanguage.json", grammar(builtins)),
+        ("snippets.json", snippets()),
+        ("language-configuration.json", language_configuration()),
+    ]
+    .into_iter()
+    .map(|(name, json)| (name, serde_json::to_string_pretty(&json).unwrap() + "\n"))
+    .collect()
+}
+
+/// A TextMate grammar highlighting keywords, annotations, builtins, literals, operators and
+/// comments.
+pub fn grammar(builtins: &Builtins) -> Value {
+    let keywords = KEYWORDS
+        .iter()
+        .map(|(keyword, _)| *keyword)
+        .chain(INLINE_BLOCKS.iter().copied())
+        .collect::<Vec<_>>()
+        .join("|");
+    let annotations = ANNOTATIONS
+        .iter()
+        .map(|(annotation, _)| annotation.chars().map(regex_escape).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("|");
+    let escapes = ESCAPES
+        .iter()
+        .map(|(escape, _)| regex_escape(*escape))
+        .collect::<String>();
+
+    json!({
+        "$schema": "https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json",
+        "name": "Crust",
+        "scopeName": SCOPE_NAME,
+        "fileTypes": EXTENSIONS.iter().map(|ext| &ext[1..]).collect::<Vec<_>>(),
+        "patterns": [
+            { "include": "#comments" },
+            { "include": "#strings" },
+            { "include": "#keywords" },
+            { "include": "#annotations" },
+            { "include": "#numbers" },
+            { "include": "#functions" },
+            { "include": "#operators" },
+            { "include": "#punctuation" },
+        ],
+        "repository": {
+            "comments": {
+                "patterns": [
+                    {
+                        "name": "comment.line.double-slash.crust",
+                        "match": format!("{}.*$", LINE_COMMENT.chars().map(regex_escape).collect::<String>()),
+                    },
+                    { "include": "#block-comment" },
+                ],
+            },
+            // includes itself, since block comments nest
+            "block-comment": {
+                "name": "comment.block.crust",
+                "begin": BLOCK_COMMENT.0.chars().map(regex_escape).collect::<String>(),
+                "end": BLOCK_COMMENT.1.chars().map(regex_escape).collect::<String>(),
+                "patterns": [{ "include": "#block-comment" }],
+            },
+            "strings": {
+                "patterns": [
+                    {
+                        "name": "string.quoted.other.raw.crust",
+                        "begin": "r(#*)\"",
+                        "end": "\"\\1",
+                    },
+                    {
+                        "name": "string.quoted.double.crust",
+                        "begin": "\"",
+                        "end": "\"",
+                        "patterns": [
+                            {
+                                "name": "constant.character.escape.crust",
+                                "match": format!("\\\\([{escapes}]|x[0-9A-Fa-f]{{2}}|u[0-9A-Fa-f]{{4}})"),
+                            },
+                            {
+                                "name": "invalid.illegal.escape.crust",
+                                "match": "\\\\.",
+                            },
+                        ],
+                    },
+                ],
+            },
+            "keywords": {
+                "name": "keyword.control.crust",
+                "match": format!("\\b({keywords})\\b"),
+            },
+            "annotations": {
+                "name": "storage.modifier.crust",
+                "match": format!("({annotations})\\b"),
+            },
+            "numbers": {
+                "name": "constant.numeric.crust",
+                "match": "\\b[0-9]+(\\.[0-9]+)?\\b",
+            },
+            "functions": {
+                "patterns": [
+                    {
+                        "name": "support.function.builtin.crust",
+                        "match": format!("\\b({})\\b(?=\\s*\\()", builtins.names().join("|")),
+                    },
+                    {
+                        "name": "entity.name.function.crust",
+                        "match": "\\b[A-Za-z_][A-Za-z0-9_]*\\b(?=\\s*\\()",
+                    },
+                ],
+            },
+            "operators": {
+                "name": "keyword.operator.crust",
+                "match": OPERATORS
+                    .iter()
+                    .map(|op| op.chars().map(regex_escape).collect::<String>())
+                    .collect::<Vec<_>>()
+                    .join("|"),
+            },
+            "punctuation": {
+                "name": "punctuation.separator.crust",
+                "match": "[;,]",
+            },
+        },
+    })
+}
+
+/// VS Code snippets for each kind of definition and statement.
+pub fn snippets() -> Value {
+    json!({
+        "main": {
+            "prefix": "main",
+            "body": ["int main() {", "\t$0", "\treturn 0;", "}"],
+            "description": "Program entry point",
+        },
+        "function": {
+            "prefix": "fn",
+            "body": ["${1:int} ${2:name}(${3}) {", "\t$0", "}"],
+            "description": "Function definition",
+        },
+        "struct": {
+            "prefix": "struct",
+            "body": ["struct ${1:Name} {", "\t${2:int} ${3:field}", "}"],
+            "description": "Struct definition",
+        },
+        "import": {
+            "prefix": "import",
+            "body": ["import \"${1:file.c}\";"],
+            "description": "Import definitions from another file",
+        },
+        "return": {
+            "prefix": "return",
+            "body": ["return ${1:0};"],
+            "description": "Return from the current function",
+        },
+        "array": {
+            "prefix": "array",
+            "body": ["${1:int} ${2:name}[${3:10}];"],
+            "description": "Array declaration",
+        },
+    })
+}
+
+/// Comment and bracket configuration for VS Code.
+pub fn language_configuration() -> Value {
+    let brackets = DELIMITERS
+        .chars()
+        .collect::<Vec<_>>()
+        .chunks(2)
+        .filter(|pair| pair.len() == 2 && pair[0] != pair[1] && is_open(pair[0]))
+        .map(|pair| [pair[0].to_string(), pair[1].to_string()])
+        .collect::<Vec<_>>();
+
+    let mut auto_closing = brackets
+        .iter()
+        .map(|[open, close]| json!({ "open": open, "close": close }))
+        .collect::<Vec<_>>();
+    auto_closing.push(json!({ "open": "\"", "close": "\"", "notIn": ["string"] }));
+
+    json!({
+        "comments": {
+            "lineComment": LINE_COMMENT,
+            "blockComment": [BLOCK_COMMENT.0, BLOCK_COMMENT.1],
+        },
+        "brackets": brackets,
+        "autoClosingPairs": auto_closing,
+        "surroundingPairs": brackets,
+    })
+}
+
+fn is_open(c: char) -> bool {
+    matches!(c, '(' | '[' | '{')
+}
+
+fn regex_escape(c: char) -> String {
+    match c {
+        '\\' | '^' | '$' | '.' | '|' | '?' | '*' | '+' | '(' | ')' | '[' | ']' | '{' | '}'
+        | '/' | '-' => format!("\\{c}"),
+        c => c.to_string(),
+    }
+}
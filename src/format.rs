@@ -0,0 +1,442 @@
+//! Formats source code, used by `fmt`.
+//!
+//! A file is parsed and printed back from its AST with canonical indentation, spacing and brace
+//! placement, so parentheses that don't change the meaning of an expression are dropped and raw
+//! strings are printed as escaped strings. Comments aren't part of the AST, so they're printed
+//! around the nodes they sit between: at the end of the line of the definition or statement they
+//! follow on the same line, and otherwise on their own lines before the one after them. A single
+//! blank line between statements or comments is kept.
+
+use std::{iter::Peekable, vec};
+
+use chumsky::{Parser, Stream};
+
+use crate::{
+    ast::{Definition, Expr, Func, Param, Spanned, Statement},
+    literal, precedence,
+    token::Span,
+    Ast, Diagnostic, Token,
+};
+
+/// Indentation for each level of nesting.
+pub const INDENT: &str = "    ";
+
+/// Formats `source`, failing with the errors that stop it from being parsed.
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let (lexed, errors) = Token::lexer_with_comments().parse_recovery(source);
+    let (Some((tokens, comments)), true) = (lexed, errors.is_empty()) else {
+        return Err(errors.into_iter().map(Diagnostic::from).collect());
+    };
+
+    let len = source.chars().count();
+    let (ast, errors) =
+        Ast::parser().parse_recovery(Stream::from_iter(len..len + 1, tokens.iter().cloned()));
+    let (Some(ast), true) = (ast, errors.is_empty()) else {
+        return Err(errors.into_iter().map(Diagnostic::from).collect());
+    };
+
+    let mut printer = Printer {
+        source: source.chars().collect(),
+        tokens: &tokens,
+        comments: comments.into_iter().peekable(),
+        out: String::new(),
+        end: 0,
+        block_start: true,
+    };
+    printer.ast(&ast);
+    Ok(printer.out)
+}
+
+struct Printer<'a> {
+    source: Vec<char>,
+    tokens: &'a [Spanned<Token>],
+    comments: Peekable<vec::IntoIter<Spanned<String>>>,
+    out: String,
+    /// Where the last line printed ends in the source
+    end: usize,
+    /// Whether nothing has been printed since the start of the file or a block, where blank
+    /// lines aren't kept
+    block_start: bool,
+}
+
+impl Printer<'_> {
+    fn ast(&mut self, ast: &Ast) {
+        for (i, def) in ast.defs.iter().enumerate() {
+            // imports, prototypes and globals are kept together without blank lines between them
+            let previous = ast.defs.get(i.wrapping_sub(1));
+            let grouped = match def {
+                Definition::Import { .. } => matches!(previous, Some(Definition::Import { .. })),
+                Definition::Prototype(_) => matches!(previous, Some(Definition::Prototype(_))),
+                Definition::Global(_) => matches!(previous, Some(Definition::Global(_))),
+                _ => false,
+            };
+            if i > 0 && !grouped {
+                self.out.push('\n');
+                self.block_start = true;
+            }
+            self.def(def);
+        }
+        self.comments_before(usize::MAX, 0);
+    }
+
+    fn def(&mut self, def: &Definition) {
+        match def {
+            Definition::Import { path, span } => {
+                let text = format!("import {};", literal::escape(path));
+                self.line(0, span.clone(), &text);
+            }
+            Definition::Struct { name, params, span } => {
+                let start = self.token_before(span.start).start;
+                self.line(0, start..span.end, &format!("struct {name}"));
+                let open = self.find(span.end, Token::Ctrl('{'));
+                self.open(0, open.clone());
+                let mut end = open.end;
+                for param in params {
+                    let semicolon = self.find(param.span.end, Token::Ctrl(';'));
+                    let text = format!("{} {};", param.ty, param.name);
+                    self.line(1, param.span.start..semicolon.end, &text);
+                    end = semicolon.end;
+                }
+                let close = self.find(end, Token::Ctrl('}'));
+                self.comments_before(close.start, 1);
+                let semicolon = self.find(close.end, Token::Ctrl(';'));
+                self.line(0, close.start..semicolon.end, "};");
+            }
+            Definition::Func(func) => self.func(func),
+            Definition::Prototype(prototype) => {
+                let start = self.token_before(prototype.span.start).start;
+                let semicolon = self.find(prototype.span.end, Token::Ctrl(';'));
+                let text = format!(
+                    "{} {}({});",
+                    prototype.ret,
+                    prototype.name,
+                    params(&prototype.params)
+                );
+                self.line(0, start..semicolon.end, &text);
+            }
+            Definition::Global(global) => {
+                // the type, and `const`, come before the name
+                let name = self
+                    .tokens
+                    .partition_point(|(_, span)| span.start < global.span.start);
+                let words = 1 + global.ty.matches('*').count() + usize::from(global.constant);
+                let start = self.tokens[name.saturating_sub(words)].1.start;
+                let semicolon = self.find(global.span.end, Token::Ctrl(';'));
+                let mut text = format!("{} {}", global.ty, global.name);
+                if global.constant {
+                    text.insert_str(0, "const ");
+                }
+                if let Some((expr, _)) = &global.expr {
+                    text += &format!(" = {}", self::expr(expr));
+                }
+                text.push(';');
+                self.line(0, start..semicolon.end, &text);
+            }
+            Definition::Macro(r#macro) => {
+                let start = self.token_before(r#macro.span.start).start;
+                let params = r#macro
+                    .params
+                    .iter()
+                    .map(|(param, _)| param.as_str())
+                    .collect::<Vec<_>>();
+                let header = format!("macro {}({})", r#macro.name, params.join(", "));
+                self.body(start, r#macro.span.end, header, &r#macro.body);
+            }
+        }
+    }
+
+    fn func(&mut self, func: &Func) {
+        let mut start = self.token_before(func.span.start).start;
+        let mut header = format!("{} {}({})", func.ret, func.name, params(&func.params));
+        if func.comptime {
+            start = self.token_before(start).start;
+            header = format!("{} {header}", Token::Comptime);
+        }
+        self.body(start, func.span.end, header, &func.body);
+    }
+
+    /// Prints `header`, which starts at `start`, and the block of `statements` after `pos`.
+    fn body(
+        &mut self,
+        start: usize,
+        pos: usize,
+        header: String,
+        statements: &[Spanned<Statement>],
+    ) {
+        let open = self.find(pos, Token::Ctrl('{'));
+        self.line(0, start..open.start, &header);
+        self.open(0, open.clone());
+
+        let end = self.statements(1, open.end, statements);
+        let close = self.find(end, Token::Ctrl('}'));
+        self.comments_before(close.start, 1);
+        self.line(0, close, "}");
+    }
+
+    /// Prints `statements` at `indent`, returning where the last of them ends in the source, or
+    /// `end` if there are none.
+    fn statements(
+        &mut self,
+        indent: usize,
+        mut end: usize,
+        statements: &[Spanned<Statement>],
+    ) -> usize {
+        for (statement, span) in statements {
+            match statement {
+                Statement::Switch { expr, cases } => {
+                    let open = self.find(expr.1.end, Token::Ctrl('{'));
+                    let header = format!("switch ({})", self::expr(&expr.0));
+                    self.line(indent, span.start..open.start, &header);
+                    self.open(indent, open.clone());
+
+                    let mut end = open.end;
+                    for case in cases {
+                        let colon = self.find(case.span.end, Token::Op(":"));
+                        let label = match case.value {
+                            Some(value) => format!("case {value}:"),
+                            None => String::from("default:"),
+                        };
+                        self.line(indent + 1, case.span.start..colon.end, &label);
+                        self.block_start = true;
+                        end = self.statements(indent + 2, colon.end, &case.body);
+                    }
+                    let close = self.find(end, Token::Ctrl('}'));
+                    self.comments_before(close.start, indent + 1);
+                    self.line(indent, close, "}");
+                }
+                statement => self.line(indent, span.clone(), &self::statement(statement)),
+            }
+            end = span.end;
+        }
+        end
+    }
+
+    /// Prints a line that opens a block.
+    fn open(&mut self, indent: usize, span: Span) {
+        self.line(indent, span, "{");
+        self.block_start = true;
+    }
+
+    /// Prints `text` as a line for the source at `span`, after the comments that come before it
+    /// and followed by the first comment inside it or after it on the same line. Any lines after
+    /// the first in `text` are indented as much as it is.
+    fn line(&mut self, indent: usize, span: Span, text: &str) {
+        self.comments_before(span.start, indent);
+        self.blank_line_before(span.start);
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            if !line.is_empty() {
+                self.out.push_str(&INDENT.repeat(indent));
+                self.out.push_str(line);
+            }
+        }
+        self.end = span.end;
+        self.block_start = false;
+
+        let next_token = self.find_any(span.end).start;
+        let source = &self.source;
+        let trailing = self.comments.next_if(|(_, comment)| {
+            comment.start < span.end
+                || comment.start < next_token && !source[span.end..comment.start].contains(&'\n')
+        });
+        if let Some((comment, comment_span)) = trailing {
+            self.out.push(' ');
+            self.out.push_str(&comment);
+            self.end = self.end.max(comment_span.end);
+        }
+        self.out.push('\n');
+
+        // any other comments inside it follow on their own lines
+        self.comments_before(span.end, indent);
+    }
+
+    /// Prints every comment that starts before `pos` on its own line.
+    fn comments_before(&mut self, pos: usize, indent: usize) {
+        while let Some((text, span)) = self.comments.next_if(|(_, span)| span.start < pos) {
+            self.blank_line_before(span.start);
+            self.out.push_str(&INDENT.repeat(indent));
+            self.out.push_str(&text);
+            self.out.push('\n');
+            self.end = self.end.max(span.end);
+            self.block_start = false;
+        }
+    }
+
+    /// Keeps a blank line between the last line printed and the source at `pos`.
+    fn blank_line_before(&mut self, pos: usize) {
+        let between = &self.source[self.end.min(pos)..pos];
+        if !self.block_start && between.iter().filter(|c| **c == '\n').count() > 1 {
+            self.out.push('\n');
+        }
+    }
+
+    /// The span of the token just before the one at `pos`.
+    fn token_before(&self, pos: usize) -> Span {
+        let index = self.tokens.partition_point(|(_, span)| span.start < pos);
+        self.tokens[index.saturating_sub(1)].1.clone()
+    }
+
+    /// The span of the first token at or after `pos`, or the end of the file if there isn't one.
+    fn find_any(&self, pos: usize) -> Span {
+        let index = self.tokens.partition_point(|(_, span)| span.start < pos);
+        self.tokens
+            .get(index)
+            .map_or(self.source.len()..self.source.len(), |(_, span)| {
+                span.clone()
+            })
+    }
+
+    /// The span of the first `token` at or after `pos`.
+    fn find(&self, pos: usize, token: Token) -> Span {
+        self.tokens
+            .iter()
+            .find(|(found, span)| span.start >= pos && *found == token)
+            .map(|(_, span)| span.clone())
+            .unwrap_or(pos..pos)
+    }
+}
+
+fn params(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|param| format!("{} {}", param.ty, param.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn statement(statement: &Statement) -> String {
+    match statement {
+        Statement::Invalid => unreachable!("invalid statements are only parsed with errors"),
+        Statement::Return(expr) => format!("return {};", self::expr(&expr.0)),
+        Statement::ReturnVoid => String::from("return;"),
+        Statement::Expr(expr) => format!("{};", self::expr(&expr.0)),
+        Statement::Inline { lang, code } if code.is_empty() => format!("{} {{}}", lang.keyword()),
+        Statement::Inline { lang, code } => {
+            let mut text = format!("{} {{\n", lang.keyword());
+            for line in code.lines() {
+                if !line.is_empty() {
+                    text.push_str(INDENT);
+                    text.push_str(line);
+                }
+                text.push('\n');
+            }
+            text + "}"
+        }
+        Statement::Assign { ty, name, expr } => format!("{ty} {name} = {};", self::expr(&expr.0)),
+        Statement::Array { ty, name, len } => format!("{ty} {name}[{len}];"),
+        Statement::Store { name, index, expr } => {
+            format!(
+                "{name}[{}] = {};",
+                self::expr(&index.0),
+                self::expr(&expr.0)
+            )
+        }
+        Statement::Reassign { name, op, expr } => {
+            format!("{name} {} {};", op.text(), self::expr(&expr.0))
+        }
+        Statement::Write { pointer, expr } => format!(
+            "{} = {};",
+            self::expr(&Expr::Deref(pointer.clone())),
+            self::expr(&expr.0)
+        ),
+        Statement::Expand { name, args } => {
+            let args = args
+                .iter()
+                .map(|(arg, _)| self::expr(arg))
+                .collect::<Vec<_>>();
+            format!("{name}!({});", args.join(", "))
+        }
+        Statement::Switch { .. } => unreachable!("switches are printed over several lines"),
+        Statement::Break => String::from("break;"),
+        Statement::Continue => String::from("continue;"),
+    }
+}
+
+fn expr(expr: &Expr) -> String {
+    let binary = |lhs: &Expr, op, rhs: &Expr| {
+        // operators are left associative, so an operand on the right of one with the same
+        // precedence needs parentheses
+        let precedence = precedence::binding(expr);
+        format!(
+            "{} {op} {}",
+            operand(lhs, precedence),
+            operand(rhs, precedence + 1)
+        )
+    };
+    match expr {
+        Expr::Err => unreachable!("invalid expressions are only parsed with errors"),
+        Expr::Int(value) => value.to_string(),
+        Expr::Float(value) => literal::float(*value),
+        Expr::Str(value) => literal::escape(value),
+        Expr::Var(name) => name.to_string(),
+        Expr::Neg(inner) => {
+            // `--` would lex as a decrement, so negating a negative is spaced out
+            let operand = operand(&inner.0, precedence::binding(expr));
+            match operand.starts_with('-') {
+                true => format!("- {operand}"),
+                false => format!("-{operand}"),
+            }
+        }
+        Expr::Not(inner) => format!("!{}", operand(&inner.0, precedence::binding(expr))),
+        Expr::PreInc(name) => format!("++{name}"),
+        Expr::PreDec(name) => format!("--{name}"),
+        Expr::AddrOf(name) => format!("&{name}"),
+        Expr::Deref(inner) => format!("*{}", operand(&inner.0, precedence::binding(expr))),
+        Expr::SizeOf(ty) => format!("sizeof({ty})"),
+        Expr::Mul(lhs, rhs) => binary(&lhs.0, "*", &rhs.0),
+        Expr::Div(lhs, rhs) => binary(&lhs.0, "/", &rhs.0),
+        Expr::Rem(lhs, rhs) => binary(&lhs.0, "%", &rhs.0),
+        Expr::Add(lhs, rhs) => binary(&lhs.0, "+", &rhs.0),
+        Expr::Sub(lhs, rhs) => binary(&lhs.0, "-", &rhs.0),
+        Expr::Shl(lhs, rhs) => binary(&lhs.0, "<<", &rhs.0),
+        Expr::Shr(lhs, rhs) => binary(&lhs.0, ">>", &rhs.0),
+        Expr::BitAnd(lhs, rhs) => binary(&lhs.0, "&", &rhs.0),
+        Expr::BitXor(lhs, rhs) => binary(&lhs.0, "^", &rhs.0),
+        Expr::BitOr(lhs, rhs) => binary(&lhs.0, "|", &rhs.0),
+        Expr::And(lhs, rhs) => binary(&lhs.0, "&&", &rhs.0),
+        Expr::Or(lhs, rhs) => binary(&lhs.0, "||", &rhs.0),
+        Expr::Lt(lhs, rhs) => binary(&lhs.0, "<", &rhs.0),
+        Expr::Le(lhs, rhs) => binary(&lhs.0, "<=", &rhs.0),
+        Expr::Gt(lhs, rhs) => binary(&lhs.0, ">", &rhs.0),
+        Expr::Ge(lhs, rhs) => binary(&lhs.0, ">=", &rhs.0),
+        Expr::Eq(lhs, rhs) => binary(&lhs.0, "==", &rhs.0),
+        Expr::Ne(lhs, rhs) => binary(&lhs.0, "!=", &rhs.0),
+        // right associative, so only a conditional as the condition needs parentheses
+        Expr::Cond(cond, then, otherwise) => format!(
+            "{} ? {} : {}",
+            operand(&cond.0, precedence::binding(expr) + 1),
+            self::expr(&then.0),
+            operand(&otherwise.0, precedence::binding(expr))
+        ),
+        Expr::Index(array, index) => format!(
+            "{}[{}]",
+            operand(&array.0, precedence::binding(expr)),
+            self::expr(&index.0)
+        ),
+        Expr::Call { name, params } => {
+            let params = params
+                .iter()
+                .map(|(param, _)| self::expr(param))
+                .collect::<Vec<_>>();
+            format!("{name}({})", params.join(", "))
+        }
+        Expr::Spawn { name, params } => {
+            let call = Expr::Call {
+                name: *name,
+                params: params.clone(),
+            };
+            format!("spawn {}", self::expr(&call))
+        }
+        Expr::Join(handle) => format!("join({})", self::expr(&handle.0)),
+    }
+}
+
+/// Formats `expr`, in parentheses if it binds less tightly than `precedence`.
+fn operand(expr: &Expr, precedence: usize) -> String {
+    match precedence::binding(expr) < precedence {
+        true => format!("({})", self::expr(expr)),
+        false => self::expr(expr),
+    }
+}
@@ -0,0 +1,36 @@
+//! Entry points for fuzzing, which the targets in `fuzz/` call with arbitrary bytes.
+//!
+//! Each one runs part of the compiler on the input and panics only if the compiler breaks one of
+//! its own promises, such as a diagnostic pointing outside of the source, so that any panic a
+//! fuzzer finds is a bug.
+
+use crate::{sources::SourceMap, Diagnostic};
+
+/// Lexes and parses `data` as a file, checking the diagnostics of both.
+pub fn parse(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    if let Err(diagnostics) = crate::parse_tokens(&source) {
+        check(&source, &diagnostics);
+    }
+    if let Err(diagnostics) = crate::parse_ast(&source) {
+        check(&source, &diagnostics);
+    }
+}
+
+/// Asserts that every label of `diagnostics` is within `source`, or just past its end, and that
+/// each can be rendered.
+fn check(source: &str, diagnostics: &[Diagnostic]) {
+    let len = source.chars().count();
+    let mut sources = SourceMap::default();
+    sources.add("fuzz.c", source);
+    for diagnostic in diagnostics {
+        for label in &diagnostic.labels {
+            assert!(
+                label.span.start <= label.span.end && label.span.end <= len + 1,
+                "{:?} is outside of a source of {len} characters in {diagnostic:?}",
+                label.span
+            );
+        }
+        diagnostic.to_json(&sources, "fuzz.c");
+    }
+}
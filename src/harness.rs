@@ -0,0 +1,273 @@
+//! The test runner behind `crust test`, which runs every source file in a directory and checks
+//! how it ends against expectations written in its comments:
+//!
+//! ```c
+//! // expect-exit: 7
+//! // expect-output: hello
+//! // expect-output: world
+//! ```
+//!
+//! `expect-exit` is the exit code the program ends with, and each `expect-output` is the next
+//! line it prints, so that together they're the whole of its output. A file that expects
+//! nothing is skipped. Every file runs in a `crust run` process of its own, several at once, so
+//! a program that crashes or prints can't disturb the others or the runner.
+
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// What a test expects of the program it runs, from its comments.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Expectations {
+    pub exit: Option<i32>,
+    /// The lines of its output, if it expects any
+    pub output: Option<Vec<String>>,
+}
+
+impl Expectations {
+    /// Reads the `// expect-exit:` and `// expect-output:` comments of `source`, failing on an
+    /// exit code that isn't an int or one given twice.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut expect = Self::default();
+        for (i, line) in source.lines().enumerate() {
+            let Some(comment) = line.trim_start().strip_prefix("//") else {
+                continue;
+            };
+            let comment = comment.trim_start();
+            if let Some(code) = comment.strip_prefix("expect-exit:") {
+                let code = code.trim();
+                let code = code
+                    .parse()
+                    .map_err(|_| format!("line {}: `{code}` isn't an exit code", i + 1))?;
+                if expect.exit.replace(code).is_some() {
+                    return Err(format!("line {}: the exit code is expected twice", i + 1));
+                }
+            } else if let Some(text) = comment.strip_prefix("expect-output:") {
+                // one space separates the colon from the line, any more are part of it
+                let text = text.strip_prefix(' ').unwrap_or(text);
+                expect
+                    .output
+                    .get_or_insert_with(Vec::new)
+                    .push(text.to_string());
+            }
+        }
+        Ok(expect)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exit.is_none() && self.output.is_none()
+    }
+
+    /// How a program that exited with `code` after printing `stdout` differs from these
+    /// expectations, as a line for each difference.
+    pub fn check(&self, code: Option<i32>, stdout: &str) -> Vec<String> {
+        let mut differences = Vec::new();
+        if let Some(expected) = self.exit {
+            if code != Some(expected) {
+                let found = code.map_or(String::from("no exit code"), |code| code.to_string());
+                differences.push(format!("expected exit code {expected}, found {found}"));
+            }
+        }
+        if let Some(expected) = &self.output {
+            let found = stdout.lines().collect::<Vec<_>>();
+            if found != *expected {
+                differences.push(String::from("expected output:"));
+                differences.extend(expected.iter().map(|line| format!("  {line}")));
+                differences.push(String::from("found output:"));
+                differences.extend(found.iter().map(|line| format!("  {line}")));
+            }
+        }
+        differences
+    }
+}
+
+/// The source files in `dir` and its subdirectories, in order of their paths.
+pub fn discover(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "c") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// The differences from what the test expected, or why it couldn't run
+    Failed(Vec<String>),
+    /// The test expects nothing
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+    pub time: Duration,
+    /// What the program wrote to stderr, to show when it fails
+    pub stderr: String,
+}
+
+/// Runs the test in `path` with `command`, which runs a source file with `crust run`.
+pub fn run(path: &Path, command: impl Fn(&Path) -> Command) -> Report {
+    let start = Instant::now();
+    let report = |outcome, stderr| Report {
+        path: path.to_path_buf(),
+        outcome,
+        time: start.elapsed(),
+        stderr,
+    };
+
+    let expect = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|source| Expectations::parse(&source))
+    {
+        Ok(expect) if expect.is_empty() => return report(Outcome::Skipped, String::new()),
+        Ok(expect) => expect,
+        Err(e) => return report(Outcome::Failed(vec![e]), String::new()),
+    };
+    let output = match command(path).output() {
+        Ok(output) => output,
+        Err(e) => return report(Outcome::Failed(vec![e.to_string()]), String::new()),
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // `crust run` ends what the program printed with a line saying how it exited, which starts
+    // on the line the program's output ends on if it doesn't end with a newline
+    let stdout = stdout.strip_suffix('\n').unwrap_or(&stdout);
+    let stdout = match stdout.rfind("-- exited with code") {
+        Some(at) if !stdout[at..].contains('\n') => &stdout[..at],
+        _ => stdout,
+    };
+    let differences = expect.check(output.status.code(), stdout);
+    let outcome = match differences.is_empty() {
+        true => Outcome::Passed,
+        false => Outcome::Failed(differences),
+    };
+    report(outcome, String::from_utf8_lossy(&output.stderr).to_string())
+}
+
+/// Runs the tests in `paths` on `jobs` threads at once, returning their reports in the same
+/// order.
+pub fn run_all(
+    paths: &[PathBuf],
+    jobs: usize,
+    command: impl Fn(&Path) -> Command + Sync,
+) -> Vec<Report> {
+    parallel(paths, jobs, |path| run(path, &command))
+}
+
+/// Calls `f` with each of `items` on `jobs` threads at once, returning the results in the same
+/// order as the items.
+pub fn parallel<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else {
+                    break;
+                };
+                let result = f(item);
+                results.lock().unwrap().push((i, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// A table of `reports` with a row for each test, followed by the differences of every test
+/// that failed and a count of how many passed, failed and were skipped.
+pub fn summary(reports: &[Report], dir: &Path) -> String {
+    let name = |report: &Report| {
+        let path = report.path.strip_prefix(dir).unwrap_or(&report.path);
+        path.display().to_string()
+    };
+    let rows = reports
+        .iter()
+        .map(|report| {
+            let result = match report.outcome {
+                Outcome::Passed => "ok",
+                Outcome::Failed(_) => "FAILED",
+                Outcome::Skipped => "skipped",
+            };
+            let ms = report.time.as_secs_f64() * 1000.0;
+            [name(report), result.to_string(), format!("{ms:.1}")]
+        })
+        .collect::<Vec<_>>();
+    let header = ["TEST", "RESULT", "MS"].map(String::from);
+
+    let widths = (0..3)
+        .map(|column| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[column].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let mut text = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        writeln!(
+            text,
+            "{:<w0$}  {:<w1$}  {:>w2$}",
+            row[0],
+            row[1],
+            row[2],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        )
+        .unwrap();
+    }
+
+    for report in reports {
+        if let Outcome::Failed(differences) = &report.outcome {
+            writeln!(text, "\n---- {} ----", name(report)).unwrap();
+            for difference in differences {
+                writeln!(text, "{difference}").unwrap();
+            }
+            if !report.stderr.is_empty() {
+                writeln!(text, "stderr:\n{}", report.stderr.trim_end()).unwrap();
+            }
+        }
+    }
+
+    let count = |outcome: fn(&Outcome) -> bool| {
+        reports
+            .iter()
+            .filter(|report| outcome(&report.outcome))
+            .count()
+    };
+    writeln!(
+        text,
+        "\n{} passed, {} failed, {} skipped",
+        count(|outcome| *outcome == Outcome::Passed),
+        count(|outcome| matches!(outcome, Outcome::Failed(_))),
+        count(|outcome| *outcome == Outcome::Skipped),
+    )
+    .unwrap();
+    text
+}
@@ -0,0 +1,84 @@
+//! Stopping a running program on Ctrl-C, for `crust run`.
+//!
+//! The interpreter checks [`RunOptions::interrupt`](crate::RunOptions) before each statement and
+//! the VM before each call, and once it's set they stop with an [`interrupted`] error, which has
+//! a backtrace of the calls in progress like any other runtime error. A program that doesn't get
+//! that far, as when it's waiting for input, is stopped by pressing Ctrl-C again.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{diagnostics::Diagnostic, messages::Message, token::Span};
+
+/// The exit code of a program stopped by Ctrl-C, which is what shells report for a process killed
+/// by SIGINT.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// The code of the error a program stopped by Ctrl-C fails with.
+pub const INTERRUPTED_CODE: &str = "E0205";
+
+/// Whether the program should stop.
+#[derive(Debug, Default, Clone)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    /// Asks the program to stop before its next statement.
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with an [`interrupted`] error at `span` if the program should stop.
+    pub(crate) fn check(&self, span: &Span) -> Result<(), Diagnostic> {
+        match self.is_set() {
+            true => Err(interrupted(span)),
+            false => Ok(()),
+        }
+    }
+}
+
+/// The error a program fails with when it's stopped before the statement at `span`.
+pub fn interrupted(span: &Span) -> Diagnostic {
+    Diagnostic::error(INTERRUPTED_CODE, Message::new("E0205"))
+        .with_label(span.clone(), Message::new("label.interrupted"))
+}
+
+#[cfg(unix)]
+static CTRL_C: std::sync::OnceLock<Interrupt> = std::sync::OnceLock::new();
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_signal: libc::c_int) {
+    let Some(interrupt) = CTRL_C.get() else {
+        return;
+    };
+    if interrupt.0.swap(true, Ordering::Relaxed) {
+        // SAFETY: `_exit` is async-signal-safe, unlike `exit`
+        unsafe { libc::_exit(INTERRUPTED_EXIT_CODE) };
+    }
+}
+
+/// Handles Ctrl-C for the rest of the process by setting the returned interrupt, and by exiting
+/// if it's already set.
+#[cfg(unix)]
+pub fn on_ctrl_c() -> std::io::Result<Interrupt> {
+    let interrupt = CTRL_C.get_or_init(Interrupt::default).clone();
+    let handler = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only touches atomics and calls `_exit`
+    match unsafe { libc::signal(libc::SIGINT, handler) } {
+        libc::SIG_ERR => Err(std::io::Error::last_os_error()),
+        _ => Ok(interrupt),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn on_ctrl_c() -> std::io::Result<Interrupt> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "handling Ctrl-C is only supported on Unix",
+    ))
+}
@@ -0,0 +1,1550 @@
+//! A mid-level IR of three-address code in basic blocks, lowered from the AST for the compiled
+//! backends.
+//!
+//! Each function has numbered temporaries (`%0`, `%1`, ...) holding ints or strings, arrays
+//! (`&0`, ...) with a fixed length, and a list of basic blocks (`bb0` is the entry). Every
+//! instruction takes its operands from temporaries or constants and writes at most one temporary,
+//! and every block ends in a single [`Terminator`]. Parameters are the first temporaries and each
+//! variable gets a temporary of its own, which is assigned exactly once, so the code is in SSA form:
+//! assigning to a variable again gives it a new temporary. Where control flow joins, an
+//! [`Inst::Phi`] picks the temporary of whichever block control came from.
+//!
+//! Lowering checks everything the backends rely on: that every value has the type it's used as,
+//! that arrays are only indexed, and that called functions exist. Instructions that can fail at
+//! runtime check for it themselves, so [`BinOp::Div`] and [`BinOp::Rem`] fail on division by zero
+//! and
+//! [`Inst::Load`] and [`Inst::Store`] fail on an index out of bounds, exactly as the interpreter
+//! does. [`verify`] checks these invariants on a lowered program, so that `--self-check` can
+//! catch a lowering bug before a backend miscompiles it.
+
+pub mod schema;
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    ast::{self, AssignOp, Definition, Expr, InlineLang, Spanned, Statement},
+    diagnostics::Diagnostic,
+    literal,
+    messages::Message,
+    token::Span,
+    types::{self, TypeTable},
+    Ast, Builtins,
+};
+
+/// Builtins the compiled backends implement, shadowing user functions with the same name just as
+/// they are by the interpreter.
+pub const BUILTINS: [&str; 4] = ["print", "println", "read_int", "len"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ty {
+    Int,
+    Str,
+    /// What a function that returns nothing returns, which no temporary or array has
+    Void,
+}
+
+impl Ty {
+    pub fn of(name: &str) -> Self {
+        match name {
+            "string" => Self::Str,
+            ast::VOID => Self::Void,
+            _ => Self::Int,
+        }
+    }
+}
+
+impl Display for Ty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int => write!(f, "int"),
+            Self::Str => write!(f, "string"),
+            Self::Void => write!(f, "void"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Temp(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArrayId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId(pub usize);
+
+impl Display for Temp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for ArrayId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "&{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Temp(Temp),
+    Int(i32),
+    Str(String),
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Temp(temp) => write!(f, "{temp}"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Str(value) => write!(f, "{}", literal::escape(value)),
+        }
+    }
+}
+
+/// An operation on two ints, with the semantics of the matching function in
+/// [`semantics`](crate::semantics).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    /// Fails at runtime when dividing by zero
+    Div,
+    /// Fails at runtime when dividing by zero
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    /// An arithmetic shift, keeping the sign
+    Shr,
+    /// Signed comparisons, which are 1 if they hold and 0 otherwise
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl BinOp {
+    /// Whether it also takes floats, which only the interpreter and the VM have.
+    pub fn takes_floats(self) -> bool {
+        !matches!(
+            self,
+            Self::Rem | Self::And | Self::Or | Self::Xor | Self::Shl | Self::Shr
+        )
+    }
+}
+
+impl Display for BinOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Add => write!(f, "add"),
+            Self::Sub => write!(f, "sub"),
+            Self::Mul => write!(f, "mul"),
+            Self::Div => write!(f, "div"),
+            Self::Rem => write!(f, "rem"),
+            Self::And => write!(f, "and"),
+            Self::Or => write!(f, "or"),
+            Self::Xor => write!(f, "xor"),
+            Self::Shl => write!(f, "shl"),
+            Self::Shr => write!(f, "shr"),
+            Self::Lt => write!(f, "lt"),
+            Self::Le => write!(f, "le"),
+            Self::Gt => write!(f, "gt"),
+            Self::Ge => write!(f, "ge"),
+            Self::Eq => write!(f, "eq"),
+            Self::Ne => write!(f, "ne"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inst {
+    Copy {
+        dest: Temp,
+        src: Operand,
+    },
+    Neg {
+        dest: Temp,
+        src: Operand,
+    },
+    /// 1 if `src` is 0, and 0 otherwise
+    Not {
+        dest: Temp,
+        src: Operand,
+    },
+    Binary {
+        dest: Temp,
+        op: BinOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// Sets every element of an array to `value`
+    Fill {
+        array: ArrayId,
+        value: Operand,
+    },
+    /// Reads an element of an array, failing if `index` is out of bounds
+    Load {
+        dest: Temp,
+        array: ArrayId,
+        index: Operand,
+    },
+    /// Writes an element of an array, failing if `index` is out of bounds
+    Store {
+        array: ArrayId,
+        index: Operand,
+        value: Operand,
+    },
+    /// Calls a function, writing what it returns to `dest` unless it returns void
+    Call {
+        dest: Option<Temp>,
+        func: String,
+        args: Vec<Operand>,
+    },
+    /// Prints the arguments separated by spaces, then a newline or a flush of stdout
+    Print {
+        args: Vec<Operand>,
+        newline: bool,
+    },
+    ReadInt {
+        dest: Temp,
+    },
+    /// The length of a string
+    StrLen {
+        dest: Temp,
+        src: Operand,
+    },
+    /// Hand-written code from an `__asm` or `__ir` block, which only the backend for `lang` can
+    /// compile. The code can write the variables it names, which changes their temporaries in
+    /// place rather than assigning new ones.
+    Inline {
+        lang: InlineLang,
+        pieces: Vec<InlinePiece>,
+        span: Span,
+    },
+    /// The operand for whichever block of `incoming` control came from. Phis come before any
+    /// other instruction in their block, and the backends implement them by copying the operand
+    /// to `dest` as each incoming block jumps here.
+    Phi {
+        dest: Temp,
+        incoming: Vec<(BlockId, Operand)>,
+    },
+}
+
+/// A piece of the code of an [`Inst::Inline`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InlinePiece {
+    Text(String),
+    /// Where the temporary lives, replacing a variable named in the code
+    Temp(Temp),
+    /// Where the first element of the array lives
+    Array(ArrayId),
+}
+
+impl Display for InlinePiece {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => write!(f, "{text}"),
+            Self::Temp(temp) => write!(f, "{{{temp}}}"),
+            Self::Array(array) => write!(f, "{{{array}}}"),
+        }
+    }
+}
+
+impl Display for Inst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Copy { dest, src } => write!(f, "{dest} = {src}"),
+            Self::Neg { dest, src } => write!(f, "{dest} = neg {src}"),
+            Self::Not { dest, src } => write!(f, "{dest} = not {src}"),
+            Self::Binary { dest, op, lhs, rhs } => write!(f, "{dest} = {op} {lhs}, {rhs}"),
+            Self::Fill { array, value } => write!(f, "fill {array}, {value}"),
+            Self::Load { dest, array, index } => write!(f, "{dest} = load {array}[{index}]"),
+            Self::Store {
+                array,
+                index,
+                value,
+            } => write!(f, "store {array}[{index}], {value}"),
+            Self::Call {
+                dest: Some(dest),
+                func,
+                args,
+            } => write!(f, "{dest} = call {func}({})", join(args)),
+            Self::Call {
+                dest: None,
+                func,
+                args,
+            } => write!(f, "call {func}({})", join(args)),
+            Self::Print { args, newline } => match newline {
+                true => write!(f, "println {}", join(args)),
+                false => write!(f, "print {}", join(args)),
+            },
+            Self::ReadInt { dest } => write!(f, "{dest} = read_int"),
+            Self::StrLen { dest, src } => write!(f, "{dest} = strlen {src}"),
+            Self::Inline { lang, pieces, .. } => {
+                let code = pieces
+                    .iter()
+                    .map(InlinePiece::to_string)
+                    .collect::<String>();
+                write!(f, "{} {}", lang.keyword(), literal::escape(&code))
+            }
+            Self::Phi { dest, incoming } => {
+                let incoming = incoming
+                    .iter()
+                    .map(|(block, value)| format!("[{block}: {value}]"))
+                    .collect::<Vec<_>>();
+                write!(f, "{dest} = phi {}", incoming.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    /// Returns a value, or nothing from a function that returns void
+    Return(Option<Operand>),
+    Jump(BlockId),
+    /// Jumps to `then` if `cond` is a nonzero int, and to `otherwise` if it's zero
+    Branch {
+        cond: Operand,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    /// Fails at runtime because control reached the end of the function without returning
+    MissingReturn,
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Return(Some(value)) => write!(f, "ret {value}"),
+            Self::Return(None) => write!(f, "ret"),
+            Self::Jump(block) => write!(f, "jump {block}"),
+            Self::Branch {
+                cond,
+                then,
+                otherwise,
+            } => write!(f, "branch {cond}, {then}, {otherwise}"),
+            Self::MissingReturn => write!(f, "missing_return"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+/// The type of a temporary, and the variable it holds if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TempInfo {
+    pub ty: Ty,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Array {
+    pub name: String,
+    pub ty: Ty,
+    pub len: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    /// The temporaries holding each parameter, which are always the first ones
+    pub params: Vec<Temp>,
+    pub ret: Ty,
+    pub temps: Vec<TempInfo>,
+    pub arrays: Vec<Array>,
+    pub blocks: Vec<Block>,
+    /// Span of the function's name
+    pub span: Span,
+}
+
+impl Function {
+    pub fn ty(&self, operand: &Operand) -> Ty {
+        match operand {
+            Operand::Temp(temp) => self.temps[temp.0].ty,
+            Operand::Int(_) => Ty::Int,
+            Operand::Str(_) => Ty::Str,
+        }
+    }
+
+    /// The copies to make when `from` jumps to `to`, one for each of the phis `to` starts with.
+    pub fn phi_moves(&self, from: BlockId, to: BlockId) -> Vec<(Temp, &Operand)> {
+        self.blocks[to.0]
+            .insts
+            .iter()
+            .map_while(|inst| match inst {
+                Inst::Phi { dest, incoming } => Some((dest, incoming)),
+                _ => None,
+            })
+            .filter_map(|(dest, incoming)| {
+                incoming
+                    .iter()
+                    .find(|(block, _)| *block == from)
+                    .map(|(_, value)| (*dest, value))
+            })
+            .collect()
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .map(|param| format!("{} {param}", self.temps[param.0].ty))
+            .collect::<Vec<_>>();
+        writeln!(
+            f,
+            "func {}({}) -> {} {{",
+            self.name,
+            params.join(", "),
+            self.ret
+        )?;
+        for (i, temp) in self.temps.iter().enumerate() {
+            if let Some(name) = &temp.name {
+                writeln!(f, "    var {} {} {name}", temp.ty, Temp(i))?;
+            }
+        }
+        for (i, array) in self.arrays.iter().enumerate() {
+            writeln!(
+                f,
+                "    array {}[{}] {} {}",
+                array.ty,
+                array.len,
+                ArrayId(i),
+                array.name
+            )?;
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(i))?;
+            for inst in &block.insts {
+                writeln!(f, "    {inst}")?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub funcs: Vec<Function>,
+}
+
+impl Program {
+    pub fn func(&self, name: &str) -> Option<&Function> {
+        self.funcs.iter().find(|func| func.name == name)
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, func) in self.funcs.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "{func}")?;
+        }
+        Ok(())
+    }
+}
+
+fn join(operands: &[Operand]) -> String {
+    operands
+        .iter()
+        .map(Operand::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks the invariants that lowering guarantees and the backends rely on, returning a
+/// description of each one that `program` breaks.
+///
+/// Every temporary, array and block referenced must exist, parameters must be the first
+/// temporaries, each temporary must be assigned at most once, no temporary or array may be void,
+/// every operand must have the type its instruction expects, calls and returns must match the
+/// signature of the function they call or return from, and phis must start their block and only
+/// name blocks that jump to it.
+pub fn verify(program: &Program) -> Vec<String> {
+    let mut errors = Vec::new();
+    match program.func("main") {
+        Some(main) if main.ret == Ty::Int => {}
+        Some(_) => errors.push(String::from("main doesn't return an int")),
+        None => errors.push(String::from("there is no main function")),
+    }
+
+    for func in &program.funcs {
+        let mut verifier = Verifier {
+            program,
+            func,
+            assigned: vec![false; func.temps.len()],
+            at: String::new(),
+            errors: &mut errors,
+        };
+        verifier.func();
+    }
+    errors
+}
+
+struct Verifier<'a> {
+    program: &'a Program,
+    func: &'a Function,
+    /// Whether each temporary has been assigned yet
+    assigned: Vec<bool>,
+    /// Where in the function is being verified, prefixed to errors
+    at: String,
+    errors: &'a mut Vec<String>,
+}
+
+impl Verifier<'_> {
+    fn error(&mut self, error: String) {
+        self.errors.push(format!("{}: {error}", self.at));
+    }
+
+    fn func(&mut self) {
+        let func = self.func;
+        self.at = format!("in {}", func.name);
+        for (i, param) in func.params.iter().enumerate() {
+            if *param != Temp(i) {
+                self.error(format!("parameter {i} is {param}, not {}", Temp(i)));
+            }
+            self.assign(*param);
+        }
+        if func.blocks.is_empty() {
+            self.error(String::from("there are no blocks"));
+        }
+        for (i, temp) in func.temps.iter().enumerate() {
+            if temp.ty == Ty::Void {
+                self.error(format!("{} is void", Temp(i)));
+            }
+        }
+        for (i, array) in func.arrays.iter().enumerate() {
+            if array.ty == Ty::Void {
+                self.error(format!("{} is void", ArrayId(i)));
+            }
+        }
+
+        for (i, block) in func.blocks.iter().enumerate() {
+            let mut phis = true;
+            for inst in &block.insts {
+                self.at = format!("in {} {}: {inst}", func.name, BlockId(i));
+                self.inst(inst);
+                match inst {
+                    Inst::Phi { incoming, .. } => {
+                        if !phis {
+                            self.error(String::from("follows an instruction that isn't a phi"));
+                        }
+                        for (from, _) in incoming {
+                            self.jumps_to(*from, BlockId(i));
+                        }
+                    }
+                    _ => phis = false,
+                }
+            }
+            self.at = format!("in {} {}: {}", func.name, BlockId(i), block.terminator);
+            self.terminator(&block.terminator);
+        }
+    }
+
+    /// The type of `operand`, or `None` if it's a temporary that doesn't exist.
+    fn operand(&mut self, operand: &Operand) -> Option<Ty> {
+        if let Operand::Temp(temp) = operand {
+            if temp.0 >= self.func.temps.len() {
+                self.error(format!("{temp} doesn't exist"));
+                return None;
+            }
+        }
+        Some(self.func.ty(operand))
+    }
+
+    fn expect(&mut self, operand: &Operand, expected: Ty) {
+        if let Some(found) = self.operand(operand) {
+            if found != expected {
+                self.error(format!("{operand} has type {found}, not {expected}"));
+            }
+        }
+    }
+
+    fn assign(&mut self, temp: Temp) -> Option<Ty> {
+        let Some(assigned) = self.assigned.get_mut(temp.0) else {
+            self.error(format!("{temp} doesn't exist"));
+            return None;
+        };
+        if std::mem::replace(assigned, true) {
+            self.error(format!("{temp} is assigned more than once"));
+        }
+        Some(self.func.temps[temp.0].ty)
+    }
+
+    fn define(&mut self, dest: Temp, ty: Ty) {
+        if let Some(found) = self.assign(dest) {
+            if found != ty {
+                self.error(format!("{dest} has type {found}, not {ty}"));
+            }
+        }
+    }
+
+    fn array(&mut self, array: ArrayId) -> Option<Ty> {
+        match self.func.arrays.get(array.0) {
+            Some(info) => Some(info.ty),
+            None => {
+                self.error(format!("{array} doesn't exist"));
+                None
+            }
+        }
+    }
+
+    fn block(&mut self, block: BlockId) -> bool {
+        let exists = block.0 < self.func.blocks.len();
+        if !exists {
+            self.error(format!("{block} doesn't exist"));
+        }
+        exists
+    }
+
+    /// Checks that `from` ends by jumping to `to`, for a phi in `to`.
+    fn jumps_to(&mut self, from: BlockId, to: BlockId) {
+        if !self.block(from) {
+            return;
+        }
+        let jumps = match &self.func.blocks[from.0].terminator {
+            Terminator::Jump(target) => *target == to,
+            Terminator::Branch {
+                then, otherwise, ..
+            } => *then == to || *otherwise == to,
+            Terminator::Return(_) | Terminator::MissingReturn => false,
+        };
+        if !jumps {
+            self.error(format!("{from} doesn't jump to {to}"));
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy { dest, src } => {
+                if let Some(ty) = self.operand(src) {
+                    self.define(*dest, ty);
+                }
+            }
+            Inst::Neg { dest, src } | Inst::Not { dest, src } => {
+                self.expect(src, Ty::Int);
+                self.define(*dest, Ty::Int);
+            }
+            Inst::Binary { dest, lhs, rhs, .. } => {
+                self.expect(lhs, Ty::Int);
+                self.expect(rhs, Ty::Int);
+                self.define(*dest, Ty::Int);
+            }
+            Inst::Fill { array, value } => {
+                if let Some(ty) = self.array(*array) {
+                    self.expect(value, ty);
+                }
+            }
+            Inst::Load { dest, array, index } => {
+                self.expect(index, Ty::Int);
+                if let Some(ty) = self.array(*array) {
+                    self.define(*dest, ty);
+                }
+            }
+            Inst::Store {
+                array,
+                index,
+                value,
+            } => {
+                self.expect(index, Ty::Int);
+                if let Some(ty) = self.array(*array) {
+                    self.expect(value, ty);
+                }
+            }
+            Inst::Call { dest, func, args } => {
+                let Some(callee) = self.program.func(func) else {
+                    self.error(format!("there is no function {func}"));
+                    return;
+                };
+                if args.len() != callee.params.len() {
+                    self.error(format!(
+                        "{func} takes {} arguments but {} were supplied",
+                        callee.params.len(),
+                        args.len()
+                    ));
+                }
+                for (arg, param) in args.iter().zip(&callee.params) {
+                    if let Some(info) = callee.temps.get(param.0) {
+                        self.expect(arg, info.ty);
+                    }
+                }
+                match (dest, callee.ret) {
+                    (None, Ty::Void) => {}
+                    (Some(_), Ty::Void) => self.error(format!("{func} returns void")),
+                    (None, ret) => self.error(format!("the {ret} {func} returns is dropped")),
+                    (Some(dest), ret) => self.define(*dest, ret),
+                }
+            }
+            Inst::Print { args, .. } => {
+                for arg in args {
+                    self.operand(arg);
+                }
+            }
+            Inst::ReadInt { dest } => self.define(*dest, Ty::Int),
+            Inst::StrLen { dest, src } => {
+                self.expect(src, Ty::Str);
+                self.define(*dest, Ty::Int);
+            }
+            Inst::Phi { dest, incoming } => {
+                let Some(ty) = self.assign(*dest) else {
+                    return;
+                };
+                for (_, value) in incoming {
+                    self.expect(value, ty);
+                }
+            }
+            Inst::Inline { pieces, .. } => {
+                for piece in pieces {
+                    match piece {
+                        InlinePiece::Text(_) => {}
+                        InlinePiece::Temp(temp) => {
+                            self.operand(&Operand::Temp(*temp));
+                        }
+                        InlinePiece::Array(array) => {
+                            self.array(*array);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn terminator(&mut self, terminator: &Terminator) {
+        match terminator {
+            Terminator::Return(Some(value)) if self.func.ret != Ty::Void => {
+                self.expect(value, self.func.ret)
+            }
+            Terminator::Return(Some(_)) => self.error(String::from("returns a value from void")),
+            Terminator::Return(None) if self.func.ret != Ty::Void => {
+                self.error(format!("returns nothing instead of {}", self.func.ret))
+            }
+            Terminator::Return(None) => {}
+            Terminator::Jump(block) => {
+                self.block(*block);
+            }
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.expect(cond, Ty::Int);
+                self.block(*then);
+                self.block(*otherwise);
+            }
+            Terminator::MissingReturn => {}
+        }
+    }
+}
+
+/// Lowers every function in `ast`, which must have an int returning `main`.
+pub fn lower(ast: &Ast) -> Result<Program, Diagnostic> {
+    let funcs = ast
+        .defs
+        .iter()
+        .filter_map(|def| match def {
+            Definition::Func(func) => Some((func.name.as_str(), func)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let types = TypeTable::new(ast).map_err(|mut errors| errors.remove(0))?;
+    if let Some(Definition::Global(global)) = ast
+        .defs
+        .iter()
+        .find(|def| matches!(def, Definition::Global(_)))
+    {
+        return Err(unsupported(&global.span, Message::new("feature.globals")));
+    }
+
+    let Some(main) = funcs.get("main") else {
+        return Err(Diagnostic::error("E0400", Message::new("main-not-found")));
+    };
+    if Ty::of(&main.ret) != Ty::Int {
+        return Err(
+            Diagnostic::error("E0400", Message::new("E0400.main-return"))
+                .with_label(main.span.clone(), Message::new("label.main-defined")),
+        );
+    }
+
+    let funcs = ast
+        .defs
+        .iter()
+        .filter_map(|def| match def {
+            Definition::Func(func) => Some(Lowering::func(&funcs, &types, func)),
+            _ => None,
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Program { funcs })
+}
+
+/// A variable in scope while lowering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Var {
+    Scalar(Temp),
+    Array(ArrayId),
+}
+
+/// Variables in scope, later declarations shadowing earlier ones.
+type Scope<'a> = Vec<(&'a str, Var)>;
+
+/// The state of the function currently being lowered.
+struct Lowering<'a> {
+    funcs: &'a HashMap<&'a str, &'a ast::Func>,
+    /// The layout of every struct, which `sizeof` is folded with
+    types: &'a TypeTable,
+    func: Function,
+    scope: Scope<'a>,
+    /// Instructions of the block being built, which is numbered `func.blocks.len()`
+    insts: Vec<Inst>,
+    /// The blocks ending in a `break` in each switch being lowered, innermost last, with the
+    /// variables in scope at each, so that their jumps can be pointed at the end of the switch
+    breaks: Vec<Vec<(BlockId, Scope<'a>)>>,
+}
+
+impl<'a> Lowering<'a> {
+    fn func(
+        funcs: &'a HashMap<&'a str, &'a ast::Func>,
+        types: &'a TypeTable,
+        func: &'a ast::Func,
+    ) -> Result<Function, Diagnostic> {
+        if ast::is_pointer(&func.ret) {
+            return Err(unsupported(&func.span, Message::new("feature.pointers")));
+        }
+        if crate::types::is_float(&func.ret) {
+            return Err(unsupported(&func.span, Message::new("feature.floats")));
+        }
+        let mut lowering = Self {
+            funcs,
+            types,
+            func: Function {
+                name: func.name.to_string(),
+                params: Vec::new(),
+                ret: Ty::of(&func.ret),
+                temps: Vec::new(),
+                arrays: Vec::new(),
+                blocks: Vec::new(),
+                span: func.span.clone(),
+            },
+            scope: Vec::new(),
+            insts: Vec::new(),
+            breaks: Vec::new(),
+        };
+
+        for param in &func.params {
+            let temp = lowering.temp(var_ty(&param.ty, &param.span)?, Some(&param.name));
+            lowering.func.params.push(temp);
+            lowering.scope.push((&param.name, Var::Scalar(temp)));
+        }
+        for statement in &func.body {
+            lowering.statement(statement)?;
+        }
+        match lowering.func.ret {
+            Ty::Void => lowering.terminate(Terminator::Return(None)),
+            _ => lowering.terminate(Terminator::MissingReturn),
+        };
+        Ok(lowering.func)
+    }
+
+    fn temp(&mut self, ty: Ty, name: Option<&str>) -> Temp {
+        self.func.temps.push(TempInfo {
+            ty,
+            name: name.map(str::to_string),
+        });
+        Temp(self.func.temps.len() - 1)
+    }
+
+    /// Ends the current block with `terminator` and starts the next one.
+    fn terminate(&mut self, terminator: Terminator) -> BlockId {
+        self.func.blocks.push(Block {
+            insts: std::mem::take(&mut self.insts),
+            terminator,
+        });
+        BlockId(self.func.blocks.len())
+    }
+
+    fn var(&self, name: &str, span: &Span) -> Result<Var, Diagnostic> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(var, _)| *var == name)
+            .map(|(_, var)| *var)
+            .ok_or_else(|| {
+                Diagnostic::error(
+                    "E0400",
+                    Message::new("undeclared-variable").arg("name", name),
+                )
+                .with_label(span.clone(), Message::new("label.used-here"))
+            })
+    }
+
+    /// The temporary holding the scalar variable `name`, and its type.
+    fn scalar(&self, name: &str, span: &Span) -> Result<(Temp, Ty), Diagnostic> {
+        match self.var(name, span)? {
+            Var::Scalar(temp) => Ok((temp, self.func.temps[temp.0].ty)),
+            Var::Array(_) => Err(unsupported(span, Message::new("feature.assign-array"))),
+        }
+    }
+
+    /// Gives the variable `name` a new temporary holding its new value, writing it with `inst`.
+    fn reassign(&mut self, name: &str, ty: Ty, inst: impl FnOnce(Temp) -> Inst) -> Temp {
+        let dest = self.temp(ty, Some(name));
+        self.insts.push(inst(dest));
+        if let Some((_, var)) = self.scope.iter_mut().rev().find(|(var, _)| *var == name) {
+            *var = Var::Scalar(dest);
+        }
+        dest
+    }
+
+    fn array(&self, name: &str, span: &Span) -> Result<ArrayId, Diagnostic> {
+        match self.var(name, span)? {
+            Var::Array(array) => Ok(array),
+            Var::Scalar(_) => Err(unsupported(span, Message::new("feature.index-non-array"))),
+        }
+    }
+
+    fn statement(&mut self, (statement, span): &'a Spanned<Statement>) -> Result<(), Diagnostic> {
+        match statement {
+            Statement::Invalid => {
+                return Err(unsupported(
+                    span,
+                    Message::new("feature.invalid-statements"),
+                ))
+            }
+            Statement::Expand { .. } => {
+                return Err(unsupported(span, Message::new("feature.unexpanded-macros")))
+            }
+            Statement::Write { .. } => {
+                return Err(unsupported(span, Message::new("feature.pointers")))
+            }
+            Statement::Inline { lang, code } => {
+                let pieces = ast::inline_pieces(code)
+                    .into_iter()
+                    .map(|piece| match piece {
+                        ast::InlinePiece::Text(text) => Ok(InlinePiece::Text(text.to_string())),
+                        ast::InlinePiece::Var(name) => match self.var(name, span)? {
+                            Var::Scalar(temp) => Ok(InlinePiece::Temp(temp)),
+                            Var::Array(array) => Ok(InlinePiece::Array(array)),
+                        },
+                    })
+                    .collect::<Result<_, Diagnostic>>()?;
+                self.insts.push(Inst::Inline {
+                    lang: *lang,
+                    pieces,
+                    span: span.clone(),
+                });
+            }
+            Statement::Return(expr) => {
+                let value = self.expect(expr, self.func.ret)?;
+                // anything after a return is unreachable, but is still lowered into a block
+                self.terminate(Terminator::Return(Some(value)));
+            }
+            Statement::ReturnVoid => {
+                self.terminate(Terminator::Return(None));
+            }
+            Statement::Expr(expr) => match &expr.0 {
+                Expr::Call { name, params } if self.returns_void(name) => {
+                    let args = self.args(name, params, &expr.1)?;
+                    self.insts.push(Inst::Call {
+                        dest: None,
+                        func: name.to_string(),
+                        args,
+                    });
+                }
+                _ => {
+                    self.expr(expr)?;
+                }
+            },
+            Statement::Assign { ty, name, expr } => {
+                let ty = var_ty(ty, span)?;
+                let src = self.expect(expr, ty)?;
+                let dest = self.temp(ty, Some(name));
+                self.insts.push(Inst::Copy { dest, src });
+                self.scope.push((name, Var::Scalar(dest)));
+            }
+            Statement::Array { ty, name, len } => {
+                let ty = var_ty(ty, span)?;
+                self.func.arrays.push(Array {
+                    name: name.to_string(),
+                    ty,
+                    len: *len,
+                });
+                let array = ArrayId(self.func.arrays.len() - 1);
+                let value = match ty {
+                    Ty::Int => Operand::Int(0),
+                    Ty::Str => Operand::Str(String::new()),
+                    Ty::Void => unreachable!("variables can't be void"),
+                };
+                self.insts.push(Inst::Fill { array, value });
+                self.scope.push((name, Var::Array(array)));
+            }
+            Statement::Store { name, index, expr } => {
+                let array = self.array(name, span)?;
+                let index = self.expect(index, Ty::Int)?;
+                let value = self.expect(expr, self.func.arrays[array.0].ty)?;
+                self.insts.push(Inst::Store {
+                    array,
+                    index,
+                    value,
+                });
+            }
+            Statement::Reassign { name, op, expr } => {
+                let (temp, ty) = self.scalar(name, span)?;
+                let op = match op {
+                    AssignOp::Set => {
+                        let src = self.expect(expr, ty)?;
+                        self.reassign(name, ty, |dest| Inst::Copy { dest, src });
+                        return Ok(());
+                    }
+                    AssignOp::Add => BinOp::Add,
+                    AssignOp::Sub => BinOp::Sub,
+                    AssignOp::Mul => BinOp::Mul,
+                    AssignOp::Div => BinOp::Div,
+                };
+                if ty != Ty::Int {
+                    let feature = match op {
+                        BinOp::Add => Message::new("feature.string-concatenation"),
+                        _ => Message::new("feature.type-mismatch")
+                            .arg("found", ty.to_string())
+                            .arg("expected", Ty::Int.to_string()),
+                    };
+                    return Err(unsupported(span, feature));
+                }
+                let rhs = self.expect(expr, Ty::Int)?;
+                self.reassign(name, Ty::Int, |dest| Inst::Binary {
+                    dest,
+                    op,
+                    lhs: Operand::Temp(temp),
+                    rhs,
+                });
+            }
+            Statement::Switch { expr, cases } => self.switch(expr, cases)?,
+            Statement::Break => {
+                let block = BlockId(self.func.blocks.len());
+                let scope = self.scope.clone();
+                let Some(breaks) = self.breaks.last_mut() else {
+                    return Err(unsupported(span, Message::new("feature.break")));
+                };
+                breaks.push((block, scope));
+                // pointed at the end of the switch once it's known
+                self.terminate(Terminator::Jump(block));
+            }
+            Statement::Continue => {
+                return Err(unsupported(span, Message::new("feature.continue")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Lowers a switch to a chain of blocks comparing the int with the value of each case in
+    /// turn, branching to the first block of the case that matches, and the blocks of the cases
+    /// in order, each jumping to the next where it falls through.
+    fn switch(&mut self, expr: &Spanned<Expr>, cases: &'a [ast::Case]) -> Result<(), Diagnostic> {
+        let value = self.expect(expr, Ty::Int)?;
+        let entry = self.scope.clone();
+        let default = cases.iter().position(|case| case.value.is_none());
+
+        // the branches to each case are pointed at its first block once it's known
+        let mut dispatch = vec![None; cases.len()];
+        for (i, case) in cases.iter().enumerate() {
+            let Some(label) = case.value else {
+                continue;
+            };
+            let cond = self.temp(Ty::Int, None);
+            self.insts.push(Inst::Binary {
+                dest: cond,
+                op: BinOp::Eq,
+                lhs: value.clone(),
+                rhs: Operand::Int(label as i32),
+            });
+            let block = BlockId(self.func.blocks.len());
+            dispatch[i] = Some(block);
+            self.terminate(Terminator::Branch {
+                cond: Operand::Temp(cond),
+                then: block,
+                otherwise: BlockId(block.0 + 1),
+            });
+        }
+        let otherwise = BlockId(self.func.blocks.len());
+        if let Some(default) = default {
+            dispatch[default] = Some(otherwise);
+        }
+        self.terminate(Terminator::Jump(otherwise));
+
+        self.breaks.push(Vec::new());
+        for (i, case) in cases.iter().enumerate() {
+            // the first case starts in the block after the dispatch, which nothing falls into
+            let start = match i {
+                0 => BlockId(self.func.blocks.len()),
+                _ => {
+                    let end = BlockId(self.func.blocks.len());
+                    let scope = std::mem::replace(&mut self.scope, entry.clone());
+                    let start = self.terminate(Terminator::Jump(BlockId(end.0 + 1)));
+                    let mut incoming = vec![(end, scope)];
+                    incoming.extend(dispatch[i].map(|block| (block, entry.clone())));
+                    self.merge(&incoming);
+                    start
+                }
+            };
+            if let Some(block) = dispatch[i] {
+                self.retarget(block, start);
+            }
+            for statement in &case.body {
+                self.statement(statement)?;
+            }
+        }
+
+        let mut incoming = self.breaks.pop().unwrap();
+        incoming.push((BlockId(self.func.blocks.len()), self.scope.clone()));
+        if default.is_none() {
+            incoming.push((otherwise, entry.clone()));
+        }
+        self.scope = entry;
+        let end = self.terminate(Terminator::Jump(BlockId(self.func.blocks.len() + 1)));
+        for (block, _) in &incoming {
+            self.retarget(*block, end);
+        }
+        self.merge(&incoming);
+        Ok(())
+    }
+
+    /// Points the jump that ends `block`, or the `then` of its branch, at `target`.
+    fn retarget(&mut self, block: BlockId, target: BlockId) {
+        match &mut self.func.blocks[block.0].terminator {
+            Terminator::Jump(to) | Terminator::Branch { then: to, .. } => *to = target,
+            _ => {}
+        }
+    }
+
+    /// Starts the block being built with a phi for every variable in scope whose temporary
+    /// differs between the blocks in `incoming`, which jump to it with the variables in scope
+    /// at each, and puts the phis in scope.
+    fn merge(&mut self, incoming: &[(BlockId, Scope<'a>)]) {
+        for i in 0..self.scope.len() {
+            let temps = incoming
+                .iter()
+                .filter_map(|(block, scope)| match scope[i].1 {
+                    Var::Scalar(temp) => Some((*block, temp)),
+                    Var::Array(_) => None,
+                })
+                .collect::<Vec<_>>();
+            let Some(&(_, first)) = temps.first() else {
+                continue;
+            };
+            if temps.iter().all(|(_, temp)| *temp == first) {
+                self.scope[i].1 = Var::Scalar(first);
+                continue;
+            }
+            let name = self.scope[i].0;
+            let dest = self.temp(self.func.temps[first.0].ty, Some(name));
+            self.insts.push(Inst::Phi {
+                dest,
+                incoming: temps
+                    .into_iter()
+                    .map(|(block, temp)| (block, Operand::Temp(temp)))
+                    .collect(),
+            });
+            self.scope[i].1 = Var::Scalar(dest);
+        }
+    }
+
+    /// Lowers `expr`, requiring it to have type `ty`.
+    fn expect(&mut self, expr: &Spanned<Expr>, ty: Ty) -> Result<Operand, Diagnostic> {
+        let (value, found) = self.expr(expr)?;
+        if found != ty {
+            return Err(unsupported(
+                &expr.1,
+                Message::new("feature.type-mismatch")
+                    .arg("found", found.to_string())
+                    .arg("expected", ty.to_string()),
+            ));
+        }
+        Ok(value)
+    }
+
+    fn binary(
+        &mut self,
+        op: BinOp,
+        lhs: &Spanned<Expr>,
+        rhs: &Spanned<Expr>,
+    ) -> Result<(Operand, Ty), Diagnostic> {
+        let lhs = self.expect(lhs, Ty::Int)?;
+        let rhs = self.expect(rhs, Ty::Int)?;
+        let dest = self.temp(Ty::Int, None);
+        self.insts.push(Inst::Binary { dest, op, lhs, rhs });
+        Ok((Operand::Temp(dest), Ty::Int))
+    }
+
+    /// Lowers `expr`, returning the operand holding its value and its type.
+    fn expr(&mut self, (expr, span): &Spanned<Expr>) -> Result<(Operand, Ty), Diagnostic> {
+        match expr {
+            Expr::Err => Err(unsupported(
+                span,
+                Message::new("feature.invalid-expressions"),
+            )),
+            Expr::Int(value) => Ok((Operand::Int(*value as i32), Ty::Int)),
+            Expr::Str(value) => Ok((Operand::Str(value.clone()), Ty::Str)),
+            Expr::Float(_) => Err(unsupported(span, Message::new("feature.floats"))),
+            Expr::SizeOf(ty) => match self.types.size_of(ty) {
+                Ok(size) => Ok((Operand::Int(size as i32), Ty::Int)),
+                Err(error) => Err(error.with_label(span.clone(), Message::new("label.sized-here"))),
+            },
+            Expr::AddrOf(_) | Expr::Deref(_) => {
+                Err(unsupported(span, Message::new("feature.pointers")))
+            }
+            Expr::Spawn { .. } | Expr::Join(_) => {
+                Err(unsupported(span, Message::new("feature.threads")))
+            }
+            Expr::Neg(expr) => {
+                let src = self.expect(expr, Ty::Int)?;
+                let dest = self.temp(Ty::Int, None);
+                self.insts.push(Inst::Neg { dest, src });
+                Ok((Operand::Temp(dest), Ty::Int))
+            }
+            Expr::Not(expr) => {
+                let src = self.expect(expr, Ty::Int)?;
+                let dest = self.temp(Ty::Int, None);
+                self.insts.push(Inst::Not { dest, src });
+                Ok((Operand::Temp(dest), Ty::Int))
+            }
+            Expr::PreInc(name) | Expr::PreDec(name) => {
+                let (temp, ty) = self.scalar(name, span)?;
+                if ty != Ty::Int {
+                    return Err(unsupported(
+                        span,
+                        Message::new("feature.type-mismatch")
+                            .arg("found", ty.to_string())
+                            .arg("expected", Ty::Int.to_string()),
+                    ));
+                }
+                let op = match expr {
+                    Expr::PreInc(_) => BinOp::Add,
+                    _ => BinOp::Sub,
+                };
+                let dest = self.reassign(name, Ty::Int, |dest| Inst::Binary {
+                    dest,
+                    op,
+                    lhs: Operand::Temp(temp),
+                    rhs: Operand::Int(1),
+                });
+                Ok((Operand::Temp(dest), Ty::Int))
+            }
+            Expr::Add(lhs, rhs) => {
+                let (lhs, lhs_ty) = self.expr(lhs)?;
+                let (rhs, rhs_ty) = self.expr(rhs)?;
+                if lhs_ty == Ty::Str || rhs_ty == Ty::Str {
+                    return Err(unsupported(
+                        span,
+                        Message::new("feature.string-concatenation"),
+                    ));
+                }
+                let dest = self.temp(Ty::Int, None);
+                self.insts.push(Inst::Binary {
+                    dest,
+                    op: BinOp::Add,
+                    lhs,
+                    rhs,
+                });
+                Ok((Operand::Temp(dest), Ty::Int))
+            }
+            Expr::Sub(lhs, rhs) => self.binary(BinOp::Sub, lhs, rhs),
+            Expr::Mul(lhs, rhs) => self.binary(BinOp::Mul, lhs, rhs),
+            Expr::Div(lhs, rhs) => self.binary(BinOp::Div, lhs, rhs),
+            Expr::Rem(lhs, rhs) => self.binary(BinOp::Rem, lhs, rhs),
+            Expr::BitAnd(lhs, rhs) => self.binary(BinOp::And, lhs, rhs),
+            Expr::BitOr(lhs, rhs) => self.binary(BinOp::Or, lhs, rhs),
+            Expr::BitXor(lhs, rhs) => self.binary(BinOp::Xor, lhs, rhs),
+            Expr::Shl(lhs, rhs) => self.binary(BinOp::Shl, lhs, rhs),
+            Expr::Shr(lhs, rhs) => self.binary(BinOp::Shr, lhs, rhs),
+            Expr::Lt(lhs, rhs) => self.binary(BinOp::Lt, lhs, rhs),
+            Expr::Le(lhs, rhs) => self.binary(BinOp::Le, lhs, rhs),
+            Expr::Gt(lhs, rhs) => self.binary(BinOp::Gt, lhs, rhs),
+            Expr::Ge(lhs, rhs) => self.binary(BinOp::Ge, lhs, rhs),
+            Expr::Eq(lhs, rhs) => self.binary(BinOp::Eq, lhs, rhs),
+            Expr::Ne(lhs, rhs) => self.binary(BinOp::Ne, lhs, rhs),
+            Expr::Cond(cond, then, otherwise) => self.cond(cond, then, otherwise),
+            Expr::And(lhs, rhs) => self.logical(true, lhs, rhs),
+            Expr::Or(lhs, rhs) => self.logical(false, lhs, rhs),
+            Expr::Var(name) => match self.var(name, span)? {
+                Var::Scalar(temp) => Ok((Operand::Temp(temp), self.func.temps[temp.0].ty)),
+                Var::Array(_) => Err(unsupported(span, Message::new("feature.array-as-value"))),
+            },
+            Expr::Index(array, index) => {
+                let Expr::Var(name) = &array.0 else {
+                    return Err(unsupported(
+                        span,
+                        Message::new("feature.index-non-variable"),
+                    ));
+                };
+                let array = self.array(name, span)?;
+                let index = self.expect(index, Ty::Int)?;
+                let ty = self.func.arrays[array.0].ty;
+                let dest = self.temp(ty, None);
+                self.insts.push(Inst::Load { dest, array, index });
+                Ok((Operand::Temp(dest), ty))
+            }
+            Expr::Call { name, params } => {
+                if BUILTINS.contains(&name.as_str()) {
+                    return self.builtin(name, params, span);
+                }
+                if !self.funcs.contains_key(name.as_str()) && Builtins::default().contains(name) {
+                    return Err(unsupported(
+                        span,
+                        Message::new("feature.builtin").arg("name", name.as_str()),
+                    ));
+                }
+                if self.returns_void(name) {
+                    return Err(unsupported(span, Message::new("feature.void-value")));
+                }
+
+                let args = self.args(name, params, span)?;
+                let ret = Ty::of(&self.funcs[name.as_str()].ret);
+                let dest = self.temp(ret, None);
+                self.insts.push(Inst::Call {
+                    dest: Some(dest),
+                    func: name.to_string(),
+                    args,
+                });
+                Ok((Operand::Temp(dest), ret))
+            }
+        }
+    }
+
+    /// Lowers `cond ? then : otherwise` to a branch to a block for each, which both jump to a
+    /// block that starts with a phi for the value and one for each variable either changes.
+    fn cond(
+        &mut self,
+        cond: &Spanned<Expr>,
+        then: &Spanned<Expr>,
+        otherwise: &Spanned<Expr>,
+    ) -> Result<(Operand, Ty), Diagnostic> {
+        let cond = self.expect(cond, Ty::Int)?;
+        // either branch can start blocks of its own, so the targets of the jumps to and from
+        // them are patched once they're known
+        let branch = self.func.blocks.len();
+        let then_start = self.terminate(Terminator::Branch {
+            cond,
+            then: BlockId(branch + 1),
+            otherwise: BlockId(branch + 1),
+        });
+        let scope = self.scope.clone();
+
+        let (then_value, ty) = self.expr(then)?;
+        let then_end = BlockId(self.func.blocks.len());
+        let then_scope = std::mem::replace(&mut self.scope, scope);
+        let otherwise_start = self.terminate(Terminator::Jump(then_start));
+        if let Terminator::Branch { otherwise, .. } = &mut self.func.blocks[branch].terminator {
+            *otherwise = otherwise_start;
+        }
+
+        let otherwise_value = self.expect(otherwise, ty)?;
+        let otherwise_end = BlockId(self.func.blocks.len());
+        let join = self.terminate(Terminator::Jump(BlockId(otherwise_end.0 + 1)));
+        self.func.blocks[then_end.0].terminator = Terminator::Jump(join);
+
+        let dest = self.temp(ty, None);
+        self.insts.push(Inst::Phi {
+            dest,
+            incoming: vec![(then_end, then_value), (otherwise_end, otherwise_value)],
+        });
+        for (i, then_var) in then_scope.into_iter().enumerate() {
+            let (name, otherwise_var) = self.scope[i];
+            if let (Var::Scalar(then_temp), Var::Scalar(otherwise_temp)) =
+                (then_var.1, otherwise_var)
+            {
+                if then_temp != otherwise_temp {
+                    let var = self.temp(self.func.temps[then_temp.0].ty, Some(name));
+                    self.insts.push(Inst::Phi {
+                        dest: var,
+                        incoming: vec![
+                            (then_end, Operand::Temp(then_temp)),
+                            (otherwise_end, Operand::Temp(otherwise_temp)),
+                        ],
+                    });
+                    self.scope[i].1 = Var::Scalar(var);
+                }
+            }
+        }
+        Ok((Operand::Temp(dest), ty))
+    }
+
+    /// Lowers `lhs && rhs`, or `lhs || rhs` if not `and`, to a branch past a block for `rhs` when
+    /// `lhs` decides the value, to a block that starts with a phi for the value and one for each
+    /// variable `rhs` changes.
+    fn logical(
+        &mut self,
+        and: bool,
+        lhs: &Spanned<Expr>,
+        rhs: &Spanned<Expr>,
+    ) -> Result<(Operand, Ty), Diagnostic> {
+        let cond = self.expect(lhs, Ty::Int)?;
+        // the block skipping `rhs` is patched once it's known
+        let branch = BlockId(self.func.blocks.len());
+        let rhs_start = self.terminate(Terminator::Branch {
+            cond,
+            then: BlockId(branch.0 + 1),
+            otherwise: BlockId(branch.0 + 1),
+        });
+        let scope = self.scope.clone();
+
+        let value = self.expect(rhs, Ty::Int)?;
+        let rhs_value = self.temp(Ty::Int, None);
+        self.insts.push(Inst::Binary {
+            dest: rhs_value,
+            op: BinOp::Ne,
+            lhs: value,
+            rhs: Operand::Int(0),
+        });
+        let rhs_end = BlockId(self.func.blocks.len());
+        let rhs_scope = self.scope.clone();
+        let join = self.terminate(Terminator::Jump(BlockId(rhs_end.0 + 1)));
+        if let Terminator::Branch {
+            then, otherwise, ..
+        } = &mut self.func.blocks[branch.0].terminator
+        {
+            match and {
+                true => (*then, *otherwise) = (rhs_start, join),
+                false => (*then, *otherwise) = (join, rhs_start),
+            }
+        }
+
+        let dest = self.temp(Ty::Int, None);
+        self.insts.push(Inst::Phi {
+            dest,
+            incoming: vec![
+                (branch, Operand::Int(i32::from(!and))),
+                (rhs_end, Operand::Temp(rhs_value)),
+            ],
+        });
+        self.merge(&[(branch, scope), (rhs_end, rhs_scope)]);
+        Ok((Operand::Temp(dest), Ty::Int))
+    }
+
+    /// Whether `name` is a user function that returns void.
+    fn returns_void(&self, name: &str) -> bool {
+        !BUILTINS.contains(&name)
+            && self
+                .funcs
+                .get(name)
+                .is_some_and(|func| Ty::of(&func.ret) == Ty::Void)
+    }
+
+    /// Lowers the arguments `params` of a call to the user function `name` at `span`.
+    fn args(
+        &mut self,
+        name: &str,
+        params: &[Spanned<Expr>],
+        span: &Span,
+    ) -> Result<Vec<Operand>, Diagnostic> {
+        let Some(func) = self.funcs.get(name).copied() else {
+            return Err(Diagnostic::error(
+                "E0400",
+                Message::new("unknown-function").arg("name", name),
+            )
+            .with_label(span.clone(), Message::new("label.called-here")));
+        };
+        if params.len() != func.params.len() {
+            return Err(Diagnostic::error(
+                "E0400",
+                Message::new("arity")
+                    .arg("name", name)
+                    .arg("expected", func.params.len().to_string())
+                    .arg("found", params.len().to_string()),
+            )
+            .with_label(span.clone(), Message::new("label.called-here")));
+        }
+
+        params
+            .iter()
+            .zip(&func.params)
+            .map(|(expr, param)| self.expect(expr, Ty::of(&param.ty)))
+            .collect()
+    }
+
+    fn builtin(
+        &mut self,
+        name: &str,
+        params: &[Spanned<Expr>],
+        span: &Span,
+    ) -> Result<(Operand, Ty), Diagnostic> {
+        match name {
+            "print" | "println" => {
+                // every argument is evaluated before anything is printed
+                let args = params
+                    .iter()
+                    .map(|param| self.expr(param).map(|(value, _)| value))
+                    .collect::<Result<_, _>>()?;
+                self.insts.push(Inst::Print {
+                    args,
+                    newline: name == "println",
+                });
+                Ok((Operand::Int(0), Ty::Int))
+            }
+            "read_int" => {
+                let dest = self.temp(Ty::Int, None);
+                self.insts.push(Inst::ReadInt { dest });
+                Ok((Operand::Temp(dest), Ty::Int))
+            }
+            "len" => {
+                let [param] = params else {
+                    return Err(unsupported(span, Message::new("feature.len-arity")));
+                };
+                if let Expr::Var(name) = &param.0 {
+                    if let Var::Array(array) = self.var(name, &param.1)? {
+                        let len = self.func.arrays[array.0].len;
+                        return Ok((Operand::Int(len as i32), Ty::Int));
+                    }
+                }
+
+                let src = self.expect(param, Ty::Str)?;
+                let dest = self.temp(Ty::Int, None);
+                self.insts.push(Inst::StrLen { dest, src });
+                Ok((Operand::Temp(dest), Ty::Int))
+            }
+            _ => unreachable!("{name} is not a compiled builtin"),
+        }
+    }
+}
+
+/// The type of a variable or parameter declared with the type `name` at `span`.
+fn var_ty(name: &str, span: &Span) -> Result<Ty, Diagnostic> {
+    if ast::is_pointer(name) {
+        return Err(unsupported(span, Message::new("feature.pointers")));
+    }
+    if types::is_float(name) {
+        return Err(unsupported(span, Message::new("feature.floats")));
+    }
+    match Ty::of(name) {
+        Ty::Void => Err(unsupported(span, Message::new("feature.void-variable"))),
+        ty => Ok(ty),
+    }
+}
+
+fn unsupported(span: &Span, feature: Message) -> Diagnostic {
+    Diagnostic::error(
+        "E0400",
+        Message::new("E0400.unsupported").arg("feature", feature),
+    )
+    .with_label(span.clone(), Message::new("label.used-here"))
+    .with_note(Message::new("note.use-interpreter"))
+}
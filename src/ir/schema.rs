@@ -0,0 +1,657 @@
+//! The layout of IR files, as serde types of their own.
+//!
+//! The [typed IR](crate::typed) is what the compiler works with, and these types are what it
+//! writes: an IR file is an [`Artifact`], converted from and to the typed IR with `From`. Keeping
+//! them apart means the typed IR can be refactored freely, and only a change to this module
+//! changes the files the compiler writes, which is when [`FORMAT_VERSION`] has to be bumped.
+//!
+//! Every enum variant is tagged with an explicit name, which is its key in JSON IR, so renaming
+//! a variant of the typed IR doesn't rename it here. Fields are written in the order they're
+//! declared, which is what makes the output the same on every build. Binary IR writes a variant
+//! as its index, so variants are only ever added at the end of an enum, and fields are never
+//! reordered.
+//!
+//! [`FORMAT_VERSION`]: crate::artifact::FORMAT_VERSION
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ast, ir::BinOp, token, typed};
+
+/// The contents of an IR file, starting with the versions needed to check it can be read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Artifact {
+    pub format_version: u32,
+    pub compiler_version: String,
+    pub program: Program,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Program {
+    pub structs: Vec<Struct>,
+    pub funcs: Vec<Func>,
+    /// Since format version 3
+    pub globals: Vec<Global>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<Var>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Var {
+    pub name: String,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Global {
+    pub var: Var,
+    pub constant: bool,
+    pub expr: Option<Spanned<Expr>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Func {
+    pub name: String,
+    pub ret: Type,
+    /// How many of the first slots are parameters
+    pub params: u32,
+    pub slots: Vec<Var>,
+    pub body: Vec<Spanned<Stmt>>,
+    pub span: Span,
+}
+
+/// A range of bytes in the source the program was compiled from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// A node with the span of the source it was compiled from, written as a pair.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Spanned<T>(pub T, pub Span);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Type {
+    #[serde(rename = "Int")]
+    Int,
+    #[serde(rename = "Str")]
+    Str,
+    #[serde(rename = "Void")]
+    Void,
+    /// The struct at this index of [`Program::structs`]
+    #[serde(rename = "Struct")]
+    Struct(u32),
+    #[serde(rename = "Pointer")]
+    Pointer(Box<Type>),
+    #[serde(rename = "Array")]
+    Array { elem: Box<Type>, len: u32 },
+    #[serde(rename = "Float")]
+    Float,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Stmt {
+    #[serde(rename = "Return")]
+    Return(Spanned<Expr>),
+    #[serde(rename = "ReturnVoid")]
+    ReturnVoid,
+    #[serde(rename = "Expr")]
+    Expr(Spanned<Expr>),
+    #[serde(rename = "Let")]
+    Let { slot: u32, expr: Spanned<Expr> },
+    #[serde(rename = "Array")]
+    Array { slot: u32 },
+    #[serde(rename = "Store")]
+    Store {
+        slot: u32,
+        index: Spanned<Expr>,
+        expr: Spanned<Expr>,
+    },
+    #[serde(rename = "Reassign")]
+    Reassign {
+        slot: u32,
+        op: AssignOp,
+        expr: Spanned<Expr>,
+    },
+    #[serde(rename = "Write")]
+    Write {
+        pointer: Spanned<Expr>,
+        expr: Spanned<Expr>,
+    },
+    #[serde(rename = "Inline")]
+    Inline { lang: InlineLang, code: String },
+    #[serde(rename = "Switch")]
+    Switch {
+        expr: Spanned<Expr>,
+        cases: Vec<Case>,
+    },
+    #[serde(rename = "Break")]
+    Break,
+    #[serde(rename = "Continue")]
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Case {
+    /// The value the case matches, or `None` for `default`
+    pub value: Option<i64>,
+    pub span: Span,
+    pub body: Vec<Spanned<Stmt>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Expr {
+    #[serde(rename = "Int")]
+    Int(u32),
+    #[serde(rename = "Str")]
+    Str(String),
+    /// The variable in this slot
+    #[serde(rename = "Var")]
+    Var(u32),
+    #[serde(rename = "Neg")]
+    Neg(Box<Spanned<Expr>>),
+    #[serde(rename = "Not")]
+    Not(Box<Spanned<Expr>>),
+    #[serde(rename = "Binary")]
+    Binary(Op, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    #[serde(rename = "Index")]
+    Index(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    #[serde(rename = "Call")]
+    Call {
+        callee: Callee,
+        args: Vec<Spanned<Expr>>,
+    },
+    #[serde(rename = "PreInc")]
+    PreInc(u32),
+    #[serde(rename = "PreDec")]
+    PreDec(u32),
+    #[serde(rename = "Cond")]
+    Cond(Box<Spanned<Expr>>, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    #[serde(rename = "AddrOf")]
+    AddrOf(u32),
+    #[serde(rename = "Deref")]
+    Deref(Box<Spanned<Expr>>),
+    #[serde(rename = "Spawn")]
+    Spawn {
+        callee: Callee,
+        args: Vec<Spanned<Expr>>,
+    },
+    #[serde(rename = "Join")]
+    Join(Box<Spanned<Expr>>),
+    #[serde(rename = "Logical")]
+    Logical(LogicalOp, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    #[serde(rename = "Float")]
+    Float(f64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Callee {
+    /// The function at this index of [`Program::funcs`]
+    #[serde(rename = "Func")]
+    Func(u32),
+    /// A function looked up by name when it's called
+    #[serde(rename = "Extern")]
+    Extern(String),
+}
+
+/// The operator of an [`Expr::Binary`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+    #[serde(rename = "Add")]
+    Add,
+    #[serde(rename = "Sub")]
+    Sub,
+    #[serde(rename = "Mul")]
+    Mul,
+    #[serde(rename = "Div")]
+    Div,
+    #[serde(rename = "Rem")]
+    Rem,
+    #[serde(rename = "And")]
+    And,
+    #[serde(rename = "Or")]
+    Or,
+    #[serde(rename = "Xor")]
+    Xor,
+    #[serde(rename = "Shl")]
+    Shl,
+    #[serde(rename = "Shr")]
+    Shr,
+    #[serde(rename = "Lt")]
+    Lt,
+    #[serde(rename = "Le")]
+    Le,
+    #[serde(rename = "Gt")]
+    Gt,
+    #[serde(rename = "Ge")]
+    Ge,
+    #[serde(rename = "Eq")]
+    Eq,
+    #[serde(rename = "Ne")]
+    Ne,
+}
+
+/// The operator of an [`Expr::Logical`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogicalOp {
+    #[serde(rename = "And")]
+    And,
+    #[serde(rename = "Or")]
+    Or,
+}
+
+/// The operator of a [`Stmt::Reassign`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssignOp {
+    #[serde(rename = "Set")]
+    Set,
+    #[serde(rename = "Add")]
+    Add,
+    #[serde(rename = "Sub")]
+    Sub,
+    #[serde(rename = "Mul")]
+    Mul,
+    #[serde(rename = "Div")]
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InlineLang {
+    #[serde(rename = "Asm")]
+    Asm,
+    #[serde(rename = "Ir")]
+    Ir,
+}
+
+impl From<&typed::Program> for Program {
+    fn from(program: &typed::Program) -> Self {
+        Self {
+            structs: program
+                .structs
+                .iter()
+                .map(|r#struct| Struct {
+                    name: r#struct.name.to_string(),
+                    fields: r#struct.fields.iter().map(Var::from).collect(),
+                    span: Span::from(&r#struct.span),
+                })
+                .collect(),
+            funcs: program.funcs.iter().map(Func::from).collect(),
+            globals: program
+                .globals
+                .iter()
+                .map(|global| Global {
+                    var: Var::from(&global.var),
+                    constant: global.constant,
+                    expr: global.expr.as_ref().map(spanned),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<Program> for typed::Program {
+    fn from(program: Program) -> Self {
+        Self {
+            structs: program
+                .structs
+                .into_iter()
+                .map(|r#struct| typed::Struct {
+                    name: r#struct.name.into(),
+                    fields: r#struct.fields.into_iter().map(typed::Var::from).collect(),
+                    span: r#struct.span.into(),
+                })
+                .collect(),
+            funcs: program.funcs.into_iter().map(typed::Func::from).collect(),
+            globals: program
+                .globals
+                .into_iter()
+                .map(|global| typed::Global {
+                    var: global.var.into(),
+                    constant: global.constant,
+                    expr: global.expr.map(unspanned),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&typed::Var> for Var {
+    fn from(var: &typed::Var) -> Self {
+        Self {
+            name: var.name.to_string(),
+            ty: Type::from(&var.ty),
+            span: Span::from(&var.span),
+        }
+    }
+}
+
+impl From<Var> for typed::Var {
+    fn from(var: Var) -> Self {
+        Self {
+            name: var.name.into(),
+            ty: var.ty.into(),
+            span: var.span.into(),
+        }
+    }
+}
+
+impl From<&typed::Func> for Func {
+    fn from(func: &typed::Func) -> Self {
+        Self {
+            name: func.name.to_string(),
+            ret: Type::from(&func.ret),
+            params: func.params,
+            slots: func.slots.iter().map(Var::from).collect(),
+            body: func.body.iter().map(spanned).collect(),
+            span: Span::from(&func.span),
+        }
+    }
+}
+
+impl From<Func> for typed::Func {
+    fn from(func: Func) -> Self {
+        Self {
+            name: func.name.into(),
+            ret: func.ret.into(),
+            params: func.params,
+            slots: func.slots.into_iter().map(typed::Var::from).collect(),
+            body: func.body.into_iter().map(unspanned).collect(),
+            span: func.span.into(),
+        }
+    }
+}
+
+impl From<&token::Span> for Span {
+    fn from(span: &token::Span) -> Self {
+        Self {
+            start: span.start as u64,
+            end: span.end as u64,
+        }
+    }
+}
+
+impl From<Span> for token::Span {
+    fn from(span: Span) -> Self {
+        span.start as usize..span.end as usize
+    }
+}
+
+/// The schema of a spanned node of the typed IR.
+fn spanned<'a, T: 'a, U: From<&'a T>>((node, span): &'a ast::Spanned<T>) -> Spanned<U> {
+    Spanned(U::from(node), Span::from(span))
+}
+
+/// The spanned node of the typed IR of a spanned node of the schema.
+fn unspanned<T, U: From<T>>(Spanned(node, span): Spanned<T>) -> ast::Spanned<U> {
+    (U::from(node), span.into())
+}
+
+fn boxed<'a, T: 'a, U: From<&'a T>>(node: &'a ast::Spanned<T>) -> Box<Spanned<U>> {
+    Box::new(spanned(node))
+}
+
+fn unboxed<T, U: From<T>>(node: Spanned<T>) -> Box<ast::Spanned<U>> {
+    Box::new(unspanned(node))
+}
+
+impl From<&typed::Type> for Type {
+    fn from(ty: &typed::Type) -> Self {
+        match ty {
+            typed::Type::Int => Self::Int,
+            typed::Type::Str => Self::Str,
+            typed::Type::Void => Self::Void,
+            typed::Type::Float => Self::Float,
+            typed::Type::Struct(index) => Self::Struct(*index),
+            typed::Type::Pointer(pointee) => Self::Pointer(Box::new(Self::from(&**pointee))),
+            typed::Type::Array { elem, len } => Self::Array {
+                elem: Box::new(Self::from(&**elem)),
+                len: *len,
+            },
+        }
+    }
+}
+
+impl From<Type> for typed::Type {
+    fn from(ty: Type) -> Self {
+        match ty {
+            Type::Int => Self::Int,
+            Type::Str => Self::Str,
+            Type::Void => Self::Void,
+            Type::Float => Self::Float,
+            Type::Struct(index) => Self::Struct(index),
+            Type::Pointer(pointee) => Self::Pointer(Box::new((*pointee).into())),
+            Type::Array { elem, len } => Self::Array {
+                elem: Box::new((*elem).into()),
+                len,
+            },
+        }
+    }
+}
+
+impl From<&typed::Stmt> for Stmt {
+    fn from(statement: &typed::Stmt) -> Self {
+        use typed::Stmt as S;
+
+        match statement {
+            S::Return(expr) => Self::Return(spanned(expr)),
+            S::ReturnVoid => Self::ReturnVoid,
+            S::Expr(expr) => Self::Expr(spanned(expr)),
+            S::Let { slot, expr } => Self::Let {
+                slot: *slot,
+                expr: spanned(expr),
+            },
+            S::Array { slot } => Self::Array { slot: *slot },
+            S::Store { slot, index, expr } => Self::Store {
+                slot: *slot,
+                index: spanned(index),
+                expr: spanned(expr),
+            },
+            S::Reassign { slot, op, expr } => Self::Reassign {
+                slot: *slot,
+                op: (*op).into(),
+                expr: spanned(expr),
+            },
+            S::Write { pointer, expr } => Self::Write {
+                pointer: spanned(pointer),
+                expr: spanned(expr),
+            },
+            S::Inline { lang, code } => Self::Inline {
+                lang: (*lang).into(),
+                code: code.clone(),
+            },
+            S::Switch { expr, cases } => Self::Switch {
+                expr: spanned(expr),
+                cases: cases
+                    .iter()
+                    .map(|case| Case {
+                        value: case.value,
+                        span: (&case.span).into(),
+                        body: case.body.iter().map(spanned).collect(),
+                    })
+                    .collect(),
+            },
+            S::Break => Self::Break,
+            S::Continue => Self::Continue,
+        }
+    }
+}
+
+impl From<Stmt> for typed::Stmt {
+    fn from(statement: Stmt) -> Self {
+        match statement {
+            Stmt::Return(expr) => Self::Return(unspanned(expr)),
+            Stmt::ReturnVoid => Self::ReturnVoid,
+            Stmt::Expr(expr) => Self::Expr(unspanned(expr)),
+            Stmt::Let { slot, expr } => Self::Let {
+                slot,
+                expr: unspanned(expr),
+            },
+            Stmt::Array { slot } => Self::Array { slot },
+            Stmt::Store { slot, index, expr } => Self::Store {
+                slot,
+                index: unspanned(index),
+                expr: unspanned(expr),
+            },
+            Stmt::Reassign { slot, op, expr } => Self::Reassign {
+                slot,
+                op: op.into(),
+                expr: unspanned(expr),
+            },
+            Stmt::Write { pointer, expr } => Self::Write {
+                pointer: unspanned(pointer),
+                expr: unspanned(expr),
+            },
+            Stmt::Inline { lang, code } => Self::Inline {
+                lang: lang.into(),
+                code,
+            },
+            Stmt::Switch { expr, cases } => Self::Switch {
+                expr: unspanned(expr),
+                cases: cases
+                    .into_iter()
+                    .map(|case| typed::Case {
+                        value: case.value,
+                        span: case.span.into(),
+                        body: case.body.into_iter().map(unspanned).collect(),
+                    })
+                    .collect(),
+            },
+            Stmt::Break => Self::Break,
+            Stmt::Continue => Self::Continue,
+        }
+    }
+}
+
+impl From<&typed::Expr> for Expr {
+    fn from(expr: &typed::Expr) -> Self {
+        use typed::Expr as E;
+
+        let args = |args: &[ast::Spanned<E>]| args.iter().map(spanned).collect();
+        match expr {
+            E::Int(value) => Self::Int(*value),
+            E::Float(value) => Self::Float(*value),
+            E::Str(value) => Self::Str(value.clone()),
+            E::Var(slot) => Self::Var(*slot),
+            E::Neg(inner) => Self::Neg(boxed(inner)),
+            E::Not(inner) => Self::Not(boxed(inner)),
+            E::Binary(op, lhs, rhs) => Self::Binary((*op).into(), boxed(lhs), boxed(rhs)),
+            E::Index(array, index) => Self::Index(boxed(array), boxed(index)),
+            E::Call {
+                callee,
+                args: params,
+            } => Self::Call {
+                callee: callee.into(),
+                args: args(params),
+            },
+            E::PreInc(slot) => Self::PreInc(*slot),
+            E::PreDec(slot) => Self::PreDec(*slot),
+            E::Cond(cond, then, otherwise) => {
+                Self::Cond(boxed(cond), boxed(then), boxed(otherwise))
+            }
+            E::AddrOf(slot) => Self::AddrOf(*slot),
+            E::Deref(inner) => Self::Deref(boxed(inner)),
+            E::Spawn {
+                callee,
+                args: params,
+            } => Self::Spawn {
+                callee: callee.into(),
+                args: args(params),
+            },
+            E::Join(handle) => Self::Join(boxed(handle)),
+            E::Logical(op, lhs, rhs) => Self::Logical((*op).into(), boxed(lhs), boxed(rhs)),
+        }
+    }
+}
+
+impl From<Expr> for typed::Expr {
+    fn from(expr: Expr) -> Self {
+        let args = |args: Vec<Spanned<Expr>>| args.into_iter().map(unspanned).collect();
+        match expr {
+            Expr::Int(value) => Self::Int(value),
+            Expr::Float(value) => Self::Float(value),
+            Expr::Str(value) => Self::Str(value),
+            Expr::Var(slot) => Self::Var(slot),
+            Expr::Neg(inner) => Self::Neg(unboxed(*inner)),
+            Expr::Not(inner) => Self::Not(unboxed(*inner)),
+            Expr::Binary(op, lhs, rhs) => Self::Binary(op.into(), unboxed(*lhs), unboxed(*rhs)),
+            Expr::Index(array, index) => Self::Index(unboxed(*array), unboxed(*index)),
+            Expr::Call {
+                callee,
+                args: params,
+            } => Self::Call {
+                callee: callee.into(),
+                args: args(params),
+            },
+            Expr::PreInc(slot) => Self::PreInc(slot),
+            Expr::PreDec(slot) => Self::PreDec(slot),
+            Expr::Cond(cond, then, otherwise) => {
+                Self::Cond(unboxed(*cond), unboxed(*then), unboxed(*otherwise))
+            }
+            Expr::AddrOf(slot) => Self::AddrOf(slot),
+            Expr::Deref(inner) => Self::Deref(unboxed(*inner)),
+            Expr::Spawn {
+                callee,
+                args: params,
+            } => Self::Spawn {
+                callee: callee.into(),
+                args: args(params),
+            },
+            Expr::Join(handle) => Self::Join(unboxed(*handle)),
+            Expr::Logical(op, lhs, rhs) => Self::Logical(op.into(), unboxed(*lhs), unboxed(*rhs)),
+        }
+    }
+}
+
+impl From<&typed::Callee> for Callee {
+    fn from(callee: &typed::Callee) -> Self {
+        match callee {
+            typed::Callee::Func(index) => Self::Func(*index),
+            typed::Callee::Extern(name) => Self::Extern(name.clone()),
+        }
+    }
+}
+
+impl From<Callee> for typed::Callee {
+    fn from(callee: Callee) -> Self {
+        match callee {
+            Callee::Func(index) => Self::Func(index),
+            Callee::Extern(name) => Self::Extern(name),
+        }
+    }
+}
+
+/// Pairs of the same variant on both sides of a conversion between fieldless enums.
+macro_rules! convert {
+    ($schema:ty, $other:ty, [$($variant:ident),* $(,)?]) => {
+        impl From<$other> for $schema {
+            fn from(value: $other) -> Self {
+                match value {
+                    $(<$other>::$variant => Self::$variant,)*
+                }
+            }
+        }
+
+        impl From<$schema> for $other {
+            fn from(value: $schema) -> Self {
+                match value {
+                    $(<$schema>::$variant => Self::$variant,)*
+                }
+            }
+        }
+    };
+}
+
+convert!(
+    Op,
+    BinOp,
+    [Add, Sub, Mul, Div, Rem, And, Or, Xor, Shl, Shr, Lt, Le, Gt, Ge, Eq, Ne]
+);
+convert!(LogicalOp, ast::LogicalOp, [And, Or]);
+convert!(AssignOp, ast::AssignOp, [Set, Add, Sub, Mul, Div]);
+convert!(InlineLang, ast::InlineLang, [Asm, Ir]);
@@ -0,0 +1,105 @@
+//! Running a program in a child process with limits on its CPU time and memory, for
+//! `run --isolate`, so that a program that loops or allocates forever is killed without taking
+//! down whatever started it.
+//!
+//! The parent runs `crust` again with the same arguments and [`ISOLATED_VAR`] set, and the child
+//! applies the limits to itself with `setrlimit` before it runs the program. How the child ended
+//! is then reported by the parent, as an error if it was killed.
+
+use std::{io, process::ExitStatus};
+
+use crate::{diagnostics::Diagnostic, messages::Message};
+
+/// Set in the environment of the child process, which applies the limits instead of starting
+/// another child.
+pub const ISOLATED_VAR: &str = "CRUST_ISOLATED";
+
+/// The default limit on CPU time, in seconds.
+pub const DEFAULT_CPU_SECONDS: u64 = 10;
+/// The default limit on memory, in megabytes.
+pub const DEFAULT_MEMORY_MB: u64 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// CPU time in seconds, after which the program is killed
+    pub cpu_seconds: u64,
+    /// Address space in megabytes, beyond which allocations fail
+    pub memory_mb: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            cpu_seconds: DEFAULT_CPU_SECONDS,
+            memory_mb: DEFAULT_MEMORY_MB,
+        }
+    }
+}
+
+/// Applies `limits` to the current process, and to any process it starts.
+#[cfg(unix)]
+pub fn apply(limits: Limits) -> io::Result<()> {
+    // the resource's type differs between platforms, so it's left to be inferred
+    let set = |resource, soft: u64, hard: u64| {
+        let limit = libc::rlimit {
+            rlim_cur: soft as libc::rlim_t,
+            rlim_max: hard as libc::rlim_t,
+        };
+        // SAFETY: `limit` is a valid rlimit that outlives the call, which only reads it
+        match unsafe { libc::setrlimit(resource, &limit) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    };
+
+    // the soft limit sends SIGXCPU, and the hard limit a second later SIGKILL in case that's caught
+    set(
+        libc::RLIMIT_CPU,
+        limits.cpu_seconds,
+        limits.cpu_seconds.saturating_add(1),
+    )?;
+    let memory = limits.memory_mb.saturating_mul(1024 * 1024);
+    set(libc::RLIMIT_AS, memory, memory)
+}
+
+#[cfg(not(unix))]
+pub fn apply(_limits: Limits) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "resource limits are only supported on Unix",
+    ))
+}
+
+/// The exit code of a child process that ran with `limits` and ended with `status`, or the error
+/// to report if it was killed.
+pub fn outcome(status: ExitStatus, limits: Limits) -> Result<i32, Diagnostic> {
+    if let Some(code) = status.code() {
+        return Ok(code);
+    }
+
+    let signal = signal(status);
+    let message = match signal {
+        #[cfg(unix)]
+        Some(libc::SIGXCPU | libc::SIGKILL) => {
+            Message::new("E0204.cpu").arg("seconds", limits.cpu_seconds.to_string())
+        }
+        // Rust aborts when an allocation fails
+        #[cfg(unix)]
+        Some(libc::SIGABRT | libc::SIGSEGV) => {
+            Message::new("E0204.memory").arg("megabytes", limits.memory_mb.to_string())
+        }
+        Some(signal) => Message::new("E0204.signal").arg("signal", signal.to_string()),
+        None => Message::new("E0204.signal").arg("signal", "unknown"),
+    };
+    Err(Diagnostic::error("E0204", message).with_note(Message::new("note.limits")))
+}
+
+#[cfg(unix)]
+fn signal(status: ExitStatus) -> Option<i32> {
+    std::os::unix::process::ExitStatusExt::signal(&status)
+}
+
+#[cfg(not(unix))]
+fn signal(_status: ExitStatus) -> Option<i32> {
+    None
+}
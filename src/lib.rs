@@ -1,5 +1,61 @@
+// chumsky's `Simple<Token>` error is large by design and is returned from every parser closure
+#![allow(clippy::result_large_err)]
+
+pub mod annotate;
+pub mod artifact;
 pub mod ast;
+pub mod binary;
+pub mod builtins;
+pub mod cgen;
+pub mod codegen;
+pub mod completions;
+pub mod comptime;
+pub mod config;
+pub mod debug;
+pub mod delimiters;
+pub mod diagnostics;
+pub mod dump;
+pub mod editor;
+pub mod format;
+pub mod fuzz;
+pub mod harness;
+pub mod interrupt;
+pub mod ir;
+pub mod isolate;
+pub mod lint;
+pub mod literal;
+pub mod llvm;
+pub mod lsp;
+pub mod macros;
+pub mod messages;
+pub mod opt;
+pub mod pipeline;
+pub mod precedence;
+pub mod query;
+pub mod repl;
+pub mod sema;
+pub mod semantics;
+pub mod sources;
+pub mod suggest;
+pub mod symbol;
+pub mod symtab;
+pub mod tac;
+pub mod telemetry;
+pub mod threads;
 pub mod token;
+pub mod trace;
+pub mod typed;
+pub mod types;
+pub mod value;
+pub mod visit;
+pub mod viz;
+pub mod vm;
+pub mod watch;
 
-pub use ast::Ast;
+pub use ast::{Ast, RunOptions};
+pub use builtins::Builtins;
+pub use diagnostics::Diagnostic;
+pub use pipeline::{compile, parse_ast, parse_tokens, Program};
+pub use symbol::Symbol;
 pub use token::Token;
+pub use value::Value;
@@ -0,0 +1,187 @@
+//! Warnings about code that compiles but is probably a mistake: variables that are assigned but
+//! never read, functions that `main` never calls, and statements after a `return`.
+//!
+//! The lints are found from the [`SymbolTable`] of the analysed program, so a variable declared
+//! again in the same function is a symbol of its own, and each one is warned about separately.
+//! A variable whose name starts with `_` is never warned about.
+
+use std::collections::HashSet;
+
+use crate::{
+    ast::{self, Definition, Expr, Func, InlinePiece, Spanned, Statement},
+    diagnostics::Diagnostic,
+    messages::Message,
+    sources::SourceMap,
+    symtab::{Scope, SymbolKind, SymbolTable},
+    visit::{self, Visitor},
+    Ast,
+};
+
+/// Finds the warnings in `ast`, which must have passed semantic analysis, with its spans in
+/// `sources`.
+pub fn check(sources: &SourceMap, ast: &Ast) -> Vec<Diagnostic> {
+    let table = SymbolTable::new(sources, ast);
+    let mut diagnostics = unused_functions(ast, &table.scopes[0]);
+
+    // every function and struct has a scope after the global one, in the order they're defined
+    let scopes = ast
+        .defs
+        .iter()
+        .filter(|def| matches!(def, Definition::Func(_) | Definition::Struct { .. }))
+        .zip(&table.scopes[1..]);
+    for (def, scope) in scopes {
+        if let Definition::Func(func) = def {
+            diagnostics.extend(unused_variables(func, scope));
+            diagnostics.extend(unreachable(func));
+        }
+    }
+    diagnostics
+}
+
+/// Reports the functions in `global` that `main` never calls, directly or through others.
+fn unused_functions(ast: &Ast, global: &Scope) -> Vec<Diagnostic> {
+    let funcs = ast
+        .defs
+        .iter()
+        .filter_map(|def| match def {
+            Definition::Func(func) => Some(func),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut called = HashSet::from(["main"]);
+    let mut stack = vec!["main"];
+    while let Some(name) = stack.pop() {
+        let Some(func) = funcs.iter().find(|func| func.name == name) else {
+            continue;
+        };
+        let mut calls = Calls::default();
+        calls.visit_func(func);
+        stack.extend(calls.0.into_iter().filter(|callee| called.insert(callee)));
+    }
+
+    global
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.kind == SymbolKind::Function)
+        .filter(|symbol| !called.contains(symbol.name.as_str()))
+        .map(|symbol| {
+            Diagnostic::warning(
+                "W0102",
+                Message::new("W0102").arg("name", symbol.name.as_str()),
+            )
+            .with_label(symbol.span.clone(), Message::new("label.declared-here"))
+        })
+        .collect()
+}
+
+/// The functions a function calls.
+#[derive(Default)]
+struct Calls<'a>(Vec<&'a str>);
+
+impl<'a> Visitor<'a> for Calls<'a> {
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        if let Expr::Call { name, .. } | Expr::Spawn { name, .. } = &expr.0 {
+            self.0.push(name);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Reports the variables declared in `func`, whose symbols are in `scope`, that are never read.
+fn unused_variables(func: &Func, scope: &Scope) -> Vec<Diagnostic> {
+    let mut reads = Reads {
+        bindings: func
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| (param.name.as_str(), i))
+            .collect(),
+        read: vec![false; scope.symbols.len()],
+    };
+    for statement in &func.body {
+        reads.visit_statement(statement);
+        if let Statement::Assign { name, .. } | Statement::Array { name, .. } = &statement.0 {
+            let symbol = reads.bindings.len();
+            reads.bindings.push((name, symbol));
+        }
+    }
+
+    scope
+        .symbols
+        .iter()
+        .zip(reads.read)
+        .filter(|(symbol, read)| {
+            // variables a macro declares are renamed with a `.`, and are the macro's to warn about
+            matches!(symbol.kind, SymbolKind::Variable | SymbolKind::Array)
+                && !read
+                && !symbol.name.starts_with('_')
+                && !symbol.name.contains('.')
+        })
+        .map(|(symbol, _)| {
+            Diagnostic::warning(
+                "W0101",
+                Message::new("W0101").arg("name", symbol.name.as_str()),
+            )
+            .with_label(symbol.span.clone(), Message::new("label.declared-here"))
+            .with_note(Message::new("note.underscore"))
+        })
+        .collect()
+}
+
+/// Which of a function's symbols are read, given the symbol each name in scope refers to.
+struct Reads<'a> {
+    /// Every name declared so far, with the index of its symbol, latest last
+    bindings: Vec<(&'a str, usize)>,
+    read: Vec<bool>,
+}
+
+impl Reads<'_> {
+    fn read(&mut self, name: &str) {
+        if let Some((_, symbol)) = self.bindings.iter().rev().find(|(var, _)| *var == name) {
+            self.read[*symbol] = true;
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Reads<'a> {
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        if let Statement::Inline { code, .. } = &statement.0 {
+            for piece in ast::inline_pieces(code) {
+                if let InlinePiece::Var(name) = piece {
+                    self.read(name);
+                }
+            }
+        }
+        visit::walk_statement(self, statement);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        // a variable a pointer is taken to may be read through it
+        if let Expr::Var(name) | Expr::PreInc(name) | Expr::PreDec(name) | Expr::AddrOf(name) =
+            &expr.0
+        {
+            self.read(name);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Reports the statements in `func` after its first `return`, which are never run.
+fn unreachable(func: &Func) -> Option<Diagnostic> {
+    let returns = func.body.iter().position(|(statement, _)| {
+        matches!(statement, Statement::Return(_) | Statement::ReturnVoid)
+    })?;
+    let (first, last) = (func.body.get(returns + 1)?, func.body.last()?);
+    Some(
+        Diagnostic::warning(
+            "W0103",
+            Message::new("W0103").arg("name", func.name.as_str()),
+        )
+        .with_label(first.1.start..last.1.end, Message::new("label.unreachable"))
+        .with_label(
+            func.body[returns].1.clone(),
+            Message::new("label.returned-here"),
+        ),
+    )
+}
@@ -0,0 +1,184 @@
+//! Conversion between string literal source text and the strings it represents, the values of
+//! int literals, and the source text of float literals.
+//!
+//! An int literal is decimal, or hex, binary or octal after a `0x`, `0b` or `0o` prefix, and its
+//! digits may be separated by `_`s, as in `1_000_000` or `0xFF_FF`.
+//!
+//! Supported escapes are `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\'`, `\xNN` (a character up to
+//! `\x7F`) and `\uXXXX` (any unicode scalar value). Raw strings (`r"..."`, `r#"..."#`) contain no
+//! escapes at all and are handled entirely by the lexer.
+
+use crate::token::Span;
+
+/// Escapes made of a backslash and a single character, with the character each represents.
+pub const ESCAPES: &[(char, char)] = &[
+    ('n', '\n'),
+    ('t', '\t'),
+    ('r', '\r'),
+    ('0', '\0'),
+    ('\\', '\\'),
+    ('"', '"'),
+    ('\'', '\''),
+];
+
+/// An invalid escape sequence, with the span of just the offending sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscapeError {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Resolves the escape sequences in the contents of a string literal (without its quotes).
+///
+/// `base` is the offset of the first character of `raw` in the source, used to report errors.
+pub fn unescape(raw: &str, base: usize) -> Result<String, EscapeError> {
+    let mut value = String::with_capacity(raw.len());
+    let mut chars = raw.chars().enumerate().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+
+        let error = |len: usize, message: String| EscapeError {
+            span: base + start..base + start + len,
+            message,
+        };
+
+        let Some((_, kind)) = chars.next() else {
+            return Err(error(1, "unterminated escape sequence".into()));
+        };
+
+        if let Some((_, escaped)) = ESCAPES.iter().find(|(escape, _)| *escape == kind) {
+            value.push(*escaped);
+            continue;
+        }
+
+        let escaped = match kind {
+            'x' | 'u' => {
+                let digits = if kind == 'x' { 2 } else { 4 };
+                let hex = (0..digits)
+                    .map_while(|_| chars.next_if(|(_, c)| c.is_ascii_hexdigit()))
+                    .map(|(_, c)| c)
+                    .collect::<String>();
+                let len = 2 + hex.chars().count();
+                if hex.len() != digits {
+                    return Err(error(
+                        len,
+                        format!("\\{kind} escape must be followed by {digits} hex digits"),
+                    ));
+                }
+
+                let code = u32::from_str_radix(&hex, 16).unwrap();
+                match char::from_u32(code) {
+                    Some(c) if kind == 'u' || c.is_ascii() => c,
+                    Some(_) => {
+                        return Err(error(
+                            len,
+                            format!("\\x{hex} is out of range, \\x escapes must be at most \\x7F"),
+                        ))
+                    }
+                    None => {
+                        return Err(error(
+                            len,
+                            format!("\\u{hex} is not a valid unicode character"),
+                        ))
+                    }
+                }
+            }
+            other => {
+                return Err(error(2, format!("unknown escape sequence \\{other}")));
+            }
+        };
+        value.push(escaped);
+    }
+
+    Ok(value)
+}
+
+/// Produces the source text for a string literal with the given value, including its quotes.
+pub fn escape(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            '\0' => literal.push_str("\\0"),
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            c if c.is_control() => literal.push_str(&format!("\\u{:04X}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Produces the source text for a float literal with the given value, which always has a decimal
+/// point and never an exponent so that it lexes as a float again. Infinity and NaN have no
+/// literal, and are written as `inf` and `NaN`.
+pub fn float(value: f64) -> String {
+    let text = value.to_string();
+    match text.contains('.') || !value.is_finite() {
+        true => text,
+        false => text + ".0",
+    }
+}
+
+/// The prefixes of int literals in other bases than 10, after their `0`, with their bases.
+pub const RADIX_PREFIXES: &[(char, u32)] = &[('x', 16), ('b', 2), ('o', 8)];
+
+/// Why an int literal has no value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntError {
+    /// A character that isn't a digit in the literal's base
+    InvalidDigit { digit: char, radix: u32 },
+    /// A prefix without any digits after it
+    NoDigits,
+    /// A value above the largest the literal may have
+    TooLarge,
+}
+
+/// The largest int literal, which is the largest int.
+pub const INT_MAX: u32 = i32::MAX as u32;
+
+/// The largest int literal after a unary `-`, which negates it to the smallest int.
+pub const NEGATED_INT_MAX: u32 = i32::MIN.unsigned_abs();
+
+/// The value of an int literal as the lexer produced it, with its prefix and separators, which
+/// is at most `max`.
+pub fn int(text: &str, max: u32) -> Result<u32, IntError> {
+    let (digits, radix) = radix(text);
+    let digits = digits
+        .chars()
+        .filter(|c| *c != '_')
+        .map(|c| {
+            c.to_digit(radix)
+                .ok_or(IntError::InvalidDigit { digit: c, radix })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if digits.is_empty() {
+        return Err(IntError::NoDigits);
+    }
+    digits.into_iter().try_fold(0u32, |value, digit| {
+        value
+            .checked_mul(radix)
+            .and_then(|value| value.checked_add(digit))
+            .filter(|value| *value <= max)
+            .ok_or(IntError::TooLarge)
+    })
+}
+
+/// The digits of an int literal after its prefix, and their base.
+fn radix(text: &str) -> (&str, u32) {
+    let mut chars = text.chars();
+    if let (Some('0'), Some(prefix)) = (chars.next(), chars.next()) {
+        let prefix = prefix.to_ascii_lowercase();
+        if let Some((_, radix)) = RADIX_PREFIXES.iter().find(|(c, _)| *c == prefix) {
+            return (chars.as_str(), *radix);
+        }
+    }
+    (text, 10)
+}
@@ -0,0 +1,648 @@
+//! LLVM IR text generation.
+//!
+//! Programs are translated from the [`ir`] to a textual LLVM module that can be compiled with
+//! `clang` or `llc`. Every temporary and array lives in an `alloca` (which LLVM's `mem2reg` turns
+//! back into SSA values), ints are `i32` and use plain (non `nsw`) arithmetic so that they wrap
+//! exactly as [`semantics`] specifies, and strings are pointers to constant C strings. Typed
+//! pointer syntax is used so that the output is accepted by LLVM 14 as well as newer versions.
+//!
+//! The backend has the same runtime errors and exit statuses as the [`codegen`](crate::codegen)
+//! backend.
+//!
+//! The code of an `__ir` block is copied into the function it's in, with each variable it names
+//! replaced by a pointer to its `alloca`. The values and labels it defines must be named, and
+//! mustn't clash with the `%t`, `%v`, `%a`, `%p`, `L` and `bb` names generated around it.
+//!
+//! [`semantics`]: crate::semantics
+
+use std::fmt::Write;
+
+use crate::{
+    ast::{InlineLang, DEFAULT_MAX_CALL_DEPTH},
+    diagnostics::Diagnostic,
+    ir::{self, BinOp, BlockId, Function, InlinePiece, Inst, Operand, Terminator, Ty},
+    messages::Message,
+    semantics::ArithError,
+};
+
+/// Generates an LLVM module for a whole program.
+pub fn emit_llvm(program: &ir::Program) -> Result<String, Diagnostic> {
+    let mut module = Module {
+        text: String::new(),
+        strings: Vec::new(),
+    };
+    if let Some(main) = program.func("main") {
+        module.entry(main);
+    }
+    for func in &program.funcs {
+        module.func(func)?;
+    }
+    Ok(module.finish())
+}
+
+fn llvm_type(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Int => "i32",
+        Ty::Str => "i8*",
+        Ty::Void => "void",
+    }
+}
+
+struct Module {
+    text: String,
+    /// String constants, each emitted as `@.str.{index}`
+    strings: Vec<String>,
+}
+
+impl Module {
+    /// A pointer to the first character of a string constant.
+    fn string(&mut self, value: &str) -> String {
+        let index = match self.strings.iter().position(|s| s == value) {
+            Some(index) => index,
+            None => {
+                self.strings.push(value.to_string());
+                self.strings.len() - 1
+            }
+        };
+        let len = value.len() + 1;
+        format!("getelementptr inbounds ([{len} x i8], [{len} x i8]* @.str.{index}, i64 0, i64 0)")
+    }
+
+    /// The `main` symbol called by libc, which parses the command line and calls `main`.
+    fn entry(&mut self, main: &Function) {
+        let count = main.params.len();
+        let arity = self.string("main takes %d arguments but %d were supplied\n");
+        let mut text = String::new();
+        writeln!(text, "define i32 @main(i32 %argc, i8** %argv) {{").unwrap();
+        writeln!(text, "entry:").unwrap();
+        writeln!(text, "  %ok = icmp eq i32 %argc, {}", count + 1).unwrap();
+        writeln!(text, "  br i1 %ok, label %args, label %arity").unwrap();
+        writeln!(text, "arity:").unwrap();
+        writeln!(text, "  %supplied = sub i32 %argc, 1").unwrap();
+        writeln!(
+            text,
+            "  call i32 (i32, i8*, ...) @dprintf(i32 2, i8* {arity}, i32 {count}, i32 %supplied)"
+        )
+        .unwrap();
+        writeln!(text, "  call void @exit(i32 255)").unwrap();
+        writeln!(text, "  unreachable").unwrap();
+        writeln!(text, "args:").unwrap();
+
+        let mut args = Vec::new();
+        for (i, param) in main.params.iter().enumerate() {
+            writeln!(
+                text,
+                "  %arg{i}.ptr = getelementptr inbounds i8*, i8** %argv, i64 {}",
+                i + 1
+            )
+            .unwrap();
+            writeln!(text, "  %arg{i}.str = load i8*, i8** %arg{i}.ptr").unwrap();
+            match main.temps[param.0].ty {
+                Ty::Int => {
+                    writeln!(
+                        text,
+                        "  %arg{i} = call i32 @crust_parse_int(i8* %arg{i}.str)"
+                    )
+                    .unwrap();
+                    args.push(format!("i32 %arg{i}"));
+                }
+                Ty::Str => args.push(format!("i8* %arg{i}.str")),
+                Ty::Void => unreachable!("no parameter is void"),
+            }
+        }
+        writeln!(
+            text,
+            "  %code = call i32 @crust_fn_main({})",
+            args.join(", ")
+        )
+        .unwrap();
+        writeln!(text, "  ret i32 %code").unwrap();
+        writeln!(text, "}}").unwrap();
+        writeln!(text).unwrap();
+        self.text.push_str(&text);
+    }
+
+    fn func(&mut self, func: &Function) -> Result<(), Diagnostic> {
+        let mut body = Body {
+            module: self,
+            func,
+            text: String::new(),
+            values: 0,
+            labels: 0,
+        };
+
+        let params = func
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| format!("{} %p{i}", llvm_type(func.temps[param.0].ty)))
+            .collect::<Vec<_>>();
+        writeln!(
+            body.text,
+            "define internal {} @crust_fn_{}({}) {{",
+            llvm_type(func.ret),
+            func.name,
+            params.join(", ")
+        )
+        .unwrap();
+        body.place_label("entry");
+
+        for (i, temp) in func.temps.iter().enumerate() {
+            body.line(format!("%v{i} = alloca {}", llvm_type(temp.ty)));
+        }
+        for (i, array) in func.arrays.iter().enumerate() {
+            body.line(format!(
+                "%a{i} = alloca [{} x {}]",
+                array.len,
+                llvm_type(array.ty)
+            ));
+        }
+
+        // track the call depth so that runaway recursion fails the way it does in the interpreter
+        // rather than by overflowing the native stack, main counts as the first call
+        let depth = body.value();
+        let next = body.value();
+        let overflow = body.value();
+        body.line(format!("{depth} = load i32, i32* @crust_call_depth"));
+        body.line(format!("{next} = add i32 {depth}, 1"));
+        body.line(format!(
+            "{overflow} = icmp ugt i32 {next}, {}",
+            DEFAULT_MAX_CALL_DEPTH + 1
+        ));
+        body.error_if(
+            &overflow,
+            &format!("stack overflow, exceeded the maximum call depth of {DEFAULT_MAX_CALL_DEPTH}"),
+        );
+        body.line(format!("store i32 {next}, i32* @crust_call_depth"));
+
+        for (i, param) in func.params.iter().enumerate() {
+            let ty = llvm_type(func.temps[param.0].ty);
+            body.line(format!("store {ty} %p{i}, {ty}* %v{}", param.0));
+        }
+        body.line("br label %bb0");
+
+        for (i, block) in func.blocks.iter().enumerate() {
+            body.place_label(&format!("bb{i}"));
+            for inst in &block.insts {
+                body.inst(inst)?;
+            }
+            body.terminator(&block.terminator, BlockId(i));
+        }
+        body.text.push_str("}\n\n");
+
+        let text = body.text;
+        self.text.push_str(&text);
+        Ok(())
+    }
+
+    /// Appends the runtime support functions, declarations and string constants.
+    fn finish(self) -> String {
+        let mut module = String::from("; generated by crust\n\n");
+        for (i, value) in self.strings.iter().enumerate() {
+            writeln!(
+                module,
+                "@.str.{i} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+                value.len() + 1,
+                llvm_escape(value)
+            )
+            .unwrap();
+        }
+        module.push('\n');
+        module.push_str(&self.text);
+        module.push_str(RUNTIME);
+        module
+    }
+}
+
+/// The state of the function currently being generated.
+struct Body<'m, 'f> {
+    module: &'m mut Module,
+    func: &'f Function,
+    text: String,
+    values: usize,
+    labels: usize,
+}
+
+impl Body<'_, '_> {
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.text.push_str("  ");
+        self.text.push_str(line.as_ref());
+        self.text.push('\n');
+    }
+
+    /// A fresh SSA value name.
+    fn value(&mut self) -> String {
+        self.values += 1;
+        format!("%t{}", self.values)
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("L{}", self.labels)
+    }
+
+    fn place_label(&mut self, label: &str) {
+        writeln!(self.text, "{label}:").unwrap();
+    }
+
+    /// Branches to a runtime error with `message` when the `i1` value `condition` is true.
+    fn error_if(&mut self, condition: &str, message: &str) {
+        let (fail, ok) = (self.label(), self.label());
+        self.line(format!("br i1 {condition}, label %{fail}, label %{ok}"));
+        self.place_label(&fail);
+        let message = self.module.string(message);
+        self.line(format!("call void @crust_error(i8* {message})"));
+        self.line("unreachable");
+        self.place_label(&ok);
+    }
+
+    /// The LLVM value of `operand`, loading it if it's a temporary.
+    fn operand(&mut self, operand: &Operand) -> String {
+        match operand {
+            Operand::Temp(temp) => {
+                let ty = llvm_type(self.func.temps[temp.0].ty);
+                let value = self.value();
+                self.line(format!("{value} = load {ty}, {ty}* %v{}", temp.0));
+                value
+            }
+            Operand::Int(value) => value.to_string(),
+            Operand::Str(value) => self.module.string(value),
+        }
+    }
+
+    fn store(&mut self, dest: ir::Temp, value: &str) {
+        let ty = llvm_type(self.func.temps[dest.0].ty);
+        self.line(format!("store {ty} {value}, {ty}* %v{}", dest.0));
+    }
+
+    /// A pointer to element `index` of `array`, after checking it's in bounds.
+    fn element(&mut self, array: ir::ArrayId, index: &str) -> String {
+        let ir::Array { len, ty, .. } = &self.func.arrays[array.0];
+        let (len, ty) = (*len, llvm_type(*ty));
+        let out_of_bounds = self.value();
+        self.line(format!("{out_of_bounds} = icmp uge i32 {index}, {len}"));
+        self.error_if(&out_of_bounds, "array index out of bounds");
+
+        let wide = self.value();
+        let element = self.value();
+        self.line(format!("{wide} = zext i32 {index} to i64"));
+        self.line(format!(
+            "{element} = getelementptr inbounds [{len} x {ty}], [{len} x {ty}]* %a{}, i64 0, i64 {wide}",
+            array.0
+        ));
+        element
+    }
+
+    fn inst(&mut self, inst: &Inst) -> Result<(), Diagnostic> {
+        match inst {
+            Inst::Copy { dest, src } => {
+                let value = self.operand(src);
+                self.store(*dest, &value);
+            }
+            Inst::Neg { dest, src } => {
+                let value = self.operand(src);
+                let result = self.value();
+                self.line(format!("{result} = sub i32 0, {value}"));
+                self.store(*dest, &result);
+            }
+            Inst::Not { dest, src } => {
+                let value = self.operand(src);
+                let zero = self.value();
+                self.line(format!("{zero} = icmp eq i32 {value}, 0"));
+                let result = self.value();
+                self.line(format!("{result} = zext i1 {zero} to i32"));
+                self.store(*dest, &result);
+            }
+            Inst::Binary { dest, op, lhs, rhs } => {
+                let lhs = self.operand(lhs);
+                let rhs = self.operand(rhs);
+                let result = self.value();
+                match op {
+                    BinOp::Add => self.line(format!("{result} = add i32 {lhs}, {rhs}")),
+                    BinOp::Sub => self.line(format!("{result} = sub i32 {lhs}, {rhs}")),
+                    BinOp::Mul => self.line(format!("{result} = mul i32 {lhs}, {rhs}")),
+                    BinOp::Div => {
+                        let zero = self.value();
+                        self.line(format!("{zero} = icmp eq i32 {rhs}, 0"));
+                        self.error_if(
+                            &zero,
+                            &Message::from(ArithError::DivisionByZero).to_string(),
+                        );
+
+                        // sdiv is undefined for INT_MIN / -1, whose wrapped result is the negated
+                        // dividend
+                        let minus_one = self.value();
+                        let divisor = self.value();
+                        let quotient = self.value();
+                        let negated = self.value();
+                        self.line(format!("{minus_one} = icmp eq i32 {rhs}, -1"));
+                        self.line(format!(
+                            "{divisor} = select i1 {minus_one}, i32 1, i32 {rhs}"
+                        ));
+                        self.line(format!("{quotient} = sdiv i32 {lhs}, {divisor}"));
+                        self.line(format!("{negated} = sub i32 0, {lhs}"));
+                        self.line(format!(
+                            "{result} = select i1 {minus_one}, i32 {negated}, i32 {quotient}"
+                        ));
+                    }
+                    BinOp::Rem => {
+                        let zero = self.value();
+                        self.line(format!("{zero} = icmp eq i32 {rhs}, 0"));
+                        self.error_if(
+                            &zero,
+                            &Message::from(ArithError::DivisionByZero).to_string(),
+                        );
+
+                        // srem is undefined for INT_MIN % -1, and anything % -1 is 0 like % 1
+                        let minus_one = self.value();
+                        let divisor = self.value();
+                        self.line(format!("{minus_one} = icmp eq i32 {rhs}, -1"));
+                        self.line(format!(
+                            "{divisor} = select i1 {minus_one}, i32 1, i32 {rhs}"
+                        ));
+                        self.line(format!("{result} = srem i32 {lhs}, {divisor}"));
+                    }
+                    BinOp::And => self.line(format!("{result} = and i32 {lhs}, {rhs}")),
+                    BinOp::Or => self.line(format!("{result} = or i32 {lhs}, {rhs}")),
+                    BinOp::Xor => self.line(format!("{result} = xor i32 {lhs}, {rhs}")),
+                    BinOp::Shl | BinOp::Shr => {
+                        // shifting by the width or more is poison, so only the low 5 bits of the
+                        // amount are used, as in the interpreter
+                        let amount = self.value();
+                        self.line(format!("{amount} = and i32 {rhs}, 31"));
+                        let inst = match op {
+                            BinOp::Shl => "shl",
+                            _ => "ashr",
+                        };
+                        self.line(format!("{result} = {inst} i32 {lhs}, {amount}"));
+                    }
+                    BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge | BinOp::Eq | BinOp::Ne => {
+                        let condition = match op {
+                            BinOp::Lt => "slt",
+                            BinOp::Le => "sle",
+                            BinOp::Gt => "sgt",
+                            BinOp::Ge => "sge",
+                            BinOp::Eq => "eq",
+                            _ => "ne",
+                        };
+                        let holds = self.value();
+                        self.line(format!("{holds} = icmp {condition} i32 {lhs}, {rhs}"));
+                        self.line(format!("{result} = zext i1 {holds} to i32"));
+                    }
+                }
+                self.store(*dest, &result);
+            }
+            Inst::Fill { array, value } => {
+                let ir::Array { len, ty, .. } = &self.func.arrays[array.0];
+                let (len, ty) = (*len, llvm_type(*ty));
+                let value = self.operand(value);
+
+                // fill every element with the value in a loop
+                let (check, fill, done) = (self.label(), self.label(), self.label());
+                let before = self.label();
+                self.line(format!("br label %{before}"));
+                self.place_label(&before);
+                self.line(format!("br label %{check}"));
+                self.place_label(&check);
+                let i = self.value();
+                let next = self.value();
+                let more = self.value();
+                self.line(format!("{i} = phi i64 [0, %{before}], [{next}, %{fill}]"));
+                self.line(format!("{more} = icmp ult i64 {i}, {len}"));
+                self.line(format!("br i1 {more}, label %{fill}, label %{done}"));
+                self.place_label(&fill);
+                let element = self.value();
+                self.line(format!(
+                    "{element} = getelementptr inbounds [{len} x {ty}], [{len} x {ty}]* %a{}, i64 0, i64 {i}",
+                    array.0
+                ));
+                self.line(format!("store {ty} {value}, {ty}* {element}"));
+                self.line(format!("{next} = add i64 {i}, 1"));
+                self.line(format!("br label %{check}"));
+                self.place_label(&done);
+            }
+            Inst::Load { dest, array, index } => {
+                let index = self.operand(index);
+                let element = self.element(*array, &index);
+                let ty = llvm_type(self.func.arrays[array.0].ty);
+                let result = self.value();
+                self.line(format!("{result} = load {ty}, {ty}* {element}"));
+                self.store(*dest, &result);
+            }
+            Inst::Store {
+                array,
+                index,
+                value,
+            } => {
+                let index = self.operand(index);
+                let value = self.operand(value);
+                let element = self.element(*array, &index);
+                let ty = llvm_type(self.func.arrays[array.0].ty);
+                self.line(format!("store {ty} {value}, {ty}* {element}"));
+            }
+            Inst::Call { dest, func, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        let ty = llvm_type(self.func.ty(arg));
+                        format!("{ty} {}", self.operand(arg))
+                    })
+                    .collect::<Vec<_>>();
+                match dest {
+                    Some(dest) => {
+                        let ret = llvm_type(self.func.temps[dest.0].ty);
+                        let result = self.value();
+                        self.line(format!(
+                            "{result} = call {ret} @crust_fn_{func}({})",
+                            args.join(", ")
+                        ));
+                        self.store(*dest, &result);
+                    }
+                    None => self.line(format!("call void @crust_fn_{func}({})", args.join(", "))),
+                }
+            }
+            Inst::Print { args, newline } => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.line("call i32 @putchar(i32 32)");
+                    }
+                    let ty = self.func.ty(arg);
+                    let format = match ty {
+                        Ty::Int => "@.crust.int_format",
+                        Ty::Str => "@.crust.str_format",
+                        Ty::Void => unreachable!("no operand is void"),
+                    };
+                    let value = self.operand(arg);
+                    self.line(format!(
+                        "call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* {format}, i64 0, i64 0), {} {value})",
+                        llvm_type(ty)
+                    ));
+                }
+                match newline {
+                    true => self.line("call i32 @putchar(i32 10)"),
+                    false => self.line("call i32 @fflush(i8* null)"),
+                }
+            }
+            Inst::ReadInt { dest } => {
+                let result = self.value();
+                self.line(format!("{result} = call i32 @crust_read_int()"));
+                self.store(*dest, &result);
+            }
+            Inst::StrLen { dest, src } => {
+                let value = self.operand(src);
+                let wide = self.value();
+                let result = self.value();
+                self.line(format!("{wide} = call i64 @strlen(i8* {value})"));
+                self.line(format!("{result} = trunc i64 {wide} to i32"));
+                self.store(*dest, &result);
+            }
+            Inst::Inline {
+                lang: InlineLang::Ir,
+                pieces,
+                ..
+            } => {
+                let code = pieces
+                    .iter()
+                    .map(|piece| match piece {
+                        InlinePiece::Text(text) => text.clone(),
+                        InlinePiece::Temp(temp) => format!("%v{}", temp.0),
+                        InlinePiece::Array(array) => format!("%a{}", array.0),
+                    })
+                    .collect::<String>();
+                for line in code.lines() {
+                    self.line(line);
+                }
+            }
+            // the blocks that branch here have already stored their operand to `dest`
+            Inst::Phi { .. } => {}
+            Inst::Inline { lang, span, .. } => {
+                return Err(Diagnostic::error(
+                    "E0400",
+                    Message::new("E0400.unsupported-llvm").arg(
+                        "feature",
+                        Message::new("feature.inline-block").arg("block", lang.keyword()),
+                    ),
+                )
+                .with_label(span.clone(), Message::new("label.used-here"))
+                .with_note(Message::new("note.inline-backends")))
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the operands the phis of `to` take from `from`, before `from` branches there.
+    fn phi_moves(&mut self, from: BlockId, to: BlockId) {
+        for (dest, value) in self.func.phi_moves(from, to) {
+            let value = self.operand(value);
+            self.store(dest, &value);
+        }
+    }
+
+    fn terminator(&mut self, terminator: &Terminator, block: BlockId) {
+        match terminator {
+            Terminator::Return(value) => {
+                let value = value.as_ref().map(|value| self.operand(value));
+                let depth = self.value();
+                let prev = self.value();
+                self.line(format!("{depth} = load i32, i32* @crust_call_depth"));
+                self.line(format!("{prev} = sub i32 {depth}, 1"));
+                self.line(format!("store i32 {prev}, i32* @crust_call_depth"));
+                match value {
+                    Some(value) => self.line(format!("ret {} {value}", llvm_type(self.func.ret))),
+                    None => self.line("ret void"),
+                }
+            }
+            Terminator::Jump(target) => {
+                self.phi_moves(block, *target);
+                self.line(format!("br label %{target}"));
+            }
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                // each phi is only read in its own block, so copying for both targets is harmless
+                self.phi_moves(block, *then);
+                self.phi_moves(block, *otherwise);
+                let cond = self.operand(cond);
+                let nonzero = self.value();
+                self.line(format!("{nonzero} = icmp ne i32 {cond}, 0"));
+                self.line(format!(
+                    "br i1 {nonzero}, label %{then}, label %{otherwise}"
+                ));
+            }
+            Terminator::MissingReturn => {
+                let message = format!("reached end of function {} with no return", self.func.name);
+                let message = self.module.string(&message);
+                self.line(format!("call void @crust_error(i8* {message})"));
+                self.line("unreachable");
+            }
+        }
+    }
+}
+
+/// Support functions and declarations used by generated code.
+const RUNTIME: &str = r#"@crust_call_depth = internal global i32 0
+@.crust.int_format = private unnamed_addr constant [3 x i8] c"%d\00"
+@.crust.str_format = private unnamed_addr constant [3 x i8] c"%s\00"
+@.crust.arg_format = private unnamed_addr constant [5 x i8] c"%d%c\00"
+@.crust.runtime_error = private unnamed_addr constant [19 x i8] c"runtime error: %s\0A\00"
+@.crust.read_int_error = private unnamed_addr constant [35 x i8] c"read_int: input is not a valid int\00"
+@.crust.arg_error = private unnamed_addr constant [34 x i8] c"argument '%s' is not a valid int\0A\00"
+
+define internal void @crust_error(i8* %message) noreturn {
+  call i32 @fflush(i8* null)
+  call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([19 x i8], [19 x i8]* @.crust.runtime_error, i64 0, i64 0), i8* %message)
+  call void @exit(i32 255)
+  unreachable
+}
+
+define internal i32 @crust_read_int() {
+  %value = alloca i32
+  %read = call i32 (i8*, ...) @scanf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @.crust.int_format, i64 0, i64 0), i32* %value)
+  %ok = icmp eq i32 %read, 1
+  br i1 %ok, label %done, label %fail
+done:
+  %result = load i32, i32* %value
+  ret i32 %result
+fail:
+  call void @crust_error(i8* getelementptr inbounds ([35 x i8], [35 x i8]* @.crust.read_int_error, i64 0, i64 0))
+  unreachable
+}
+
+define internal i32 @crust_parse_int(i8* %arg) {
+  %value = alloca i32
+  %trailing = alloca i8
+  %read = call i32 (i8*, i8*, ...) @sscanf(i8* %arg, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.crust.arg_format, i64 0, i64 0), i32* %value, i8* %trailing)
+  %ok = icmp eq i32 %read, 1
+  br i1 %ok, label %done, label %fail
+done:
+  %result = load i32, i32* %value
+  ret i32 %result
+fail:
+  call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([34 x i8], [34 x i8]* @.crust.arg_error, i64 0, i64 0), i8* %arg)
+  call void @exit(i32 255)
+  unreachable
+}
+
+declare i32 @printf(i8*, ...)
+declare i32 @dprintf(i32, i8*, ...)
+declare i32 @scanf(i8*, ...)
+declare i32 @sscanf(i8*, i8*, ...)
+declare i32 @putchar(i32)
+declare i32 @fflush(i8*)
+declare i64 @strlen(i8*)
+declare void @exit(i32) noreturn
+"#;
+
+fn llvm_escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' => write!(escaped, "\\{byte:02X}").unwrap(),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => write!(escaped, "\\{byte:02X}").unwrap(),
+        }
+    }
+    escaped
+}
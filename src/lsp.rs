@@ -0,0 +1,450 @@
+//! A language server for `crust lsp`, speaking JSON-RPC over stdin and stdout.
+//!
+//! Documents are synced in full, and compiled with the same pipeline as `crust check` every time
+//! one is opened or changed, publishing its errors and warnings. Hover and go-to-definition work
+//! on the functions, structs, parameters and variables in the [`SymbolTable`] of the last version
+//! of a document that compiled, so they're unavailable while it has errors.
+//!
+//! Positions are converted between the character offsets of spans and the UTF-16 code units LSP
+//! counts columns in.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    ast::Definition,
+    diagnostics::{Diagnostic, Severity},
+    lint,
+    messages::Locale,
+    pipeline::{self, Feature},
+    sources::{SourceFile, SourceMap},
+    symtab::{Symbol, SymbolKind, SymbolTable},
+    telemetry::Timings,
+    token::Span,
+    Ast, Builtins, Token,
+};
+
+/// The JSON-RPC error code for a request whose method the server doesn't implement.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Serves requests read from `input`, writing responses and notifications to `output`, until
+/// the client sends `exit`. Diagnostics are written in `locale`, and documents are compiled with
+/// the language `features` enabled.
+pub fn serve(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    features: &[Feature],
+    locale: Locale,
+) -> io::Result<()> {
+    let mut server = Server {
+        features,
+        locale,
+        documents: HashMap::new(),
+        output: &mut output,
+    };
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let result = match method {
+            "exit" => return Ok(()),
+            "initialize" => Some(json!({
+                "capabilities": {
+                    // the whole text of a document is sent on every change
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "crust", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => Some(Value::Null),
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                server.update(document["uri"].as_str(), document["text"].as_str())?;
+                None
+            }
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                server.update(params["textDocument"]["uri"].as_str(), text)?;
+                None
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    server.documents.remove(uri);
+                    server.publish(uri, Vec::new())?;
+                }
+                None
+            }
+            "textDocument/hover" => Some(server.lookup(params).map_or(Value::Null, hover)),
+            "textDocument/definition" => Some(match server.lookup(params) {
+                Some(Found::Symbol(_, declared, sources)) => {
+                    location(sources, &declared).unwrap_or(Value::Null)
+                }
+                Some(Found::Builtin(_)) | None => Value::Null,
+            }),
+            _ => None,
+        };
+
+        // notifications have no id and are never answered
+        let Some(id) = message.get("id") else {
+            continue;
+        };
+        let response = match result {
+            Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": METHOD_NOT_FOUND, "message": format!("unknown method {method}") },
+            }),
+        };
+        write_message(server.output, &response)?;
+    }
+    Ok(())
+}
+
+struct Server<'a, W> {
+    features: &'a [Feature],
+    locale: Locale,
+    /// Open documents by URI
+    documents: HashMap<String, Document>,
+    output: &'a mut W,
+}
+
+struct Document {
+    text: String,
+    /// The program and symbols of `text`, if it compiled
+    compiled: Option<(SourceMap, Ast, SymbolTable)>,
+}
+
+impl<W: Write> Server<'_, W> {
+    /// Compiles the new `text` of the document at `uri`, publishing its diagnostics.
+    fn update(&mut self, uri: Option<&str>, text: Option<&str>) -> io::Result<()> {
+        let (Some(uri), Some(text)) = (uri, text) else {
+            return Ok(());
+        };
+
+        let mut sources = SourceMap::default();
+        let result = pipeline::compile_timed(
+            &mut sources,
+            text,
+            &path(uri),
+            self.features,
+            &mut Timings::default(),
+        );
+        let (diagnostics, compiled) = match result {
+            Ok(ast) => {
+                let table = SymbolTable::new(&sources, &ast);
+                (lint::check(&sources, &ast), Some((ast, table)))
+            }
+            Err(diagnostics) => (diagnostics, None),
+        };
+
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|diagnostic| self.diagnostic(diagnostic, &sources))
+            .collect();
+        self.documents.insert(
+            uri.to_string(),
+            Document {
+                text: text.to_string(),
+                compiled: compiled.map(|(ast, table)| (sources, ast, table)),
+            },
+        );
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Value>) -> io::Result<()> {
+        write_message(
+            self.output,
+            &json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": uri, "diagnostics": diagnostics },
+            }),
+        )
+    }
+
+    /// An LSP diagnostic for the document compiled into `sources`, which is the first file in
+    /// it. One whose first label is in another file, like an import, is shown at the start of
+    /// the document with that file's name.
+    fn diagnostic(&self, diagnostic: Diagnostic, sources: &SourceMap) -> Value {
+        let diagnostic = diagnostic.localize(self.locale);
+        let document = &sources.files()[0];
+        let primary = diagnostic
+            .labels
+            .first()
+            .and_then(|label| sources.locate(&label.span));
+        let (range, prefix) = match primary {
+            Some((file, span)) if file.name == document.name => (range(file, &span), String::new()),
+            Some((file, _)) => (range(document, &(0..0)), format!("{}: ", file.name)),
+            None => (range(document, &(0..0)), String::new()),
+        };
+
+        let mut message = prefix + &diagnostic.message.to_string();
+        for note in &diagnostic.notes {
+            message += &format!("\nnote: {note}");
+        }
+        let related = diagnostic
+            .labels
+            .iter()
+            .filter_map(|label| {
+                Some(json!({
+                    "location": location(sources, &label.span)?,
+                    "message": label.message.to_string(),
+                }))
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "range": range,
+            "severity": match diagnostic.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            },
+            "code": diagnostic.code,
+            "source": "crust",
+            "message": message,
+            "relatedInformation": related,
+        })
+    }
+
+    /// What's named at the position in a hover or definition request's `params`.
+    fn lookup(&self, params: &Value) -> Option<Found<'_>> {
+        let document = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        let (sources, ast, table) = document.compiled.as_ref()?;
+        let position = &params["position"];
+        let offset = offset(
+            &document.text,
+            position["line"].as_u64()? as usize,
+            position["character"].as_u64()? as usize,
+        );
+
+        let tokens = pipeline::lex(&document.text).ok()?;
+        let (name, span) = tokens.iter().find_map(|(token, span)| match token {
+            Token::Ident(name) if span.contains(&offset) || span.end == offset => {
+                Some((name, span))
+            }
+            _ => None,
+        })?;
+        match resolve(ast, table, &tokens, name, span) {
+            Some(symbol) => {
+                // the declarations of imported symbols aren't among the document's tokens
+                let declared = name_span(&tokens, symbol).unwrap_or(symbol.span.clone());
+                Some(Found::Symbol(symbol, declared, sources))
+            }
+            None => Builtins::default()
+                .contains(name)
+                .then(|| Found::Builtin(name.to_string())),
+        }
+    }
+}
+
+/// What a name in a document refers to.
+enum Found<'a> {
+    /// A symbol declared in the program, with the span of its name in its declaration and the
+    /// sources that's in
+    Symbol(&'a Symbol, Span, &'a SourceMap),
+    /// A builtin function, which has no declaration to go to
+    Builtin(String),
+}
+
+/// The symbol `name` at `span` in the document refers to, which is the first file of the program.
+fn resolve<'a>(
+    ast: &Ast,
+    table: &'a SymbolTable,
+    tokens: &[(Token, Span)],
+    name: &str,
+    span: &Span,
+) -> Option<&'a Symbol> {
+    let symbols = table.scopes.iter().flat_map(|scope| &scope.symbols);
+    if let Some(declared) = symbols
+        .clone()
+        .find(|symbol| name_span(tokens, symbol).as_ref() == Some(span))
+    {
+        return Some(declared);
+    }
+
+    // a variable is the latest one declared before it in its function, or else a definition
+    let scope = ast
+        .defs
+        .iter()
+        .filter(|def| matches!(def, Definition::Func(_) | Definition::Struct { .. }))
+        .zip(&table.scopes[1..])
+        .find_map(|(def, scope)| match def {
+            Definition::Func(func) => {
+                let end = func.body.last().map_or(func.span.end, |(_, span)| span.end);
+                (func.span.start..end)
+                    .contains(&span.start)
+                    .then_some(scope)
+            }
+            _ => None,
+        });
+    scope
+        .into_iter()
+        .flat_map(|scope| scope.symbols.iter().rev())
+        .find(|symbol| symbol.name == name && symbol.span.end <= span.start)
+        .or_else(|| {
+            table.scopes[0]
+                .symbols
+                .iter()
+                .find(|symbol| symbol.name == name)
+        })
+}
+
+/// The span of the name in the declaration of `symbol`, which is the first identifier after its
+/// type, or all of the span of a function, struct or global, which is that of its name.
+fn name_span(tokens: &[(Token, Span)], symbol: &Symbol) -> Option<Span> {
+    if matches!(
+        symbol.kind,
+        SymbolKind::Function | SymbolKind::Struct | SymbolKind::Global | SymbolKind::Constant
+    ) {
+        return Some(symbol.span.clone());
+    }
+    tokens
+        .iter()
+        .filter(|(_, span)| span.start >= symbol.span.start && span.end <= symbol.span.end)
+        .skip(1)
+        .find(|(token, _)| *token == Token::Ident(symbol.name))
+        .map(|(_, span)| span.clone())
+}
+
+/// The hover for what a name refers to, like `variable a: int`.
+fn hover(found: Found) -> Value {
+    let text = match found {
+        Found::Symbol(symbol, ..) => format!("{} {}: {}", symbol.kind, symbol.name, symbol.ty),
+        Found::Builtin(name) => format!("builtin {name}"),
+    };
+    json!({ "contents": { "kind": "markdown", "value": format!("```crust\n{text}\n```") } })
+}
+
+/// The LSP location of `span` in `sources`.
+fn location(sources: &SourceMap, span: &Span) -> Option<Value> {
+    let (file, span) = sources.locate(span)?;
+    Some(json!({ "uri": uri(&file.name), "range": range(file, &span) }))
+}
+
+/// The LSP range of `span`, relative to `file`.
+fn range(file: &SourceFile, span: &Span) -> Value {
+    json!({ "start": position(&file.source, span.start), "end": position(&file.source, span.end) })
+}
+
+/// The LSP position of the character at `offset` in `text`.
+fn position(text: &str, offset: usize) -> Value {
+    let (mut line, mut character) = (0, 0);
+    for c in text.chars().take(offset) {
+        match c {
+            '\n' => (line, character) = (line + 1, 0),
+            c => character += c.len_utf16(),
+        }
+    }
+    json!({ "line": line, "character": character })
+}
+
+/// The character offset of an LSP position in `text`.
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let mut offset = 0;
+    for (i, text) in text.split('\n').enumerate() {
+        if i == line {
+            let mut units = 0;
+            return offset
+                + text
+                    .chars()
+                    .take_while(|c| {
+                        units += c.len_utf16();
+                        units <= character
+                    })
+                    .count();
+        }
+        offset += text.chars().count() + 1;
+    }
+    offset
+}
+
+/// The path of the file a `file://` URI names, with percent-escapes decoded, used as the name
+/// the document is compiled under so that its imports are found. Other URIs are used as is.
+fn path(uri: &str) -> String {
+    let Some(path) = uri.strip_prefix("file://") else {
+        return uri.to_string();
+    };
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let escaped = after
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &after[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The URI of the file at `path`, the reverse of [`path`].
+fn uri(path: &str) -> String {
+    if path.contains("://") {
+        return path.to_string();
+    }
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            byte => uri += &format!("%{byte:02X}"),
+        }
+    }
+    uri
+}
+
+/// Reads a message framed by a `Content-Length` header, or `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
@@ -0,0 +1,381 @@
+//! Expansion of macros, behind the `macros` language feature.
+//!
+//! `macro name(params) { body }` defines a macro, and the statement `name!(args);` in a function
+//! is replaced by the macro's body with each parameter replaced by its argument. Arguments are
+//! substituted as they're written rather than evaluated first, so an argument used twice is
+//! evaluated twice, and one whose parameter is assigned to or incremented must be a variable.
+//!
+//! Expansion is hygienic: the variables a macro declares are renamed at each expansion so they
+//! can't clash with those where it's expanded, and the macro can only reach those through its
+//! arguments. A macro can expand other macros, but never itself.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{Case, Definition, Expr, Macro, Spanned, Statement},
+    diagnostics::Diagnostic,
+    messages::Message,
+    pipeline::Feature,
+    token::Span,
+    visit::{self, Folder, Visitor},
+    Symbol,
+};
+
+/// Removes the macros from `defs`, replacing every expansion of one with its body, or reports
+/// them if `features` doesn't enable macros.
+pub fn expand(defs: &mut Vec<Definition>, features: &[Feature]) -> Vec<Diagnostic> {
+    let (macros, rest) = std::mem::take(defs)
+        .into_iter()
+        .partition::<Vec<_>, _>(|def| matches!(def, Definition::Macro(_)));
+    *defs = rest;
+    let macros = macros
+        .into_iter()
+        .filter_map(|def| match def {
+            Definition::Macro(r#macro) => Some(r#macro),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if !features.contains(&Feature::Macros) {
+        return disabled(&macros, defs);
+    }
+
+    let mut expander = Expander {
+        macros: HashMap::new(),
+        stack: Vec::new(),
+        expansions: 0,
+        diagnostics: Vec::new(),
+    };
+    for r#macro in &macros {
+        if let Some(first) = expander.macros.insert(&r#macro.name, r#macro) {
+            expander.diagnostics.push(
+                Diagnostic::error(
+                    "E0104",
+                    Message::new("E0104").arg("name", r#macro.name.as_str()),
+                )
+                .with_label(first.span.clone(), Message::new("label.first-defined"))
+                .with_label(r#macro.span.clone(), Message::new("label.defined-again")),
+            );
+        }
+        expander.diagnostics.extend(free_variables(r#macro));
+    }
+    if !expander.diagnostics.is_empty() {
+        return expander.diagnostics;
+    }
+
+    for def in defs.iter_mut() {
+        if let Definition::Func(func) = def {
+            func.body = expander.body(std::mem::take(&mut func.body));
+        }
+    }
+    expander.diagnostics
+}
+
+/// Reports every macro and expansion, for a program compiled without the `macros` feature.
+fn disabled(macros: &[Macro], defs: &[Definition]) -> Vec<Diagnostic> {
+    let error = |span: &Span, label| {
+        Diagnostic::error("E0108", Message::new("E0108"))
+            .with_label(span.clone(), Message::new(label))
+            .with_note(Message::new("note.enable-macros"))
+    };
+
+    let mut diagnostics = macros
+        .iter()
+        .map(|r#macro| error(&r#macro.span, "label.macro-defined"))
+        .collect::<Vec<_>>();
+    let mut expansions = Expansions::default();
+    for def in defs {
+        if let Definition::Func(func) = def {
+            expansions.visit_func(func);
+        }
+    }
+    diagnostics.extend(
+        expansions
+            .0
+            .into_iter()
+            .map(|span| error(span, "label.expanded-here")),
+    );
+    diagnostics
+}
+
+/// The span of every expansion, including those in the cases of a switch.
+#[derive(Default)]
+struct Expansions<'a>(Vec<&'a Span>);
+
+impl<'a> Visitor<'a> for Expansions<'a> {
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        if let (Statement::Expand { .. }, span) = statement {
+            self.0.push(span);
+        }
+        visit::walk_statement(self, statement);
+    }
+}
+
+/// Reports the variables `r#macro` uses that are neither its parameters nor declared in it.
+fn free_variables(r#macro: &Macro) -> Vec<Diagnostic> {
+    let mut scope = Scope {
+        name: &r#macro.name,
+        vars: r#macro
+            .params
+            .iter()
+            .map(|(param, _)| param.as_str())
+            .collect(),
+        diagnostics: Vec::new(),
+    };
+    for statement in &r#macro.body {
+        scope.visit_statement(statement);
+    }
+    scope.diagnostics
+}
+
+/// The variables in scope at each point of a macro's body.
+struct Scope<'a> {
+    name: &'a str,
+    vars: HashSet<&'a str>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Scope<'a> {
+    fn check(&mut self, var: &str, span: &Span) {
+        if !self.vars.contains(var) {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    "E0112",
+                    Message::new("E0112").arg("name", self.name).arg("var", var),
+                )
+                .with_label(span.clone(), Message::new("label.not-in-scope"))
+                .with_note(Message::new("note.hygiene")),
+            );
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Scope<'a> {
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        visit::walk_statement(self, statement);
+        match statement {
+            (Statement::Assign { name, .. } | Statement::Array { name, .. }, _) => {
+                self.vars.insert(name);
+            }
+            (Statement::Store { name, .. } | Statement::Reassign { name, .. }, span) => {
+                self.check(name, span)
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        if let (
+            Expr::Var(name) | Expr::PreInc(name) | Expr::PreDec(name) | Expr::AddrOf(name),
+            span,
+        ) = expr
+        {
+            self.check(name, span);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+struct Expander<'a> {
+    macros: HashMap<&'a str, &'a Macro>,
+    /// The macros being expanded, innermost last
+    stack: Vec<&'a str>,
+    /// Expansions so far, which number the variables each declares
+    expansions: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Expander<'a> {
+    /// Replaces the expansions in `body` with the statements they expand to.
+    fn body(&mut self, body: Vec<Spanned<Statement>>) -> Vec<Spanned<Statement>> {
+        let mut expanded = Vec::new();
+        for (statement, span) in body {
+            match statement {
+                Statement::Expand { name, args } => {
+                    expanded.extend(self.expand(&name, args, span));
+                }
+                Statement::Switch { expr, cases } => {
+                    let cases = cases
+                        .into_iter()
+                        .map(|case| Case {
+                            body: self.body(case.body),
+                            ..case
+                        })
+                        .collect();
+                    expanded.push((Statement::Switch { expr, cases }, span));
+                }
+                statement => expanded.push((statement, span)),
+            }
+        }
+        expanded
+    }
+
+    /// The statements `name!(args);` at `span` expands to.
+    fn expand(
+        &mut self,
+        name: &str,
+        args: Vec<Spanned<Expr>>,
+        span: Span,
+    ) -> Vec<Spanned<Statement>> {
+        let Some(&r#macro) = self.macros.get(name) else {
+            self.diagnostics.push(
+                Diagnostic::error("E0109", Message::new("E0109").arg("name", name))
+                    .with_label(span, Message::new("label.not-in-scope")),
+            );
+            return Vec::new();
+        };
+
+        if args.len() != r#macro.params.len() {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    "E0110",
+                    Message::new("E0110")
+                        .arg("name", name)
+                        .arg("expected", r#macro.params.len().to_string())
+                        .arg("found", args.len().to_string()),
+                )
+                .with_label(span, Message::new("label.wrong-arg-count"))
+                .with_label(r#macro.span.clone(), Message::new("label.macro-defined")),
+            );
+            return Vec::new();
+        }
+
+        if let Some(start) = self.stack.iter().position(|expanding| *expanding == name) {
+            let chain = self.stack[start..]
+                .iter()
+                .chain([&r#macro.name.as_str()])
+                .map(|name| format!("{name}!"))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            self.diagnostics.push(
+                Diagnostic::error("E0111", Message::new("E0111").arg("name", name))
+                    .with_label(span, Message::new("label.expanded-here"))
+                    .with_note(Message::new("note.expansion-chain").arg("chain", chain)),
+            );
+            return Vec::new();
+        }
+
+        self.expansions += 1;
+        let mut substitution = Substitution {
+            name: &r#macro.name,
+            expansion: self.expansions,
+            span: span.clone(),
+            bindings: r#macro
+                .params
+                .iter()
+                .map(|(param, _)| *param)
+                .zip(args.into_iter().map(Binding::Arg))
+                .collect(),
+            diagnostics: Vec::new(),
+        };
+        let body = r#macro
+            .body
+            .iter()
+            .map(|statement| substitution.fold_statement(statement.clone()))
+            .collect();
+        self.diagnostics.extend(substitution.diagnostics);
+
+        self.stack.push(&r#macro.name);
+        let body = self.body(body);
+        self.stack.pop();
+        body
+    }
+}
+
+/// What a name in the body of a macro being expanded stands for.
+enum Binding {
+    /// A parameter, given this argument
+    Arg(Spanned<Expr>),
+    /// A variable the macro declares, renamed to this
+    Local(Symbol),
+}
+
+/// Rewrites the body of a macro for one expansion of it.
+struct Substitution<'a> {
+    name: &'a str,
+    /// The number of the expansion, which the variables the macro declares are renamed with
+    expansion: usize,
+    /// Span of the expansion
+    span: Span,
+    bindings: HashMap<Symbol, Binding>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Substitution<'_> {
+    /// Renames a variable declared by the macro. The name can't be written in source, so it
+    /// can't clash with one where the macro is expanded.
+    fn declare(&mut self, name: Symbol) -> Symbol {
+        let renamed = Symbol::intern(&format!("{name}.{}", self.expansion));
+        self.bindings.insert(name, Binding::Local(renamed));
+        renamed
+    }
+
+    /// The variable a statement or expression at `span` changes when it changes `name`.
+    fn target(&mut self, name: Symbol, span: &Span) -> Symbol {
+        match self.bindings.get(&name) {
+            Some(Binding::Local(renamed)) => *renamed,
+            Some(Binding::Arg((Expr::Var(var), _))) => *var,
+            Some(Binding::Arg((_, arg_span))) => {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        "E0113",
+                        Message::new("E0113")
+                            .arg("name", self.name)
+                            .arg("param", name.as_str()),
+                    )
+                    .with_label(span.clone(), Message::new("label.assigned-to"))
+                    .with_label(arg_span.clone(), Message::new("label.not-a-variable"))
+                    .with_label(self.span.clone(), Message::new("label.expanded-here")),
+                );
+                name
+            }
+            // free variables were reported before expanding anything
+            None => name,
+        }
+    }
+}
+
+impl Folder for Substitution<'_> {
+    fn fold_statement(&mut self, statement: Spanned<Statement>) -> Spanned<Statement> {
+        let (statement, span) = visit::fold_statement(self, statement);
+        let statement = match statement {
+            Statement::Assign { ty, name, expr } => Statement::Assign {
+                ty,
+                name: self.declare(name),
+                expr,
+            },
+            Statement::Array { ty, name, len } => Statement::Array {
+                ty,
+                name: self.declare(name),
+                len,
+            },
+            Statement::Store { name, index, expr } => Statement::Store {
+                name: self.target(name, &span),
+                index,
+                expr,
+            },
+            Statement::Reassign { name, op, expr } => Statement::Reassign {
+                name: self.target(name, &span),
+                op,
+                expr,
+            },
+            statement => statement,
+        };
+        (statement, span)
+    }
+
+    fn fold_expr(&mut self, expr: Spanned<Expr>) -> Spanned<Expr> {
+        let (expr, span) = visit::fold_expr(self, expr);
+        match expr {
+            Expr::Var(name) => match self.bindings.get(&name) {
+                Some(Binding::Arg(arg)) => arg.clone(),
+                Some(Binding::Local(renamed)) => (Expr::Var(*renamed), span),
+                None => (Expr::Var(name), span),
+            },
+            Expr::PreInc(name) => (Expr::PreInc(self.target(name, &span)), span),
+            Expr::PreDec(name) => (Expr::PreDec(self.target(name, &span)), span),
+            Expr::AddrOf(name) => (Expr::AddrOf(self.target(name, &span)), span),
+            expr => (expr, span),
+        }
+    }
+}
@@ -1,118 +1,1695 @@
-use std::{fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, Mutex},
+};
 
-use ariadne::{Label, Report, ReportKind, Source, Span};
-use chumsky::{chain::Chain, Parser as CParser, Stream};
-use clap::{Args, Parser, Subcommand};
-use crust::{Ast, Token};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use crust::{
+    artifact::{self, Artifact},
+    ast::{Hook, DEFAULT_MAX_CALL_DEPTH},
+    codegen, comptime,
+    config::{self, Config, Source},
+    debug::Debugger,
+    harness,
+    interrupt::{self, Interrupt},
+    isolate::{self, Limits},
+    messages::{Locale, Message},
+    opt,
+    pipeline::{Feature, Options},
+    repl::Session,
+    semantics::{IntMode, Overflow, Width},
+    sources::SourceMap,
+    telemetry::{Metrics, ProgramSize, Timings},
+    trace::{Profiler, Tracer},
+    watch::Watcher,
+    Ast, Builtins, Diagnostic, Program, RunOptions, Value,
+};
 
 #[derive(Parser, Debug)]
-#[command(version, about)]
+#[command(version, about, disable_version_flag = true)]
 struct Cli {
     #[command(subcommand)]
-    commands: Commands,
+    commands: Option<Commands>,
+    /// Print version
+    #[arg(short = 'V', long)]
+    version: bool,
+    /// Write how long each pass took and how big the program is to stderr, the same as setting
+    /// `log` to `human`. With --version, also print the IR format version, features, emit
+    /// targets and builtins
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// How diagnostics are written to stderr [default: human]
+    #[arg(long, global = true, value_enum)]
+    error_format: Option<ErrorFormat>,
+    /// Language diagnostics are written in [default: en]
+    #[arg(long, global = true, value_enum)]
+    locale: Option<Locale>,
+    /// Experimental language features to enable, separated by commas
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    features: Vec<Feature>,
+    /// Report warnings as errors, failing the command if there are any
+    #[arg(long, global = true)]
+    deny_warnings: bool,
+}
+
+/// How the metrics of an invocation are written to stderr, with `--verbose` or `log`.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Log {
+    /// A table of pass timings followed by the size of the program
+    Human,
+    /// The same JSON object telemetry records
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ErrorFormat {
+    Human,
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
+    /// Compile a source file to IR or native code
     Build(BuildArgs),
+    /// Run a source file or IR with the interpreter
     Run(RunArgs),
+    /// Check a source file for errors without producing any output
+    Check(CheckArgs),
+    /// Run every source file in a directory, checking its exit code and output against the
+    /// `// expect-exit:` and `// expect-output:` comments in it
+    Test(TestArgs),
+    /// Enter definitions, statements and expressions one at a time and see their values
+    Repl(ReplArgs),
+    /// Evaluate an expression on the bytecode VM and print its value
+    Eval(EvalArgs),
+    /// Rewrite a source file with canonical indentation, spacing and brace placement
+    Fmt(FmtArgs),
+    /// Write the AST or call graph of a source file as a Graphviz DOT graph
+    Viz(VizArgs),
+    /// Run a language server for editors, speaking LSP over stdin and stdout
+    Lsp,
+    /// Inspect IR files
+    #[command(subcommand)]
+    Ir(IrCommands),
+    /// Generate a TextMate grammar, snippets and language configuration for editors
+    EditorSupport(EditorSupportArgs),
+    /// Print a shell completion script
+    Completions(CompletionsArgs),
+    /// Print the man page, in roff format
+    Manpage,
+    /// Print the precedence and associativity of every operator, from the tightest binding
+    Precedence,
+    /// Inspect settings from crust.toml and CRUST_* environment variables
+    #[command(subcommand)]
+    Config(ConfigCommands),
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print the settings from the config file
+    Show(ShowArgs),
+}
+
+#[derive(Args, Debug)]
+struct ShowArgs {
+    /// Print every setting after applying environment variables, flags and defaults
+    #[arg(long)]
+    resolved: bool,
+}
+
+#[derive(Args, Debug)]
+struct CompletionsArgs {
+    shell: Shell,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Subcommand, Debug)]
+enum IrCommands {
+    /// Print an IR file (binary or JSON) as readable JSON
+    Dump(DumpArgs),
+}
+
+#[derive(Args, Debug)]
+struct DumpArgs {
+    /// The IR file to print
+    input: PathBuf,
+    /// Write the JSON to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Emit {
+    /// Pretty printed JSON IR
+    Json,
+    /// Compact binary IR
+    Bin,
+    /// The source listing with the IR each line compiles to
+    Annotated,
+    /// x86-64 assembly
+    Asm,
+    /// An x86-64 object file, assembled with the system C compiler
+    Obj,
+    /// A native x86-64 executable, linked with the system C compiler
+    Exe,
+    /// LLVM IR text, which can be compiled with clang or llc
+    Llvm,
+    /// C99 source, which can be compiled with `cc -std=c99`
+    C,
+    /// Three-address code in basic blocks, as text
+    Ir,
+    /// The AST in prefix form after optimization, at the highest level unless one is given
+    AstOpt,
+    /// A table of the tokens with their positions and kinds, stopping after lexing
+    Tokens,
+    /// The AST drawn as a tree, stopping after parsing
+    Ast,
+    /// Three-address code as textbook quadruples, with numbered temporaries and labels
+    Tac,
+    /// The symbols declared in each scope after analysis, as JSON if the output ends in `.json`
+    Symtab,
+}
+
+impl Emit {
+    /// The extension of the files written for each input when several are compiled to a
+    /// directory, which `build` infers the same format from where it can.
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::Json => "json",
+            Emit::Bin => "bin",
+            Emit::Annotated | Emit::Symtab => "txt",
+            Emit::Asm => "s",
+            Emit::Obj => "o",
+            Emit::Exe => "",
+            Emit::Llvm => "ll",
+            Emit::C => "c",
+            Emit::Ir => "ir",
+            Emit::AstOpt => "ast",
+            Emit::Tokens => "tokens",
+            Emit::Ast => "tree",
+            Emit::Tac => "tac",
+        }
+    }
 }
 
 #[derive(Args, Debug)]
 struct BuildArgs {
+    /// The source files to compile, or directories to compile every source file in, followed by
+    /// where to write the output unless it's given with `-o`
+    #[arg(required = true, value_name = "PATHS")]
+    paths: Vec<PathBuf>,
+    /// Where to write the output, a directory to write a file for each input to when several are
+    /// compiled without `--link`
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Link the inputs into one program instead of compiling each on its own
+    #[arg(long)]
+    link: bool,
+    /// How many files to compile at once [default: the number of CPUs]
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+    /// Only emit the function with this name and those it calls (struct definitions are kept)
+    #[arg(long, value_name = "NAME")]
+    only_fn: Option<String>,
+    /// Output format, inferred from the output extension when omitted (`.bin` is binary)
+    #[arg(long, value_enum)]
+    emit: Option<Emit>,
+    /// Optimize the program, the same as `--opt-level 2`
+    #[arg(short = 'O', conflicts_with = "opt_level")]
+    optimize: bool,
+    /// 0 doesn't optimize, 1 folds constants and simplifies arithmetic, 2 also removes dead code
+    #[arg(long, value_name = "LEVEL", value_parser = clap::value_parser!(u8).range(0..=2))]
+    opt_level: Option<u8>,
+    /// Validate the program again after every optimization pass and after lowering, reporting
+    /// the pass that broke it
+    #[arg(long)]
+    self_check: bool,
+    /// Build again whenever an input changes, until stopped with Ctrl-C
+    #[arg(long)]
+    watch: bool,
+}
+
+#[derive(Args, Debug)]
+struct EditorSupportArgs {
+    /// Directory to write the files to, created if it doesn't exist
+    #[arg(long)]
+    out: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct CheckArgs {
+    /// The source file to check
+    input: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct TestArgs {
+    /// The directory to find source files in, including its subdirectories
+    dir: PathBuf,
+    /// How many tests to run at once [default: the number of CPUs]
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+    /// Run the tests with the bytecode VM instead of walking the AST
+    #[arg(long)]
+    vm: bool,
+}
+
+#[derive(Args, Debug)]
+struct ReplArgs {
+    /// Resume a session saved with `:save`
+    #[arg(long, value_name = "FILE")]
+    load: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct EvalArgs {
+    /// The expression to evaluate, which can call builtins but has no variables
+    expr: String,
+    /// Print the bytecode the expression compiles to, then the stack after each op
+    #[arg(long)]
+    show_stack: bool,
+    /// Milliseconds to pause between steps of --show-stack when stdout is a terminal
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 400,
+        requires = "show_stack"
+    )]
+    delay: u64,
+}
+
+#[derive(Args, Debug)]
+struct FmtArgs {
+    /// The source file to format
     input: PathBuf,
-    output: PathBuf,
+    /// Don't rewrite the file, but fail if it isn't formatted
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(Args, Debug)]
+struct VizArgs {
+    /// Which graph to draw
+    graph: Graph,
+    /// The source file to draw
+    input: PathBuf,
+    /// Write the graph to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Graph {
+    /// The syntax tree, with a node for every definition, statement and expression
+    Ast,
+    /// The functions each function calls
+    Calls,
 }
 
 #[derive(Args, Debug)]
 #[command(version, about)]
 struct RunArgs {
+    /// A source file, or IR produced by `build`
     input: PathBuf,
+    /// Treat the input as source code regardless of its extension
+    #[arg(long)]
+    from_source: bool,
+    /// Maximum depth of nested function calls before reporting a stack overflow [default: 1000]
+    #[arg(long, value_name = "DEPTH")]
+    max_call_depth: Option<usize>,
+    /// Validate the program again before running it
+    #[arg(long)]
+    self_check: bool,
+    /// Run with the bytecode VM instead of walking the AST
+    #[arg(long)]
+    vm: bool,
+    /// Number of bits in an int [default: 32]
+    #[arg(long, value_enum, value_name = "BITS")]
+    int_width: Option<Width>,
+    /// Whether arithmetic that overflows an int wraps around or fails with an error
+    /// [default: wrap]
+    #[arg(long, value_enum)]
+    overflow: Option<Overflow>,
+    /// Pause before the first statement and step through the program, setting breakpoints and
+    /// inspecting variables at a prompt (type `help` there for its commands)
+    #[arg(long, conflicts_with = "vm")]
+    debug: bool,
+    /// Print each statement to stderr as it runs, with the values of the variables it reads
+    #[arg(long, conflicts_with = "vm")]
+    trace: bool,
+    /// Count the calls to each function and the time spent in them, printing a table of them to
+    /// stderr when the program finishes
+    #[arg(long, conflicts_with = "vm")]
+    profile: bool,
+    /// Run in a child process with limits on its CPU time and memory, reporting it as an error
+    /// if it's killed
+    #[arg(long)]
+    isolate: bool,
+    /// CPU time the isolated program can use before it's killed [default: 10]
+    #[arg(long, value_name = "SECONDS", requires = "isolate")]
+    cpu_limit: Option<u64>,
+    /// Memory the isolated program can allocate [default: 512]
+    #[arg(long, value_name = "MB", requires = "isolate")]
+    memory_limit: Option<u64>,
+    /// Run the program again whenever its source changes, until stopped with Ctrl-C
+    #[arg(long)]
+    watch: bool,
+    /// Set a variable the program reads with `getenv`, which can't read any other (repeatable)
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env)]
+    env: Vec<(String, String)>,
+    /// Arguments passed to the program's main function
+    #[arg(last = true)]
+    args: Vec<String>,
 }
 
 fn main() {
-    match Cli::parse().commands {
-        Commands::Build(args) => build(args),
-        Commands::Run(args) => run(args),
+    let cli = Cli::parse();
+    if cli.version {
+        print!("{}", version(cli.verbose));
+        return;
+    }
+    let Some(commands) = cli.commands else {
+        cli_command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a subcommand is required but one was not provided",
+            )
+            .exit()
+    };
+    // settings are reported as they're resolved, before the locale is known
+    let mut invocation = Invocation::new(Options::default(), ErrorFormat::Human);
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(diagnostic) => {
+            invocation.format = cli.error_format.unwrap_or(ErrorFormat::Human);
+            invocation.options.locale = cli.locale.unwrap_or_default();
+            invocation.report(&diagnostic, &SourceMap::default(), config::FILE_NAME);
+            invocation.exit(-1);
+        }
+    };
+
+    let format = setting(
+        &invocation,
+        &config,
+        "error-format",
+        cli.error_format,
+        ErrorFormat::parse,
+    )
+    .map_or(ErrorFormat::Human, |(format, _)| format);
+    invocation.options.locale = setting(&invocation, &config, "locale", cli.locale, parse_locale)
+        .map_or(Locale::En, |(locale, _)| locale);
+    let file = setting(&invocation, &config, "telemetry.file", None, |value| {
+        Ok(PathBuf::from(value))
+    })
+    .map(|(path, _)| path);
+    let log = setting(
+        &invocation,
+        &config,
+        "log",
+        cli.verbose.then_some(Log::Human),
+        Log::parse,
+    )
+    .map(|(log, _)| log);
+    invocation.format = format;
+    invocation.options.features = cli.features.clone();
+    invocation.options.deny_warnings = cli.deny_warnings;
+    if file.is_some() || log.is_some() {
+        *invocation.telemetry.lock().unwrap() = Some(Telemetry {
+            file,
+            log,
+            metrics: Metrics::new(commands.name()),
+        });
     }
+
+    match commands {
+        Commands::Build(args) => build(args, &config, &invocation),
+        Commands::Run(args) => run(args, &config, &invocation),
+        Commands::Check(args) => check(args, &invocation),
+        Commands::Test(args) => test(args, &invocation),
+        Commands::Repl(args) => repl(args, &invocation),
+        Commands::Eval(args) => eval(args, &invocation),
+        Commands::Fmt(args) => fmt(args, &invocation),
+        Commands::Viz(args) => viz(args, &invocation),
+        Commands::Lsp => lsp(&invocation),
+        Commands::Ir(IrCommands::Dump(args)) => dump(args, &invocation),
+        Commands::EditorSupport(args) => editor_support(args, &invocation),
+        Commands::Completions(args) => completions(args),
+        Commands::Manpage => print!("{}", crust::completions::manpage(&cli_command())),
+        Commands::Precedence => print!("{}", crust::precedence::table()),
+        Commands::Config(ConfigCommands::Show(args)) => {
+            config_show(args, &config, &invocation, cli.error_format, cli.locale)
+        }
+    }
+    invocation.write_telemetry(0);
 }
 
-fn build(args: BuildArgs) {
-    let source = match fs::read_to_string(&args.input) {
-        Ok(code) => code,
-        Err(e) => {
-            eprintln!("Failed to read file: {e}");
+/// The text printed by `--version`, which with `verbose` also describes what this build supports
+/// so that bug reports can pin down the environment they came from.
+fn version(verbose: bool) -> String {
+    let mut text = format!("crust {}\n", artifact::COMPILER_VERSION);
+    if verbose {
+        let emit = value_names::<Emit>();
+        let features = value_names::<Feature>();
+        text += &format!("IR format version: {}\n", artifact::FORMAT_VERSION);
+        text += &format!("features: {features}\n");
+        text += &format!("emit targets: {emit}\n");
+        text += &format!("builtins: {}\n", Builtins::default().names().join(", "));
+    }
+    text
+}
+
+/// What this invocation was configured with, which every command is given rather than reading it
+/// from statics, so that `build` can compile several inputs at once on threads of its own.
+#[derive(Clone)]
+struct Invocation {
+    options: Options,
+    format: ErrorFormat,
+    /// Metrics for this invocation, if telemetry or logging is enabled, shared by every thread
+    telemetry: Arc<Mutex<Option<Telemetry>>>,
+    /// What's been reported, when it's held back to be written to stderr all at once
+    held: Option<Arc<Mutex<Vec<u8>>>>,
+    /// The files read so far, when they're watched to run the command again with `--watch`
+    watcher: Option<Arc<Mutex<Watcher>>>,
+}
+
+/// Where this invocation's metrics go once it's done.
+struct Telemetry {
+    /// The file they're appended to, with the `telemetry.file` setting
+    file: Option<PathBuf>,
+    /// How they're written to stderr, with `--verbose` or the `log` setting
+    log: Option<Log>,
+    metrics: Metrics,
+}
+
+/// A command that failed, once what went wrong has been reported.
+#[derive(Debug)]
+struct Failed;
+
+impl Invocation {
+    fn new(options: Options, format: ErrorFormat) -> Self {
+        Self {
+            options,
+            format,
+            telemetry: Arc::new(Mutex::new(None)),
+            held: None,
+            watcher: None,
+        }
+    }
+
+    /// The same invocation, but holding back what it reports until [`Invocation::take_held`].
+    fn held(&self) -> Self {
+        Self {
+            held: Some(Arc::new(Mutex::new(Vec::new()))),
+            ..self.clone()
+        }
+    }
+
+    /// The same invocation, for one run of a command that's watching `inputs` and every file it
+    /// reads, with metrics of its own.
+    fn watching(&self, inputs: &[PathBuf]) -> Self {
+        let telemetry = self
+            .telemetry
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|telemetry| Telemetry {
+                file: telemetry.file.clone(),
+                log: telemetry.log,
+                metrics: Metrics::new(telemetry.metrics.command.clone()),
+            });
+        Self {
+            telemetry: Arc::new(Mutex::new(telemetry)),
+            watcher: Some(Arc::new(Mutex::new(Watcher::new(inputs.to_vec())))),
+            ..self.clone()
+        }
+    }
+
+    /// Watches every file in `sources`, if the command is watching the files it reads.
+    fn watch_sources(&self, sources: &SourceMap) {
+        if let Some(watcher) = &self.watcher {
+            let mut watcher = watcher.lock().unwrap();
+            for file in sources.files() {
+                watcher.add(PathBuf::from(&file.name));
+            }
+        }
+    }
+
+    /// What's been reported and held back so far.
+    fn take_held(&self) -> Vec<u8> {
+        self.held
+            .as_ref()
+            .map_or_else(Vec::new, |held| std::mem::take(&mut held.lock().unwrap()))
+    }
+
+    /// Writes `message` to stderr as a line of its own, or holds it back.
+    fn eprintln(&self, message: impl std::fmt::Display) {
+        match &self.held {
+            Some(held) => writeln!(held.lock().unwrap(), "{message}").unwrap(),
+            None => eprintln!("{message}"),
+        }
+    }
+
+    /// Writes a diagnostic to stderr, or holds it back, attributing it to `fallback` if its spans
+    /// aren't in `sources`.
+    fn report(&self, diagnostic: &Diagnostic, sources: &SourceMap, fallback: &str) {
+        self.with_metrics(|metrics| metrics.record(diagnostic));
+        let diagnostic = diagnostic.clone().localize(self.options.locale);
+        let mut rendered = Vec::new();
+        match self.format {
+            ErrorFormat::Json => writeln!(rendered, "{}", diagnostic.to_json(sources, fallback)),
+            ErrorFormat::Human => diagnostic.write(sources, fallback, &mut rendered),
+        }
+        .unwrap();
+        match &self.held {
+            Some(held) => held.lock().unwrap().extend(rendered),
+            None => io::stderr().write_all(&rendered).unwrap(),
+        }
+    }
+
+    /// Applies `f` to this invocation's metrics, if telemetry is enabled.
+    fn with_metrics(&self, f: impl FnOnce(&mut Metrics)) {
+        if let Some(telemetry) = self.telemetry.lock().unwrap().as_mut() {
+            f(&mut telemetry.metrics);
+        }
+    }
+
+    /// Runs `f`, adding the time it takes to `pass` when telemetry is enabled.
+    fn timed<T>(&self, pass: &'static str, f: impl FnOnce() -> T) -> T {
+        let mut timings = Timings::default();
+        let value = timings.time(pass, f);
+        self.with_metrics(|metrics| metrics.timings.extend(timings));
+        value
+    }
+
+    /// Appends this invocation's metrics to the telemetry file and writes them to stderr, if
+    /// either is enabled.
+    ///
+    /// Failing to write metrics is reported, but never changes the outcome of the command.
+    fn write_telemetry(&self, exit_code: i32) {
+        let Some(Telemetry { file, log, metrics }) = self.telemetry.lock().unwrap().take() else {
             return;
+        };
+        match log {
+            Some(Log::Human) => eprint!("{}", metrics.to_text()),
+            Some(Log::Json) => eprintln!("{}", metrics.to_json(exit_code)),
+            None => {}
         }
-    };
+        if let Some(path) = file {
+            if let Err(e) = metrics.append(&path, exit_code) {
+                eprintln!("Failed to write telemetry to {}: {e}", path.display());
+            }
+        }
+    }
+
+    fn exit(&self, code: i32) -> ! {
+        self.write_telemetry(code);
+        std::process::exit(code)
+    }
+}
+
+impl Commands {
+    /// The name of the subcommand, as recorded in telemetry.
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Build(_) => "build",
+            Commands::Run(_) => "run",
+            Commands::Check(_) => "check",
+            Commands::Test(_) => "test",
+            Commands::Repl(_) => "repl",
+            Commands::Eval(_) => "eval",
+            Commands::Fmt(_) => "fmt",
+            Commands::Viz(_) => "viz",
+            Commands::Lsp => "lsp",
+            Commands::Ir(_) => "ir",
+            Commands::EditorSupport(_) => "editor-support",
+            Commands::Completions(_) => "completions",
+            Commands::Manpage => "manpage",
+            Commands::Precedence => "precedence",
+            Commands::Config(_) => "config",
+        }
+    }
+}
+
+impl ErrorFormat {
+    fn parse(value: &str) -> Result<Self, String> {
+        Self::from_str(value, true)
+    }
+}
+
+impl Log {
+    fn parse(value: &str) -> Result<Self, String> {
+        Self::from_str(value, true)
+    }
+}
+
+impl Emit {
+    fn parse(value: &str) -> Result<Self, String> {
+        Self::from_str(value, true)
+    }
+}
+
+/// Resolves a setting from its flag, falling back to the environment and then the config file.
+///
+/// Exits if the setting has a value that can't be parsed.
+fn setting<T>(
+    invocation: &Invocation,
+    config: &Config,
+    key: &str,
+    flag: Option<T>,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Option<(T, Source)> {
+    if let Some(value) = flag {
+        return Some((value, Source::Flag));
+    }
+
+    let (value, source) = config.get(key)?;
+    match parse(&value) {
+        Ok(value) => Some((value, source)),
+        Err(e) => {
+            let diagnostic = Diagnostic::error(
+                "E0501",
+                Message::new("E0501")
+                    .arg("value", value.as_str())
+                    .arg("key", key)
+                    .arg("source", source.to_string()),
+            )
+            .with_note(Message::Text(e));
+            let invocation = Invocation {
+                format: ErrorFormat::Human,
+                ..invocation.clone()
+            };
+            invocation.report(&diagnostic, &SourceMap::default(), key);
+            invocation.exit(-1);
+        }
+    }
+}
 
-    let source_len = source.chars().len();
-    let filename = args
-        .input
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .to_string();
-    let (Some(tokens), lexer_errors) = Token::lexer().parse_recovery(source.as_str()) else {
-        eprintln!("Failed to generate tokens...");
+fn config_show(
+    args: ShowArgs,
+    config: &Config,
+    invocation: &Invocation,
+    error_format: Option<ErrorFormat>,
+    locale: Option<Locale>,
+) {
+    if !args.resolved {
+        match &config.path {
+            Some(path) => println!("# {}", path.display()),
+            None => println!("# no {} found", config::FILE_NAME),
+        }
+        for (key, value) in &config.values {
+            println!("{key} = {}", toml_value(value));
+        }
         return;
+    }
+
+    for config::Setting { key, description } in config::SETTINGS {
+        let (value, source) = match *key {
+            "error-format" => setting(invocation, config, key, error_format, ErrorFormat::parse)
+                .map(|(format, source)| (value_name(format), source)),
+            "locale" => setting(invocation, config, key, locale, parse_locale)
+                .map(|(locale, source)| (value_name(locale), source)),
+            "build.emit" => setting(invocation, config, key, None, Emit::parse)
+                .map(|(emit, source)| (value_name(emit), source)),
+            "run.max-call-depth" => setting(invocation, config, key, None, parse_depth)
+                .map(|(depth, source)| (depth.to_string(), source)),
+            "telemetry.file" => config.get(key),
+            _ => unreachable!("setting {key} is not resolved"),
+        }
+        .unwrap_or_else(|| match *key {
+            "error-format" => ("human".into(), Source::Default),
+            "locale" => ("en".into(), Source::Default),
+            "build.emit" => ("inferred from the output extension".into(), Source::Default),
+            "telemetry.file" => ("disabled".into(), Source::Default),
+            _ => (DEFAULT_MAX_CALL_DEPTH.to_string(), Source::Default),
+        });
+        println!("# {description}");
+        println!("{key} = {}  # from {source}", toml_value(&value));
+    }
+}
+
+/// Formats a setting's value as it would be written in the config file.
+fn toml_value(value: &str) -> String {
+    match value.parse::<i64>().is_ok() || value == "true" || value == "false" {
+        true => value.to_string(),
+        false => format!("{value:?}"),
+    }
+}
+
+fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
+
+/// The name of every value of `T`, separated by commas.
+fn value_names<T: ValueEnum>() -> String {
+    let names = T::value_variants()
+        .iter()
+        .map(|value| value_name(value.clone()));
+    names.collect::<Vec<_>>().join(", ")
+}
+
+fn parse_locale(value: &str) -> Result<Locale, String> {
+    Locale::from_str(value, true)
+}
+
+fn parse_depth(value: &str) -> Result<usize, String> {
+    value.parse().map_err(|e| format!("{e}"))
+}
+
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(String::from("expected KEY=VALUE")),
+    }
+}
+
+/// Reads and compiles `input`, reporting any diagnostics and then any warnings.
+///
+/// Returns `None` if the file could not be read or failed to compile, or if it has warnings and
+/// `--deny-warnings` was given.
+fn compile_file(input: &Path, invocation: &Invocation) -> Option<Program> {
+    compile_files(&[input.to_path_buf()], invocation)
+}
+
+/// Like [`compile_file`], but links `inputs` into one program named after the first of them.
+fn compile_files(inputs: &[PathBuf], invocation: &Invocation) -> Option<Program> {
+    let mut files = Vec::new();
+    for input in inputs {
+        match fs::read_to_string(input) {
+            Ok(source) => files.push((input.to_string_lossy().to_string(), source)),
+            Err(e) => {
+                invocation.eprintln(format_args!("Failed to read {}: {e}", input.display()));
+                return None;
+            }
+        }
+    }
+
+    let filename = files[0].0.clone();
+    let mut sources = SourceMap::default();
+    let mut timings = Timings::default();
+    let files = files
+        .iter()
+        .map(|(name, source)| (name.as_str(), source.as_str()))
+        .collect::<Vec<_>>();
+    let result =
+        crust::pipeline::link_with(&mut sources, &files, &invocation.options, &mut timings);
+    invocation.with_metrics(|metrics| metrics.timings.extend(timings));
+    // imports are recorded as they're read, even if compiling fails
+    invocation.watch_sources(&sources);
+    match result {
+        Ok((ast, warnings)) => {
+            invocation
+                .with_metrics(|metrics| metrics.size = Some(ProgramSize::new(&sources, &ast)));
+            for warning in warnings {
+                invocation.report(&warning, &sources, &filename);
+            }
+            Some(Program::new(filename, sources, ast))
+        }
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                invocation.report(&diagnostic, &sources, &filename);
+            }
+            None
+        }
+    }
+}
+
+fn check(args: CheckArgs, invocation: &Invocation) {
+    if compile_file(&args.input, invocation).is_none() {
+        invocation.exit(-1);
+    }
+}
+
+/// Reads inputs from stdin until it ends or `:quit` is entered, continuing an input over the
+/// lines after it while it has unclosed delimiters.
+fn test(args: TestArgs, invocation: &Invocation) {
+    let paths = harness::discover(&args.dir).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {e}", args.dir.display());
+        invocation.exit(-1);
+    });
+    let exe = std::env::current_exe().unwrap_or_else(|e| {
+        eprintln!("Failed to find the crust executable: {e}");
+        invocation.exit(-1);
+    });
+    let features = invocation
+        .options
+        .features
+        .iter()
+        .map(|feature| value_name(*feature))
+        .collect::<Vec<_>>();
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+
+    let reports = harness::run_all(&paths, jobs, |path| {
+        let mut command = Command::new(&exe);
+        if !features.is_empty() {
+            command.arg("--features").arg(features.join(","));
+        }
+        command.arg("run").arg(path);
+        if args.vm {
+            command.arg("--vm");
+        }
+        command
+    });
+    print!("{}", harness::summary(&reports, &args.dir));
+    if reports
+        .iter()
+        .any(|report| matches!(report.outcome, harness::Outcome::Failed(_)))
+    {
+        invocation.exit(1);
+    }
+}
+
+fn repl(args: ReplArgs, invocation: &Invocation) {
+    let mut session = match args.load {
+        Some(path) => Session::load(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to load {}: {e}", path.display());
+            invocation.exit(-1);
+        }),
+        None => Session::default(),
     };
 
-    for error in lexer_errors.iter() {
-        Report::build(ReportKind::Error, &filename, error.span().start())
-            .with_message("Lexer Error")
-            .with_label(Label::new((&filename, error.span())).with_message(&error))
-            .finish()
-            .eprint((&filename, Source::from(source.as_str())))
-            .unwrap();
+    let interactive = io::stdin().is_terminal();
+    let mut lines = io::stdin().lines();
+    loop {
+        let mut input = String::new();
+        loop {
+            if interactive {
+                print!("{}", if input.is_empty() { "> " } else { "... " });
+                io::stdout().flush().unwrap();
+            }
+            match lines.next() {
+                Some(Ok(line)) => input += &line,
+                _ if input.is_empty() => return,
+                _ => break,
+            }
+            if !crust::repl::is_incomplete(&input) {
+                break;
+            }
+            input.push('\n');
+        }
+
+        let input = input.trim();
+        match input.split_once(' ').unwrap_or((input, "")) {
+            ("", _) => {}
+            (":quit" | ":q", _) => return,
+            (":save", path) => {
+                if let Err(e) = session.save(Path::new(path)) {
+                    eprintln!("Failed to save {path}: {e}");
+                }
+            }
+            (":load", path) => match Session::load(Path::new(path)) {
+                Ok(loaded) => session = loaded,
+                Err(e) => eprintln!("Failed to load {path}: {e}"),
+            },
+            (command, _) if command.starts_with(':') => {
+                eprintln!("Unknown command {command}, expected :save, :load or :quit")
+            }
+            _ => match session.eval(input) {
+                Ok(Some(value)) => println!("{value}"),
+                Ok(None) => {}
+                Err(diagnostics) => {
+                    for diagnostic in diagnostics {
+                        invocation.report(&diagnostic, session.sources(), "<repl>");
+                    }
+                }
+            },
+        }
     }
+}
 
-    let (ast, parse_errors) = Ast::parser().parse_recovery(Stream::from_iter(
-        source_len..source_len + 1,
-        tokens.into_iter(),
-    ));
+/// Evaluates an expression with the VM. With `--show-stack` the bytecode is listed first, and
+/// each op is printed with the stack it leaves, pausing between them on a terminal so that the
+/// stack can be watched as it grows and shrinks.
+fn eval(args: EvalArgs, invocation: &Invocation) {
+    let mut sources = SourceMap::default();
+    sources.add("<eval>", args.expr.as_str());
+    let expr = match invocation.timed("parse", || crust::pipeline::parse_expr(&args.expr)) {
+        Ok(expr) => expr,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                invocation.report(&diagnostic, &sources, "<eval>");
+            }
+            invocation.exit(-1);
+        }
+    };
+
+    let options = RunOptions::default();
+    let bytecode = crust::vm::compile_expr(&expr, &options.builtins);
+    if !args.show_stack {
+        match invocation.timed("run", || bytecode.run(0, Vec::new(), &options)) {
+            Ok(value) => println!("{value}"),
+            Err(diagnostic) => {
+                invocation.report(&diagnostic, &sources, "<eval>");
+                invocation.exit(-1);
+            }
+        }
+        return;
+    }
 
-    for error in parse_errors.iter() {
-        Report::build(ReportKind::Error, &filename, error.span().start())
-            .with_message("Parser Error")
-            .with_label(Label::new((&filename, error.span())).with_message(&error))
-            .finish()
-            .eprint((&filename, Source::from(source.as_str())))
-            .unwrap();
+    let code = &bytecode.funcs[0].code;
+    let width = code.len().to_string().len();
+    println!("bytecode:");
+    for (i, op) in code.iter().enumerate() {
+        println!("  {i:>width$}  {op}");
     }
+    println!();
+    println!("stack:");
 
-    if !parse_errors.is_empty() || !lexer_errors.is_empty() {
-        std::process::exit(-1);
+    let delay = match io::stdout().is_terminal() {
+        true => std::time::Duration::from_millis(args.delay),
+        false => std::time::Duration::ZERO,
+    };
+    let result = bytecode.trace(0, Vec::new(), &options, |at, op, stack| {
+        let stack = stack
+            .iter()
+            .map(|value| match value {
+                Value::Str(value) => format!("{value:?}"),
+                value => value.to_string(),
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "  {at:>width$}  {:<16}[{}]",
+            op.to_string(),
+            stack.join(", ")
+        );
+        io::stdout().flush().unwrap();
+        std::thread::sleep(delay);
+    });
+    match result {
+        Ok(value) => println!("\nvalue: {value}"),
+        Err(diagnostic) => {
+            invocation.report(&diagnostic, &sources, "<eval>");
+            invocation.exit(-1);
+        }
     }
+}
 
-    let serialized = match serde_json::to_string_pretty(&ast) {
-        Ok(s) => s,
+fn fmt(args: FmtArgs, invocation: &Invocation) {
+    let source = match fs::read_to_string(&args.input) {
+        Ok(code) => code,
         Err(e) => {
-            eprint!("Failed to serialize AST: {e}");
-            return;
+            eprintln!("Failed to read file: {e}");
+            invocation.exit(-1);
+        }
+    };
+
+    let filename = args.input.to_string_lossy().to_string();
+    let formatted = match invocation.timed("format", || crust::format::format(&source)) {
+        Ok(formatted) => formatted,
+        Err(diagnostics) => {
+            let mut sources = SourceMap::default();
+            sources.add(filename.as_str(), source);
+            for diagnostic in diagnostics {
+                invocation.report(&diagnostic, &sources, &filename);
+            }
+            invocation.exit(-1);
         }
     };
 
-    fs::write(args.output, serialized).unwrap();
+    if formatted == source {
+        return;
+    }
+    if args.check {
+        eprintln!("{filename} is not formatted");
+        invocation.exit(1);
+    }
+    if let Err(e) = fs::write(&args.input, formatted) {
+        eprintln!("Failed to write {filename}: {e}");
+        invocation.exit(-1);
+    }
 }
 
-fn run(args: RunArgs) {
+/// Writes a DOT graph of the input, which only has to parse so that a program can be drawn even
+/// if it doesn't compile.
+fn viz(args: VizArgs, invocation: &Invocation) {
     let source = match fs::read_to_string(&args.input) {
         Ok(code) => code,
         Err(e) => {
             eprintln!("Failed to read file: {e}");
-            return;
+            invocation.exit(-1);
         }
     };
 
-    let ast = match serde_json::from_str::<Ast>(&source) {
+    let filename = args.input.to_string_lossy().to_string();
+    let ast = match invocation.timed("parse", || crust::pipeline::parse_file(&source)) {
         Ok(ast) => ast,
+        Err(diagnostics) => {
+            let mut sources = SourceMap::default();
+            sources.add(filename.as_str(), source);
+            for diagnostic in diagnostics {
+                invocation.report(&diagnostic, &sources, &filename);
+            }
+            invocation.exit(-1);
+        }
+    };
+
+    let dot = match args.graph {
+        Graph::Ast => crust::viz::ast(&ast),
+        Graph::Calls => crust::viz::calls(&ast),
+    };
+    match args.output {
+        Some(output) => fs::write(output, dot).unwrap(),
+        None => print!("{dot}"),
+    }
+}
+
+fn lsp(invocation: &Invocation) {
+    let Options {
+        features, locale, ..
+    } = &invocation.options;
+    if let Err(e) = crust::lsp::serve(io::stdin().lock(), io::stdout().lock(), features, *locale) {
+        eprintln!("Language server stopped: {e}");
+        invocation.exit(-1);
+    }
+}
+
+/// Writes the tokens or AST of the input for `--emit tokens` and `--emit ast`, stopping after
+/// the pass that produces them so that a file can be dumped even if later passes reject it.
+fn dump_front_end(
+    args: &BuildArgs,
+    input: &Path,
+    output: &Path,
+    emit: Emit,
+    invocation: &Invocation,
+) -> Result<(), Failed> {
+    let source = match fs::read_to_string(input) {
+        Ok(code) => code,
         Err(e) => {
-            eprint!("Error reading C IR: {e}");
-            return;
+            invocation.eprintln(format_args!("Failed to read file: {e}"));
+            return Err(Failed);
         }
     };
 
-    let exit_code = ast.run_main();
-    println!("-- exited with code : {exit_code} --");
+    let filename = input.to_string_lossy().to_string();
+    let dumped = match emit {
+        Emit::Tokens => invocation
+            .timed("lex", || crust::pipeline::lex(&source))
+            .map(|tokens| crust::dump::tokens(&source, &tokens)),
+        _ => invocation
+            .timed("parse", || crust::pipeline::parse_file(&source))
+            .map(|mut ast| {
+                if let Some(name) = &args.only_fn {
+                    ast.retain_func(name);
+                }
+                crust::dump::tree(&ast)
+            }),
+    };
+
+    match dumped {
+        Ok(dumped) => write_output(invocation, output, dumped),
+        Err(diagnostics) => {
+            let mut sources = SourceMap::default();
+            sources.add(filename.as_str(), source);
+            for diagnostic in diagnostics {
+                invocation.report(&diagnostic, &sources, &filename);
+            }
+            Err(Failed)
+        }
+    }
+}
+
+/// Writes what was built to `output`, reporting it if it can't be written.
+fn write_output(
+    invocation: &Invocation,
+    output: &Path,
+    contents: impl AsRef<[u8]>,
+) -> Result<(), Failed> {
+    fs::write(output, contents).map_err(|e| {
+        invocation.eprintln(format_args!("Failed to write {}: {e}", output.display()));
+        Failed
+    })
+}
+
+/// What `build` compiles and where it writes the output.
+enum Targets {
+    /// One program, from a single file or from several linked together, written to a file
+    Program(Vec<PathBuf>, PathBuf),
+    /// Each file compiled on its own, written to a file named after it in a directory
+    Each(Vec<PathBuf>, PathBuf),
+}
+
+impl Targets {
+    /// The targets of `args`, where the output is the last path unless it's given with `-o`,
+    /// and every source file in a directory is an input.
+    fn new(args: &BuildArgs) -> Result<Self, String> {
+        let (paths, output) = match (&args.paths[..], &args.output) {
+            (paths, Some(output)) => (paths, output.clone()),
+            // an output that's a source file is more likely to be a forgotten `-o`
+            ([input, output], None) if !input.is_dir() && !crust::pipeline::is_source(output) => {
+                (&args.paths[..1], output.clone())
+            }
+            _ => {
+                return Err(String::from(
+                    "Give the output with -o to build several inputs",
+                ))
+            }
+        };
+
+        let mut inputs = Vec::new();
+        for path in paths {
+            match path.is_dir() {
+                true => inputs.extend(
+                    harness::discover(path)
+                        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?,
+                ),
+                false => inputs.push(path.clone()),
+            }
+        }
+        if inputs.contains(&output) {
+            return Err(format!("Building {} would overwrite it", output.display()));
+        }
+        match &inputs[..] {
+            [] => Err(String::from("There are no source files to build")),
+            _ if args.link || matches!(paths, [path] if !path.is_dir()) => {
+                Ok(Targets::Program(inputs, output))
+            }
+            _ => Ok(Targets::Each(inputs, output)),
+        }
+    }
+}
+
+fn build(args: BuildArgs, config: &Config, invocation: &Invocation) {
+    if args.watch {
+        if let Err(e) = Targets::new(&args) {
+            eprintln!("{e}");
+            invocation.exit(-1);
+        }
+        // the inputs are checked above, and are all of the paths when the output is given
+        let paths = match args.output {
+            Some(_) => &args.paths[..],
+            None => &args.paths[..1],
+        };
+        watch(paths, invocation, |invocation| {
+            match build_targets(&args, config, invocation) {
+                Ok(()) => 0,
+                Err(Failed) => -1,
+            }
+        });
+    }
+    if build_targets(&args, config, invocation).is_err() {
+        invocation.exit(-1);
+    }
+}
+
+/// Builds what `args` asks for once, finding the source files in any directories again.
+fn build_targets(args: &BuildArgs, config: &Config, invocation: &Invocation) -> Result<(), Failed> {
+    let targets = Targets::new(args).map_err(|e| {
+        invocation.eprintln(e);
+        Failed
+    })?;
+    let output = match &targets {
+        Targets::Program(_, output) | Targets::Each(_, output) => output,
+    };
+    let emit = setting(invocation, config, "build.emit", args.emit, Emit::parse)
+        .map(|(emit, _)| emit)
+        .unwrap_or_else(|| match output.extension().and_then(|ext| ext.to_str()) {
+            Some("bin") => Emit::Bin,
+            _ => Emit::Json,
+        });
+
+    match targets {
+        Targets::Program(inputs, output) => build_program(args, &inputs, &output, emit, invocation),
+        Targets::Each(inputs, dir) => build_each(args, &inputs, &dir, emit, invocation),
+    }
+}
+
+/// Builds each of `inputs` on its own into `dir`, several at once on threads of their own. What
+/// each reports is held back until it's done and written in the order of the inputs, so that the
+/// diagnostics of different files aren't interleaved.
+fn build_each(
+    args: &BuildArgs,
+    inputs: &[PathBuf],
+    dir: &Path,
+    emit: Emit,
+    invocation: &Invocation,
+) -> Result<(), Failed> {
+    let mut outputs = HashMap::<PathBuf, &Path>::new();
+    for input in inputs {
+        let name = input.file_stem().unwrap_or(input.as_os_str());
+        let output = dir.join(name).with_extension(emit.extension());
+        // with `--emit c`, a C input would be built over itself
+        if inputs.contains(&output) {
+            eprintln!("Building {} would overwrite it", output.display());
+            return Err(Failed);
+        }
+        if let Some(other) = outputs.insert(output.clone(), input) {
+            eprintln!(
+                "Both {} and {} would be built to {}",
+                other.display(),
+                input.display(),
+                output.display()
+            );
+            return Err(Failed);
+        }
+    }
+    if let Err(e) = fs::create_dir_all(dir) {
+        eprintln!("Failed to create {}: {e}", dir.display());
+        return Err(Failed);
+    }
+
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+    let results = harness::parallel(inputs, jobs, |input| {
+        let name = input.file_stem().unwrap_or(input.as_os_str());
+        let output = dir.join(name).with_extension(emit.extension());
+        let held = invocation.held();
+        let built = build_program(args, std::slice::from_ref(input), &output, emit, &held);
+        (built, held.take_held())
+    });
+
+    let mut failed = 0;
+    for (built, reported) in results {
+        io::stderr().write_all(&reported).unwrap();
+        failed += usize::from(built.is_err());
+    }
+    if failed > 0 {
+        eprintln!("{failed} of {} files failed to build", inputs.len());
+        return Err(Failed);
+    }
+    Ok(())
+}
+
+/// Builds the program in `inputs`, linking them if there are several, into `output`.
+fn build_program(
+    args: &BuildArgs,
+    inputs: &[PathBuf],
+    output: &Path,
+    emit: Emit,
+    invocation: &Invocation,
+) -> Result<(), Failed> {
+    if matches!(emit, Emit::Tokens | Emit::Ast) {
+        let [input] = inputs else {
+            invocation.eprintln(format_args!(
+                "Only one file at a time can be dumped with --emit {}",
+                value_name(emit)
+            ));
+            return Err(Failed);
+        };
+        return dump_front_end(args, input, output, emit, invocation);
+    }
+
+    let Some(Program {
+        name: filename,
+        sources,
+        mut ast,
+        ..
+    }) = compile_files(inputs, invocation)
+    else {
+        return Err(Failed);
+    };
+    let failed = |diagnostic: Diagnostic| {
+        invocation.report(&diagnostic, &sources, &filename);
+        Failed
+    };
+
+    if emit == Emit::Symtab {
+        let table = crust::symtab::SymbolTable::new(&sources, &ast);
+        let table = match output.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => table.to_json(),
+            _ => table.to_string(),
+        };
+        return write_output(invocation, output, table);
+    }
+
+    if let Err(diagnostics) = invocation.timed("comptime", || comptime::expand(&mut ast)) {
+        for diagnostic in diagnostics {
+            invocation.report(&diagnostic, &sources, &filename);
+        }
+        return Err(Failed);
+    }
+
+    if let Some(name) = &args.only_fn {
+        if !ast.retain_func(name) {
+            invocation.eprintln(format_args!("Function '{name}' not found in {filename}"));
+            return Err(Failed);
+        }
+    }
+
+    let level = match (args.optimize, args.opt_level) {
+        (true, _) => opt::MAX_LEVEL,
+        (false, Some(level)) => level,
+        (false, None) if emit == Emit::AstOpt => opt::MAX_LEVEL,
+        (false, None) => 0,
+    };
+    for (pass, optimize) in opt::passes(level) {
+        invocation.timed("opt", || optimize(&mut ast));
+        if args.self_check {
+            self_check(pass, check_ast(&ast), invocation, &sources, &filename)?;
+        }
+    }
+
+    let serialized = match emit {
+        Emit::AstOpt => ast.to_string().into_bytes(),
+        Emit::Tokens | Emit::Ast => unreachable!("dumped before compiling"),
+        Emit::Symtab => unreachable!("listed before optimizing"),
+        Emit::Json | Emit::Bin => {
+            let artifact = Artifact::new(&ast).map_err(failed)?;
+            invocation.timed("serialize", || match emit {
+                Emit::Json => artifact.to_json(),
+                _ => artifact.to_binary(),
+            })
+        }
+        Emit::Annotated => crust::annotate::annotate(&sources, &ast).into_bytes(),
+        Emit::C => invocation
+            .timed("codegen", || crust::cgen::emit_c(&ast))
+            .map_err(failed)?
+            .into_bytes(),
+        Emit::Ir | Emit::Tac | Emit::Llvm | Emit::Asm | Emit::Obj | Emit::Exe => {
+            let program = invocation
+                .timed("lower", || crust::ir::lower(&ast))
+                .map_err(failed)?;
+            if args.self_check {
+                let errors = crust::ir::verify(&program)
+                    .into_iter()
+                    .map(|error| Diagnostic::error("E0600", Message::Text(error)))
+                    .collect();
+                self_check("lower", errors, invocation, &sources, &filename)?;
+            }
+            let asm = match emit {
+                Emit::Ir => return write_output(invocation, output, program.to_string()),
+                Emit::Tac => return write_output(invocation, output, crust::tac::emit(&program)),
+                Emit::Llvm => {
+                    let llvm = invocation
+                        .timed("codegen", || crust::llvm::emit_llvm(&program))
+                        .map_err(failed)?;
+                    return write_output(invocation, output, llvm);
+                }
+                _ => invocation
+                    .timed("codegen", || codegen::emit_asm(&program))
+                    .map_err(failed)?,
+            };
+
+            let kind = match emit {
+                Emit::Obj => codegen::Output::Object,
+                Emit::Exe => codegen::Output::Executable,
+                _ => return write_output(invocation, output, asm),
+            };
+            return invocation
+                .timed("assemble", || codegen::assemble(&asm, output, kind))
+                .map_err(failed);
+        }
+    };
+
+    write_output(invocation, output, serialized)
+}
+
+/// Runs semantic analysis again, for `--self-check`.
+fn check_ast(ast: &Ast) -> Vec<Diagnostic> {
+    crust::sema::check(ast, &Builtins::default())
+}
+
+/// For `--self-check`, fails if `pass` left the program with any of the problems in `errors`.
+///
+/// The problems are gathered into one diagnostic naming the pass, with a note for each problem.
+fn self_check(
+    pass: &str,
+    errors: Vec<Diagnostic>,
+    invocation: &Invocation,
+    sources: &SourceMap,
+    filename: &str,
+) -> Result<(), Failed> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let mut diagnostic = Diagnostic::error("E0600", Message::new("E0600").arg("pass", pass));
+    for error in errors {
+        for label in error.labels {
+            diagnostic = diagnostic.with_label(label.span, label.message);
+        }
+        diagnostic = diagnostic.with_note(error.message);
+    }
+    invocation.report(&diagnostic, sources, filename);
+    Err(Failed)
+}
+
+/// Reads an IR file produced by `build`, reporting it if it can't be used.
+fn read_ir(input: &Path, invocation: &Invocation) -> Result<Artifact, Failed> {
+    let ir = match fs::read(input) {
+        Ok(ir) => ir,
+        Err(e) => {
+            invocation.eprintln(format_args!("Failed to read file: {e}"));
+            return Err(Failed);
+        }
+    };
+
+    Artifact::read(&ir).map_err(|diagnostic| {
+        let filename = input.to_string_lossy();
+        invocation.report(&diagnostic, &SourceMap::default(), &filename);
+        Failed
+    })
+}
+
+fn dump(args: DumpArgs, invocation: &Invocation) {
+    let Ok(artifact) = read_ir(&args.input, invocation) else {
+        invocation.exit(-1);
+    };
+    let json = artifact.to_json();
+    match args.output {
+        Some(output) => fs::write(output, json).unwrap(),
+        None => println!("{}", String::from_utf8(json).unwrap()),
+    }
+}
+
+/// The fully built clap command, with global and help arguments propagated to subcommands.
+fn cli_command() -> clap::Command {
+    let mut cmd = Cli::command();
+    cmd.build();
+    cmd
+}
+
+fn completions(args: CompletionsArgs) {
+    let cmd = cli_command();
+    let script = match args.shell {
+        Shell::Bash => crust::completions::bash(&cmd),
+        Shell::Zsh => crust::completions::zsh(&cmd),
+        Shell::Fish => crust::completions::fish(&cmd),
+    };
+    print!("{script}");
+}
+
+fn editor_support(args: EditorSupportArgs, invocation: &Invocation) {
+    if let Err(e) = fs::create_dir_all(&args.out) {
+        eprintln!("Failed to create {}: {e}", args.out.display());
+        invocation.exit(-1);
+    }
+
+    for (name, contents) in crust::editor::files(&Builtins::default()) {
+        let path = args.out.join(name);
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("Failed to write {}: {e}", path.display());
+            invocation.exit(-1);
+        }
+    }
+}
+
+/// Runs `command` in this process, and again each time one of the files it read changes: the
+/// `inputs`, the source files in any that are directories and the files they import, as they're
+/// recorded by each compile. The screen is cleared before each run so that only the latest
+/// diagnostics are shown, and each run records telemetry of its own.
+fn watch(
+    inputs: &[PathBuf],
+    invocation: &Invocation,
+    mut command: impl FnMut(&Invocation) -> i32,
+) -> ! {
+    loop {
+        if io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+            io::stdout().flush().unwrap();
+        }
+        let watching = invocation.watching(inputs);
+        let code = command(&watching);
+        watching.write_telemetry(code);
+        let watcher = watching.watcher.unwrap();
+        let watcher = watcher.lock().unwrap();
+        eprintln!(
+            "-- watching {} files for changes, Ctrl-C to stop --",
+            watcher.files().count()
+        );
+        watcher.wait();
+    }
+}
+
+/// Runs this command again in a child process that applies `limits` to itself, returning its exit
+/// code.
+fn run_isolated(limits: Limits, invocation: &Invocation) -> i32 {
+    let mut args = std::env::args_os().skip(1).collect::<Vec<_>>();
+    // the child runs the program once for each time this process does, and arguments after `--`
+    // are the program's, even if one of them is `--watch`
+    let end = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    if let Some(at) = args[..end].iter().position(|arg| arg == "--watch") {
+        args.remove(at);
+    }
+    let status = std::env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .args(args)
+            .env(isolate::ISOLATED_VAR, "1")
+            .status()
+    });
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("Failed to start the isolated process: {e}");
+            return -1;
+        }
+    };
+
+    // the child records its own telemetry, with the passes it ran
+    invocation.telemetry.lock().unwrap().take();
+    match isolate::outcome(status, limits) {
+        Ok(code) => code,
+        Err(diagnostic) => {
+            invocation.report(&diagnostic, &SourceMap::default(), "");
+            -1
+        }
+    }
+}
+
+fn run(args: RunArgs, config: &Config, invocation: &Invocation) {
+    if args.watch {
+        watch(
+            std::slice::from_ref(&args.input),
+            invocation,
+            |invocation| run_once(&args, config, invocation),
+        );
+    }
+    invocation.exit(run_once(&args, config, invocation));
+}
+
+/// Runs the program given to `run` once, returning its exit code, or -1 if it failed to compile or
+/// stopped with an error.
+fn run_once(args: &RunArgs, config: &Config, invocation: &Invocation) -> i32 {
+    let is_source = args.from_source || crust::pipeline::is_source(&args.input);
+    if args.isolate {
+        let limits = Limits {
+            cpu_seconds: args.cpu_limit.unwrap_or(isolate::DEFAULT_CPU_SECONDS),
+            memory_mb: args.memory_limit.unwrap_or(isolate::DEFAULT_MEMORY_MB),
+        };
+        match std::env::var_os(isolate::ISOLATED_VAR) {
+            None => {
+                match args.watch {
+                    // the child compiles the program again and reports what it finds, so this
+                    // only compiles it for the files it imports
+                    true if is_source => {
+                        compile_file(&args.input, &invocation.held());
+                    }
+                    true => {}
+                    // Ctrl-C reaches the child too, which stops and is waited for like any other
+                    // exit
+                    false => {
+                        let _ = interrupt::on_ctrl_c();
+                    }
+                }
+                return run_isolated(limits, invocation);
+            }
+            Some(_) => {
+                if let Err(e) = isolate::apply(limits) {
+                    eprintln!("Failed to limit resources: {e}");
+                    return -1;
+                }
+            }
+        }
+    }
+
+    let filename = args.input.to_string_lossy().to_string();
+    let (ast, sources, pass) = if is_source {
+        let Some(program) = compile_file(&args.input, invocation) else {
+            return -1;
+        };
+        (program.ast, program.sources, "compile")
+    } else {
+        let Ok(artifact) = read_ir(&args.input, invocation) else {
+            return -1;
+        };
+        let ast = artifact.program.to_ast();
+        invocation.with_metrics(|metrics| {
+            metrics.size = Some(ProgramSize::new(&SourceMap::default(), &ast))
+        });
+        (ast, SourceMap::default(), "read-ir")
+    };
+    if args.self_check
+        && self_check(pass, check_ast(&ast), invocation, &sources, &filename).is_err()
+    {
+        return -1;
+    }
+
+    // the debugger adds the functions edited while the program runs, which statements and errors
+    // may point into
+    let shared = Arc::new(Mutex::new(sources));
+    // the tracer writes first, so a statement is traced before the debugger pauses on it
+    let mut hooks = Vec::<Box<dyn Hook>>::new();
+    if args.trace {
+        hooks.push(Box::new(Tracer::new(shared.clone(), io::stderr())));
+    }
+    if args.debug {
+        let input = io::BufReader::new(io::stdin());
+        let prompt = io::stdin().is_terminal();
+        hooks.push(Box::new(Debugger::new(
+            shared.clone(),
+            input,
+            io::stdout(),
+            prompt,
+        )));
+    }
+    if args.profile {
+        hooks.push(Box::new(Profiler::new(io::stderr())));
+    }
+    let mut builtins = Builtins::default();
+    builtins.set_args(args.args.clone());
+    builtins.set_env(args.env.clone());
+    let options = RunOptions {
+        builtins,
+        max_call_depth: setting(
+            invocation,
+            config,
+            "run.max-call-depth",
+            args.max_call_depth,
+            parse_depth,
+        )
+        .map_or(DEFAULT_MAX_CALL_DEPTH, |(depth, _)| depth),
+        ints: IntMode {
+            width: args.int_width.unwrap_or_default(),
+            overflow: args.overflow.unwrap_or_default(),
+        },
+        // without a handler, Ctrl-C kills the process as it always has, which is how watching
+        // is stopped
+        interrupt: match args.watch {
+            true => Interrupt::default(),
+            false => interrupt::on_ctrl_c().unwrap_or_else(|_| Interrupt::default()),
+        },
+        hooks,
+        ..RunOptions::default()
+    };
+    let result = invocation.timed("run", || match args.vm {
+        true => crust::vm::run_main_with(&ast, options, &args.args),
+        false => ast.run_main_with(options, &args.args),
+    });
+    match result {
+        Ok(exit_code) => {
+            println!("-- exited with code : {exit_code} --");
+            exit_code
+        }
+        Err(diagnostic) => {
+            invocation.report(&diagnostic, &shared.lock().unwrap(), &filename);
+            match diagnostic.code == interrupt::INTERRUPTED_CODE {
+                true => interrupt::INTERRUPTED_EXIT_CODE,
+                false => -1,
+            }
+        }
+    }
 }
@@ -0,0 +1,521 @@
+//! The text of every diagnostic, as templates in a catalog with one translation per [`Locale`].
+//!
+//! Templates are keyed by the code of the diagnostic they belong to (`E0101`, or `E0200.main-arity`
+//! when a code has several messages). Text shared between diagnostics uses a plain key, with
+//! labels and notes under `label.` and `note.`. A template names its parameters in braces, like
+//! `Unknown function '{name}'`, and [`Message::arg`] gives them values.
+//!
+//! Text that comes from outside the compiler, such as errors from the operating system or the
+//! system C compiler, isn't in the catalog and is shown as written. It's made into a message
+//! explicitly with [`Message::Text`], never converted from a string.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Serialize, Serializer};
+
+/// A language that diagnostics can be written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+/// A catalog entry: one template per locale.
+#[derive(Debug)]
+pub struct Template {
+    pub key: &'static str,
+    pub en: &'static str,
+    pub es: &'static str,
+}
+
+impl Template {
+    pub fn text(&self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::En => self.en,
+            Locale::Es => self.es,
+        }
+    }
+}
+
+/// The text of a diagnostic, its labels or its notes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A template from the [`CATALOG`], with the values of its parameters
+    Template {
+        key: &'static str,
+        args: Vec<(&'static str, Message)>,
+    },
+    /// Text that isn't translated
+    Text(String),
+    /// Messages written on separate lines
+    Lines(Vec<Message>),
+}
+
+impl Message {
+    /// The template for `key`, which must be in the [`CATALOG`].
+    pub fn new(key: &'static str) -> Self {
+        debug_assert!(template(key).is_some(), "no message {key} in the catalog");
+        Self::Template {
+            key,
+            args: Vec::new(),
+        }
+    }
+
+    /// Gives the parameter `name` a value, which may itself be translated.
+    pub fn arg(mut self, name: &'static str, value: impl Arg) -> Self {
+        if let Self::Template { args, .. } = &mut self {
+            args.push((name, value.into_message()));
+        }
+        self
+    }
+
+    pub fn render(&self, locale: Locale) -> String {
+        match self {
+            Self::Template { key, args } => {
+                let Some(template) = template(key) else {
+                    return key.to_string();
+                };
+                fill(template.text(locale), |name| {
+                    let (_, value) = args.iter().find(|(arg, _)| *arg == name)?;
+                    Some(value.render(locale))
+                })
+            }
+            Self::Text(text) => text.clone(),
+            Self::Lines(lines) => lines
+                .iter()
+                .map(|line| line.render(locale))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+/// Messages are displayed in English, see [`Message::render`] for other locales.
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(Locale::En))
+    }
+}
+
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The value of a template's parameter: a message, translated along with the template, or text
+/// such as a name, shown as written.
+///
+/// Only parameters can be plain text, so that the text of every diagnostic is in the catalog.
+pub trait Arg {
+    fn into_message(self) -> Message;
+}
+
+impl Arg for Message {
+    fn into_message(self) -> Message {
+        self
+    }
+}
+
+impl Arg for String {
+    fn into_message(self) -> Message {
+        Message::Text(self)
+    }
+}
+
+impl Arg for &str {
+    fn into_message(self) -> Message {
+        Message::Text(self.to_string())
+    }
+}
+
+pub fn template(key: &str) -> Option<&'static Template> {
+    CATALOG.iter().find(|template| template.key == key)
+}
+
+/// Replaces every `{name}` in `template` with its value, leaving unknown parameters as they are.
+fn fill(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 1..start + len];
+        text.push_str(&rest[..start]);
+        match value(name) {
+            Some(value) => text.push_str(&value),
+            None => text.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+macro_rules! catalog {
+    ($($key:literal => $en:literal, $es:literal;)*) => {
+        pub const CATALOG: &[Template] = &[$(Template { key: $key, en: $en, es: $es }),*];
+    };
+}
+
+catalog! {
+    // lexing and parsing
+    "E0001" => "Lexer Error", "Error léxico";
+    "E0002" => "Parser Error", "Error de sintaxis";
+    "E0002.keyword-typo" => "Misspelled keyword", "Palabra clave mal escrita";
+    "label.did-you-mean" => "did you mean `{keyword}`?", "¿quisiste decir `{keyword}`?";
+    "E0003.unclosed" => "Unclosed `{open}`", "`{open}` sin cerrar";
+    "E0003.unexpected" => "Unexpected `{close}`", "`{close}` inesperado";
+    "E0003.too-deep" => "Delimiters nested more than {max} deep", "Delimitadores anidados a más de {max} niveles";
+    "label.nested-too-deep" => "nested too deeply here", "anidado demasiado profundo aquí";
+    "label.opened-here" => "`{open}` opened here", "`{open}` abierto aquí";
+    "label.expected-close" => "expected `{close}` before this", "se esperaba `{close}` antes de esto";
+    "note.expected-close-by-end" => "expected `{close}` by the end of the file",
+        "se esperaba `{close}` antes del final del archivo";
+    "label.closes-nothing" => "there's no `{open}` for this to close",
+        "no hay ningún `{open}` que esto cierre";
+    "label.unclosed-delimiter" => "unclosed delimiter {delimiter}", "delimitador {delimiter} sin cerrar";
+    "E0001.unterminated-string" => "Unterminated string literal", "Literal de cadena sin terminar";
+    "E0001.unterminated-raw-string" => "Unterminated raw string literal",
+        "Literal de cadena sin procesar sin terminar";
+    "label.string-started" => "string literal started here", "el literal de cadena empieza aquí";
+    "label.raw-string-started" => "raw string literal started here",
+        "el literal de cadena sin procesar empieza aquí";
+    "E0001.unterminated-comment" => "Unterminated block comment", "Comentario de bloque sin terminar";
+    "label.comment-started" => "block comment started here", "el comentario de bloque empieza aquí";
+    "note.close-comment" => "add a closing `*/`, and one for each `/*` inside it since block comments nest",
+        "añade un `*/` de cierre, y uno por cada `/*` dentro ya que los comentarios de bloque se anidan";
+    "label.file-ends" => "the file ends before it's closed", "el archivo termina antes de cerrarlo";
+    "note.close-string" => "add a closing `\"`", "añade unas comillas `\"` de cierre";
+    "note.close-raw-string" => "close it with `\"` followed by as many `#` as it starts with",
+        "ciérralo con `\"` seguido de tantos `#` como tiene al principio";
+    "label.unexpected-char" => "`{char}` can't start a token", "`{char}` no puede iniciar un token";
+    "E0004.directive" => "Preprocessor directives like `#{name}` aren't supported",
+        "Las directivas del preprocesador como `#{name}` no se admiten";
+    "E0004.loop" => "`{keyword}` loops aren't supported yet", "Los bucles `{keyword}` aún no se admiten";
+    "E0004.if" => "`if` statements aren't supported yet", "Las sentencias `if` aún no se admiten";
+    "label.unsupported" => "not supported", "no se admite";
+    "note.no-preprocessor" => "`print`, `println`, `read_int` and `len` are built in without including anything, and `import \"file.c\";` brings in the definitions from another file",
+        "`print`, `println`, `read_int` y `len` están integradas sin incluir nada, e `import \"file.c\";` trae las definiciones de otro archivo";
+    "note.no-loops" => "repeat work with a function that calls itself until it's done",
+        "repite el trabajo con una función que se llame a sí misma hasta terminar";
+    "note.no-if" => "choose between two values with `cond ? then : otherwise`",
+        "elige entre dos valores con `cond ? then : otherwise`";
+    "E0005" => "Int literal `{literal}` is too large", "El literal entero `{literal}` es demasiado grande";
+    "label.too-large" => "doesn't fit in an int", "no cabe en un entero";
+    "note.int-range" => "the largest int literal is 2147483647, or 2147483648 after a `-`",
+        "el mayor literal entero es 2147483647, o 2147483648 tras un `-`";
+    "E0006" => "Invalid int literal `{literal}`", "Literal entero `{literal}` no válido";
+    "label.invalid-digit" => "`{digit}` isn't a digit in base {radix}", "`{digit}` no es un dígito en base {radix}";
+    "label.no-digits" => "there are no digits after the prefix", "no hay dígitos después del prefijo";
+    "E0007" => "Invalid float literal `{literal}`", "Literal de punto flotante `{literal}` no válido";
+    "label.invalid-float" => "isn't a number", "no es un número";
+    "note.allowed-chars" => "outside strings and comments, only letters, digits, `_`, whitespace, the operators `{operators}` and the delimiters `{delimiters}` may appear",
+        "fuera de cadenas y comentarios solo pueden aparecer letras, dígitos, `_`, espacios, los operadores `{operators}` y los delimitadores `{delimiters}`";
+
+    // semantic analysis and imports
+    "main-not-found" => "main function not found", "no se encontró la función main";
+    "E0101" => "Undeclared variable '{name}'", "Variable no declarada '{name}'";
+    "E0102" => "Unknown function '{name}'", "Función desconocida '{name}'";
+    "E0103" => "Function '{name}' takes {expected} arguments but {found} were supplied",
+        "La función '{name}' recibe {expected} argumentos pero se pasaron {found}";
+    "E0104" => "Duplicate definition of '{name}'", "Definición duplicada de '{name}'";
+    "E0105" => "Failed to import '{path}'", "No se pudo importar '{path}'";
+    "E0106" => "Import cycle detected for '{path}'", "Se detectó un ciclo de importación en '{path}'";
+    "E0107" => "@comptime function '{name}' returned an array, which can't be a constant",
+        "la función @comptime '{name}' devolvió un arreglo, que no puede ser una constante";
+    "E0107.pointer" => "@comptime function '{name}' returned a pointer, which can't be a constant",
+        "la función @comptime '{name}' devolvió un puntero, que no puede ser una constante";
+    "E0108" => "Macros are an experimental feature", "Las macros son una característica experimental";
+    "E0109" => "Unknown macro '{name}'", "Macro desconocida '{name}'";
+    "E0110" => "Macro '{name}' takes {expected} arguments but {found} were supplied",
+        "La macro '{name}' recibe {expected} argumentos pero se pasaron {found}";
+    "E0111" => "Macro '{name}' expands to itself", "La macro '{name}' se expande a sí misma";
+    "E0112" => "Macro '{name}' uses '{var}', which is neither its parameter nor declared in it",
+        "La macro '{name}' usa '{var}', que no es su parámetro ni está declarada en ella";
+    "E0113" => "Macro '{name}' assigns to its parameter '{param}', whose argument isn't a variable",
+        "La macro '{name}' asigna a su parámetro '{param}', cuyo argumento no es una variable";
+    "E0114" => "Function '{name}' is void, so its call has no value",
+        "La función '{name}' es void, así que su llamada no tiene valor";
+    "E0115" => "Function '{name}' returns {ty} but `return;` has no value",
+        "La función '{name}' devuelve {ty} pero `return;` no tiene valor";
+    "E0116" => "Function '{name}' is void but returns a value",
+        "La función '{name}' es void pero devuelve un valor";
+    "E0117" => "Function 'main' can't be void", "La función 'main' no puede ser void";
+    "E0118" => "Variable '{name}' can't be void", "La variable '{name}' no puede ser void";
+    "E0119" => "Function '{name}' is declared but never defined",
+        "La función '{name}' se declara pero nunca se define";
+    "E0120" => "Declaration of '{name}' doesn't match its definition",
+        "La declaración de '{name}' no coincide con su definición";
+    "E0121" => "Unknown type '{ty}'", "Tipo desconocido '{ty}'";
+    "E0122" => "Struct '{name}' contains itself, so it has no size",
+        "El struct '{name}' se contiene a sí mismo, así que no tiene tamaño";
+    "E0123" => "'{ty}' has no size", "'{ty}' no tiene tamaño";
+    "E0124" => "Threads are an experimental feature", "Los hilos son una característica experimental";
+    "E0125" => "Function '{name}' takes or returns a pointer, so it can't run on another thread",
+        "La función '{name}' recibe o devuelve un puntero, así que no puede ejecutarse en otro hilo";
+    "E0126" => "Function '{owner}' has two parameters named '{name}'",
+        "La función '{owner}' tiene dos parámetros llamados '{name}'";
+    "E0127" => "Struct '{owner}' has two fields named '{name}'",
+        "El struct '{owner}' tiene dos campos llamados '{name}'";
+    "E0128" => "Switch has two cases for {value}", "El switch tiene dos casos para {value}";
+    "E0128.default" => "Switch has two `default` cases", "El switch tiene dos casos `default`";
+    "E0129" => "`break` outside of a switch", "`break` fuera de un switch";
+    "E0130" => "Variable '{name}' is declared in a switch",
+        "La variable '{name}' se declara en un switch";
+    "E0131" => "`continue` outside of a loop", "`continue` fuera de un bucle";
+    "E0132" => "Constant '{name}' can't be assigned to",
+        "No se puede asignar a la constante '{name}'";
+    "E0133" => "The value of a global must be a constant",
+        "El valor de una global debe ser una constante";
+    "E0134" => "Constant '{name}' has no value", "La constante '{name}' no tiene valor";
+    "E0135" => "Function '{name}' uses the global '{global}', which the program changes, so it can't run on another thread",
+        "La función '{name}' usa la global '{global}', que el programa modifica, así que no puede ejecutarse en otro hilo";
+    "label.stored-into" => "stored into here", "se almacena aquí";
+    "label.assigned-to" => "assigned to here", "se asigna aquí";
+    "label.not-in-scope" => "not found in this scope", "no se encuentra en este ámbito";
+    "label.pointer-taken" => "pointer taken here", "se toma un puntero aquí";
+    "label.not-constant" => "not known until the program runs",
+        "no se conoce hasta que se ejecuta el programa";
+    "label.wrong-arg-count" => "incorrect number of arguments", "número incorrecto de argumentos";
+    "label.called-here" => "called here", "llamada aquí";
+    "label.first-defined" => "first defined here", "definido primero aquí";
+    "label.defined-again" => "defined again here", "definido de nuevo aquí";
+    "label.first-case" => "first case here", "primer caso aquí";
+    "label.case-again" => "same case again here", "el mismo caso de nuevo aquí";
+    "label.not-in-switch" => "not inside a switch", "no está dentro de un switch";
+    "label.not-in-loop" => "not inside a loop", "no está dentro de un bucle";
+    "label.imported-here" => "imported here", "importado aquí";
+    "label.macro-defined" => "macro defined here", "macro definida aquí";
+    "label.expanded-here" => "expanded here", "expandida aquí";
+    "label.not-a-variable" => "not a variable", "no es una variable";
+    "label.returned-here" => "returned here", "se devuelve aquí";
+    "label.declared-here" => "declared here", "declarada aquí";
+    "label.defined-here" => "defined here", "definida aquí";
+    "label.field" => "field {name}", "campo {name}";
+    "label.sized-here" => "sized here", "se mide aquí";
+    "label.spawned-here" => "spawned here", "lanzado aquí";
+    "label.joined-here" => "joined here", "esperado aquí";
+    "label.comptime" => "evaluated at build time here", "evaluada al compilar aquí";
+    "note.comptime" => "while evaluating a call to a @comptime function at build time",
+        "al evaluar una llamada a una función @comptime al compilar";
+    "note.enable-macros" => "enable them with `--features macros`",
+        "actívalas con `--features macros`";
+    "note.enable-threads" => "enable them with `--features threads`",
+        "actívalos con `--features threads`";
+    "note.shared-variable" => "a pointer would let both threads change the same variable at once",
+        "un puntero permitiría que ambos hilos cambien la misma variable a la vez";
+    "note.share-atomics" => "threads can share an int through `atomic_load`, `atomic_store` and `atomic_add` instead",
+        "los hilos pueden compartir un entero mediante `atomic_load`, `atomic_store` y `atomic_add` en su lugar";
+    "note.thread-globals" => "every thread has globals of its own, so no other thread would see the change",
+        "cada hilo tiene sus propias globales, así que ningún otro hilo vería el cambio";
+    "note.switch-declaration" => "declare it before the switch, since a case could jump past its declaration",
+        "declárala antes del switch, ya que un caso podría saltarse su declaración";
+    "note.switch-not-loop" => "a switch isn't a loop, so `continue` can't go on to its next case",
+        "un switch no es un bucle, así que `continue` no puede pasar a su siguiente caso";
+    "note.hygiene" => "a macro can only use the variables where it's expanded through its arguments",
+        "una macro solo puede usar las variables de donde se expande a través de sus argumentos";
+    "note.expansion-chain" => "expanded through {chain}", "expandida a través de {chain}";
+    "note.cycle" => "cycle: {cycle}", "ciclo: {cycle}";
+    "note.c-function" => "`{name}` is from C's standard library, which isn't available; use the builtin `{builtin}` instead",
+        "`{name}` es de la biblioteca estándar de C, que no está disponible; usa la función integrada `{builtin}` en su lugar";
+    "note.signatures" => "declared as `{declared}` but defined as `{defined}`",
+        "declarada como `{declared}` pero definida como `{defined}`";
+    "note.global-not-constant" => "'{name}' is a global that isn't `const`, so it can change",
+        "'{name}' es una global que no es `const`, así que puede cambiar";
+    "note.defined-in-both" => "'{name}' is defined in both {first} and {second}",
+        "'{name}' está definido tanto en {first} como en {second}";
+
+    // lints
+    "W0101" => "Variable '{name}' is assigned but never read",
+        "La variable '{name}' se asigna pero nunca se lee";
+    "W0102" => "Function '{name}' is never called from main",
+        "La función '{name}' nunca se llama desde main";
+    "W0103" => "Unreachable code in '{name}'", "Código inalcanzable en '{name}'";
+    "label.unreachable" => "this is never run", "esto nunca se ejecuta";
+    "note.underscore" => "if that's intended, start its name with `_`",
+        "si es intencionado, empieza su nombre con `_`";
+    "note.deny-warnings" => "warnings are errors because of `--deny-warnings`",
+        "las advertencias son errores por `--deny-warnings`";
+
+    // running
+    "E0200.duplicate-function" => "Duplicate functions with name {name}",
+        "Funciones duplicadas con el nombre {name}";
+    "E0200.main-arity" => "main takes {expected} arguments but {found} were supplied",
+        "main recibe {expected} argumentos pero se pasaron {found}";
+    "E0200.invalid-int" => "argument '{arg}' is not a valid int",
+        "el argumento '{arg}' no es un int válido";
+    "E0200.start" => "failed to start the interpreter: {error}",
+        "no se pudo iniciar el intérprete: {error}";
+    "E0200.main-return" => "main must return an int, found {type}",
+        "main debe devolver un int, se encontró {type}";
+    "E0200.load" => "failed to load {path}: {error}", "no se pudo cargar {path}: {error}";
+    "E0200.debugger-quit" => "the program was stopped from the debugger",
+        "el programa se detuvo desde el depurador";
+    "E0200.debugger-io" => "the debugger failed to read a command: {error}",
+        "el depurador no pudo leer un comando: {error}";
+    "E0201" => "reached end of function with no return", "se llegó al final de la función sin return";
+    "E0202.invalid-statement" => "reached invalid statement", "se alcanzó una sentencia inválida";
+    "E0202.unexpanded-macro" => "reached {name}!, which wasn't expanded",
+        "se alcanzó {name}!, que no se expandió";
+    "E0202.inline-code" => "reached a `{block}` block, which only a compiled backend can run",
+        "se alcanzó un bloque `{block}`, que solo un backend compilado puede ejecutar";
+    "E0202.invalid-expression" => "invalid expression found", "se encontró una expresión inválida";
+    "E0202.dangling-pointer" => "the variable this pointer points to no longer exists",
+        "la variable a la que apunta este puntero ya no existe";
+    "E0202.break" => "reached a `break` outside of a switch", "se alcanzó un `break` fuera de un switch";
+    "E0202.continue" => "reached a `continue` outside of a loop", "se alcanzó un `continue` fuera de un bucle";
+    "E0202.spawn-pointer" => "a pointer can't be passed to another thread",
+        "no se puede pasar un puntero a otro hilo";
+    "E0202.spawn" => "couldn't start a thread: {error}", "no se pudo iniciar un hilo: {error}";
+    "E0202.joined" => "this thread has already been joined, or was spawned by another thread",
+        "este hilo ya se esperó, o lo lanzó otro hilo";
+    "E0202.vm-threads" => "reached a `spawn`, which only the interpreter can run",
+        "se alcanzó un `spawn`, que solo el intérprete puede ejecutar";
+    "E0202.division-by-zero" => "attempt to divide by zero", "intento de dividir entre cero";
+    "E0202.overflow" => "attempt to {op} with overflow", "intento de {op} con desbordamiento";
+    "E0202.out-of-range" => "{value} doesn't fit in a {bits}-bit int",
+        "{value} no cabe en un int de {bits} bits";
+    "E0202.expected" => "expected {expected}, found {found}",
+        "se esperaba {expected}, se encontró {found}";
+    "E0202.cannot-add" => "cannot add {lhs} and {rhs}", "no se puede sumar {lhs} y {rhs}";
+    "E0202.out-of-bounds" => "index {index} out of bounds for array of length {len}",
+        "índice {index} fuera de los límites de un arreglo de longitud {len}";
+    "E0202.builtin-args" => "{name} expects {args}", "{name} espera {args}";
+    "E0202.builtin-io" => "{name} failed: {error}", "{name} falló: {error}";
+    "E0202.read-int" => "read_int: '{line}' is not a valid int",
+        "read_int: '{line}' no es un int válido";
+    "E0202.no-arg" => "arg: no argument {index}, there are {count}",
+        "arg: no existe el argumento {index}, hay {count}";
+    "E0202.locked" => "lock: '{name}' is already held by this thread",
+        "lock: este hilo ya tiene '{name}'";
+    "E0202.not-locked" => "unlock: '{name}' isn't held by this thread",
+        "unlock: este hilo no tiene '{name}'";
+    "E0202.comptime-builtin" => "{name} can't be called at build time",
+        "{name} no se puede llamar al compilar";
+    "op.add" => "add", "sumar";
+    "op.subtract" => "subtract", "restar";
+    "op.multiply" => "multiply", "multiplicar";
+    "op.divide" => "divide", "dividir";
+    "op.remainder" => "take the remainder", "calcular el resto";
+    "op.negate" => "negate", "negar";
+    "op.shift-left" => "shift left", "desplazar a la izquierda";
+    "op.shift-right" => "shift right", "desplazar a la derecha";
+    "args.array-or-string" => "a single array or string argument",
+        "un único argumento arreglo o cadena";
+    "args.int" => "a single int argument", "un único argumento int";
+    "args.string" => "a single string argument", "un único argumento cadena";
+    "args.string-and-int" => "a string and an int argument", "un argumento cadena y otro int";
+    "E0203" => "stack overflow at call to {name}", "desbordamiento de pila en la llamada a {name}";
+    "E0204.cpu" => "the program was killed after using {seconds} seconds of CPU time",
+        "el programa se terminó tras usar {seconds} segundos de tiempo de CPU";
+    "E0204.memory" => "the program was killed after running out of its {megabytes} MB of memory",
+        "el programa se terminó al agotar sus {megabytes} MB de memoria";
+    "E0204.signal" => "the program was killed by signal {signal}",
+        "el programa se terminó por la señal {signal}";
+    "note.limits" => "the limits of `--isolate` are set with `--cpu-limit` and `--memory-limit`",
+        "los límites de `--isolate` se fijan con `--cpu-limit` y `--memory-limit`";
+    "E0205" => "the program was interrupted", "el programa se interrumpió";
+    "label.interrupted" => "stopped here", "detenido aquí";
+    "undeclared-variable" => "undeclared variable {name}", "variable no declarada {name}";
+    "unknown-function" => "unknown function {name}", "función desconocida {name}";
+    "arity" => "function {name} takes {expected} arguments but {found} were supplied",
+        "la función {name} recibe {expected} argumentos pero se pasaron {found}";
+    "label.main-defined" => "main defined here", "main definida aquí";
+    "label.func-defined" => "{name} defined here", "{name} definida aquí";
+    "label.for-parameter" => "for this parameter", "para este parámetro";
+    "label.in-function" => "in function {name}", "en la función {name}";
+    "label.error-here" => "error occurred here", "el error ocurrió aquí";
+    "label.stack-overflow" => "stack overflow at this call", "desbordamiento de pila en esta llamada";
+    "label.func-called" => "{name} called here", "{name} llamada aquí";
+    "note.inline-backends" => "`__asm` blocks are compiled by `build --emit asm`, `obj` and `exe`, and `__ir` blocks by `build --emit llvm`",
+        "los bloques `__asm` se compilan con `build --emit asm`, `obj` y `exe`, y los bloques `__ir` con `build --emit llvm`";
+    "note.stack-size" => "a max call depth of {depth} needs {bytes} bytes of stack",
+        "una profundidad máxima de llamadas de {depth} necesita {bytes} bytes de pila";
+    "note.max-call-depth" => "exceeded the maximum call depth of {depth}",
+        "se superó la profundidad máxima de llamadas de {depth}";
+    "note.backtrace" => "stack backtrace:\n{trace}", "traza de la pila:\n{trace}";
+    "backtrace.frame" => "{index}: {name}", "{index}: {name}";
+    "backtrace.recursive-frame" => "{index}: {name} ({count} recursive calls)",
+        "{index}: {name} ({count} llamadas recursivas)";
+
+    // IR files
+    "E0300" => "Error reading C IR: {error}", "Error al leer el IR de C: {error}";
+    "E0301.unversioned" => "IR file has no format version; rebuild required",
+        "El archivo IR no tiene versión de formato; es necesario recompilar";
+    "E0301.version" => "IR format version {version} is not supported; rebuild required",
+        "La versión de formato IR {version} no es compatible; es necesario recompilar";
+    "E0302" => "IR file exceeds a limit: {limit}", "El archivo IR supera un límite: {limit}";
+    "E0303" => "Error writing C IR: {error}", "Error al escribir el IR de C: {error}";
+    "limit.definitions" => "more than {max} definitions", "más de {max} definiciones";
+    "limit.statements" => "function {name} has more than {max} statements",
+        "la función {name} tiene más de {max} sentencias";
+    "limit.name" => "a name is longer than {max} bytes", "un nombre ocupa más de {max} bytes";
+    "limit.array" => "array {name} has more than {max} elements",
+        "el arreglo {name} tiene más de {max} elementos";
+    "note.predates-versioning" => "the file was built by a compiler that predates versioned IR",
+        "el archivo fue generado por un compilador anterior al IR versionado";
+    "note.built-with" => "the file was built by crust {built_with}, this is crust {current} which reads format versions {oldest} to {supported}",
+        "el archivo fue generado por crust {built_with}; este es crust {current}, que lee las versiones de formato {oldest} a {supported}";
+
+    // native, LLVM and C backends
+    "E0400.main-return" => "main must return an int", "main debe devolver un int";
+    "E0400.unsupported-native" => "{feature} is not supported by the native backend",
+        "{feature} no es compatible con el backend nativo";
+    "E0400.unsupported-llvm" => "{feature} is not supported by the LLVM backend",
+        "{feature} no es compatible con el backend LLVM";
+    "E0400.unsupported-c" => "{feature} is not supported by the C backend",
+        "{feature} no es compatible con el backend de C";
+    "E0400.unsupported" => "{feature} cannot be compiled", "{feature} no se puede compilar";
+    "E0401.write" => "failed to write assembly: {error}", "no se pudo escribir el ensamblador: {error}";
+    "E0401.run" => "failed to run cc: {error}", "no se pudo ejecutar cc: {error}";
+    "E0401.failed" => "cc failed to assemble the program", "cc no pudo ensamblar el programa";
+    "label.used-here" => "used here", "usado aquí";
+    "note.use-interpreter" => "run the program with the interpreter instead",
+        "ejecuta el programa con el intérprete en su lugar";
+    "feature.many-main-params" => "main with more than {count} parameters",
+        "main con más de {count} parámetros";
+    "feature.many-params" => "functions with more than {count} parameters",
+        "funciones con más de {count} parámetros";
+    "feature.type-mismatch" => "using a {found} where a {expected} is expected",
+        "usar un {found} donde se espera un {expected}";
+    "feature.inline-block" => "a `{block}` block", "un bloque `{block}`";
+    "feature.invalid-statements" => "invalid statements", "sentencias inválidas";
+    "feature.void-variable" => "void variables", "variables void";
+    "feature.pointers" => "pointers", "los punteros";
+    "feature.threads" => "threads", "los hilos";
+    "feature.floats" => "floats", "los números de punto flotante";
+    "feature.globals" => "global variables and constants", "las variables globales y constantes";
+    "feature.break" => "`break` outside of a switch", "`break` fuera de un switch";
+    "feature.continue" => "`continue` outside of a loop", "`continue` fuera de un bucle";
+    "feature.void-value" => "using the result of a void function",
+        "usar el resultado de una función void";
+    "feature.unexpanded-macros" => "macros that haven't been expanded", "macros sin expandir";
+    "feature.invalid-expressions" => "invalid expressions", "expresiones inválidas";
+    "feature.string-concatenation" => "string concatenation", "la concatenación de cadenas";
+    "feature.array-as-value" => "using an array as a value", "usar un arreglo como valor";
+    "feature.assign-array" => "assigning to an array variable", "asignar a una variable de arreglo";
+    "feature.index-non-array" => "indexing a value that isn't an array",
+        "indexar un valor que no es un arreglo";
+    "feature.index-non-variable" => "indexing anything but an array variable",
+        "indexar algo que no sea una variable de arreglo";
+    "feature.builtin" => "the builtin {name}", "la función integrada {name}";
+    "feature.len-arity" => "len without exactly one argument", "len sin exactamente un argumento";
+
+    // self-checks
+    "E0600" => "self-check failed: {pass} broke an invariant",
+        "autocomprobación fallida: {pass} rompió un invariante";
+
+    // queries
+    "E0700" => "invalid selector '{selector}' at column {column}: {reason}",
+        "selector inválido '{selector}' en la columna {column}: {reason}";
+
+    // configuration
+    "E0500.read" => "failed to read {path}: {error}", "no se pudo leer {path}: {error}";
+    "E0500.invalid" => "invalid config {path}:{line}: {reason}",
+        "configuración inválida {path}:{line}: {reason}";
+    "E0501" => "invalid value '{value}' for setting {key} from {source}",
+        "valor inválido '{value}' para el ajuste {key} desde {source}";
+}
@@ -0,0 +1,263 @@
+//! Optimizations on the AST, run by `build` before any output is emitted.
+//!
+//! Every pass keeps the exact behaviour of the program: what it prints, what it returns, and the
+//! runtime errors it fails with. Values are dynamically typed, so an identity like `x + 0 == x`
+//! is only applied when `x` is known to be an int (a literal or the result of arithmetic on
+//! ints); otherwise `x` could be a float, or a string that `+` concatenates or that `-` rejects.
+//! Division by a constant zero is left in place so that it still fails when it's reached.
+//!
+//! Arithmetic on constants is only folded when its exact result is a 32-bit int, so that the
+//! program behaves the same whatever `--int-width` and `--overflow` it's run with.
+
+use crate::{
+    ast::{Definition, Expr, Spanned, Statement},
+    semantics::{self, IntMode},
+    token::Span,
+    visit::{self, Folder},
+    Ast, Value,
+};
+
+/// The highest optimization level, also selected by `-O`.
+pub const MAX_LEVEL: u8 = 2;
+
+/// A transformation of the whole program.
+pub type Pass = fn(&mut Ast);
+
+/// Optimizes every function in `ast` in place, running each of the [`passes`] for `level`.
+pub fn optimize(ast: &mut Ast, level: u8) {
+    for (_, pass) in passes(level) {
+        pass(ast);
+    }
+}
+
+/// The passes run at an optimization level, in order, with their names.
+///
+/// Level 1 folds constants and simplifies arithmetic, level 2 also removes dead code, and level
+/// 0 leaves the program untouched.
+pub fn passes(level: u8) -> Vec<(&'static str, Pass)> {
+    let mut passes = Vec::<(&'static str, Pass)>::new();
+    if level >= 1 {
+        passes.push(("fold-constants", fold_constants));
+    }
+    if level >= 2 {
+        passes.push(("remove-dead-code", remove_dead_code));
+    }
+    passes
+}
+
+/// Evaluates arithmetic on constants at compile time, and removes operations that can't change
+/// an int, like adding zero, multiplying by one or negating twice.
+pub fn fold_constants(ast: &mut Ast) {
+    let defs = std::mem::take(&mut ast.defs);
+    ast.defs = defs
+        .into_iter()
+        .map(|def| ConstantFolder.fold_definition(def))
+        .collect();
+}
+
+/// Removes the statements after a function's first `return`, which can never run.
+pub fn remove_dead_code(ast: &mut Ast) {
+    for def in &mut ast.defs {
+        if let Definition::Func(func) = def {
+            if let Some(ret) = func.body.iter().position(|(statement, _)| {
+                matches!(statement, Statement::Return(_) | Statement::ReturnVoid)
+            }) {
+                func.body.truncate(ret + 1);
+            }
+        }
+    }
+}
+
+/// Folds every expression bottom up, simplifying each operation once its operands have been.
+struct ConstantFolder;
+
+impl Folder for ConstantFolder {
+    fn fold_expr(&mut self, expr: Spanned<Expr>) -> Spanned<Expr> {
+        let (node, span) = visit::fold_expr(self, expr);
+        (simplify(node, &span), span)
+    }
+}
+
+/// Simplifies a single operation spanning `span`, whose operands have already been folded.
+fn simplify(expr: Expr, span: &Span) -> Expr {
+    match expr {
+        Expr::Neg(inner) => match (constant(&inner.0), inner.0) {
+            (Some(value), node) => {
+                fold(-value, span).unwrap_or(Expr::Neg(Box::new((node, inner.1))))
+            }
+            // -(-x) is x for every int, including INT_MIN, and every float
+            (None, Expr::Neg(x)) if is_number(&x.0) => x.0,
+            (None, node) => Expr::Neg(Box::new((node, inner.1))),
+        },
+        Expr::Add(lhs, rhs) => {
+            if let Some(sum) = both(&lhs, &rhs).and_then(|(a, b)| fold(a + b, span)) {
+                return sum;
+            }
+            if let (Some(a), Some(b)) = (value(&lhs.0), value(&rhs.0)) {
+                if let Ok(Value::Str(text)) = a.add(b, IntMode::default()) {
+                    return Expr::Str(text);
+                }
+            }
+            match (constant(&lhs.0), constant(&rhs.0)) {
+                (Some(0), None) if semantics::is_int(&rhs.0) => rhs.0,
+                (None, Some(0)) if semantics::is_int(&lhs.0) => lhs.0,
+                (None, Some(b)) => match lhs.0 {
+                    Expr::Add(x, a)
+                        if semantics::is_int(&x.0) && reassociate(&a.0, b).is_some() =>
+                    {
+                        let sum = int(reassociate(&a.0, b).unwrap(), &rhs.1);
+                        simplify(Expr::Add(x, Box::new((sum, rhs.1))), span)
+                    }
+                    node => Expr::Add(Box::new((node, lhs.1)), rhs),
+                },
+                _ => Expr::Add(lhs, rhs),
+            }
+        }
+        Expr::Sub(lhs, rhs) => match both(&lhs, &rhs).and_then(|(a, b)| fold(a - b, span)) {
+            Some(difference) => difference,
+            None if constant(&rhs.0) == Some(0) && is_number(&lhs.0) => lhs.0,
+            None => Expr::Sub(lhs, rhs),
+        },
+        Expr::Mul(lhs, rhs) => match both(&lhs, &rhs).and_then(|(a, b)| fold(a * b, span)) {
+            Some(product) => product,
+            None => match (constant(&lhs.0), constant(&rhs.0)) {
+                (Some(1), None) if is_number(&rhs.0) => rhs.0,
+                (None, Some(1)) if is_number(&lhs.0) => lhs.0,
+                _ => Expr::Mul(lhs, rhs),
+            },
+        },
+        // a division by zero is left to fail when it's reached
+        Expr::Div(lhs, rhs) => match both(&lhs, &rhs) {
+            Some((a, b)) if b != 0 => fold(a / b, span).unwrap_or(Expr::Div(lhs, rhs)),
+            _ if constant(&rhs.0) == Some(1) && is_number(&lhs.0) => lhs.0,
+            _ => Expr::Div(lhs, rhs),
+        },
+        Expr::Rem(lhs, rhs) => match both(&lhs, &rhs) {
+            Some((a, b)) if b != 0 => fold(a % b, span).unwrap_or(Expr::Rem(lhs, rhs)),
+            _ => Expr::Rem(lhs, rhs),
+        },
+        // a shift by a negative amount or by at least 32 depends on the width of ints, as does
+        // shifting bits out to the left
+        Expr::Shl(lhs, rhs) => match both(&lhs, &rhs) {
+            Some((a, b)) if (0..32).contains(&b) => {
+                fold(a << b, span).unwrap_or(Expr::Shl(lhs, rhs))
+            }
+            _ => Expr::Shl(lhs, rhs),
+        },
+        Expr::Shr(lhs, rhs) => match both(&lhs, &rhs) {
+            Some((a, b)) if (0..32).contains(&b) => int(a >> b, span),
+            _ => Expr::Shr(lhs, rhs),
+        },
+        // only the branch a constant condition picks can ever be evaluated
+        Expr::Cond(cond, then, otherwise) => match constant(&cond.0) {
+            Some(0) => otherwise.0,
+            Some(_) => then.0,
+            None => Expr::Cond(cond, then, otherwise),
+        },
+        // nor is the right side of a `&&` or `||` whose constant left side decides it
+        Expr::And(lhs, rhs) => match (constant(&lhs.0), constant(&rhs.0)) {
+            (Some(0), _) => Expr::Int(0),
+            (Some(_), Some(b)) => Expr::Int((b != 0) as u32),
+            _ => Expr::And(lhs, rhs),
+        },
+        Expr::Or(lhs, rhs) => match (constant(&lhs.0), constant(&rhs.0)) {
+            (Some(0), Some(b)) => Expr::Int((b != 0) as u32),
+            (Some(a), _) if a != 0 => Expr::Int(1),
+            _ => Expr::Or(lhs, rhs),
+        },
+        expr => match bit_op(&expr) {
+            Some((op, lhs, rhs)) => match (constant(lhs), constant(rhs)) {
+                (Some(a), Some(b)) => int(op(a as i32, b as i32).into(), span),
+                _ => expr,
+            },
+            None => expr,
+        },
+    }
+}
+
+/// An operation on two ints that can't fail, from [`semantics`].
+type IntOp = fn(i32, i32) -> i32;
+
+/// The operation and operands of a bitwise operation or comparison, which never fail and give
+/// the same result whatever the width of ints.
+fn bit_op(expr: &Expr) -> Option<(IntOp, &Expr, &Expr)> {
+    let (op, lhs, rhs): (IntOp, _, _) = match expr {
+        Expr::BitAnd(lhs, rhs) => (semantics::bit_and, lhs, rhs),
+        Expr::BitOr(lhs, rhs) => (semantics::bit_or, lhs, rhs),
+        Expr::BitXor(lhs, rhs) => (semantics::bit_xor, lhs, rhs),
+        Expr::Lt(lhs, rhs) => (semantics::lt, lhs, rhs),
+        Expr::Le(lhs, rhs) => (semantics::le, lhs, rhs),
+        Expr::Gt(lhs, rhs) => (semantics::gt, lhs, rhs),
+        Expr::Ge(lhs, rhs) => (semantics::ge, lhs, rhs),
+        Expr::Eq(lhs, rhs) => (semantics::eq, lhs, rhs),
+        Expr::Ne(lhs, rhs) => (semantics::ne, lhs, rhs),
+        _ => return None,
+    };
+    Some((op, &lhs.0, &rhs.0))
+}
+
+/// The value of an int constant, either a literal or a negated literal, if it's a 32-bit int,
+/// which it is whatever the width of ints.
+fn constant(expr: &Expr) -> Option<i64> {
+    let value = match expr {
+        Expr::Int(value) => i64::from(*value),
+        Expr::Neg(inner) => match inner.0 {
+            Expr::Int(value) => -i64::from(value),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    i32::try_from(value).is_ok().then_some(value)
+}
+
+/// The values of two int constants.
+fn both(lhs: &Spanned<Expr>, rhs: &Spanned<Expr>) -> Option<(i64, i64)> {
+    Some((constant(&lhs.0)?, constant(&rhs.0)?))
+}
+
+/// The constant `value`, the exact result of an operation, if it's a 32-bit int. Any other
+/// result would wrap, or fail with `--overflow trap`, only with 32-bit ints, so it's left to be
+/// computed when the program runs.
+fn fold(value: i64, span: &Span) -> Option<Expr> {
+    i32::try_from(value).is_ok().then(|| int(value, span))
+}
+
+/// `a + b`, if `(x + a) + b` can be written `x + (a + b)` for an int `x`: when `a` is a constant
+/// of the same sign as `b`, so that `x + a` overflows only if `x + (a + b)` does, and their sum is
+/// a 32-bit int.
+fn reassociate(a: &Expr, b: i64) -> Option<i64> {
+    let a = constant(a)?;
+    let sum = a + b;
+    ((a < 0) == (b < 0) && i32::try_from(sum).is_ok()).then_some(sum)
+}
+
+/// The value of an int or string constant.
+fn value(expr: &Expr) -> Option<Value> {
+    match expr {
+        Expr::Str(text) => Some(Value::Str(text.clone())),
+        _ => constant(expr).map(Value::Int),
+    }
+}
+
+/// An int constant, written as a negated literal when it's negative since literals can't be.
+fn int(value: i64, span: &Span) -> Expr {
+    match value < 0 {
+        true => Expr::Neg(Box::new((
+            Expr::Int(value.unsigned_abs() as u32),
+            span.clone(),
+        ))),
+        false => Expr::Int(value as u32),
+    }
+}
+
+/// Whether `expr` always evaluates to an int or a float when it doesn't fail, which subtracting
+/// zero from or multiplying or dividing by one doesn't change. Adding zero does change a float,
+/// since `-0.0 + 0` is `0.0`.
+fn is_number(expr: &Expr) -> bool {
+    match expr {
+        Expr::Float(_) | Expr::Neg(_) | Expr::Sub(..) | Expr::Mul(..) | Expr::Div(..) => true,
+        Expr::Add(lhs, rhs) => is_number(&lhs.0) && is_number(&rhs.0),
+        Expr::Cond(_, then, otherwise) => is_number(&then.0) && is_number(&otherwise.0),
+        expr => semantics::is_int(expr),
+    }
+}
@@ -0,0 +1,468 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use chumsky::{error::SimpleReason, primitive::end, Parser, Stream};
+
+use crate::{
+    artifact::Artifact,
+    ast::{Definition, Expr, Interpreter, Spanned},
+    delimiters,
+    diagnostics::{Diagnostic, Severity},
+    lint, macros,
+    messages::{Locale, Message},
+    sema,
+    sources::SourceMap,
+    telemetry::Timings,
+    threads,
+    token::Span,
+    types::TypeTable,
+    Ast, Builtins, RunOptions, Token, Value,
+};
+
+/// A language feature that's off unless it's enabled, with `--features` on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Feature {
+    /// `macro` definitions and their expansions
+    Macros,
+    /// `spawn` and `join`, which run functions on threads of their own
+    Threads,
+}
+
+/// How programs are compiled and their diagnostics reported, as set on the command line. It's
+/// given to each compile rather than kept in statics, so that several programs can be compiled at
+/// once with the same settings.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// The language features that are enabled, with `--features`
+    pub features: Vec<Feature>,
+    /// Whether warnings are reported as errors, failing the compile, with `--deny-warnings`
+    pub deny_warnings: bool,
+    /// The language diagnostics are reported in
+    pub locale: Locale,
+}
+
+/// A program that has been parsed and passed semantic analysis.
+#[derive(Debug)]
+pub struct Program {
+    /// Name of the source the program was compiled from, used when reporting diagnostics
+    pub name: String,
+    /// Every file the program was compiled from, including imports
+    pub sources: SourceMap,
+    pub ast: Ast,
+    /// The program as it was when it was loaded or first called, which every call runs
+    interpreter: OnceLock<Interpreter>,
+}
+
+impl Program {
+    /// The program `ast`, compiled from the `sources` of the one called `name`.
+    pub fn new(name: String, sources: SourceMap, ast: Ast) -> Self {
+        Self {
+            name,
+            sources,
+            ast,
+            interpreter: OnceLock::new(),
+        }
+    }
+
+    /// Loads the program at `path` to call into it repeatedly: a source file, which is compiled,
+    /// or IR produced by `build`, which is read.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Vec<Diagnostic>> {
+        let path = path.as_ref();
+        let name = path.to_string_lossy().to_string();
+        let read_error = |e: std::io::Error| {
+            vec![Diagnostic::error(
+                "E0200",
+                Message::new("E0200.load")
+                    .arg("path", name.as_str())
+                    .arg("error", e.to_string()),
+            )]
+        };
+
+        let program = match is_source(path) {
+            true => {
+                let source = fs::read_to_string(path).map_err(read_error)?;
+                compile(&source, &name)?
+            }
+            false => {
+                let bytes = fs::read(path).map_err(read_error)?;
+                let artifact = Artifact::read(&bytes).map_err(|diagnostic| vec![diagnostic])?;
+                Self::new(name, SourceMap::default(), artifact.program.to_ast())
+            }
+        };
+        program
+            .interpreter()
+            .map_err(|diagnostic| vec![diagnostic])?;
+        Ok(program)
+    }
+
+    /// Calls the function `name` with `args`, returning the value it returns. Each call starts
+    /// afresh, with nothing left over from the calls before it, but the program is only resolved
+    /// once, by [`Program::load`] or else by its first call.
+    pub fn call<T: Clone + Into<Value>>(
+        &self,
+        name: &str,
+        args: &[T],
+    ) -> Result<Value, Diagnostic> {
+        self.call_with(RunOptions::default(), name, args)
+    }
+
+    /// Like [`Program::call`], but with custom builtins and limits.
+    pub fn call_with<T: Clone + Into<Value>>(
+        &self,
+        options: RunOptions,
+        name: &str,
+        args: &[T],
+    ) -> Result<Value, Diagnostic> {
+        let args = args.iter().cloned().map(Into::into).collect();
+        self.interpreter()?.call(options, name, args)
+    }
+
+    fn interpreter(&self) -> Result<&Interpreter, Diagnostic> {
+        if let Some(interpreter) = self.interpreter.get() {
+            return Ok(interpreter);
+        }
+        let interpreter = Interpreter::new(self.ast.clone())?;
+        Ok(self.interpreter.get_or_init(|| interpreter))
+    }
+}
+
+/// Whether `path` names a source file rather than IR, by its extension.
+pub fn is_source(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("c" | "cst")
+    )
+}
+
+/// Runs the full front end over `source`: lexing, parsing and semantic analysis.
+///
+/// `name` identifies the source (usually its path) in the resulting program, and imports are
+/// resolved relative to it. Diagnostics in imported files can only be rendered with the files
+/// that were read, so callers that want to report them should use [`compile_in`] instead.
+pub fn compile(source: &str, name: &str) -> Result<Program, Vec<Diagnostic>> {
+    let mut sources = SourceMap::default();
+    let ast = compile_in(&mut sources, source, name)?;
+    Ok(Program::new(name.to_string(), sources, ast))
+}
+
+/// Like [`compile`], but records every file read (the root and its imports) in `sources`.
+pub fn compile_in(
+    sources: &mut SourceMap,
+    source: &str,
+    name: &str,
+) -> Result<Ast, Vec<Diagnostic>> {
+    compile_timed(sources, source, name, &[], &mut Timings::default())
+}
+
+/// Like [`compile_in`], but with the language `features` enabled, adding the time spent in each
+/// pass to `timings`.
+pub fn compile_timed(
+    sources: &mut SourceMap,
+    source: &str,
+    name: &str,
+    features: &[Feature],
+    timings: &mut Timings,
+) -> Result<Ast, Vec<Diagnostic>> {
+    link_timed(sources, &[(name, source)], features, timings)
+}
+
+/// Like [`compile_timed`], but links several root `files`, given by name and source, into one
+/// program, as if a file importing each of them in turn were compiled.
+pub fn link_timed(
+    sources: &mut SourceMap,
+    files: &[(&str, &str)],
+    features: &[Feature],
+    timings: &mut Timings,
+) -> Result<Ast, Vec<Diagnostic>> {
+    let mut loader = Loader {
+        sources,
+        stack: Vec::new(),
+        loaded: HashSet::new(),
+        diagnostics: Vec::new(),
+        complete: true,
+        timings,
+    };
+
+    let mut defs = Vec::new();
+    for (name, source) in files {
+        let key = fs::canonicalize(name).unwrap_or_else(|_| PathBuf::from(name));
+        // a file imported by one linked before it is already in the program
+        if !loader.loaded.contains(&key) {
+            defs.extend(loader.load(key, name, source));
+        }
+    }
+    let mut diagnostics = loader.diagnostics;
+    // the parser recovers from syntax errors with invalid statements and expressions in place of
+    // what it couldn't parse, so the rest of the program is checked with them unless something
+    // is missing from it altogether
+    if !loader.complete {
+        return Err(diagnostics);
+    }
+
+    // each pass only runs if the ones before it (the parser aside) found no errors
+    let pass = |diagnostics: &mut Vec<Diagnostic>, errors: Vec<Diagnostic>| {
+        let failed = !errors.is_empty();
+        diagnostics.extend(errors);
+        failed
+    };
+    let resolved = timings.time("resolve", || check_duplicates(sources, &defs));
+    if pass(&mut diagnostics, resolved) {
+        return Err(diagnostics);
+    }
+
+    let expanded = timings.time("macros", || macros::expand(&mut defs, features));
+    if pass(&mut diagnostics, expanded) {
+        return Err(diagnostics);
+    }
+
+    let threads = timings.time("threads", || threads::check(&defs, features));
+    if pass(&mut diagnostics, threads) {
+        return Err(diagnostics);
+    }
+
+    let ast = Ast { defs };
+    let checked = timings.time("sema", || sema::check(&ast, &Builtins::default()));
+    pass(&mut diagnostics, checked);
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let ast = timings.time("sizeof", || match TypeTable::new(&ast) {
+        Ok(types) => types.fold(ast),
+        Err(_) => ast,
+    });
+    Ok(ast)
+}
+
+/// Like [`link_timed`], but with `options`, and checking the program for warnings once it
+/// compiles, which are returned with it. With `deny_warnings`, the warnings are errors instead,
+/// and fail the compile if there are any.
+///
+/// Every file read is recorded in `sources` whether or not the program compiles, so that the
+/// files it imports are known either way.
+pub fn link_with(
+    sources: &mut SourceMap,
+    files: &[(&str, &str)],
+    options: &Options,
+    timings: &mut Timings,
+) -> Result<(Ast, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let ast = link_timed(sources, files, &options.features, timings)?;
+    let warnings = timings.time("lint", || lint::check(sources, &ast));
+    if !options.deny_warnings || warnings.is_empty() {
+        return Ok((ast, warnings));
+    }
+    Err(warnings
+        .into_iter()
+        .map(|warning| Diagnostic {
+            severity: Severity::Error,
+            ..warning.with_note(Message::new("note.deny-warnings"))
+        })
+        .collect())
+}
+
+/// Lexes `source` on its own, failing with every lexer error.
+pub fn lex(source: &str) -> Result<Vec<Spanned<Token>>, Vec<Diagnostic>> {
+    match Token::lexer().parse_recovery(source) {
+        (Some(tokens), errors) if errors.is_empty() => Ok(tokens),
+        (_, errors) => Err(errors.into_iter().map(Diagnostic::from).collect()),
+    }
+}
+
+/// Lexes and parses `source` on its own, without resolving its imports or analysing it.
+pub fn parse_file(source: &str) -> Result<Ast, Vec<Diagnostic>> {
+    match parse(source, 0, &mut Timings::default()) {
+        (Some(ast), diagnostics) if diagnostics.is_empty() => Ok(ast),
+        (_, diagnostics) => Err(diagnostics),
+    }
+}
+
+/// Lexes `source` on its own like [`lex`], for tools that embed or fuzz the lexer, which can rely on
+/// it never panicking whatever the input.
+pub fn parse_tokens(source: &str) -> Result<Vec<Spanned<Token>>, Vec<Diagnostic>> {
+    lex(source)
+}
+
+/// Lexes and parses `source` on its own like [`parse_file`], for tools that embed or fuzz the
+/// parser, which can rely on it never panicking whatever the input.
+pub fn parse_ast(source: &str) -> Result<Ast, Vec<Diagnostic>> {
+    parse_file(source)
+}
+
+/// Lexes and parses `source` as a single expression, without analysing it.
+pub fn parse_expr(source: &str) -> Result<Spanned<Expr>, Vec<Diagnostic>> {
+    let tokens = lex(source)?;
+    let errors = delimiters::check(source, &tokens);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let len = source.chars().count();
+    Expr::parser()
+        .then_ignore(end())
+        .parse(Stream::from_iter(len..len + 1, tokens.into_iter()))
+        .map_err(|errors| errors.into_iter().map(Diagnostic::from).collect())
+}
+
+/// Lexes and parses a single file whose spans start at `base`.
+fn parse(source: &str, base: usize, timings: &mut Timings) -> (Option<Ast>, Vec<Diagnostic>) {
+    let source_len = source.chars().count();
+    let (tokens, lexer_errors) = timings.time("lex", || Token::lexer().parse_recovery(source));
+    // an unterminated literal runs to the end of the file, so parsing what's left would only
+    // report errors caused by the literal
+    let unterminated = lexer_errors
+        .iter()
+        .any(|error| matches!(error.reason(), SimpleReason::Unclosed { .. }));
+    let mut diagnostics = lexer_errors
+        .into_iter()
+        .map(|error| Diagnostic::from(error).offset(base))
+        .collect::<Vec<_>>();
+
+    let Some(tokens) = tokens.filter(|_| !unterminated) else {
+        return (None, diagnostics);
+    };
+
+    // parsing past an unbalanced delimiter only reports more errors caused by it
+    let delimiter_errors = timings.time("delimiters", || delimiters::check(source, &tokens));
+    if !delimiter_errors.is_empty() {
+        diagnostics.extend(delimiter_errors.into_iter().map(|error| error.offset(base)));
+        return (None, diagnostics);
+    }
+
+    let (ast, parse_errors) = timings.time("parse", || {
+        Ast::parser().parse_recovery(Stream::from_iter(
+            base + source_len..base + source_len + 1,
+            tokens
+                .into_iter()
+                .map(|(token, span)| (token, span.start + base..span.end + base)),
+        ))
+    });
+    diagnostics.extend(parse_errors.into_iter().map(Diagnostic::from));
+
+    (ast, diagnostics)
+}
+
+/// Recursively reads a file and its imports, producing their merged definitions.
+struct Loader<'a> {
+    sources: &'a mut SourceMap,
+    /// Files currently being loaded, used to detect import cycles
+    stack: Vec<PathBuf>,
+    loaded: HashSet<PathBuf>,
+    diagnostics: Vec<Diagnostic>,
+    /// Whether every file was read and parsed, though perhaps with errors recovered from
+    complete: bool,
+    timings: &'a mut Timings,
+}
+
+impl Loader<'_> {
+    fn load(&mut self, key: PathBuf, name: &str, source: &str) -> Vec<Definition> {
+        let base = self.sources.add(name, source);
+        let (ast, diagnostics) = parse(source, base, self.timings);
+        self.diagnostics.extend(diagnostics);
+        let Some(ast) = ast else {
+            self.complete = false;
+            return Vec::new();
+        };
+
+        self.loaded.insert(key.clone());
+        self.stack.push(key);
+
+        let mut defs = Vec::new();
+        let mut own_defs = Vec::new();
+        for def in ast.defs {
+            match def {
+                Definition::Import { path, span } => defs.extend(self.import(name, &path, span)),
+                def => own_defs.push(def),
+            }
+        }
+
+        self.stack.pop();
+        defs.extend(own_defs);
+        defs
+    }
+
+    fn import(&mut self, importer: &str, path: &str, span: Span) -> Vec<Definition> {
+        let resolved = Path::new(importer)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(path);
+
+        let (key, source) = match fs::canonicalize(&resolved)
+            .and_then(|key| fs::read_to_string(&key).map(|source| (key, source)))
+        {
+            Ok(file) => file,
+            Err(e) => {
+                self.diagnostics.push(
+                    Diagnostic::error("E0105", Message::new("E0105").arg("path", path))
+                        .with_label(span, Message::Text(e.to_string())),
+                );
+                self.complete = false;
+                return Vec::new();
+            }
+        };
+
+        if let Some(start) = self.stack.iter().position(|file| *file == key) {
+            let cycle = self.stack[start..]
+                .iter()
+                .chain([&key])
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            self.diagnostics.push(
+                Diagnostic::error("E0106", Message::new("E0106").arg("path", path))
+                    .with_label(span, Message::new("label.imported-here"))
+                    .with_note(Message::new("note.cycle").arg("cycle", cycle)),
+            );
+            self.complete = false;
+            return Vec::new();
+        }
+
+        if self.loaded.contains(&key) {
+            return Vec::new();
+        }
+
+        self.load(key, &resolved.to_string_lossy(), &source)
+    }
+}
+
+/// Reports definitions with the same name that come from different files, saying which files,
+/// before anything else is checked. Sema reports those from the same file.
+fn check_duplicates(sources: &SourceMap, defs: &[Definition]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut seen = HashMap::<&str, &Span>::new();
+    for def in defs {
+        let (name, span) = match def {
+            Definition::Func(func) => (func.name.as_str(), &func.span),
+            Definition::Struct { name, span, .. } => (name.as_str(), span),
+            Definition::Global(global) => (global.name.as_str(), &global.span),
+            // macros are named apart from functions and structs, and expanded away by now, and
+            // a prototype declares a function that's defined too
+            Definition::Import { .. } | Definition::Macro(_) | Definition::Prototype(_) => continue,
+        };
+
+        let Some(first) = seen.insert(name, span) else {
+            continue;
+        };
+
+        let file_of = |span| sources.locate(span).map(|(file, _)| file.name.clone());
+        let (first_file, second_file) = (file_of(first), file_of(span));
+        if first_file != second_file {
+            diagnostics.push(
+                Diagnostic::error("E0104", Message::new("E0104").arg("name", name))
+                    .with_label(first.clone(), Message::new("label.first-defined"))
+                    .with_label(span.clone(), Message::new("label.defined-again"))
+                    .with_note(
+                        Message::new("note.defined-in-both")
+                            .arg("name", name)
+                            .arg("first", first_file.unwrap_or_default())
+                            .arg("second", second_file.unwrap_or_default()),
+                    ),
+            );
+        }
+    }
+
+    diagnostics
+}
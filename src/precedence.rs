@@ -0,0 +1,249 @@
+//! The precedence and associativity of every operator, as a table that the expression parser is
+//! built from, the formatter parenthesizes by, and `crust precedence` prints.
+//!
+//! Levels are listed from the tightest binding to the loosest, as in C, so adding an operator is
+//! a matter of adding it to the right level here.
+
+use std::fmt::Write;
+
+use crate::ast::{BinaryOp, Expr, StepOp, UnaryOp};
+
+/// How operators of the same level group when they're chained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a ? b : c ? d : e` is `a ? b : (c ? d : e)`
+    Right,
+}
+
+/// The operators of a level, by where they go around their operands.
+#[derive(Debug, Clone, Copy)]
+pub enum Ops {
+    /// `a[i]` and `f(args)`, after their operand
+    Postfix(&'static [&'static str]),
+    /// Operators before any operand, and the ones before a variable like increments
+    Prefix {
+        ops: &'static [(&'static str, UnaryOp)],
+        steps: &'static [(&'static str, StepOp)],
+    },
+    /// Operators between their two operands
+    Binary(&'static [(&'static str, BinaryOp)]),
+    /// `cond ? then : otherwise`
+    Conditional,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Level {
+    pub name: &'static str,
+    pub ops: Ops,
+    pub assoc: Assoc,
+}
+
+impl Level {
+    /// The operators of the level as they're written, with `a` and `b` for their operands.
+    pub fn forms(&self) -> Vec<String> {
+        match self.ops {
+            Ops::Postfix(ops) => ops
+                .iter()
+                .map(|op| {
+                    let (open, close) = op.split_at(1);
+                    format!("a{open}b{close}")
+                })
+                .collect(),
+            Ops::Prefix { ops, steps } => ops
+                .iter()
+                .map(|(op, _)| *op)
+                .chain(steps.iter().map(|(op, _)| *op))
+                .map(|op| format!("{op}a"))
+                .collect(),
+            Ops::Binary(ops) => ops.iter().map(|(op, _)| format!("a {op} b")).collect(),
+            Ops::Conditional => vec![String::from("a ? b : c")],
+        }
+    }
+}
+
+pub const LEVELS: &[Level] = &[
+    Level {
+        name: "postfix",
+        ops: Ops::Postfix(&["[]", "()"]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "prefix",
+        ops: Ops::Prefix {
+            ops: &[("-", Expr::Neg), ("!", Expr::Not), ("*", Expr::Deref)],
+            steps: &[
+                ("++", Expr::PreInc),
+                ("--", Expr::PreDec),
+                ("&", Expr::AddrOf),
+            ],
+        },
+        assoc: Assoc::Right,
+    },
+    Level {
+        name: "multiplicative",
+        ops: Ops::Binary(&[("*", Expr::Mul), ("/", Expr::Div), ("%", Expr::Rem)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "additive",
+        ops: Ops::Binary(&[("+", Expr::Add), ("-", Expr::Sub)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "shift",
+        ops: Ops::Binary(&[("<<", Expr::Shl), (">>", Expr::Shr)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "relational",
+        ops: Ops::Binary(&[
+            ("<", Expr::Lt),
+            ("<=", Expr::Le),
+            (">", Expr::Gt),
+            (">=", Expr::Ge),
+        ]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "equality",
+        ops: Ops::Binary(&[("==", Expr::Eq), ("!=", Expr::Ne)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "bitwise and",
+        ops: Ops::Binary(&[("&", Expr::BitAnd)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "bitwise xor",
+        ops: Ops::Binary(&[("^", Expr::BitXor)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "bitwise or",
+        ops: Ops::Binary(&[("|", Expr::BitOr)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "logical and",
+        ops: Ops::Binary(&[("&&", Expr::And)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "logical or",
+        ops: Ops::Binary(&[("||", Expr::Or)]),
+        assoc: Assoc::Left,
+    },
+    Level {
+        name: "conditional",
+        ops: Ops::Conditional,
+        assoc: Assoc::Right,
+    },
+];
+
+/// How tightly `expr` binds as an operand, higher binding tighter: `1` for the loosest level,
+/// up to one more than the tightest level for literals, variables and invalid expressions.
+pub fn binding(expr: &Expr) -> usize {
+    let op = match expr {
+        Expr::Err
+        | Expr::Int(_)
+        | Expr::Float(_)
+        | Expr::Str(_)
+        | Expr::Var(_)
+        | Expr::SizeOf(_)
+        | Expr::Spawn { .. }
+        | Expr::Join(_) => return LEVELS.len() + 1,
+        Expr::Index(..) => "[]",
+        Expr::Call { .. } => "()",
+        Expr::Neg(_) => "-",
+        Expr::Not(_) => "!",
+        Expr::PreInc(_) => "++",
+        Expr::PreDec(_) => "--",
+        Expr::Deref(_) => "*",
+        Expr::AddrOf(_) => "&",
+        Expr::Mul(..) => "*",
+        Expr::Div(..) => "/",
+        Expr::Rem(..) => "%",
+        Expr::Add(..) => "+",
+        Expr::Sub(..) => "-",
+        Expr::Shl(..) => "<<",
+        Expr::Shr(..) => ">>",
+        Expr::Lt(..) => "<",
+        Expr::Le(..) => "<=",
+        Expr::Gt(..) => ">",
+        Expr::Ge(..) => ">=",
+        Expr::Eq(..) => "==",
+        Expr::Ne(..) => "!=",
+        Expr::BitAnd(..) => "&",
+        Expr::BitXor(..) => "^",
+        Expr::BitOr(..) => "|",
+        Expr::And(..) => "&&",
+        Expr::Or(..) => "||",
+        Expr::Cond(..) => "?:",
+    };
+    // `-`, `*` and `&` are both prefix and binary operators, which are told apart by where they go
+    let prefix = matches!(
+        expr,
+        Expr::Neg(_)
+            | Expr::Not(_)
+            | Expr::PreInc(_)
+            | Expr::PreDec(_)
+            | Expr::Deref(_)
+            | Expr::AddrOf(_)
+    );
+    let level = LEVELS
+        .iter()
+        .position(|level| match level.ops {
+            Ops::Postfix(ops) => ops.contains(&op),
+            Ops::Prefix { ops, steps } => {
+                prefix
+                    && (ops.iter().any(|(text, _)| *text == op)
+                        || steps.iter().any(|(text, _)| *text == op))
+            }
+            Ops::Binary(ops) => !prefix && ops.iter().any(|(text, _)| *text == op),
+            Ops::Conditional => op == "?:",
+        })
+        .expect("every operator is in the precedence table");
+    LEVELS.len() - level
+}
+
+/// The table printed by `crust precedence`, from the tightest binding level to the loosest.
+pub fn table() -> String {
+    let rows = LEVELS
+        .iter()
+        .enumerate()
+        .map(|(i, level)| {
+            let assoc = match level.assoc {
+                Assoc::Left => "left",
+                Assoc::Right => "right",
+            };
+            [
+                (i + 1).to_string(),
+                level.name.to_string(),
+                assoc.to_string(),
+                level.forms().join("  "),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["LEVEL", "NAME", "ASSOCIATIVITY", "OPERATORS"].map(String::from);
+
+    let widths = (0..3)
+        .map(|column| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[column].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let mut table = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        for (cell, width) in row.iter().zip(&widths) {
+            write!(table, "{cell:<width$}  ").unwrap();
+        }
+        writeln!(table, "{}", row[3]).unwrap();
+    }
+    table
+}
@@ -0,0 +1,438 @@
+//! Selecting nodes of an AST with CSS-like selectors, for lints and scripts that look for
+//! particular constructs.
+//!
+//! A selector is a list of steps, each matching a node anywhere inside the node matched by the
+//! step before it, or directly inside it if the two are separated by `>`. A step names the kind of
+//! node — `fn`, `struct`, `import`, `macro`, `global`, `param`, `stmt`, `expr`, or `*` for any — optionally
+//! followed by `:` and a variant, and by any number of `[attr=value]` filters:
+//!
+//! ```text
+//! fn[name=main] > stmt:return expr:call[name=println]
+//! ```
+//!
+//! The variants of [`Statement`] and [`Expr`] are named after them in lowercase, `Assign` being
+//! `let`, `Reassign` being `set`, `ReturnVoid` being `return` too, the [`Statement::Expr`] of an
+//! expression whose value is discarded being `discard`, `Inline` being `asm` or `ir` for the
+//! block's language, `Err` being `error`, `PreInc` and `PreDec` being `inc` and `dec` and the
+//! `Bit` ops dropping the prefix, so that `&&` and `||` are `land` and `lor`. The attributes are `name`, `ty`, which is also the return type
+//! of a function and the type `sizeof` measures, and `value`, which is the value of a literal, the length of an array, the path
+//! of an import or the code of an inline block.
+
+use crate::{
+    ast::{Definition, Expr, Param, Spanned, Statement},
+    diagnostics::Diagnostic,
+    literal,
+    messages::Message,
+    token::Span,
+    visit::{self, Visitor},
+    Ast,
+};
+
+/// Every kind of node and the variants it has.
+const KINDS: &[(&str, &[&str])] = &[
+    ("fn", &[]),
+    ("struct", &[]),
+    ("import", &[]),
+    ("macro", &[]),
+    ("decl", &[]),
+    ("global", &[]),
+    ("param", &[]),
+    (
+        "stmt",
+        &[
+            "invalid", "return", "let", "array", "store", "set", "expand", "discard", "asm", "ir",
+            "write", "switch", "break", "continue",
+        ],
+    ),
+    (
+        "expr",
+        &[
+            "error", "int", "str", "var", "neg", "mul", "div", "add", "sub", "rem", "and", "or",
+            "xor", "shl", "shr", "index", "call", "not", "inc", "dec", "lt", "le", "gt", "ge",
+            "eq", "ne", "cond", "addr", "deref", "sizeof", "spawn", "join", "land", "lor", "float",
+        ],
+    ),
+];
+
+const ATTRS: &[&str] = &["name", "ty", "value"];
+
+/// Selects the nodes of `ast` that match `selector`, in the order they appear in the source.
+pub fn query<'a>(ast: &'a Ast, selector: &str) -> Result<Vec<Node<'a>>, Diagnostic> {
+    Ok(Selector::parse(selector)?.select(ast))
+}
+
+/// A node of the AST, which is what a selector matches.
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Definition(&'a Definition),
+    Param(&'a Param),
+    Statement(&'a Spanned<Statement>),
+    Expr(&'a Spanned<Expr>),
+}
+
+impl Node<'_> {
+    /// The kind of the node, as it's named in selectors.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Node::Definition(Definition::Func(_)) => "fn",
+            Node::Definition(Definition::Struct { .. }) => "struct",
+            Node::Definition(Definition::Import { .. }) => "import",
+            Node::Definition(Definition::Macro(_)) => "macro",
+            Node::Definition(Definition::Prototype(_)) => "decl",
+            Node::Definition(Definition::Global(_)) => "global",
+            Node::Param(_) => "param",
+            Node::Statement(_) => "stmt",
+            Node::Expr(_) => "expr",
+        }
+    }
+
+    /// The variant of a statement or expression, as it's named in selectors.
+    pub fn variant(&self) -> Option<&'static str> {
+        let variant = match self {
+            Node::Definition(_) | Node::Param(_) => return None,
+            Node::Statement((statement, _)) => match statement {
+                Statement::Invalid => "invalid",
+                Statement::Return(_) | Statement::ReturnVoid => "return",
+                Statement::Expr(_) => "discard",
+                Statement::Assign { .. } => "let",
+                Statement::Array { .. } => "array",
+                Statement::Store { .. } => "store",
+                Statement::Reassign { .. } => "set",
+                Statement::Write { .. } => "write",
+                Statement::Expand { .. } => "expand",
+                Statement::Inline { lang, .. } => lang.keyword().trim_start_matches('_'),
+                Statement::Switch { .. } => "switch",
+                Statement::Break => "break",
+                Statement::Continue => "continue",
+            },
+            Node::Expr((expr, _)) => match expr {
+                Expr::Err => "error",
+                Expr::Int(_) => "int",
+                Expr::Float(_) => "float",
+                Expr::Str(_) => "str",
+                Expr::Var(_) => "var",
+                Expr::Neg(_) => "neg",
+                Expr::Mul(..) => "mul",
+                Expr::Div(..) => "div",
+                Expr::Add(..) => "add",
+                Expr::Sub(..) => "sub",
+                Expr::Rem(..) => "rem",
+                Expr::BitAnd(..) => "and",
+                Expr::BitOr(..) => "or",
+                Expr::BitXor(..) => "xor",
+                Expr::Shl(..) => "shl",
+                Expr::Shr(..) => "shr",
+                Expr::Index(..) => "index",
+                Expr::Call { .. } => "call",
+                Expr::Not(_) => "not",
+                Expr::PreInc(_) => "inc",
+                Expr::PreDec(_) => "dec",
+                Expr::AddrOf(_) => "addr",
+                Expr::Deref(_) => "deref",
+                Expr::SizeOf(_) => "sizeof",
+                Expr::Spawn { .. } => "spawn",
+                Expr::Join(_) => "join",
+                Expr::Lt(..) => "lt",
+                Expr::Le(..) => "le",
+                Expr::Gt(..) => "gt",
+                Expr::Ge(..) => "ge",
+                Expr::Eq(..) => "eq",
+                Expr::Ne(..) => "ne",
+                Expr::Cond(..) => "cond",
+                Expr::And(..) => "land",
+                Expr::Or(..) => "lor",
+            },
+        };
+        Some(variant)
+    }
+
+    /// Span of the node, which for functions and parameters is that of their name.
+    pub fn span(&self) -> Span {
+        match self {
+            Node::Definition(Definition::Func(func)) => func.span.clone(),
+            Node::Definition(Definition::Macro(r#macro)) => r#macro.span.clone(),
+            Node::Definition(Definition::Prototype(prototype)) => prototype.span.clone(),
+            Node::Definition(Definition::Global(global)) => global.span.clone(),
+            Node::Definition(Definition::Struct { span, .. } | Definition::Import { span, .. }) => {
+                span.clone()
+            }
+            Node::Param(param) => param.span.clone(),
+            Node::Statement((_, span)) | Node::Expr((_, span)) => span.clone(),
+        }
+    }
+
+    fn attr(&self, attr: &str) -> Option<String> {
+        match (self, attr) {
+            (Node::Definition(Definition::Func(func)), "name") => Some(func.name.to_string()),
+            (Node::Definition(Definition::Func(func)), "ty") => Some(func.ret.to_string()),
+            (Node::Definition(Definition::Struct { name, .. }), "name") => Some(name.to_string()),
+            (Node::Definition(Definition::Macro(r#macro)), "name") => {
+                Some(r#macro.name.to_string())
+            }
+            (Node::Definition(Definition::Prototype(prototype)), "name") => {
+                Some(prototype.name.to_string())
+            }
+            (Node::Definition(Definition::Prototype(prototype)), "ty") => {
+                Some(prototype.ret.to_string())
+            }
+            (Node::Definition(Definition::Global(global)), "name") => Some(global.name.to_string()),
+            (Node::Definition(Definition::Global(global)), "ty") => Some(global.ty.to_string()),
+            (Node::Definition(Definition::Import { path, .. }), "value") => Some(path.clone()),
+            (Node::Param(param), "name") => Some(param.name.to_string()),
+            (Node::Param(param), "ty") => Some(param.ty.to_string()),
+            (Node::Statement((statement, _)), _) => match (statement, attr) {
+                (
+                    Statement::Assign { name, .. }
+                    | Statement::Array { name, .. }
+                    | Statement::Store { name, .. }
+                    | Statement::Reassign { name, .. }
+                    | Statement::Expand { name, .. },
+                    "name",
+                ) => Some(name.to_string()),
+                (Statement::Assign { ty, .. } | Statement::Array { ty, .. }, "ty") => {
+                    Some(ty.to_string())
+                }
+                (Statement::Array { len, .. }, "value") => Some(len.to_string()),
+                (Statement::Inline { code, .. }, "value") => Some(code.clone()),
+                _ => None,
+            },
+            (Node::Expr((expr, _)), _) => match (expr, attr) {
+                (
+                    Expr::Var(name)
+                    | Expr::Call { name, .. }
+                    | Expr::Spawn { name, .. }
+                    | Expr::PreInc(name)
+                    | Expr::PreDec(name)
+                    | Expr::AddrOf(name),
+                    "name",
+                ) => Some(name.to_string()),
+                (Expr::Int(value), "value") => Some(value.to_string()),
+                (Expr::Float(value), "value") => Some(literal::float(*value)),
+                (Expr::Str(value), "value") => Some(value.clone()),
+                (Expr::SizeOf(ty), "ty") => Some(ty.to_string()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A parsed selector, which can select from any number of ASTs.
+#[derive(Debug)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug)]
+struct Step {
+    /// Whether the node must be directly inside the one matched by the step before
+    child: bool,
+    /// `None` for `*`
+    kind: Option<&'static str>,
+    variant: Option<&'static str>,
+    attrs: Vec<(&'static str, String)>,
+}
+
+impl Step {
+    fn matches(&self, node: &Node) -> bool {
+        self.kind.is_none_or(|kind| kind == node.kind())
+            && self
+                .variant
+                .is_none_or(|variant| Some(variant) == node.variant())
+            && self
+                .attrs
+                .iter()
+                .all(|(attr, value)| node.attr(attr).as_ref() == Some(value))
+    }
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Self, Diagnostic> {
+        let invalid = |column: usize, reason: &str| {
+            Diagnostic::error(
+                "E0700",
+                Message::new("E0700")
+                    .arg("selector", selector)
+                    .arg("column", (column + 1).to_string())
+                    .arg("reason", reason),
+            )
+        };
+
+        let chars = selector.chars().collect::<Vec<_>>();
+        let mut i = 0;
+        let skip_spaces = |i: &mut usize| {
+            while chars.get(*i).is_some_and(|c| c.is_whitespace()) {
+                *i += 1;
+            }
+        };
+        let word = |i: &mut usize| {
+            let start = *i;
+            while chars
+                .get(*i)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+            {
+                *i += 1;
+            }
+            chars[start..*i].iter().collect::<String>()
+        };
+
+        let mut steps = Vec::new();
+        skip_spaces(&mut i);
+        while i < chars.len() {
+            let child = chars[i] == '>';
+            if child {
+                if steps.is_empty() {
+                    return Err(invalid(i, "`>` must come after another step"));
+                }
+                i += 1;
+                skip_spaces(&mut i);
+                if i == chars.len() {
+                    return Err(invalid(i, "expected a step after `>`"));
+                }
+            }
+
+            let start = i;
+            let kind = if chars.get(i) == Some(&'*') {
+                i += 1;
+                None
+            } else {
+                let name = word(&mut i);
+                if name.is_empty() {
+                    return Err(invalid(start, "expected a kind of node or `*`"));
+                }
+                match KINDS.iter().find(|(kind, _)| *kind == name) {
+                    Some(kind) => Some(kind),
+                    None => return Err(invalid(start, &format!("unknown kind `{name}`"))),
+                }
+            };
+
+            let mut variant = None;
+            if chars.get(i) == Some(&':') {
+                i += 1;
+                let start = i;
+                let name = word(&mut i);
+                let variants = kind.map_or(&[][..], |(_, variants)| variants);
+                match variants.iter().find(|variant| **variant == name) {
+                    Some(found) => variant = Some(*found),
+                    None => {
+                        let kind = kind.map_or("*", |(kind, _)| kind);
+                        return Err(invalid(start, &format!("`{kind}` has no variant `{name}`")));
+                    }
+                }
+            }
+
+            let mut attrs = Vec::new();
+            while chars.get(i) == Some(&'[') {
+                i += 1;
+                let start = i;
+                let name = word(&mut i);
+                let Some(attr) = ATTRS.iter().find(|attr| **attr == name) else {
+                    return Err(invalid(start, &format!("unknown attribute `{name}`")));
+                };
+                if chars.get(i) != Some(&'=') {
+                    return Err(invalid(i, "expected `=`"));
+                }
+                i += 1;
+                let Some(len) = chars[i..].iter().position(|c| *c == ']') else {
+                    return Err(invalid(start - 1, "unclosed `[`"));
+                };
+                attrs.push((*attr, chars[i..i + len].iter().collect()));
+                i += len + 1;
+            }
+
+            steps.push(Step {
+                child,
+                kind: kind.map(|(kind, _)| *kind),
+                variant,
+                attrs,
+            });
+
+            let end = i;
+            skip_spaces(&mut i);
+            if i == end && i < chars.len() && chars[i] != '>' {
+                return Err(invalid(i, &format!("unexpected `{}`", chars[i])));
+            }
+        }
+
+        if steps.is_empty() {
+            return Err(invalid(0, "the selector is empty"));
+        }
+        Ok(Self { steps })
+    }
+
+    /// The nodes of `ast` that match the selector, in the order they appear in the source.
+    pub fn select<'a>(&self, ast: &'a Ast) -> Vec<Node<'a>> {
+        let mut tree = Tree::default();
+        tree.visit_ast(ast);
+
+        let last = self.steps.len() - 1;
+        (0..tree.nodes.len())
+            .filter(|&index| self.matches(&tree, index, last))
+            .map(|index| tree.nodes[index].0)
+            .collect()
+    }
+
+    /// Whether the node at `index` matches `step`, and the nodes around it the steps before.
+    fn matches(&self, tree: &Tree, index: usize, step: usize) -> bool {
+        let (node, parent) = &tree.nodes[index];
+        if !self.steps[step].matches(node) {
+            return false;
+        }
+        if step == 0 {
+            return true;
+        }
+
+        if self.steps[step].child {
+            return parent.is_some_and(|parent| self.matches(tree, parent, step - 1));
+        }
+        let mut ancestor = *parent;
+        while let Some(index) = ancestor {
+            if self.matches(tree, index, step - 1) {
+                return true;
+            }
+            ancestor = tree.nodes[index].1;
+        }
+        false
+    }
+}
+
+/// Every node of an AST with the index of its parent, in the order they appear in the source.
+#[derive(Default)]
+struct Tree<'a> {
+    nodes: Vec<(Node<'a>, Option<usize>)>,
+    /// The nodes enclosing the one being visited, innermost last
+    parents: Vec<usize>,
+}
+
+impl<'a> Tree<'a> {
+    /// Adds `node` under the current parent, visiting its children with `walk`.
+    fn node(&mut self, node: Node<'a>, walk: impl FnOnce(&mut Self)) {
+        self.parents.push(self.nodes.len());
+        self.nodes
+            .push((node, self.parents.iter().rev().nth(1).copied()));
+        walk(self);
+        self.parents.pop();
+    }
+}
+
+impl<'a> Visitor<'a> for Tree<'a> {
+    fn visit_definition(&mut self, def: &'a Definition) {
+        self.node(Node::Definition(def), |tree| {
+            visit::walk_definition(tree, def)
+        });
+    }
+
+    fn visit_param(&mut self, param: &'a Param) {
+        self.node(Node::Param(param), |_| {});
+    }
+
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        self.node(Node::Statement(statement), |tree| {
+            visit::walk_statement(tree, statement)
+        });
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        self.node(Node::Expr(expr), |tree| visit::walk_expr(tree, expr));
+    }
+}
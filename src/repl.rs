@@ -0,0 +1,200 @@
+//! An interactive session for `repl`, where definitions, statements and expressions are entered
+//! one at a time.
+//!
+//! Each input is added to the session's [`SourceMap`] as a file of its own, so the spans of the
+//! definitions it holds keep pointing at their source. A session is saved as JSON with every
+//! input, so loading it rebuilds the same map along with the functions and variables.
+
+use std::{fs, io, path::Path};
+
+use chumsky::{error::Simple, primitive::end, Parser, Stream};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::{self, Definition, Expr, Spanned, Statement},
+    delimiters,
+    diagnostics::Diagnostic,
+    pipeline,
+    sources::SourceMap,
+    Ast, RunOptions, Symbol, Token, Value,
+};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    /// Every input entered so far, in order
+    inputs: Vec<String>,
+    /// The functions and structs defined so far, a definition replacing any with its name
+    pub ast: Ast,
+    /// The variables declared at the prompt, in order
+    pub vars: Vec<(Symbol, Value)>,
+    #[serde(skip)]
+    sources: SourceMap,
+}
+
+/// What a single input holds.
+enum Entry {
+    Defs(Vec<Definition>),
+    Statement(Spanned<Statement>),
+    Expr(Spanned<Expr>),
+}
+
+impl Session {
+    /// Reads a session saved by [`Session::save`].
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut session: Self = serde_json::from_slice(&fs::read(path)?)?;
+        for (i, input) in session.inputs.iter().enumerate() {
+            session.sources.add(input_name(i), input.as_str());
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Every input entered so far, for rendering diagnostics.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Defines the definitions, runs the statement or evaluates the expression in `input`,
+    /// returning the value of an expression or a `return`.
+    pub fn eval(&mut self, input: &str) -> Result<Option<Value>, Vec<Diagnostic>> {
+        let base = self.sources.add(input_name(self.inputs.len()), input);
+        self.inputs.push(input.to_string());
+
+        let statement = match parse(input, base)? {
+            Entry::Defs(defs) => {
+                for def in defs {
+                    let name = name(&def);
+                    // a prototype only replaces an earlier prototype, and never the definition
+                    let declares = matches!(def, Definition::Prototype(_));
+                    self.ast.defs.retain(|old| {
+                        name.is_none()
+                            || self::name(old) != name
+                            || declares && !matches!(old, Definition::Prototype(_))
+                    });
+                    self.ast.defs.push(def);
+                }
+                return Ok(None);
+            }
+            Entry::Statement(statement) => statement,
+            Entry::Expr(expr) => {
+                let span = expr.1.clone();
+                // a void function has no value to show
+                let void = match &expr.0 {
+                    Expr::Call { name, .. } => self.ast.defs.iter().any(|def| match def {
+                        Definition::Func(func) => func.name == *name && func.ret == ast::VOID,
+                        _ => false,
+                    }),
+                    _ => false,
+                };
+                if void {
+                    (Statement::Expr(Box::new(expr)), span)
+                } else {
+                    (Statement::Return(Box::new(expr)), span)
+                }
+            }
+        };
+
+        self.ast
+            .run_statement(RunOptions::default(), &statement, &mut self.vars)
+            .map_err(|diagnostic| vec![diagnostic])
+    }
+}
+
+/// Whether `input` opens more delimiters than it closes, so the next line should continue it.
+pub fn is_incomplete(input: &str) -> bool {
+    let Ok(tokens) = pipeline::lex(input) else {
+        return false;
+    };
+    let depth = tokens.iter().fold(0, |depth, (token, _)| match token {
+        Token::Ctrl('(' | '[' | '{') => depth + 1,
+        Token::Ctrl(')' | ']' | '}') => depth - 1,
+        _ => depth,
+    });
+    depth > 0
+}
+
+fn input_name(index: usize) -> String {
+    format!("<repl:{}>", index + 1)
+}
+
+/// The name a definition defines, if it defines one.
+fn name(def: &Definition) -> Option<&str> {
+    match def {
+        Definition::Func(func) => Some(&func.name),
+        Definition::Struct { name, .. } => Some(name),
+        Definition::Macro(r#macro) => Some(&r#macro.name),
+        Definition::Prototype(prototype) => Some(&prototype.name),
+        Definition::Global(global) => Some(&global.name),
+        Definition::Import { .. } => None,
+    }
+}
+
+/// Parses `input`, whose spans start at `base`, as definitions, a statement or an expression,
+/// failing with the errors of whichever got furthest. Constants are definitions, but other
+/// globals are parsed as statements declaring variables.
+fn parse(input: &str, base: usize) -> Result<Entry, Vec<Diagnostic>> {
+    let offset = |errors: Vec<Diagnostic>| {
+        errors
+            .into_iter()
+            .map(|error| error.offset(base))
+            .collect::<Vec<_>>()
+    };
+    let tokens = pipeline::lex(input).map_err(offset)?;
+    let errors = delimiters::check(input, &tokens);
+    if !errors.is_empty() {
+        return Err(offset(errors));
+    }
+
+    let len = input.chars().count();
+    let stream = || {
+        let tokens = tokens
+            .iter()
+            .map(|(token, span)| (token.clone(), span.start + base..span.end + base));
+        Stream::from_iter(
+            base + len..base + len + 1,
+            tokens.collect::<Vec<_>>().into_iter(),
+        )
+    };
+    let attempts = [
+        Definition::parser()
+            .repeated()
+            .at_least(1)
+            .then_ignore(end())
+            .map(Entry::Defs)
+            .parse(stream()),
+        Statement::parser()
+            .then_ignore(end())
+            .map(Entry::Statement)
+            .parse(stream()),
+        Expr::parser()
+            .then_ignore(end())
+            .map(Entry::Expr)
+            .parse(stream()),
+    ];
+
+    let mut furthest = Vec::new();
+    for attempt in attempts {
+        match attempt {
+            // a variable declared at the prompt is one of the session's variables, not a global
+            Ok(Entry::Defs(defs))
+                if defs
+                    .iter()
+                    .any(|def| matches!(def, Definition::Global(global) if !global.constant)) =>
+            {
+                continue
+            }
+            Ok(entry) => return Ok(entry),
+            Err(errors) => {
+                let reached =
+                    |errors: &[Simple<Token>]| errors.iter().map(|error| error.span().start).max();
+                if furthest.is_empty() || reached(&errors) > reached(&furthest) {
+                    furthest = errors;
+                }
+            }
+        }
+    }
+    Err(furthest.into_iter().map(Diagnostic::from).collect())
+}
@@ -0,0 +1,604 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use crate::{
+    ast::{
+        self, Case, Definition, Expr, Func, Global, InlinePiece, Param, Prototype, Spanned,
+        Statement,
+    },
+    diagnostics::Diagnostic,
+    messages::Message,
+    token::Span,
+    types::TypeTable,
+    Ast, Builtins,
+};
+
+/// Functions from C's standard library that aren't builtins, with the builtin that does their job.
+const C_FUNCTIONS: &[(&str, &str)] = &[
+    ("printf", "print"),
+    ("puts", "println"),
+    ("scanf", "read_int"),
+    ("strlen", "len"),
+];
+
+/// Validates that every name used in `ast` refers to something that exists and every name it
+/// defines is only defined once, that every type a struct field or `sizeof` uses has a size, that
+/// `void` is only used as the return type of a function whose value is never used, and that
+/// globals start with constant values and constants are never assigned to.
+pub fn check(ast: &Ast, builtins: &Builtins) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        funcs: HashMap::new(),
+        prototypes: HashMap::new(),
+        globals: HashMap::new(),
+        types: None,
+        builtins,
+        recovered: false,
+        diagnostics: Vec::new(),
+    };
+    match TypeTable::new(ast) {
+        Ok(types) => checker.types = Some(types),
+        Err(errors) => checker.diagnostics.extend(errors),
+    }
+
+    // every function is known before any is checked, so functions can call each other in any order
+    for def in &ast.defs {
+        match def {
+            Definition::Func(func) => {
+                checker.funcs.insert(&func.name, func);
+            }
+            Definition::Prototype(prototype) => {
+                checker
+                    .prototypes
+                    .entry(&prototype.name)
+                    .or_insert(prototype);
+            }
+            Definition::Global(global) => {
+                checker.globals.entry(&global.name).or_insert(global);
+            }
+            _ => (),
+        }
+    }
+    checker.check_duplicates(&ast.defs);
+    let mut globals = Vec::new();
+    for def in &ast.defs {
+        match def {
+            Definition::Prototype(prototype) => checker.check_prototype(prototype),
+            Definition::Global(global) => {
+                checker.check_global(global, &globals);
+                globals.push(global);
+            }
+            _ => (),
+        }
+    }
+
+    match checker.funcs.get("main") {
+        None => checker
+            .diagnostics
+            .push(Diagnostic::error("E0100", Message::new("main-not-found"))),
+        Some(main) if main.ret == ast::VOID => checker.diagnostics.push(
+            Diagnostic::error("E0117", Message::new("E0117"))
+                .with_label(main.span.clone(), Message::new("label.main-defined")),
+        ),
+        Some(_) => (),
+    }
+
+    for def in &ast.defs {
+        if let Definition::Func(func) = def {
+            checker.check_func(func);
+        }
+    }
+
+    checker.diagnostics
+}
+
+struct Checker<'a> {
+    funcs: HashMap<&'a str, &'a Func>,
+    /// The first prototype of each function, which calls are checked against if it isn't defined
+    prototypes: HashMap<&'a str, &'a Prototype>,
+    /// The first global with each name, which every function can use
+    globals: HashMap<&'a str, &'a Global>,
+    /// The layout of every struct, unless a struct's fields were invalid
+    types: Option<TypeTable>,
+    builtins: &'a Builtins,
+    /// Whether the function being checked has had an invalid statement, which the parser
+    /// recovered from and which may have declared any variable
+    recovered: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    /// Reports every function, struct or global with the name of one defined before it, and every
+    /// parameter or field with the name of one before it in the same signature or struct.
+    fn check_duplicates(&mut self, defs: &'a [Definition]) {
+        let mut defined = HashMap::<&str, &Span>::new();
+        for def in defs {
+            let (name, span) = match def {
+                Definition::Func(func) => {
+                    self.check_unique(&func.params, "E0126", &func.name);
+                    (func.name.as_str(), &func.span)
+                }
+                Definition::Struct { name, params, span } => {
+                    self.check_unique(params, "E0127", name);
+                    (name.as_str(), span)
+                }
+                Definition::Prototype(prototype) => {
+                    self.check_unique(&prototype.params, "E0126", &prototype.name);
+                    continue;
+                }
+                Definition::Global(global) => (global.name.as_str(), &global.span),
+                // macros are named apart from functions and structs
+                Definition::Import { .. } | Definition::Macro(_) => continue,
+            };
+            match defined.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert(span);
+                }
+                Entry::Occupied(first) => self.diagnostics.push(
+                    Diagnostic::error("E0104", Message::new("E0104").arg("name", name))
+                        .with_label((*first.get()).clone(), Message::new("label.first-defined"))
+                        .with_label(span.clone(), Message::new("label.defined-again")),
+                ),
+            }
+        }
+    }
+
+    /// Reports every one of `params`, the parameters of the function or fields of the struct
+    /// `owner`, with the name of one before it, with `code`.
+    fn check_unique(&mut self, params: &[Param], code: &'static str, owner: &str) {
+        for (i, param) in params.iter().enumerate() {
+            let Some(first) = params[..i].iter().find(|first| first.name == param.name) else {
+                continue;
+            };
+            self.diagnostics.push(
+                Diagnostic::error(
+                    code,
+                    Message::new(code)
+                        .arg("owner", owner)
+                        .arg("name", param.name.as_str()),
+                )
+                .with_label(first.span.clone(), Message::new("label.first-defined"))
+                .with_label(param.span.clone(), Message::new("label.defined-again")),
+            );
+        }
+    }
+
+    /// Checks that `prototype` declares a function that's defined, with the same signature.
+    fn check_prototype(&mut self, prototype: &Prototype) {
+        let name = prototype.name.as_str();
+        let Some(func) = self.funcs.get(name) else {
+            self.diagnostics.push(
+                Diagnostic::error("E0119", Message::new("E0119").arg("name", name))
+                    .with_label(prototype.span.clone(), Message::new("label.declared-here")),
+            );
+            return;
+        };
+
+        let types = |params: &[Param]| {
+            params
+                .iter()
+                .map(|param| param.ty.as_str())
+                .collect::<Vec<_>>()
+        };
+        if prototype.ret != func.ret || types(&prototype.params) != types(&func.params) {
+            let signature =
+                |ret: &str, params: &[Param]| format!("{ret} {name}({})", types(params).join(", "));
+            self.diagnostics.push(
+                Diagnostic::error("E0120", Message::new("E0120").arg("name", name))
+                    .with_label(prototype.span.clone(), Message::new("label.declared-here"))
+                    .with_label(func.span.clone(), Message::new("label.defined-here"))
+                    .with_note(
+                        Message::new("note.signatures")
+                            .arg("declared", signature(&prototype.ret, &prototype.params))
+                            .arg("defined", signature(&func.ret, &func.params)),
+                    ),
+            );
+        }
+    }
+
+    /// Checks that `global` isn't `void`, and that a constant has a value, which can be worked out
+    /// before the program runs from the `earlier` globals.
+    fn check_global(&mut self, global: &Global, earlier: &[&Global]) {
+        self.check_var_ty(&global.name, &global.ty, &global.span);
+        match &global.expr {
+            Some(expr) => self.check_constant(expr, earlier),
+            None if global.constant => self.diagnostics.push(
+                Diagnostic::error(
+                    "E0134",
+                    Message::new("E0134").arg("name", global.name.as_str()),
+                )
+                .with_label(global.span.clone(), Message::new("label.declared-here")),
+            ),
+            None => (),
+        }
+    }
+
+    /// Checks that the value of a global is a constant: literals, `sizeof` and the constants of
+    /// the `earlier` globals, combined with operators that don't have side effects.
+    fn check_constant(&mut self, (expr, span): &Spanned<Expr>, earlier: &[&Global]) {
+        match expr {
+            Expr::Err | Expr::Int(_) | Expr::Float(_) | Expr::Str(_) => (),
+            Expr::SizeOf(_) => self.check_expr(&(expr.clone(), span.clone()), &[]),
+            Expr::Neg(expr) | Expr::Not(expr) => self.check_constant(expr, earlier),
+            Expr::Mul(lhs, rhs)
+            | Expr::Div(lhs, rhs)
+            | Expr::Add(lhs, rhs)
+            | Expr::Sub(lhs, rhs)
+            | Expr::Rem(lhs, rhs)
+            | Expr::BitAnd(lhs, rhs)
+            | Expr::BitOr(lhs, rhs)
+            | Expr::BitXor(lhs, rhs)
+            | Expr::Shl(lhs, rhs)
+            | Expr::Shr(lhs, rhs)
+            | Expr::Lt(lhs, rhs)
+            | Expr::Le(lhs, rhs)
+            | Expr::Gt(lhs, rhs)
+            | Expr::Ge(lhs, rhs)
+            | Expr::Eq(lhs, rhs)
+            | Expr::Ne(lhs, rhs)
+            | Expr::And(lhs, rhs)
+            | Expr::Or(lhs, rhs) => {
+                self.check_constant(lhs, earlier);
+                self.check_constant(rhs, earlier);
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                self.check_constant(cond, earlier);
+                self.check_constant(then, earlier);
+                self.check_constant(otherwise, earlier);
+            }
+            Expr::Var(name) => match earlier.iter().rev().find(|global| global.name == *name) {
+                Some(global) if global.constant => (),
+                Some(_) => self.diagnostics.push(
+                    Diagnostic::error("E0133", Message::new("E0133"))
+                        .with_label(span.clone(), Message::new("label.not-constant"))
+                        .with_note(
+                            Message::new("note.global-not-constant").arg("name", name.as_str()),
+                        ),
+                ),
+                None => self.diagnostics.push(
+                    Diagnostic::error("E0101", Message::new("E0101").arg("name", name.as_str()))
+                        .with_label(span.clone(), Message::new("label.not-in-scope")),
+                ),
+            },
+            Expr::Index(..)
+            | Expr::Call { .. }
+            | Expr::PreInc(_)
+            | Expr::PreDec(_)
+            | Expr::AddrOf(_)
+            | Expr::Deref(_)
+            | Expr::Spawn { .. }
+            | Expr::Join(_) => self.diagnostics.push(
+                Diagnostic::error("E0133", Message::new("E0133"))
+                    .with_label(span.clone(), Message::new("label.not-constant")),
+            ),
+        }
+    }
+
+    /// Reports assigning to, or taking a pointer to, the variable `name` at `span` if it's a
+    /// constant, which it is unless one of `vars` shadows it.
+    fn check_assignable(&mut self, name: &str, span: &Span, label: &'static str, vars: &[&str]) {
+        if vars.contains(&name) {
+            return;
+        }
+        if let Some(global) = self.globals.get(name).filter(|global| global.constant) {
+            self.diagnostics.push(
+                Diagnostic::error("E0132", Message::new("E0132").arg("name", name))
+                    .with_label(span.clone(), Message::new(label))
+                    .with_label(global.span.clone(), Message::new("label.defined-here")),
+            );
+        }
+    }
+
+    /// The number of parameters and the return type of the function `name`, from its definition
+    /// or else its prototype.
+    fn signature(&self, name: &str) -> Option<(usize, &'a str)> {
+        match (self.funcs.get(name), self.prototypes.get(name)) {
+            (Some(func), _) => Some((func.params.len(), func.ret.as_str())),
+            (None, Some(prototype)) => Some((prototype.params.len(), prototype.ret.as_str())),
+            (None, None) => None,
+        }
+    }
+
+    fn check_func(&mut self, func: &'a Func) {
+        let mut vars = func
+            .params
+            .iter()
+            .map(|param| param.name.as_str())
+            .collect::<Vec<_>>();
+        self.recovered = false;
+        for param in &func.params {
+            self.check_var_ty(&param.name, &param.ty, &param.span);
+        }
+
+        self.check_statements(func, &func.body, &mut vars, false);
+    }
+
+    /// Checks `statements`, which are in a switch if `in_switch`, adding the variables they
+    /// declare to `vars`.
+    fn check_statements(
+        &mut self,
+        func: &'a Func,
+        statements: &'a [Spanned<Statement>],
+        vars: &mut Vec<&'a str>,
+        in_switch: bool,
+    ) {
+        for (statement, span) in statements {
+            match statement {
+                Statement::Invalid => self.recovered = true,
+                Statement::Return(expr) => {
+                    self.check_expr(expr, vars);
+                    if func.ret == ast::VOID {
+                        self.diagnostics.push(
+                            Diagnostic::error(
+                                "E0116",
+                                Message::new("E0116").arg("name", func.name.as_str()),
+                            )
+                            .with_label(expr.1.clone(), Message::new("label.returned-here")),
+                        );
+                    }
+                }
+                Statement::ReturnVoid => {
+                    if func.ret != ast::VOID {
+                        self.diagnostics.push(
+                            Diagnostic::error(
+                                "E0115",
+                                Message::new("E0115")
+                                    .arg("name", func.name.as_str())
+                                    .arg("ty", func.ret.as_str()),
+                            )
+                            .with_label(span.clone(), Message::new("label.returned-here")),
+                        );
+                    }
+                }
+                // the value of a call is discarded here, so the function may be void
+                Statement::Expr(expr) => match &**expr {
+                    (Expr::Call { name, params }, span) => {
+                        self.check_call(name, params, span, vars)
+                    }
+                    expr => self.check_expr(expr, vars),
+                },
+                Statement::Assign { ty, name, expr } => {
+                    self.check_expr(expr, vars);
+                    self.check_var_ty(name, ty, span);
+                    self.check_not_in_switch(name, span, in_switch);
+                    vars.push(name);
+                }
+                Statement::Array { ty, name, .. } => {
+                    self.check_var_ty(name, ty, span);
+                    self.check_not_in_switch(name, span, in_switch);
+                    vars.push(name);
+                }
+                Statement::Switch { expr, cases } => {
+                    self.check_expr(expr, vars);
+                    self.check_cases(cases);
+                    for case in cases {
+                        self.check_statements(func, &case.body, vars, true);
+                    }
+                }
+                Statement::Break => {
+                    if !in_switch {
+                        self.diagnostics.push(
+                            Diagnostic::error("E0129", Message::new("E0129"))
+                                .with_label(span.clone(), Message::new("label.not-in-switch")),
+                        );
+                    }
+                }
+                Statement::Continue => {
+                    let mut error = Diagnostic::error("E0131", Message::new("E0131"))
+                        .with_label(span.clone(), Message::new("label.not-in-loop"));
+                    if in_switch {
+                        error = error.with_note(Message::new("note.switch-not-loop"));
+                    }
+                    self.diagnostics.push(error);
+                }
+                Statement::Store { name, index, expr } => {
+                    self.check_expr(index, vars);
+                    self.check_expr(expr, vars);
+                    self.check_assignable(name, &index.1, "label.stored-into", vars);
+                    if !self.in_scope(name, vars) {
+                        self.diagnostics.push(
+                            Diagnostic::error(
+                                "E0101",
+                                Message::new("E0101").arg("name", name.as_str()),
+                            )
+                            .with_label(index.1.clone(), Message::new("label.stored-into")),
+                        );
+                    }
+                }
+                Statement::Inline { code, .. } => {
+                    for piece in ast::inline_pieces(code) {
+                        match piece {
+                            InlinePiece::Var(name) if !self.in_scope(name, vars) => {
+                                self.diagnostics.push(
+                                    Diagnostic::error(
+                                        "E0101",
+                                        Message::new("E0101").arg("name", name),
+                                    )
+                                    .with_label(span.clone(), Message::new("label.not-in-scope")),
+                                )
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                Statement::Expand { args, .. } => {
+                    for arg in args {
+                        self.check_expr(arg, vars);
+                    }
+                }
+                Statement::Write { pointer, expr } => {
+                    self.check_expr(pointer, vars);
+                    self.check_expr(expr, vars);
+                }
+                Statement::Reassign { name, expr, .. } => {
+                    self.check_expr(expr, vars);
+                    self.check_assignable(name, span, "label.assigned-to", vars);
+                    if !self.in_scope(name, vars) {
+                        self.diagnostics.push(
+                            Diagnostic::error(
+                                "E0101",
+                                Message::new("E0101").arg("name", name.as_str()),
+                            )
+                            .with_label(span.clone(), Message::new("label.assigned-to")),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reports two cases of a switch with the same value, or two `default`s.
+    fn check_cases(&mut self, cases: &[Case]) {
+        for (i, case) in cases.iter().enumerate() {
+            let Some(first) = cases[..i].iter().find(|first| first.value == case.value) else {
+                continue;
+            };
+            let message = match case.value {
+                Some(value) => Message::new("E0128").arg("value", value.to_string()),
+                None => Message::new("E0128.default"),
+            };
+            self.diagnostics.push(
+                Diagnostic::error("E0128", message)
+                    .with_label(first.span.clone(), Message::new("label.first-case"))
+                    .with_label(case.span.clone(), Message::new("label.case-again")),
+            );
+        }
+    }
+
+    /// Reports the variable `name` declared at `span` if it's declared in a switch, where a case
+    /// could jump past its declaration.
+    fn check_not_in_switch(&mut self, name: &str, span: &Span, in_switch: bool) {
+        if in_switch {
+            self.diagnostics.push(
+                Diagnostic::error("E0130", Message::new("E0130").arg("name", name))
+                    .with_label(span.clone(), Message::new("label.declared-here"))
+                    .with_note(Message::new("note.switch-declaration")),
+            );
+        }
+    }
+
+    fn check_expr(&mut self, (expr, span): &Spanned<Expr>, vars: &[&str]) {
+        match expr {
+            Expr::Err | Expr::Int(_) | Expr::Float(_) | Expr::Str(_) => (),
+            Expr::Neg(expr) | Expr::Not(expr) | Expr::Deref(expr) => self.check_expr(expr, vars),
+            Expr::Spawn { name, params } => self.check_call(name, params, span, vars),
+            Expr::Join(handle) => self.check_expr(handle, vars),
+            Expr::SizeOf(ty) => {
+                if let Some(Err(error)) = self.types.as_ref().map(|types| types.size_of(ty)) {
+                    self.diagnostics
+                        .push(error.with_label(span.clone(), Message::new("label.sized-here")));
+                }
+            }
+            Expr::Mul(lhs, rhs)
+            | Expr::Div(lhs, rhs)
+            | Expr::Add(lhs, rhs)
+            | Expr::Sub(lhs, rhs)
+            | Expr::Rem(lhs, rhs)
+            | Expr::BitAnd(lhs, rhs)
+            | Expr::BitOr(lhs, rhs)
+            | Expr::BitXor(lhs, rhs)
+            | Expr::Shl(lhs, rhs)
+            | Expr::Shr(lhs, rhs)
+            | Expr::Lt(lhs, rhs)
+            | Expr::Le(lhs, rhs)
+            | Expr::Gt(lhs, rhs)
+            | Expr::Ge(lhs, rhs)
+            | Expr::Eq(lhs, rhs)
+            | Expr::Ne(lhs, rhs)
+            | Expr::And(lhs, rhs)
+            | Expr::Or(lhs, rhs)
+            | Expr::Index(lhs, rhs) => {
+                self.check_expr(lhs, vars);
+                self.check_expr(rhs, vars);
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                self.check_expr(cond, vars);
+                self.check_expr(then, vars);
+                self.check_expr(otherwise, vars);
+            }
+            Expr::Var(name) | Expr::PreInc(name) | Expr::PreDec(name) | Expr::AddrOf(name) => {
+                match expr {
+                    Expr::PreInc(_) | Expr::PreDec(_) => {
+                        self.check_assignable(name, span, "label.assigned-to", vars)
+                    }
+                    Expr::AddrOf(_) => {
+                        self.check_assignable(name, span, "label.pointer-taken", vars)
+                    }
+                    _ => (),
+                }
+                if !self.in_scope(name, vars) {
+                    self.diagnostics.push(
+                        Diagnostic::error(
+                            "E0101",
+                            Message::new("E0101").arg("name", name.as_str()),
+                        )
+                        .with_label(span.clone(), Message::new("label.not-in-scope")),
+                    );
+                }
+            }
+            Expr::Call { name, params } => {
+                self.check_call(name, params, span, vars);
+                if self
+                    .signature(name)
+                    .is_some_and(|(_, ret)| ret == ast::VOID)
+                {
+                    self.diagnostics.push(
+                        Diagnostic::error(
+                            "E0114",
+                            Message::new("E0114").arg("name", name.as_str()),
+                        )
+                        .with_label(span.clone(), Message::new("label.called-here")),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Whether `name` is one of `vars` or a global, or may be because of an invalid statement
+    /// before it.
+    fn in_scope(&self, name: &str, vars: &[&str]) -> bool {
+        self.recovered || vars.contains(&name) || self.globals.contains_key(name)
+    }
+
+    /// Checks a call to `name` at `span`, whose value may or may not be used.
+    fn check_call(&mut self, name: &str, params: &[Spanned<Expr>], span: &Span, vars: &[&str]) {
+        for param in params {
+            self.check_expr(param, vars);
+        }
+
+        if let Some((arity, _)) = self.signature(name) {
+            if arity != params.len() {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        "E0103",
+                        Message::new("E0103")
+                            .arg("name", name)
+                            .arg("expected", arity.to_string())
+                            .arg("found", params.len().to_string()),
+                    )
+                    .with_label(span.clone(), Message::new("label.wrong-arg-count")),
+                );
+            }
+        } else if !self.builtins.contains(name) {
+            let mut diagnostic =
+                Diagnostic::error("E0102", Message::new("E0102").arg("name", name))
+                    .with_label(span.clone(), Message::new("label.called-here"));
+            if let Some((_, builtin)) = C_FUNCTIONS.iter().find(|(func, _)| *func == name) {
+                diagnostic = diagnostic.with_note(
+                    Message::new("note.c-function")
+                        .arg("name", name)
+                        .arg("builtin", *builtin),
+                );
+            }
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Checks that the variable or parameter `name` declared at `span` isn't `void`.
+    fn check_var_ty(&mut self, name: &str, ty: &str, span: &Span) {
+        if ty == ast::VOID {
+            self.diagnostics.push(
+                Diagnostic::error("E0118", Message::new("E0118").arg("name", name))
+                    .with_label(span.clone(), Message::new("label.declared-here")),
+            );
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! The arithmetic semantics of the language, shared by every layer that evaluates integers.
+//!
+//! All integers are 32-bit two's complement and every operation is total except division by zero:
+//!
+//! - `+`, `-`, `*` and unary `-` wrap on overflow, so `-INT_MIN == INT_MIN`.
+//! - `/` truncates toward zero (`-7 / 2 == -3`) and `INT_MIN / -1` wraps to `INT_MIN`.
+//! - `%` takes the sign of the dividend (`-7 % 2 == -1`) and `INT_MIN % -1 == 0`.
+//! - `<<` and `>>` use only the low 5 bits of the shift amount, so shifting by 32 is a no-op and
+//!   shifting by -1 shifts by 31. `>>` is an arithmetic shift that preserves the sign.
+//! - `!` is 1 for 0 and 0 for any other int, and `++x` and `--x` wrap like `+` and `-`.
+//! - Comparisons (`<`, `<=`, `>`, `>=`, `==` and `!=`) are signed, and are 1 when they hold and 0
+//!   when they don't.
+//! - Division or remainder by zero is an [`ArithError::DivisionByZero`].
+//!
+//! These are the semantics the compiled backends have. The interpreter and the VM evaluate
+//! integers with an [`IntMode`] instead, which by default has the same semantics but can make ints
+//! 64-bit, or report overflow as an [`ArithError::Overflow`] instead of wrapping.
+//!
+//! Floats, whether declared `float` or `double`, are 64-bit IEEE 754 and only the interpreter and
+//! the VM have them:
+//!
+//! - `+`, `-`, `*`, `/` and the comparisons with a float on either side convert an int on the
+//!   other to a float, as [`Value::float_binary`](crate::Value::float_binary) does. `%`, the
+//!   bitwise operators and the shifts only take ints.
+//! - Dividing a float by zero is infinity or NaN rather than an error.
+//! - A float stored in an int variable is truncated toward zero, as [`IntMode::truncate`] does,
+//!   and an int stored in a float variable is converted to the nearest float.
+
+use crate::{ast::Expr, ir::BinOp, messages::Message};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithError {
+    DivisionByZero,
+    /// The result of an operation, named as in "attempt to add with overflow", doesn't fit
+    Overflow(&'static str),
+    /// A literal doesn't fit in an int of the given number of bits
+    OutOfRange(i64, u32),
+}
+
+impl From<ArithError> for Message {
+    fn from(error: ArithError) -> Self {
+        match error {
+            ArithError::DivisionByZero => Message::new("E0202.division-by-zero"),
+            ArithError::Overflow(op) => {
+                let op = match op {
+                    "add" => Message::new("op.add"),
+                    "subtract" => Message::new("op.subtract"),
+                    "multiply" => Message::new("op.multiply"),
+                    "divide" => Message::new("op.divide"),
+                    "take the remainder" => Message::new("op.remainder"),
+                    "negate" => Message::new("op.negate"),
+                    "shift left" => Message::new("op.shift-left"),
+                    "shift right" => Message::new("op.shift-right"),
+                    op => Message::Text(op.to_string()),
+                };
+                Message::new("E0202.overflow").arg("op", op)
+            }
+            ArithError::OutOfRange(value, bits) => Message::new("E0202.out-of-range")
+                .arg("value", value.to_string())
+                .arg("bits", bits.to_string()),
+        }
+    }
+}
+
+pub fn add(lhs: i32, rhs: i32) -> i32 {
+    lhs.wrapping_add(rhs)
+}
+
+pub fn sub(lhs: i32, rhs: i32) -> i32 {
+    lhs.wrapping_sub(rhs)
+}
+
+pub fn mul(lhs: i32, rhs: i32) -> i32 {
+    lhs.wrapping_mul(rhs)
+}
+
+pub fn div(lhs: i32, rhs: i32) -> Result<i32, ArithError> {
+    match rhs {
+        0 => Err(ArithError::DivisionByZero),
+        _ => Ok(lhs.wrapping_div(rhs)),
+    }
+}
+
+pub fn rem(lhs: i32, rhs: i32) -> Result<i32, ArithError> {
+    match rhs {
+        0 => Err(ArithError::DivisionByZero),
+        _ => Ok(lhs.wrapping_rem(rhs)),
+    }
+}
+
+pub fn neg(value: i32) -> i32 {
+    value.wrapping_neg()
+}
+
+/// Logical not, which is 1 for 0 and 0 for any other int.
+pub fn not(value: i32) -> i32 {
+    (value == 0) as i32
+}
+
+pub fn bit_and(lhs: i32, rhs: i32) -> i32 {
+    lhs & rhs
+}
+
+pub fn bit_or(lhs: i32, rhs: i32) -> i32 {
+    lhs | rhs
+}
+
+pub fn bit_xor(lhs: i32, rhs: i32) -> i32 {
+    lhs ^ rhs
+}
+
+pub fn shl(lhs: i32, rhs: i32) -> i32 {
+    lhs.wrapping_shl(rhs as u32)
+}
+
+pub fn shr(lhs: i32, rhs: i32) -> i32 {
+    lhs.wrapping_shr(rhs as u32)
+}
+
+pub fn lt(lhs: i32, rhs: i32) -> i32 {
+    (lhs < rhs) as i32
+}
+
+pub fn le(lhs: i32, rhs: i32) -> i32 {
+    (lhs <= rhs) as i32
+}
+
+pub fn gt(lhs: i32, rhs: i32) -> i32 {
+    (lhs > rhs) as i32
+}
+
+pub fn ge(lhs: i32, rhs: i32) -> i32 {
+    (lhs >= rhs) as i32
+}
+
+pub fn eq(lhs: i32, rhs: i32) -> i32 {
+    (lhs == rhs) as i32
+}
+
+pub fn ne(lhs: i32, rhs: i32) -> i32 {
+    (lhs != rhs) as i32
+}
+
+/// Whether `expr` always evaluates to an int when it doesn't fail.
+pub fn is_int(expr: &Expr) -> bool {
+    match expr {
+        Expr::Int(_) => true,
+        Expr::Neg(inner) => is_int(&inner.0),
+        Expr::Sub(lhs, rhs) | Expr::Mul(lhs, rhs) | Expr::Div(lhs, rhs) => {
+            is_int(&lhs.0) && is_int(&rhs.0)
+        }
+        Expr::Rem(..) | Expr::BitAnd(..) | Expr::BitOr(..) | Expr::BitXor(..) => true,
+        Expr::Shl(..) | Expr::Shr(..) => true,
+        Expr::Lt(..) | Expr::Le(..) | Expr::Gt(..) | Expr::Ge(..) | Expr::Eq(..) | Expr::Ne(..) => {
+            true
+        }
+        Expr::Not(_) | Expr::PreInc(_) | Expr::PreDec(_) | Expr::And(..) | Expr::Or(..) => true,
+        Expr::Add(lhs, rhs) => is_int(&lhs.0) && is_int(&rhs.0),
+        Expr::Cond(_, then, otherwise) => is_int(&then.0) && is_int(&otherwise.0),
+        _ => false,
+    }
+}
+
+/// The number of bits in an int.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Width {
+    #[default]
+    #[value(name = "32")]
+    W32,
+    #[value(name = "64")]
+    W64,
+}
+
+impl Width {
+    pub fn bits(self) -> u32 {
+        match self {
+            Self::W32 => 32,
+            Self::W64 => 64,
+        }
+    }
+}
+
+/// What happens when the result of an operation doesn't fit in an int.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Overflow {
+    /// Keep the low bits of the result, as two's complement does
+    #[default]
+    Wrap,
+    /// Stop the program with an overflow error, as shifting by a negative amount or one of at
+    /// least the width does too
+    Trap,
+}
+
+/// How the interpreter and the VM evaluate ints, which they hold as `i64` whatever the width.
+///
+/// Every operation takes operands that fit in the width and returns a result that does, or an
+/// error. The default is 32-bit ints that wrap, the semantics of the functions in this module.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IntMode {
+    pub width: Width,
+    pub overflow: Overflow,
+}
+
+impl IntMode {
+    /// The smallest and largest ints.
+    pub fn range(self) -> (i64, i64) {
+        match self.width {
+            Width::W32 => (i32::MIN.into(), i32::MAX.into()),
+            Width::W64 => (i64::MIN, i64::MAX),
+        }
+    }
+
+    /// `exact`, the result of the operation named `op` computed without overflow, as an int.
+    fn fit(self, exact: i128, op: &'static str) -> Result<i64, ArithError> {
+        let (min, max) = self.range();
+        if (i128::from(min)..=i128::from(max)).contains(&exact) {
+            return Ok(exact as i64);
+        }
+        match (self.overflow, self.width) {
+            (Overflow::Trap, _) => Err(ArithError::Overflow(op)),
+            (Overflow::Wrap, Width::W32) => Ok(exact as i32 as i64),
+            (Overflow::Wrap, Width::W64) => Ok(exact as i64),
+        }
+    }
+
+    /// The int a literal stands for, with the `-` written before it if it has one.
+    ///
+    /// A literal that doesn't fit wraps, or with [`Overflow::Trap`] is an
+    /// [`ArithError::OutOfRange`].
+    pub fn literal(self, value: i64) -> Result<i64, ArithError> {
+        self.fit(value.into(), "")
+            .map_err(|_| ArithError::OutOfRange(value, self.width.bits()))
+    }
+
+    pub fn add(self, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        self.fit(i128::from(lhs) + i128::from(rhs), "add")
+    }
+
+    pub fn sub(self, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        self.fit(i128::from(lhs) - i128::from(rhs), "subtract")
+    }
+
+    pub fn mul(self, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        self.fit(i128::from(lhs) * i128::from(rhs), "multiply")
+    }
+
+    pub fn div(self, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        match rhs {
+            0 => Err(ArithError::DivisionByZero),
+            _ => self.fit(i128::from(lhs) / i128::from(rhs), "divide"),
+        }
+    }
+
+    /// The remainder, which always fits, so `INT_MIN % -1 == 0` even with [`Overflow::Trap`].
+    pub fn rem(self, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        match rhs {
+            0 => Err(ArithError::DivisionByZero),
+            _ => self.fit(i128::from(lhs) % i128::from(rhs), "take the remainder"),
+        }
+    }
+
+    pub fn neg(self, value: i64) -> Result<i64, ArithError> {
+        self.fit(-i128::from(value), "negate")
+    }
+
+    pub fn shl(self, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        let amount = self.shift_amount(rhs, "shift left")?;
+        Ok(match self.width {
+            Width::W32 => (lhs as i32).wrapping_shl(amount).into(),
+            Width::W64 => lhs.wrapping_shl(amount),
+        })
+    }
+
+    pub fn shr(self, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        let amount = self.shift_amount(rhs, "shift right")?;
+        Ok(match self.width {
+            Width::W32 => (lhs as i32).wrapping_shr(amount).into(),
+            Width::W64 => lhs.wrapping_shr(amount),
+        })
+    }
+
+    /// `lhs op rhs`. The bitwise operators and comparisons always fit, whatever the width.
+    pub fn binary(self, op: BinOp, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        match op {
+            BinOp::Add => self.add(lhs, rhs),
+            BinOp::Sub => self.sub(lhs, rhs),
+            BinOp::Mul => self.mul(lhs, rhs),
+            BinOp::Div => self.div(lhs, rhs),
+            BinOp::Rem => self.rem(lhs, rhs),
+            BinOp::Shl => self.shl(lhs, rhs),
+            BinOp::Shr => self.shr(lhs, rhs),
+            BinOp::And => Ok(lhs & rhs),
+            BinOp::Or => Ok(lhs | rhs),
+            BinOp::Xor => Ok(lhs ^ rhs),
+            BinOp::Lt => Ok((lhs < rhs) as i64),
+            BinOp::Le => Ok((lhs <= rhs) as i64),
+            BinOp::Gt => Ok((lhs > rhs) as i64),
+            BinOp::Ge => Ok((lhs >= rhs) as i64),
+            BinOp::Eq => Ok((lhs == rhs) as i64),
+            BinOp::Ne => Ok((lhs != rhs) as i64),
+        }
+    }
+
+    /// The int a float converts to, truncated toward zero, or the smallest or largest int if
+    /// it's beyond them. NaN converts to 0.
+    pub fn truncate(self, value: f64) -> i64 {
+        match self.width {
+            Width::W32 => (value as i32).into(),
+            Width::W64 => value as i64,
+        }
+    }
+
+    /// The amount to shift by, which wraps to the low bits or must be less than the width.
+    fn shift_amount(self, amount: i64, op: &'static str) -> Result<u32, ArithError> {
+        let bits = self.width.bits();
+        match self.overflow {
+            Overflow::Trap if !(0..i64::from(bits)).contains(&amount) => {
+                Err(ArithError::Overflow(op))
+            }
+            _ => Ok(amount as u32 % bits),
+        }
+    }
+}
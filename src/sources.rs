@@ -0,0 +1,49 @@
+use crate::token::Span;
+
+/// Every source file that makes up a program.
+///
+/// Files are laid out one after another in a single offset space so that a span on its own is
+/// enough to identify both the file and the position within it. A program compiled from a single
+/// file has exactly one entry starting at offset 0.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+    /// Offset of the first character of this file
+    pub base: usize,
+}
+
+impl SourceMap {
+    /// Adds a file to the map, returning the offset its spans should be shifted by.
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> usize {
+        let base = self.files.last().map_or(0, |file| {
+            // leave a gap so that the end-of-input span of one file never touches the next
+            file.base + file.source.chars().count() + 1
+        });
+        self.files.push(SourceFile {
+            name: name.into(),
+            source: source.into(),
+            base,
+        });
+        base
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// Finds the file containing `span`, returning it with the span made relative to the file.
+    pub fn locate(&self, span: &Span) -> Option<(&SourceFile, Span)> {
+        let file = self
+            .files
+            .iter()
+            .rev()
+            .find(|file| file.base <= span.start)?;
+        Some((file, span.start - file.base..span.end - file.base))
+    }
+}
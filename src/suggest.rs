@@ -0,0 +1,27 @@
+//! Finding the word someone probably meant when they misspelled one.
+
+/// The Levenshtein distance between `a` and `b`: how many characters have to be inserted,
+/// removed or replaced to turn one into the other.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let replace = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = replace.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Whether `word` is close enough to `target` to be a misspelling of it.
+///
+/// Up to two edits are allowed, so that swapping two letters counts, but never half of the
+/// target or more, since short words are only a few edits away from most other short words.
+pub fn is_misspelling(word: &str, target: &str) -> bool {
+    let distance = levenshtein(word, target);
+    distance > 0 && distance <= 2 && distance * 2 < target.chars().count()
+}
@@ -0,0 +1,200 @@
+//! Interned identifiers, so that names are copied and compared as ints rather than as strings.
+//!
+//! Every name the lexer reads is interned once in a table shared by the whole process, and the
+//! AST, symbol tables and interpreter hold the [`Symbol`] it's given, as they do for the names of
+//! types. The text of a symbol is kept for as long as the process runs, so a process that compiles
+//! program after program, like the language server or `--watch`, keeps one copy of each name any
+//! of them used. Reading the text of a symbol takes no lock, so that symbols can be displayed and
+//! sorted as cheaply as strings; only interning a name does. Symbols are written and read as their
+//! names, so the JSON IR is as readable as ever.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    ops::Deref,
+    sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A name interned in the process's table, which is equal to another only if their names are.
+///
+/// Symbols dereference to their names, and compare with strings as them, so that most code can
+/// treat one like a `&str`. They're ordered by name too, so that sorting them sorts the names.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// The number of chunks of [`NAMES`], which is enough for every `u32`.
+const CHUNKS: usize = 32;
+
+/// A chunk of [`NAMES`], whose names are each stored once.
+type Chunk = Box<[OnceLock<Box<str>>]>;
+
+/// The name of each symbol, by number, in chunks that are never moved once they're allocated so
+/// that a name can be read while another is added. Chunk `k` holds the `2^k` names of the symbols
+/// numbered from `2^k - 1`.
+static NAMES: [OnceLock<Chunk>; CHUNKS] = [const { OnceLock::new() }; CHUNKS];
+
+/// The symbol of each name interned so far, which interning a name waits to look it up in.
+fn symbols() -> &'static Mutex<HashMap<&'static str, Symbol>> {
+    static SYMBOLS: OnceLock<Mutex<HashMap<&'static str, Symbol>>> = OnceLock::new();
+    SYMBOLS.get_or_init(|| {
+        let mut symbols = HashMap::new();
+        symbols.insert(store(Symbol::EMPTY, ""), Symbol::EMPTY);
+        Mutex::new(symbols)
+    })
+}
+
+/// The chunk of [`NAMES`] and the index in it of the name of `symbol`.
+fn slot(symbol: Symbol) -> (usize, usize) {
+    let position = symbol.0 as u64 + 1;
+    let chunk = position.ilog2();
+    (chunk as usize, (position - (1 << chunk)) as usize)
+}
+
+/// Stores `name` as the name of `symbol`, which doesn't have one yet.
+fn store(symbol: Symbol, name: &str) -> &'static str {
+    let (chunk, index) = slot(symbol);
+    let chunk =
+        NAMES[chunk].get_or_init(|| (0..1usize << chunk).map(|_| OnceLock::new()).collect());
+    chunk[index].get_or_init(|| name.into())
+}
+
+impl Symbol {
+    /// The symbol of the empty string, which no identifier is, for a variable that doesn't have
+    /// its name yet.
+    pub const EMPTY: Symbol = Symbol(0);
+
+    /// The symbol of `name`, interning it if it hasn't been before.
+    pub fn intern(name: &str) -> Self {
+        let mut symbols = symbols().lock().unwrap();
+        if let Some(&symbol) = symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(symbols.len() as u32);
+        symbols.insert(store(symbol, name), symbol);
+        symbol
+    }
+
+    /// The number of the symbol, which is small since symbols are numbered as they're interned,
+    /// so that a table of something for each name can be indexed by it.
+    pub fn number(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn as_str(self) -> &'static str {
+        if self == Self::EMPTY {
+            return "";
+        }
+        let (chunk, index) = slot(self);
+        // a symbol is only ever made once its name is stored
+        NAMES[chunk].get().unwrap()[index].get().unwrap()
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Self::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Self::intern(&name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match self == other {
+            true => std::cmp::Ordering::Equal,
+            false => self.as_str().cmp(other.as_str()),
+        }
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Symbol::from)
+    }
+}
@@ -0,0 +1,260 @@
+//! Symbol tables for `build --emit symtab`, listing every scope of an analysed program with the
+//! symbols declared in it.
+//!
+//! The global scope holds the functions, structs and globals, and each function and struct has a
+//! scope of its own nested in it: a function's holds its parameters and then its local variables in
+//! the order they are declared, and a struct's holds its fields. A variable declared again in the
+//! same function shadows the first one from then on, so both are listed.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+
+use crate::{
+    ast::{Definition, Statement},
+    sources::SourceMap,
+    symbol,
+    token::Span,
+    Ast,
+};
+
+#[derive(Debug, Serialize)]
+pub struct SymbolTable {
+    /// Every scope, with the global scope first and each scope before the ones nested in it
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Scope {
+    pub name: String,
+    /// Index of the scope this one is nested in, which is `None` only for the global scope
+    pub parent: Option<usize>,
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Symbol {
+    pub name: symbol::Symbol,
+    pub kind: SymbolKind,
+    /// The type as it's written, e.g. `int`, `string[4]` or `int(int, string)` for a function
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub location: Location,
+    /// The span of the declaration in the [`SourceMap`] the table was built from, which
+    /// diagnostics about the symbol point at
+    #[serde(skip)]
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Field,
+    Parameter,
+    Variable,
+    Array,
+    Global,
+    Constant,
+}
+
+impl Display for SymbolKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Function => write!(f, "function"),
+            Self::Struct => write!(f, "struct"),
+            Self::Field => write!(f, "field"),
+            Self::Parameter => write!(f, "parameter"),
+            Self::Variable => write!(f, "variable"),
+            Self::Array => write!(f, "array"),
+            Self::Global => write!(f, "global"),
+            Self::Constant => write!(f, "constant"),
+        }
+    }
+}
+
+/// Where a symbol is declared.
+#[derive(Debug, Serialize)]
+pub struct Location {
+    pub file: String,
+    /// 1-based line and column of the start of the declaration
+    pub line: usize,
+    pub column: usize,
+    /// The span of the declaration within its file
+    pub span: Span,
+}
+
+impl SymbolTable {
+    /// Builds the symbol table of `ast`, whose spans are in `sources`.
+    pub fn new(sources: &SourceMap, ast: &Ast) -> Self {
+        let symbol = |name: &str, kind, ty: String, span: &Span| Symbol {
+            name: symbol::Symbol::intern(name),
+            kind,
+            ty,
+            location: locate(sources, span),
+            span: span.clone(),
+        };
+
+        let mut global = Scope {
+            name: String::from("global"),
+            parent: None,
+            symbols: Vec::new(),
+        };
+        let mut scopes = Vec::new();
+        for def in &ast.defs {
+            match def {
+                Definition::Func(func) => {
+                    let params = func
+                        .params
+                        .iter()
+                        .map(|param| param.ty.as_str())
+                        .collect::<Vec<_>>();
+                    let ty = format!("{}({})", func.ret, params.join(", "));
+                    global
+                        .symbols
+                        .push(symbol(&func.name, SymbolKind::Function, ty, &func.span));
+
+                    let mut symbols = func
+                        .params
+                        .iter()
+                        .map(|param| {
+                            symbol(
+                                &param.name,
+                                SymbolKind::Parameter,
+                                param.ty.to_string(),
+                                &param.span,
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    for (statement, span) in &func.body {
+                        match statement {
+                            Statement::Assign { ty, name, .. } => symbols.push(symbol(
+                                name,
+                                SymbolKind::Variable,
+                                ty.to_string(),
+                                span,
+                            )),
+                            Statement::Array { ty, name, len } => symbols.push(symbol(
+                                name,
+                                SymbolKind::Array,
+                                format!("{ty}[{len}]"),
+                                span,
+                            )),
+                            _ => {}
+                        }
+                    }
+                    scopes.push(Scope {
+                        name: func.name.to_string(),
+                        parent: Some(0),
+                        symbols,
+                    });
+                }
+                Definition::Struct { name, params, span } => {
+                    let ty = format!("struct {name}");
+                    global
+                        .symbols
+                        .push(symbol(name, SymbolKind::Struct, ty, span));
+                    scopes.push(Scope {
+                        name: format!("struct {name}"),
+                        parent: Some(0),
+                        symbols: params
+                            .iter()
+                            .map(|field| {
+                                symbol(
+                                    &field.name,
+                                    SymbolKind::Field,
+                                    field.ty.to_string(),
+                                    &field.span,
+                                )
+                            })
+                            .collect(),
+                    });
+                }
+                Definition::Global(var) => {
+                    let kind = match var.constant {
+                        true => SymbolKind::Constant,
+                        false => SymbolKind::Global,
+                    };
+                    global
+                        .symbols
+                        .push(symbol(&var.name, kind, var.ty.to_string(), &var.span));
+                }
+                // imports are resolved and macros expanded by the time a program is analysed, and
+                // a prototype's function is defined too
+                Definition::Import { .. } | Definition::Macro(_) | Definition::Prototype(_) => {}
+            }
+        }
+
+        scopes.insert(0, global);
+        Self { scopes }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// Lists each scope in a table of its symbols, separated by blank lines.
+impl Display for SymbolTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, scope) in self.scopes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match scope.parent {
+                Some(parent) => {
+                    writeln!(f, "scope {} (in {})", scope.name, self.scopes[parent].name)?
+                }
+                None => writeln!(f, "scope {}", scope.name)?,
+            }
+
+            let mut rows = vec![[
+                String::from("NAME"),
+                String::from("KIND"),
+                String::from("TYPE"),
+                String::from("LOCATION"),
+            ]];
+            for symbol in &scope.symbols {
+                let location = &symbol.location;
+                rows.push([
+                    symbol.name.to_string(),
+                    symbol.kind.to_string(),
+                    symbol.ty.clone(),
+                    format!("{}:{}:{}", location.file, location.line, location.column),
+                ]);
+            }
+            let widths = (0..3)
+                .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+                .collect::<Vec<_>>();
+            for row in rows {
+                write!(f, "  ")?;
+                for (cell, width) in row.iter().zip(&widths) {
+                    write!(f, "{cell:<width$}  ")?;
+                }
+                writeln!(f, "{}", row[3])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn locate(sources: &SourceMap, span: &Span) -> Location {
+    let Some((file, span)) = sources.locate(span) else {
+        return Location {
+            file: String::new(),
+            line: 0,
+            column: 0,
+            span: span.clone(),
+        };
+    };
+    let before = file.source.chars().take(span.start).collect::<Vec<_>>();
+    let line = before.iter().filter(|c| **c == '\n').count() + 1;
+    let column = span.start - before.iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1) + 1;
+    Location {
+        file: file.name.clone(),
+        line,
+        column,
+        span,
+    }
+}
@@ -0,0 +1,234 @@
+//! Three-address code listings of the IR, written the way textbooks write it: one quadruple per
+//! line such as `t1 = a + b`, `ifFalse t1 goto L2` and `param x` before a `call`.
+//!
+//! Temporaries that don't hold a variable are numbered `t1`, `t2`, ... in each function, and
+//! variables are written by name. The IR is in SSA form, so assigning to a variable again gives it
+//! a new temporary, which is written as a new version of the variable: `a`, then `a.1`, `a.2`,
+//! and so on. Phis are replaced by a copy at the end of each block that jumps to them, and every
+//! block that's jumped to is labelled with its number, `L1`, `L2`, ... while blocks that can't be
+//! reached, like the one after a `return` at the end of a function, are left out.
+
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+    ir::{BinOp, BlockId, Function, InlinePiece, Inst, Operand, Program, Terminator},
+    literal,
+};
+
+/// Lists every function in `program`, separated by blank lines.
+pub fn emit(program: &Program) -> String {
+    program
+        .funcs
+        .iter()
+        .map(function)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn function(func: &Function) -> String {
+    let names = versions(func.temps.iter().map(|temp| temp.name.as_deref()));
+    let arrays = versions(func.arrays.iter().map(|array| Some(array.name.as_str())));
+    let operand = |operand: &Operand| match operand {
+        Operand::Temp(temp) => names[temp.0].clone(),
+        Operand::Int(value) => value.to_string(),
+        Operand::Str(value) => literal::escape(value),
+    };
+
+    let mut targets = HashSet::new();
+    for block in &func.blocks {
+        match &block.terminator {
+            Terminator::Jump(to) => {
+                targets.insert(*to);
+            }
+            Terminator::Branch {
+                then, otherwise, ..
+            } => {
+                targets.extend([*then, *otherwise]);
+            }
+            Terminator::Return(_) | Terminator::MissingReturn => {}
+        }
+    }
+
+    let params = func
+        .params
+        .iter()
+        .map(|param| names[param.0].as_str())
+        .collect::<Vec<_>>();
+    let mut out = format!("{}({}):\n", func.name, params.join(", "));
+    for (i, block) in func.blocks.iter().enumerate() {
+        match targets.contains(&BlockId(i)) {
+            true => writeln!(out, "L{i}:").unwrap(),
+            // nothing jumps to it, or falls through to it without a label, so it never runs
+            false if i > 0 => continue,
+            false => {}
+        }
+        for inst in &block.insts {
+            for line in inst_lines(inst, &names, &arrays, &operand) {
+                writeln!(out, "    {line}").unwrap();
+            }
+        }
+
+        // the copies for the phis of every block this one jumps to
+        let successors = match &block.terminator {
+            Terminator::Jump(to) => vec![*to],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+            Terminator::Return(_) | Terminator::MissingReturn => Vec::new(),
+        };
+        for to in successors {
+            for (dest, value) in func.phi_moves(BlockId(i), to) {
+                let (dest, value) = (&names[dest.0], operand(value));
+                if *dest != value {
+                    writeln!(out, "    {dest} = {value}").unwrap();
+                }
+            }
+        }
+
+        let next = BlockId(i + 1);
+        match &block.terminator {
+            Terminator::Return(Some(value)) => writeln!(out, "    return {}", operand(value)),
+            Terminator::Return(None) => writeln!(out, "    return"),
+            Terminator::Jump(to) if *to == next => Ok(()),
+            Terminator::Jump(to) => writeln!(out, "    goto L{}", to.0),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                writeln!(out, "    ifFalse {} goto L{}", operand(cond), otherwise.0).unwrap();
+                match *then == next {
+                    true => Ok(()),
+                    false => writeln!(out, "    goto L{}", then.0),
+                }
+            }
+            Terminator::MissingReturn => writeln!(out, "    missing_return"),
+        }
+        .unwrap();
+    }
+    out
+}
+
+/// The name each of a function's temporaries or arrays is written with, given the variable each
+/// one holds if any.
+fn versions<'a>(vars: impl Iterator<Item = Option<&'a str>>) -> Vec<String> {
+    let mut temps = 0;
+    let mut versions = Vec::<(&str, usize)>::new();
+    vars.map(|var| match var {
+        None => {
+            temps += 1;
+            format!("t{temps}")
+        }
+        Some(name) => match versions.iter_mut().find(|(var, _)| *var == name) {
+            Some((_, version)) => {
+                *version += 1;
+                format!("{name}.{version}")
+            }
+            None => {
+                versions.push((name, 0));
+                name.to_string()
+            }
+        },
+    })
+    .collect()
+}
+
+/// The quadruples `inst` is written as, which are several for a call with arguments.
+fn inst_lines(
+    inst: &Inst,
+    names: &[String],
+    arrays: &[String],
+    operand: &impl Fn(&Operand) -> String,
+) -> Vec<String> {
+    let call = |dest: Option<&str>, func: &str, args: &[Operand]| {
+        let mut lines = args
+            .iter()
+            .map(|arg| format!("param {}", operand(arg)))
+            .collect::<Vec<_>>();
+        lines.push(match dest {
+            Some(dest) => format!("{dest} = call {func}, {}", args.len()),
+            None => format!("call {func}, {}", args.len()),
+        });
+        lines
+    };
+
+    let line = match inst {
+        Inst::Copy { dest, src } => format!("{} = {}", names[dest.0], operand(src)),
+        Inst::Neg { dest, src } => format!("{} = -{}", names[dest.0], operand(src)),
+        Inst::Not { dest, src } => format!("{} = !{}", names[dest.0], operand(src)),
+        Inst::Binary { dest, op, lhs, rhs } => format!(
+            "{} = {} {} {}",
+            names[dest.0],
+            operand(lhs),
+            symbol(*op),
+            operand(rhs)
+        ),
+        Inst::Fill { array, value } => format!("fill {}, {}", arrays[array.0], operand(value)),
+        Inst::Load { dest, array, index } => format!(
+            "{} = {}[{}]",
+            names[dest.0],
+            arrays[array.0],
+            operand(index)
+        ),
+        Inst::Store {
+            array,
+            index,
+            value,
+        } => format!(
+            "{}[{}] = {}",
+            arrays[array.0],
+            operand(index),
+            operand(value)
+        ),
+        Inst::Call { dest, func, args } => {
+            return call(dest.map(|dest| names[dest.0].as_str()), func, args)
+        }
+        Inst::Print { args, newline } => {
+            let func = match newline {
+                true => "println",
+                false => "print",
+            };
+            return call(None, func, args);
+        }
+        Inst::ReadInt { dest } => return call(Some(&names[dest.0]), "read_int", &[]),
+        Inst::StrLen { dest, src } => {
+            return call(Some(&names[dest.0]), "len", std::slice::from_ref(src))
+        }
+        Inst::Inline { lang, pieces, .. } => {
+            let code = pieces
+                .iter()
+                .map(|piece| match piece {
+                    InlinePiece::Text(text) => text.clone(),
+                    InlinePiece::Temp(temp) => format!("{{{}}}", names[temp.0]),
+                    InlinePiece::Array(array) => format!("{{{}}}", arrays[array.0]),
+                })
+                .collect::<String>();
+            format!("{} {}", lang.keyword(), literal::escape(&code))
+        }
+        // replaced by copies in the blocks that jump to it
+        Inst::Phi { .. } => return Vec::new(),
+    };
+    vec![line]
+}
+
+/// The operator `op` is written with in C.
+fn symbol(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Rem => "%",
+        BinOp::And => "&",
+        BinOp::Or => "|",
+        BinOp::Xor => "^",
+        BinOp::Shl => "<<",
+        BinOp::Shr => ">>",
+        BinOp::Lt => "<",
+        BinOp::Le => "<=",
+        BinOp::Gt => ">",
+        BinOp::Ge => ">=",
+        BinOp::Eq => "==",
+        BinOp::Ne => "!=",
+    }
+}
@@ -0,0 +1,230 @@
+//! Opt-in usage metrics, appended as one JSON object per line to a local file.
+//!
+//! Nothing is recorded unless the `telemetry.file` setting names a file. Each invocation appends a
+//! record of the command that ran, how large the program was, how long each pass took and how
+//! many diagnostics of each code were reported, so that records from many runs can be aggregated
+//! to find the errors that come up most. Source text is never recorded.
+//!
+//! The same metrics are written to stderr with `--verbose` or the `log` setting, to see how long
+//! each pass of a single run took.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+
+use crate::{
+    ast::{Definition, Expr, Spanned, Statement},
+    pipeline,
+    sources::SourceMap,
+    visit::{walk_definition, walk_expr, walk_statement, Visitor},
+    Ast, Diagnostic,
+};
+
+/// Time spent in each pass, accumulated over every file a pass runs on.
+#[derive(Debug, Default)]
+pub struct Timings {
+    passes: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// Runs `f`, adding the time it takes to `pass`.
+    pub fn time<T>(&mut self, pass: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let value = f();
+        self.add(pass, start.elapsed());
+        value
+    }
+
+    /// Adds the time spent in each of `other`'s passes to this.
+    pub fn extend(&mut self, other: Timings) {
+        for (pass, elapsed) in other.passes {
+            self.add(pass, elapsed);
+        }
+    }
+
+    fn add(&mut self, pass: &'static str, elapsed: Duration) {
+        match self.passes.iter_mut().find(|(name, _)| *name == pass) {
+            Some((_, total)) => *total += elapsed,
+            None => self.passes.push((pass, elapsed)),
+        }
+    }
+
+    /// Passes in the order they first ran, with their total time.
+    pub fn passes(&self) -> &[(&'static str, Duration)] {
+        &self.passes
+    }
+}
+
+/// The size of a compiled program.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramSize {
+    pub files: usize,
+    pub bytes: usize,
+    pub lines: usize,
+    pub functions: usize,
+    pub statements: usize,
+    pub tokens: usize,
+    /// Definitions, statements and expressions
+    pub nodes: usize,
+}
+
+impl ProgramSize {
+    pub fn new(sources: &SourceMap, ast: &Ast) -> Self {
+        let files = sources.files();
+        let funcs = ast.defs.iter().filter_map(|def| match def {
+            Definition::Func(func) => Some(func),
+            _ => None,
+        });
+        Self {
+            files: files.len(),
+            bytes: files.iter().map(|file| file.source.len()).sum(),
+            lines: files.iter().map(|file| file.source.lines().count()).sum(),
+            functions: funcs.clone().count(),
+            statements: funcs.map(|func| func.body.len()).sum(),
+            tokens: files
+                .iter()
+                .map(|file| pipeline::lex(&file.source).map_or(0, |tokens| tokens.len()))
+                .sum(),
+            nodes: {
+                let mut counter = NodeCounter(0);
+                counter.visit_ast(ast);
+                counter.0
+            },
+        }
+    }
+}
+
+struct NodeCounter(usize);
+
+impl<'a> Visitor<'a> for NodeCounter {
+    fn visit_definition(&mut self, def: &'a Definition) {
+        self.0 += 1;
+        walk_definition(self, def);
+    }
+
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        self.0 += 1;
+        walk_statement(self, statement);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        self.0 += 1;
+        walk_expr(self, expr);
+    }
+}
+
+/// Metrics collected over a single invocation of the compiler.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub command: String,
+    pub timings: Timings,
+    pub size: Option<ProgramSize>,
+    /// Number of diagnostics reported, by code
+    pub diagnostics: BTreeMap<String, usize>,
+}
+
+impl Metrics {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn record(&mut self, diagnostic: &Diagnostic) {
+        *self
+            .diagnostics
+            .entry(diagnostic.code.to_string())
+            .or_default() += 1;
+    }
+
+    /// The record for this invocation, as a single line of JSON.
+    pub fn to_json(&self, exit_code: i32) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let passes = self
+            .timings
+            .passes()
+            .iter()
+            .map(|(pass, time)| (pass.to_string(), json!(time.as_secs_f64() * 1000.0)))
+            .collect::<serde_json::Map<_, _>>();
+        let size = self.size.map(|size| {
+            json!({
+                "files": size.files,
+                "bytes": size.bytes,
+                "lines": size.lines,
+                "functions": size.functions,
+                "statements": size.statements,
+                "tokens": size.tokens,
+                "nodes": size.nodes,
+            })
+        });
+
+        json!({
+            "timestamp": timestamp,
+            "version": env!("CARGO_PKG_VERSION"),
+            "command": self.command,
+            "exit_code": exit_code,
+            "size": size,
+            "pass_ms": passes,
+            "diagnostics": self.diagnostics,
+        })
+        .to_string()
+    }
+
+    /// The time each pass took and the size of the program, as a table for `--verbose`.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let passes = self.timings.passes();
+        let width = passes
+            .iter()
+            .map(|(pass, _)| pass.len())
+            .chain(["total".len()])
+            .max()
+            .unwrap_or(0);
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        for (pass, time) in passes {
+            writeln!(text, "{pass:<width$}  {:>9.3} ms", ms(*time)).unwrap();
+        }
+        let total = passes.iter().map(|(_, time)| *time).sum::<Duration>();
+        writeln!(text, "{:<width$}  {:>9.3} ms", "total", ms(total)).unwrap();
+
+        if let Some(size) = self.size {
+            writeln!(
+                text,
+                "files: {}, lines: {}, bytes: {}, tokens: {}, nodes: {}, functions: {}, statements: {}",
+                size.files,
+                size.lines,
+                size.bytes,
+                size.tokens,
+                size.nodes,
+                size.functions,
+                size.statements,
+            )
+            .unwrap();
+        }
+        if !self.diagnostics.is_empty() {
+            let counts = self
+                .diagnostics
+                .iter()
+                .map(|(code, count)| format!("{code} x{count}"))
+                .collect::<Vec<_>>();
+            writeln!(text, "diagnostics: {}", counts.join(", ")).unwrap();
+        }
+        text
+    }
+
+    /// Appends the record for this invocation to `path`, creating it if needed.
+    pub fn append(&self, path: &Path, exit_code: i32) -> std::io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", self.to_json(exit_code))
+    }
+}
@@ -0,0 +1,260 @@
+//! Checks on `spawn` and `join`, which run a function on a thread of its own and wait for it.
+//!
+//! A spawned thread runs on a copy of the program, and shares nothing with the thread that
+//! spawned it but the arguments it's passed, which are copied too. A pointer is the only value
+//! that would let two threads change the same variable, so a function that takes or returns
+//! one can't be spawned, and the interpreter refuses to pass one to a thread whatever type the
+//! function declares. Each thread has globals of its own too, which start with the values they
+//! were defined with, so the code a thread runs can only use the globals nothing in the program
+//! changes. Threads share state through the atomic ints and locks of the builtins instead, which
+//! every thread calls the same instance of.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{self, Definition, Expr, Func, Param, Spanned, Statement},
+    diagnostics::Diagnostic,
+    messages::Message,
+    pipeline::Feature,
+    token::Span,
+    visit::{self, Visitor},
+};
+
+/// Reports every `spawn` and `join` in `defs` if `features` doesn't enable threads, or else the
+/// spawns of functions that take or return a pointer or that use a global the program changes.
+pub fn check(defs: &[Definition], features: &[Feature]) -> Vec<Diagnostic> {
+    let mut sites = Sites::default();
+    for def in defs {
+        sites.visit_definition(def);
+    }
+
+    if !features.contains(&Feature::Threads) {
+        return sites
+            .0
+            .iter()
+            .map(|(_, span, label)| {
+                Diagnostic::error("E0124", Message::new("E0124"))
+                    .with_label(span.clone(), Message::new(label))
+                    .with_note(Message::new("note.enable-threads"))
+            })
+            .collect();
+    }
+
+    let funcs = defs
+        .iter()
+        .filter_map(|def| match def {
+            Definition::Func(func) => Some((func.name.as_str(), func)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let globals = GlobalUses::of(defs);
+    sites
+        .0
+        .iter()
+        .filter_map(|(name, span, _)| {
+            let func: &Func = funcs.get((*name)?)?;
+            let pointer = func
+                .params
+                .iter()
+                .map(|param| (&param.ty, &param.span))
+                .chain([(&func.ret, &func.span)])
+                .find(|(ty, _)| ast::is_pointer(ty));
+            if let Some((_, pointer_span)) = pointer {
+                return Some(
+                    Diagnostic::error(
+                        "E0125",
+                        Message::new("E0125").arg("name", func.name.as_str()),
+                    )
+                    .with_label(span.clone(), Message::new("label.spawned-here"))
+                    .with_label(pointer_span.clone(), Message::new("label.declared-here"))
+                    .with_note(Message::new("note.shared-variable"))
+                    .with_note(Message::new("note.share-atomics")),
+                );
+            }
+
+            let (used, changed) = globals.shared(&func.name)?;
+            let mut error = Diagnostic::error(
+                "E0135",
+                Message::new("E0135")
+                    .arg("name", func.name.as_str())
+                    .arg("global", used.name),
+            )
+            .with_label(span.clone(), Message::new("label.spawned-here"))
+            .with_label(used.span.clone(), Message::new(used.label));
+            if changed.span != used.span {
+                error = error.with_label(changed.span.clone(), Message::new(changed.label));
+            }
+            Some(
+                error
+                    .with_note(Message::new("note.thread-globals"))
+                    .with_note(Message::new("note.share-atomics")),
+            )
+        })
+        .collect()
+}
+
+/// Every `spawn`, with the name of the function it calls, and every `join`, with its label.
+#[derive(Default)]
+struct Sites<'a>(Vec<(Option<&'a str>, Span, &'static str)>);
+
+impl<'a> Visitor<'a> for Sites<'a> {
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        match &expr.0 {
+            Expr::Spawn { name, .. } => {
+                self.0
+                    .push((Some(name.as_str()), expr.1.clone(), "label.spawned-here"))
+            }
+            Expr::Join(_) => self.0.push((None, expr.1.clone(), "label.joined-here")),
+            _ => (),
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// A use of a global, which changes it unless it only reads it.
+#[derive(Clone)]
+struct Use<'a> {
+    name: &'a str,
+    span: Span,
+    label: &'static str,
+    changes: bool,
+}
+
+/// The globals each function of a program uses and the functions it calls.
+struct GlobalUses<'a> {
+    funcs: HashMap<&'a str, Uses<'a>>,
+    /// Where the program first changes each global it changes
+    changed: HashMap<&'a str, Use<'a>>,
+}
+
+/// The globals a function uses, in order, and the functions it calls.
+#[derive(Default)]
+struct Uses<'a> {
+    uses: Vec<Use<'a>>,
+    calls: Vec<&'a str>,
+}
+
+impl<'a> GlobalUses<'a> {
+    fn of(defs: &'a [Definition]) -> Self {
+        // a constant is never changed, so it's the same on every thread
+        let globals = defs
+            .iter()
+            .filter_map(|def| match def {
+                Definition::Global(global) if !global.constant => Some(global.name.as_str()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let funcs = defs
+            .iter()
+            .filter_map(|def| match def {
+                Definition::Func(func) => {
+                    let mut collector = Collector {
+                        globals: &globals,
+                        vars: Vec::new(),
+                        uses: Uses::default(),
+                    };
+                    collector.visit_func(func);
+                    Some((func.name.as_str(), collector.uses))
+                }
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut changed = HashMap::new();
+        for def in defs {
+            if let Definition::Func(func) = def {
+                for used in funcs[func.name.as_str()]
+                    .uses
+                    .iter()
+                    .filter(|used| used.changes)
+                {
+                    changed.entry(used.name).or_insert_with(|| used.clone());
+                }
+            }
+        }
+        Self { funcs, changed }
+    }
+
+    /// The first use of a global the program changes by the function `name` or any function it
+    /// calls, with where the program changes it.
+    fn shared(&self, name: &'a str) -> Option<(&Use<'a>, &Use<'a>)> {
+        let mut seen = HashSet::from([name]);
+        let mut pending = vec![name];
+        while let Some(name) = pending.pop() {
+            let Some(uses) = self.funcs.get(name) else {
+                continue;
+            };
+            if let Some(shared) = uses
+                .uses
+                .iter()
+                .find_map(|used| Some((used, self.changed.get(used.name)?)))
+            {
+                return Some(shared);
+            }
+            for &callee in uses.calls.iter().rev() {
+                if seen.insert(callee) {
+                    pending.push(callee);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Collects the [`Uses`] of a function, telling its variables apart from the `globals` they
+/// shadow.
+struct Collector<'a, 'g> {
+    globals: &'g HashSet<&'a str>,
+    /// The parameters and the variables declared so far
+    vars: Vec<&'a str>,
+    uses: Uses<'a>,
+}
+
+impl<'a> Collector<'a, '_> {
+    fn record(&mut self, name: &'a str, span: &Span, label: &'static str, changes: bool) {
+        if self.globals.contains(name) && !self.vars.contains(&name) {
+            self.uses.uses.push(Use {
+                name,
+                span: span.clone(),
+                label,
+                changes,
+            });
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Collector<'a, '_> {
+    fn visit_param(&mut self, param: &'a Param) {
+        self.vars.push(&param.name);
+    }
+
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        match &statement.0 {
+            Statement::Reassign { name, .. } => {
+                self.record(name, &statement.1, "label.assigned-to", true)
+            }
+            Statement::Store { name, index, .. } => {
+                self.record(name, &index.1, "label.stored-into", true)
+            }
+            _ => (),
+        }
+        visit::walk_statement(self, statement);
+        if let Statement::Assign { name, .. } | Statement::Array { name, .. } = &statement.0 {
+            self.vars.push(name);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        match &expr.0 {
+            Expr::Var(name) => self.record(name, &expr.1, "label.used-here", false),
+            Expr::PreInc(name) | Expr::PreDec(name) => {
+                self.record(name, &expr.1, "label.assigned-to", true)
+            }
+            // the global could be changed through the pointer
+            Expr::AddrOf(name) => self.record(name, &expr.1, "label.pointer-taken", true),
+            Expr::Call { name, .. } => self.uses.calls.push(name),
+            _ => (),
+        }
+        visit::walk_expr(self, expr);
+    }
+}
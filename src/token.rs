@@ -1,61 +1,345 @@
 use chumsky::{
-    error::Simple,
-    primitive::{end, just, one_of, take_until},
+    error::{Error, Simple},
+    primitive::{any, choice, end, filter, just, one_of, take_until},
     recovery::skip_then_retry_until,
+    recursive::recursive,
     text::{self, TextParser},
     Parser,
 };
 use derive_more::Display;
 
+use crate::{
+    literal::{self, RADIX_PREFIXES},
+    Symbol,
+};
+
 pub type Span = std::ops::Range<usize>;
 
+/// Words that lex as keywords rather than identifiers.
+pub const KEYWORDS: &[(&str, Token)] = &[
+    ("return", Token::Return),
+    ("struct", Token::Struct),
+    ("import", Token::Import),
+    ("macro", Token::Macro),
+    ("switch", Token::Switch),
+    ("case", Token::Case),
+    ("default", Token::Default),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("const", Token::Const),
+];
+
+/// Words that start a block of hand-written code for a backend, whose text up to the matching
+/// `}` lexes as a single token.
+pub const INLINE_BLOCKS: &[&str] = &["__asm", "__ir"];
+
+/// Annotations that can come before a definition, which lex as tokens of their own.
+pub const ANNOTATIONS: &[(&str, Token)] = &[("@comptime", Token::Comptime)];
+
+/// Text that lexes as [`Token::Op`], longest first so that `<<` isn't lexed as two `<`.
+pub const OPERATORS: &[&str] = &[
+    "<<", ">>", "++", "--", "+=", "-=", "*=", "/=", "<=", ">=", "==", "!=", "+", "-", "*", "/",
+    "%", "&&", "||", "&", "|", "^", "!", "=", "<", ">", "?", ":",
+];
+
+/// The characters operators are made of, in the order they first appear in [`OPERATORS`].
+pub fn operator_chars() -> String {
+    let mut chars = String::new();
+    for c in OPERATORS.iter().flat_map(|op| op.chars()) {
+        if !chars.contains(c) {
+            chars.push(c);
+        }
+    }
+    chars
+}
+
+/// Characters that lex as [`Token::Ctrl`].
+pub const DELIMITERS: &str = "()[]{};,";
+
+/// Starts a comment that runs to the end of the line.
+pub const LINE_COMMENT: &str = "//";
+
+/// Open and close a comment that can span lines, and can contain block comments of its own.
+pub const BLOCK_COMMENT: (&str, &str) = ("/*", "*/");
+
+/// Labels the lexer gives the errors for literals and comments that reach the end of the file
+/// unclosed.
+pub const UNTERMINATED_STRING: &str = "string";
+pub const UNTERMINATED_RAW_STRING: &str = "raw-string";
+pub const UNTERMINATED_COMMENT: &str = "comment";
+
+/// Label the lexer gives the error for a C preprocessor directive like `#include`, whose message
+/// is the directive's name.
+pub const DIRECTIVE: &str = "directive";
+
 #[derive(Debug, Display, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Token {
     Return,
     Struct,
-    Op(char),
-    Ident(String),
+    Import,
+    Macro,
+    Switch,
+    Case,
+    Default,
+    Break,
+    Continue,
+    Const,
+    #[display(fmt = "@comptime")]
+    Comptime,
+    /// `__asm { ... }`, holding the text between the braces
+    #[display(fmt = "__asm")]
+    Asm(String),
+    /// `__ir { ... }`, holding the text between the braces
+    #[display(fmt = "__ir")]
+    Ir(String),
+    Op(&'static str),
+    Ident(Symbol),
     Ctrl(char),
+    /// An int literal as it's written, with its prefix and separators, like `0xFF_FF`
     Num(String),
+    Str(String),
+    /// A number with a decimal point, like `3.14`
+    Float(String),
+}
+
+/// Digits separated by any `_`s, starting with a digit `first` accepts.
+fn digits(
+    first: impl Fn(&char) -> bool + Clone,
+) -> impl Parser<char, String, Error = Simple<char>> + Clone {
+    filter(first)
+        .chain::<char, _, _>(filter(|c: &char| c.is_ascii_digit() || *c == '_').repeated())
+        .collect()
 }
 
+/// The tokens of a source file, and the comments between them.
+pub type Lexed = (Vec<(Token, Span)>, Vec<(String, Span)>);
+
 impl Token {
     pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
-        // A parser for numbers
-        let num = text::int(10)
-            .chain::<char, _, _>(just('.').chain(text::digits(10)).or_not().flatten())
+        Self::lexer_with_comments().map(|(tokens, _)| tokens)
+    }
+
+    /// Like [`Token::lexer`], but also produces the text and span of every comment, which the
+    /// parser never sees but the formatter has to keep.
+    pub fn lexer_with_comments() -> impl Parser<char, Lexed, Error = Simple<char>> {
+        // A parser for numbers, whose digits may be separated by `_`s. They're floats if they
+        // have a decimal point, and ints otherwise, which may be hex, binary or octal after a
+        // prefix. Every letter and digit after a prefix is part of the int, so that a digit
+        // outside of its base is reported by `literal::int` rather than starting another token
+        let prefixed = just('0')
+            .chain(one_of(
+                RADIX_PREFIXES
+                    .iter()
+                    .flat_map(|(prefix, _)| [*prefix, prefix.to_ascii_uppercase()])
+                    .collect::<String>(),
+            ))
+            .chain::<char, _, _>(
+                filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_').repeated(),
+            )
             .collect::<String>()
             .map(Token::Num);
+        // like C, a decimal int doesn't start with a 0 unless it's 0
+        let decimal = just('0')
+            .map(String::from)
+            .or(digits(|c| c.is_ascii_digit() && *c != '0'));
+        let num = prefixed.or(decimal
+            .then(just('.').ignore_then(digits(char::is_ascii_digit)).or_not())
+            .map(|(int, fraction)| match fraction {
+                Some(fraction) => Token::Float(format!("{int}.{fraction}")),
+                None => Token::Num(int),
+            }));
+
+        // A parser for string literals, escapes are resolved by `literal::unescape` so that a bad
+        // escape is reported at its own span rather than failing the whole literal. A literal
+        // that's never closed runs to the end of the file, and is reported from its opening quote
+        let string = just('"')
+            .ignore_then(
+                filter(|c| *c != '\\' && *c != '"')
+                    .map(|c| vec![c])
+                    .or(just('\\').chain(any().or_not()))
+                    .repeated()
+                    .flatten()
+                    .collect::<String>(),
+            )
+            .then(just('"').or_not())
+            .validate(|(raw, close), span: Span, emit| {
+                if close.is_none() {
+                    emit(unterminated(
+                        UNTERMINATED_STRING,
+                        span.start..span.start + 1,
+                        span.end,
+                    ));
+                    return raw;
+                }
+                literal::unescape(&raw, span.start + 1).unwrap_or_else(|error| {
+                    emit(Simple::custom(error.span, error.message));
+                    raw
+                })
+            })
+            .map(Token::Str);
+
+        // A parser for raw strings, which contain no escapes and may be delimited by `r#"` and
+        // `"#` (with any number of `#`) so that they can contain quotes. The span of an unclosed
+        // one's opening is kept and only reported once the literal has been chosen over an
+        // identifier `r`, which would otherwise win for having no errors
+        let raw_string = just('r')
+            .ignore_then(just('#').repeated().map(|hashes| hashes.len()))
+            .then_ignore(just('"'))
+            .then_with(|hashes| {
+                let close = just('"').then(just('#').repeated().exactly(hashes));
+                take_until(close.to(true).or(end().to(false))).map(move |(chars, closed)| {
+                    (hashes, chars.into_iter().collect::<String>(), closed)
+                })
+            })
+            .map_with_span(|(hashes, raw, closed), span: Span| {
+                let open = (!closed).then(|| span.start..span.start + hashes + 2);
+                (Token::Str(raw), open)
+            });
 
         // A parser for operators
-        let op = one_of("+-*/!=").map(|c| Token::Op(c));
+        let op = choice(
+            OPERATORS
+                .iter()
+                .map(|op| just(*op).to(Token::Op(op)))
+                .collect::<Vec<_>>(),
+        );
+
+        // A parser for annotations, where anything but a known one is reported as a stray `@`
+        let annotation = just('@')
+            .chain::<char, _, _>(
+                filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_').repeated(),
+            )
+            .collect::<String>()
+            .try_map(|text, span: Span| {
+                ANNOTATIONS
+                    .iter()
+                    .find(|(annotation, _)| *annotation == text)
+                    .map(|(_, token)| token.clone())
+                    .ok_or_else(|| {
+                        Simple::expected_input_found(span.start..span.start + 1, [], Some('@'))
+                    })
+            });
+
+        // A parser for inline blocks, whose text may hold balanced braces of its own
+        let braced = recursive(|braced| {
+            filter(|c: &char| *c != '{' && *c != '}')
+                .map(String::from)
+                .or(braced.map(|inner| format!("{{{inner}}}")))
+                .repeated()
+                .collect::<String>()
+                .delimited_by(just('{'), just('}'))
+        });
+        let inline = just(INLINE_BLOCKS[0])
+            .to(Token::Asm as fn(_) -> _)
+            .or(just(INLINE_BLOCKS[1]).to(Token::Ir as fn(_) -> _))
+            .then_ignore(text::whitespace())
+            .then(braced)
+            .map(|(token, code)| token(code));
 
         // A parser for control characters (delimiters, semicolons, etc.)
-        let ctrl = one_of("()[]{};,").map(|c| Token::Ctrl(c));
+        let ctrl = one_of(DELIMITERS).map(Token::Ctrl);
 
         // parser for identifiers
-        let ident = text::ident().map(|ident: String| match ident.as_str() {
-            "return" => Token::Return,
-            "struct" => Token::Struct,
-            _ => Token::Ident(ident),
+        let ident = text::ident().map(|ident: String| {
+            KEYWORDS
+                .iter()
+                .find(|(keyword, _)| *keyword == ident)
+                .map_or(Token::Ident(Symbol::intern(&ident)), |(_, token)| {
+                    token.clone()
+                })
         });
 
         // combine parsers into single token parser
         let token = num
-            .or(op)
-            .or(ctrl)
-            .or(ident)
+            .or(string)
+            .map(|token| (token, None))
+            .or(raw_string)
+            .or(annotation
+                .or(inline)
+                .or(op)
+                .or(ctrl)
+                .or(ident)
+                .map(|token| (token, None)))
+            .validate(|(token, unclosed_raw_string), span: Span, emit| {
+                if let Some(open) = unclosed_raw_string {
+                    emit(unterminated(UNTERMINATED_RAW_STRING, open, span.end));
+                }
+                token
+            })
             .recover_with(skip_then_retry_until([]));
 
-        // create a parser for comments
-        let comment = just("//").then(take_until(just('\n'))).padded();
+        // create a parser for comments, which run to the end of the line or the file
+        let line_comment = just(LINE_COMMENT)
+            .ignore_then(take_until(just('\n').ignored().or(end())))
+            .map_with_span(|(text, _), span: Span| {
+                let text = LINE_COMMENT.to_string() + &text.into_iter().collect::<String>();
+                let span = span.start..span.start + text.chars().count();
+                (text, span)
+            });
+
+        // and for block comments, which nest so that code holding one can be commented out. One
+        // that's never closed runs to the end of the file, and is reported from its opening
+        let (open, close) = BLOCK_COMMENT;
+        let block_comment = recursive(|nested| {
+            just(open)
+                .ignore_then(
+                    nested
+                        .map(|(text, _)| text)
+                        .or(just(close).not().map(String::from))
+                        .repeated()
+                        .collect::<String>(),
+                )
+                .then(just(close).or_not())
+                .map(move |(text, closed)| {
+                    (
+                        format!("{open}{text}{}", closed.unwrap_or("")),
+                        closed.is_some(),
+                    )
+                })
+        })
+        .validate(move |(text, closed), span: Span, emit| {
+            if !closed {
+                let opening = span.start..span.start + open.len();
+                emit(unterminated(UNTERMINATED_COMMENT, opening, span.end));
+            }
+            (text, span)
+        });
+
+        // C preprocessor directives, which there's no preprocessor for, are reported and then
+        // skipped to the end of the line like a comment so that the code after them is still lexed
+        let directive = just('#')
+            .ignore_then(text::ident())
+            .then_ignore(take_until(just('\n').ignored().or(end())))
+            .validate(|name: String, span: Span, emit| {
+                let directive = span.start..span.start + 1 + name.chars().count();
+                emit(Simple::custom(directive, name).with_label(DIRECTIVE));
+                (String::new(), span)
+            });
+
+        let comment = line_comment.or(block_comment).or(directive).padded();
 
         // combine all parsers with span and allow for comments
-        token
-            .map_with_span(|token, span| (token, span))
-            .padded_by(comment.repeated())
-            .padded()
+        comment
+            .clone()
+            .repeated()
+            .then(token.map_with_span(|token, span| (token, span)).padded())
+            .then(comment.repeated())
             .repeated()
             .then_ignore(end())
+            .map(|lexed| {
+                let (mut tokens, mut comments) = (Vec::new(), Vec::new());
+                for ((before, token), after) in lexed {
+                    comments.extend(before);
+                    tokens.push(token);
+                    comments.extend(after);
+                }
+                (tokens, comments)
+            })
     }
 }
+
+/// The error for a literal or comment that's still open at `end`, the end of the file, labelled
+/// `kind`.
+fn unterminated(kind: &'static str, open: Span, end: usize) -> Simple<char> {
+    Simple::unclosed_delimiter(open, '"', end..end, '"', None).with_label(kind)
+}
@@ -0,0 +1,209 @@
+//! The interpreter hooks behind `run --trace`, which prints every statement as it runs with the
+//! values of the variables it reads, and `run --profile`, which counts the calls to each
+//! function and the time spent in them and prints a table of them when the program finishes.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ast::{AssignOp, Expr, Hook, Spanned, State, Statement},
+    debug::show,
+    diagnostics::Diagnostic,
+    sources::SourceMap,
+    visit::{self, Visitor},
+};
+
+/// Prints each statement before it runs.
+pub struct Tracer<W> {
+    sources: Arc<Mutex<SourceMap>>,
+    output: W,
+}
+
+impl<W: Write> Tracer<W> {
+    /// A tracer for a program compiled from `sources`, which may be empty for a program read
+    /// from IR and which the debugger adds the functions edited while it runs to, writing to
+    /// `output`.
+    pub fn new(sources: Arc<Mutex<SourceMap>>, output: W) -> Self {
+        Self { sources, output }
+    }
+}
+
+impl<W: Write + Send> Hook for Tracer<W> {
+    fn before_statement(
+        &mut self,
+        statement: &Spanned<Statement>,
+        state: State<'_>,
+    ) -> Result<(), Diagnostic> {
+        // IR has no source to quote, so its statements are written in prefix form
+        let sources = self.sources.lock().unwrap();
+        let (location, text) = match sources.locate(&statement.1) {
+            Some((file, span)) => {
+                let chars = file.source.chars().collect::<Vec<_>>();
+                let line = chars[..span.start].iter().filter(|c| **c == '\n').count() + 1;
+                let text = chars[span].iter().collect::<String>();
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                (format!("{}:{line}", file.name), text)
+            }
+            None => (String::from("<ir>"), statement.0.to_string()),
+        };
+
+        let mut reads = Reads::default();
+        reads.visit_statement(statement);
+        let values = reads
+            .0
+            .iter()
+            .filter_map(|name| {
+                let (_, value) = state.vars.iter().rev().find(|(var, _)| var == name)?;
+                Some(format!("{name} = {}", show(value)))
+            })
+            .collect::<Vec<_>>();
+
+        let indent = "  ".repeat(state.calls.len());
+        let mut line = format!("{location} {indent}{}: {text}", state.func);
+        if !values.is_empty() {
+            write!(line, "  [{}]", values.join(", ")).unwrap();
+        }
+        // tracing is best effort, and a closed output shouldn't stop the program
+        let _ = writeln!(self.output, "{line}");
+        Ok(())
+    }
+}
+
+/// The variables a statement reads, each once, in the order they're first read.
+#[derive(Default)]
+struct Reads<'a>(Vec<&'a str>);
+
+impl<'a> Reads<'a> {
+    fn read(&mut self, name: &'a str) {
+        if !self.0.contains(&name) {
+            self.0.push(name);
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Reads<'a> {
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        // compound assignments read the variable they assign
+        if let Statement::Reassign { name, op, .. } = &statement.0 {
+            if *op != AssignOp::Set {
+                self.read(name);
+            }
+        }
+        visit::walk_statement(self, statement);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        if let Expr::Var(name) | Expr::PreInc(name) | Expr::PreDec(name) = &expr.0 {
+            self.read(name);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// The calls to a function and the time spent in them.
+#[derive(Debug, Default, Clone, Copy)]
+struct Calls {
+    count: usize,
+    /// Time from the start of each call to its return, counting a recursive call only once
+    total: Duration,
+    /// Time spent running the function's own statements, leaving out its calls
+    own: Duration,
+}
+
+/// Counts the calls to each function and times them, printing a table when the program
+/// finishes.
+pub struct Profiler<W> {
+    output: W,
+    funcs: HashMap<String, Calls>,
+    /// The calls in progress, innermost last, with when each started and the time spent in the
+    /// calls it made
+    stack: Vec<(String, Instant, Duration)>,
+}
+
+impl<W: Write> Profiler<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            funcs: HashMap::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// The table of calls, the function with the most time of its own first.
+    fn table(&self) -> String {
+        let mut funcs = self.funcs.iter().collect::<Vec<_>>();
+        funcs.sort_by(|(a, a_calls), (b, b_calls)| b_calls.own.cmp(&a_calls.own).then(a.cmp(b)));
+
+        let ms = |duration: Duration| format!("{:.3}", duration.as_secs_f64() * 1000.0);
+        let rows = funcs
+            .into_iter()
+            .map(|(name, calls)| {
+                [
+                    name.clone(),
+                    calls.count.to_string(),
+                    ms(calls.total),
+                    ms(calls.own),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let header = ["FUNCTION", "CALLS", "TOTAL MS", "SELF MS"].map(String::from);
+
+        let widths = (0..4)
+            .map(|column| {
+                rows.iter()
+                    .chain([&header])
+                    .map(|row| row[column].len())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        let mut table = String::new();
+        for row in [&header].into_iter().chain(&rows) {
+            // the name is aligned left and the numbers right
+            write!(table, "{:<width$}", row[0], width = widths[0]).unwrap();
+            for (cell, width) in row[1..].iter().zip(&widths[1..]) {
+                write!(table, "  {cell:>width$}").unwrap();
+            }
+            table.push('\n');
+        }
+        table
+    }
+}
+
+impl<W: Write + Send> Hook for Profiler<W> {
+    fn enter(&mut self, name: &str) {
+        self.funcs.entry(name.to_string()).or_default().count += 1;
+        self.stack
+            .push((name.to_string(), Instant::now(), Duration::ZERO));
+    }
+
+    fn exit(&mut self, _name: &str) {
+        let Some((name, start, inner)) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        let recursive = self.stack.iter().any(|(caller, ..)| *caller == name);
+        let calls = self.funcs.entry(name).or_default();
+        calls.own += elapsed.saturating_sub(inner);
+        if !recursive {
+            calls.total += elapsed;
+        }
+        if let Some((.., callee_time)) = self.stack.last_mut() {
+            *callee_time += elapsed;
+        }
+    }
+
+    fn finish(&mut self) {
+        // calls an error propagated out of end here
+        while !self.stack.is_empty() {
+            self.exit("");
+        }
+        let table = self.table();
+        let _ = write!(self.output, "{table}");
+    }
+}
@@ -0,0 +1,909 @@
+//! The typed IR, which IR files hold from format version 2 on in the layout of
+//! [`ir::schema`](crate::ir::schema): the program after semantic analysis, with every name
+//! resolved to what it refers to.
+//!
+//! Where the AST has names, the typed IR has indices: a call holds the index of the function it
+//! calls in [`Program::funcs`], a variable is the index of its slot, and a type is a [`Type`]
+//! instead of the name it was written with. The first slots are those of [`Program::globals`],
+//! which every function can use, and the slots of [`Func::slots`] are numbered after them. A backend reading an IR file doesn't have
+//! to resolve names and scopes again, and can't resolve them differently than the compiler did,
+//! which [`check`] makes sure of for files the compiler may not have written.
+//!
+//! [`resolve`] builds the typed IR from a checked AST, which is also how IR files of format
+//! version 1, which hold the AST itself, are still read. [`Program::to_ast`] turns it back into an
+//! AST for the interpreter and the VM.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{self, AssignOp, Definition, InlineLang, LogicalOp, Param, Spanned, Statement},
+    ir::BinOp,
+    token::Span,
+    types::{self, TypeTable},
+    Ast, Builtins, Symbol,
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub structs: Vec<Struct>,
+    pub funcs: Vec<Func>,
+    pub globals: Vec<Global>,
+}
+
+/// A global variable, or a constant if `constant`, set before `main` is called.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub var: Var,
+    pub constant: bool,
+    /// The value it starts with, which can only use the globals before it, or `None` for zero
+    pub expr: Option<Spanned<Expr>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+    pub name: Symbol,
+    pub fields: Vec<Var>,
+    pub span: Span,
+}
+
+/// A parameter, variable or struct field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Var {
+    pub name: Symbol,
+    pub ty: Type,
+    /// Span of its declaration
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    pub name: Symbol,
+    pub ret: Type,
+    /// How many of the first slots are the function's parameters
+    pub params: u32,
+    /// The parameters and then the variables of the function, in the order they're declared,
+    /// whose slots come after those of the globals
+    pub slots: Vec<Var>,
+    pub body: Vec<Spanned<Stmt>>,
+    /// Span of the function's name
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    /// Every type other than `string`, `void`, the floats and the structs, like `int`, `char` or
+    /// `long`, all of which hold ints
+    Int,
+    Str,
+    /// What a function that returns nothing returns
+    Void,
+    /// The struct at this index of [`Program::structs`]
+    Struct(u32),
+    Pointer(Box<Type>),
+    /// An array of `len` elements, which only a variable declared by [`Stmt::Array`] has
+    Array {
+        elem: Box<Type>,
+        len: u32,
+    },
+    /// `float` and `double`, both of which hold a double
+    Float,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Return(Spanned<Expr>),
+    /// `return;`, leaving a void function
+    ReturnVoid,
+    /// An expression evaluated for its side effects, discarding its value
+    Expr(Spanned<Expr>),
+    /// Declares the variable in `slot`, whose value is `expr`
+    Let {
+        slot: u32,
+        expr: Spanned<Expr>,
+    },
+    /// Declares the array in `slot`, whose elements are all zero or empty
+    Array {
+        slot: u32,
+    },
+    Store {
+        slot: u32,
+        index: Spanned<Expr>,
+        expr: Spanned<Expr>,
+    },
+    Reassign {
+        slot: u32,
+        op: AssignOp,
+        expr: Spanned<Expr>,
+    },
+    /// `*pointer = expr;`
+    Write {
+        pointer: Spanned<Expr>,
+        expr: Spanned<Expr>,
+    },
+    /// Hand-written code for the backend for `lang`, whose variables are still named
+    Inline {
+        lang: InlineLang,
+        code: String,
+    },
+    /// Runs the statements from the case whose value `expr` equals, or else from `default`, up
+    /// to a [`Stmt::Break`]. The cases can't declare variables
+    Switch {
+        expr: Spanned<Expr>,
+        cases: Vec<Case>,
+    },
+    /// Leaves the switch it's in
+    Break,
+    /// Goes on to the next iteration of the loop it's in, though there are no loops yet
+    Continue,
+}
+
+/// A `case` or `default` of a [`Stmt::Switch`], and the statements after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    /// The value the case matches, or `None` for `default`
+    pub value: Option<i64>,
+    /// Span of the label
+    pub span: Span,
+    pub body: Vec<Spanned<Stmt>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Int(u32),
+    Str(String),
+    /// The variable in this slot
+    Var(u32),
+    Neg(Box<Spanned<Expr>>),
+    Not(Box<Spanned<Expr>>),
+    Binary(BinOp, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Index(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Call {
+        callee: Callee,
+        args: Vec<Spanned<Expr>>,
+    },
+    PreInc(u32),
+    PreDec(u32),
+    Cond(Box<Spanned<Expr>>, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    AddrOf(u32),
+    Deref(Box<Spanned<Expr>>),
+    Spawn {
+        callee: Callee,
+        args: Vec<Spanned<Expr>>,
+    },
+    Join(Box<Spanned<Expr>>),
+    /// `&&` or `||`, which evaluates its right side only if its left side doesn't decide it
+    Logical(LogicalOp, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    Float(f64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callee {
+    /// The function at this index of [`Program::funcs`]
+    Func(u32),
+    /// A builtin, which is looked up by name when it's called
+    Extern(String),
+}
+
+/// Resolves every name in `ast`, which has passed semantic analysis, with `builtins` taking the
+/// place of the functions they share a name with as they do when the program runs.
+pub fn resolve(ast: &Ast, builtins: &Builtins) -> Result<Program, String> {
+    let mut resolver = Resolver {
+        structs: HashMap::new(),
+        funcs: HashMap::new(),
+        globals: Vec::new(),
+        builtins,
+        types: TypeTable::new(ast).map_err(|errors| errors[0].message.to_string())?,
+    };
+    // a function defined twice fails semantic analysis, so which definition its calls refer to
+    // doesn't matter
+    let (mut structs, mut funcs) = (0, 0);
+    for def in &ast.defs {
+        match def {
+            Definition::Struct { name, .. } => {
+                resolver.structs.entry(name).or_insert(structs);
+                structs += 1;
+            }
+            Definition::Func(func) => {
+                resolver.funcs.entry(&func.name).or_insert(funcs);
+                funcs += 1;
+            }
+            Definition::Import { .. }
+            | Definition::Macro(_)
+            | Definition::Prototype(_)
+            | Definition::Global(_) => (),
+        }
+    }
+
+    let mut program = Program::default();
+    // every function can use every global, wherever it's defined
+    for def in &ast.defs {
+        if let Definition::Global(global) = def {
+            let expr = global
+                .expr
+                .as_ref()
+                .map(|expr| resolver.expr(expr, &resolver.globals))
+                .transpose()
+                .map_err(|e| format!("in {}: {e}", global.name))?;
+            let var = Var {
+                name: global.name,
+                ty: resolver.ty(&global.ty),
+                span: global.span.clone(),
+            };
+            resolver.globals.push(var.clone());
+            program.globals.push(Global {
+                var,
+                constant: global.constant,
+                expr,
+            });
+        }
+    }
+    for def in &ast.defs {
+        match def {
+            Definition::Struct { name, params, span } => program.structs.push(Struct {
+                name: *name,
+                fields: params.iter().map(|param| resolver.var(param)).collect(),
+                span: span.clone(),
+            }),
+            Definition::Func(func) => program.funcs.push(resolver.func(func)?),
+            Definition::Import { .. }
+            | Definition::Macro(_)
+            | Definition::Prototype(_)
+            | Definition::Global(_) => (),
+        }
+    }
+    Ok(program)
+}
+
+struct Resolver<'a> {
+    structs: HashMap<&'a str, u32>,
+    funcs: HashMap<&'a str, u32>,
+    /// The globals, whose slots come before those of every function
+    globals: Vec<Var>,
+    builtins: &'a Builtins,
+    /// The layout of every struct, which `sizeof` is folded with
+    types: TypeTable,
+}
+
+impl Resolver<'_> {
+    fn ty(&self, name: &str) -> Type {
+        if let Some(pointee) = name.strip_suffix('*') {
+            return Type::Pointer(Box::new(self.ty(pointee)));
+        }
+        match name {
+            "string" => Type::Str,
+            ast::VOID => Type::Void,
+            _ if types::is_float(name) => Type::Float,
+            _ => self
+                .structs
+                .get(name)
+                .map_or(Type::Int, |&i| Type::Struct(i)),
+        }
+    }
+
+    fn var(&self, param: &Param) -> Var {
+        Var {
+            name: param.name,
+            ty: self.ty(&param.ty),
+            span: param.span.clone(),
+        }
+    }
+
+    fn func(&self, func: &ast::Func) -> Result<Func, String> {
+        let mut slots = self.globals.clone();
+        slots.extend(func.params.iter().map(|param| self.var(param)));
+        let body = func
+            .body
+            .iter()
+            .map(|(statement, span)| {
+                Ok((self.statement(statement, span, &mut slots)?, span.clone()))
+            })
+            .collect::<Result<_, String>>()
+            .map_err(|e| format!("in {}: {e}", func.name))?;
+        Ok(Func {
+            name: func.name,
+            ret: self.ty(&func.ret),
+            params: func.params.len() as u32,
+            slots: slots.split_off(self.globals.len()),
+            body,
+            span: func.span.clone(),
+        })
+    }
+
+    fn statement(
+        &self,
+        statement: &Statement,
+        span: &Span,
+        slots: &mut Vec<Var>,
+    ) -> Result<Stmt, String> {
+        let declare = |slots: &mut Vec<Var>, name: &str, ty| {
+            slots.push(Var {
+                name: Symbol::intern(name),
+                ty,
+                span: span.clone(),
+            });
+            slots.len() as u32 - 1
+        };
+
+        Ok(match statement {
+            Statement::Invalid => return Err(String::from("an invalid statement is left")),
+            Statement::Expand { name, .. } => {
+                return Err(format!("the macro {name}! isn't expanded"))
+            }
+            Statement::Return(expr) => Stmt::Return(self.expr(expr, slots)?),
+            Statement::ReturnVoid => Stmt::ReturnVoid,
+            Statement::Expr(expr) => Stmt::Expr(self.expr(expr, slots)?),
+            // the value is worked out before the variable is declared, so it can't refer to it
+            Statement::Assign { ty, name, expr } => {
+                let expr = self.expr(expr, slots)?;
+                let slot = declare(slots, name, self.ty(ty));
+                Stmt::Let { slot, expr }
+            }
+            Statement::Array { ty, name, len } => {
+                let elem = Box::new(self.ty(ty));
+                let slot = declare(slots, name, Type::Array { elem, len: *len });
+                Stmt::Array { slot }
+            }
+            Statement::Store { name, index, expr } => Stmt::Store {
+                slot: slot(slots, name)?,
+                index: self.expr(index, slots)?,
+                expr: self.expr(expr, slots)?,
+            },
+            Statement::Reassign { name, op, expr } => Stmt::Reassign {
+                slot: slot(slots, name)?,
+                op: *op,
+                expr: self.expr(expr, slots)?,
+            },
+            Statement::Write { pointer, expr } => Stmt::Write {
+                pointer: self.expr(pointer, slots)?,
+                expr: self.expr(expr, slots)?,
+            },
+            Statement::Inline { lang, code } => Stmt::Inline {
+                lang: *lang,
+                code: code.clone(),
+            },
+            Statement::Switch { expr, cases } => Stmt::Switch {
+                expr: self.expr(expr, slots)?,
+                cases: cases
+                    .iter()
+                    .map(|case| {
+                        let body = case
+                            .body
+                            .iter()
+                            .map(|(statement, span)| {
+                                if let Statement::Assign { name, .. }
+                                | Statement::Array { name, .. } = statement
+                                {
+                                    return Err(format!("{name} is declared in a switch"));
+                                }
+                                Ok((self.statement(statement, span, slots)?, span.clone()))
+                            })
+                            .collect::<Result<_, String>>()?;
+                        Ok(Case {
+                            value: case.value,
+                            span: case.span.clone(),
+                            body,
+                        })
+                    })
+                    .collect::<Result<_, String>>()?,
+            },
+            Statement::Break => Stmt::Break,
+            Statement::Continue => Stmt::Continue,
+        })
+    }
+
+    fn call(
+        &self,
+        name: &str,
+        params: &[Spanned<ast::Expr>],
+        slots: &[Var],
+    ) -> Result<(Callee, Vec<Spanned<Expr>>), String> {
+        let callee = match self.funcs.get(name) {
+            Some(&index) if !self.builtins.contains(name) => Callee::Func(index),
+            _ => Callee::Extern(name.to_string()),
+        };
+        let args = params
+            .iter()
+            .map(|param| self.expr(param, slots))
+            .collect::<Result<_, _>>()?;
+        Ok((callee, args))
+    }
+
+    fn expr(
+        &self,
+        (expr, span): &Spanned<ast::Expr>,
+        slots: &[Var],
+    ) -> Result<Spanned<Expr>, String> {
+        use ast::Expr as E;
+
+        let boxed = |expr| self.expr(expr, slots).map(Box::new);
+        let binary =
+            |op, lhs, rhs| -> Result<_, String> { Ok(Expr::Binary(op, boxed(lhs)?, boxed(rhs)?)) };
+        let expr = match expr {
+            E::Err => return Err(String::from("an invalid expression is left")),
+            E::Int(value) => Expr::Int(*value),
+            E::Str(value) => Expr::Str(value.clone()),
+            E::Float(value) => Expr::Float(*value),
+            E::Var(name) => Expr::Var(slot(slots, name)?),
+            E::PreInc(name) => Expr::PreInc(slot(slots, name)?),
+            E::PreDec(name) => Expr::PreDec(slot(slots, name)?),
+            E::AddrOf(name) => Expr::AddrOf(slot(slots, name)?),
+            E::Neg(inner) => Expr::Neg(boxed(inner)?),
+            E::Not(inner) => Expr::Not(boxed(inner)?),
+            E::Deref(inner) => Expr::Deref(boxed(inner)?),
+            E::SizeOf(ty) => Expr::Int(
+                self.types
+                    .size_of(ty)
+                    .map_err(|error| error.message.to_string())?,
+            ),
+            E::Mul(lhs, rhs) => binary(BinOp::Mul, lhs, rhs)?,
+            E::Div(lhs, rhs) => binary(BinOp::Div, lhs, rhs)?,
+            E::Add(lhs, rhs) => binary(BinOp::Add, lhs, rhs)?,
+            E::Sub(lhs, rhs) => binary(BinOp::Sub, lhs, rhs)?,
+            E::Rem(lhs, rhs) => binary(BinOp::Rem, lhs, rhs)?,
+            E::BitAnd(lhs, rhs) => binary(BinOp::And, lhs, rhs)?,
+            E::BitOr(lhs, rhs) => binary(BinOp::Or, lhs, rhs)?,
+            E::BitXor(lhs, rhs) => binary(BinOp::Xor, lhs, rhs)?,
+            E::Shl(lhs, rhs) => binary(BinOp::Shl, lhs, rhs)?,
+            E::Shr(lhs, rhs) => binary(BinOp::Shr, lhs, rhs)?,
+            E::Lt(lhs, rhs) => binary(BinOp::Lt, lhs, rhs)?,
+            E::Le(lhs, rhs) => binary(BinOp::Le, lhs, rhs)?,
+            E::Gt(lhs, rhs) => binary(BinOp::Gt, lhs, rhs)?,
+            E::Ge(lhs, rhs) => binary(BinOp::Ge, lhs, rhs)?,
+            E::Eq(lhs, rhs) => binary(BinOp::Eq, lhs, rhs)?,
+            E::Ne(lhs, rhs) => binary(BinOp::Ne, lhs, rhs)?,
+            E::And(lhs, rhs) => Expr::Logical(LogicalOp::And, boxed(lhs)?, boxed(rhs)?),
+            E::Or(lhs, rhs) => Expr::Logical(LogicalOp::Or, boxed(lhs)?, boxed(rhs)?),
+            E::Index(array, index) => Expr::Index(boxed(array)?, boxed(index)?),
+            E::Cond(cond, then, otherwise) => {
+                Expr::Cond(boxed(cond)?, boxed(then)?, boxed(otherwise)?)
+            }
+            E::Call { name, params } => {
+                let (callee, args) = self.call(name, params, slots)?;
+                Expr::Call { callee, args }
+            }
+            E::Spawn { name, params } => {
+                let (callee, args) = self.call(name, params, slots)?;
+                Expr::Spawn { callee, args }
+            }
+            E::Join(handle) => Expr::Join(boxed(handle)?),
+        };
+        Ok((expr, span.clone()))
+    }
+}
+
+/// The slot of the variable `name` where `slots` are declared, which is the last one with that
+/// name.
+fn slot(slots: &[Var], name: &str) -> Result<u32, String> {
+    match slots.iter().rposition(|var| var.name == name) {
+        Some(slot) => Ok(slot as u32),
+        None => Err(format!("{name} isn't declared")),
+    }
+}
+
+/// Checks that every index in `program` is in bounds, and that each variable is the one its name
+/// refers to where it's used, so that [`Program::to_ast`] gives back the program it came from.
+pub fn check(program: &Program) -> Result<(), String> {
+    for r#struct in &program.structs {
+        for field in &r#struct.fields {
+            check_ty(program, &field.ty)
+                .map_err(|e| format!("in struct {}: {e}", r#struct.name))?;
+        }
+    }
+
+    for (i, global) in program.globals.iter().enumerate() {
+        let checker = Checker {
+            program,
+            globals: i,
+            locals: &[],
+        };
+        check_ty(program, &global.var.ty)
+            .and_then(|()| match &global.expr {
+                Some(expr) => checker.expr(expr, i),
+                None => Ok(()),
+            })
+            .map_err(|e| format!("in {}: {e}", global.var.name))?;
+    }
+
+    for func in &program.funcs {
+        let checker = Checker {
+            program,
+            globals: program.globals.len(),
+            locals: &func.slots,
+        };
+        checker
+            .func(func)
+            .map_err(|e| format!("in {}: {e}", func.name))?;
+    }
+    Ok(())
+}
+
+/// Checks that `ty` is a type a value can have, whose structs exist.
+fn check_ty(program: &Program, ty: &Type) -> Result<(), String> {
+    match ty {
+        Type::Int | Type::Str | Type::Void | Type::Float => Ok(()),
+        Type::Struct(index) if *index as usize >= program.structs.len() => {
+            Err(format!("struct {index} doesn't exist"))
+        }
+        Type::Struct(_) => Ok(()),
+        Type::Pointer(pointee) => check_ty(program, pointee),
+        Type::Array { .. } => Err(String::from("only a declared array can have an array type")),
+    }
+}
+
+struct Checker<'a> {
+    program: &'a Program,
+    /// How many of the first globals can be used, whose slots come first
+    globals: usize,
+    /// The slots of the function being checked, numbered after the globals
+    locals: &'a [Var],
+}
+
+impl Checker<'_> {
+    fn func(&self, func: &Func) -> Result<(), String> {
+        let params = func.params as usize;
+        if params > self.locals.len() {
+            return Err(format!("{params} parameters don't fit in its slots"));
+        }
+        check_ty(self.program, &func.ret)?;
+        for param in &self.locals[..params] {
+            check_ty(self.program, &param.ty)?;
+        }
+
+        let mut declared = self.globals + params;
+        self.statements(&func.body, &mut declared, false)?;
+
+        match declared == self.globals + self.locals.len() {
+            true => Ok(()),
+            false => Err(format!(
+                "it declares {} of its {} slots",
+                declared - self.globals,
+                self.locals.len()
+            )),
+        }
+    }
+
+    /// The variable in `slot`, which is one of the first `declared`, if there is one.
+    fn slot(&self, slot: u32, declared: usize) -> Option<&Var> {
+        let slot = slot as usize;
+        if slot >= declared {
+            return None;
+        }
+        match slot.checked_sub(self.globals) {
+            None => Some(&self.program.globals[slot].var),
+            Some(local) => self.locals.get(local),
+        }
+    }
+
+    /// Checks `statements`, which are the cases of a switch if `in_switch`, after the first
+    /// `declared` slots have been declared, counting those they declare.
+    fn statements(
+        &self,
+        statements: &[Spanned<Stmt>],
+        declared: &mut usize,
+        in_switch: bool,
+    ) -> Result<(), String> {
+        for (statement, _) in statements {
+            match statement {
+                Stmt::Let { .. } | Stmt::Array { .. } if in_switch => {
+                    return Err(String::from("a variable is declared in a switch"))
+                }
+                Stmt::Return(expr) | Stmt::Expr(expr) => self.expr(expr, *declared)?,
+                Stmt::ReturnVoid | Stmt::Inline { .. } => (),
+                Stmt::Let { slot, expr } => {
+                    self.expr(expr, *declared)?;
+                    let var = self.declare(*slot, *declared)?;
+                    check_ty(self.program, &var.ty)?;
+                    *declared += 1;
+                }
+                Stmt::Array { slot } => {
+                    let var = self.declare(*slot, *declared)?;
+                    let Type::Array { elem, .. } = &var.ty else {
+                        return Err(format!("the array in slot {slot} has no array type"));
+                    };
+                    check_ty(self.program, elem)?;
+                    *declared += 1;
+                }
+                Stmt::Store { slot, index, expr } => {
+                    self.var(*slot, *declared)?;
+                    self.expr(index, *declared)?;
+                    self.expr(expr, *declared)?;
+                }
+                Stmt::Reassign { slot, expr, .. } => {
+                    self.var(*slot, *declared)?;
+                    self.expr(expr, *declared)?;
+                }
+                Stmt::Write { pointer, expr } => {
+                    self.expr(pointer, *declared)?;
+                    self.expr(expr, *declared)?;
+                }
+                Stmt::Switch { expr, cases } => {
+                    self.expr(expr, *declared)?;
+                    for case in cases {
+                        self.statements(&case.body, declared, true)?;
+                    }
+                }
+                Stmt::Break if !in_switch => {
+                    return Err(String::from("a break is outside of a switch"))
+                }
+                Stmt::Break => (),
+                Stmt::Continue => return Err(String::from("a continue is outside of a loop")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `slot` is the next to be declared, after the first `declared`, returning its
+    /// variable.
+    fn declare(&self, slot: u32, declared: usize) -> Result<&Var, String> {
+        match self.slot(slot, declared + 1) {
+            Some(var) if slot as usize == declared && declared >= self.globals => Ok(var),
+            _ => Err(format!("slot {slot} is declared out of order")),
+        }
+    }
+
+    /// Checks that `slot` is declared, and is the variable its name refers to after the first
+    /// `declared` slots are, a local one shadowing a global.
+    fn var(&self, slot: u32, declared: usize) -> Result<(), String> {
+        let Some(var) = self.slot(slot, declared) else {
+            return Err(format!("slot {slot} is used before it's declared"));
+        };
+        let locals = &self.locals[..declared.saturating_sub(self.globals)];
+        let found = match self::slot(locals, &var.name) {
+            Ok(local) => Some(self.globals as u32 + local),
+            Err(_) => self.program.globals[..self.globals.min(declared)]
+                .iter()
+                .rposition(|global| global.var.name == var.name)
+                .map(|global| global as u32),
+        };
+        match found {
+            Some(found) if found == slot => Ok(()),
+            _ => Err(format!(
+                "slot {slot} is used where {} refers to another slot",
+                var.name
+            )),
+        }
+    }
+
+    fn expr(&self, (expr, _): &Spanned<Expr>, declared: usize) -> Result<(), String> {
+        match expr {
+            Expr::Int(_) | Expr::Str(_) | Expr::Float(_) => Ok(()),
+            Expr::Var(slot) | Expr::PreInc(slot) | Expr::PreDec(slot) | Expr::AddrOf(slot) => {
+                self.var(*slot, declared)
+            }
+            Expr::Neg(inner) | Expr::Not(inner) | Expr::Deref(inner) => self.expr(inner, declared),
+            Expr::Binary(_, lhs, rhs) | Expr::Index(lhs, rhs) | Expr::Logical(_, lhs, rhs) => {
+                self.expr(lhs, declared)?;
+                self.expr(rhs, declared)
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                self.expr(cond, declared)?;
+                self.expr(then, declared)?;
+                self.expr(otherwise, declared)
+            }
+            Expr::Call { callee, args } | Expr::Spawn { callee, args } => {
+                if let Callee::Func(index) = callee {
+                    if *index as usize >= self.program.funcs.len() {
+                        return Err(format!("function {index} doesn't exist"));
+                    }
+                }
+                args.iter().try_for_each(|arg| self.expr(arg, declared))
+            }
+            Expr::Join(handle) => self.expr(handle, declared),
+        }
+    }
+}
+
+impl Program {
+    /// The AST of the program, which has passed [`check`], with every index replaced by the name
+    /// it refers to.
+    pub fn to_ast(&self) -> Ast {
+        let structs = self.structs.iter().map(|r#struct| Definition::Struct {
+            name: r#struct.name,
+            params: r#struct
+                .fields
+                .iter()
+                .map(|field| self.param(field))
+                .collect(),
+            span: r#struct.span.clone(),
+        });
+        let globals = self.globals.iter().map(|global| {
+            Definition::Global(ast::Global {
+                ty: self.type_name(&global.var.ty),
+                name: global.var.name,
+                expr: global.expr.as_ref().map(|expr| self.expr(&[], expr)),
+                constant: global.constant,
+                span: global.var.span.clone(),
+            })
+        });
+        let funcs = self.funcs.iter().map(|func| {
+            Definition::Func(ast::Func {
+                name: func.name,
+                params: func.slots[..func.params as usize]
+                    .iter()
+                    .map(|param| self.param(param))
+                    .collect(),
+                ret: self.type_name(&func.ret),
+                body: func
+                    .body
+                    .iter()
+                    .map(|(statement, span)| (self.statement(&func.slots, statement), span.clone()))
+                    .collect(),
+                span: func.span.clone(),
+                comptime: false,
+            })
+        });
+        Ast {
+            defs: structs.chain(globals).chain(funcs).collect(),
+        }
+    }
+
+    /// The name of `ty` in the source, which for [`Type::Int`] is `int` and for an array is that
+    /// of its elements.
+    pub fn type_name(&self, ty: &Type) -> Symbol {
+        match ty {
+            Type::Int => Symbol::intern("int"),
+            Type::Str => Symbol::intern("string"),
+            Type::Void => Symbol::intern(ast::VOID),
+            Type::Struct(index) => self.structs[*index as usize].name,
+            Type::Pointer(pointee) => Symbol::intern(&format!("{}*", self.type_name(pointee))),
+            Type::Array { elem, .. } => self.type_name(elem),
+            Type::Float => Symbol::intern("double"),
+        }
+    }
+
+    fn param(&self, var: &Var) -> Param {
+        Param {
+            ty: self.type_name(&var.ty),
+            name: var.name,
+            span: var.span.clone(),
+        }
+    }
+
+    /// The variable in `slot` of a function whose own slots are `locals`.
+    fn var<'a>(&'a self, locals: &'a [Var], slot: u32) -> &'a Var {
+        let slot = slot as usize;
+        match self.globals.get(slot) {
+            Some(global) => &global.var,
+            None => &locals[slot - self.globals.len()],
+        }
+    }
+
+    fn statement(&self, locals: &[Var], statement: &Stmt) -> Statement {
+        let name = |slot: &u32| self.var(locals, *slot).name;
+        let expr = |expr| Box::new(self.expr(locals, expr));
+        match statement {
+            Stmt::Return(value) => Statement::Return(expr(value)),
+            Stmt::ReturnVoid => Statement::ReturnVoid,
+            Stmt::Expr(value) => Statement::Expr(expr(value)),
+            Stmt::Let { slot, expr: value } => Statement::Assign {
+                ty: self.type_name(&self.var(locals, *slot).ty),
+                name: name(slot),
+                expr: expr(value),
+            },
+            Stmt::Array { slot } => {
+                let var = self.var(locals, *slot);
+                let len = match var.ty {
+                    Type::Array { len, .. } => len,
+                    _ => 0,
+                };
+                Statement::Array {
+                    ty: self.type_name(&var.ty),
+                    name: var.name,
+                    len,
+                }
+            }
+            Stmt::Store {
+                slot,
+                index,
+                expr: value,
+            } => Statement::Store {
+                name: name(slot),
+                index: expr(index),
+                expr: expr(value),
+            },
+            Stmt::Reassign {
+                slot,
+                op,
+                expr: value,
+            } => Statement::Reassign {
+                name: name(slot),
+                op: *op,
+                expr: expr(value),
+            },
+            Stmt::Write {
+                pointer,
+                expr: value,
+            } => Statement::Write {
+                pointer: expr(pointer),
+                expr: expr(value),
+            },
+            Stmt::Inline { lang, code } => Statement::Inline {
+                lang: *lang,
+                code: code.clone(),
+            },
+            Stmt::Switch { expr: value, cases } => Statement::Switch {
+                expr: expr(value),
+                cases: cases
+                    .iter()
+                    .map(|case| ast::Case {
+                        value: case.value,
+                        span: case.span.clone(),
+                        body: case
+                            .body
+                            .iter()
+                            .map(|(statement, span)| {
+                                (self.statement(locals, statement), span.clone())
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            },
+            Stmt::Break => Statement::Break,
+            Stmt::Continue => Statement::Continue,
+        }
+    }
+
+    /// The name of the function `callee` calls.
+    fn callee(&self, callee: &Callee) -> Symbol {
+        match callee {
+            Callee::Func(index) => self.funcs[*index as usize].name,
+            Callee::Extern(name) => Symbol::intern(name),
+        }
+    }
+
+    fn expr(&self, locals: &[Var], (expr, span): &Spanned<Expr>) -> Spanned<ast::Expr> {
+        use ast::Expr as E;
+
+        let name = |slot: &u32| self.var(locals, *slot).name;
+        let boxed = |expr| Box::new(self.expr(locals, expr));
+        let expr = match expr {
+            Expr::Int(value) => E::Int(*value),
+            Expr::Str(value) => E::Str(value.clone()),
+            Expr::Float(value) => E::Float(*value),
+            Expr::Var(slot) => E::Var(name(slot)),
+            Expr::PreInc(slot) => E::PreInc(name(slot)),
+            Expr::PreDec(slot) => E::PreDec(name(slot)),
+            Expr::AddrOf(slot) => E::AddrOf(name(slot)),
+            Expr::Neg(inner) => E::Neg(boxed(inner)),
+            Expr::Not(inner) => E::Not(boxed(inner)),
+            Expr::Deref(inner) => E::Deref(boxed(inner)),
+            Expr::Binary(op, lhs, rhs) => {
+                let binary = match op {
+                    BinOp::Add => E::Add,
+                    BinOp::Sub => E::Sub,
+                    BinOp::Mul => E::Mul,
+                    BinOp::Div => E::Div,
+                    BinOp::Rem => E::Rem,
+                    BinOp::And => E::BitAnd,
+                    BinOp::Or => E::BitOr,
+                    BinOp::Xor => E::BitXor,
+                    BinOp::Shl => E::Shl,
+                    BinOp::Shr => E::Shr,
+                    BinOp::Lt => E::Lt,
+                    BinOp::Le => E::Le,
+                    BinOp::Gt => E::Gt,
+                    BinOp::Ge => E::Ge,
+                    BinOp::Eq => E::Eq,
+                    BinOp::Ne => E::Ne,
+                };
+                binary(boxed(lhs), boxed(rhs))
+            }
+            Expr::Index(array, index) => E::Index(boxed(array), boxed(index)),
+            Expr::Cond(cond, then, otherwise) => {
+                E::Cond(boxed(cond), boxed(then), boxed(otherwise))
+            }
+            Expr::Call { callee, args } => E::Call {
+                name: self.callee(callee),
+                params: args.iter().map(|arg| self.expr(locals, arg)).collect(),
+            },
+            Expr::Spawn { callee, args } => E::Spawn {
+                name: self.callee(callee),
+                params: args.iter().map(|arg| self.expr(locals, arg)).collect(),
+            },
+            Expr::Join(handle) => E::Join(boxed(handle)),
+            Expr::Logical(LogicalOp::And, lhs, rhs) => E::And(boxed(lhs), boxed(rhs)),
+            Expr::Logical(LogicalOp::Or, lhs, rhs) => E::Or(boxed(lhs), boxed(rhs)),
+        };
+        (expr, span.clone())
+    }
+}
@@ -0,0 +1,227 @@
+//! The types a program can lay out in memory, for the fields of its structs and for `sizeof`.
+//!
+//! The scalar types have the sizes C compilers give them on x86-64, a `string` is the pointer to
+//! its characters, and a struct lays its fields out in order, each at a multiple of its own
+//! alignment, padded to a multiple of the alignment of its most aligned field. A struct can only
+//! contain another struct, or itself, through a pointer, since otherwise it would have no size.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{self, Definition, Expr, Param, Spanned},
+    diagnostics::Diagnostic,
+    messages::Message,
+    token::Span,
+    visit::{self, Folder},
+    Ast,
+};
+
+/// The size of a pointer, or of a `string`, in bytes.
+const POINTER_SIZE: u32 = 8;
+
+/// The scalar types and their sizes in bytes, which are also their alignments.
+const SCALARS: &[(&str, u32)] = &[
+    ("char", 1),
+    ("int", 4),
+    ("long", 8),
+    ("float", 4),
+    ("double", 8),
+    ("string", POINTER_SIZE),
+];
+
+/// The scalar types that hold ints.
+const INT_TYPES: &[&str] = &["char", "int", "long"];
+
+/// The scalar types that hold floats, which are all 64-bit when a program runs, whatever their
+/// size.
+const FLOAT_TYPES: &[&str] = &["float", "double"];
+
+/// Whether `ty` holds ints, which a float stored in it is truncated to.
+pub fn is_int(ty: &str) -> bool {
+    INT_TYPES.contains(&ty)
+}
+
+/// Whether `ty` holds floats, which an int stored in it is converted to.
+pub fn is_float(ty: &str) -> bool {
+    FLOAT_TYPES.contains(&ty)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub size: u32,
+    pub align: u32,
+}
+
+impl Layout {
+    fn scalar(size: u32) -> Self {
+        Self { size, align: size }
+    }
+}
+
+/// The layout of every struct in a program, built when it's checked and when it's loaded to run.
+#[derive(Debug, Default, Clone)]
+pub struct TypeTable {
+    /// The layout of each struct, or `None` for one that contains itself
+    structs: HashMap<String, Option<Layout>>,
+}
+
+impl TypeTable {
+    /// Lays out the structs of `ast`, failing with every field whose type isn't known or has no
+    /// size.
+    pub fn new(ast: &Ast) -> Result<Self, Vec<Diagnostic>> {
+        let mut builder = Builder {
+            defs: HashMap::new(),
+            table: TypeTable::default(),
+            stack: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        for def in &ast.defs {
+            if let Definition::Struct { name, params, span } = def {
+                builder.defs.entry(name).or_insert((params, span));
+            }
+        }
+        for def in &ast.defs {
+            if let Definition::Struct { name, .. } = def {
+                builder.layout_struct(name);
+            }
+        }
+
+        match builder.diagnostics.is_empty() {
+            true => Ok(builder.table),
+            false => Err(builder.diagnostics),
+        }
+    }
+
+    /// The layout of `ty`, failing with an error for the code that uses it to label.
+    pub fn layout(&self, ty: &str) -> Result<Layout, Diagnostic> {
+        if ast::is_pointer(ty) {
+            return match self.is_known(ty.trim_end_matches('*')) {
+                true => Ok(Layout::scalar(POINTER_SIZE)),
+                false => Err(unknown(ty)),
+            };
+        }
+        if let Some(&(_, size)) = SCALARS.iter().find(|(name, _)| *name == ty) {
+            return Ok(Layout::scalar(size));
+        }
+        match self.structs.get(ty) {
+            Some(Some(layout)) => Ok(*layout),
+            Some(None) => Err(Diagnostic::error(
+                "E0122",
+                Message::new("E0122").arg("name", ty),
+            )),
+            None if ty == ast::VOID => Err(Diagnostic::error(
+                "E0123",
+                Message::new("E0123").arg("ty", ty),
+            )),
+            None => Err(unknown(ty)),
+        }
+    }
+
+    /// The size of `ty` in bytes, as `sizeof(ty)` evaluates to.
+    pub fn size_of(&self, ty: &str) -> Result<u32, Diagnostic> {
+        self.layout(ty).map(|layout| layout.size)
+    }
+
+    /// Replaces every `sizeof` in `ast` with the size it measures, leaving the ones that measure a
+    /// type without a size for the program to fail on if they're reached.
+    pub fn fold(&self, ast: Ast) -> Ast {
+        SizeFolder(self).fold_ast(ast)
+    }
+
+    /// Whether `ty` names a type, which a pointer can point to even if it has no size.
+    fn is_known(&self, ty: &str) -> bool {
+        ty == ast::VOID
+            || SCALARS.iter().any(|(name, _)| *name == ty)
+            || self.structs.contains_key(ty)
+    }
+}
+
+struct SizeFolder<'a>(&'a TypeTable);
+
+impl Folder for SizeFolder<'_> {
+    fn fold_expr(&mut self, expr: Spanned<Expr>) -> Spanned<Expr> {
+        match visit::fold_expr(self, expr) {
+            (Expr::SizeOf(ty), span) => match self.0.size_of(&ty) {
+                Ok(size) => (Expr::Int(size), span),
+                Err(_) => (Expr::SizeOf(ty), span),
+            },
+            expr => expr,
+        }
+    }
+}
+
+fn unknown(ty: &str) -> Diagnostic {
+    Diagnostic::error("E0121", Message::new("E0121").arg("ty", ty))
+}
+
+struct Builder<'a> {
+    defs: HashMap<&'a str, (&'a [Param], &'a Span)>,
+    table: TypeTable,
+    /// The structs being laid out, each containing the next
+    stack: Vec<&'a str>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Builder<'a> {
+    fn layout_struct(&mut self, name: &'a str) -> Option<Layout> {
+        if let Some(layout) = self.table.structs.get(name) {
+            return *layout;
+        }
+        let (fields, span) = self.defs[name];
+        if let Some(start) = self.stack.iter().position(|outer| *outer == name) {
+            let cycle = self.stack[start..]
+                .iter()
+                .chain([&name])
+                .copied()
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            self.diagnostics.push(
+                Diagnostic::error("E0122", Message::new("E0122").arg("name", name))
+                    .with_label(span.clone(), Message::new("label.first-defined"))
+                    .with_note(Message::new("note.cycle").arg("cycle", cycle)),
+            );
+            return None;
+        }
+
+        self.stack.push(name);
+        let mut layout = Some(Layout { size: 0, align: 1 });
+        for field in fields {
+            let field_layout = self.layout_field(field);
+            layout = layout.zip(field_layout).map(|(layout, field)| Layout {
+                size: layout.size.next_multiple_of(field.align) + field.size,
+                align: layout.align.max(field.align),
+            });
+        }
+        self.stack.pop();
+
+        let layout = layout.map(|layout| Layout {
+            size: layout.size.next_multiple_of(layout.align),
+            ..layout
+        });
+        // every struct in a cycle has no size, but the cycle is only reported once
+        self.table.structs.insert(name.to_string(), layout);
+        layout
+    }
+
+    fn layout_field(&mut self, field: &Param) -> Option<Layout> {
+        let error = match self.defs.get_key_value(field.ty.as_str()) {
+            Some((name, _)) => return self.layout_struct(name),
+            None if ast::is_pointer(&field.ty) => {
+                let pointee = field.ty.trim_end_matches('*');
+                match self.table.is_known(pointee) || self.defs.contains_key(pointee) {
+                    true => return Some(Layout::scalar(POINTER_SIZE)),
+                    false => unknown(&field.ty),
+                }
+            }
+            None => match self.table.layout(&field.ty) {
+                Ok(layout) => return Some(layout),
+                Err(error) => error,
+            },
+        };
+        self.diagnostics.push(error.with_label(
+            field.span.clone(),
+            Message::new("label.field").arg("name", field.name.as_str()),
+        ));
+        None
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ir::BinOp, literal, messages::Message, semantics::IntMode, types};
+
+/// A runtime value produced by evaluating an expression.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    Int(i64),
+    Str(String),
+    Array(Vec<Value>),
+    Pointer(Pointer),
+    /// The handle of a thread started with `spawn`, by its number
+    Thread(u64),
+    Float(f64),
+}
+
+/// Where a pointer points: a variable of a call that may since have returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pointer {
+    /// The index of the variable among the variables of every call in progress
+    pub slot: usize,
+    /// The number of the call the variable belongs to, counting every call the program has made,
+    /// so that a pointer into a call that's returned doesn't reach a later call's variable
+    pub call: usize,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Str(value) => write!(f, "{value}"),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Self::Pointer(_) => write!(f, "<pointer>"),
+            Self::Thread(_) => write!(f, "<thread>"),
+            Self::Float(value) => write!(f, "{}", literal::float(*value)),
+        }
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Self::Int(value.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::Str(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::Str(value)
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Int(_) => "int",
+            Self::Str(_) => "string",
+            Self::Array(_) => "array",
+            Self::Pointer(_) => "pointer",
+            Self::Thread(_) => "thread",
+            Self::Float(_) => "float",
+        }
+    }
+
+    /// This value stored in a variable declared with type `ty`: an int converted to a float for a
+    /// float type, or a float truncated to an int for an int type.
+    pub fn convert(self, ty: &str, ints: IntMode) -> Value {
+        match self {
+            Self::Int(value) if types::is_float(ty) => Self::Float(value as f64),
+            Self::Float(value) if types::is_int(ty) => Self::Int(ints.truncate(value)),
+            value => value,
+        }
+    }
+
+    /// The value a variable or element of type `ty` starts with when it isn't given one.
+    pub fn zero(ty: &str) -> Value {
+        match ty {
+            "string" => Self::Str(String::new()),
+            ty if types::is_float(ty) => Self::Float(0.0),
+            _ => Self::Int(0),
+        }
+    }
+
+    /// This value stored where `old` is: a number converted to the kind of number `old` is.
+    pub fn convert_like(self, old: &Value, ints: IntMode) -> Value {
+        match (self, old) {
+            (Self::Int(value), Self::Float(_)) => Self::Float(value as f64),
+            (Self::Float(value), Self::Int(_)) => Self::Int(ints.truncate(value)),
+            (value, _) => value,
+        }
+    }
+
+    /// `lhs op rhs` if either is a float and the other a number, which is a float for arithmetic
+    /// and 1 or 0 for a comparison, or `None` if they aren't or `op` only takes ints.
+    pub fn float_binary(op: BinOp, lhs: &Value, rhs: &Value) -> Option<Value> {
+        let (lhs, rhs) = match (lhs, rhs) {
+            (Self::Float(lhs), Self::Float(rhs)) => (*lhs, *rhs),
+            (Self::Float(lhs), Self::Int(rhs)) => (*lhs, *rhs as f64),
+            (Self::Int(lhs), Self::Float(rhs)) => (*lhs as f64, *rhs),
+            _ => return None,
+        };
+        Some(match op {
+            BinOp::Add => Self::Float(lhs + rhs),
+            BinOp::Sub => Self::Float(lhs - rhs),
+            BinOp::Mul => Self::Float(lhs * rhs),
+            BinOp::Div => Self::Float(lhs / rhs),
+            BinOp::Lt => Self::Int((lhs < rhs) as i64),
+            BinOp::Le => Self::Int((lhs <= rhs) as i64),
+            BinOp::Gt => Self::Int((lhs > rhs) as i64),
+            BinOp::Ge => Self::Int((lhs >= rhs) as i64),
+            BinOp::Eq => Self::Int((lhs == rhs) as i64),
+            BinOp::Ne => Self::Int((lhs != rhs) as i64),
+            BinOp::Rem | BinOp::Shl | BinOp::Shr | BinOp::And | BinOp::Or | BinOp::Xor => {
+                return None
+            }
+        })
+    }
+
+    /// Returns the element at `index`, or an error if it is out of bounds.
+    pub fn element(&self, index: i64) -> Result<&Value, Message> {
+        let values = self.as_array()?;
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| values.get(i))
+            .ok_or_else(|| out_of_bounds(index, values.len()))
+    }
+
+    /// Returns a mutable reference to the element at `index`, or an error if it is out of bounds.
+    pub fn element_mut(&mut self, index: i64) -> Result<&mut Value, Message> {
+        let Self::Array(values) = self else {
+            return Err(expected("array", self));
+        };
+        let len = values.len();
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| values.get_mut(i))
+            .ok_or_else(|| out_of_bounds(index, len))
+    }
+
+    pub fn as_array(&self) -> Result<&[Value], Message> {
+        match self {
+            Self::Array(values) => Ok(values),
+            other => Err(expected("array", other)),
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i64, Message> {
+        match self {
+            Self::Int(value) => Ok(*value),
+            other => Err(expected("int", other)),
+        }
+    }
+
+    pub fn as_pointer(&self) -> Result<Pointer, Message> {
+        match self {
+            Self::Pointer(pointer) => Ok(*pointer),
+            other => Err(expected("pointer", other)),
+        }
+    }
+
+    pub fn as_thread(&self) -> Result<u64, Message> {
+        match self {
+            Self::Thread(id) => Ok(*id),
+            other => Err(expected("thread", other)),
+        }
+    }
+
+    /// Adds two values, ints as `ints` does, concatenating when either side is a string.
+    pub fn add(self, rhs: Value, ints: IntMode) -> Result<Value, Message> {
+        if let Some(sum) = Self::float_binary(BinOp::Add, &self, &rhs) {
+            return Ok(sum);
+        }
+        match (self, rhs) {
+            (Self::Int(lhs), Self::Int(rhs)) => {
+                ints.add(lhs, rhs).map(Self::Int).map_err(Message::from)
+            }
+            (Self::Str(lhs), rhs) => Ok(Self::Str(format!("{lhs}{rhs}"))),
+            (lhs, Self::Str(rhs)) => Ok(Self::Str(format!("{lhs}{rhs}"))),
+            (lhs, rhs) => Err(Message::new("E0202.cannot-add")
+                .arg("lhs", lhs.type_name())
+                .arg("rhs", rhs.type_name())),
+        }
+    }
+}
+
+fn out_of_bounds(index: i64, len: usize) -> Message {
+    Message::new("E0202.out-of-bounds")
+        .arg("index", index.to_string())
+        .arg("len", len.to_string())
+}
+
+fn expected(expected: &str, found: &Value) -> Message {
+    Message::new("E0202.expected")
+        .arg("expected", expected)
+        .arg("found", found.type_name())
+}
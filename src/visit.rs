@@ -0,0 +1,353 @@
+//! Traversal of the AST without hand-written recursion.
+//!
+//! A [`Visitor`] overrides the methods for the nodes it's interested in and calls the matching
+//! `walk_*` function from them to carry on into the node's children, or doesn't to skip them.
+//! Every method defaults to walking, so a visitor that overrides nothing visits every node.
+//!
+//! A [`Folder`] rebuilds the AST it takes ownership of in the same way, with `fold_*` functions
+//! that rebuild a node from its folded children, so a folder that overrides nothing returns the
+//! AST unchanged.
+
+use crate::{
+    ast::{Case, Definition, Expr, Func, Global, Macro, Param, Prototype, Spanned, Statement},
+    Ast,
+};
+
+/// Visits the nodes of an AST borrowed for `'a`, in the order they appear in the source.
+pub trait Visitor<'a> {
+    fn visit_ast(&mut self, ast: &'a Ast) {
+        walk_ast(self, ast);
+    }
+
+    fn visit_definition(&mut self, def: &'a Definition) {
+        walk_definition(self, def);
+    }
+
+    fn visit_func(&mut self, func: &'a Func) {
+        walk_func(self, func);
+    }
+
+    /// Visits a struct field or a function parameter.
+    fn visit_param(&mut self, _param: &'a Param) {}
+
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_ast<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, ast: &'a Ast) {
+    for def in &ast.defs {
+        visitor.visit_definition(def);
+    }
+}
+
+pub fn walk_definition<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, def: &'a Definition) {
+    match def {
+        Definition::Struct { params, .. } => {
+            for param in params {
+                visitor.visit_param(param);
+            }
+        }
+        Definition::Func(func) => visitor.visit_func(func),
+        Definition::Prototype(prototype) => {
+            for param in &prototype.params {
+                visitor.visit_param(param);
+            }
+        }
+        Definition::Import { .. } => {}
+        Definition::Macro(r#macro) => {
+            for statement in &r#macro.body {
+                visitor.visit_statement(statement);
+            }
+        }
+        Definition::Global(global) => {
+            if let Some(expr) = &global.expr {
+                visitor.visit_expr(expr);
+            }
+        }
+    }
+}
+
+pub fn walk_func<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, func: &'a Func) {
+    for param in &func.params {
+        visitor.visit_param(param);
+    }
+    for statement in &func.body {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    (statement, _): &'a Spanned<Statement>,
+) {
+    match statement {
+        Statement::Invalid
+        | Statement::Array { .. }
+        | Statement::ReturnVoid
+        | Statement::Inline { .. }
+        | Statement::Break
+        | Statement::Continue => {}
+        Statement::Return(expr)
+        | Statement::Expr(expr)
+        | Statement::Assign { expr, .. }
+        | Statement::Reassign { expr, .. } => visitor.visit_expr(expr),
+        Statement::Store { index, expr, .. } => {
+            visitor.visit_expr(index);
+            visitor.visit_expr(expr);
+        }
+        Statement::Write { pointer, expr } => {
+            visitor.visit_expr(pointer);
+            visitor.visit_expr(expr);
+        }
+        Statement::Expand { args, .. } => {
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Statement::Switch { expr, cases } => {
+            visitor.visit_expr(expr);
+            for statement in cases.iter().flat_map(|case| &case.body) {
+                visitor.visit_statement(statement);
+            }
+        }
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, (expr, _): &'a Spanned<Expr>) {
+    match expr {
+        Expr::Err | Expr::Int(_) | Expr::Float(_) | Expr::Str(_) | Expr::Var(_) => {}
+        Expr::SizeOf(_) => {}
+        Expr::PreInc(_) | Expr::PreDec(_) | Expr::AddrOf(_) => {}
+        Expr::Neg(inner) | Expr::Not(inner) | Expr::Deref(inner) => visitor.visit_expr(inner),
+        Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, rhs)
+        | Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Rem(lhs, rhs)
+        | Expr::BitAnd(lhs, rhs)
+        | Expr::BitOr(lhs, rhs)
+        | Expr::BitXor(lhs, rhs)
+        | Expr::Shl(lhs, rhs)
+        | Expr::Shr(lhs, rhs)
+        | Expr::Lt(lhs, rhs)
+        | Expr::Le(lhs, rhs)
+        | Expr::Gt(lhs, rhs)
+        | Expr::Ge(lhs, rhs)
+        | Expr::Eq(lhs, rhs)
+        | Expr::Ne(lhs, rhs)
+        | Expr::And(lhs, rhs)
+        | Expr::Or(lhs, rhs)
+        | Expr::Index(lhs, rhs) => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        Expr::Cond(cond, then, otherwise) => {
+            visitor.visit_expr(cond);
+            visitor.visit_expr(then);
+            visitor.visit_expr(otherwise);
+        }
+        Expr::Call { params, .. } | Expr::Spawn { params, .. } => {
+            for param in params {
+                visitor.visit_expr(param);
+            }
+        }
+        Expr::Join(handle) => visitor.visit_expr(handle),
+    }
+}
+
+/// Rebuilds the nodes of an AST, in the order they appear in the source.
+pub trait Folder {
+    fn fold_ast(&mut self, ast: Ast) -> Ast {
+        fold_ast(self, ast)
+    }
+
+    fn fold_definition(&mut self, def: Definition) -> Definition {
+        fold_definition(self, def)
+    }
+
+    fn fold_func(&mut self, func: Func) -> Func {
+        fold_func(self, func)
+    }
+
+    /// Folds a struct field or a function parameter.
+    fn fold_param(&mut self, param: Param) -> Param {
+        param
+    }
+
+    fn fold_statement(&mut self, statement: Spanned<Statement>) -> Spanned<Statement> {
+        fold_statement(self, statement)
+    }
+
+    fn fold_expr(&mut self, expr: Spanned<Expr>) -> Spanned<Expr> {
+        fold_expr(self, expr)
+    }
+}
+
+pub fn fold_ast<F: Folder + ?Sized>(folder: &mut F, ast: Ast) -> Ast {
+    Ast {
+        defs: ast
+            .defs
+            .into_iter()
+            .map(|def| folder.fold_definition(def))
+            .collect(),
+    }
+}
+
+pub fn fold_definition<F: Folder + ?Sized>(folder: &mut F, def: Definition) -> Definition {
+    match def {
+        Definition::Struct { name, params, span } => Definition::Struct {
+            name,
+            params: params
+                .into_iter()
+                .map(|param| folder.fold_param(param))
+                .collect(),
+            span,
+        },
+        Definition::Func(func) => Definition::Func(folder.fold_func(func)),
+        Definition::Prototype(prototype) => Definition::Prototype(Prototype {
+            params: prototype
+                .params
+                .into_iter()
+                .map(|param| folder.fold_param(param))
+                .collect(),
+            ..prototype
+        }),
+        Definition::Import { .. } => def,
+        Definition::Macro(r#macro) => Definition::Macro(Macro {
+            body: r#macro
+                .body
+                .into_iter()
+                .map(|statement| folder.fold_statement(statement))
+                .collect(),
+            ..r#macro
+        }),
+        Definition::Global(global) => Definition::Global(Global {
+            expr: global.expr.map(|expr| folder.fold_expr(expr)),
+            ..global
+        }),
+    }
+}
+
+pub fn fold_func<F: Folder + ?Sized>(folder: &mut F, func: Func) -> Func {
+    Func {
+        params: func
+            .params
+            .into_iter()
+            .map(|param| folder.fold_param(param))
+            .collect(),
+        body: func
+            .body
+            .into_iter()
+            .map(|statement| folder.fold_statement(statement))
+            .collect(),
+        ..func
+    }
+}
+
+pub fn fold_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    (statement, span): Spanned<Statement>,
+) -> Spanned<Statement> {
+    let mut fold = |expr: Box<Spanned<Expr>>| Box::new(folder.fold_expr(*expr));
+    let statement = match statement {
+        Statement::Invalid
+        | Statement::Array { .. }
+        | Statement::ReturnVoid
+        | Statement::Inline { .. }
+        | Statement::Break
+        | Statement::Continue => statement,
+        Statement::Return(expr) => Statement::Return(fold(expr)),
+        Statement::Expr(expr) => Statement::Expr(fold(expr)),
+        Statement::Assign { ty, name, expr } => Statement::Assign {
+            ty,
+            name,
+            expr: fold(expr),
+        },
+        Statement::Store { name, index, expr } => Statement::Store {
+            name,
+            index: fold(index),
+            expr: fold(expr),
+        },
+        Statement::Reassign { name, op, expr } => Statement::Reassign {
+            name,
+            op,
+            expr: fold(expr),
+        },
+        Statement::Write { pointer, expr } => Statement::Write {
+            pointer: fold(pointer),
+            expr: fold(expr),
+        },
+        Statement::Expand { name, args } => Statement::Expand {
+            name,
+            args: args.into_iter().map(|arg| folder.fold_expr(arg)).collect(),
+        },
+        Statement::Switch { expr, cases } => Statement::Switch {
+            expr: Box::new(folder.fold_expr(*expr)),
+            cases: cases
+                .into_iter()
+                .map(|case| Case {
+                    body: case
+                        .body
+                        .into_iter()
+                        .map(|statement| folder.fold_statement(statement))
+                        .collect(),
+                    ..case
+                })
+                .collect(),
+        },
+    };
+    (statement, span)
+}
+
+pub fn fold_expr<F: Folder + ?Sized>(folder: &mut F, (expr, span): Spanned<Expr>) -> Spanned<Expr> {
+    let mut fold = |expr: Box<Spanned<Expr>>| Box::new(folder.fold_expr(*expr));
+    let expr = match expr {
+        Expr::Err | Expr::Int(_) | Expr::Float(_) | Expr::Str(_) | Expr::Var(_) => expr,
+        Expr::SizeOf(_) => expr,
+        Expr::PreInc(_) | Expr::PreDec(_) | Expr::AddrOf(_) => expr,
+        Expr::Neg(inner) => Expr::Neg(fold(inner)),
+        Expr::Not(inner) => Expr::Not(fold(inner)),
+        Expr::Deref(inner) => Expr::Deref(fold(inner)),
+        Expr::Mul(lhs, rhs) => Expr::Mul(fold(lhs), fold(rhs)),
+        Expr::Div(lhs, rhs) => Expr::Div(fold(lhs), fold(rhs)),
+        Expr::Add(lhs, rhs) => Expr::Add(fold(lhs), fold(rhs)),
+        Expr::Sub(lhs, rhs) => Expr::Sub(fold(lhs), fold(rhs)),
+        Expr::Rem(lhs, rhs) => Expr::Rem(fold(lhs), fold(rhs)),
+        Expr::BitAnd(lhs, rhs) => Expr::BitAnd(fold(lhs), fold(rhs)),
+        Expr::BitOr(lhs, rhs) => Expr::BitOr(fold(lhs), fold(rhs)),
+        Expr::And(lhs, rhs) => Expr::And(fold(lhs), fold(rhs)),
+        Expr::Or(lhs, rhs) => Expr::Or(fold(lhs), fold(rhs)),
+        Expr::BitXor(lhs, rhs) => Expr::BitXor(fold(lhs), fold(rhs)),
+        Expr::Shl(lhs, rhs) => Expr::Shl(fold(lhs), fold(rhs)),
+        Expr::Shr(lhs, rhs) => Expr::Shr(fold(lhs), fold(rhs)),
+        Expr::Lt(lhs, rhs) => Expr::Lt(fold(lhs), fold(rhs)),
+        Expr::Le(lhs, rhs) => Expr::Le(fold(lhs), fold(rhs)),
+        Expr::Gt(lhs, rhs) => Expr::Gt(fold(lhs), fold(rhs)),
+        Expr::Ge(lhs, rhs) => Expr::Ge(fold(lhs), fold(rhs)),
+        Expr::Eq(lhs, rhs) => Expr::Eq(fold(lhs), fold(rhs)),
+        Expr::Ne(lhs, rhs) => Expr::Ne(fold(lhs), fold(rhs)),
+        Expr::Cond(cond, then, otherwise) => Expr::Cond(fold(cond), fold(then), fold(otherwise)),
+        Expr::Index(array, index) => Expr::Index(fold(array), fold(index)),
+        Expr::Call { name, params } => Expr::Call {
+            name,
+            params: params
+                .into_iter()
+                .map(|param| folder.fold_expr(param))
+                .collect(),
+        },
+        Expr::Spawn { name, params } => Expr::Spawn {
+            name,
+            params: params
+                .into_iter()
+                .map(|param| folder.fold_expr(param))
+                .collect(),
+        },
+        Expr::Join(handle) => Expr::Join(fold(handle)),
+    };
+    (expr, span)
+}
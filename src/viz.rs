@@ -0,0 +1,202 @@
+//! Graphviz DOT graphs of a program, for `viz`.
+
+use crate::{
+    ast::{Definition, Expr, Func, Param, Spanned, Statement},
+    literal,
+    visit::{self, Visitor},
+    Ast,
+};
+
+/// Draws `ast` as a graph with a node for every definition, statement and expression, pointing
+/// to its children in order.
+pub fn ast(ast: &Ast) -> String {
+    let mut graph = AstGraph {
+        dot: String::from("digraph ast {\n    ordering=out;\n    node [shape=box];\n"),
+        parents: Vec::new(),
+        nodes: 0,
+    };
+    graph.visit_ast(ast);
+    graph.dot + "}\n"
+}
+
+struct AstGraph {
+    dot: String,
+    /// The nodes enclosing the one being visited, innermost last
+    parents: Vec<usize>,
+    nodes: usize,
+}
+
+impl AstGraph {
+    /// Adds a node with `label` under the current parent, visiting its children with `walk`.
+    fn node(&mut self, label: &str, walk: impl FnOnce(&mut Self)) {
+        let id = self.nodes;
+        self.nodes += 1;
+        self.dot += &format!("    n{id} [label={}];\n", quote(label));
+        if let Some(parent) = self.parents.last() {
+            self.dot += &format!("    n{parent} -> n{id};\n");
+        }
+
+        self.parents.push(id);
+        walk(self);
+        self.parents.pop();
+    }
+}
+
+impl<'a> Visitor<'a> for AstGraph {
+    fn visit_definition(&mut self, def: &'a Definition) {
+        let label = match def {
+            Definition::Import { path, .. } => format!("import {}", literal::escape(path)),
+            Definition::Struct { name, .. } => format!("struct {name}"),
+            Definition::Func(_) | Definition::Macro(_) => def.to_string(),
+            Definition::Global(global) => {
+                let keyword = if global.constant { "const" } else { "global" };
+                format!("{keyword} {} {}", global.ty, global.name)
+            }
+            // parameters are already in the prototype's label
+            Definition::Prototype(_) => return self.node(&def.to_string(), |_| {}),
+        };
+        self.node(&label, |graph| visit::walk_definition(graph, def));
+    }
+
+    fn visit_func(&mut self, func: &'a Func) {
+        // parameters are already in the function's label
+        for statement in &func.body {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_param(&mut self, param: &'a Param) {
+        self.node(&format!("field {} {}", param.ty, param.name), |_| {});
+    }
+
+    fn visit_statement(&mut self, statement: &'a Spanned<Statement>) {
+        let label = match &statement.0 {
+            Statement::Invalid => String::from("invalid"),
+            Statement::Return(_) | Statement::ReturnVoid => String::from("return"),
+            Statement::Expr(_) => String::from("discard"),
+            Statement::Inline { lang, .. } => String::from(lang.keyword()),
+            Statement::Assign { ty, name, .. } => format!("let {ty} {name}"),
+            Statement::Array { ty, name, len } => format!("array {ty} {name}[{len}]"),
+            Statement::Store { name, .. } => format!("store {name}"),
+            Statement::Reassign { name, op, .. } => format!("{name} {}", op.text()),
+            Statement::Write { .. } => String::from("write"),
+            Statement::Expand { name, .. } => format!("{name}!"),
+            Statement::Break => String::from("break"),
+            Statement::Continue => String::from("continue"),
+            // each case is a node of its own, holding its statements
+            Statement::Switch { expr, cases } => {
+                return self.node("switch", |graph| {
+                    graph.visit_expr(expr);
+                    for case in cases {
+                        let label = match case.value {
+                            Some(value) => format!("case {value}"),
+                            None => String::from("default"),
+                        };
+                        graph.node(&label, |graph| {
+                            for statement in &case.body {
+                                graph.visit_statement(statement);
+                            }
+                        });
+                    }
+                })
+            }
+        };
+        self.node(&label, |graph| visit::walk_statement(graph, statement));
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        let label = match &expr.0 {
+            Expr::Err => String::from("error"),
+            Expr::Int(value) => value.to_string(),
+            Expr::Float(value) => literal::float(*value),
+            Expr::Str(value) => literal::escape(value),
+            Expr::Var(name) => format!("var {name}"),
+            Expr::Neg(_) => String::from("negate"),
+            Expr::Not(_) => String::from("not"),
+            Expr::PreInc(name) => format!("++ {name}"),
+            Expr::PreDec(name) => format!("-- {name}"),
+            Expr::AddrOf(name) => format!("address of {name}"),
+            Expr::Deref(_) => String::from("deref"),
+            Expr::SizeOf(ty) => format!("sizeof {ty}"),
+            Expr::Mul(..) => String::from("*"),
+            Expr::Div(..) => String::from("/"),
+            Expr::Add(..) => String::from("+"),
+            Expr::Sub(..) => String::from("-"),
+            Expr::Rem(..) => String::from("%"),
+            Expr::BitAnd(..) => String::from("&"),
+            Expr::BitOr(..) => String::from("|"),
+            Expr::And(..) => String::from("&&"),
+            Expr::Or(..) => String::from("||"),
+            Expr::BitXor(..) => String::from("^"),
+            Expr::Shl(..) => String::from("<<"),
+            Expr::Shr(..) => String::from(">>"),
+            Expr::Lt(..) => String::from("<"),
+            Expr::Le(..) => String::from("<="),
+            Expr::Gt(..) => String::from(">"),
+            Expr::Ge(..) => String::from(">="),
+            Expr::Eq(..) => String::from("=="),
+            Expr::Ne(..) => String::from("!="),
+            Expr::Cond(..) => String::from("?:"),
+            Expr::Index(..) => String::from("index"),
+            Expr::Call { name, .. } => format!("call {name}"),
+            Expr::Spawn { name, .. } => format!("spawn {name}"),
+            Expr::Join(_) => String::from("join"),
+        };
+        self.node(&label, |graph| visit::walk_expr(graph, expr));
+    }
+}
+
+/// Draws which functions each function in `ast` calls, with an edge for every caller and callee
+/// however many times it's called. Callees that aren't defined in the program, such as builtins,
+/// are dashed.
+pub fn calls(ast: &Ast) -> String {
+    let mut graph = CallGraph::default();
+    graph.visit_ast(ast);
+
+    let mut dot = String::from("digraph calls {\n    node [shape=box];\n");
+    for name in &graph.funcs {
+        dot += &format!("    {};\n", quote(name));
+    }
+    let mut external = Vec::new();
+    for (_, callee) in &graph.calls {
+        if !graph.funcs.contains(callee) && !external.contains(callee) {
+            dot += &format!("    {} [style=dashed];\n", quote(callee));
+            external.push(*callee);
+        }
+    }
+    for (caller, callee) in &graph.calls {
+        dot += &format!("    {} -> {};\n", quote(caller), quote(callee));
+    }
+    dot + "}\n"
+}
+
+#[derive(Default)]
+struct CallGraph<'a> {
+    funcs: Vec<&'a str>,
+    /// Every caller and callee, in the order they're first called
+    calls: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Visitor<'a> for CallGraph<'a> {
+    fn visit_func(&mut self, func: &'a Func) {
+        self.funcs.push(&func.name);
+        visit::walk_func(self, func);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+        if let (Expr::Call { name, .. } | Expr::Spawn { name, .. }, Some(caller)) =
+            (&expr.0, self.funcs.last())
+        {
+            let call = (*caller, name.as_str());
+            if !self.calls.contains(&call) {
+                self.calls.push(call);
+            }
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Quotes `text` as a DOT string.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,1283 @@
+//! A bytecode compiler and a stack based virtual machine to run it, an alternative to walking the
+//! AST.
+//!
+//! Each function compiles to a flat list of [`Op`]s working on a stack of values. Variables are
+//! resolved at compile time to slots in the function's frame, or to the globals at the bottom of
+//! the stack, which [`Bytecode::init`] sets before `main` is called, and calls name functions by
+//! index,
+//! so nothing is looked up by name or cloned per call while running. Errors that can be found
+//! while compiling, like an undeclared variable or a call with the wrong number of arguments,
+//! compile to an [`Op::Fail`] that only fails if it's reached, so a program behaves exactly as it
+//! does in the interpreter: it prints the same output, returns the same exit code and fails with
+//! the same diagnostics, backtraces included.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    ast::{self, AssignOp, Definition, Expr, Func, Spanned, Statement},
+    builtins::BuiltinFn,
+    diagnostics::Diagnostic,
+    ir::BinOp,
+    messages::Message,
+    semantics,
+    token::Span,
+    types::{self, TypeTable},
+    value::Pointer,
+    Ast, Builtins, RunOptions, Symbol, Value,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Pushes an int, failing if it doesn't fit in the width of ints
+    Int(i64),
+    /// Pushes a copy of a constant from [`Bytecode::consts`]
+    Const(u32),
+    /// Pushes a copy of the value in a slot
+    Load(u32),
+    /// Pops a value into a slot
+    Store(u32),
+    /// Pushes an array of `len` zeros, or of empty strings if `string` is set
+    Array {
+        len: u32,
+        string: bool,
+    },
+    /// Fails unless the value on top of the stack is an int
+    AsInt,
+    Neg,
+    Not,
+    /// Pops two values, pushing their sum or concatenation
+    Add,
+    Sub,
+    Mul,
+    /// Fails on division by zero
+    Div,
+    /// Fails on division by zero
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    /// Continues at the op at this index
+    Jump(u32),
+    /// Pops an int, continuing at the op at this index if it's 0
+    JumpIfZero(u32),
+    /// Pops an index and an array, pushing the element
+    Index,
+    /// Pops an index, pushing the element of the array in a slot
+    LoadElement(u32),
+    /// Pops a value and an index, storing the value in the array in a slot
+    StoreElement(u32),
+    /// Pushes a pointer to a slot
+    AddrOf(u32),
+    /// Fails unless the value on top of the stack is a pointer
+    AsPointer,
+    /// Pops a pointer, pushing a copy of the value it points to
+    Deref,
+    /// Pops a value and a pointer, storing the value where the pointer points
+    StoreDeref,
+    /// Fails if calling a function would exceed the maximum call depth, or if the program has
+    /// been interrupted
+    CheckDepth(u32),
+    /// Calls a function with its arguments on top of the stack
+    Call(u32),
+    /// Calls a builtin from [`Bytecode::builtins`] with `argc` arguments on top of the stack
+    Builtin {
+        builtin: u32,
+        argc: u32,
+    },
+    /// Pops a value and returns it to the caller
+    Return,
+    /// Pops a value and discards it
+    Pop,
+    /// Fails with an error from [`Bytecode::errors`]
+    Fail(u32),
+    /// Fails unless the value on top of the stack is an int or a float
+    AsNumber,
+    /// Converts an int on top of the stack to a float, for a variable of a float type
+    ToFloat,
+    /// Truncates a float on top of the stack to an int, for a variable of an int type
+    ToInt,
+    /// Pops a value into a slot, converted to the kind of number the slot holds
+    Reassign(u32),
+    /// Pushes an array of `len` zero floats
+    FloatArray(u32),
+    /// Pushes a pointer to a global
+    Global(u32),
+    /// Pops a value, an index and a pointer, storing the value in the array the pointer points to
+    StoreElementDeref,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Int(value) => write!(f, "int {value}"),
+            Op::Const(index) => write!(f, "const {index}"),
+            Op::Load(slot) => write!(f, "load {slot}"),
+            Op::Store(slot) => write!(f, "store {slot}"),
+            Op::Array { len, string: false } => write!(f, "array {len}"),
+            Op::Array { len, string: true } => write!(f, "array {len} string"),
+            Op::AsInt => write!(f, "as-int"),
+            Op::Neg => write!(f, "neg"),
+            Op::Not => write!(f, "not"),
+            Op::Add => write!(f, "add"),
+            Op::Sub => write!(f, "sub"),
+            Op::Mul => write!(f, "mul"),
+            Op::Div => write!(f, "div"),
+            Op::Rem => write!(f, "rem"),
+            Op::BitAnd => write!(f, "and"),
+            Op::BitOr => write!(f, "or"),
+            Op::BitXor => write!(f, "xor"),
+            Op::Shl => write!(f, "shl"),
+            Op::Shr => write!(f, "shr"),
+            Op::Lt => write!(f, "lt"),
+            Op::Le => write!(f, "le"),
+            Op::Gt => write!(f, "gt"),
+            Op::Ge => write!(f, "ge"),
+            Op::Eq => write!(f, "eq"),
+            Op::Ne => write!(f, "ne"),
+            Op::Jump(target) => write!(f, "jump {target}"),
+            Op::JumpIfZero(target) => write!(f, "jump-if-zero {target}"),
+            Op::Index => write!(f, "index"),
+            Op::LoadElement(slot) => write!(f, "load-element {slot}"),
+            Op::StoreElement(slot) => write!(f, "store-element {slot}"),
+            Op::AddrOf(slot) => write!(f, "addr-of {slot}"),
+            Op::AsPointer => write!(f, "as-pointer"),
+            Op::Deref => write!(f, "deref"),
+            Op::StoreDeref => write!(f, "store-deref"),
+            Op::CheckDepth(func) => write!(f, "check-depth {func}"),
+            Op::Call(func) => write!(f, "call {func}"),
+            Op::Builtin { builtin, argc } => write!(f, "builtin {builtin} {argc}"),
+            Op::Return => write!(f, "return"),
+            Op::Pop => write!(f, "pop"),
+            Op::Fail(index) => write!(f, "fail {index}"),
+            Op::AsNumber => write!(f, "as-number"),
+            Op::ToFloat => write!(f, "to-float"),
+            Op::ToInt => write!(f, "to-int"),
+            Op::Reassign(slot) => write!(f, "reassign {slot}"),
+            Op::FloatArray(len) => write!(f, "array {len} float"),
+            Op::Global(global) => write!(f, "global {global}"),
+            Op::StoreElementDeref => write!(f, "store-element-deref"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: usize,
+    /// Slots for the parameters, which come first, and every local variable
+    pub slots: usize,
+    pub code: Vec<Op>,
+    /// The span each op reports errors at
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug, Default)]
+pub struct Bytecode {
+    pub funcs: Vec<Function>,
+    pub consts: Vec<Value>,
+    /// Names of the builtins the program calls
+    pub builtins: Vec<String>,
+    /// Errors found while compiling, raised when the op that fails with them is reached
+    pub errors: Vec<Diagnostic>,
+    /// The function that sets the globals before `main` is called, if the program has any, whose
+    /// slots are the globals
+    pub init: Option<usize>,
+}
+
+/// Compiles and runs the `main` function, binding `args` to its parameters, with the same
+/// results as [`Ast::run_main_with`].
+pub fn run_main_with(ast: &Ast, options: RunOptions, args: &[String]) -> Result<i32, Diagnostic> {
+    let bytecode = compile(ast, &options.builtins)?;
+    let Some(main) = bytecode.funcs.iter().position(|func| func.name == "main") else {
+        return Err(Diagnostic::error("E0200", Message::new("main-not-found")));
+    };
+    let main_func = funcs(ast).find(|func| func.name == "main").unwrap();
+
+    let args = ast::main_args(main_func, args)?
+        .into_iter()
+        .zip(&main_func.params)
+        .map(|(arg, param)| arg.convert(&param.ty, options.ints))
+        .collect();
+    let value = bytecode.run(main, args, &options)?;
+    ast::exit_code(main_func, value)
+}
+
+fn funcs(ast: &Ast) -> impl Iterator<Item = &Func> {
+    ast.defs.iter().filter_map(|def| match def {
+        Definition::Func(func) => Some(func),
+        _ => None,
+    })
+}
+
+/// Compiles `expr` on its own into a function that returns its value, for showing how an
+/// expression is evaluated. It has no variables, and the only functions it can call are builtins.
+pub fn compile_expr(expr: &Spanned<Expr>, builtins: &Builtins) -> Bytecode {
+    let func = Func {
+        name: Symbol::intern("<expr>"),
+        params: Vec::new(),
+        // no return type, so that its value isn't converted to one
+        ret: Symbol::EMPTY,
+        body: vec![(Statement::Return(Box::new(expr.clone())), expr.1.clone())],
+        span: expr.1.clone(),
+        comptime: false,
+    };
+    let mut bytecode = Bytecode::default();
+    let compiled = Compiler::new(
+        &HashMap::new(),
+        &TypeTable::default(),
+        builtins,
+        &mut bytecode,
+        &[],
+    )
+    .func(&func);
+    bytecode.funcs.push(compiled);
+    bytecode
+}
+
+/// Compiles every function in `ast`, failing if two have the same name, and the function that
+/// sets its globals.
+///
+/// Calls to a function in `builtins` call the builtin, as they do in the interpreter.
+pub fn compile(ast: &Ast, builtins: &Builtins) -> Result<Bytecode, Diagnostic> {
+    let mut indices = HashMap::new();
+    for (i, func) in funcs(ast).enumerate() {
+        if indices
+            .insert(func.name.as_str(), (i as u32, func))
+            .is_some()
+        {
+            return Err(ast::duplicate_function(func));
+        }
+    }
+
+    let types = TypeTable::new(ast).map_err(|mut errors| errors.remove(0))?;
+    let globals = ast
+        .defs
+        .iter()
+        .filter_map(|def| match def {
+            Definition::Global(global) => Some(global),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let names = globals
+        .iter()
+        .map(|global| global.name.as_str())
+        .collect::<Vec<_>>();
+
+    let mut bytecode = Bytecode::default();
+    for func in funcs(ast) {
+        let compiled = Compiler::new(&indices, &types, builtins, &mut bytecode, &names).func(func);
+        bytecode.funcs.push(compiled);
+    }
+    if !globals.is_empty() {
+        // the globals are the slots of the function that sets them, and so the earlier ones are
+        // in scope for the later ones' values
+        let compiled = Compiler::new(&indices, &types, builtins, &mut bytecode, &[]).init(&globals);
+        bytecode.funcs.push(compiled);
+        bytecode.init = Some(bytecode.funcs.len() - 1);
+    }
+    Ok(bytecode)
+}
+
+struct Compiler<'a> {
+    funcs: &'a HashMap<&'a str, (u32, &'a Func)>,
+    /// The layout of every struct, which `sizeof` is folded with
+    types: &'a TypeTable,
+    builtins: &'a Builtins,
+    bytecode: &'a mut Bytecode,
+    code: Vec<Op>,
+    spans: Vec<Span>,
+    /// The names of the globals, which are in scope unless a variable shadows them
+    globals: &'a [&'a str],
+    /// The variables in scope and their slots, latest last so that it shadows earlier ones
+    vars: Vec<(&'a str, u32)>,
+    slots: u32,
+    /// The jumps of the `break`s in each switch being compiled, innermost last, which are
+    /// patched to its end
+    breaks: Vec<Vec<usize>>,
+    /// The return type of the function being compiled
+    ret: &'a str,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(
+        funcs: &'a HashMap<&'a str, (u32, &'a Func)>,
+        types: &'a TypeTable,
+        builtins: &'a Builtins,
+        bytecode: &'a mut Bytecode,
+        globals: &'a [&'a str],
+    ) -> Self {
+        Compiler {
+            funcs,
+            types,
+            builtins,
+            bytecode,
+            code: Vec::new(),
+            spans: Vec::new(),
+            globals,
+            vars: Vec::new(),
+            slots: 0,
+            breaks: Vec::new(),
+            ret: "",
+        }
+    }
+
+    fn func(mut self, func: &'a Func) -> Function {
+        self.ret = &func.ret;
+        for param in &func.params {
+            self.declare(&param.name);
+        }
+
+        let mut returns = false;
+        for statement in &func.body {
+            // statements after a return or an invalid statement can never run
+            if self.statement(statement) {
+                returns = true;
+                break;
+            }
+        }
+        if !returns && func.ret == ast::VOID {
+            self.emit(Op::Int(0), &func.span);
+            self.emit(Op::Return, &func.span);
+        } else if !returns {
+            self.fail(ast::missing_return(func), &func.span);
+        }
+
+        Function {
+            name: func.name.to_string(),
+            params: func.params.len(),
+            slots: self.slots as usize,
+            code: self.code,
+            spans: self.spans,
+        }
+    }
+
+    /// Compiles the function that sets `globals` in order, each in a slot of its own.
+    fn init(mut self, globals: &[&'a ast::Global]) -> Function {
+        for global in globals {
+            match &global.expr {
+                Some(expr) => {
+                    self.expr(expr);
+                    self.convert(&global.ty, expr);
+                }
+                None => self.constant(Value::zero(&global.ty), &global.span),
+            }
+            let slot = self.declare(&global.name);
+            self.emit(Op::Store(slot), &global.span);
+        }
+        let span = globals.last().map_or(0..0, |global| global.span.clone());
+        self.emit(Op::Int(0), &span);
+        self.emit(Op::Return, &span);
+
+        Function {
+            name: String::from("<globals>"),
+            params: 0,
+            slots: self.slots as usize,
+            code: self.code,
+            spans: self.spans,
+        }
+    }
+
+    fn emit(&mut self, op: Op, span: &Span) {
+        self.code.push(op);
+        self.spans.push(span.clone());
+    }
+
+    /// Emits a jump whose target is set later by [`Compiler::patch`], returning its index.
+    fn jump(&mut self, op: fn(u32) -> Op, span: &Span) -> usize {
+        self.emit(op(0), span);
+        self.code.len() - 1
+    }
+
+    /// Points the jump at index `jump` to the next op to be emitted.
+    fn patch(&mut self, jump: usize) {
+        let target = self.code.len() as u32;
+        self.code[jump] = match self.code[jump] {
+            Op::Jump(_) => Op::Jump(target),
+            _ => Op::JumpIfZero(target),
+        };
+    }
+
+    fn fail(&mut self, error: Diagnostic, span: &Span) {
+        self.bytecode.errors.push(error);
+        let index = self.bytecode.errors.len() as u32 - 1;
+        self.emit(Op::Fail(index), span);
+    }
+
+    fn declare(&mut self, name: &'a str) -> u32 {
+        let slot = self.slots;
+        self.slots += 1;
+        self.vars.push((name, slot));
+        slot
+    }
+
+    fn lookup(&self, name: &str) -> Option<Place> {
+        match self.vars.iter().rev().find(|(var, _)| *var == name) {
+            Some((_, slot)) => Some(Place::Slot(*slot)),
+            None => self
+                .globals
+                .iter()
+                .rposition(|global| *global == name)
+                .map(|global| Place::Global(global as u32)),
+        }
+    }
+
+    /// Compiles pushing a copy of the value of the variable in `place`.
+    fn load(&mut self, place: Place, span: &Span) {
+        match place {
+            Place::Slot(slot) => self.emit(Op::Load(slot), span),
+            Place::Global(global) => {
+                self.emit(Op::Global(global), span);
+                self.emit(Op::Deref, span);
+            }
+        }
+    }
+
+    /// Compiles a statement, returning whether it always leaves the function.
+    fn statement(&mut self, (statement, span): &'a Spanned<Statement>) -> bool {
+        match statement {
+            Statement::Invalid => {
+                let error = ast::runtime_error(span)(Message::new("E0202.invalid-statement"));
+                self.fail(error, span);
+                true
+            }
+            Statement::Expand { name, .. } => {
+                let error = ast::runtime_error(span)(
+                    Message::new("E0202.unexpanded-macro").arg("name", name.as_str()),
+                );
+                self.fail(error, span);
+                true
+            }
+            Statement::Inline { lang, .. } => {
+                let error = ast::runtime_error(span)(
+                    Message::new("E0202.inline-code").arg("block", lang.keyword()),
+                )
+                .with_note(Message::new("note.inline-backends"));
+                self.fail(error, span);
+                true
+            }
+            Statement::Return(expr) => {
+                self.expr(expr);
+                self.convert(self.ret, expr);
+                self.emit(Op::Return, span);
+                true
+            }
+            Statement::ReturnVoid => {
+                // a void function's value is never used, so any will do
+                self.emit(Op::Int(0), span);
+                self.emit(Op::Return, span);
+                true
+            }
+            Statement::Expr(expr) => {
+                self.expr(expr);
+                self.emit(Op::Pop, span);
+                false
+            }
+            Statement::Assign { ty, name, expr } => {
+                self.expr(expr);
+                self.convert(ty, expr);
+                let slot = self.declare(name);
+                self.emit(Op::Store(slot), span);
+                false
+            }
+            Statement::Array { ty, name, len } => {
+                if types::is_float(ty) {
+                    self.emit(Op::FloatArray(*len), span);
+                } else {
+                    let string = ty == "string";
+                    self.emit(Op::Array { len: *len, string }, span);
+                }
+                let slot = self.declare(name);
+                self.emit(Op::Store(slot), span);
+                false
+            }
+            Statement::Store { name, index, expr } => {
+                let place = self.lookup(name);
+                if let Some(Place::Global(global)) = place {
+                    self.emit(Op::Global(global), span);
+                }
+                self.int_operand(index);
+                self.expr(expr);
+                match place {
+                    Some(Place::Slot(slot)) => self.emit(Op::StoreElement(slot), &index.1),
+                    Some(Place::Global(_)) => self.emit(Op::StoreElementDeref, &index.1),
+                    None => self.fail(ast::undeclared_variable(name, span), span),
+                }
+                false
+            }
+            Statement::Reassign { name, op, expr } => {
+                let Some(place) = self.lookup(name) else {
+                    if *op == AssignOp::Set {
+                        self.expr(expr);
+                    }
+                    self.fail(ast::undeclared_variable(name, span), span);
+                    return false;
+                };
+                if let Place::Global(global) = place {
+                    self.emit(Op::Global(global), span);
+                }
+                match op {
+                    AssignOp::Set => self.expr(expr),
+                    AssignOp::Add => {
+                        self.load(place, span);
+                        self.expr(expr);
+                        self.emit(Op::Add, span);
+                    }
+                    AssignOp::Sub | AssignOp::Mul | AssignOp::Div => {
+                        self.load(place, span);
+                        self.emit(Op::AsNumber, span);
+                        self.number_operand(expr);
+                        let op = match op {
+                            AssignOp::Sub => Op::Sub,
+                            AssignOp::Mul => Op::Mul,
+                            _ => Op::Div,
+                        };
+                        self.emit(op, span);
+                    }
+                }
+                match place {
+                    Place::Slot(slot) => self.emit(Op::Reassign(slot), span),
+                    // storing through a pointer converts the value just as reassigning does
+                    Place::Global(_) => self.emit(Op::StoreDeref, span),
+                }
+                false
+            }
+            Statement::Write { pointer, expr } => {
+                self.pointer_operand(pointer);
+                self.expr(expr);
+                self.emit(Op::StoreDeref, span);
+                false
+            }
+            Statement::Switch { expr, cases } => self.switch(expr, cases, span),
+            Statement::Break if self.breaks.is_empty() => {
+                let error = ast::runtime_error(span)(Message::new("E0202.break"));
+                self.fail(error, span);
+                true
+            }
+            Statement::Break => {
+                let jump = self.jump(Op::Jump, span);
+                self.breaks.last_mut().unwrap().push(jump);
+                true
+            }
+            Statement::Continue => {
+                let error = ast::runtime_error(span)(Message::new("E0202.continue"));
+                self.fail(error, span);
+                true
+            }
+        }
+    }
+
+    /// Compiles a switch, which compares the int in a slot of its own with each case's value and
+    /// jumps to the first case that matches, or else to `default` or the end. The bodies of the
+    /// cases follow in order, so that one that doesn't `break` falls through to the next.
+    ///
+    /// Returns whether it always leaves the function, which it does if every case is reached
+    /// from `default` and runs into a `return` without a `break`.
+    fn switch(&mut self, expr: &'a Spanned<Expr>, cases: &'a [ast::Case], span: &Span) -> bool {
+        self.int_operand(expr);
+        let value = self.slots;
+        self.slots += 1;
+        self.emit(Op::Store(value), span);
+
+        let mut dispatch = Vec::new();
+        for case in cases {
+            if let Some(label) = case.value {
+                self.emit(Op::Load(value), &case.span);
+                self.emit(Op::Int(label), &case.span);
+                self.emit(Op::Ne, &case.span);
+                dispatch.push(Some(self.jump(Op::JumpIfZero, &case.span)));
+            } else {
+                dispatch.push(None);
+            }
+        }
+        let otherwise = self.jump(Op::Jump, span);
+
+        let vars = self.vars.len();
+        self.breaks.push(Vec::new());
+        let mut leaves = false;
+        for (case, jump) in cases.iter().zip(dispatch) {
+            match jump {
+                Some(jump) => self.patch(jump),
+                None => self.patch(otherwise),
+            }
+            leaves = false;
+            for statement in &case.body {
+                if self.statement(statement) {
+                    leaves = true;
+                    break;
+                }
+            }
+        }
+        let breaks = self.breaks.pop().unwrap();
+        self.vars.truncate(vars);
+
+        let default = cases.iter().any(|case| case.value.is_none());
+        if !default {
+            self.patch(otherwise);
+        }
+        for jump in &breaks {
+            self.patch(*jump);
+        }
+        default && breaks.is_empty() && leaves
+    }
+
+    fn expr(&mut self, (expr, span): &'a Spanned<Expr>) {
+        match expr {
+            Expr::Err => {
+                let error = ast::runtime_error(span)(Message::new("E0202.invalid-expression"));
+                self.fail(error, span);
+            }
+            Expr::Int(value) => self.emit(Op::Int((*value).into()), span),
+            Expr::Spawn { .. } | Expr::Join(_) => {
+                let error = ast::runtime_error(span)(Message::new("E0202.vm-threads"));
+                self.fail(error, span);
+            }
+            Expr::SizeOf(ty) => match self.types.size_of(ty) {
+                Ok(size) => self.emit(Op::Int(size.into()), span),
+                Err(error) => {
+                    let error = error.with_label(span.clone(), Message::new("label.sized-here"));
+                    self.fail(error, span);
+                }
+            },
+            Expr::Str(value) => self.constant(Value::Str(value.clone()), span),
+            Expr::Float(value) => self.constant(Value::Float(*value), span),
+            Expr::Neg(inner) => {
+                // `-2147483648` is a 32-bit int, though `2147483648` isn't
+                if let Expr::Int(value) = inner.0 {
+                    self.emit(Op::Int(-i64::from(value)), span);
+                    return;
+                }
+                self.number_operand(inner);
+                self.emit(Op::Neg, span);
+            }
+            Expr::Not(inner) => {
+                self.int_operand(inner);
+                self.emit(Op::Not, span);
+            }
+            Expr::PreInc(name) | Expr::PreDec(name) => {
+                let Some(place) = self.lookup(name) else {
+                    self.fail(ast::undeclared_variable(name, span), span);
+                    return;
+                };
+                if let Place::Global(global) = place {
+                    self.emit(Op::Global(global), span);
+                }
+                self.load(place, span);
+                self.emit(Op::AsInt, span);
+                self.emit(Op::Int(1), span);
+                match expr {
+                    Expr::PreInc(_) => self.emit(Op::Add, span),
+                    _ => self.emit(Op::Sub, span),
+                }
+                match place {
+                    Place::Slot(slot) => self.emit(Op::Store(slot), span),
+                    Place::Global(_) => self.emit(Op::StoreDeref, span),
+                }
+                self.load(place, span);
+            }
+            Expr::Add(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.emit(Op::Add, span);
+            }
+            Expr::Sub(lhs, rhs)
+            | Expr::Mul(lhs, rhs)
+            | Expr::Div(lhs, rhs)
+            | Expr::Rem(lhs, rhs)
+            | Expr::BitAnd(lhs, rhs)
+            | Expr::BitOr(lhs, rhs)
+            | Expr::BitXor(lhs, rhs)
+            | Expr::Shl(lhs, rhs)
+            | Expr::Shr(lhs, rhs)
+            | Expr::Lt(lhs, rhs)
+            | Expr::Le(lhs, rhs)
+            | Expr::Gt(lhs, rhs)
+            | Expr::Ge(lhs, rhs)
+            | Expr::Eq(lhs, rhs)
+            | Expr::Ne(lhs, rhs) => {
+                // `%`, the bitwise operators and the shifts only take ints
+                if !matches!(
+                    expr,
+                    Expr::Rem(..)
+                        | Expr::BitAnd(..)
+                        | Expr::BitOr(..)
+                        | Expr::BitXor(..)
+                        | Expr::Shl(..)
+                        | Expr::Shr(..)
+                ) {
+                    self.number_operand(lhs);
+                    self.number_operand(rhs);
+                } else {
+                    self.int_operand(lhs);
+                    self.int_operand(rhs);
+                }
+                let op = match expr {
+                    Expr::Sub(..) => Op::Sub,
+                    Expr::Mul(..) => Op::Mul,
+                    Expr::Div(..) => Op::Div,
+                    Expr::Rem(..) => Op::Rem,
+                    Expr::BitAnd(..) => Op::BitAnd,
+                    Expr::BitOr(..) => Op::BitOr,
+                    Expr::BitXor(..) => Op::BitXor,
+                    Expr::Shl(..) => Op::Shl,
+                    Expr::Shr(..) => Op::Shr,
+                    Expr::Lt(..) => Op::Lt,
+                    Expr::Le(..) => Op::Le,
+                    Expr::Gt(..) => Op::Gt,
+                    Expr::Ge(..) => Op::Ge,
+                    Expr::Eq(..) => Op::Eq,
+                    _ => Op::Ne,
+                };
+                self.emit(op, span);
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                self.int_operand(cond);
+                let to_otherwise = self.jump(Op::JumpIfZero, span);
+                self.expr(then);
+                let to_end = self.jump(Op::Jump, span);
+                self.patch(to_otherwise);
+                self.expr(otherwise);
+                self.patch(to_end);
+            }
+            Expr::And(lhs, rhs) => {
+                self.int_operand(lhs);
+                let to_false = self.jump(Op::JumpIfZero, span);
+                self.truth(rhs, span);
+                let to_end = self.jump(Op::Jump, span);
+                self.patch(to_false);
+                self.emit(Op::Int(0), span);
+                self.patch(to_end);
+            }
+            Expr::Or(lhs, rhs) => {
+                self.int_operand(lhs);
+                let to_rhs = self.jump(Op::JumpIfZero, span);
+                self.emit(Op::Int(1), span);
+                let to_end = self.jump(Op::Jump, span);
+                self.patch(to_rhs);
+                self.truth(rhs, span);
+                self.patch(to_end);
+            }
+            Expr::Var(name) => match self.lookup(name) {
+                Some(place) => self.load(place, span),
+                None => self.fail(ast::undeclared_variable(name, span), span),
+            },
+            Expr::AddrOf(name) => match self.lookup(name) {
+                Some(Place::Slot(slot)) => self.emit(Op::AddrOf(slot), span),
+                Some(Place::Global(global)) => self.emit(Op::Global(global), span),
+                None => self.fail(ast::undeclared_variable(name, span), span),
+            },
+            Expr::Deref(pointer) => {
+                self.pointer_operand(pointer);
+                self.emit(Op::Deref, span);
+            }
+            Expr::Index(array, index) => {
+                // a variable can't change while the index is evaluated, so its element can be
+                // read in place rather than from a copy of the whole array
+                if let (Expr::Var(name), _) = &**array {
+                    if let Some(Place::Slot(slot)) = self.lookup(name) {
+                        self.int_operand(index);
+                        self.emit(Op::LoadElement(slot), span);
+                        return;
+                    }
+                }
+                self.expr(array);
+                self.int_operand(index);
+                self.emit(Op::Index, span);
+            }
+            Expr::Call { name, params } => {
+                if self.builtins.contains(name) {
+                    params.iter().for_each(|param| self.expr(param));
+                    let builtin = match self.bytecode.builtins.iter().position(|b| b == name) {
+                        Some(builtin) => builtin,
+                        None => {
+                            self.bytecode.builtins.push(name.to_string());
+                            self.bytecode.builtins.len() - 1
+                        }
+                    };
+                    let argc = params.len() as u32;
+                    self.emit(
+                        Op::Builtin {
+                            builtin: builtin as u32,
+                            argc,
+                        },
+                        span,
+                    );
+                    return;
+                }
+
+                match self.funcs.get(name.as_str()) {
+                    None => {
+                        let error = ast::runtime_error(span)(
+                            Message::new("unknown-function").arg("name", name.as_str()),
+                        );
+                        self.fail(error, span);
+                    }
+                    Some((_, func)) if func.params.len() != params.len() => {
+                        self.fail(ast::arity_error(func, params.len(), span), span)
+                    }
+                    Some((index, func)) => {
+                        self.emit(Op::CheckDepth(*index), span);
+                        for (arg, param) in params.iter().zip(&func.params) {
+                            self.expr(arg);
+                            self.convert(&param.ty, arg);
+                        }
+                        self.emit(Op::Call(*index), span);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Compiles an expression whose value must be an int, failing at its span if it isn't.
+    fn int_operand(&mut self, expr: &'a Spanned<Expr>) {
+        self.expr(expr);
+        if !semantics::is_int(&expr.0) {
+            self.emit(Op::AsInt, &expr.1);
+        }
+    }
+
+    /// Compiles an expression whose value must be an int or a float, failing at its span if it
+    /// isn't.
+    fn number_operand(&mut self, expr: &'a Spanned<Expr>) {
+        self.expr(expr);
+        if !semantics::is_int(&expr.0) && !matches!(expr.0, Expr::Float(_)) {
+            self.emit(Op::AsNumber, &expr.1);
+        }
+    }
+
+    /// Converts the value of `expr`, just compiled, for a variable or return value of type `ty`.
+    fn convert(&mut self, ty: &str, expr: &Spanned<Expr>) {
+        if types::is_float(ty) {
+            self.emit(Op::ToFloat, &expr.1);
+        } else if types::is_int(ty) && !semantics::is_int(&expr.0) {
+            self.emit(Op::ToInt, &expr.1);
+        }
+    }
+
+    /// Compiles pushing a copy of `value`, kept in [`Bytecode::consts`].
+    fn constant(&mut self, value: Value, span: &Span) {
+        self.bytecode.consts.push(value);
+        let index = self.bytecode.consts.len() as u32 - 1;
+        self.emit(Op::Const(index), span);
+    }
+
+    /// Compiles the int `expr` to 1 if it's nonzero and 0 if it isn't, for the `&&` or `||` at
+    /// `span`.
+    fn truth(&mut self, expr: &'a Spanned<Expr>, span: &Span) {
+        self.int_operand(expr);
+        self.emit(Op::Int(0), span);
+        self.emit(Op::Ne, span);
+    }
+
+    /// Compiles an expression whose value must be a pointer, failing at its span if it isn't.
+    fn pointer_operand(&mut self, expr: &'a Spanned<Expr>) {
+        self.expr(expr);
+        if !matches!(expr.0, Expr::AddrOf(_)) {
+            self.emit(Op::AsPointer, &expr.1);
+        }
+    }
+}
+
+/// Where a variable is kept.
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    /// A slot in the frame of the function
+    Slot(u32),
+    /// A global, at this index of [`Compiler::globals`]
+    Global(u32),
+}
+
+/// A call in progress.
+struct Frame {
+    func: usize,
+    /// The next op to run, or for a caller, the op after its call
+    pc: usize,
+    /// Where the function's slots start on the stack
+    base: usize,
+    /// The number of the call, counting every call the program has made, which tells a pointer
+    /// to one of its slots from a pointer to a call that's returned
+    call: usize,
+}
+
+impl Bytecode {
+    /// Runs the function at index `main` with `args` as its parameters, after setting the
+    /// globals, returning its value.
+    pub fn run(
+        &self,
+        main: usize,
+        args: Vec<Value>,
+        options: &RunOptions,
+    ) -> Result<Value, Diagnostic> {
+        self.trace(main, args, options, |_, _, _| {})
+    }
+
+    /// Like [`Bytecode::run`], but calls `step` after each op that doesn't fail with the op, its
+    /// index in its function, and the values the function running now has pushed that are still
+    /// on the stack, bottom first.
+    pub fn trace(
+        &self,
+        main: usize,
+        args: Vec<Value>,
+        options: &RunOptions,
+        mut step: impl FnMut(usize, Op, &[Value]),
+    ) -> Result<Value, Diagnostic> {
+        let builtins = self
+            .builtins
+            .iter()
+            .map(|name| options.builtins.get(name).unwrap())
+            .collect::<Vec<&BuiltinFn>>();
+
+        // the globals are set by a call of their own at the bottom of the stack, which `main`
+        // is called on top of once it returns
+        let mut main = Some((main, args));
+        let mut stack = Vec::new();
+        let mut frame = Frame {
+            func: self.init.unwrap_or(usize::MAX),
+            pc: 0,
+            base: 0,
+            call: 0,
+        };
+        match self.init {
+            Some(init) => stack.resize(self.funcs[init].slots, Value::Int(0)),
+            None => frame = self.enter(main.take().unwrap(), &mut stack, 0),
+        }
+        let mut callers = Vec::<Frame>::new();
+        let mut next_call = 1;
+
+        loop {
+            let func = &self.funcs[frame.func];
+            let (at, op) = (frame.pc, func.code[frame.pc]);
+            frame.pc += 1;
+
+            // fails at the span of the current op, with a backtrace of the calls in progress
+            let fail = |error: Diagnostic, callers: &[Frame], frame: &Frame| {
+                let mut calls = Vec::new();
+                for (caller, callee) in callers.iter().zip(callers.iter().skip(1).chain([frame])) {
+                    let span = self.funcs[caller.func].spans[caller.pc - 1].clone();
+                    calls.push((self.funcs[callee.func].name.as_str(), span));
+                }
+                Err(ast::backtrace(&calls, error))
+            };
+            let span = &func.spans[frame.pc - 1];
+
+            match op {
+                Op::Int(value) => match options.ints.literal(value) {
+                    Ok(value) => stack.push(Value::Int(value)),
+                    Err(e) => return fail(ast::arith_error(span)(e), &callers, &frame),
+                },
+                Op::Const(index) => stack.push(self.consts[index as usize].clone()),
+                Op::Load(slot) => stack.push(stack[frame.base + slot as usize].clone()),
+                Op::Store(slot) => {
+                    let value = stack.pop().unwrap();
+                    stack[frame.base + slot as usize] = value;
+                }
+                Op::Array { len, string } => {
+                    let elem = match string {
+                        true => Value::Str(String::new()),
+                        false => Value::Int(0),
+                    };
+                    stack.push(Value::Array(vec![elem; len as usize]));
+                }
+                Op::AsInt => {
+                    if let Err(e) = stack.last().unwrap().as_int() {
+                        return fail(ast::runtime_error(span)(e), &callers, &frame);
+                    }
+                }
+                Op::Neg => match stack.pop().unwrap() {
+                    Value::Float(value) => stack.push(Value::Float(-value)),
+                    value => match options.ints.neg(int(value)) {
+                        Ok(value) => stack.push(Value::Int(value)),
+                        Err(e) => return fail(ast::arith_error(span)(e), &callers, &frame),
+                    },
+                },
+                Op::Not => {
+                    let value = int(stack.pop().unwrap());
+                    stack.push(Value::Int((value == 0) as i64));
+                }
+                Op::Add => {
+                    let rhs = stack.pop().unwrap();
+                    let lhs = stack.pop().unwrap();
+                    match lhs.add(rhs, options.ints) {
+                        Ok(value) => stack.push(value),
+                        Err(e) => return fail(ast::runtime_error(span)(e), &callers, &frame),
+                    }
+                }
+                Op::Sub
+                | Op::Mul
+                | Op::Div
+                | Op::Rem
+                | Op::BitAnd
+                | Op::BitOr
+                | Op::BitXor
+                | Op::Shl
+                | Op::Shr
+                | Op::Lt
+                | Op::Le
+                | Op::Gt
+                | Op::Ge
+                | Op::Eq
+                | Op::Ne => {
+                    let rhs = stack.pop().unwrap();
+                    let lhs = stack.pop().unwrap();
+                    let op = bin_op(op);
+                    let value = match Value::float_binary(op, &lhs, &rhs) {
+                        Some(value) => Ok(value),
+                        None => options.ints.binary(op, int(lhs), int(rhs)).map(Value::Int),
+                    };
+                    match value {
+                        Ok(value) => stack.push(value),
+                        Err(e) => return fail(ast::arith_error(span)(e), &callers, &frame),
+                    }
+                }
+                Op::Jump(target) => frame.pc = target as usize,
+                Op::JumpIfZero(target) => {
+                    if int(stack.pop().unwrap()) == 0 {
+                        frame.pc = target as usize;
+                    }
+                }
+                Op::Index => {
+                    let index = int(stack.pop().unwrap());
+                    let array = stack.pop().unwrap();
+                    match array.element(index) {
+                        Ok(value) => stack.push(value.clone()),
+                        Err(e) => return fail(ast::runtime_error(span)(e), &callers, &frame),
+                    }
+                }
+                Op::LoadElement(slot) => {
+                    let index = int(stack.pop().unwrap());
+                    match stack[frame.base + slot as usize].element(index) {
+                        Ok(value) => stack.push(value.clone()),
+                        Err(e) => return fail(ast::runtime_error(span)(e), &callers, &frame),
+                    }
+                }
+                Op::StoreElement(slot) => {
+                    let value = stack.pop().unwrap();
+                    let index = int(stack.pop().unwrap());
+                    match stack[frame.base + slot as usize].element_mut(index) {
+                        Ok(element) => *element = value.convert_like(element, options.ints),
+                        Err(e) => return fail(ast::runtime_error(span)(e), &callers, &frame),
+                    }
+                }
+                Op::AddrOf(slot) => stack.push(Value::Pointer(Pointer {
+                    slot: frame.base + slot as usize,
+                    call: frame.call,
+                })),
+                Op::AsPointer => {
+                    if let Err(e) = stack.last().unwrap().as_pointer() {
+                        return fail(ast::runtime_error(span)(e), &callers, &frame);
+                    }
+                }
+                Op::Deref | Op::StoreDeref => {
+                    let value = match op {
+                        Op::StoreDeref => stack.pop(),
+                        _ => None,
+                    };
+                    let pointer = pointer(stack.pop().unwrap());
+                    let Some(slot) = self.slot(pointer, &callers, &frame) else {
+                        let error =
+                            ast::runtime_error(span)(Message::new("E0202.dangling-pointer"));
+                        return fail(error, &callers, &frame);
+                    };
+                    match value {
+                        Some(value) => stack[slot] = value.convert_like(&stack[slot], options.ints),
+                        None => stack.push(stack[slot].clone()),
+                    }
+                }
+                Op::CheckDepth(callee) => {
+                    // programs only loop by recursing, so a program that's still running ops
+                    // reaches a call soon enough to stop at it
+                    if let Err(error) = options.interrupt.check(span) {
+                        return fail(error, &callers, &frame);
+                    }
+                    if callers.len() >= options.max_call_depth {
+                        let name = &self.funcs[callee as usize].name;
+                        let error = ast::stack_overflow(name, span, options.max_call_depth);
+                        return fail(error, &callers, &frame);
+                    }
+                }
+                Op::Call(callee) => {
+                    let callee = callee as usize;
+                    let base = stack.len() - self.funcs[callee].params;
+                    stack.resize(base + self.funcs[callee].slots, Value::Int(0));
+                    let caller = std::mem::replace(
+                        &mut frame,
+                        Frame {
+                            func: callee,
+                            pc: 0,
+                            base,
+                            call: next_call,
+                        },
+                    );
+                    next_call += 1;
+                    callers.push(caller);
+                }
+                Op::Builtin { builtin, argc } => {
+                    let start = stack.len() - argc as usize;
+                    let result = builtins[builtin as usize](&stack[start..]);
+                    stack.truncate(start);
+                    match result {
+                        Ok(value) => stack.push(value),
+                        Err(e) => return fail(ast::runtime_error(span)(e), &callers, &frame),
+                    }
+                }
+                Op::Return => {
+                    let value = stack.pop().unwrap();
+                    match callers.pop() {
+                        Some(caller) => {
+                            stack.truncate(frame.base);
+                            frame = caller;
+                            stack.push(value);
+                        }
+                        None => {
+                            step(at, op, &[]);
+                            match main.take() {
+                                Some(main) => {
+                                    frame = self.enter(main, &mut stack, next_call);
+                                    next_call += 1;
+                                    continue;
+                                }
+                                None => return Ok(value),
+                            }
+                        }
+                    }
+                }
+                Op::Pop => {
+                    stack.pop();
+                }
+                Op::Fail(index) => {
+                    let error = self.errors[index as usize].clone();
+                    return fail(error, &callers, &frame);
+                }
+                Op::AsNumber => {
+                    let value = stack.last().unwrap();
+                    if !matches!(value, Value::Float(_)) {
+                        if let Err(e) = value.as_int() {
+                            return fail(ast::runtime_error(span)(e), &callers, &frame);
+                        }
+                    }
+                }
+                Op::ToFloat => {
+                    if let Some(Value::Int(value)) = stack.last() {
+                        *stack.last_mut().unwrap() = Value::Float(*value as f64);
+                    }
+                }
+                Op::ToInt => {
+                    if let Some(Value::Float(value)) = stack.last() {
+                        *stack.last_mut().unwrap() = Value::Int(options.ints.truncate(*value));
+                    }
+                }
+                Op::Reassign(slot) => {
+                    let value = stack.pop().unwrap();
+                    let var = &mut stack[frame.base + slot as usize];
+                    *var = value.convert_like(var, options.ints);
+                }
+                Op::FloatArray(len) => {
+                    stack.push(Value::Array(vec![Value::Float(0.0); len as usize]));
+                }
+                Op::Global(global) => stack.push(Value::Pointer(Pointer {
+                    slot: global as usize,
+                    call: 0,
+                })),
+                Op::StoreElementDeref => {
+                    let value = stack.pop().unwrap();
+                    let index = int(stack.pop().unwrap());
+                    let pointer = pointer(stack.pop().unwrap());
+                    let Some(slot) = self.slot(pointer, &callers, &frame) else {
+                        let error =
+                            ast::runtime_error(span)(Message::new("E0202.dangling-pointer"));
+                        return fail(error, &callers, &frame);
+                    };
+                    match stack[slot].element_mut(index) {
+                        Ok(element) => *element = value.convert_like(element, options.ints),
+                        Err(e) => return fail(ast::runtime_error(span)(e), &callers, &frame),
+                    }
+                }
+            }
+            step(at, op, &stack[frame.base + self.funcs[frame.func].slots..]);
+        }
+    }
+
+    /// Pushes the slots of the function `main`, with `args` as its parameters, on top of the
+    /// globals on `stack`, returning the frame of its call numbered `call`.
+    fn enter(
+        &self,
+        (main, args): (usize, Vec<Value>),
+        stack: &mut Vec<Value>,
+        call: usize,
+    ) -> Frame {
+        let base = stack.len();
+        stack.extend(args);
+        stack.resize(base + self.funcs[main].slots, Value::Int(0));
+        Frame {
+            func: main,
+            pc: 0,
+            base,
+            call,
+        }
+    }
+
+    /// The number of globals, which are the first slots on the stack.
+    fn globals(&self) -> usize {
+        self.init.map_or(0, |init| self.funcs[init].slots)
+    }
+
+    /// The index on the stack of the slot `pointer` points to, if the call it belongs to hasn't
+    /// returned.
+    fn slot(&self, pointer: Pointer, callers: &[Frame], frame: &Frame) -> Option<usize> {
+        // the globals live as long as the program, even once the call that set them returns
+        if pointer.call == 0 && pointer.slot < self.globals() {
+            return Some(pointer.slot);
+        }
+        let owner = [frame]
+            .into_iter()
+            .chain(callers.iter().rev())
+            .find(|frame| frame.base <= pointer.slot)?;
+        let slots = owner.base + self.funcs[owner.func].slots;
+        (owner.call == pointer.call && pointer.slot < slots).then_some(pointer.slot)
+    }
+}
+
+/// The operator of a binary op.
+fn bin_op(op: Op) -> BinOp {
+    match op {
+        Op::Sub => BinOp::Sub,
+        Op::Mul => BinOp::Mul,
+        Op::Div => BinOp::Div,
+        Op::Rem => BinOp::Rem,
+        Op::BitAnd => BinOp::And,
+        Op::BitOr => BinOp::Or,
+        Op::BitXor => BinOp::Xor,
+        Op::Shl => BinOp::Shl,
+        Op::Shr => BinOp::Shr,
+        Op::Lt => BinOp::Lt,
+        Op::Le => BinOp::Le,
+        Op::Gt => BinOp::Gt,
+        Op::Ge => BinOp::Ge,
+        Op::Eq => BinOp::Eq,
+        Op::Ne => BinOp::Ne,
+        other => unreachable!("{other} isn't a binary op"),
+    }
+}
+
+/// An operand the compiler has already checked is a pointer.
+fn pointer(value: Value) -> Pointer {
+    match value {
+        Value::Pointer(pointer) => pointer,
+        other => unreachable!("expected a pointer operand, found {}", other.type_name()),
+    }
+}
+
+/// An operand the compiler has already checked is an int.
+fn int(value: Value) -> i64 {
+    match value {
+        Value::Int(value) => value,
+        other => unreachable!("expected an int operand, found {}", other.type_name()),
+    }
+}
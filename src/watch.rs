@@ -0,0 +1,74 @@
+//! Watching files for `build --watch` and `run --watch`, which build or run the program again
+//! whenever one of its files changes.
+//!
+//! Files are watched by polling their modification times, which also notices a file that's
+//! created or removed. A directory's modification time changes when a file is added to it or
+//! removed from it, so watching a directory notices new source files in it too.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// How often the watched files are checked for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long to wait after a change before reporting it, so that an editor that saves a file in
+/// several writes has finished before it's read.
+pub const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+/// The modification times of a set of files, as they were when it was created.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Watcher {
+    /// Watches `paths`, each of which may be a file or directory, or not exist yet.
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut watcher = Self { files: Vec::new() };
+        for path in paths {
+            watcher.add(path);
+        }
+        watcher
+    }
+
+    /// Watches `path` too, from how it is now, unless it's already watched.
+    pub fn add(&mut self, path: PathBuf) {
+        if self.files.iter().all(|(file, _)| *file != path) {
+            let modified = modified(&path);
+            self.files.push((path, modified));
+        }
+    }
+
+    /// The watched files, in the order they were first given.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+
+    /// The first watched file that's been modified, created or removed since it started being
+    /// watched.
+    pub fn changed(&self) -> Option<&Path> {
+        self.files
+            .iter()
+            .find(|(path, modified)| self::modified(path) != *modified)
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// Blocks until a watched file changes, returning it.
+    pub fn wait(&self) -> &Path {
+        loop {
+            if let Some(path) = self.changed() {
+                thread::sleep(SETTLE_TIME);
+                return path;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
@@ -0,0 +1,287 @@
+//! Tests reading IR files, which may have been crafted to exhaust the reader.
+
+use std::fs;
+
+use crust::{
+    artifact::{Artifact, COMPILER_VERSION, FORMAT_VERSION, LIMITS},
+    binary,
+    ir::schema,
+    pipeline,
+    typed::{self, Callee, Expr, Stmt, Type},
+    Program, RunOptions, Value,
+};
+
+fn artifact(source: &str) -> Artifact {
+    Artifact::new(&pipeline::compile(source, "main.c").unwrap().ast).unwrap()
+}
+
+fn error(bytes: &[u8]) -> String {
+    Artifact::read(bytes).unwrap_err().message.to_string()
+}
+
+#[test]
+fn reads_long_expressions() {
+    let sum = vec!["1"; 100].join(" + ");
+    let artifact = artifact(&format!("int main() {{ return {sum}; }}"));
+    for bytes in [artifact.to_json(), artifact.to_binary()] {
+        let read = Artifact::read(&bytes).unwrap();
+        assert_eq!(read.program.to_ast().run_main(&[]).unwrap(), 100);
+    }
+}
+
+#[test]
+fn rejects_deep_nesting() {
+    let json = String::from_utf8(artifact("int main() { return 1; }").to_json()).unwrap();
+    let deep = "[".repeat(100_000) + &"]".repeat(100_000);
+    let json = json.replacen("\"funcs\": [", &format!("\"funcs\": [{deep},"), 1);
+    assert!(error(json.as_bytes()).contains("nested too deeply"));
+
+    // brackets inside strings aren't nesting
+    let brackets = "[{".repeat(LIMITS.nesting);
+    let strings = artifact(&format!(
+        "int main() {{ string s = \"{brackets}\\\"\"; return 0; }}"
+    ));
+    Artifact::read(&strings.to_json()).unwrap();
+
+    // writing the sum recurses once for each of its operators, which needs more than the stack
+    // of a test thread in unoptimized builds
+    let sum = vec!["1"; LIMITS.nesting].join(" + ");
+    let binary = std::thread::Builder::new()
+        .stack_size(32 << 20)
+        .spawn(move || artifact(&format!("int main() {{ return {sum}; }}")).to_binary())
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(error(&binary).contains("nested too deeply"));
+}
+
+#[test]
+fn rejects_programs_over_limits() {
+    let mut long = artifact("int main() { return 0; }");
+    let main = &mut long.program.funcs[0];
+    let statement = main.body[0].clone();
+    main.body = vec![statement; LIMITS.statements + 1];
+    assert!(error(&long.to_binary()).contains("function main has more than"));
+
+    let name = "x".repeat(LIMITS.name_len + 1);
+    let named = artifact(&format!("int main() {{ int {name} = 1; return {name}; }}"));
+    assert!(error(&named.to_json()).contains("a name is longer than"));
+
+    let mut huge = artifact("int main() { int a[4]; return 0; }");
+    let Type::Array { len, .. } = &mut huge.program.funcs[0].slots[0].ty else {
+        unreachable!()
+    };
+    *len = u32::MAX;
+    assert!(error(&huge.to_binary()).contains("array a has more than"));
+}
+
+#[test]
+fn loads_programs_to_call_repeatedly() {
+    let dir = std::env::temp_dir().join(format!("crust-artifact-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = "int fib(int n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); }
+string greet(string name, int times) { return times > 0 ? name + greet(name, times - 1) : \"\"; }
+int main() { return fib(10); }";
+    fs::write(dir.join("main.c"), source).unwrap();
+    fs::write(dir.join("main.bin"), artifact(source).to_binary()).unwrap();
+
+    for path in ["main.c", "main.bin"] {
+        let program = Program::load(dir.join(path)).unwrap();
+        for (n, fib) in [(0, 0), (1, 1), (10, 55), (20, 6765)] {
+            assert_eq!(program.call("fib", &[n]).unwrap(), Value::Int(fib));
+        }
+        assert_eq!(
+            program
+                .call("greet", &[Value::from("ab"), Value::Int(3)])
+                .unwrap(),
+            Value::from("ababab")
+        );
+
+        // a call allowed to nest deeper than the ones before it is given the stack for it
+        let args = [Value::from("a"), Value::Int(3000)];
+        assert!(program.call("greet", &args).is_err());
+        let options = RunOptions {
+            max_call_depth: 5000,
+            ..RunOptions::default()
+        };
+        assert_eq!(
+            program.call_with(options, "greet", &args).unwrap(),
+            Value::from("a".repeat(3000))
+        );
+
+        let error = program.call("fib", &[1, 2]).unwrap_err();
+        assert_eq!(
+            error.message.to_string(),
+            "function fib takes 1 arguments but 2 were supplied"
+        );
+        let error = program.call::<i32>("missing", &[]).unwrap_err();
+        assert_eq!(error.message.to_string(), "unknown function missing");
+    }
+
+    let errors = Program::load(dir.join("missing.bin")).unwrap_err();
+    assert!(errors[0].message.to_string().starts_with("failed to load"));
+}
+
+#[test]
+fn resolves_names_to_indices() {
+    let artifact = artifact(
+        "int twice(int* p) { *p = *p * 2; return *p; }
+        int main(int x) {
+            string s = \"a\";
+            int x = x + 1;
+            twice(&x);
+            println(s);
+            return x;
+        }",
+    );
+    let program = &artifact.program;
+    let main = &program.funcs[1];
+    assert_eq!(main.params, 1);
+    let slots = main
+        .slots
+        .iter()
+        .map(|var| (var.name.as_str(), var.ty.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        slots,
+        [("x", Type::Int), ("s", Type::Str), ("x", Type::Int)]
+    );
+    assert_eq!(
+        program.funcs[0].slots[0].ty,
+        Type::Pointer(Box::new(Type::Int))
+    );
+
+    // the new x is declared from the old one, and every use after that is of the new one
+    let Stmt::Let { slot: 2, expr } = &main.body[1].0 else {
+        panic!("{:?}", main.body[1])
+    };
+    assert!(matches!(&expr.0, Expr::Binary(_, lhs, _) if lhs.0 == Expr::Var(0)));
+    let calls = main.body[2..4]
+        .iter()
+        .map(|(statement, _)| match statement {
+            Stmt::Expr((Expr::Call { callee, args }, _)) => (callee.clone(), args[0].0.clone()),
+            statement => panic!("{statement:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        calls,
+        [
+            (Callee::Func(0), Expr::AddrOf(2)),
+            (Callee::Extern(String::from("println")), Expr::Var(1)),
+        ]
+    );
+
+    let read = Artifact::read(&artifact.to_binary()).unwrap();
+    assert_eq!(read.program, artifact.program);
+    assert_eq!(
+        read.program
+            .to_ast()
+            .run_main(&[String::from("2")])
+            .unwrap(),
+        6
+    );
+}
+
+#[test]
+fn reads_format_version_1() {
+    let ast = pipeline::compile(
+        "int sq(int x) { return x * x; } int main() { int x = 3; return sq(x); }",
+        "main.c",
+    )
+    .unwrap()
+    .ast;
+    let json = serde_json::to_vec(&serde_json::json!({
+        "format_version": 1,
+        "compiler_version": "0.1.0",
+        "ast": ast,
+    }))
+    .unwrap();
+    let binary = binary::to_bytes(&(1u32, "0.1.0", &ast)).unwrap();
+
+    for bytes in [json, binary] {
+        let artifact = Artifact::read(&bytes).unwrap();
+        assert_eq!(artifact.format_version, FORMAT_VERSION);
+        assert_eq!(artifact.compiler_version, "0.1.0");
+        assert_eq!(artifact.program.to_ast().run_main(&[]).unwrap(), 9);
+    }
+
+    let undeclared = serde_json::to_string(&serde_json::json!({
+        "format_version": 1,
+        "compiler_version": "0.1.0",
+        "ast": ast,
+    }))
+    .unwrap()
+    .replace("\"Var\":\"x\"", "\"Var\":\"y\"");
+    assert_eq!(
+        error(undeclared.as_bytes()),
+        "Error reading C IR: in sq: y isn't declared"
+    );
+}
+
+#[test]
+fn reads_format_version_2() {
+    let artifact = artifact("int sq(int x) { return x * x; } int main() { return sq(3); }");
+    let mut json = serde_json::from_slice::<serde_json::Value>(&artifact.to_json()).unwrap();
+    json["format_version"] = 2.into();
+    json["program"].as_object_mut().unwrap().remove("globals");
+    let json = serde_json::to_vec(&json).unwrap();
+
+    let read = Artifact::read(&json).unwrap();
+    assert_eq!(read.format_version, FORMAT_VERSION);
+    assert!(read.program.globals.is_empty());
+    assert_eq!(read.program.to_ast().run_main(&[]).unwrap(), 9);
+}
+
+#[test]
+fn rejects_references_that_dont_resolve() {
+    let source = "int main(int x) { int x = 1; int y = x; return y; }";
+    let mut out_of_bounds = artifact(source);
+    out_of_bounds.program.funcs[0].body[1].0 = Stmt::Let {
+        slot: 2,
+        expr: (Expr::Var(3), 0..0),
+    };
+    assert_eq!(
+        error(&out_of_bounds.to_json()),
+        "Error reading C IR: in main: slot 3 is used before it's declared"
+    );
+
+    let mut shadowed = artifact(source);
+    shadowed.program.funcs[0].body[1].0 = Stmt::Let {
+        slot: 2,
+        expr: (Expr::Var(0), 0..0),
+    };
+    assert_eq!(
+        error(&shadowed.to_binary()),
+        "Error reading C IR: in main: slot 0 is used where x refers to another slot"
+    );
+
+    let mut missing = artifact(source);
+    missing.program.funcs[0].body[1].0 = Stmt::Expr((
+        Expr::Call {
+            callee: Callee::Func(7),
+            args: Vec::new(),
+        },
+        0..0,
+    ));
+    assert_eq!(
+        error(&missing.to_json()),
+        "Error reading C IR: in main: function 7 doesn't exist"
+    );
+}
+
+#[test]
+fn writes_the_layout_of_the_schema() {
+    let artifact = artifact("int sq(int x) { return x * x; }\nint main() { return sq(3); }");
+    let json = String::from_utf8(artifact.to_json()).unwrap();
+    let compact = json.split_whitespace().collect::<String>();
+    assert_eq!(
+        compact,
+        format!(
+            r#"{{"format_version":{FORMAT_VERSION},"compiler_version":"{COMPILER_VERSION}","program":{{"structs":[],"funcs":[{{"name":"sq","ret":"Int","params":1,"slots":[{{"name":"x","ty":"Int","span":{{"start":7,"end":12}}}}],"body":[[{{"Return":[{{"Binary":["Mul",[{{"Var":0}},{{"start":23,"end":24}}],[{{"Var":0}},{{"start":27,"end":28}}]]}},{{"start":23,"end":28}}]}},{{"start":16,"end":29}}]],"span":{{"start":4,"end":6}}}},{{"name":"main","ret":"Int","params":0,"slots":[],"body":[[{{"Return":[{{"Call":{{"callee":{{"Func":0}},"args":[[{{"Int":3}},{{"start":55,"end":56}}]]}}}},{{"start":52,"end":57}}]}},{{"start":45,"end":58}}]],"span":{{"start":36,"end":40}}}}],"globals":[]}}}}"#
+        )
+    );
+
+    let written = schema::Program::from(&artifact.program);
+    assert_eq!(typed::Program::from(written), artifact.program);
+    assert_eq!(artifact.to_binary(), artifact.to_binary());
+}
@@ -0,0 +1,180 @@
+//! Tests for `build` with several inputs, compiled each on its own or linked into one program.
+
+use std::{fs, path::PathBuf, process::Command};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+fn write_sources(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crust-build-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    for (path, source) in files {
+        fs::write(dir.join("src").join(path), source).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn builds_each_input_into_a_directory() {
+    let dir = write_sources(
+        "each",
+        &[
+            ("a.c", "int main() { return 1; }"),
+            ("b.c", "int main() { return x; }"),
+            ("c.c", "int main() { return y; }"),
+        ],
+    );
+    let out = dir.join("out");
+    let build = Command::new(CRUST)
+        .args(["--error-format", "json", "build", "-j", "2"])
+        .arg(dir.join("src"))
+        .arg("-o")
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert!(!build.status.success());
+
+    // the diagnostics of each file name it, in the order of the files
+    let stderr = String::from_utf8(build.stderr).unwrap();
+    let files = stderr
+        .lines()
+        .filter_map(|line| line.split("\"file\":\"").nth(1))
+        .map(|rest| rest.split('"').next().unwrap().rsplit('/').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(files, ["b.c", "c.c"]);
+    assert!(
+        stderr.ends_with("2 of 3 files failed to build\n"),
+        "{stderr}"
+    );
+    assert!(out.join("a.json").exists());
+    assert!(!out.join("b.json").exists());
+
+    let run = Command::new(CRUST)
+        .arg("run")
+        .arg(out.join("a.json"))
+        .output()
+        .unwrap();
+    assert_eq!(run.status.code(), Some(1));
+}
+
+#[test]
+fn links_inputs_into_one_program() {
+    let dir = write_sources(
+        "link",
+        &[
+            (
+                "main.c",
+                "import \"lib.c\"; int main() { return g() + f(); }",
+            ),
+            ("f.c", "int f() { return 2; }"),
+            ("lib.c", "int g() { return f() * 10; }"),
+        ],
+    );
+    let src = dir.join("src");
+    let out = dir.join("linked.bin");
+    let build = Command::new(CRUST)
+        .arg("build")
+        .args([src.join("main.c"), src.join("f.c"), src.join("lib.c")])
+        .arg("--link")
+        .arg("-o")
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert!(build.status.success(), "{build:?}");
+
+    let run = Command::new(CRUST).arg("run").arg(&out).output().unwrap();
+    assert_eq!(run.status.code(), Some(22));
+
+    // a definition in two linked files is reported with both of them
+    fs::write(src.join("g.c"), "int g() { return 0; }").unwrap();
+    let build = Command::new(CRUST)
+        .args(["--error-format", "json", "build", "--link"])
+        .args([src.join("main.c"), src.join("f.c"), src.join("g.c")])
+        .arg("-o")
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert!(!build.status.success());
+    let stderr = String::from_utf8(build.stderr).unwrap();
+    assert!(stderr.contains("\"code\":\"E0104\""), "{stderr}");
+    assert!(stderr.contains("g.c"), "{stderr}");
+}
+
+#[test]
+fn needs_an_output_for_several_inputs() {
+    let dir = write_sources("output", &[("a.c", "int main() { return 0; }")]);
+    let src = dir.join("src");
+    for args in [vec![src.clone()], vec![src.join("a.c"), src.join("a.c")]] {
+        let build = Command::new(CRUST)
+            .arg("build")
+            .args(&args)
+            .output()
+            .unwrap();
+        assert!(!build.status.success(), "{args:?}");
+        assert_eq!(
+            String::from_utf8(build.stderr).unwrap(),
+            "Give the output with -o to build several inputs\n"
+        );
+    }
+}
+
+#[test]
+fn wont_build_a_c_input_over_itself() {
+    let dir = write_sources("overwrite", &[("a.c", "int main() { return 0; }")]);
+    let src = dir.join("src");
+    // one file built to itself, and each file in a directory built back into it
+    for (input, output) in [
+        (src.join("a.c"), src.join("a.c")),
+        (src.clone(), src.clone()),
+    ] {
+        let build = Command::new(CRUST)
+            .args(["build", "--emit", "c"])
+            .arg(input)
+            .arg("-o")
+            .arg(&output)
+            .output()
+            .unwrap();
+        assert!(!build.status.success(), "{output:?}");
+        assert_eq!(
+            String::from_utf8(build.stderr).unwrap(),
+            format!(
+                "Building {} would overwrite it\n",
+                src.join("a.c").display()
+            )
+        );
+    }
+    assert_eq!(
+        fs::read_to_string(src.join("a.c")).unwrap(),
+        "int main() { return 0; }"
+    );
+}
+
+#[test]
+fn keeps_what_the_only_function_calls() {
+    let dir = write_sources(
+        "only",
+        &[(
+            "main.c",
+            "int scale = 3;
+            int unused() { return 1 / 0; }
+            int twice(int x) { return add(x, x); }
+            int add(int a, int b) { return (a + b) * scale; }
+            int main() { return twice(2) + one(); }
+            int one() { return 1; }",
+        )],
+    );
+    let out = dir.join("main.json");
+    let build = Command::new(CRUST)
+        .args(["build", "--only-fn", "main"])
+        .arg(dir.join("src").join("main.c"))
+        .arg("-o")
+        .arg(&out)
+        .output()
+        .unwrap();
+    assert!(build.status.success(), "{build:?}");
+    let ir = fs::read_to_string(&out).unwrap();
+    assert!(!ir.contains("\"unused\""), "{ir}");
+
+    let run = Command::new(CRUST).arg("run").arg(&out).output().unwrap();
+    assert_eq!(run.status.code(), Some(13), "{run:?}");
+}
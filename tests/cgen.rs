@@ -0,0 +1,244 @@
+//! Differential tests compiling programs through `--emit c` and comparing them with the
+//! interpreter.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+mod common;
+
+fn emit_c(source: &Path) -> Output {
+    Command::new(common::CRUST)
+        .args(["build", "--emit", "c"])
+        .arg(source)
+        .arg("-o")
+        .arg(source.with_extension("c"))
+        .output()
+        .unwrap()
+}
+
+/// Compiles `source` to C and the C with `cc -std=c99`, warning about anything it's unsure of.
+fn build(source: &Path) -> PathBuf {
+    let emitted = emit_c(source);
+    assert!(
+        emitted.status.success(),
+        "{}",
+        String::from_utf8_lossy(&emitted.stderr)
+    );
+
+    let exe = source.with_extension("");
+    let cc = Command::new("cc")
+        .args(["-std=c99", "-pedantic", "-Wall", "-Werror"])
+        .arg(source.with_extension("c"))
+        .arg("-o")
+        .arg(&exe)
+        .output()
+        .unwrap();
+    assert!(
+        cc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&cc.stderr)
+    );
+    exe
+}
+
+/// Asserts that `source` behaves the same compiled through C and interpreted, for each set of
+/// arguments.
+fn agree(name: &str, source: &str, runs: &[&[&str]]) {
+    common::agree("cgen", name, source, runs, |path| {
+        vec![("through C", build(path))]
+    });
+}
+
+#[test]
+fn arithmetic_wraps_and_fails_like_the_interpreter() {
+    agree(
+        "arithmetic",
+        "int main(int a, int b) {
+            int x = a;
+            x += b;
+            x *= ++a;
+            int y = --b * 3;
+            println(a + b, a - b, a * b, -a, a / b, a % b, x);
+            println(a & b, a | b, a ^ b, a << b, a >> b, -a >> 1, !a, a < b && b != 0, y);
+            return a - b;
+        }",
+        &[
+            &["7", "2"],
+            &["-7", "2"],
+            &["2147483647", "1"],
+            &["-2147483648", "-1"],
+            &["-1", "33"],
+            &["5", "0"],
+        ],
+    );
+}
+
+#[test]
+fn floats_are_printed_and_truncated_like_the_interpreter() {
+    agree(
+        "floats",
+        "int main(int a) {
+            float f = a;
+            f = f / 4;
+            double big = 1.0 / 0.0;
+            println(f, f * 3, 0.1 + 0.2, -f, 1000000000000000000000.0, 0.00000015, big, -big, big - big);
+            int t = f * 1000000000000.0;
+            int n = big - big;
+            return t + n;
+        }",
+        &[&["7"], &["-7"], &["0"], &["2147483647"]],
+    );
+}
+
+#[test]
+fn strings_concatenate_and_count_characters() {
+    agree(
+        "strings",
+        "string greet(string name) { return \"hi \" + name; }
+        int main(string name) {
+            string empty = \"\";
+            string s = greet(name) + 1 + 2.5 + &empty;
+            println(s, len(s), len(empty), empty + \"%d ??= 100%\");
+            print(\"no newline\", \"\\t\\\"quoted\\\"\\n\");
+            return len(name);
+        }",
+        &[&["world"], &["ñandú"], &[""]],
+    );
+}
+
+#[test]
+fn arrays_pointers_and_switches() {
+    agree(
+        "arrays",
+        "struct Pair { int a; float b; };
+        void set(int* p, int value) { *p = value; }
+        int main(int i, int v) {
+            int xs[4];
+            xs[1] = v;
+            int x = 0;
+            set(&x, xs[1] + sizeof(Pair));
+            switch (x) {
+                case 3:
+                    println(\"three\");
+                case 11:
+                    println(\"eleven\");
+                    break;
+                default:
+                    println(\"other\", *&x, len(xs));
+            }
+            return xs[i];
+        }",
+        &[
+            &["1", "-13"],
+            &["0", "-5"],
+            &["3", "-10"],
+            &["4", "0"],
+            &["-1", "0"],
+        ],
+    );
+}
+
+#[test]
+fn globals_are_set_before_main() {
+    agree(
+        "globals",
+        "const int LIMIT = -3;
+        const string NAME = \"crust\";
+        int counter = LIMIT * 2;
+        float ratio;
+        string unset;
+        int bump() { counter += 1; return counter; }
+        int main() {
+            bump();
+            int bumped = bump();
+            println(LIMIT, NAME, counter, ratio, bumped, unset + len(unset));
+            return counter;
+        }",
+        &[&[]],
+    );
+}
+
+#[test]
+fn operands_and_arguments_are_evaluated_in_order() {
+    agree(
+        "order",
+        "int counter = 100 / 4 - 20 % 7;
+        int next(string name) { counter += 1; println(name, counter); return counter; }
+        int pair(int a, int b) { return a * 100 + b; }
+        int main(int x) {
+            int b = x;
+            int c = b & ++b;
+            int d = next(\"a\") - next(\"b\");
+            println(next(\"c\"), counter, next(\"d\"), b);
+            int e = pair(next(\"e\"), counter) + pair(counter, next(\"f\"));
+            return c + d + e + (x > 0 || next(\"g\") > 0) + 10 / (x - 2);
+        }",
+        &[&["3"], &["-3"], &["2"]],
+    );
+}
+
+#[test]
+fn names_c_reserves_are_renamed() {
+    agree(
+        "names",
+        "int printf(int exit) { return exit + 1; }
+        int crust_add(int x) { return x; }
+        int main(int int_) {
+            int x = printf(int_);
+            int x = x * crust_add(2);
+            int printf = x;
+            int strlen = printf + 1;
+            println(x, printf, strlen);
+            return strlen;
+        }",
+        &[&["3"], &["-3"]],
+    );
+}
+
+#[test]
+fn functions_without_a_return_fail() {
+    agree(
+        "no-return",
+        "int check(int x) { if_positive(x); }
+        void if_positive(int x) { print(x); }
+        int main(int x) { return check(x); }",
+        &[&["3"]],
+    );
+}
+
+#[test]
+fn reports_calls_to_unknown_functions() {
+    let ast = crust::pipeline::parse_file("int main() { return add(1, 2); }").unwrap();
+    let error = crust::cgen::emit_c(&ast).unwrap_err();
+    assert_eq!(error.message.to_string(), "unknown function add");
+}
+
+#[test]
+fn rejects_what_it_cannot_write() {
+    let path = common::write_source(
+        "cgen",
+        "unsupported",
+        "int work(int x) { return x; }
+        int main() {
+            int t = spawn work(1);
+            return join(t);
+        }",
+    );
+    let output = Command::new(common::CRUST)
+        .args(["build", "--features", "threads", "--emit", "c"])
+        .arg(&path)
+        .arg("-o")
+        .arg(path.with_extension("c"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("threads is not supported by the C backend"),
+        "{stderr}"
+    );
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,373 @@
+//! Differential tests running programs natively, through LLVM, and through the interpreter.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+mod common;
+
+/// Compiles `source` to a native executable through `--emit exe`.
+fn build_native(source: &Path) -> PathBuf {
+    let exe = source.with_extension("");
+    let build = Command::new(common::CRUST)
+        .args(["build", "--emit", "exe"])
+        .arg(source)
+        .arg(&exe)
+        .output()
+        .unwrap();
+    assert!(
+        build.status.success(),
+        "{}",
+        String::from_utf8_lossy(&build.stderr)
+    );
+    exe
+}
+
+/// Compiles `source` through `--emit llvm` and `llc`, or returns `None` if `llc` isn't installed.
+fn build_llvm(source: &Path) -> Option<PathBuf> {
+    Command::new("llc").arg("--version").output().ok()?;
+
+    let ir = source.with_extension("ll");
+    let asm = source.with_extension("ll.s");
+    let exe = source.with_extension("llvm");
+    let build = Command::new(common::CRUST)
+        .args(["build", "--emit", "llvm"])
+        .arg(source)
+        .arg(&ir)
+        .output()
+        .unwrap();
+    assert!(
+        build.status.success(),
+        "{}",
+        String::from_utf8_lossy(&build.stderr)
+    );
+
+    let llc = Command::new("llc")
+        .arg("-relocation-model=pic")
+        .arg(&ir)
+        .arg("-o")
+        .arg(&asm)
+        .output()
+        .unwrap();
+    assert!(
+        llc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&llc.stderr)
+    );
+
+    let cc = Command::new("cc")
+        .arg(&asm)
+        .arg("-o")
+        .arg(&exe)
+        .output()
+        .unwrap();
+    assert!(
+        cc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&cc.stderr)
+    );
+    Some(exe)
+}
+
+/// Asserts that `source` behaves the same natively, through LLVM and interpreted, for each set of
+/// arguments.
+fn agree(name: &str, source: &str, runs: &[&[&str]]) {
+    common::agree("codegen", name, source, runs, |path| {
+        let mut exes = vec![("natively", build_native(path))];
+        exes.extend(build_llvm(path).map(|exe| ("through LLVM", exe)));
+        exes
+    });
+}
+
+#[test]
+fn arithmetic() {
+    agree(
+        "arithmetic",
+        "int main(int a, int b) {
+            int _p = println(a + b, a - b, a * b, -a, a / b);
+            return a - b;
+        }",
+        &[
+            &["7", "2"],
+            &["-7", "2"],
+            &["2147483647", "1"],
+            &["-2147483648", "-1"],
+            &["-2147483648", "2"],
+            &["5", "0"],
+        ],
+    );
+}
+
+#[test]
+fn remainders_bits_and_shifts() {
+    agree(
+        "bits",
+        "int main(int a, int b) {
+            int _p = println(a & b, a | b, a ^ b, a << b, a >> b, -a >> 1, a % b);
+            return a % b;
+        }",
+        &[
+            &["7", "2"],
+            &["-7", "2"],
+            &["-2147483648", "-1"],
+            &["-1", "33"],
+            &["3", "32"],
+            &["5", "0"],
+        ],
+    );
+}
+
+#[test]
+fn unary_operators_and_assignments() {
+    agree(
+        "assignments",
+        "int main(int a, int b) {
+            int x = a;
+            x += b;
+            int y = ++x * 2;
+            x -= --y;
+            x *= !b + !!a;
+            y = x + y;
+            y /= b;
+            int _p = println(x, y, !x, - --a);
+            return y;
+        }",
+        &[
+            &["7", "2"],
+            &["-7", "3"],
+            &["0", "-1"],
+            &["2147483647", "1"],
+            &["-2147483648", "-1"],
+            &["5", "0"],
+        ],
+    );
+}
+
+#[test]
+fn calls_arrays_and_strings() {
+    agree(
+        "calls",
+        "int sq(int x) { return x * x; }
+        int pick(int a, int b, int c, int d, int e, int f) { return a - b + c - d + e - f; }
+        string greet(string name) { return name; }
+        int main(int i, string name) {
+            int squares[4];
+            squares[0] = sq(1);
+            squares[1] = sq(2);
+            squares[i] = pick(1, 2, 3, 4, 5, sq(i));
+            string words[2];
+            words[1] = greet(name);
+            int _p = println(squares[0], squares[1], squares[2], squares[3], len(squares));
+            int _q = println(words[0], words[1], len(name), \"esc\\t\\\"aped\\\"\");
+            int _r = print(\"done\");
+            return squares[i];
+        }",
+        &[&["2", "crust"], &["3", "x"], &["4", "oob"], &["-1", "neg"]],
+    );
+}
+
+#[test]
+fn comparisons_and_conditionals() {
+    agree(
+        "conditionals",
+        "int max(int a, int b) { return a > b ? a : b; }
+        int main(int a, int b) {
+            int x = 0;
+            int y = a < b ? ++x : a == b ? 1 / (a - b) : b < 0 ? --x : x;
+            string s = a >= b ? \"ge\" : \"lt\";
+            println(x, y, s, max(a, b), a <= b, a != b, a == b == 0, a < 0 ? 1 / 0 : a);
+            return x < 0 ? -x : x ? 10 : 20;
+        }",
+        &[
+            &["1", "2"],
+            &["2", "-1"],
+            &["3", "1"],
+            &["-2147483648", "2147483647"],
+            &["4", "4"],
+        ],
+    );
+}
+
+#[test]
+fn void_functions() {
+    agree(
+        "void",
+        "void log(int a, string s) {
+            print(s);
+            println(a);
+            return;
+            println(99);
+        }
+        void twice(int a) { log(a, \"once \"); log(a * 2, \"twice \"); }
+        int main(int a) { twice(a); a + 1; return a; }",
+        &[&["1"], &["21"]],
+    );
+}
+
+#[test]
+fn switches() {
+    agree(
+        "switch",
+        "int classify(int n) {
+            int r = n;
+            switch (n) {
+                case 1:
+                    r += 1;
+                case 2:
+                    r += 10;
+                    break;
+                case -3:
+                    return 99;
+                default:
+                    r = 7;
+                case 4:
+                    r *= 2;
+            }
+            return r;
+        }
+        int main(int n) {
+            switch (n) {
+                case 5:
+                    println(\"five\");
+            }
+            println(classify(n));
+            return classify(n + 1);
+        }",
+        &[&["0"], &["1"], &["2"], &["-3"], &["-4"], &["4"], &["5"]],
+    );
+}
+
+#[test]
+fn short_circuits() {
+    agree(
+        "logical",
+        "int side(int x) {
+            println(x);
+            return x;
+        }
+        int main(int a) {
+            int n = 0;
+            int f = a || ++n;
+            int g = a && ++n && side(n);
+            println(a && side(2), a || side(3), (a > 1 || a < -1) && !(a == 5), f, g, n);
+            return 1 || 0 && 0 ? a && 7 || 0 : 9;
+        }",
+        &[&["0"], &["1"], &["5"], &["-3"]],
+    );
+}
+
+#[test]
+fn inline_blocks() {
+    let path = common::write_source(
+        "codegen",
+        "inline",
+        "int main(int x) {
+            int a[2];
+            __asm {
+                movl {x}, %eax
+                addl %eax, %eax
+                movl %eax, {x}
+                movq $7, 8+{a}
+            }
+            int _p = println(x, a[1]);
+            return x;
+        }",
+    );
+    assert_eq!(
+        common::run_exe(&build_native(&path), &["5"]),
+        (String::from("10 7\n"), 10)
+    );
+
+    // only the backend a block is written for can compile it
+    let ir = path.with_extension("ll");
+    let build = Command::new(common::CRUST)
+        .args(["build", "--emit", "llvm"])
+        .arg(&path)
+        .arg(&ir)
+        .output()
+        .unwrap();
+    assert!(!build.status.success());
+    assert!(String::from_utf8_lossy(&build.stderr)
+        .contains("a `__asm` block is not supported by the LLVM backend"));
+
+    fs::write(
+        &path,
+        "int main(int x) {
+            __ir {
+                %x.old = load i32, i32* {x}
+                %x.new = mul i32 %x.old, 3
+                store i32 %x.new, i32* {x}
+            }
+            return x;
+        }",
+    )
+    .unwrap();
+    if let Some(llvm) = build_llvm(&path) {
+        assert_eq!(common::run_exe(&llvm, &["5"]), (String::new(), 15));
+    }
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn recursion_limit() {
+    agree(
+        "recursion",
+        "int down(int n) { return down(n - 1); }
+        int main() { return down(5); }",
+        &[&[]],
+    );
+}
+
+#[test]
+fn ir_dump() {
+    let path = common::write_source(
+        "codegen",
+        "ir",
+        "int sq(int x) { return x * x; }
+        int main(int i) {
+            int a[2];
+            a[i] = sq(i) + 1;
+            return a[0];
+            int dead = 1;
+        }",
+    );
+    let ir = path.with_extension("ir");
+    let build = Command::new(common::CRUST)
+        .args(["build", "--emit", "ir"])
+        .arg(&path)
+        .arg(&ir)
+        .output()
+        .unwrap();
+    assert!(build.status.success());
+
+    assert_eq!(
+        fs::read_to_string(ir).unwrap(),
+        "func sq(int %0) -> int {
+    var int %0 x
+bb0:
+    %1 = mul %0, %0
+    ret %1
+bb1:
+    missing_return
+}
+
+func main(int %0) -> int {
+    var int %0 i
+    var int %4 dead
+    array int[2] &0 a
+bb0:
+    fill &0, 0
+    %1 = call sq(%0)
+    %2 = add %1, 1
+    store &0[%0], %2
+    %3 = load &0[0]
+    ret %3
+bb1:
+    %4 = 1
+    missing_return
+}
+"
+    );
+}
@@ -0,0 +1,118 @@
+//! Helpers shared by the integration tests that run programs through the `crust` binary.
+
+// each test crate only uses some of them
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use crust::{ir, Ast};
+
+pub const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+/// Writes `source` to a fresh directory for the test called `name` in `suite`.
+pub fn write_source(suite: &str, name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crust-{suite}-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.cst");
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// Runs `source` with the interpreter, returning what it printed and its exit code as a process
+/// would see it.
+pub fn interpret(source: &Path, args: &[&str]) -> (String, i32) {
+    let output = Command::new(CRUST)
+        .arg("run")
+        .arg(source)
+        .arg("--")
+        .args(args)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    match stdout.rsplit_once("-- exited with code : ") {
+        Some((printed, code)) => {
+            let code = code.trim().trim_end_matches(" --").parse::<i32>().unwrap();
+            (printed.to_string(), code & 0xff)
+        }
+        None => (stdout, 255),
+    }
+}
+
+pub fn run_exe(exe: &Path, args: &[&str]) -> (String, i32) {
+    let Output { status, stdout, .. } = Command::new(exe).args(args).output().unwrap();
+    (String::from_utf8(stdout).unwrap(), status.code().unwrap())
+}
+
+/// Asserts that each executable `build` compiles `source` to behaves the same as the
+/// interpreter, for each set of arguments. `build` names each executable by how it was compiled.
+pub fn agree(
+    suite: &str,
+    name: &str,
+    source: &str,
+    runs: &[&[&str]],
+    build: impl Fn(&Path) -> Vec<(&'static str, PathBuf)>,
+) {
+    let path = write_source(suite, name, source);
+    let exes = build(&path);
+    for args in runs {
+        let expected = interpret(&path, args);
+        for (how, exe) in &exes {
+            assert_eq!(run_exe(exe, args), expected, "{name} {how} with {args:?}");
+        }
+    }
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+/// Runs `command`, returning its output, diagnostics and exit code.
+pub fn output(command: &mut Command) -> (String, String, Option<i32>) {
+    let output = command.output().unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+        output.status.code(),
+    )
+}
+
+/// Runs `source` on the interpreter, the VM and the VM from binary IR, requiring the same
+/// results, and returns its output, diagnostics and exit code.
+pub fn run(suite: &str, name: &str, source: &str) -> (String, String, Option<i32>) {
+    let path = write_source(suite, name, source);
+    let ir = path.with_extension("bin");
+    let build = Command::new(CRUST)
+        .arg("build")
+        .arg(&path)
+        .arg("-o")
+        .arg(&ir)
+        .output()
+        .unwrap();
+    assert!(build.status.success(), "{build:?}");
+
+    let run = |path: &Path, vm: bool| {
+        let mut command = Command::new(CRUST);
+        command.arg("run").arg(path);
+        if vm {
+            command.arg("--vm");
+        }
+        output(&mut command)
+    };
+    let output = run(&path, false);
+    assert_eq!(run(&path, true), output, "{name}");
+    assert_eq!(run(&ir, true).0, output.0, "{name} from IR");
+    output
+}
+
+/// Asserts that the native backends reject `ast` for using `feature`, which they don't support.
+pub fn assert_unsupported(ast: &Ast, feature: &str) {
+    let error = ir::lower(ast).unwrap_err();
+    assert_eq!(error.code, "E0400", "{feature}");
+    assert!(
+        error.message.to_string().contains(feature),
+        "{}",
+        error.message
+    );
+}
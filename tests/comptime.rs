@@ -0,0 +1,100 @@
+//! Tests for evaluating calls to `@comptime` functions at build time.
+
+use std::{fs, process::Command};
+
+use crust::{comptime, pipeline, Ast, Diagnostic};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+fn expanded(source: &str) -> Result<Ast, Vec<Diagnostic>> {
+    let mut ast = pipeline::compile(source, "main.c").unwrap().ast;
+    comptime::expand(&mut ast).map(|()| ast)
+}
+
+#[test]
+fn replaces_calls_with_constant_arguments() {
+    let ast = expanded(
+        "@comptime int square(int n) { return n * n; }
+        @comptime string twice(string s) { return s + s; }
+        int main(int a) {
+            string s = twice(\"ab\");
+            int b = square(-square(2) - 1) + square(a);
+            return 0 - square(3);
+        }",
+    )
+    .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "@comptime func square(int n) -> int
+  (return (* n n))
+@comptime func twice(string s) -> string
+  (return (+ s s))
+func main(int a) -> int
+  (let string s \"abab\")
+  (let int b (+ 25 (call square a)))
+  (return (- 0 9))
+"
+    );
+}
+
+#[test]
+fn leaves_calls_that_depend_on_the_width_of_ints() {
+    let ast = expanded(
+        "@comptime int big(int n) { return n * 65536; }
+        @comptime int shifted(int n) { return 1 << n; }
+        int main() { return big(2) + big(65536) + shifted(3) + shifted(40); }",
+    )
+    .unwrap();
+    assert!(ast
+        .to_string()
+        .ends_with("(return (+ (+ (+ 131072 (call big 65536)) 8) (call shifted 40)))\n"));
+}
+
+#[test]
+fn reports_calls_that_fail_at_build_time() {
+    let errors = expanded(
+        "@comptime int inv(int n) { return 1 / n; }
+        @comptime int noisy() { return println(1); }
+        int main() { return inv(0) + inv(1) + noisy(); }",
+    )
+    .unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].message.to_string().contains("divide by zero"));
+    assert!(errors[1]
+        .message
+        .to_string()
+        .contains("println can't be called at build time"));
+    for error in &errors {
+        assert!(error.notes[0].to_string().contains("@comptime"));
+        assert_eq!(
+            error.labels[1].message.to_string(),
+            "evaluated at build time here"
+        );
+    }
+}
+
+#[test]
+fn build_writes_the_results() {
+    let dir = std::env::temp_dir().join(format!("crust-comptime-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.c");
+    fs::write(
+        &source,
+        "@comptime int cube(int n) { return n * n * n; }
+        int main(int a) { return cube(3) + cube(a); }",
+    )
+    .unwrap();
+
+    let output = dir.join("main.ir");
+    let status = Command::new(CRUST)
+        .args(["build", "--emit", "ir"])
+        .arg(&source)
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let ir = fs::read_to_string(&output).unwrap();
+    assert!(ir.contains("27"));
+    assert_eq!(ir.matches("call cube").count(), 1);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,176 @@
+//! Tests for `run --debug`, driven through its stdin.
+
+use std::{
+    fs,
+    hash::{BuildHasher, RandomState},
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+const PROGRAM: &str = "int sq(int x) {
+    int y = x * x;
+    return y;
+}
+int main() {
+    int a = 2;
+    string s = \"hi\";
+    int b = sq(a);
+    println(s);
+    return b;
+}
+";
+
+/// Runs `PROGRAM` in the debugger, entering `commands`.
+fn debug(commands: &str) -> Output {
+    debug_editing(commands, "")
+}
+
+/// Like [`debug`], with an editor that runs the `sed` script `edit` on the functions it edits.
+fn debug_editing(commands: &str, edit: &str) -> Output {
+    let dir = std::env::temp_dir().join(format!("crust-debug-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("main.c");
+    fs::write(&input, PROGRAM).unwrap();
+
+    // tests run at once, so each edit has a script of its own
+    let editor = dir.join(format!("editor-{:x}.sh", RandomState::new().hash_one(edit)));
+    fs::write(&editor, format!("sed -i '{edit}' \"$1\"\n")).unwrap();
+
+    let mut debugger = Command::new(CRUST)
+        .args(["run", "--debug"])
+        .arg(&input)
+        .env_remove("VISUAL")
+        .env("EDITOR", format!("sh {}", editor.display()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    debugger
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    debugger.wait_with_output().unwrap()
+}
+
+/// The lines of `output`'s stdout, without the directory the program was written to.
+fn lines(output: &Output) -> Vec<String> {
+    let dir = std::env::temp_dir().join(format!("crust-debug-{}", std::process::id()));
+    String::from_utf8_lossy(&output.stdout)
+        .replace(&format!("{}/", dir.display()), "")
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn steps_through_breakpoints() {
+    let output = debug("break sq\nbreak 9\ncontinue\nbt\nlocals\nnext\nnext\nlocals\nnext\n");
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(
+        lines(&output),
+        [
+            // paused before the first statement of main
+            "main.c:6 in main",
+            "    6 | int a = 2;",
+            "Breakpoint 1 at sq",
+            "Breakpoint 2 at main.c:9",
+            "main.c:2 in sq",
+            "    2 | int y = x * x;",
+            "   0: sq, called at main.c:8",
+            "   1: main",
+            "x = 2",
+            "main.c:3 in sq",
+            "    3 | return y;",
+            // `next` carries on in the caller once the function returns
+            "main.c:9 in main",
+            "    9 | println(s);",
+            "a = 2",
+            "s = \"hi\"",
+            "b = 4",
+            "hi",
+            "main.c:10 in main",
+            "   10 | return b;",
+            "-- exited with code : 4 --",
+        ]
+    );
+}
+
+#[test]
+fn quits_and_stops_asking_at_the_end_of_input() {
+    let output = debug("print a\nstep\nprint a\nprint b\nfrobnicate\nquit\n");
+    assert!(!output.status.success());
+    assert_eq!(
+        lines(&output),
+        [
+            "main.c:6 in main",
+            "    6 | int a = 2;",
+            "No variable a in main",
+            "main.c:7 in main",
+            "    7 | string s = \"hi\";",
+            "a = 2",
+            "No variable b in main",
+            "Unknown command frobnicate, try help",
+        ]
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("the program was stopped from the debugger"));
+
+    // without commands to read, the program runs as it would without the debugger
+    let output = debug("");
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(lines(&output)[2..], ["hi", "-- exited with code : 4 --"]);
+}
+
+#[test]
+fn edits_functions_from_their_next_call() {
+    let commands = "break sq\ncontinue\nedit sq\nnext\nnext\nnext\n";
+    let output = debug_editing(commands, "s/x \\* x/x * x + 1/");
+    // the call in progress returns 4, and calls from then on run the edit
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(
+        lines(&output),
+        [
+            "main.c:6 in main",
+            "    6 | int a = 2;",
+            "Breakpoint 1 at sq",
+            "main.c:2 in sq",
+            "    2 | int y = x * x;",
+            "Edited sq, which runs from its next call while the calls in progress finish as they were",
+            "main.c:3 in sq",
+            "    3 | return y;",
+            "main.c:9 in main",
+            "    9 | println(s);",
+            "hi",
+            "main.c:10 in main",
+            "   10 | return b;",
+            "-- exited with code : 4 --",
+        ]
+    );
+}
+
+#[test]
+fn keeps_functions_whose_edits_dont_fit_their_callers() {
+    for (edit, error) in [
+        (
+            "s/sq/square/",
+            "sq wasn't changed, since the edit renamed it",
+        ),
+        (
+            "s/int x/int x, int z/",
+            "sq wasn't changed, since its callers pass it 1 arguments",
+        ),
+        ("s/return y;/return y/", "sq wasn't changed:"),
+    ] {
+        let output = debug_editing("edit sq\ncontinue\n", edit);
+        assert_eq!(output.status.code(), Some(4), "{edit}");
+        assert!(lines(&output).contains(&error.to_string()), "{edit}");
+    }
+
+    let output = debug_editing("edit nope\nquit\n", "");
+    assert!(lines(&output).contains(&"No function nope".to_string()));
+}
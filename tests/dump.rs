@@ -0,0 +1,137 @@
+//! Tests for the token and AST dumps written by `build --emit tokens` and `--emit ast`, and the
+//! symbol tables written by `--emit symtab`.
+
+use std::{fs, process::Command};
+
+use crust::{dump, pipeline, symtab::SymbolTable};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+#[test]
+fn tabulates_tokens() {
+    let source = "int main() {\n  return \"a\tb\" + -1;\n}";
+    let tokens = pipeline::lex(source).unwrap();
+    assert_eq!(
+        dump::tokens(source, &tokens),
+        "LINE:COL  SPAN    KIND        TEXT
+1:1       0..3    identifier  int
+1:5       4..8    identifier  main
+1:9       8..9    delimiter   (
+1:10      9..10   delimiter   )
+1:12      11..12  delimiter   {
+2:3       15..21  keyword     return
+2:10      22..27  string      \"a\\tb\"
+2:16      28..29  operator    +
+2:18      30..31  operator    -
+2:19      31..32  number      1
+2:20      32..33  delimiter   ;
+3:1       34..35  delimiter   }
+"
+    );
+}
+
+#[test]
+fn draws_the_ast() {
+    let ast = pipeline::parse_file(
+        "struct P { int x; };
+        int main(int n) { int a[2]; a[n] = f(n - 1) * 2; return -a[0]; }",
+    )
+    .unwrap();
+    assert_eq!(
+        dump::tree(&ast),
+        "struct P
+└── field int x
+func main(int n) -> int
+├── array int a[2]
+├── store a
+│   ├── index
+│   │   └── var n
+│   └── value
+│       └── *
+│           ├── call f
+│           │   └── -
+│           │       ├── var n
+│           │       └── 1
+│           └── 2
+└── return
+    └── negate
+        └── index
+            ├── var a
+            └── 0
+"
+    );
+}
+
+#[test]
+fn stops_after_the_dumped_pass() {
+    let dir = std::env::temp_dir().join(format!("crust-dump-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("main.c");
+    let output = dir.join("out.txt");
+
+    // tokens are dumped even though the file doesn't parse, and the AST though it doesn't check
+    for (source, emit) in [
+        ("int main() { return }", "tokens"),
+        ("int main() { return x; }", "ast"),
+    ] {
+        fs::write(&input, source).unwrap();
+        let build = Command::new(CRUST)
+            .args(["build", "--emit", emit])
+            .arg(&input)
+            .arg(&output)
+            .output()
+            .unwrap();
+        assert!(build.status.success(), "{emit}");
+    }
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "func main() -> int\n└── return\n    └── var x\n"
+    );
+}
+
+#[test]
+fn lists_the_symbols_of_each_scope() {
+    let program = pipeline::compile(
+        "struct P { int x; };
+int sq(int x) { return x * x; }
+int main(string arg) {
+    int a = sq(1);
+    string s[3];
+    int a = a + 1;
+    return a;
+}",
+        "main.c",
+    )
+    .unwrap();
+    let table = SymbolTable::new(&program.sources, &program.ast);
+    assert_eq!(
+        table.to_string(),
+        "scope global
+  NAME  KIND      TYPE         LOCATION
+  P     struct    struct P     main.c:1:8
+  sq    function  int(int)     main.c:2:5
+  main  function  int(string)  main.c:3:5
+
+scope struct P (in global)
+  NAME  KIND   TYPE  LOCATION
+  x     field  int   main.c:1:12
+
+scope sq (in global)
+  NAME  KIND       TYPE  LOCATION
+  x     parameter  int   main.c:2:8
+
+scope main (in global)
+  NAME  KIND       TYPE       LOCATION
+  arg   parameter  string     main.c:3:10
+  a     variable   int        main.c:4:5
+  s     array      string[3]  main.c:5:5
+  a     variable   int        main.c:6:5
+"
+    );
+
+    let json = serde_json::from_str::<serde_json::Value>(&table.to_json()).unwrap();
+    let main = &json["scopes"][3];
+    assert_eq!(main["parent"], 0);
+    assert_eq!(main["symbols"][2]["type"], "string[3]");
+    assert_eq!(main["symbols"][2]["location"]["span"]["start"], 99);
+}
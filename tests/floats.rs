@@ -0,0 +1,127 @@
+//! Tests for float literals and values, which the interpreter and the VM run alike and the native
+//! backends reject.
+
+use crust::{format, pipeline, Value};
+
+mod common;
+
+#[test]
+fn computes_with_floats_and_ints() {
+    let (stdout, stderr, code) = common::run(
+        "floats",
+        "arithmetic",
+        "double half(int x) { return x / 2.0; }
+int main() {
+    float f = 2.5;
+    println(f * 2, 1 + f, 7 - 0.5, -f, 0.1 + 0.2, 1 / 0.0);
+    println(half(3), 3.0 == 3, 2.5 < 2, \"f\" + f);
+    return 0;
+}",
+    );
+    assert_eq!(stderr, "");
+    assert_eq!(code, Some(0));
+    assert!(
+        stdout.starts_with("5.0 3.5 6.5 -2.5 0.30000000000000004 inf\n1.5 1 0 f2.5\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn converts_between_ints_and_floats_where_stored() {
+    let (stdout, _, code) = common::run(
+        "floats",
+        "conversions",
+        "int truncated(double x) { return x; }
+double widened(double x) { return x; }
+int main() {
+    int i = 7.9;
+    i += 0.6;
+    double d = 1;
+    d *= 3;
+    double a[2];
+    a[0] = 4;
+    int* p = &i;
+    *p = -2.5;
+    println(i, d, a[0], a[1], truncated(-2.7), widened(5));
+    return 9.99;
+}",
+    );
+    assert!(stdout.starts_with("-2 3.0 4.0 0.0 -2 5.0\n"), "{stdout}");
+    assert_eq!(code, Some(9));
+}
+
+#[test]
+fn int_only_operators_reject_floats() {
+    for (name, source) in [
+        ("rem", "int main() { double x = 7.5; return x % 2; }"),
+        ("shift", "int main() { return 1 << 2.0; }"),
+        ("increment", "int main() { double x = 1; return ++x; }"),
+    ] {
+        let (_, stderr, code) = common::run("floats", name, source);
+        assert_eq!(code, Some(255), "{name}");
+        assert!(stderr.contains("expected int, found float"), "{stderr}");
+    }
+}
+
+#[test]
+fn reports_int_literals_too_large() {
+    for source in [
+        "int main() { return 4294967296 + 1; }",
+        "int main() { int a[99999999999]; return 0; }",
+        "int main() { return 2147483648; }",
+        "int main() { return 4294967295; }",
+        "int main() { return 0xFFFFFFFF; }",
+        "int main() { return 1 - 2147483648; }",
+        "int main() { return -2147483649; }",
+        "int main() { return -4294967295; }",
+        "int main(int x) { switch (x) { case 2147483648: return 1; } return 0; }",
+    ] {
+        let errors = pipeline::compile(source, "main.c").unwrap_err();
+        assert_eq!(errors.len(), 1, "{source}");
+        assert_eq!(errors[0].code, "E0005");
+        assert!(errors[0].message.to_string().contains("is too large"));
+    }
+    // the largest int still parses, as the smallest does as the negation of one past it
+    for source in [
+        "int main() { return 2147483647; }",
+        "int main() { return 0x7FFFFFFF; }",
+        "int main() { return -2147483648; }",
+        "int main(int x) { switch (x) { case -2147483648: return 1; } return 0; }",
+    ] {
+        let program = pipeline::compile(source, "main.c").unwrap();
+        let expected = match source.contains('-') {
+            true => i32::MIN,
+            false => i32::MAX,
+        };
+        if !source.contains("switch") {
+            assert_eq!(program.ast.run_main(&[]).unwrap(), expected, "{source}");
+        }
+    }
+}
+
+#[test]
+fn formats_and_prints_floats() {
+    let source = "double main() { return 1.50 + 2.0 * -0.25; }";
+    let formatted = format::format(source).unwrap();
+    assert!(
+        formatted.contains("return 1.5 + 2.0 * -0.25;"),
+        "{formatted}"
+    );
+    assert_eq!(format::format(&formatted).unwrap(), formatted);
+
+    assert_eq!(Value::Float(3.0).to_string(), "3.0");
+    assert_eq!(Value::Float(-0.125).to_string(), "-0.125");
+    assert_eq!(Value::Float(f64::NAN).to_string(), "NaN");
+}
+
+#[test]
+fn native_backends_report_floats_as_unsupported() {
+    for source in [
+        "int main() { return 1.5; }",
+        "double f() { return 1; } int main() { return 0; }",
+        "int main() { float x = 1; return 0; }",
+    ] {
+        let program = pipeline::compile(source, "main.c").unwrap();
+        common::assert_unsupported(&program.ast, "floats");
+    }
+}
@@ -0,0 +1,335 @@
+//! Tests for the formatter, which must keep everything a file says, comments included.
+
+use std::{fs, process::Command};
+
+use chumsky::{Parser, Stream};
+use crust::{format::format, Ast, Token};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+const MESSY: &str = "// header comment
+
+import \"lib.c\"; import \"other.c\"; // trailing import
+struct P { int x; // x coord
+  string name;
+  // end of P
+}; /* after P, /* nested */
+      and over lines */
+int   f( int a,int b ) { return (a+b)*(a - (b - 1)) / -(-a) - (a - b) - -b; } // after f
+// before main
+
+
+int main(int n){
+  int a[4];   a[ n ] = f(1, 2) ;
+
+
+  // lonely
+  string s = r#\"raw \\ \"\"# + \"x\\n\" + -a[0]; // trailing
+  return
+     // inside
+     (-a)[n] // second inside
+     ;
+  // last in body
+}
+// end of file";
+
+/// The program `source` parses to, without spans.
+fn parsed(source: &str) -> String {
+    let tokens = Token::lexer().parse(source).unwrap();
+    let len = source.chars().count();
+    let ast = Ast::parser()
+        .parse(Stream::from_iter(len..len + 1, tokens.into_iter()))
+        .unwrap();
+    ast.to_string()
+}
+
+fn comments(source: &str) -> Vec<String> {
+    let (_, comments) = Token::lexer_with_comments().parse(source).unwrap();
+    comments.into_iter().map(|(text, _)| text).collect()
+}
+
+#[test]
+fn formats_canonically() {
+    assert_eq!(
+        format(MESSY).unwrap(),
+        "// header comment
+
+import \"lib.c\";
+import \"other.c\"; // trailing import
+
+struct P
+{
+    int x; // x coord
+    string name;
+    // end of P
+}; /* after P, /* nested */
+      and over lines */
+
+int f(int a, int b)
+{
+    return (a + b) * (a - (b - 1)) / - -a - (a - b) - -b;
+} // after f
+
+// before main
+
+int main(int n)
+{
+    int a[4];
+    a[n] = f(1, 2);
+
+    // lonely
+    string s = \"raw \\\\ \\\"\" + \"x\\n\" + -a[0]; // trailing
+    return (-a)[n]; // inside
+    // second inside
+    // last in body
+}
+// end of file
+"
+    );
+}
+
+#[test]
+fn round_trips() {
+    let mut sources = vec![MESSY.to_string()];
+    for entry in fs::read_dir("cases").unwrap() {
+        let source = fs::read_to_string(entry.unwrap().path()).unwrap();
+        if format(&source).is_ok() {
+            sources.push(source);
+        }
+    }
+
+    for source in sources {
+        let formatted = format(&source).unwrap();
+        assert_eq!(parsed(&formatted), parsed(&source), "{source}");
+        assert_eq!(comments(&formatted), comments(&source), "{source}");
+        assert_eq!(format(&formatted).unwrap(), formatted, "{source}");
+    }
+}
+
+#[test]
+fn keeps_annotations_with_their_function() {
+    let source = "// squares\n@comptime   int sq(int n) { return n*n; }";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "// squares
+@comptime int sq(int n)
+{
+    return n * n;
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn formats_assignments_and_unary_operators() {
+    let source = "int main(int a) { a=1; a+=!a; a -= - -a; a*=- --a; a/=++a; return a; }";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "int main(int a)
+{
+    a = 1;
+    a += !a;
+    a -= - -a;
+    a *= - --a;
+    a /= ++a;
+    return a;
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn formats_pointers() {
+    let source = "int *f(int *p, int **pp) { *p=**pp*-*p; **pp = *(*pp); return *(&p) ? p : *pp; }";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "int* f(int* p, int** pp)
+{
+    *p = **pp * -*p;
+    **pp = **pp;
+    return *&p ? p : *pp;
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn formats_prototypes_together() {
+    let source = "int  odd( int n ) ;\nvoid log(string s);\nint odd(int n) { return n; }";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "int odd(int n);
+void log(string s);
+
+int odd(int n)
+{
+    return n;
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn formats_comparisons_and_conditionals() {
+    let source = "int main(int a) { return (a<1?a:2)?a==(a<=a):(a>=1)!=(a>2)?a:a!=0 ? 1 : -1; }";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "int main(int a)
+{
+    return (a < 1 ? a : 2) ? a == a <= a : a >= 1 != a > 2 ? a : a != 0 ? 1 : -1;
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn formats_macros_like_functions() {
+    let source =
+        "macro swap(a,b){int t=a; a=b; b=t;} int main(int x,int y){swap!(x,y+1); return x;}";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "macro swap(a, b)
+{
+    int t = a;
+    a = b;
+    b = t;
+}
+
+int main(int x, int y)
+{
+    swap!(x, y + 1);
+    return x;
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn formats_void_functions_and_expression_statements() {
+    let source = "void log(int a){println(a);return;} int main(){log(1) ;1+2; return 0;}";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "void log(int a)
+{
+    println(a);
+    return;
+}
+
+int main()
+{
+    log(1);
+    1 + 2;
+    return 0;
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn formats_switches() {
+    let source = "int main(int a){switch(a){case 1:a+=1;case -2:case 3: break;default:return a;}switch (a) {} return 0;}";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "int main(int a)
+{
+    switch (a)
+    {
+        case 1:
+            a += 1;
+        case -2:
+        case 3:
+            break;
+        default:
+            return a;
+    }
+    switch (a)
+    {
+    }
+    return 0;
+}
+"
+    );
+    assert_eq!(format(&formatted).unwrap(), formatted);
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn indents_inline_blocks() {
+    let source = "int main(int x){__asm {
+movl {x}, %eax
+      incl %eax
+
+  movl %eax, {x}   } __ir{} return x;}";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "int main(int x)
+{
+    __asm {
+        movl {x}, %eax
+        incl %eax
+
+        movl %eax, {x}
+    }
+    __ir {}
+    return x;
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn keeps_only_parentheses_that_matter() {
+    let source =
+        "int main(int a) { return (a | 1) & (a ^ 2) << ((a % 3) + 1) | (a & a) ^ a >> (a >> 1); }";
+    let formatted = format(source).unwrap();
+    assert_eq!(
+        formatted,
+        "int main(int a)
+{
+    return (a | 1) & (a ^ 2) << a % 3 + 1 | a & a ^ a >> (a >> 1);
+}
+"
+    );
+    assert_eq!(parsed(&formatted), parsed(source));
+}
+
+#[test]
+fn check_fails_on_unformatted_files() {
+    let dir = std::env::temp_dir().join(format!("crust-fmt-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.c");
+    fs::write(&path, "int main() { return 0; }").unwrap();
+
+    let fmt = |check: bool| {
+        let mut command = Command::new(CRUST);
+        command.arg("fmt").arg(&path);
+        if check {
+            command.arg("--check");
+        }
+        command.status().unwrap().code()
+    };
+    assert_eq!(fmt(true), Some(1));
+    assert_eq!(fmt(false), Some(0));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "int main()\n{\n    return 0;\n}\n"
+    );
+    assert_eq!(fmt(true), Some(0));
+}
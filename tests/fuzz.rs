@@ -0,0 +1,135 @@
+//! Runs the fuzzing entry points over mutations of the example programs, so that the inputs a
+//! fuzzer would find first are tried by every test run.
+
+use std::fs;
+
+/// Characters that start or make up tokens, which mutations insert more often than any others.
+const ALPHABET: &[&str] = &[
+    "(",
+    ")",
+    "{",
+    "}",
+    "[",
+    "]",
+    ";",
+    ",",
+    "\"",
+    "'",
+    "r#\"",
+    "\"#",
+    "/*",
+    "*/",
+    "//",
+    "\\",
+    "\\u",
+    "\\x",
+    "0x",
+    "0b",
+    "0o",
+    "1_",
+    "_",
+    ".",
+    "1.",
+    "e",
+    "#",
+    "@",
+    "$",
+    "\n",
+    " ",
+    "\t",
+    "é",
+    "🦀",
+    "int",
+    "float",
+    "struct",
+    "switch",
+    "case",
+    "default",
+    "return",
+    "void",
+    "const",
+    "macro",
+    "spawn",
+    "join",
+    "sizeof",
+    "4294967296",
+    "99999999999999999999",
+    "*",
+    "&",
+    "++",
+    "--",
+    "+=",
+    "<<",
+    ">>",
+    "?",
+    ":",
+    "!",
+    "\u{0}",
+    "\u{feff}",
+];
+
+/// A xorshift generator, so that the mutations are the same on every run.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+fn mutate(rng: &mut Rng, source: &str) -> String {
+    let mut chars = source.chars().collect::<Vec<_>>();
+    for _ in 0..=rng.below(4) {
+        let at = rng.below(chars.len() + 1);
+        match rng.below(3) {
+            0 => {
+                let insert = ALPHABET[rng.below(ALPHABET.len())];
+                chars.splice(at..at, insert.chars());
+            }
+            1 => {
+                let end = (at + rng.below(8)).min(chars.len());
+                chars.drain(at..end);
+            }
+            _ => {
+                let end = (at + rng.below(16)).min(chars.len());
+                let copy = chars[at..end].to_vec();
+                let to = rng.below(chars.len() + 1);
+                chars.splice(to..to, copy);
+            }
+        }
+    }
+    chars.into_iter().collect()
+}
+
+#[test]
+fn parsing_never_panics() {
+    // set CRUST_FUZZ_ITERATIONS to try more than every run does
+    let iterations = std::env::var("CRUST_FUZZ_ITERATIONS").map_or(500, |n| n.parse().unwrap());
+    let mut seeds = fs::read_dir("cases")
+        .unwrap()
+        .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect::<Vec<_>>();
+    seeds.sort();
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for i in 0..iterations {
+        let seed = &seeds[i % seeds.len()];
+        let input = mutate(&mut rng, seed);
+        if let Err(panic) = std::panic::catch_unwind(|| crust::fuzz::parse(input.as_bytes())) {
+            panic!("parsing {input:?} panicked: {panic:?}");
+        }
+    }
+}
+
+#[test]
+fn parsing_arbitrary_bytes_never_panics() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..200 {
+        let bytes = (0..rng.below(64))
+            .map(|_| rng.below(256) as u8)
+            .collect::<Vec<_>>();
+        crust::fuzz::parse(&bytes);
+    }
+}
@@ -0,0 +1,138 @@
+//! Tests for globals and constants, which are set before `main` and visible in every function.
+
+use crust::{format, pipeline};
+
+mod common;
+
+/// The codes of the errors compiling `source`.
+fn errors(source: &str) -> Vec<&'static str> {
+    match pipeline::compile(source, "main.c") {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors.iter().map(|error| error.code).collect(),
+    }
+}
+
+#[test]
+fn constants_are_visible_in_every_function() {
+    let (stdout, stderr, code) = common::run(
+        "globals",
+        "constants",
+        "const int N = 10;
+const int M = N * 2 + 1;
+const double HALF = 1 / 2.0;
+const string NAME = \"crust\";
+int twice(int x) { return x * N; }
+int main() {
+    println(N, M, HALF, NAME, twice(3), sizeof(int) * N);
+    return M;
+}",
+    );
+    assert_eq!(stderr, "");
+    assert!(stdout.starts_with("10 21 0.5 crust 30 40\n"), "{stdout}");
+    assert_eq!(code, Some(21));
+}
+
+#[test]
+fn globals_keep_their_values_across_calls() {
+    let (stdout, _, code) = common::run(
+        "globals",
+        "counter",
+        "int count;
+double total = 1;
+int bump(int by) {
+    count += by;
+    ++count;
+    total = total * 2;
+    return count;
+}
+int main() {
+    bump(0);
+    bump(2);
+    int* p = &count;
+    *p = *p + 100;
+    println(count, total);
+    return bump(1);
+}",
+    );
+    assert!(stdout.starts_with("104 4.0\n"), "{stdout}");
+    assert_eq!(code, Some(106));
+}
+
+#[test]
+fn locals_and_parameters_shadow_globals() {
+    let (stdout, _, code) = common::run(
+        "globals",
+        "shadowing",
+        "const int x = 1;
+int y = 2;
+int param(int x) { return x; }
+int main() {
+    int y = 5;
+    y = 6;
+    println(x, y, param(7));
+    int x = 3;
+    return x;
+}",
+    );
+    assert!(stdout.starts_with("1 6 7\n"), "{stdout}");
+    assert_eq!(code, Some(3));
+}
+
+#[test]
+fn constants_cant_be_assigned() {
+    for source in [
+        "const int N = 1; int main() { N = 2; return 0; }",
+        "const int N = 1; int main() { N += 2; return 0; }",
+        "const int N = 1; int main() { return ++N; }",
+        "const int N = 1; int main() { int* p = &N; return 0; }",
+    ] {
+        assert_eq!(errors(source), ["E0132"], "{source}");
+    }
+    // a local of the same name can be
+    assert_eq!(
+        errors("const int N = 1; int main() { int N = 2; N = 3; return N; }"),
+        [""; 0]
+    );
+}
+
+#[test]
+fn globals_are_set_to_constants() {
+    assert_eq!(errors("const int N; int main() { return 0; }"), ["E0134"]);
+    assert_eq!(
+        errors("int f() { return 1; } int x = f(); int main() { return 0; }"),
+        ["E0133"]
+    );
+    assert_eq!(
+        errors("int x = 1; const int y = x; int main() { return 0; }"),
+        ["E0133"]
+    );
+    assert_eq!(
+        errors("const int y = x; const int x = 1; int main() { return 0; }"),
+        ["E0101"]
+    );
+    assert_eq!(
+        errors("const int x = 1; int x = 2; int main() { return 0; }"),
+        ["E0104"]
+    );
+    assert_eq!(
+        errors("const int x = 1; int y = x + 1; int main() { return 0; }"),
+        [""; 0]
+    );
+}
+
+#[test]
+fn formats_globals() {
+    let source = "const   int N=10;\nint  total ;\nint main(){return N;}";
+    let formatted = format::format(source).unwrap();
+    assert!(
+        formatted.starts_with("const int N = 10;\nint total;\n\nint main()\n"),
+        "{formatted}"
+    );
+    assert_eq!(format::format(&formatted).unwrap(), formatted);
+}
+
+#[test]
+fn native_backends_report_globals_as_unsupported() {
+    let program = pipeline::compile("const int N = 1; int main() { return N; }", "main.c").unwrap();
+    common::assert_unsupported(&program.ast, "global");
+}
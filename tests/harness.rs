@@ -0,0 +1,105 @@
+//! Tests for `crust test`, which runs a directory of programs against the expectations in their
+//! comments.
+
+use std::{fs, process::Command};
+
+use crust::harness::Expectations;
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+#[test]
+fn reads_expectations_from_comments() {
+    let expect = Expectations::parse(
+        "// expect-exit: 7
+int main() {
+    // expect-output: hello
+    //expect-output:   indented
+    // expect-output:
+    return 7;
+}",
+    )
+    .unwrap();
+    assert_eq!(expect.exit, Some(7));
+    assert_eq!(
+        expect.output.as_deref(),
+        Some(&["hello", "  indented", ""].map(String::from)[..])
+    );
+
+    assert!(Expectations::parse("int main() { return 0; }")
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        Expectations::parse("// expect-exit: seven").unwrap_err(),
+        "line 1: `seven` isn't an exit code"
+    );
+    assert!(Expectations::parse("// expect-exit: 1\n// expect-exit: 2").is_err());
+}
+
+#[test]
+fn passes_the_cases() {
+    let output = Command::new(CRUST)
+        .args(["test", "cases", "--jobs", "2"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(0), "{stdout}");
+    assert!(stdout.starts_with("TEST "), "{stdout}");
+    assert!(
+        stdout.ends_with("\n6 passed, 0 failed, 0 skipped\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn reports_what_failed() {
+    let dir = std::env::temp_dir().join(format!("crust-harness-{}", std::process::id()));
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    fs::write(
+        dir.join("pass.c"),
+        "// expect-output: 1 2\n// expect-output: done\n// expect-exit: 3\nint main() { println(1, 2); print(\"done\"); return 3; }",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("nested/fail.c"),
+        "// expect-output: 4\nint main() { println(2 + 3); return 1; }",
+    )
+    .unwrap();
+    fs::write(dir.join("skip.c"), "int main() { return 0; }").unwrap();
+    fs::write(dir.join("notes.txt"), "// expect-exit: 1").unwrap();
+
+    let output = Command::new(CRUST).arg("test").arg(&dir).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+
+    let rows = stdout
+        .lines()
+        .skip(1)
+        .take(3)
+        .map(|row| row.split_whitespace().take(2).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        [
+            ["nested/fail.c", "FAILED"],
+            ["pass.c", "ok"],
+            ["skip.c", "skipped"]
+        ]
+    );
+    assert!(
+        stdout.contains(
+            "---- nested/fail.c ----
+expected output:
+  4
+found output:
+  5
+"
+        ),
+        "{stdout}"
+    );
+    // the exit code wasn't expected, so it isn't a failure
+    assert!(!stdout.contains("exit code"), "{stdout}");
+    assert!(
+        stdout.ends_with("\n1 passed, 1 failed, 1 skipped\n"),
+        "{stdout}"
+    );
+}
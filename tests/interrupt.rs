@@ -0,0 +1,83 @@
+//! Tests for stopping `crust run` with Ctrl-C, which ends the program at the next statement with
+//! a backtrace instead of killing it.
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    process::{Command, Output, Stdio},
+    thread,
+    time::Duration,
+};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+/// Counts its calls for far longer than any test waits, telling the test when it's started.
+const SOURCE: &str = "int fib(int n) {
+    return n < 2 ? n : fib(n - 1) + fib(n - 2);
+}
+
+int main() {
+    println(\"ready\");
+    return fib(60);
+}
+";
+
+/// Runs [`SOURCE`] with `flags`, sending SIGINT once it's printed that it's ready.
+fn interrupt(name: &str, flags: &[&str]) -> (Output, String) {
+    let dir = std::env::temp_dir().join(format!("crust-interrupt-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{name}.c"));
+    fs::write(&path, SOURCE).unwrap();
+
+    let mut child = Command::new(CRUST)
+        .arg("run")
+        .args(flags)
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut ready = String::new();
+    BufReader::new(child.stdout.as_mut().unwrap())
+        .read_line(&mut ready)
+        .unwrap();
+    assert_eq!(ready, "ready\n");
+    // long enough to be deep in the calls
+    thread::sleep(Duration::from_millis(200));
+
+    // SAFETY: the child hasn't been waited for, so its id is still its own
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    (output, stderr)
+}
+
+#[test]
+fn stops_the_interpreter_with_a_backtrace_and_profile() {
+    let (output, stderr) = interrupt("interpreter", &["--profile"]);
+    assert_eq!(output.status.code(), Some(130), "{stderr}");
+    assert!(stderr.contains("[E0205] Error: the program was interrupted"));
+    assert!(stderr.contains("stopped here"));
+    assert!(stderr.contains("0: fib ("), "{stderr}");
+    assert!(stderr.contains("1: main"), "{stderr}");
+
+    // the profile counts the calls made before the program stopped
+    let profile = stderr
+        .lines()
+        .skip_while(|line| !line.starts_with("FUNCTION"))
+        .take(3)
+        .collect::<Vec<_>>();
+    assert_eq!(profile.len(), 3, "{stderr}");
+    assert!(profile[1].starts_with("fib "));
+    assert!(profile[2].starts_with("main "));
+}
+
+#[test]
+fn stops_the_vm() {
+    let (output, stderr) = interrupt("vm", &["--vm"]);
+    assert_eq!(output.status.code(), Some(130), "{stderr}");
+    assert!(stderr.contains("[E0205] Error: the program was interrupted"));
+    assert!(stderr.contains("0: fib ("), "{stderr}");
+    assert!(stderr.contains("1: main"), "{stderr}");
+}
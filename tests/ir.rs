@@ -0,0 +1,145 @@
+//! Tests for the three-address code IR.
+
+use crust::{
+    ir::{self, BlockId, Inst, Operand, Temp, Terminator},
+    pipeline, tac,
+};
+
+fn lower(source: &str) -> ir::Program {
+    let program = pipeline::compile(source, "main.c").unwrap();
+    ir::lower(&program.ast).unwrap()
+}
+
+const SOURCE: &str = "int sq(int x) { return x * x; }
+    int main(int i) {
+        string words[2];
+        words[i] = \"w\";
+        int _p = println(sq(i), words[0], len(words[1]));
+        return 0;
+    }";
+
+#[test]
+fn lowered_programs_verify() {
+    assert_eq!(ir::verify(&lower(SOURCE)), Vec::<String>::new());
+}
+
+#[test]
+fn reports_broken_invariants() {
+    let mut program = lower(SOURCE);
+    let main = program.funcs.iter_mut().find(|f| f.name == "main").unwrap();
+    main.blocks[0].insts.push(Inst::Copy {
+        dest: Temp(0),
+        src: Operand::Str(String::from("s")),
+    });
+    main.blocks[0].terminator = Terminator::Jump(BlockId(9));
+
+    assert_eq!(
+        ir::verify(&program),
+        [
+            "in main bb0: %0 = \"s\": %0 is assigned more than once",
+            "in main bb0: %0 = \"s\": %0 has type int, not string",
+            "in main bb0: jump bb9: bb9 doesn't exist",
+        ]
+    );
+}
+
+#[test]
+fn conditionals_join_with_phis() {
+    let mut program = lower(
+        "int main(int a) {
+            int x = 0;
+            int y = a < 0 ? ++x : 7;
+            return x + y;
+        }",
+    );
+    assert_eq!(ir::verify(&program), Vec::<String>::new());
+    assert_eq!(
+        program.funcs[0].to_string(),
+        "func main(int %0) -> int {
+    var int %0 a
+    var int %1 x
+    var int %3 x
+    var int %5 x
+    var int %6 y
+bb0:
+    %1 = 0
+    %2 = lt %0, 0
+    branch %2, bb1, bb2
+bb1:
+    %3 = add %1, 1
+    jump bb3
+bb2:
+    jump bb3
+bb3:
+    %4 = phi [bb1: %3], [bb2: 7]
+    %5 = phi [bb1: %3], [bb2: %1]
+    %6 = %4
+    %7 = add %5, %6
+    ret %7
+bb4:
+    missing_return
+}"
+    );
+
+    let main = &mut program.funcs[0];
+    main.blocks[3].insts.swap(1, 2);
+    main.blocks[2].terminator = Terminator::Return(Some(Operand::Int(0)));
+    assert_eq!(
+        ir::verify(&program),
+        [
+            "in main bb3: %4 = phi [bb1: %3], [bb2: 7]: bb2 doesn't jump to bb3",
+            "in main bb3: %5 = phi [bb1: %3], [bb2: %1]: follows an instruction that isn't a phi",
+            "in main bb3: %5 = phi [bb1: %3], [bb2: %1]: bb2 doesn't jump to bb3",
+        ]
+    );
+}
+
+#[test]
+fn lists_three_address_code() {
+    let program = lower(
+        "int sq(int x) { return x * x; }
+        int main(int a) {
+            string s[2];
+            s[0] = \"hi\";
+            int b = a * 2 - sq(a + 1);
+            int c = a > 2 ? ++b : -b;
+            println(s[0], c);
+            return b;
+        }",
+    );
+    assert_eq!(
+        tac::emit(&program),
+        "sq(x):
+    t1 = x * x
+    return t1
+
+main(a):
+    fill s, \"\"
+    s[0] = \"hi\"
+    t1 = a * 2
+    t2 = a + 1
+    param t2
+    t3 = call sq, 1
+    t4 = t1 - t3
+    b = t4
+    t5 = a > 2
+    ifFalse t5 goto L2
+L1:
+    b.1 = b + 1
+    t7 = b.1
+    b.2 = b.1
+    goto L3
+L2:
+    t6 = -b
+    t7 = t6
+    b.2 = b
+L3:
+    c = t7
+    t8 = s[0]
+    param t8
+    param c
+    call println, 2
+    return b.2
+"
+    );
+}
@@ -0,0 +1,81 @@
+//! Tests for `run --isolate`, which runs the program in a child process with resource limits.
+#![cfg(unix)]
+
+use std::{fs, process::Command};
+
+use serde_json::Value;
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+/// Runs `source` isolated with `limits`, returning its exit code, stdout and the JSON
+/// diagnostics the parent reported.
+fn isolated(name: &str, source: &str, limits: &[&str]) -> (Option<i32>, String, Vec<Value>) {
+    let dir = std::env::temp_dir().join(format!("crust-isolate-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{name}.c"));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(CRUST)
+        .args(["--error-format", "json", "run", "--isolate"])
+        .args(limits)
+        .arg(&path)
+        .args(["--", "an", "argument"])
+        .output()
+        .unwrap();
+    let diagnostics = String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+        diagnostics,
+    )
+}
+
+#[test]
+fn runs_programs_within_their_limits() {
+    let (code, stdout, diagnostics) = isolated(
+        "within",
+        "int main() { println(arg(1)); return argc(); }",
+        &[],
+    );
+    assert_eq!(code, Some(2));
+    assert_eq!(stdout, "argument\n-- exited with code : 2 --\n");
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn reports_programs_killed_for_their_cpu_time() {
+    let (code, stdout, diagnostics) = isolated(
+        "cpu",
+        "int fib(int n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); }
+int main() { return fib(50); }",
+        &["--cpu-limit", "1"],
+    );
+    assert_eq!(code, Some(255));
+    assert_eq!(stdout, "");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "E0204");
+    assert_eq!(
+        diagnostics[0]["message"],
+        "the program was killed after using 1 seconds of CPU time"
+    );
+}
+
+#[test]
+fn reports_programs_killed_for_their_memory() {
+    let (code, _, diagnostics) = isolated(
+        "memory",
+        "string grow(string s, int n) { return n == 0 ? s : grow(s + s, n - 1); }
+int main() { return len(grow(\"x\", 40)); }",
+        &["--memory-limit", "200"],
+    );
+    assert_eq!(code, Some(255));
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0]["message"],
+        "the program was killed after running out of its 200 MB of memory"
+    );
+}
@@ -0,0 +1,138 @@
+//! Tests for the diagnostics reported while lexing.
+
+use crust::{pipeline, Diagnostic};
+
+fn errors(source: &str) -> Vec<Diagnostic> {
+    pipeline::compile(source, "main.c").unwrap_err()
+}
+
+/// The span and text of each of a diagnostic's labels.
+fn labels(diagnostic: &Diagnostic) -> Vec<(std::ops::Range<usize>, String)> {
+    diagnostic
+        .labels
+        .iter()
+        .map(|label| (label.span.clone(), label.message.to_string()))
+        .collect()
+}
+
+#[test]
+fn unterminated_strings_point_at_their_start() {
+    let source = "int main() {\n  string s = \"abc;\n  return 0;\n}\n";
+    let errors = errors(source);
+    // the rest of the file is inside the literal, so it isn't parsed
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message.to_string(), "Unterminated string literal");
+    let end = source.len();
+    assert_eq!(
+        labels(&errors[0]),
+        [
+            (26..27, String::from("string literal started here")),
+            (end..end, String::from("the file ends before it's closed")),
+        ]
+    );
+    assert_eq!(errors[0].notes[0].to_string(), "add a closing `\"`");
+
+    // a trailing backslash escapes nothing
+    let errors = self::errors("int main() { string s = \"ab\\");
+    assert_eq!(labels(&errors[0])[0].0, 24..25);
+}
+
+#[test]
+fn unterminated_raw_strings_point_at_their_start() {
+    for (source, open) in [
+        ("int main() { string s = r\"abc", 24..26),
+        ("int main() { string s = r##\"a\"#; return 0; }", 24..28),
+    ] {
+        let errors = errors(source);
+        assert_eq!(errors.len(), 1, "{source}");
+        assert_eq!(
+            errors[0].message.to_string(),
+            "Unterminated raw string literal"
+        );
+        assert_eq!(
+            labels(&errors[0])[0],
+            (open, String::from("raw string literal started here"))
+        );
+    }
+    pipeline::compile("int main() { string s = r#\"a\"b\"#; return 0; }", "main.c").unwrap();
+}
+
+#[test]
+fn block_comments_nest() {
+    let source = "/* a\n /* b */ c */ int main() { return /* 1 */ 2 /* /* */ */; }";
+    let program = pipeline::compile(source, "main.c").unwrap();
+    assert_eq!(program.ast.run_main(&[]).unwrap(), 2);
+}
+
+#[test]
+fn unterminated_block_comments_point_at_their_start() {
+    let source = "int main() {\n  /* a /* b */\n  return 0;\n}\n";
+    let errors = errors(source);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message.to_string(), "Unterminated block comment");
+    let end = source.len();
+    assert_eq!(
+        labels(&errors[0]),
+        [
+            (15..17, String::from("block comment started here")),
+            (end..end, String::from("the file ends before it's closed")),
+        ]
+    );
+    assert!(errors[0].notes[0]
+        .to_string()
+        .contains("block comments nest"));
+}
+
+#[test]
+fn stray_characters_are_named() {
+    let errors = errors("int main() { int x = 1 @ 2; return x; }");
+    assert_eq!(errors[0].code, "E0001");
+    assert_eq!(
+        labels(&errors[0]),
+        [(23..24, String::from("`@` can't start a token"))]
+    );
+    assert!(errors[0].notes[0].to_string().contains("`<>+-=*/!%&|^?:`"));
+}
+
+#[test]
+fn ints_may_be_hex_binary_or_octal_and_separated() {
+    let source = "int main() { return 0xFF + 0B1010 + 0o755 + 1_000_000 + 0x_7f_ff; }";
+    let program = pipeline::compile(source, "main.c").unwrap();
+    assert_eq!(
+        program.ast.run_main(&[]).unwrap(),
+        255 + 10 + 493 + 1_000_000 + 0x7fff
+    );
+
+    let source = "int main() { return 1_0.2_5 * 4; }";
+    let program = pipeline::compile(source, "main.c").unwrap();
+    assert_eq!(program.ast.run_main(&[]).unwrap(), 41);
+}
+
+#[test]
+fn invalid_ints_are_reported_at_their_span() {
+    let errors = errors("int main() { return 0b102 + 0x + 0xFFFFFFFFF; }");
+    let reported = errors
+        .iter()
+        .map(|error| (error.code, error.message.to_string(), labels(error)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reported,
+        [
+            (
+                "E0006",
+                String::from("Invalid int literal `0b102`"),
+                vec![(20..25, String::from("`2` isn't a digit in base 2"))]
+            ),
+            (
+                "E0006",
+                String::from("Invalid int literal `0x`"),
+                vec![(28..30, String::from("there are no digits after the prefix"))]
+            ),
+            (
+                "E0005",
+                String::from("Int literal `0xFFFFFFFFF` is too large"),
+                vec![(33..44, String::from("doesn't fit in an int"))]
+            ),
+        ]
+    );
+}
@@ -0,0 +1,91 @@
+//! Tests for the warnings about unused variables and functions and unreachable code.
+
+use std::{fs, process::Command};
+
+use crust::{lint, pipeline};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+/// The code and message of each warning about `source`, which must compile.
+fn warnings(source: &str) -> Vec<String> {
+    let program = pipeline::compile(source, "main.c").unwrap();
+    lint::check(&program.sources, &program.ast)
+        .into_iter()
+        .map(|warning| {
+            assert!(!warning.is_error());
+            format!("{}: {}", warning.code, warning.message)
+        })
+        .collect()
+}
+
+#[test]
+fn warns_about_unused_and_unreachable_code() {
+    assert_eq!(
+        warnings(
+            "int unused(int x) { return x; }
+int twice(int n) {
+    int tmp = n;
+    int _ignored = 1;
+    return n * 2;
+    println(\"never\");
+}
+int main() {
+    int a = 1;
+    int a = a + 1;
+    string s[3];
+    s[0] = \"stored\";
+    return twice(a);
+}"
+        ),
+        [
+            "W0102: Function 'unused' is never called from main",
+            "W0101: Variable 'tmp' is assigned but never read",
+            "W0103: Unreachable code in 'twice'",
+            "W0101: Variable 's' is assigned but never read",
+        ]
+    );
+
+    // functions called only by functions main calls are used, and so is a variable that's only
+    // read by an inline block or incremented
+    assert!(warnings(
+        "int inner() { return 1; }
+int outer() { return inner(); }
+int main() {
+    int a = 0;
+    int b = 0;
+    __asm { incl {a} }
+    return outer() + ++b;
+}"
+    )
+    .is_empty());
+}
+
+#[test]
+fn denies_warnings() {
+    let dir = std::env::temp_dir().join(format!("crust-lint-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("main.c");
+    fs::write(&input, "int main() { int unused = 1; return 0; }").unwrap();
+
+    let check = |deny: bool| {
+        let mut command = Command::new(CRUST);
+        command
+            .args(["check", "--error-format", "json"])
+            .arg(&input);
+        if deny {
+            command.arg("--deny-warnings");
+        }
+        command.output().unwrap()
+    };
+
+    let warned = check(false);
+    assert!(warned.status.success());
+    let stderr = String::from_utf8(warned.stderr).unwrap();
+    assert!(stderr.contains(r#""severity":"warning","code":"W0101""#));
+
+    let denied = check(true);
+    assert!(!denied.status.success());
+    let stderr = String::from_utf8(denied.stderr).unwrap();
+    assert!(stderr.contains(r#""severity":"error","code":"W0101""#));
+    assert!(stderr.contains("--deny-warnings"));
+}
@@ -0,0 +1,135 @@
+//! Tests for the language server, driven through in-memory input and output.
+
+use crust::{lsp, messages::Locale};
+use serde_json::{json, Value};
+
+const URI: &str = "file:///work/main%20file.c";
+
+/// Frames each of `messages` with its `Content-Length` header.
+fn frame(messages: &[Value]) -> Vec<u8> {
+    messages
+        .iter()
+        .flat_map(|message| {
+            let body = message.to_string();
+            format!("Content-Length: {}\r\n\r\n{body}", body.len()).into_bytes()
+        })
+        .collect()
+}
+
+/// Serves `messages` followed by `exit`, returning everything the server wrote.
+fn serve(messages: &[Value]) -> Vec<Value> {
+    let mut input = frame(messages);
+    input.extend(frame(&[json!({ "jsonrpc": "2.0", "method": "exit" })]));
+    let mut output = Vec::new();
+    lsp::serve(&input[..], &mut output, &[], Locale::En).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    output
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| {
+            let (_, body) = message.split_once("\r\n\r\n").unwrap();
+            serde_json::from_str(body).unwrap()
+        })
+        .collect()
+}
+
+fn open(text: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": { "textDocument": { "uri": URI, "languageId": "crust", "version": 1, "text": text } },
+    })
+}
+
+fn request(id: u64, method: &str, line: u64, character: u64) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": {
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        },
+    })
+}
+
+#[test]
+fn publishes_diagnostics_on_change() {
+    let change = json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "int main() {\n    return 0;\n}" }],
+        },
+    });
+    let output = serve(&[
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+        open("int main() {\n    int unused = 1;\n    return b;\n}"),
+        change,
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+    ]);
+
+    assert_eq!(output[0]["id"], 1);
+    assert_eq!(output[0]["result"]["capabilities"]["hoverProvider"], true);
+
+    let diagnostics = &output[1]["params"]["diagnostics"];
+    assert_eq!(output[1]["params"]["uri"], URI);
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+    assert_eq!(diagnostics[0]["code"], "E0101");
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({ "start": { "line": 2, "character": 11 }, "end": { "line": 2, "character": 12 } })
+    );
+    assert_eq!(
+        diagnostics[0]["relatedInformation"][0]["location"]["uri"],
+        URI
+    );
+
+    // fixed, with nothing left to warn about
+    assert_eq!(output[2]["params"]["diagnostics"], json!([]));
+    assert_eq!(
+        output[3],
+        json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+    );
+}
+
+#[test]
+fn hovers_and_goes_to_definitions() {
+    let output = serve(&[
+        open(
+            "int sq(int x) {\n    return x * x;\n}\nint main() {\n    int a = 2;\n    int a = sq(a);\n    println(a);\n    return a;\n}",
+        ),
+        // the `x` read in sq, the `a` passed to sq, and the call to sq
+        request(1, "textDocument/hover", 1, 11),
+        request(2, "textDocument/definition", 5, 15),
+        request(3, "textDocument/definition", 5, 13),
+        request(4, "textDocument/hover", 6, 6),
+        request(5, "textDocument/hover", 7, 0),
+    ]);
+
+    // a warning-free program publishes no diagnostics
+    assert_eq!(output[0]["params"]["diagnostics"], json!([]));
+    assert_eq!(
+        output[1]["result"]["contents"]["value"],
+        "```crust\nparameter x: int\n```"
+    );
+    // the argument is the `a` declared before the one it initializes
+    assert_eq!(
+        output[2]["result"]["range"]["start"],
+        json!({ "line": 4, "character": 8 })
+    );
+    assert_eq!(output[2]["result"]["uri"], URI);
+    assert_eq!(
+        output[3]["result"]["range"],
+        json!({ "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 6 } })
+    );
+    assert_eq!(
+        output[4]["result"]["contents"]["value"],
+        "```crust\nbuiltin println\n```"
+    );
+    // nothing is named at the start of `return`
+    assert_eq!(output[5]["result"], Value::Null);
+}
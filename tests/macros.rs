@@ -0,0 +1,103 @@
+//! Tests for expanding macros, behind the `macros` language feature.
+
+use crust::{
+    pipeline::{self, Feature},
+    sources::SourceMap,
+    telemetry::Timings,
+    Ast, Diagnostic,
+};
+
+fn compile(source: &str, features: &[Feature]) -> Result<Ast, Vec<Diagnostic>> {
+    pipeline::compile_timed(
+        &mut SourceMap::default(),
+        source,
+        "main.c",
+        features,
+        &mut Timings::default(),
+    )
+}
+
+fn errors(source: &str) -> Vec<String> {
+    compile(source, &[Feature::Macros])
+        .unwrap_err()
+        .iter()
+        .map(|error| format!("{}: {}", error.code, error.message))
+        .collect()
+}
+
+#[test]
+fn expands_hygienically() {
+    let ast = compile(
+        "macro swap(a, b) { int t = a; a = b; b = t; }
+        macro scale(n, x) { int t = n; x *= t; swap!(x, t); }
+        int main(int x, int t) {
+            swap!(x, t);
+            scale!(t + 1, x);
+            return x * 100 + t;
+        }",
+        &[Feature::Macros],
+    )
+    .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "func main(int x, int t) -> int
+  (let int t.1 x)
+  (= x t)
+  (= t t.1)
+  (let int t.2 (+ t 1))
+  (*= x t.2)
+  (let int t.3 x)
+  (= x t.2)
+  (= t.2 t.3)
+  (return (+ (* x 100) t))
+"
+    );
+    let args = [String::from("3"), String::from("4")];
+    assert_eq!(ast.run_main(&args).unwrap(), 403);
+}
+
+#[test]
+fn needs_the_feature() {
+    let errors = compile(
+        "macro twice(x) { x += x; }
+        int main(int a) { twice!(a); return a; }",
+        &[],
+    )
+    .unwrap_err();
+    assert_eq!(errors.len(), 2);
+    for error in &errors {
+        assert_eq!(error.code, "E0108");
+        assert!(error.notes[0].to_string().contains("--features macros"));
+    }
+}
+
+#[test]
+fn reports_bad_macros_and_expansions() {
+    assert_eq!(
+        errors("macro add(a) { a += b; } int main() { return 0; }"),
+        ["E0112: Macro 'add' uses 'b', which is neither its parameter nor declared in it"]
+    );
+    assert_eq!(
+        errors(
+            "macro inc(a) { a += 1; }
+            int main(int x) { inc!(x + 1); inc!(); dec!(x); return x; }"
+        ),
+        [
+            "E0113: Macro 'inc' assigns to its parameter 'a', whose argument isn't a variable",
+            "E0110: Macro 'inc' takes 1 arguments but 0 were supplied",
+            "E0109: Unknown macro 'dec'",
+        ]
+    );
+    let errors = compile(
+        "macro ping(a) { pong!(a); }
+        macro pong(a) { ping!(a); }
+        int main() { ping!(1); return 0; }",
+        &[Feature::Macros],
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].code, "E0111");
+    assert_eq!(
+        errors[0].notes[0].to_string(),
+        "expanded through ping! -> pong! -> ping!"
+    );
+}
@@ -0,0 +1,85 @@
+//! Tests for the diagnostic message catalog.
+
+use std::collections::BTreeSet;
+
+use crust::{
+    messages::{Locale, Message, CATALOG},
+    pipeline,
+};
+
+/// The names of the parameters a template uses.
+fn params(template: &str) -> BTreeSet<&str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .collect()
+}
+
+#[test]
+fn translations_use_the_same_parameters() {
+    let mut keys = BTreeSet::new();
+    for template in CATALOG {
+        assert!(keys.insert(template.key), "{} is duplicated", template.key);
+        assert!(
+            !template.es.is_empty(),
+            "{} has no translation",
+            template.key
+        );
+        assert_eq!(
+            params(template.en),
+            params(template.es),
+            "parameters of {}",
+            template.key
+        );
+    }
+}
+
+#[test]
+fn renders_nested_messages() {
+    let message = Message::new("E0400.unsupported-native")
+        .arg("feature", Message::new("feature.string-concatenation"));
+    assert_eq!(
+        message.to_string(),
+        "string concatenation is not supported by the native backend"
+    );
+    assert_eq!(
+        message.render(Locale::Es),
+        "la concatenación de cadenas no es compatible con el backend nativo"
+    );
+
+    let missing = Message::new("E0102");
+    assert_eq!(missing.render(Locale::En), "Unknown function '{name}'");
+    assert_eq!(
+        Message::Text(String::from("as written")).render(Locale::Es),
+        "as written"
+    );
+}
+
+#[test]
+fn translates_errors_at_run_time() {
+    let error = |source: &str| {
+        let program = pipeline::compile(source, "main.c").unwrap();
+        program.ast.run_main(&[]).unwrap_err().message
+    };
+
+    let division = error("int main() { int zero = 0; return 1 / zero; }");
+    assert_eq!(division.to_string(), "attempt to divide by zero");
+    assert_eq!(division.render(Locale::Es), "intento de dividir entre cero");
+
+    let bounds = error("int main() { int a[2]; return a[2]; }");
+    assert_eq!(
+        bounds.render(Locale::Es),
+        "índice 2 fuera de los límites de un arreglo de longitud 2"
+    );
+
+    let builtin = error("int main() { string s = getenv(1); return 0; }");
+    assert_eq!(
+        builtin.to_string(),
+        "getenv expects a single string argument"
+    );
+    assert_eq!(
+        builtin.render(Locale::Es),
+        "getenv espera un único argumento cadena"
+    );
+}
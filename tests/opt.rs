@@ -0,0 +1,184 @@
+//! Tests for the AST optimizations, which must never change what a program does.
+
+mod common;
+
+use std::process::Command;
+
+use common::{interpret, write_source, CRUST};
+use crust::{opt, pipeline};
+
+fn optimized(source: &str, level: u8) -> String {
+    let mut program = pipeline::compile(source, "main.c").unwrap();
+    opt::optimize(&mut program.ast, level);
+    program.ast.to_string()
+}
+
+/// Runs `source` with `args` before and after optimizing, requiring the same result.
+fn same_result(source: &str, args: &[&str]) {
+    let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    let mut program = pipeline::compile(source, "main.c").unwrap();
+    let expected = program
+        .ast
+        .run_main(&args)
+        .map_err(|e| e.message.to_string());
+    opt::optimize(&mut program.ast, opt::MAX_LEVEL);
+    let found = program
+        .ast
+        .run_main(&args)
+        .map_err(|e| e.message.to_string());
+    assert_eq!(found, expected, "{source} with {args:?}");
+}
+
+#[test]
+fn folds_constants() {
+    assert_eq!(
+        optimized(
+            "int main(int x) {
+                int a = 2 * 3 + x;
+                int b = 0 - 2147483647 - 2;
+                int c = -(-(x * 2)) / 1 + 0;
+                string s = \"n\" + 4;
+                return 7 / 0;
+            }",
+            1
+        ),
+        "func main(int x) -> int
+  (let int a (+ 6 x))
+  (let int b (- (- 2147483647) 2))
+  (let int c (+ (* x 2) 0))
+  (let string s \"n4\")
+  (return (/ 7 0))
+"
+    );
+}
+
+#[test]
+fn leaves_arithmetic_that_depends_on_the_width_of_ints() {
+    assert_eq!(
+        optimized(
+            "int main(int x) {
+                int a = 2147483647 + 1 + (1 << 40) + (1 << 31) + (-16 >> 40);
+                int b = -(-2147483647 - 1) + (-2147483647 - 1) / -1 + 65536 * 65536 * 0;
+                int c = x + 2147483647 + -1;
+                return -2147483647 - 1;
+            }",
+            1
+        ),
+        "func main(int x) -> int
+  (let int a (+ (+ (+ (+ 2147483647 1) (<< 1 40)) (<< 1 31)) (>> (- 16) 40)))
+  (let int b (+ (+ (- (- 2147483648)) (/ (- 2147483648) (- 1))) (* (* 65536 65536) 0)))
+  (let int c (+ (+ x 2147483647) (- 1)))
+  (return (- 2147483648))
+"
+    );
+}
+
+#[test]
+fn folds_remainders_bits_and_shifts() {
+    assert_eq!(
+        optimized(
+            "int main(int x) {
+                int a = -7 % 2 + (6 & 3) + (6 | 3) + (6 ^ 3);
+                int b = 1 << 33 + (-16 >> 2);
+                int c = x % 0 + (x << 1);
+                return -2147483647 - 1 % -1;
+            }",
+            1
+        ),
+        "func main(int x) -> int
+  (let int a 13)
+  (let int b 536870912)
+  (let int c (+ (% x 0) (<< x 1)))
+  (return (- 2147483647))
+"
+    );
+}
+
+#[test]
+fn folds_comparisons_and_constant_conditions() {
+    assert_eq!(
+        optimized(
+            "int main(int x) {
+                int a = (1 < 2) + (-1 >= 0) + (3 == 3) + (3 != 3);
+                int b = 1 ? x : 1 / 0;
+                int c = 2 - 2 ? 1 / 0 : x;
+                return x ? 1 : 2;
+            }",
+            1
+        ),
+        "func main(int x) -> int
+  (let int a 2)
+  (let int b x)
+  (let int c x)
+  (return (? x 1 2))
+"
+    );
+}
+
+#[test]
+fn keeps_identities_on_values_that_may_be_strings() {
+    assert_eq!(
+        optimized(
+            "int main(string x) { string y = x + 0; int z = x * 1; return 0; }",
+            1
+        ),
+        "func main(string x) -> int
+  (let string y (+ x 0))
+  (let int z (* x 1))
+  (return 0)
+"
+    );
+}
+
+#[test]
+fn removes_statements_after_return() {
+    let source = "int main() { int a = 1; return a; int b = 2; }";
+    assert!(optimized(source, 1).contains("(let int b 2)"));
+    assert!(!optimized(source, 2).contains("(let int b 2)"));
+}
+
+#[test]
+fn preserves_behaviour() {
+    same_result(
+        "int main(int x) { return (x + 1 + 2) * 1 - -(-(x / 1)) + 2147483647 * 2; }",
+        &["-2147483648"],
+    );
+    same_result("int main(string x) { return x - 0; }", &["a"]);
+    same_result("int main() { return 1 / (2 - 2); }", &[]);
+    same_result(
+        "int main(int x) { return x < 0 ? -x : 1 ? x : 1 / 0; }",
+        &["-3"],
+    );
+    same_result(
+        "int main() { string s = \"a\" + 1 + 2; return len(s); }",
+        &[],
+    );
+    same_result(
+        "int main() { double x = -0.0; return (1 / (x + 0) < 0) * 10 + (1 / (-(-x * 1) / 1 - 0) < 0); }",
+        &[],
+    );
+}
+
+#[test]
+fn keeps_the_right_side_of_an_or_whose_left_side_is_false() {
+    let source = "int f() { println(7); return 0; }
+        int main() { int a = 1 || f(); int b = 0 || f(); return a * 10 + b; }";
+    assert!(optimized(source, 2).contains("(let int a 1)"));
+    assert!(optimized(source, 2).contains("(|| 0 (call f))"));
+
+    let path = write_source("opt", "or", source);
+    let ir = path.with_extension("json");
+    for level in ["0", "1", "2"] {
+        let built = Command::new(CRUST)
+            .arg("build")
+            .arg(&path)
+            .arg(&ir)
+            .args(["--opt-level", level])
+            .status()
+            .unwrap();
+        assert!(built.success());
+        assert_eq!(interpret(&ir, &[]), interpret(&path, &[]), "at -O{level}");
+    }
+    assert_eq!(interpret(&path, &[]), (String::from("7\n"), 10));
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
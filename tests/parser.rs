@@ -0,0 +1,521 @@
+//! Tests for the diagnostics reported while parsing.
+
+use crust::{delimiters, pipeline, suggest};
+
+/// The message and label of every diagnostic for `source`.
+fn errors(source: &str) -> Vec<(String, String)> {
+    pipeline::compile(source, "main.c")
+        .unwrap_err()
+        .into_iter()
+        .map(|error| {
+            (
+                error.message.to_string(),
+                error.labels[0].message.to_string(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn binds_operators_like_c() {
+    let ast = pipeline::parse_file(
+        "int main(int a) { return a | a ^ a & a << a + a * a % a - a >> -a; }",
+    )
+    .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "func main(int a) -> int
+  (return (| a (^ a (& a (>> (<< a (- (+ a (% (* a a) a)) a)) (- a))))))
+"
+    );
+}
+
+#[test]
+fn binds_comparisons_and_conditionals_like_c() {
+    let ast = pipeline::parse_file(
+        "int main(int a) { return a < a << 1 == a >= a | a != a ? a ? 1 : 2 : a > 0 ? -a : a <= 3; }",
+    )
+    .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "func main(int a) -> int
+  (return (? (| (== (< a (<< a 1)) (>= a a)) (!= a a)) (? a 1 2) (? (> a 0) (- a) (<= a 3))))
+"
+    );
+}
+
+#[test]
+fn parses_unary_operators_and_assignments() {
+    let ast = pipeline::parse_file(
+        "int main(int a) { a = 1; a += !a; a -= - --a; a *= ++a; a /= -!-a; return a; }",
+    )
+    .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "func main(int a) -> int
+  (= a 1)
+  (+= a (! a))
+  (-= a (- (-- a)))
+  (*= a (++ a))
+  (/= a (- (! (- a))))
+  (return a)
+"
+    );
+}
+
+#[test]
+fn parses_bare_returns_and_expression_statements() {
+    let ast = pipeline::parse_file("void log(int a) { println(a); a + 1; return; }").unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "func log(int a) -> void
+  (discard (call println a))
+  (discard (+ a 1))
+  (return)
+"
+    );
+}
+
+#[test]
+fn checks_void_functions() {
+    assert_eq!(
+        errors(
+            "void log(void a) { return a; }
+            int get() { return; }
+            void main() { int a = log(1); log(2); }"
+        ),
+        [
+            ("Function 'main' can't be void", "main defined here"),
+            ("Variable 'a' can't be void", "declared here"),
+            (
+                "Function 'log' is void but returns a value",
+                "returned here"
+            ),
+            (
+                "Function 'get' returns int but `return;` has no value",
+                "returned here"
+            ),
+            (
+                "Function 'log' is void, so its call has no value",
+                "called here"
+            ),
+        ]
+        .map(|(message, label)| (message.to_string(), label.to_string()))
+    );
+}
+
+#[test]
+fn resolves_calls_against_prototypes_and_definitions() {
+    let source = "int is_odd(int n);
+        int is_even(int n) { return n == 0 ? 1 : is_odd(n - 1); }
+        int is_odd(int n) { return n == 0 ? 0 : is_even(n - 1); }
+        int main() { return is_even(10); }";
+    let program = pipeline::compile(source, "main.c").unwrap();
+    assert_eq!(
+        program.ast.defs[0].to_string(),
+        "declare func is_odd(int n) -> int"
+    );
+    assert_eq!(program.ast.run_main(&[]).unwrap(), 1);
+
+    assert_eq!(
+        errors(
+            "int missing(int n);
+            void odd(int n, string s);
+            int odd(int n) { return n; }
+            int main() { int a = missing(1, 2); return odd(1); }"
+        ),
+        [
+            (
+                "Function 'missing' is declared but never defined",
+                "declared here"
+            ),
+            (
+                "Declaration of 'odd' doesn't match its definition",
+                "declared here"
+            ),
+            (
+                "Function 'missing' takes 1 arguments but 2 were supplied",
+                "incorrect number of arguments"
+            ),
+        ]
+        .map(|(message, label)| (message.to_string(), label.to_string()))
+    );
+}
+
+#[test]
+fn reports_names_defined_twice_where_both_are() {
+    let source = "struct P { int x; string x; };
+int f(int a, int b, int a);
+int f(int a, int b) { return a + b; }
+struct f { int y; };
+int main() { return 0; }
+int main() { return 1; }";
+    let errors = pipeline::compile(source, "main.c").unwrap_err();
+    let reported = errors
+        .iter()
+        .map(|error| {
+            let lines = error
+                .labels
+                .iter()
+                .map(|label| source[..label.span.start].lines().count())
+                .collect::<Vec<_>>();
+            (error.code, error.message.to_string(), lines)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reported,
+        [
+            ("E0127", "Struct 'P' has two fields named 'x'", vec![1, 1]),
+            (
+                "E0126",
+                "Function 'f' has two parameters named 'a'",
+                vec![2, 2]
+            ),
+            ("E0104", "Duplicate definition of 'f'", vec![3, 4]),
+            ("E0104", "Duplicate definition of 'main'", vec![5, 6]),
+            (
+                "E0120",
+                "Declaration of 'f' doesn't match its definition",
+                vec![2, 3]
+            ),
+        ]
+        .map(|(code, message, lines)| (code, message.to_string(), lines))
+    );
+    assert_eq!(
+        errors[2].labels[1].message.to_string(),
+        "defined again here"
+    );
+}
+
+#[test]
+fn parses_switches_with_fallthrough() {
+    let ast = pipeline::parse_file(
+        "int main(int a) { switch (a + 1) { case -1: a = 2; case 2: case 3: break; default: return a; } return 0; }",
+    )
+    .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "func main(int a) -> int
+  (switch (+ a 1) (case -1 (= a 2)) (case 2) (case 3 (break)) (default (return a)))
+  (return 0)
+"
+    );
+}
+
+#[test]
+fn checks_switches() {
+    let source = "int main(int a) {
+    switch (a) {
+        case 1:
+        case 2:
+            int b = 1;
+        case 1:
+            break;
+        default:
+        default:
+    }
+    break;
+    return 0;
+}";
+    let errors = pipeline::compile(source, "main.c").unwrap_err();
+    let reported = errors
+        .iter()
+        .map(|error| {
+            let labels = error
+                .labels
+                .iter()
+                .map(|label| {
+                    (
+                        source[..label.span.start].lines().count(),
+                        label.message.to_string(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            (error.code, error.message.to_string(), labels)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reported,
+        [
+            (
+                "E0128",
+                "Switch has two cases for 1",
+                vec![(3, "first case here"), (6, "same case again here")]
+            ),
+            (
+                "E0128",
+                "Switch has two `default` cases",
+                vec![(8, "first case here"), (9, "same case again here")]
+            ),
+            (
+                "E0130",
+                "Variable 'b' is declared in a switch",
+                vec![(5, "declared here")]
+            ),
+            (
+                "E0129",
+                "`break` outside of a switch",
+                vec![(11, "not inside a switch")]
+            ),
+        ]
+        .map(|(code, message, labels)| (
+            code,
+            message.to_string(),
+            labels
+                .into_iter()
+                .map(|(line, label)| (line, label.to_string()))
+                .collect()
+        ))
+    );
+}
+
+#[test]
+fn rejects_continue_outside_of_a_loop() {
+    let source = "int main(int a) { continue; switch (a) { case 1: continue; } return 0; }";
+    assert_eq!(
+        pipeline::parse_file(source).unwrap().to_string(),
+        "func main(int a) -> int
+  (continue)
+  (switch a (case 1 (continue)))
+  (return 0)
+"
+    );
+    let errors = pipeline::compile(source, "main.c").unwrap_err();
+    let reported = errors
+        .iter()
+        .map(|error| {
+            (
+                error.code,
+                error.labels[0].span.clone(),
+                error.notes.iter().map(ToString::to_string).collect(),
+            )
+        })
+        .collect::<Vec<(_, _, Vec<_>)>>();
+    assert_eq!(
+        reported,
+        [
+            ("E0131", 18..27, vec![]),
+            (
+                "E0131",
+                49..58,
+                vec![String::from(
+                    "a switch isn't a loop, so `continue` can't go on to its next case"
+                )]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn parses_inline_blocks_as_their_text() {
+    let ast = pipeline::parse_file(
+        "int main(int x) {
+            __asm { incl {x} }
+            __ir {
+                %s = alloca { i32, i32 }
+
+                store i32 0, i32* {x}
+            }
+            __asm{}
+            return x;
+        }",
+    )
+    .unwrap();
+    assert_eq!(
+        ast.to_string(),
+        "func main(int x) -> int
+  (__asm \"incl {x}\")
+  (__ir \"%s = alloca { i32, i32 }\\n\\nstore i32 0, i32* {x}\")
+  (__asm \"\")
+  (return x)
+"
+    );
+}
+
+#[test]
+fn measures_edit_distance() {
+    assert_eq!(suggest::levenshtein("return", "return"), 0);
+    assert_eq!(suggest::levenshtein("retrun", "return"), 2);
+    assert_eq!(suggest::levenshtein("retur", "return"), 1);
+    assert_eq!(suggest::levenshtein("", "abc"), 3);
+    assert_eq!(suggest::levenshtein("kitten", "sitting"), 3);
+    assert!(suggest::is_misspelling("strcut", "struct"));
+    assert!(!suggest::is_misspelling("struct", "struct"));
+    assert!(!suggest::is_misspelling("ab", "abcd"));
+}
+
+#[test]
+fn suggests_misspelled_keywords() {
+    let typo = |keyword: &str| {
+        (
+            String::from("Misspelled keyword"),
+            format!("did you mean `{keyword}`?"),
+        )
+    };
+    assert_eq!(
+        errors("int main() { int x = 1; retrun x + 1; }"),
+        [typo("return")]
+    );
+    assert_eq!(
+        errors("strcut P { int x; }; int main() { retun 0; }"),
+        [typo("struct"), typo("return")]
+    );
+
+    // identifiers that aren't close to a keyword fail like any other bad statement
+    let errors = errors("int main() { banana 0; }");
+    assert!(errors[0].1.starts_with("found \"0\" but expected one of"));
+    for expected in ["\"[\"", "\"=\"", "\"+=\""] {
+        assert!(errors[0].1.contains(expected));
+    }
+}
+
+#[test]
+fn recovers_from_bad_statements_and_expressions() {
+    let parse_error = |found: &str| {
+        (
+            String::from("Parser Error"),
+            format!("found \"{found}\" but expected one of \"!\", \"(\", \"*\", \"++\", \"--\", \"&\", \"-\""),
+        )
+    };
+    let undeclared = |name: &str| {
+        (
+            format!("Undeclared variable '{name}'"),
+            String::from("not found in this scope"),
+        )
+    };
+
+    // a bad statement is skipped to its `;`, and the statements after it are still checked
+    assert_eq!(
+        errors("int main() { int a = 1 + ; return a + b; }"),
+        [parse_error(";")]
+    );
+    let missing_semicolon = errors("int f() { int a = 1; a = a + 1 } int main() { return b; }");
+    assert_eq!(missing_semicolon.len(), 2);
+    assert!(missing_semicolon[0]
+        .1
+        .starts_with("found \"}\" but expected one of"));
+    assert_eq!(missing_semicolon[1], undeclared("b"));
+
+    // a bad parenthesized or index expression only replaces itself
+    assert_eq!(
+        errors("int main() { int a = (1 - ) * 2; string s[2]; s[a +] = \"x\"; return a + c; }"),
+        [parse_error(")"), parse_error("]"), undeclared("c")]
+    );
+}
+
+#[test]
+fn reports_unsupported_c_constructs() {
+    let unsupported = |message: &str| (message.to_string(), String::from("not supported"));
+
+    assert_eq!(
+        errors(
+            "#include <stdio.h>
+int sum(int* xs, int n) {
+    int total = 0;
+    for (int i = 0; i < n; i++) { total += xs[i]; }
+    do { n--; } while (n > 0);
+    return total;
+}
+int main() {
+    char *name = \"crust\";
+    if (1) { return 1; } else if (2) return 2; else { return 3; }
+    printf(\"%d\\n\", sum(0, 0));
+    return missing;
+}"
+        ),
+        [
+            unsupported("Preprocessor directives like `#include` aren't supported"),
+            unsupported("`for` loops aren't supported yet"),
+            unsupported("`do` loops aren't supported yet"),
+            unsupported("`if` statements aren't supported yet"),
+            (
+                String::from("Unknown function 'printf'"),
+                String::from("called here")
+            ),
+        ]
+    );
+
+    // multiplying two variables isn't mistaken for declaring a pointer
+    assert!(pipeline::compile(
+        "int main() { int a = 2; int b = 3; a * b; return a * b; }",
+        "main.c"
+    )
+    .is_ok());
+}
+
+/// The span and text of a label.
+type Label = (std::ops::Range<usize>, String);
+
+/// The code of every diagnostic for `source`, with its labels.
+fn delimiter_errors(source: &str) -> Vec<(&'static str, Vec<Label>)> {
+    pipeline::compile(source, "main.c")
+        .unwrap_err()
+        .into_iter()
+        .map(|error| {
+            let labels = error
+                .labels
+                .iter()
+                .map(|label| (label.span.clone(), label.message.to_string()))
+                .collect();
+            (error.code, labels)
+        })
+        .collect()
+}
+
+#[test]
+fn pairs_unbalanced_delimiters() {
+    let opened = |span, open: &str| (span, format!("`{open}` opened here"));
+    let expected = |span, close: &str| (span, format!("expected `{close}` before this"));
+
+    // an expression is never continued past a `;`
+    assert_eq!(
+        delimiter_errors("int main() {\n  int x = (1 + 2;\n  return x[0;\n}"),
+        [
+            ("E0003", vec![opened(23..24, "("), expected(29..30, ")")]),
+            ("E0003", vec![opened(41..42, "["), expected(43..44, "]")]),
+        ]
+    );
+
+    // a brace should close before the next line indented like the one it opened on
+    let source = "int main() {\n  return 0;\n\nint f() { return 1; }\n";
+    assert_eq!(
+        delimiter_errors(source),
+        [("E0003", vec![opened(11..12, "{"), expected(26..29, "}")])]
+    );
+
+    let errors = pipeline::compile("int main() {\n  return 0;", "main.c").unwrap_err();
+    assert_eq!(
+        errors[0].notes[0].to_string(),
+        "expected `}` by the end of the file"
+    );
+
+    assert_eq!(
+        delimiter_errors("int main() { return (0)); }"),
+        [(
+            "E0003",
+            vec![(23..24, String::from("there's no `(` for this to close"))]
+        )]
+    );
+}
+
+#[test]
+fn limits_how_deep_delimiters_nest() {
+    let nested = |depth| {
+        format!(
+            "int main() {{ return {}1{}; }}",
+            "(".repeat(depth),
+            ")".repeat(depth)
+        )
+    };
+    // the braces of `main` are one level
+    assert!(pipeline::compile(&nested(delimiters::MAX_DEPTH - 1), "main.c").is_ok());
+
+    let start = 20 + delimiters::MAX_DEPTH - 1;
+    assert_eq!(
+        delimiter_errors(&nested(delimiters::MAX_DEPTH * 10)),
+        [(
+            "E0003",
+            vec![(start..start + 1, String::from("nested too deeply here"))]
+        )]
+    );
+}
@@ -0,0 +1,109 @@
+//! Tests for pointers to variables, which let a function change its caller's variables, on the
+//! interpreter and the VM alike.
+
+use crust::pipeline;
+
+mod common;
+
+#[test]
+fn functions_change_variables_through_pointers() {
+    let source = "void swap(int* a, int* b) {
+    int t = *a;
+    *a = *b;
+    *b = t;
+}
+
+void divmod(int n, int d, int* q, int* r) {
+    *q = n / d;
+    *r = n % d;
+}
+
+int main() {
+    int x = 1;
+    int y = 2;
+    swap(&x, &y);
+    int q = 0;
+    int r = 0;
+    divmod(17, 5, &q, &r);
+    println(x, y, q, r);
+    string s = \"a\";
+    string* p = &s;
+    *p = *p + \"b\";
+    string** pp = &p;
+    **pp = **pp + \"c\";
+    println(s);
+    return 0;
+}
+";
+    let (stdout, stderr, code) = common::run("pointers", "swap", source);
+    assert_eq!(
+        stdout, "2 1 3 2\nabc\n-- exited with code : 0 --\n",
+        "{stderr}"
+    );
+    assert_eq!(code, Some(0));
+}
+
+#[test]
+fn pointers_reach_variables_of_any_caller() {
+    let source = "int bump(int* p) {
+    *p = *p + 1;
+    return *p;
+}
+
+int deep(int n, int* p) {
+    return n == 0 ? bump(p) : deep(n - 1, p);
+}
+
+int main() {
+    int x = 40;
+    deep(10, &x);
+    return deep(3, &x);
+}
+";
+    let (_, stderr, code) = common::run("pointers", "callers", source);
+    assert_eq!(code, Some(42), "{stderr}");
+}
+
+#[test]
+fn reports_pointers_to_variables_that_no_longer_exist() {
+    let source = "int* local() {
+    int x = 5;
+    return &x;
+}
+
+int other() {
+    int y = 7;
+    return y;
+}
+
+int main() {
+    int* p = local();
+    other();
+    return *p;
+}
+";
+    let (_, stderr, code) = common::run("pointers", "dangling", source);
+    assert_eq!(code, Some(255));
+    assert!(stderr.contains("the variable this pointer points to no longer exists"));
+    assert!(stderr.contains("return *p;"), "{stderr}");
+
+    let (_, stderr, _) = common::run(
+        "pointers",
+        "not-a-pointer",
+        "int main() { int x = 1; return *x; }",
+    );
+    assert!(stderr.contains("expected pointer, found int"), "{stderr}");
+}
+
+#[test]
+fn native_backends_report_pointers_as_unsupported() {
+    for source in [
+        "int main() { int x = 1; int* p = &x; return x; }",
+        "int get(int* p) { return 0; } int main() { return 0; }",
+        "int* f() { return 0; } int main() { return 0; }",
+        "int main() { int x = 1; *x = 2; return x; }",
+    ] {
+        let program = pipeline::compile(source, "main.c").unwrap();
+        common::assert_unsupported(&program.ast, "pointers");
+    }
+}
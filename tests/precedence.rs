@@ -0,0 +1,154 @@
+//! Tests for operator precedence and associativity, generated from the precedence table so
+//! every pair of operators is checked against the level and associativity it's listed with.
+
+use std::{cmp::Ordering, process::Command};
+
+use crust::{
+    format::format,
+    pipeline,
+    precedence::{self, Assoc, Ops, LEVELS},
+};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+/// Parses `source` as an expression and prints it in prefix form.
+fn parse(source: &str) -> String {
+    match pipeline::parse_expr(source) {
+        Ok((expr, _)) => expr.to_string(),
+        Err(errors) => panic!("`{source}` doesn't parse: {errors:?}"),
+    }
+}
+
+/// Every binary operator, with the index of its level and the level's associativity.
+fn binary_ops() -> Vec<(&'static str, usize, Assoc)> {
+    LEVELS
+        .iter()
+        .enumerate()
+        .flat_map(|(i, level)| match level.ops {
+            Ops::Binary(ops) => ops.iter().map(|(op, _)| (*op, i, level.assoc)).collect(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// The index of the level with `name`.
+fn level(name: &str) -> usize {
+    LEVELS.iter().position(|level| level.name == name).unwrap()
+}
+
+/// How `a op1 b op2 c` groups, from the levels and associativity in the table.
+fn grouping(
+    (op1, level1, assoc): (&str, usize, Assoc),
+    (op2, level2, _): (&str, usize, Assoc),
+) -> String {
+    let left = format!("({op2} ({op1} a b) c)");
+    let right = format!("({op1} a ({op2} b c))");
+    match level1.cmp(&level2) {
+        Ordering::Less => left,
+        Ordering::Greater => right,
+        Ordering::Equal if assoc == Assoc::Left => left,
+        Ordering::Equal => right,
+    }
+}
+
+#[test]
+fn binary_operators_group_by_level_and_associativity() {
+    for first in binary_ops() {
+        for second in binary_ops() {
+            let source = format!("a {} b {} c", first.0, second.0);
+            assert_eq!(parse(&source), grouping(first, second), "`{source}`");
+        }
+    }
+}
+
+#[test]
+fn prefix_and_postfix_operators_bind_tighter_than_binary_ones() {
+    let Ops::Prefix { ops, steps } = LEVELS[level("prefix")].ops else {
+        panic!("the prefix level has prefix operators");
+    };
+    for (op, _, _) in binary_ops() {
+        let prefixes = ops
+            .iter()
+            .map(|(op, _)| op)
+            .chain(steps.iter().map(|(op, _)| op));
+        for prefix in prefixes {
+            let source = format!("{prefix}a {op} b");
+            assert_eq!(
+                parse(&source),
+                format!("({op} ({prefix} a) b)"),
+                "`{source}`"
+            );
+        }
+        assert_eq!(
+            parse(&format!("a {op} b[c]")),
+            format!("({op} a (index b c))")
+        );
+        assert_eq!(
+            parse(&format!("a {op} f(b)")),
+            format!("({op} a (call f b))")
+        );
+    }
+
+    // postfix operators bind tighter than prefix ones, which nest
+    assert_eq!(parse("-a[b]"), "(- (index a b))");
+    assert_eq!(parse("!-f(b)"), "(! (- (call f b)))");
+    assert_eq!(parse("a[b][c]"), "(index (index a b) c)");
+}
+
+#[test]
+fn conditionals_are_loosest_and_right_associative() {
+    assert_eq!(level("conditional"), LEVELS.len() - 1);
+    for (op, _, _) in binary_ops() {
+        let source = format!("a {op} b ? c {op} d : e {op} f");
+        assert_eq!(
+            parse(&source),
+            format!("(? ({op} a b) ({op} c d) ({op} e f))"),
+            "`{source}`"
+        );
+    }
+    assert_eq!(parse("a ? b : c ? d : e"), "(? a b (? c d e))");
+    assert_eq!(parse("a ? b ? c : d : e"), "(? a (? b c d) e)");
+}
+
+#[test]
+fn formatter_parenthesizes_exactly_when_needed() {
+    for first in binary_ops() {
+        for second in binary_ops() {
+            let (op1, op2) = (first.0, second.0);
+            let bare = parse(&format!("a {op1} b {op2} c"));
+            for source in [
+                format!("(a {op1} b) {op2} c"),
+                format!("a {op1} (b {op2} c)"),
+            ] {
+                let formatted = format(&format!("int main() {{ return {source}; }}")).unwrap();
+                let formatted = formatted
+                    .split_once("return ")
+                    .and_then(|(_, rest)| rest.split_once(';'))
+                    .unwrap()
+                    .0;
+                let grouped = parse(&source);
+                assert_eq!(parse(formatted), grouped, "`{source}` became `{formatted}`");
+                assert_eq!(
+                    formatted.contains('('),
+                    grouped != bare,
+                    "`{source}` became `{formatted}`"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn prints_the_table() {
+    let output = Command::new(CRUST).arg("precedence").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, precedence::table());
+
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), LEVELS.len() + 1);
+    assert!(lines[0].starts_with("LEVEL  NAME"));
+    assert!(lines[3].contains("multiplicative  left"));
+    assert!(lines[3].ends_with("a * b  a / b  a % b"));
+    assert!(lines[LEVELS.len()].contains("conditional     right"));
+}
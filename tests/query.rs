@@ -0,0 +1,65 @@
+//! Tests for selecting AST nodes with selectors.
+
+use crust::{pipeline, query::query};
+
+const SOURCE: &str = "int sq(int n) { return n * n; }
+int main(int a) {
+    int b = sq(a) + 1;
+    return sq(println(b));
+}";
+
+/// The source text of every node `selector` selects from [`SOURCE`].
+fn selected(selector: &str) -> Vec<String> {
+    let ast = pipeline::parse_file(SOURCE).unwrap();
+    query(&ast, selector)
+        .unwrap()
+        .iter()
+        .map(|node| {
+            SOURCE
+                .chars()
+                .take(node.span().end)
+                .skip(node.span().start)
+                .collect()
+        })
+        .collect()
+}
+
+fn error(selector: &str) -> String {
+    let ast = pipeline::parse_file(SOURCE).unwrap();
+    query(&ast, selector).unwrap_err().message.to_string()
+}
+
+#[test]
+fn selects_by_kind_variant_and_attribute() {
+    assert_eq!(selected("fn"), ["sq", "main"]);
+    assert_eq!(selected("param[ty=int]"), ["int n", "int a"]);
+    assert_eq!(selected("stmt:let[name=b]"), ["int b = sq(a) + 1;"]);
+    assert_eq!(selected("expr:call[name=sq]"), ["sq(a)", "sq(println(b))"]);
+    assert_eq!(selected("*[value=1]"), ["1"]);
+}
+
+#[test]
+fn selects_inside_other_nodes() {
+    assert_eq!(
+        selected("fn[name=main] > stmt:return expr:call"),
+        ["sq(println(b))", "println(b)"]
+    );
+    assert_eq!(
+        selected("fn[name=main] > stmt:return > expr:call"),
+        ["sq(println(b))"]
+    );
+    assert_eq!(selected("expr:call expr:call"), ["println(b)"]);
+    assert_eq!(selected("fn[name=sq]>*>expr:mul>expr"), ["n", "n"]);
+    assert!(selected("param expr").is_empty());
+}
+
+#[test]
+fn rejects_invalid_selectors() {
+    assert!(error("fn > stmt:loop").contains("column 11: `stmt` has no variant `loop`"));
+    assert!(error("> fn").contains("column 1: `>` must come after another step"));
+    assert!(error("fn >").contains("expected a step after `>`"));
+    assert!(error("expr[size=1]").contains("unknown attribute `size`"));
+    assert!(error("expr[name=f").contains("column 5: unclosed `[`"));
+    assert!(error("statement").contains("unknown kind `statement`"));
+    assert!(error("  ").contains("the selector is empty"));
+}
@@ -0,0 +1,88 @@
+//! Tests for REPL sessions and saving them with `:save` and `:load`.
+
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crust::{repl::Session, Symbol, Value};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+#[test]
+fn evaluates_inputs_in_one_session() {
+    let mut session = Session::default();
+    assert_eq!(
+        session.eval("int sq(int n) { return n * n; }").unwrap(),
+        None
+    );
+    assert_eq!(session.eval("int x = sq(3);").unwrap(), None);
+    assert_eq!(session.eval("x + 1").unwrap(), Some(Value::Int(10)));
+
+    // a function can be redefined, and the variables declared before are kept
+    assert_eq!(session.eval("int sq(int n) { return n; }").unwrap(), None);
+    assert_eq!(session.eval("sq(x)").unwrap(), Some(Value::Int(9)));
+
+    let errors = session.eval("y").unwrap_err();
+    assert_eq!(errors[0].code, "E0202");
+    let (file, span) = session.sources().locate(&errors[0].labels[0].span).unwrap();
+    assert_eq!((file.name.as_str(), span), ("<repl:6>", 0..1));
+}
+
+#[test]
+fn defines_constants_but_declares_variables() {
+    let mut session = Session::default();
+    assert_eq!(session.eval("const int N = 4;").unwrap(), None);
+    assert_eq!(session.eval("int twice() { return N * 2; }").unwrap(), None);
+    assert_eq!(session.eval("int x = twice();").unwrap(), None);
+    assert_eq!(session.vars, [(Symbol::from("x"), Value::Int(8))]);
+    assert_eq!(session.eval("x + N").unwrap(), Some(Value::Int(12)));
+}
+
+#[test]
+fn restores_saved_sessions() {
+    let dir = std::env::temp_dir().join(format!("crust-repl-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("session.json");
+
+    let mut session = Session::default();
+    session
+        .eval("int f(int n) {\n    return n - 1;\n}")
+        .unwrap();
+    session.eval("int a[2];").unwrap();
+    session.eval("a[1] = f(4);").unwrap();
+    session.save(&path).unwrap();
+
+    let mut loaded = Session::load(&path).unwrap();
+    assert_eq!(loaded.eval("f(a[1])").unwrap(), Some(Value::Int(2)));
+    // diagnostics still point into the inputs entered before saving
+    let errors = loaded.eval("f(\"x\")").unwrap_err();
+    let (file, _) = loaded.sources().locate(&errors[0].labels[0].span).unwrap();
+    assert_eq!(file.name, "<repl:1>");
+
+    let mut repl = Command::new(CRUST)
+        .args(["repl", "--load"])
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = format!(
+        "int g(int n) {{\n    return f(n) * 2;\n}}\ng(a[1])\n:save {}\n:quit\n",
+        path.display()
+    );
+    repl.stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = repl.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "4\n");
+    assert_eq!(
+        Session::load(&path).unwrap().eval("g(0)").unwrap(),
+        Some(Value::Int(-2))
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,399 @@
+use std::{
+    fs,
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+use crust::{
+    ast::{DefaultContext, EvalContext, LogicalOp},
+    ir::BinOp,
+    semantics::{self, ArithError, IntMode, Overflow, Width},
+    token::Span,
+    Diagnostic, RunOptions, Value,
+};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+const TRAP: IntMode = IntMode {
+    width: Width::W32,
+    overflow: Overflow::Trap,
+};
+const WIDE: IntMode = IntMode {
+    width: Width::W64,
+    overflow: Overflow::Wrap,
+};
+
+const EDGES: &[i32] = &[
+    i32::MIN,
+    i32::MIN + 1,
+    -65536,
+    -7,
+    -2,
+    -1,
+    0,
+    1,
+    2,
+    7,
+    31,
+    32,
+    33,
+    65536,
+    i32::MAX - 1,
+    i32::MAX,
+];
+
+fn wrap(value: i64) -> i32 {
+    value as i32
+}
+
+#[test]
+fn add_sub_mul_wrap() {
+    for &a in EDGES {
+        for &b in EDGES {
+            let (wa, wb) = (a as i64, b as i64);
+            assert_eq!(semantics::add(a, b), wrap(wa + wb), "{a} + {b}");
+            assert_eq!(semantics::sub(a, b), wrap(wa - wb), "{a} - {b}");
+            assert_eq!(semantics::mul(a, b), wrap(wa * wb), "{a} * {b}");
+        }
+    }
+}
+
+#[test]
+fn div_rem_truncate_toward_zero() {
+    for &a in EDGES {
+        for &b in EDGES {
+            if b == 0 {
+                assert_eq!(semantics::div(a, b), Err(ArithError::DivisionByZero));
+                assert_eq!(semantics::rem(a, b), Err(ArithError::DivisionByZero));
+                continue;
+            }
+
+            let (wa, wb) = (a as i64, b as i64);
+            let quotient = semantics::div(a, b).unwrap();
+            let remainder = semantics::rem(a, b).unwrap();
+            assert_eq!(quotient, wrap(wa / wb), "{a} / {b}");
+            assert_eq!(remainder, wrap(wa % wb), "{a} % {b}");
+            assert!(
+                remainder == 0 || remainder.signum() == a.signum(),
+                "{a} % {b}"
+            );
+            assert_eq!(
+                semantics::add(semantics::mul(quotient, b), remainder),
+                a,
+                "({a} / {b}) * {b} + {a} % {b}"
+            );
+        }
+    }
+
+    assert_eq!(semantics::div(-7, 2), Ok(-3));
+    assert_eq!(semantics::rem(-7, 2), Ok(-1));
+    assert_eq!(semantics::div(i32::MIN, -1), Ok(i32::MIN));
+    assert_eq!(semantics::rem(i32::MIN, -1), Ok(0));
+}
+
+#[test]
+fn neg_wraps_int_min() {
+    for &a in EDGES {
+        assert_eq!(semantics::neg(a), wrap(-(a as i64)), "-{a}");
+    }
+    assert_eq!(semantics::neg(i32::MIN), i32::MIN);
+}
+
+#[test]
+fn shifts_mask_the_amount() {
+    for &a in EDGES {
+        for &b in EDGES {
+            let amount = (b & 31) as u32;
+            assert_eq!(semantics::shl(a, b), a << amount, "{a} << {b}");
+            assert_eq!(semantics::shr(a, b), a >> amount, "{a} >> {b}");
+        }
+    }
+
+    assert_eq!(semantics::shl(1, 32), 1);
+    assert_eq!(semantics::shl(1, 31), i32::MIN);
+    assert_eq!(semantics::shr(i32::MIN, 31), -1);
+    assert_eq!(semantics::shr(-1, -1), -1);
+}
+
+#[test]
+fn not_is_one_only_for_zero() {
+    for &a in EDGES {
+        assert_eq!(semantics::not(a), (a == 0) as i32, "!{a}");
+    }
+}
+
+#[test]
+fn comparisons_are_signed() {
+    for &a in EDGES {
+        for &b in EDGES {
+            assert_eq!(semantics::lt(a, b), (a < b) as i32, "{a} < {b}");
+            assert_eq!(semantics::le(a, b), (a <= b) as i32, "{a} <= {b}");
+            assert_eq!(semantics::gt(a, b), (a > b) as i32, "{a} > {b}");
+            assert_eq!(semantics::ge(a, b), (a >= b) as i32, "{a} >= {b}");
+            assert_eq!(semantics::eq(a, b), (a == b) as i32, "{a} == {b}");
+            assert_eq!(semantics::ne(a, b), (a != b) as i32, "{a} != {b}");
+        }
+    }
+    assert_eq!(semantics::lt(i32::MIN, 0), 1);
+}
+
+/// Runs `main(a, b) { return <expr>; }` through the interpreter.
+fn interpret(expr: &str, a: i32, b: i32) -> Result<i32, crust::Diagnostic> {
+    let source = format!("int main(int a, int b) {{ return {expr}; }}");
+    let program = crust::compile(&source, "semantics.c").unwrap();
+    program.ast.run_main(&[a.to_string(), b.to_string()])
+}
+
+#[test]
+fn interpreter_agrees() {
+    for &a in EDGES {
+        for &b in EDGES {
+            assert_eq!(interpret("a + b", a, b).unwrap(), semantics::add(a, b));
+            assert_eq!(interpret("a - b", a, b).unwrap(), semantics::sub(a, b));
+            assert_eq!(interpret("a * b", a, b).unwrap(), semantics::mul(a, b));
+            assert_eq!(interpret("-a", a, b).unwrap(), semantics::neg(a));
+            assert_eq!(interpret("!a", a, b).unwrap(), semantics::not(a));
+            assert_eq!(interpret("++a", a, b).unwrap(), semantics::add(a, 1));
+            assert_eq!(interpret("--a - a", a, b).unwrap(), 0);
+            assert_eq!(interpret("a < b", a, b).unwrap(), semantics::lt(a, b));
+            assert_eq!(interpret("a >= b", a, b).unwrap(), semantics::ge(a, b));
+            assert_eq!(interpret("a != b", a, b).unwrap(), semantics::ne(a, b));
+            match semantics::div(a, b) {
+                Ok(expected) => assert_eq!(interpret("a / b", a, b).unwrap(), expected),
+                Err(_) => assert!(interpret("a / b", a, b).is_err()),
+            }
+        }
+    }
+}
+
+/// Saturates `+` and `*` instead of wrapping, and records the span of every division and each
+/// `&&` or `||` it's asked about.
+#[derive(Default)]
+struct Saturating {
+    seen: Mutex<Vec<String>>,
+}
+
+impl EvalContext for Saturating {
+    fn binary(
+        &self,
+        op: BinOp,
+        lhs: i64,
+        rhs: i64,
+        ints: IntMode,
+        span: &Span,
+    ) -> Result<i64, ArithError> {
+        let (min, max) = ints.range();
+        match op {
+            BinOp::Add => Ok(lhs.saturating_add(rhs).clamp(min, max)),
+            BinOp::Mul => Ok(lhs.saturating_mul(rhs).clamp(min, max)),
+            BinOp::Div => {
+                self.seen.lock().unwrap().push(format!("div at {span:?}"));
+                ints.binary(op, lhs, rhs)
+            }
+            _ => ints.binary(op, lhs, rhs),
+        }
+    }
+
+    fn logical(
+        &self,
+        op: LogicalOp,
+        lhs: i64,
+        rhs: &mut dyn FnMut() -> Result<i64, Diagnostic>,
+    ) -> Result<i64, Diagnostic> {
+        self.seen.lock().unwrap().push(format!("{op:?} {lhs}"));
+        DefaultContext.logical(op, lhs, rhs)
+    }
+}
+
+#[test]
+fn eval_context_intercepts_operators() {
+    const SOURCE: &str = "int f(int a, int b) {
+    a += b;
+    int half = a / 2;
+    return half * 4 + (b || 1 / 0) + (0 && 1 / 0);
+}
+int main() { return 0; }";
+    let program = crust::compile(SOURCE, "context.c").unwrap();
+    let args = [Value::Int(2147483000), Value::Int(1000)];
+    assert_eq!(
+        program
+            .call_with(RunOptions::default(), "f", &args)
+            .unwrap(),
+        Value::Int(705)
+    );
+
+    let context = Arc::new(Saturating::default());
+    let options = RunOptions {
+        context: context.clone(),
+        ..RunOptions::default()
+    };
+    assert_eq!(
+        program.call_with(options, "f", &args).unwrap(),
+        Value::Int(i32::MAX.into())
+    );
+    // neither `1 / 0` is evaluated
+    assert_eq!(
+        *context.seen.lock().unwrap(),
+        ["div at 49..54", "Or 1000", "And 0"]
+    );
+}
+
+#[test]
+fn default_mode_agrees_with_32_bit_semantics() {
+    let ints = IntMode::default();
+    for &a in EDGES {
+        let wa = i64::from(a);
+        assert_eq!(ints.neg(wa), Ok(semantics::neg(a).into()), "-{a}");
+        for &b in EDGES {
+            let wb = i64::from(b);
+            assert_eq!(
+                ints.add(wa, wb),
+                Ok(semantics::add(a, b).into()),
+                "{a} + {b}"
+            );
+            assert_eq!(
+                ints.sub(wa, wb),
+                Ok(semantics::sub(a, b).into()),
+                "{a} - {b}"
+            );
+            assert_eq!(
+                ints.mul(wa, wb),
+                Ok(semantics::mul(a, b).into()),
+                "{a} * {b}"
+            );
+            assert_eq!(
+                ints.shl(wa, wb),
+                Ok(semantics::shl(a, b).into()),
+                "{a} << {b}"
+            );
+            assert_eq!(
+                ints.shr(wa, wb),
+                Ok(semantics::shr(a, b).into()),
+                "{a} >> {b}"
+            );
+            assert_eq!(ints.div(wa, wb), semantics::div(a, b).map(i64::from));
+            assert_eq!(ints.rem(wa, wb), semantics::rem(a, b).map(i64::from));
+        }
+    }
+    assert_eq!(ints.literal(2147483648), Ok(i32::MIN.into()));
+}
+
+#[test]
+fn trap_reports_results_that_dont_fit() {
+    let (min, max) = (i64::from(i32::MIN), i64::from(i32::MAX));
+    assert_eq!(TRAP.add(max, 1), Err(ArithError::Overflow("add")));
+    assert_eq!(TRAP.sub(min, 1), Err(ArithError::Overflow("subtract")));
+    assert_eq!(
+        TRAP.mul(65536, 65536),
+        Err(ArithError::Overflow("multiply"))
+    );
+    assert_eq!(TRAP.neg(min), Err(ArithError::Overflow("negate")));
+    assert_eq!(TRAP.div(min, -1), Err(ArithError::Overflow("divide")));
+    assert_eq!(TRAP.rem(min, -1), Ok(0));
+    assert_eq!(TRAP.div(1, 0), Err(ArithError::DivisionByZero));
+    assert_eq!(TRAP.shl(1, 32), Err(ArithError::Overflow("shift left")));
+    assert_eq!(TRAP.shr(1, -1), Err(ArithError::Overflow("shift right")));
+    assert_eq!(TRAP.shl(1, 31), Ok(min));
+    assert_eq!(TRAP.literal(-2147483648), Ok(min));
+    assert_eq!(
+        TRAP.literal(2147483648),
+        Err(ArithError::OutOfRange(2147483648, 32))
+    );
+
+    // every result that fits is the same as when wrapping
+    for &a in EDGES {
+        for &b in EDGES {
+            let (wa, wb) = (i64::from(a), i64::from(b));
+            for op in [IntMode::add, IntMode::sub, IntMode::mul, IntMode::div] {
+                if let Ok(value) = op(TRAP, wa, wb) {
+                    assert_eq!(Ok(value), op(IntMode::default(), wa, wb), "{a}, {b}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn sixty_four_bit_ints() {
+    let max = i64::from(i32::MAX);
+    assert_eq!(WIDE.add(max, 1), Ok(max + 1));
+    assert_eq!(WIDE.mul(65536, 65536), Ok(1 << 32));
+    assert_eq!(WIDE.neg(i32::MIN.into()), Ok(-i64::from(i32::MIN)));
+    assert_eq!(WIDE.add(i64::MAX, 1), Ok(i64::MIN));
+    assert_eq!(WIDE.div(i64::MIN, -1), Ok(i64::MIN));
+    assert_eq!(WIDE.shl(1, 32), Ok(1 << 32));
+    assert_eq!(WIDE.shl(1, 64), Ok(1));
+    assert_eq!(WIDE.shr(-1, 63), Ok(-1));
+    assert_eq!(WIDE.literal(4294967295), Ok(4294967295));
+
+    let trap = IntMode {
+        overflow: Overflow::Trap,
+        ..WIDE
+    };
+    assert_eq!(trap.add(i64::MAX, 1), Err(ArithError::Overflow("add")));
+    assert_eq!(trap.shl(1, 63), Ok(i64::MIN));
+    assert_eq!(trap.shl(1, 64), Err(ArithError::Overflow("shift left")));
+}
+
+/// Runs `source` with `flags` on the interpreter and the VM, requiring them to agree, and
+/// returns what it printed and wrote to stderr.
+fn run_with(source: &str, flags: &[&str]) -> (String, String) {
+    let dir = std::env::temp_dir().join(format!("crust-semantics-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("main{}.c", flags.join("")));
+    fs::write(&path, source).unwrap();
+
+    let run = |vm: bool| {
+        let mut command = Command::new(CRUST);
+        command.arg("run").arg(&path).args(flags);
+        if vm {
+            command.arg("--vm");
+        }
+        let output = command.output().unwrap();
+        (
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+    let output = run(false);
+    assert_eq!(run(true), output, "{flags:?}");
+    output
+}
+
+#[test]
+fn run_selects_the_width_and_overflow() {
+    let source = "int main() {
+        int x = 2147483647;
+        println(x * 2, -2147483648);
+        x += 1;
+        println(x);
+        return 0;
+    }";
+
+    let (stdout, _) = run_with(source, &[]);
+    assert!(
+        stdout.starts_with("-2 -2147483648\n-2147483648\n"),
+        "{stdout}"
+    );
+
+    let (stdout, _) = run_with(source, &["--int-width", "64"]);
+    assert!(
+        stdout.starts_with("4294967294 -2147483648\n2147483648\n"),
+        "{stdout}"
+    );
+
+    let (stdout, stderr) = run_with(source, &["--overflow", "trap"]);
+    assert_eq!(stdout, "");
+    assert!(
+        stderr.contains("attempt to multiply with overflow"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("3:17"), "{stderr}");
+
+    let (stdout, stderr) = run_with(source, &["--int-width", "64", "--overflow", "trap"]);
+    assert!(
+        stdout.starts_with("4294967294 -2147483648\n2147483648\n"),
+        "{stdout}"
+    );
+    assert_eq!(stderr, "");
+}
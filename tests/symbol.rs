@@ -0,0 +1,74 @@
+//! Tests for interning identifiers as `Symbol`s.
+
+use crust::{pipeline, Ast, Symbol};
+
+#[test]
+fn interns_each_name_once() {
+    assert_eq!(Symbol::intern("count"), Symbol::from(String::from("count")));
+    assert_ne!(Symbol::intern("count"), Symbol::intern("counter"));
+    assert_eq!(Symbol::intern("count"), "count");
+    assert_eq!(Symbol::intern("count").len(), 5);
+}
+
+#[test]
+fn reads_names_while_others_are_interned() {
+    assert_eq!(Symbol::intern(""), Symbol::EMPTY);
+    assert_eq!(Symbol::EMPTY, "");
+
+    // enough names to fill several chunks of the table on every thread
+    let threads = (0..4)
+        .map(|thread| {
+            std::thread::spawn(move || {
+                let names = (0..2000)
+                    .map(|i| format!("name_{thread}_{i}"))
+                    .collect::<Vec<_>>();
+                let symbols = names
+                    .iter()
+                    .map(|name| Symbol::intern(name))
+                    .collect::<Vec<_>>();
+                for (name, symbol) in names.iter().zip(symbols) {
+                    assert_eq!(symbol, *name);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn orders_symbols_by_name() {
+    // interned in the opposite order to their names
+    let mut symbols = [
+        Symbol::intern("zeta"),
+        Symbol::intern("alpha"),
+        Symbol::intern("mu"),
+    ];
+    symbols.sort();
+    assert_eq!(symbols, ["alpha", "mu", "zeta"].map(Symbol::intern));
+}
+
+#[test]
+fn writes_names_in_json() {
+    let ast = pipeline::parse_file(
+        "struct pair { int a; }; int twice(int n) { int m = n * 2; return m + sizeof(pair); }",
+    )
+    .unwrap();
+    let json = serde_json::to_string(&ast).unwrap();
+    for name in ["\"twice\"", "\"n\"", "\"m\"", "\"pair\"", "\"int\""] {
+        assert!(json.contains(name), "{name} isn't in {json}");
+    }
+
+    let read: Ast = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&read).unwrap(), json);
+}
+
+#[test]
+fn runs_programs_with_interned_names() {
+    let ast = pipeline::parse_file(
+        "int f(int x) { int y = x + 1; return y; } int main() { int x = 3; return f(x) + x; }",
+    )
+    .unwrap();
+    assert_eq!(ast.run_main(&[]).unwrap(), 7);
+}
@@ -0,0 +1,108 @@
+//! Tests for the opt-in metrics written by the CLI.
+
+use std::{fs, path::Path, process::Command};
+
+use serde_json::Value;
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+#[test]
+fn records_diagnostics_and_sizes() {
+    let dir = std::env::temp_dir().join(format!("crust-telemetry-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.c");
+    let metrics = dir.join("metrics.jsonl");
+    fs::write(&source, "int main() {\n    return missing;\n}\n").unwrap();
+
+    fs::write(dir.join("crust.toml"), "").unwrap();
+    let check = |telemetry: Option<&Path>| {
+        let mut command = Command::new(CRUST);
+        command
+            .arg("check")
+            .arg(&source)
+            .env("CRUST_CONFIG", dir.join("crust.toml"))
+            .env_remove("CRUST_TELEMETRY_FILE");
+        if let Some(file) = telemetry {
+            command.env("CRUST_TELEMETRY_FILE", file);
+        }
+        command.output().unwrap().status.success()
+    };
+
+    assert!(!check(None));
+    assert!(!metrics.exists(), "telemetry is written without opting in");
+
+    assert!(!check(Some(&metrics)));
+    fs::write(&source, "int main() {\n    return 0;\n}\n").unwrap();
+    assert!(check(Some(&metrics)));
+
+    let records = fs::read_to_string(&metrics)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 2);
+
+    let failed = &records[0];
+    assert_eq!(failed["command"], "check");
+    assert_eq!(failed["exit_code"], -1);
+    assert_eq!(failed["diagnostics"].as_object().unwrap().len(), 1);
+    assert!(failed["size"].is_null());
+    assert!(failed["pass_ms"]["sema"].is_number());
+
+    let passed = &records[1];
+    assert_eq!(passed["exit_code"], 0);
+    assert_eq!(passed["diagnostics"], serde_json::json!({}));
+    assert_eq!(passed["size"]["functions"], 1);
+    assert_eq!(passed["size"]["lines"], 3);
+    assert_eq!(passed["size"]["tokens"], 9);
+    // the function, its return statement and the returned expression
+    assert_eq!(passed["size"]["nodes"], 3);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn logs_passes_to_stderr() {
+    let dir = std::env::temp_dir().join(format!("crust-log-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.c");
+    fs::write(&source, "int main() {\n    return 1 + 2;\n}\n").unwrap();
+    fs::write(dir.join("crust.toml"), "").unwrap();
+    let run = |args: &[&str], log: Option<&str>| {
+        let mut command = Command::new(CRUST);
+        command
+            .args(args)
+            .arg(&source)
+            .env("CRUST_CONFIG", dir.join("crust.toml"))
+            .env_remove("CRUST_LOG");
+        if let Some(log) = log {
+            command.env("CRUST_LOG", log);
+        }
+        String::from_utf8(command.output().unwrap().stderr).unwrap()
+    };
+
+    assert_eq!(run(&["check"], None), "");
+
+    let text = run(&["check", "-v"], None);
+    let passes = text
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .collect::<Vec<_>>();
+    for pass in ["lex", "parse", "sema", "lint", "total"] {
+        assert!(passes.contains(&pass), "{pass} isn't in {text}");
+    }
+    assert!(
+        text.ends_with(
+            "files: 1, lines: 3, bytes: 33, tokens: 11, nodes: 5, functions: 1, statements: 1\n"
+        ),
+        "{text}"
+    );
+
+    let json = run(&["run"], Some("json"));
+    let record = serde_json::from_str::<Value>(json.lines().last().unwrap()).unwrap();
+    assert_eq!(record["command"], "run");
+    assert!(record["pass_ms"]["run"].is_number());
+    assert_eq!(record["size"]["nodes"], 5);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,242 @@
+//! Tests for `spawn` and `join`, behind the `threads` language feature, which the interpreter
+//! runs on threads of their own.
+
+use std::process::Command;
+
+use crust::{
+    pipeline::{self, Feature},
+    sources::SourceMap,
+    telemetry::Timings,
+    Ast, Diagnostic,
+};
+
+mod common;
+
+fn compile(source: &str, features: &[Feature]) -> Result<Ast, Vec<Diagnostic>> {
+    pipeline::compile_timed(
+        &mut SourceMap::default(),
+        source,
+        "main.c",
+        features,
+        &mut Timings::default(),
+    )
+}
+
+/// Runs `source` with threads enabled, returning its output, diagnostics and exit code.
+fn run(name: &str, source: &str, flags: &[&str]) -> (String, String, Option<i32>) {
+    let path = common::write_source("threads", name, source);
+    common::output(
+        Command::new(common::CRUST)
+            .args(["--features", "threads", "run"])
+            .args(flags)
+            .arg(&path),
+    )
+}
+
+#[test]
+fn joins_the_values_spawned_functions_return() {
+    let source = "int fib(int n) {
+    return n < 2 ? n : fib(n - 1) + fib(n - 2);
+}
+
+string greet(string name) {
+    return \"hello \" + name;
+}
+
+int main() {
+    thread a = spawn fib(12);
+    thread b = spawn fib(10);
+    thread c = spawn greet(\"threads\");
+    println(join(c));
+    return join(a) - join(b);
+}
+";
+    let (stdout, stderr, code) = run("fib", source, &[]);
+    assert!(stdout.starts_with("hello threads\n"), "{stderr}");
+    assert_eq!(code, Some(144 - 55), "{stderr}");
+
+    let ast = compile(source, &[Feature::Threads]).unwrap();
+    assert_eq!(ast.run_main(&[]).unwrap(), 89);
+}
+
+#[test]
+fn reports_threads_unless_enabled() {
+    let errors = compile(
+        "int f() { return 1; } int main() { thread t = spawn f(); return join(t); }",
+        &[],
+    )
+    .unwrap_err();
+    let labels = errors
+        .iter()
+        .map(|error| (error.code, error.labels[0].message.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        [
+            ("E0124", "spawned here".into()),
+            ("E0124", "joined here".into())
+        ]
+    );
+    assert_eq!(
+        errors[0].notes[0].to_string(),
+        "enable them with `--features threads`"
+    );
+}
+
+#[test]
+fn doesnt_share_variables_between_threads() {
+    for source in [
+        "int bump(int* p) { *p = *p + 1; return 0; } int main() { int x = 0; return join(spawn bump(&x)); }",
+        "int* leak() { int x = 0; return &x; } int main() { join(spawn leak()); return 0; }",
+    ] {
+        let errors = compile(source, &[Feature::Threads]).unwrap_err();
+        assert_eq!(errors.len(), 1, "{source}");
+        assert_eq!(errors[0].code, "E0125", "{source}");
+    }
+
+    // a function that doesn't declare its pointer parameter is still refused one
+    let (_, stderr, code) = run(
+        "pointer",
+        "int peek(int p) { return 0; } int main() { int x = 1; return join(spawn peek(&x)); }",
+        &[],
+    );
+    assert_eq!(code, Some(255));
+    assert!(
+        stderr.contains("a pointer can't be passed to another thread"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn doesnt_share_globals_between_threads() {
+    for (source, labels) in [
+        (
+            "int counter = 0; void bump() { counter += 5; } int main() { join(spawn bump()); return counter; }",
+            &["spawned here", "assigned to here"][..],
+        ),
+        (
+            "int counter = 0; int get() { return counter; } int read() { return get(); } int main() { counter = 1; return join(spawn read()); }",
+            &["spawned here", "used here", "assigned to here"],
+        ),
+        (
+            "int counter = 0; int* at() { return &counter; } int peek() { return *at(); } int main() { return join(spawn peek()); }",
+            &["spawned here", "pointer taken here"],
+        ),
+    ] {
+        let errors = compile(source, &[Feature::Threads]).unwrap_err();
+        assert_eq!(errors.len(), 1, "{source}");
+        assert_eq!(errors[0].code, "E0135", "{source}");
+        let found = errors[0]
+            .labels
+            .iter()
+            .map(|label| label.message.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(found, labels, "{source}");
+    }
+
+    // constants, globals nothing changes and locals shadowing a global are the same on every thread
+    let source = "const int STEP = 2;
+int start = 40;
+int counter = 0;
+
+int count() {
+    int counter = start;
+    counter += STEP;
+    return counter;
+}
+
+int main() {
+    counter = 1;
+    return join(spawn count()) + counter;
+}
+";
+    let (_, stderr, code) = run("constants", source, &[]);
+    assert_eq!(code, Some(43), "{stderr}");
+}
+
+#[test]
+fn reports_errors_where_threads_are_joined() {
+    let (_, stderr, code) = run(
+        "fails",
+        "int fail(int n) { return 1 / n; } int main() { thread t = spawn fail(0); return join(t); }",
+        &[],
+    );
+    assert_eq!(code, Some(255));
+    assert!(stderr.contains("attempt to divide by zero"), "{stderr}");
+    assert!(stderr.contains("joined here"), "{stderr}");
+
+    let (_, stderr, _) = run(
+        "twice",
+        "int f() { return 1; } int main() { thread t = spawn f(); join(t); return join(t); }",
+        &[],
+    );
+    assert!(
+        stderr.contains("this thread has already been joined"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn only_the_interpreter_runs_threads() {
+    let source = "int f() { return 1; } int main() { return join(spawn f()); }";
+    let (_, stderr, code) = run("vm", source, &["--vm"]);
+    assert_eq!(code, Some(255));
+    assert!(stderr.contains("only the interpreter can run"), "{stderr}");
+
+    let ast = compile(source, &[Feature::Threads]).unwrap();
+    common::assert_unsupported(&ast, "threads");
+}
+
+#[test]
+fn shares_atomics_and_locks_between_threads() {
+    let (stdout, stderr, code) = run(
+        "atomics",
+        "int bump(int n) {
+    lock(\"count\");
+    atomic_store(\"count\", atomic_load(\"count\") + 1);
+    unlock(\"count\");
+    atomic_add(\"total\", 2);
+    return n > 1 ? bump(n - 1) : 0;
+}
+
+int main() {
+    thread a = spawn bump(50);
+    thread b = spawn bump(50);
+    thread c = spawn bump(50);
+    join(a);
+    join(b);
+    join(c);
+    println(atomic_load(\"count\"), atomic_load(\"total\"));
+    return atomic_add(\"count\", 1);
+}
+",
+        &[],
+    );
+    assert!(stdout.starts_with("150 300\n"), "{stderr}");
+    assert_eq!(code, Some(150), "{stderr}");
+}
+
+#[test]
+fn only_the_thread_holding_a_lock_releases_it() {
+    for (name, source, error) in [
+        (
+            "unheld",
+            "int main() { unlock(\"a\"); return 0; }",
+            "unlock: 'a' isn't held by this thread",
+        ),
+        (
+            "twice",
+            "int main() { lock(\"a\"); lock(\"a\"); return 0; }",
+            "lock: 'a' is already held by this thread",
+        ),
+        (
+            "other",
+            "int release() { unlock(\"a\"); return 0; } int main() { lock(\"a\"); return join(spawn release()); }",
+            "unlock: 'a' isn't held by this thread",
+        ),
+    ] {
+        let (_, stderr, code) = run(name, source, &[]);
+        assert_eq!(code, Some(255), "{name}");
+        assert!(stderr.contains(error), "{name}: {stderr}");
+    }
+}
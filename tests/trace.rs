@@ -0,0 +1,88 @@
+//! Tests for `run --trace` and `run --profile`.
+
+use std::{fs, path::PathBuf, process::Command};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+const PROGRAM: &str = "int fib(int n) {
+    return n < 2 ? n : fib(n - 1) + fib(n - 2);
+}
+int main() {
+    int a = 3;
+    a += fib(a);
+    println(\"done\");
+    return a;
+}
+";
+
+/// Writes `PROGRAM` to a file of its own, returning its path.
+fn program() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crust-trace-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.c");
+    fs::write(&path, PROGRAM).unwrap();
+    path
+}
+
+/// Runs `PROGRAM` with `flag`, returning its stdout and stderr.
+fn run(flag: &str) -> (String, String) {
+    let path = program();
+    let output = Command::new(CRUST)
+        .args(["run", flag])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let prefix = format!("{}:", path.display());
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        stderr.replace(&prefix, "main.c:"),
+    )
+}
+
+#[test]
+fn traces_statements_with_the_values_they_read() {
+    let (stdout, stderr) = run("--trace");
+    assert_eq!(stdout, "done\n-- exited with code : 5 --\n");
+    assert_eq!(
+        stderr.lines().collect::<Vec<_>>(),
+        [
+            "main.c:5 main: int a = 3;",
+            "main.c:6 main: a += fib(a);  [a = 3]",
+            "main.c:2   fib: return n < 2 ? n : fib(n - 1) + fib(n - 2);  [n = 3]",
+            "main.c:2     fib: return n < 2 ? n : fib(n - 1) + fib(n - 2);  [n = 2]",
+            "main.c:2       fib: return n < 2 ? n : fib(n - 1) + fib(n - 2);  [n = 1]",
+            "main.c:2       fib: return n < 2 ? n : fib(n - 1) + fib(n - 2);  [n = 0]",
+            "main.c:2     fib: return n < 2 ? n : fib(n - 1) + fib(n - 2);  [n = 1]",
+            "main.c:7 main: println(\"done\");",
+            "main.c:8 main: return a;  [a = 5]",
+        ]
+    );
+}
+
+#[test]
+fn profiles_calls_per_function() {
+    let (stdout, stderr) = run("--profile");
+    assert_eq!(stdout, "done\n-- exited with code : 5 --\n");
+
+    let rows = stderr
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(rows[0], ["FUNCTION", "CALLS", "TOTAL", "MS", "SELF", "MS"]);
+    let mut calls = rows[1..]
+        .iter()
+        .map(|row| (row[0], row[1]))
+        .collect::<Vec<_>>();
+    calls.sort();
+    assert_eq!(calls, [("fib", "5"), ("main", "1")]);
+
+    // main's total includes the time spent in fib, which is only counted once for its recursion
+    let time = |name: &str, column: usize| -> f64 {
+        let row = rows.iter().find(|row| row[0] == name).unwrap();
+        row[column].parse().unwrap()
+    };
+    assert!(time("main", 2) >= time("fib", 2));
+    assert!(time("fib", 2) >= time("fib", 3));
+}
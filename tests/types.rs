@@ -0,0 +1,139 @@
+//! Tests for the type table of a program's structs, and for `sizeof`, which the compile pipeline
+//! folds to the size of its type.
+
+use std::{fs, process::Command};
+
+use crust::{
+    ast::{Expr, Spanned},
+    pipeline, typed,
+    types::TypeTable,
+    visit::{self, Visitor},
+    Builtins,
+};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+const STRUCTS: &str = "struct Point { int x; int y; };
+struct Mixed { char c; long l; char d; };
+struct Outer { char c; Point p; };
+struct Node { int value; Node* next; };
+";
+
+/// Runs `source` on the interpreter and the VM, requiring the same results, and returns its
+/// diagnostics and exit code.
+fn run(name: &str, source: &str) -> (String, Option<i32>) {
+    let dir = std::env::temp_dir().join(format!("crust-types-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{name}.c"));
+    fs::write(&path, source).unwrap();
+
+    let run = |vm: bool| {
+        let mut command = Command::new(CRUST);
+        command.arg("run").arg(&path);
+        if vm {
+            command.arg("--vm");
+        }
+        let output = command.output().unwrap();
+        (
+            String::from_utf8(output.stderr).unwrap(),
+            output.status.code(),
+        )
+    };
+    let output = run(false);
+    assert_eq!(run(true), output, "{name}");
+    output
+}
+
+#[test]
+fn lays_out_structs_like_c() {
+    let ast = pipeline::parse_file(STRUCTS).unwrap();
+    let types = TypeTable::new(&ast).unwrap();
+    for (ty, size, align) in [
+        ("char", 1, 1),
+        ("int", 4, 4),
+        ("long", 8, 8),
+        ("string", 8, 8),
+        ("void*", 8, 8),
+        ("Point", 8, 4),
+        ("Mixed", 24, 8),
+        ("Outer", 12, 4),
+        ("Node", 16, 8),
+        ("Node**", 8, 8),
+    ] {
+        let layout = types.layout(ty).unwrap();
+        assert_eq!((layout.size, layout.align), (size, align), "{ty}");
+    }
+}
+
+#[test]
+fn folds_sizeof_when_compiling() {
+    let source = format!(
+        "{STRUCTS}
+int main() {{
+    return sizeof(Mixed) + sizeof(Node*) + sizeof(Outer);
+}}
+"
+    );
+
+    struct Sizes(usize);
+    impl Visitor<'_> for Sizes {
+        fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+            self.0 += matches!(expr.0, Expr::SizeOf(_)) as usize;
+            visit::walk_expr(self, expr);
+        }
+    }
+    let program = pipeline::compile(&source, "main.c").unwrap();
+    let mut sizes = Sizes(0);
+    sizes.visit_ast(&program.ast);
+    assert_eq!(sizes.0, 0);
+
+    // an IR file holds the sizes too, even of an AST that wasn't compiled
+    let parsed = pipeline::parse_file(&source).unwrap();
+    let ir = typed::resolve(&parsed, &Builtins::default()).unwrap();
+    let mut sizes = Sizes(0);
+    sizes.visit_ast(&ir.to_ast());
+    assert_eq!(sizes.0, 0);
+
+    let (stderr, code) = run("sizes", &source);
+    assert_eq!(code, Some(44), "{stderr}");
+}
+
+#[test]
+fn reports_fields_without_a_size() {
+    let errors = |source: &str| {
+        let ast = pipeline::parse_file(source).unwrap();
+        TypeTable::new(&ast).unwrap_err()
+    };
+
+    let cycle = errors("struct A { int x; B b; }; struct B { A* ok; A a; }; struct C { A a; };");
+    // the cycle is reported once, and the structs that contain it aren't reported at all
+    assert_eq!(cycle.len(), 1, "{cycle:?}");
+    assert_eq!(cycle[0].code, "E0122");
+    assert_eq!(
+        cycle[0].message.to_string(),
+        "Struct 'A' contains itself, so it has no size"
+    );
+    assert_eq!(cycle[0].notes[0].to_string(), "cycle: A -> B -> A");
+
+    let fields = errors("struct S { Missing m; void v; Missing* p; void* ok; S* next; };");
+    let codes = fields.iter().map(|error| error.code).collect::<Vec<_>>();
+    assert_eq!(codes, ["E0121", "E0123", "E0121"]);
+    assert_eq!(fields[0].message.to_string(), "Unknown type 'Missing'");
+    assert_eq!(fields[1].labels[0].message.to_string(), "field v");
+    assert_eq!(fields[2].message.to_string(), "Unknown type 'Missing*'");
+}
+
+#[test]
+fn checks_the_types_sizeof_measures() {
+    for (expr, code) in [("sizeof(Missing)", "E0121"), ("sizeof(void)", "E0123")] {
+        let source = format!("int main() {{ return {expr}; }}");
+        let errors = pipeline::compile(&source, "main.c").unwrap_err();
+        assert_eq!(errors.len(), 1, "{expr}");
+        assert_eq!(errors[0].code, code, "{expr}");
+        assert_eq!(errors[0].labels[0].message.to_string(), "sized here");
+    }
+
+    let errors =
+        pipeline::compile("struct S { S s; }; int main() { return 0; }", "main.c").unwrap_err();
+    assert_eq!(errors[0].code, "E0122");
+}
@@ -0,0 +1,36 @@
+//! Tests for `--version`, whose verbose output is used to pin down the environment of bug reports.
+
+use std::process::Command;
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+fn crust(args: &[&str]) -> String {
+    let output = Command::new(CRUST).args(args).output().unwrap();
+    assert!(output.status.success(), "{args:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn prints_the_version() {
+    let version = format!("crust {}\n", env!("CARGO_PKG_VERSION"));
+    assert_eq!(crust(&["--version"]), version);
+    assert_eq!(crust(&["-V"]), version);
+}
+
+#[test]
+fn verbose_version_describes_the_build() {
+    let text = crust(&["--version", "--verbose"]);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], format!("crust {}", env!("CARGO_PKG_VERSION")));
+    assert_eq!(
+        lines[1],
+        format!("IR format version: {}", crust::artifact::FORMAT_VERSION)
+    );
+    assert_eq!(lines[2], "features: macros, threads");
+    assert!(lines[3].starts_with("emit targets: json, bin, "));
+    assert!(lines[3].contains("llvm"));
+    assert_eq!(
+        lines[4],
+        "builtins: arg, argc, atomic_add, atomic_load, atomic_store, getenv, len, lock, print, println, read_int, unlock"
+    );
+}
@@ -0,0 +1,65 @@
+//! Tests for traversing the AST with `Visitor` and rebuilding it with `Folder`.
+
+use crust::{
+    ast::{Expr, Spanned},
+    pipeline,
+    visit::{self, Folder, Visitor},
+};
+
+const SOURCE: &str = "struct P { int x; };
+int f(int n) { int a[2]; a[n] = n; return a[0] + -f(n - 1); }";
+
+#[test]
+fn visits_every_variable() {
+    #[derive(Default)]
+    struct Vars<'a>(Vec<&'a str>);
+
+    impl<'a> Visitor<'a> for Vars<'a> {
+        fn visit_expr(&mut self, expr: &'a Spanned<Expr>) {
+            if let Expr::Var(name) = &expr.0 {
+                self.0.push(name);
+            }
+            visit::walk_expr(self, expr);
+        }
+    }
+
+    let ast = pipeline::parse_file(SOURCE).unwrap();
+    let mut vars = Vars::default();
+    vars.visit_ast(&ast);
+    assert_eq!(vars.0, ["n", "n", "a", "n"]);
+}
+
+#[test]
+fn folds_bottom_up() {
+    struct Rename;
+
+    impl Folder for Rename {
+        fn fold_expr(&mut self, expr: Spanned<Expr>) -> Spanned<Expr> {
+            match visit::fold_expr(self, expr) {
+                (Expr::Var(name), span) => (Expr::Var(name.to_uppercase().into()), span),
+                expr => expr,
+            }
+        }
+    }
+
+    let ast = Rename.fold_ast(pipeline::parse_file(SOURCE).unwrap());
+    assert_eq!(
+        ast.to_string(),
+        "struct P { int x }
+func f(int n) -> int
+  (array int a 2)
+  (store a N N)
+  (return (+ (index A 0) (- (call f (- N 1)))))
+"
+    );
+}
+
+#[test]
+fn default_folder_keeps_the_ast() {
+    struct Identity;
+    impl Folder for Identity {}
+
+    let ast = pipeline::parse_file(SOURCE).unwrap();
+    let expected = ast.to_string();
+    assert_eq!(Identity.fold_ast(ast).to_string(), expected);
+}
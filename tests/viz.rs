@@ -0,0 +1,92 @@
+//! Tests for the DOT graphs written by `viz`.
+
+use std::{fs, process::Command};
+
+use crust::{pipeline, viz};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+#[test]
+fn graphs_the_ast() {
+    let ast = pipeline::parse_file(
+        "struct P { int x; };
+        int main() { return -f(\"\\\"\"); }",
+    )
+    .unwrap();
+    assert_eq!(
+        viz::ast(&ast),
+        r#"digraph ast {
+    ordering=out;
+    node [shape=box];
+    n0 [label="struct P"];
+    n1 [label="field int x"];
+    n0 -> n1;
+    n2 [label="func main() -> int"];
+    n3 [label="return"];
+    n2 -> n3;
+    n4 [label="negate"];
+    n3 -> n4;
+    n5 [label="call f"];
+    n4 -> n5;
+    n6 [label="\"\\\"\""];
+    n5 -> n6;
+}
+"#
+    );
+}
+
+#[test]
+fn graphs_calls_once_per_callee() {
+    let ast = pipeline::parse_file(
+        "int f(int n) { return n; }
+        int g() { return 0; }
+        int main() { int a = print(f(1)); return f(2) + g(); }",
+    )
+    .unwrap();
+    assert_eq!(
+        viz::calls(&ast),
+        r#"digraph calls {
+    node [shape=box];
+    "f";
+    "g";
+    "main";
+    "print" [style=dashed];
+    "main" -> "print";
+    "main" -> "f";
+    "main" -> "g";
+}
+"#
+    );
+}
+
+#[test]
+fn viz_writes_to_output() {
+    let dir = std::env::temp_dir().join(format!("crust-viz-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("main.cst");
+    let output = dir.join("calls.dot");
+    fs::write(&input, "int main() { return main(); }").unwrap();
+
+    let status = Command::new(CRUST)
+        .args(["viz", "calls"])
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(fs::read_to_string(&output)
+        .unwrap()
+        .contains("\"main\" -> \"main\";"));
+
+    let stdout = Command::new(CRUST)
+        .args(["viz", "ast"])
+        .arg(&input)
+        .output()
+        .unwrap()
+        .stdout;
+    assert!(String::from_utf8(stdout)
+        .unwrap()
+        .starts_with("digraph ast {"));
+    fs::remove_dir_all(dir).unwrap();
+}
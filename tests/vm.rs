@@ -0,0 +1,396 @@
+//! Tests running programs on the bytecode VM, cross-checked against the tree-walking interpreter.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+fn write_source(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crust-vm-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.c");
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// Runs `source` with each of `runs` as arguments on the interpreter and the VM, requiring the
+/// same output, diagnostics and exit status.
+fn agree(name: &str, source: &str, runs: &[&[&str]]) {
+    agree_on(&write_source(name, source), name, runs);
+}
+
+/// Like [`agree`], but runs the IR built from `source` after replacing `from` with `to` in it.
+///
+/// IR isn't analysed before it runs, so this reaches errors that analysis would catch in source.
+fn agree_ir(name: &str, source: &str, (from, to): (&str, &str), runs: &[&[&str]]) {
+    let source = write_source(name, source);
+    let ir = source.with_extension("json");
+    let build = Command::new(CRUST)
+        .arg("build")
+        .arg(&source)
+        .arg(&ir)
+        .output()
+        .unwrap();
+    assert!(build.status.success());
+    let json = fs::read_to_string(&ir).unwrap();
+    assert!(json.contains(from), "{from} isn't in the IR of {name}");
+    fs::write(&ir, json.replace(from, to)).unwrap();
+    agree_on(&ir, name, runs);
+}
+
+fn agree_on(path: &Path, name: &str, runs: &[&[&str]]) {
+    for args in runs {
+        let run = |vm: bool| {
+            let mut command = Command::new(CRUST);
+            command.args(["--error-format", "json", "run"]);
+            if vm {
+                command.arg("--vm");
+            }
+            let output = command.arg(path).arg("--").args(*args).output().unwrap();
+            (
+                String::from_utf8(output.stdout).unwrap(),
+                String::from_utf8(output.stderr).unwrap(),
+                output.status.code(),
+            )
+        };
+        assert_eq!(run(true), run(false), "{name} with {args:?}");
+    }
+}
+
+#[test]
+fn arithmetic_and_strings() {
+    agree(
+        "arithmetic",
+        "int main(int a, string s) {
+            int _p = println(a + 1, a - 2, a * 3, -a, 100 / a, s + a, a + s);
+            string t = s + \"!\";
+            int u = t - 1;
+            return len(t);
+        }",
+        &[
+            &["7", "x"],
+            &["0", "y"],
+            &["-2147483648", "z"],
+            &["nope", "z"],
+            &[],
+        ],
+    );
+}
+
+#[test]
+fn remainders_bits_and_shifts() {
+    agree(
+        "bits",
+        "int main(int a, int b) {
+            int _p = println(a % b, a & b, a | b, a ^ b, a << b, a >> b);
+            return a % b;
+        }",
+        &[
+            &["7", "2"],
+            &["-7", "2"],
+            &["-2147483648", "-1"],
+            &["-1", "33"],
+            &["5", "0"],
+            &["x", "1"],
+        ],
+    );
+}
+
+#[test]
+fn unary_operators_and_assignments() {
+    agree(
+        "assignments",
+        "int main(int a, string s) {
+            int x = a;
+            x += 2;
+            x *= ++a;
+            x -= !x + --a;
+            x /= a;
+            s += x;
+            s = s + !a;
+            int _p = println(x, s, a);
+            s -= 1;
+            return x;
+        }",
+        &[&["7", "a"], &["0", ""], &["2147483647", "s"]],
+    );
+    agree(
+        "increment-string",
+        "int main(string s) { return ++s; }",
+        &[&["a"]],
+    );
+}
+
+#[test]
+fn comparisons_and_conditionals() {
+    agree(
+        "conditionals",
+        "int main(int a, string s) {
+            int x = 0;
+            int y = a < 0 ? --x : a == 0 ? 1 / a : ++x;
+            string t = a >= 2 ? s + a : s;
+            int _p = println(x, y, t, a <= 1, a > 1 != a, a ? 1 : 1 / 0);
+            return a != 2 ? s : x;
+        }",
+        &[
+            &["-3", "s"],
+            &["0", "s"],
+            &["2", "s"],
+            &["3", "s"],
+            &["2147483647", ""],
+        ],
+    );
+    agree(
+        "string-condition",
+        "int main(string s) { return s ? 1 : 0; }",
+        &[&["a"]],
+    );
+}
+
+#[test]
+fn arrays_and_shadowing() {
+    agree(
+        "arrays",
+        "int main(int i) {
+            int a[4];
+            a[i] = 9;
+            string words[2];
+            words[1] = \"hi\";
+            int _p = println(a[0], a[1], a[2], a[3], words[1], len(a), len(words[i]));
+            int a = 3;
+            int _q = println(a);
+            int b = a[0];
+            return a;
+        }",
+        &[&["0"], &["1"], &["3"], &["4"], &["-1"]],
+    );
+}
+
+#[test]
+fn arguments_bind_after_they_are_evaluated() {
+    let source = "int sub(int a, int b) { int c = a - b; return c; }
+        int main(int a, int b) { int c = sub(b, sub(a, b)); return c + a; }";
+    let program = crust::pipeline::compile(source, "main.c").unwrap();
+    let args = [String::from("5"), String::from("2")];
+    assert_eq!(program.ast.run_main(&args).unwrap(), 4);
+    agree("arguments", source, &[&["5", "2"]]);
+}
+
+#[test]
+fn calls_and_runtime_errors() {
+    agree(
+        "calls",
+        "int sq(int x) { return x * x; }
+        int down(int n) { int _p = print(n, \"\"); return down(n - 1); }
+        int main(int n) {
+            int _p = println(sq(n), sq(sq(n)));
+            return down(n);
+        }",
+        &[&["3"]],
+    );
+    agree(
+        "no-return",
+        "int none(int a) { int b = a; }
+        int main() { int _p = println(\"before\"); return none(1); }",
+        &[&[]],
+    );
+    agree(
+        "duplicate",
+        "int main() { return 0; } int main() { return 1; }",
+        &[&[]],
+    );
+    agree("main-return", "string main() { return \"s\"; }", &[&[]]);
+    agree(
+        "inline",
+        "int main(int x) { int _p = println(x); __asm { incl {x} } return x; }",
+        &[&["1"]],
+    );
+}
+
+#[test]
+fn void_functions_and_expression_statements() {
+    agree(
+        "void",
+        "void log(int a) {
+            println(a);
+            if_zero(a);
+            return;
+            println(99);
+        }
+        void if_zero(int a) { a / a; }
+        int main(int a) { log(a); log(a + 1); a * 2; return a; }",
+        &[&["1"], &["-1"]],
+    );
+}
+
+#[test]
+fn switches_and_fallthrough() {
+    agree(
+        "switch",
+        "int classify(int n) {
+            int r = 0;
+            switch (n) {
+                case 1:
+                    r += 1;
+                case 2:
+                    r += 10;
+                    break;
+                case -3:
+                    return 99;
+                default:
+                    r = 7;
+            }
+            return r;
+        }
+        int main(int n) {
+            switch (n) {
+                case 4:
+                case 5:
+                    println(\"four or five\");
+            }
+            println(classify(n));
+            return classify(n + 1);
+        }",
+        &[&["0"], &["1"], &["2"], &["-3"], &["-4"], &["4"]],
+    );
+}
+
+#[test]
+fn short_circuits() {
+    agree(
+        "logical",
+        "int side(int x) {
+            println(x);
+            return x;
+        }
+        int main(int a) {
+            int n = 0;
+            int f = a || ++n;
+            int g = a && ++n && side(n);
+            println(a && side(2), a || side(3), (a > 1 || a < -1) && !(a == 5), f, g, n);
+            return 1 || 0 && 0 ? a && 7 || 0 : 9;
+        }",
+        &[&["0"], &["1"], &["5"], &["-3"]],
+    );
+}
+
+#[test]
+fn errors_found_while_compiling() {
+    const SOURCE: &str = "int two(int a, int b) { return a * b; }
+        int main(int x) {
+            int b = x;
+            int _p = println(len(\"ab\"), b);
+            return two(b, 2);
+        }";
+    agree_ir(
+        "undeclared",
+        SOURCE,
+        ("\"Var\": 0", "\"Var\": 5"),
+        &[&["1"]],
+    );
+    agree_ir("unknown", SOURCE, ("println", "printx"), &[&["1"]]);
+    agree_ir("arity", SOURCE, ("\"len\"", "\"two\""), &[&["1"]]);
+    agree_ir("no-main", SOURCE, ("\"main\"", "\"start\""), &[&["1"]]);
+}
+
+#[test]
+fn eval_shows_the_stack_after_each_op() {
+    let output = Command::new(CRUST)
+        .args(["eval", "--show-stack", "1 + 2 * (3 - 4)"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "bytecode:
+  0  int 1
+  1  int 2
+  2  int 3
+  3  int 4
+  4  sub
+  5  mul
+  6  add
+  7  return
+
+stack:
+  0  int 1           [1]
+  1  int 2           [1, 2]
+  2  int 3           [1, 2, 3]
+  3  int 4           [1, 2, 3, 4]
+  4  sub             [1, 2, -1]
+  5  mul             [1, -2]
+  6  add             [-1]
+  7  return          []
+
+value: -1
+"
+    );
+
+    let eval = |expr: &str| {
+        let output = Command::new(CRUST).args(["eval", expr]).output().unwrap();
+        (
+            String::from_utf8(output.stdout).unwrap(),
+            output.status.success(),
+        )
+    };
+    assert_eq!(eval("0 ? 2 : len(\"abc\") << 1"), ("6\n".into(), true));
+    assert_eq!(eval("1 / 0"), ("".into(), false));
+}
+
+#[test]
+fn program_arguments_and_exit_code() {
+    let source = "int main() {
+        println(argc());
+        println(arg(0) + arg(argc() - 1));
+        return len(arg(1));
+    }";
+    agree("args", source, &[&["first", "second!"], &["x"], &[]]);
+
+    let output = Command::new(CRUST)
+        .arg("run")
+        .arg(write_source("args", source))
+        .args(["--", "first", "second!"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "2\nfirstsecond!\n-- exited with code : 7 --\n"
+    );
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn reads_only_the_environment_it_is_given() {
+    let source = write_source(
+        "env",
+        "int main() { println(getenv(\"GREETING\") + \"!\", len(getenv(\"HOME\"))); return 0; }",
+    );
+    for vm in [false, true] {
+        let mut command = Command::new(CRUST);
+        command.arg("run").arg(&source);
+        if vm {
+            command.arg("--vm");
+        }
+        let output = command
+            .args(["--env", "GREETING=hi=there", "--env", "UNUSED="])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "hi=there! 0\n-- exited with code : 0 --\n"
+        );
+    }
+
+    let output = Command::new(CRUST)
+        .arg("run")
+        .arg(&source)
+        .args(["--env", "=x"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("expected KEY=VALUE"));
+}
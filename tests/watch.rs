@@ -0,0 +1,137 @@
+//! Tests for watching files, and for `run --watch` and `build --watch`, which run or build the
+//! program again when they change.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crust::watch::Watcher;
+
+const CRUST: &str = env!("CARGO_BIN_EXE_crust");
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crust-watch-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `source` to `path` with a modification time later than any it had, however coarse the
+/// file system's clock is.
+fn touch(path: &PathBuf, source: &str, seconds: u64) {
+    fs::write(path, source).unwrap();
+    let file = fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(seconds))
+        .unwrap();
+}
+
+#[test]
+fn notices_changed_created_and_removed_files() {
+    let dir = temp_dir("watcher");
+    let main = dir.join("main.c");
+    let lib = dir.join("lib.c");
+    fs::write(&main, "int main() { return 0; }").unwrap();
+
+    let watcher = Watcher::new([main.clone(), lib.clone(), main.clone()]);
+    assert_eq!(watcher.files().count(), 2);
+    assert_eq!(watcher.changed(), None);
+
+    touch(&main, "int main() { return 1; }", 10);
+    assert_eq!(watcher.changed(), Some(main.as_path()));
+    assert_eq!(watcher.wait(), main.as_path());
+
+    let mut watcher = Watcher::new([main.clone()]);
+    watcher.add(lib.clone());
+    watcher.add(main.clone());
+    assert_eq!(watcher.files().count(), 2);
+    fs::write(&lib, "int f() { return 0; }").unwrap();
+    assert_eq!(watcher.changed(), Some(lib.as_path()));
+
+    let watcher = Watcher::new([main.clone(), lib.clone()]);
+    fs::remove_file(&main).unwrap();
+    assert_eq!(watcher.changed(), Some(main.as_path()));
+}
+
+#[test]
+fn runs_again_when_an_import_changes() {
+    let dir = temp_dir("run");
+    let main = dir.join("main.c");
+    let lib = dir.join("lib.c");
+    fs::write(
+        &main,
+        "import \"lib.c\"; int main() { println(f()); return 0; }",
+    )
+    .unwrap();
+    fs::write(&lib, "int f() { return 1; }").unwrap();
+
+    let mut child = Command::new(CRUST)
+        .args(["run", "--watch"])
+        .arg(&main)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let (send, lines) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if send.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let next = || lines.recv_timeout(Duration::from_secs(20)).unwrap();
+
+    assert_eq!(next(), "1");
+    assert!(next().starts_with("-- exited with code"));
+    touch(&lib, "int f() { return 2; }", 10);
+    assert_eq!(next(), "2");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn builds_again_in_process_when_an_import_changes() {
+    let dir = temp_dir("build");
+    let main = dir.join("main.c");
+    let lib = dir.join("lib.c");
+    let out = dir.join("main.txt");
+    fs::write(&main, "import \"lib.c\"; int main() { return f(); }").unwrap();
+    fs::write(&lib, "int f() { return 1; }").unwrap();
+
+    let mut child = Command::new(CRUST)
+        .args(["build", "--watch", "--emit", "ast-opt"])
+        .arg(&main)
+        .arg(&out)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (send, lines) = mpsc::channel();
+    let stderr = child.stderr.take().unwrap();
+    thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            if send.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let next = || lines.recv_timeout(Duration::from_secs(20)).unwrap();
+
+    // the import is watched because the build read it, without compiling the program again
+    assert_eq!(next(), "-- watching 2 files for changes, Ctrl-C to stop --");
+    assert!(fs::read_to_string(&out).unwrap().contains("(return 1)"));
+    touch(&lib, "int f() { return 2; }", 10);
+    assert_eq!(next(), "-- watching 2 files for changes, Ctrl-C to stop --");
+    assert!(fs::read_to_string(&out).unwrap().contains("(return 2)"));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}